@@ -33,7 +33,7 @@ String _surfaceIdForKey(String surfaceKey) {
   return 'backend_canvas_project_$normalized';
 }
 
-const int _kPointStrideBytes = 32;
+const int _kPointStrideBytes = 48;
 const int _kPointFlagDown = 1;
 const int _kPointFlagMove = 2;
 const int _kPointFlagUp = 4;
@@ -61,6 +61,10 @@ final class _PackedPointBuffer {
     required int timestampUs,
     required int flags,
     required int pointerId,
+    double tilt = 0.0,
+    double azimuth = 0.0,
+    double rotation = 0.0,
+    int device = 0,
   }) {
     _ensureCapacity(_len + 1);
     final int base = _len * _kPointStrideBytes;
@@ -71,6 +75,10 @@ final class _PackedPointBuffer {
     _data.setUint64(base + 16, timestampUs, Endian.little);
     _data.setUint32(base + 24, flags, Endian.little);
     _data.setUint32(base + 28, pointerId, Endian.little);
+    _data.setFloat32(base + 32, tilt, Endian.little);
+    _data.setFloat32(base + 36, azimuth, Endian.little);
+    _data.setFloat32(base + 40, rotation, Endian.little);
+    _data.setUint32(base + 44, device, Endian.little);
     _len++;
   }
 
@@ -912,6 +920,11 @@ class _BackendCanvasSurfaceState extends State<BackendCanvasSurface> {
       timestampUs: timestampUs,
       flags: flags,
       pointerId: event.pointer,
+      tilt: event.tilt,
+      azimuth: event.orientation - math.pi / 2,
+      // Flutter reports no barrel roll; orientation is already the azimuth.
+      rotation: 0.0,
+      device: event.device,
     );
   }
 
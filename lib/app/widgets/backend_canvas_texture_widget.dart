@@ -19,7 +19,7 @@ class _RedoIntent extends Intent {
   const _RedoIntent();
 }
 
-const int _kPointStrideBytes = 32;
+const int _kPointStrideBytes = 48;
 const int _kPointFlagDown = 1;
 const int _kPointFlagMove = 2;
 const int _kPointFlagUp = 4;
@@ -48,6 +48,10 @@ final class _PackedPointBuffer {
     required int timestampUs,
     required int flags,
     required int pointerId,
+    double tilt = 0.0,
+    double azimuth = 0.0,
+    double rotation = 0.0,
+    int device = 0,
   }) {
     _ensureCapacity(_len + 1);
     final int base = _len * _kPointStrideBytes;
@@ -58,6 +62,10 @@ final class _PackedPointBuffer {
     _data.setUint64(base + 16, timestampUs, Endian.little);
     _data.setUint32(base + 24, flags, Endian.little);
     _data.setUint32(base + 28, pointerId, Endian.little);
+    _data.setFloat32(base + 32, tilt, Endian.little);
+    _data.setFloat32(base + 36, azimuth, Endian.little);
+    _data.setFloat32(base + 40, rotation, Endian.little);
+    _data.setUint32(base + 44, device, Endian.little);
     _len++;
   }
 
@@ -319,6 +327,12 @@ class _BackendCanvasTextureWidgetState extends State<BackendCanvasTextureWidget>
       timestampUs: timestampUs,
       flags: flags,
       pointerId: event.pointer,
+      tilt: event.tilt,
+      // 0 is "pointing up" in Flutter.
+      azimuth: event.orientation - math.pi / 2,
+      // Flutter reports no barrel roll; orientation is already the azimuth.
+      rotation: 0.0,
+      device: event.device,
     );
     _scheduleFlush();
   }
@@ -22,7 +22,7 @@ import '../../src/rust/rust_init.dart';
 
 const double _kPreviewPadding = 4.0;
 const double _kPreviewPressureMinFactor = 0.09; // Keep in sync with rust.
-const int _kEnginePointStrideBytes = 32;
+const int _kEnginePointStrideBytes = 48;
 const int _kPointFlagDown = 1;
 const int _kPointFlagMove = 2;
 const int _kPointFlagUp = 4;
@@ -409,6 +409,10 @@ Uint8List _encodeEnginePoints(
     data.setUint64(offset + 16, timestampUs, Endian.little);
     data.setUint32(offset + 24, flags, Endian.little);
     data.setUint32(offset + 28, 0, Endian.little); // pointerId
+    data.setFloat32(offset + 32, 0.0, Endian.little); // tilt
+    data.setFloat32(offset + 36, 0.0, Endian.little); // azimuth
+    data.setFloat32(offset + 40, 0.0, Endian.little); // rotation
+    data.setFloat32(offset + 44, 0.0, Endian.little); // pad
     timestampUs += 16000;
   }
   return bytes;
@@ -1,7 +1,7 @@
 part of 'painting_board.dart';
 
 const double _kStylusSimulationBlend = 0.68;
-const int _kBackendPointStrideBytes = 32;
+const int _kBackendPointStrideBytes = 48;
 const int _kBackendPointFlagDown = 1;
 const int _kBackendPointFlagMove = 2;
 const int _kBackendPointFlagUp = 4;
@@ -45,6 +45,10 @@ final class _BackendPointBuffer {
     required int timestampUs,
     required int flags,
     required int pointerId,
+    double tilt = 0.0,
+    double azimuth = 0.0,
+    double rotation = 0.0,
+    int device = 0,
   }) {
     _ensureCapacity(_len + 1);
     final int base = _len * _kBackendPointStrideBytes;
@@ -55,6 +59,10 @@ final class _BackendPointBuffer {
     _data.setUint64(base + 16, timestampUs, Endian.little);
     _data.setUint32(base + 24, flags, Endian.little);
     _data.setUint32(base + 28, pointerId, Endian.little);
+    _data.setFloat32(base + 32, tilt, Endian.little);
+    _data.setFloat32(base + 36, azimuth, Endian.little);
+    _data.setFloat32(base + 40, rotation, Endian.little);
+    _data.setUint32(base + 44, device, Endian.little);
     _len++;
   }
 
@@ -306,6 +314,10 @@ mixin _PaintingBoardInteractionMixin
   double _backendLastMovementDistance = 0.0;
   double? _backendLastStylusPressure;
   double _backendLastResolvedPressure = 1.0;
+  double _backendLastPenTilt = 0.0;
+  double _backendLastPenAzimuth = 0.0;
+  double _backendLastPenRotation = 0.0;
+  int _backendLastPenDevice = 0;
   bool _backendWaitingForFirstMove = false;
   Offset? _backendStrokeStartPoint;
   double _backendStrokeStartPressure = 1.0;
@@ -271,7 +271,17 @@ extension _PaintingBoardInteractionBackendImpl
     required int timestampUs,
     required int flags,
     required int pointerId,
+    PointerEvent? event,
   }) {
+    if (event != null) {
+      _backendLastPenTilt = event.tilt;
+      _backendLastPenDevice = event.device;
+      // Flutter measures orientation from "up"; the engine wants a canvas angle.
+      _backendLastPenAzimuth = event.orientation - math.pi / 2;
+      // Flutter has no separate barrel-roll axis; the reported pen
+      // orientation stands in for it.
+      _backendLastPenRotation = event.orientation;
+    }
     final Offset? previous = _backendLastEnginePoint;
     if (previous != null) {
       final Offset delta = enginePos - previous;
@@ -290,6 +300,10 @@ extension _PaintingBoardInteractionBackendImpl
       timestampUs: timestampUs,
       flags: flags,
       pointerId: pointerId,
+      tilt: _backendLastPenTilt,
+      azimuth: _backendLastPenAzimuth,
+      rotation: _backendLastPenRotation,
+      device: _backendLastPenDevice,
     );
   }
 
@@ -459,6 +473,7 @@ extension _PaintingBoardInteractionBackendImpl
       timestampUs: timestampUs,
       flags: flags,
       pointerId: event.pointer,
+      event: event,
     );
     if (_kDebugBackendCanvasInput &&
         (flags == _kBackendPointFlagDown || flags == _kBackendPointFlagUp)) {
@@ -515,6 +530,7 @@ extension _PaintingBoardInteractionBackendImpl
       timestampUs: timestampUs,
       flags: flags,
       pointerId: anchorEvent.pointer,
+      event: anchorEvent,
     );
     _scheduleBackendFlush();
   }
@@ -841,6 +857,7 @@ extension _PaintingBoardInteractionBackendImpl
             timestampUs: timestampUs,
             flags: _kBackendPointFlagMove,
             pointerId: event.pointer,
+            event: event,
           );
           final Offset delta = tailPoint - enginePos;
           final double dist = delta.distance;
@@ -864,6 +881,7 @@ extension _PaintingBoardInteractionBackendImpl
                 timestampUs: timestampUs,
                 flags: _kBackendPointFlagMove,
                 pointerId: event.pointer,
+                event: event,
               );
             }
           }
@@ -873,6 +891,7 @@ extension _PaintingBoardInteractionBackendImpl
             timestampUs: timestampUs,
             flags: _kBackendPointFlagUp,
             pointerId: event.pointer,
+            event: event,
           );
         } else {
           _appendBackendPoint(
@@ -881,6 +900,7 @@ extension _PaintingBoardInteractionBackendImpl
             timestampUs: timestampUs,
             flags: _kBackendPointFlagUp,
             pointerId: event.pointer,
+            event: event,
           );
         }
       } else {
@@ -890,6 +910,7 @@ extension _PaintingBoardInteractionBackendImpl
           timestampUs: timestampUs,
           flags: _kBackendPointFlagUp,
           pointerId: event.pointer,
+          event: event,
         );
       }
       _scheduleBackendFlush();
@@ -971,6 +992,7 @@ extension _PaintingBoardInteractionBackendImpl
         timestampUs: startTimestampUs,
         flags: _kBackendPointFlagDown,
         pointerId: event.pointer,
+        event: event,
       );
       _appendBackendPoint(
         enginePos: endEngine,
@@ -978,6 +1000,7 @@ extension _PaintingBoardInteractionBackendImpl
         timestampUs: endTimestampUs,
         flags: _kBackendPointFlagUp,
         pointerId: event.pointer,
+        event: event,
       );
       _scheduleBackendFlush();
       _recordBackendHistoryAction(layerId: _activeLayerId, deferPreview: true);
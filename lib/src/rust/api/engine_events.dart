@@ -0,0 +1,107 @@
+// This file is automatically generated, so please do not edit it.
+// @generated by `flutter_rust_bridge`@ 2.11.1.
+
+// ignore_for_file: invalid_use_of_internal_member, unused_import, unnecessary_import
+
+import '../frb_generated.dart';
+import 'package:flutter_rust_bridge/flutter_rust_bridge_for_generated.dart';
+
+// These function are ignored because they are on traits that is not defined in current crate (put an empty `#[frb]` on it to unignore): `clone`, `clone`, `eq`, `fmt`, `fmt`
+
+/// Streams the events of engine `handle`. Subscribing again replaces the
+/// previous stream; the stream ends when the engine is disposed.
+Stream<EngineEvent> engineEventStream({required BigInt handle}) =>
+    RustLib.instance.api.crateApiEngineEventsEngineEventStream(handle: handle);
+
+class EngineEvent {
+  final EngineEventKind kind;
+  final int layerIndex;
+  final int left;
+  final int top;
+  final int width;
+  final int height;
+  final bool onMask;
+  final bool canUndo;
+  final bool canRedo;
+  final bool applied;
+  final String message;
+
+  const EngineEvent({
+    required this.kind,
+    required this.layerIndex,
+    required this.left,
+    required this.top,
+    required this.width,
+    required this.height,
+    required this.onMask,
+    required this.canUndo,
+    required this.canRedo,
+    required this.applied,
+    required this.message,
+  });
+
+  @override
+  int get hashCode =>
+      kind.hashCode ^
+      layerIndex.hashCode ^
+      left.hashCode ^
+      top.hashCode ^
+      width.hashCode ^
+      height.hashCode ^
+      onMask.hashCode ^
+      canUndo.hashCode ^
+      canRedo.hashCode ^
+      applied.hashCode ^
+      message.hashCode;
+
+  @override
+  bool operator ==(Object other) =>
+      identical(this, other) ||
+      other is EngineEvent &&
+          runtimeType == other.runtimeType &&
+          kind == other.kind &&
+          layerIndex == other.layerIndex &&
+          left == other.left &&
+          top == other.top &&
+          width == other.width &&
+          height == other.height &&
+          onMask == other.onMask &&
+          canUndo == other.canUndo &&
+          canRedo == other.canRedo &&
+          applied == other.applied &&
+          message == other.message;
+}
+
+/// What an [`EngineEvent`] reports. Fields a kind does not use stay zero,
+/// `false` or empty.
+enum EngineEventKind {
+  /// `can_undo` / `can_redo` changed.
+  undoState,
+
+  /// A step was committed, undone or redone. The rect covers the pixels it
+  /// changed on `layer_index`; `on_mask` is set when they belong to the
+  /// layer's mask.
+  layerDirty,
+
+  /// Tile-aligned bounds of `layer_index` after a change; `width` and
+  /// `height` are 0 once the layer is empty.
+  layerBounds,
+
+  /// A bucket fill on `layer_index` finished; `applied` tells whether it
+  /// changed any pixels.
+  bucketFillFinished,
+
+  /// A filter or antialias pass on `layer_index` finished.
+  filterFinished,
+
+  /// Undo or redo changed layer properties, the layer stack or the canvas;
+  /// `width` and `height` hold the canvas size afterwards.
+  layersChanged,
+
+  /// A request that could not be carried out, in `message`.
+  error,
+
+  /// A warning logged on the render thread, in `message`. The engine
+  /// carried on, so the app may only want to log it.
+  warning,
+}
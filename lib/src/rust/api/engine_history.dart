@@ -0,0 +1,89 @@
+// This file is automatically generated, so please do not edit it.
+// @generated by `flutter_rust_bridge`@ 2.11.1.
+
+// ignore_for_file: invalid_use_of_internal_member, unused_import, unnecessary_import
+
+import '../frb_generated.dart';
+import 'package:flutter_rust_bridge/flutter_rust_bridge_for_generated.dart';
+
+// These function are ignored because they are on traits that is not defined in current crate (put an empty `#[frb]` on it to unignore): `clone`, `clone`, `eq`, `fmt`, `fmt`
+
+/// Lists every state of the undo history of engine `handle`; `parent_id`
+/// links them into a tree. Empty for unknown handles.
+Future<List<EngineHistoryNode>> engineHistory({required BigInt handle}) =>
+    RustLib.instance.api.crateApiEngineHistoryEngineHistory(handle: handle);
+
+/// What a [`EngineHistoryNode`] stands for.
+enum EngineHistoryKind {
+  /// The oldest state kept; it has no step of its own.
+  start,
+
+  /// A stroke, fill, filter or other pixel change, possibly on several
+  /// layers.
+  pixels,
+
+  /// A change of layer properties, the layer stack or the canvas.
+  layers,
+}
+
+/// One state of an engine's undo history. States form a tree: a change
+/// made after undoing starts a new branch instead of dropping the old one.
+class EngineHistoryNode {
+  final BigInt id;
+
+  /// 0 for the start node.
+  final BigInt parentId;
+  final EngineHistoryKind kind;
+
+  /// Checkpoint name, empty when the state has none.
+  final String label;
+  final bool current;
+
+  /// Whether plain undo and redo reach the state.
+  final bool onActiveBranch;
+
+  /// Premultiplied RGBA preview of the changed area; empty for nodes
+  /// without pixel changes.
+  final int thumbnailWidth;
+  final int thumbnailHeight;
+  final Uint8List thumbnail;
+
+  const EngineHistoryNode({
+    required this.id,
+    required this.parentId,
+    required this.kind,
+    required this.label,
+    required this.current,
+    required this.onActiveBranch,
+    required this.thumbnailWidth,
+    required this.thumbnailHeight,
+    required this.thumbnail,
+  });
+
+  @override
+  int get hashCode =>
+      id.hashCode ^
+      parentId.hashCode ^
+      kind.hashCode ^
+      label.hashCode ^
+      current.hashCode ^
+      onActiveBranch.hashCode ^
+      thumbnailWidth.hashCode ^
+      thumbnailHeight.hashCode ^
+      thumbnail.hashCode;
+
+  @override
+  bool operator ==(Object other) =>
+      identical(this, other) ||
+      other is EngineHistoryNode &&
+          runtimeType == other.runtimeType &&
+          id == other.id &&
+          parentId == other.parentId &&
+          kind == other.kind &&
+          label == other.label &&
+          current == other.current &&
+          onActiveBranch == other.onActiveBranch &&
+          thumbnailWidth == other.thumbnailWidth &&
+          thumbnailHeight == other.thumbnailHeight &&
+          thumbnail == other.thumbnail;
+}
@@ -41,6 +41,7 @@ class GpuLayerData {
   final int blendModeIndex;
   final bool visible;
   final bool clippingMask;
+  final Uint8List? mask;
 
   const GpuLayerData({
     required this.pixels,
@@ -48,6 +49,7 @@ class GpuLayerData {
     required this.blendModeIndex,
     required this.visible,
     required this.clippingMask,
+    this.mask,
   });
 
   @override
@@ -56,7 +58,8 @@ class GpuLayerData {
       opacity.hashCode ^
       blendModeIndex.hashCode ^
       visible.hashCode ^
-      clippingMask.hashCode;
+      clippingMask.hashCode ^
+      mask.hashCode;
 
   @override
   bool operator ==(Object other) =>
@@ -67,5 +70,6 @@ class GpuLayerData {
           opacity == other.opacity &&
           blendModeIndex == other.blendModeIndex &&
           visible == other.visible &&
-          clippingMask == other.clippingMask;
+          clippingMask == other.clippingMask &&
+          mask == other.mask;
 }
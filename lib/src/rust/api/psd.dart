@@ -9,19 +9,35 @@ import 'package:flutter_rust_bridge/flutter_rust_bridge_for_generated.dart';
 Future<PsdDocument> importPsd({required List<int> bytes}) =>
     RustLib.instance.api.crateApiPsdImportPsd(bytes: bytes);
 
+/// Encodes an adjustment layer's filter as a complete Photoshop additional
+/// layer info block (`8BIM`, key, length, payload) for the PSD exporter.
+/// Returns `None` when the filter has no Photoshop counterpart.
+Uint8List? psdAdjustmentLayerInfo({
+  required int filterType,
+  required List<double> params,
+}) => RustLib.instance.api.crateApiPsdPsdAdjustmentLayerInfo(
+  filterType: filterType,
+  params: params,
+);
+
 class PsdDocument {
   final int width;
   final int height;
   final List<PsdLayer> layers;
 
+  /// Layer folders, bottom-to-top like `layers`.
+  final List<PsdGroup> groups;
+
   const PsdDocument({
     required this.width,
     required this.height,
     required this.layers,
+    required this.groups,
   });
 
   @override
-  int get hashCode => width.hashCode ^ height.hashCode ^ layers.hashCode;
+  int get hashCode =>
+      width.hashCode ^ height.hashCode ^ layers.hashCode ^ groups.hashCode;
 
   @override
   bool operator ==(Object other) =>
@@ -30,7 +46,47 @@ class PsdDocument {
           runtimeType == other.runtimeType &&
           width == other.width &&
           height == other.height &&
-          layers == other.layers;
+          layers == other.layers &&
+          groups == other.groups;
+}
+
+class PsdGroup {
+  final int id;
+  final int parentId;
+  final String name;
+  final bool visible;
+  final int opacity;
+  final String blendModeKey;
+
+  const PsdGroup({
+    required this.id,
+    required this.parentId,
+    required this.name,
+    required this.visible,
+    required this.opacity,
+    required this.blendModeKey,
+  });
+
+  @override
+  int get hashCode =>
+      id.hashCode ^
+      parentId.hashCode ^
+      name.hashCode ^
+      visible.hashCode ^
+      opacity.hashCode ^
+      blendModeKey.hashCode;
+
+  @override
+  bool operator ==(Object other) =>
+      identical(this, other) ||
+      other is PsdGroup &&
+          runtimeType == other.runtimeType &&
+          id == other.id &&
+          parentId == other.parentId &&
+          name == other.name &&
+          visible == other.visible &&
+          opacity == other.opacity &&
+          blendModeKey == other.blendModeKey;
 }
 
 class PsdLayer {
@@ -44,6 +100,13 @@ class PsdLayer {
   final int bitmapHeight;
   final int bitmapLeft;
   final int bitmapTop;
+  final int parentId;
+
+  /// Canvas filter type of an adjustment layer; its bitmap is then empty.
+  final int? adjustmentFilterType;
+
+  /// The adjustment's four filter parameters, in `ApplyFilter` units.
+  final Float32List adjustmentParams;
 
   const PsdLayer({
     required this.name,
@@ -56,6 +119,9 @@ class PsdLayer {
     required this.bitmapHeight,
     required this.bitmapLeft,
     required this.bitmapTop,
+    required this.parentId,
+    this.adjustmentFilterType,
+    required this.adjustmentParams,
   });
 
   @override
@@ -69,7 +135,10 @@ class PsdLayer {
       bitmapWidth.hashCode ^
       bitmapHeight.hashCode ^
       bitmapLeft.hashCode ^
-      bitmapTop.hashCode;
+      bitmapTop.hashCode ^
+      parentId.hashCode ^
+      adjustmentFilterType.hashCode ^
+      adjustmentParams.hashCode;
 
   @override
   bool operator ==(Object other) =>
@@ -85,5 +154,8 @@ class PsdLayer {
           bitmapWidth == other.bitmapWidth &&
           bitmapHeight == other.bitmapHeight &&
           bitmapLeft == other.bitmapLeft &&
-          bitmapTop == other.bitmapTop;
+          bitmapTop == other.bitmapTop &&
+          parentId == other.parentId &&
+          adjustmentFilterType == other.adjustmentFilterType &&
+          adjustmentParams == other.adjustmentParams;
 }
@@ -1,3 +1,4 @@
+import 'dart:async';
 import 'dart:ffi' as ffi;
 import 'dart:typed_data';
 
@@ -5,7 +6,7 @@ import 'package:ffi/ffi.dart';
 
 import 'rust_dylib.dart';
 
-const int _kPointStrideBytes = 32;
+const int _kPointStrideBytes = 48;
 const int _kViewFlagMirror = 1;
 const int _kViewFlagBlackWhite = 2;
 
@@ -31,6 +32,18 @@ final class _EnginePointNative extends ffi.Struct {
 
   @ffi.Uint32()
   external int pointerId;
+
+  @ffi.Float()
+  external double tilt;
+
+  @ffi.Float()
+  external double azimuth;
+
+  @ffi.Float()
+  external double rotation;
+
+  @ffi.Uint32()
+  external int device;
 }
 
 typedef _EngineCreateNative =
@@ -86,6 +99,15 @@ typedef _EngineSetLayerClippingMaskNative =
 typedef _EngineSetLayerClippingMaskDart =
     void Function(int handle, int layerIndex, int clippingMask);
 
+typedef _EngineSetLayerAlphaLockNative =
+    ffi.Void Function(
+      ffi.Uint64 handle,
+      ffi.Uint32 layerIndex,
+      ffi.Uint8 locked,
+    );
+typedef _EngineSetLayerAlphaLockDart =
+    void Function(int handle, int layerIndex, int locked);
+
 typedef _EngineSetLayerBlendModeNative =
     ffi.Void Function(
       ffi.Uint64 handle,
@@ -104,6 +126,157 @@ typedef _EngineReorderLayerNative =
 typedef _EngineReorderLayerDart =
     void Function(int handle, int fromIndex, int toIndex);
 
+typedef _EngineInsertLayerNative =
+    ffi.Void Function(ffi.Uint64 handle, ffi.Uint32 layerIndex);
+typedef _EngineInsertLayerDart = void Function(int handle, int layerIndex);
+
+typedef _EngineRemoveLayerNative =
+    ffi.Void Function(ffi.Uint64 handle, ffi.Uint32 layerIndex);
+typedef _EngineRemoveLayerDart = void Function(int handle, int layerIndex);
+
+typedef _EngineCreateLayerGroupNative =
+    ffi.Uint32 Function(ffi.Uint64 handle, ffi.Uint32 parentGroupId);
+typedef _EngineCreateLayerGroupDart =
+    int Function(int handle, int parentGroupId);
+
+typedef _EngineDeleteLayerGroupNative =
+    ffi.Void Function(ffi.Uint64 handle, ffi.Uint32 groupId);
+typedef _EngineDeleteLayerGroupDart = void Function(int handle, int groupId);
+
+typedef _EngineSetLayerGroupParentNative =
+    ffi.Void Function(
+      ffi.Uint64 handle,
+      ffi.Uint32 groupId,
+      ffi.Uint32 parentGroupId,
+    );
+typedef _EngineSetLayerGroupParentDart =
+    void Function(int handle, int groupId, int parentGroupId);
+
+typedef _EngineMoveLayerToGroupNative =
+    ffi.Void Function(
+      ffi.Uint64 handle,
+      ffi.Uint32 layerIndex,
+      ffi.Uint32 groupId,
+    );
+typedef _EngineMoveLayerToGroupDart =
+    void Function(int handle, int layerIndex, int groupId);
+
+typedef _EngineSetLayerGroupOpacityNative =
+    ffi.Void Function(ffi.Uint64 handle, ffi.Uint32 groupId, ffi.Float opacity);
+typedef _EngineSetLayerGroupOpacityDart =
+    void Function(int handle, int groupId, double opacity);
+
+typedef _EngineSetLayerGroupVisibleNative =
+    ffi.Void Function(ffi.Uint64 handle, ffi.Uint32 groupId, ffi.Uint8 visible);
+typedef _EngineSetLayerGroupVisibleDart =
+    void Function(int handle, int groupId, int visible);
+
+typedef _EngineSetLayerGroupBlendModeNative =
+    ffi.Void Function(
+      ffi.Uint64 handle,
+      ffi.Uint32 groupId,
+      ffi.Uint32 blendModeIndex,
+    );
+typedef _EngineSetLayerGroupBlendModeDart =
+    void Function(int handle, int groupId, int blendModeIndex);
+
+typedef _EngineSetLayerGroupPassThroughNative =
+    ffi.Void Function(
+      ffi.Uint64 handle,
+      ffi.Uint32 groupId,
+      ffi.Uint8 passThrough,
+    );
+typedef _EngineSetLayerGroupPassThroughDart =
+    void Function(int handle, int groupId, int passThrough);
+
+typedef _EngineSetLayerGroupCollapsedNative =
+    ffi.Void Function(
+      ffi.Uint64 handle,
+      ffi.Uint32 groupId,
+      ffi.Uint8 collapsed,
+    );
+typedef _EngineSetLayerGroupCollapsedDart =
+    void Function(int handle, int groupId, int collapsed);
+
+typedef _EngineGetLayerGroupsNative =
+    ffi.UintPtr Function(
+      ffi.Uint64 handle,
+      ffi.Pointer<ffi.Uint32> outPtr,
+      ffi.UintPtr outLen,
+    );
+typedef _EngineGetLayerGroupsDart =
+    int Function(int handle, ffi.Pointer<ffi.Uint32> outPtr, int outLen);
+
+typedef _EngineCreateLayerMaskNative =
+    ffi.Void Function(ffi.Uint64 handle, ffi.Uint32 layerIndex);
+typedef _EngineCreateLayerMaskDart = void Function(int handle, int layerIndex);
+
+typedef _EngineDeleteLayerMaskNative =
+    ffi.Void Function(ffi.Uint64 handle, ffi.Uint32 layerIndex);
+typedef _EngineDeleteLayerMaskDart = void Function(int handle, int layerIndex);
+
+typedef _EngineSetLayerMaskEnabledNative =
+    ffi.Void Function(
+      ffi.Uint64 handle,
+      ffi.Uint32 layerIndex,
+      ffi.Uint8 enabled,
+    );
+typedef _EngineSetLayerMaskEnabledDart =
+    void Function(int handle, int layerIndex, int enabled);
+
+typedef _EngineSetLayerMaskEditingNative =
+    ffi.Void Function(ffi.Uint64 handle, ffi.Uint8 editing);
+typedef _EngineSetLayerMaskEditingDart = void Function(int handle, int editing);
+
+typedef _EngineInvertLayerMaskNative =
+    ffi.Void Function(ffi.Uint64 handle, ffi.Uint32 layerIndex);
+typedef _EngineInvertLayerMaskDart = void Function(int handle, int layerIndex);
+
+typedef _EngineApplyLayerMaskNative =
+    ffi.Void Function(ffi.Uint64 handle, ffi.Uint32 layerIndex);
+typedef _EngineApplyLayerMaskDart = void Function(int handle, int layerIndex);
+
+typedef _EngineReadLayerMaskNative =
+    ffi.Uint8 Function(
+      ffi.Uint64 handle,
+      ffi.Uint32 layerIndex,
+      ffi.Pointer<ffi.Uint8> outPtr,
+      ffi.UintPtr outLen,
+    );
+typedef _EngineReadLayerMaskDart =
+    int Function(
+      int handle,
+      int layerIndex,
+      ffi.Pointer<ffi.Uint8> outPtr,
+      int outLen,
+    );
+
+typedef _EngineSetLayerAdjustmentNative =
+    ffi.Void Function(
+      ffi.Uint64 handle,
+      ffi.Uint32 layerIndex,
+      ffi.Uint32 filterType,
+      ffi.Float param0,
+      ffi.Float param1,
+      ffi.Float param2,
+      ffi.Float param3,
+    );
+typedef _EngineSetLayerAdjustmentDart =
+    void Function(
+      int handle,
+      int layerIndex,
+      int filterType,
+      double param0,
+      double param1,
+      double param2,
+      double param3,
+    );
+
+typedef _EngineClearLayerAdjustmentNative =
+    ffi.Void Function(ffi.Uint64 handle, ffi.Uint32 layerIndex);
+typedef _EngineClearLayerAdjustmentDart =
+    void Function(int handle, int layerIndex);
+
 typedef _EngineSetViewFlagsNative =
     ffi.Void Function(ffi.Uint64 handle, ffi.Uint32 viewFlags);
 typedef _EngineSetViewFlagsDart = void Function(int handle, int viewFlags);
@@ -319,6 +492,27 @@ typedef _EngineUndoDart = void Function(int handle);
 typedef _EngineRedoNative = ffi.Void Function(ffi.Uint64 handle);
 typedef _EngineRedoDart = void Function(int handle);
 
+typedef _EngineSetUndoLimitsNative =
+    ffi.Void Function(
+      ffi.Uint64 handle,
+      ffi.Uint32 maxSteps,
+      ffi.Uint64 budgetBytes,
+    );
+typedef _EngineSetUndoLimitsDart =
+    void Function(int handle, int maxSteps, int budgetBytes);
+
+typedef _EngineBeginUndoTransactionNative =
+    ffi.Void Function(ffi.Uint64 handle);
+typedef _EngineBeginUndoTransactionDart = void Function(int handle);
+
+typedef _EngineCommitUndoTransactionNative =
+    ffi.Void Function(ffi.Uint64 handle);
+typedef _EngineCommitUndoTransactionDart = void Function(int handle);
+
+typedef _EngineCancelUndoTransactionNative =
+    ffi.Void Function(ffi.Uint64 handle);
+typedef _EngineCancelUndoTransactionDart = void Function(int handle);
+
 typedef _EngineSetBrushNative =
     ffi.Void Function(
       ffi.Uint64 handle,
@@ -463,6 +657,73 @@ typedef _EngineApplyAntialiasNative =
 typedef _EngineApplyAntialiasDart =
     int Function(int handle, int layerIndex, int level);
 
+// Shared by the pressure curve, curve table and device calibration setters:
+// `key` is the curve target or the input device.
+typedef _EngineSetPressureCurveNative =
+    ffi.Void Function(
+      ffi.Uint64 handle,
+      ffi.Uint32 key,
+      ffi.Pointer<ffi.Float> values,
+      ffi.UintPtr count,
+    );
+typedef _EngineSetPressureCurveDart =
+    void Function(
+      int handle,
+      int key,
+      ffi.Pointer<ffi.Float> values,
+      int count,
+    );
+
+typedef _EngineSetSymmetryNative =
+    ffi.Void Function(
+      ffi.Uint64 handle,
+      ffi.Uint32 mode,
+      ffi.Float centerX,
+      ffi.Float centerY,
+      ffi.Uint32 folds,
+    );
+typedef _EngineSetSymmetryDart =
+    void Function(
+      int handle,
+      int mode,
+      double centerX,
+      double centerY,
+      int folds,
+    );
+
+typedef _EngineEncodeRinDoneNative =
+    ffi.Void Function(
+      ffi.Uint64 requestId,
+      ffi.Pointer<ffi.Uint8> bytes,
+      ffi.UintPtr len,
+    );
+
+typedef _EngineEncodeRinNative =
+    ffi.Uint8 Function(
+      ffi.Uint64 handle,
+      ffi.Pointer<ffi.Uint8> templatePtr,
+      ffi.UintPtr templateLen,
+      ffi.Uint64 requestId,
+      ffi.Pointer<ffi.NativeFunction<_EngineEncodeRinDoneNative>> onDone,
+    );
+typedef _EngineEncodeRinDart =
+    int Function(
+      int handle,
+      ffi.Pointer<ffi.Uint8> templatePtr,
+      int templateLen,
+      int requestId,
+      ffi.Pointer<ffi.NativeFunction<_EngineEncodeRinDoneNative>> onDone,
+    );
+
+typedef _EngineFreeRinNative =
+    ffi.Void Function(ffi.Pointer<ffi.Uint8> ptr, ffi.UintPtr len);
+typedef _EngineFreeRinDart = void Function(ffi.Pointer<ffi.Uint8> ptr, int len);
+
+typedef _EngineCreateFromRinNative =
+    ffi.Uint64 Function(ffi.Pointer<ffi.Uint8> bytes, ffi.UintPtr len);
+typedef _EngineCreateFromRinDart =
+    int Function(ffi.Pointer<ffi.Uint8> bytes, int len);
+
 typedef _EngineLogPopNative = ffi.Pointer<ffi.Char> Function();
 typedef _EngineLogPopDart = ffi.Pointer<ffi.Char> Function();
 typedef _EngineLogFreeNative = ffi.Void Function(ffi.Pointer<ffi.Char> ptr);
@@ -543,6 +804,15 @@ class CanvasEngineFfi {
       } catch (_) {
         _setLayerClippingMask = null;
       }
+      try {
+        _setLayerAlphaLock = _lib
+            .lookupFunction<
+              _EngineSetLayerAlphaLockNative,
+              _EngineSetLayerAlphaLockDart
+            >('engine_set_layer_alpha_lock');
+      } catch (_) {
+        _setLayerAlphaLock = null;
+      }
       try {
         _setLayerBlendMode = _lib
             .lookupFunction<
@@ -560,6 +830,148 @@ class CanvasEngineFfi {
       } catch (_) {
         _reorderLayer = null;
       }
+      try {
+        _insertLayer = _lib
+            .lookupFunction<_EngineInsertLayerNative, _EngineInsertLayerDart>(
+              'engine_insert_layer',
+            );
+        _removeLayer = _lib
+            .lookupFunction<_EngineRemoveLayerNative, _EngineRemoveLayerDart>(
+              'engine_remove_layer',
+            );
+      } catch (_) {
+        _insertLayer = null;
+        _removeLayer = null;
+      }
+
+      // Optional layer groups.
+      try {
+        _createLayerGroup = _lib
+            .lookupFunction<
+              _EngineCreateLayerGroupNative,
+              _EngineCreateLayerGroupDart
+            >('engine_create_layer_group');
+        _deleteLayerGroup = _lib
+            .lookupFunction<
+              _EngineDeleteLayerGroupNative,
+              _EngineDeleteLayerGroupDart
+            >('engine_delete_layer_group');
+        _setLayerGroupParent = _lib
+            .lookupFunction<
+              _EngineSetLayerGroupParentNative,
+              _EngineSetLayerGroupParentDart
+            >('engine_set_layer_group_parent');
+        _moveLayerToGroup = _lib
+            .lookupFunction<
+              _EngineMoveLayerToGroupNative,
+              _EngineMoveLayerToGroupDart
+            >('engine_move_layer_to_group');
+        _setLayerGroupOpacity = _lib
+            .lookupFunction<
+              _EngineSetLayerGroupOpacityNative,
+              _EngineSetLayerGroupOpacityDart
+            >('engine_set_layer_group_opacity');
+        _setLayerGroupVisible = _lib
+            .lookupFunction<
+              _EngineSetLayerGroupVisibleNative,
+              _EngineSetLayerGroupVisibleDart
+            >('engine_set_layer_group_visible');
+        _setLayerGroupBlendMode = _lib
+            .lookupFunction<
+              _EngineSetLayerGroupBlendModeNative,
+              _EngineSetLayerGroupBlendModeDart
+            >('engine_set_layer_group_blend_mode');
+        _setLayerGroupPassThrough = _lib
+            .lookupFunction<
+              _EngineSetLayerGroupPassThroughNative,
+              _EngineSetLayerGroupPassThroughDart
+            >('engine_set_layer_group_pass_through');
+        _setLayerGroupCollapsed = _lib
+            .lookupFunction<
+              _EngineSetLayerGroupCollapsedNative,
+              _EngineSetLayerGroupCollapsedDart
+            >('engine_set_layer_group_collapsed');
+        _getLayerGroups = _lib
+            .lookupFunction<
+              _EngineGetLayerGroupsNative,
+              _EngineGetLayerGroupsDart
+            >('engine_get_layer_groups');
+      } catch (_) {
+        _createLayerGroup = null;
+        _deleteLayerGroup = null;
+        _setLayerGroupParent = null;
+        _moveLayerToGroup = null;
+        _setLayerGroupOpacity = null;
+        _setLayerGroupVisible = null;
+        _setLayerGroupBlendMode = null;
+        _setLayerGroupPassThrough = null;
+        _setLayerGroupCollapsed = null;
+        _getLayerGroups = null;
+      }
+
+      // Optional layer masks.
+      try {
+        _createLayerMask = _lib
+            .lookupFunction<
+              _EngineCreateLayerMaskNative,
+              _EngineCreateLayerMaskDart
+            >('engine_create_layer_mask');
+        _deleteLayerMask = _lib
+            .lookupFunction<
+              _EngineDeleteLayerMaskNative,
+              _EngineDeleteLayerMaskDart
+            >('engine_delete_layer_mask');
+        _setLayerMaskEnabled = _lib
+            .lookupFunction<
+              _EngineSetLayerMaskEnabledNative,
+              _EngineSetLayerMaskEnabledDart
+            >('engine_set_layer_mask_enabled');
+        _setLayerMaskEditing = _lib
+            .lookupFunction<
+              _EngineSetLayerMaskEditingNative,
+              _EngineSetLayerMaskEditingDart
+            >('engine_set_layer_mask_editing');
+        _invertLayerMask = _lib
+            .lookupFunction<
+              _EngineInvertLayerMaskNative,
+              _EngineInvertLayerMaskDart
+            >('engine_invert_layer_mask');
+        _applyLayerMask = _lib
+            .lookupFunction<
+              _EngineApplyLayerMaskNative,
+              _EngineApplyLayerMaskDart
+            >('engine_apply_layer_mask');
+        _readLayerMask = _lib
+            .lookupFunction<
+              _EngineReadLayerMaskNative,
+              _EngineReadLayerMaskDart
+            >('engine_read_layer_mask');
+      } catch (_) {
+        _createLayerMask = null;
+        _deleteLayerMask = null;
+        _setLayerMaskEnabled = null;
+        _setLayerMaskEditing = null;
+        _invertLayerMask = null;
+        _applyLayerMask = null;
+        _readLayerMask = null;
+      }
+
+      // Optional adjustment layers.
+      try {
+        _setLayerAdjustment = _lib
+            .lookupFunction<
+              _EngineSetLayerAdjustmentNative,
+              _EngineSetLayerAdjustmentDart
+            >('engine_set_layer_adjustment');
+        _clearLayerAdjustment = _lib
+            .lookupFunction<
+              _EngineClearLayerAdjustmentNative,
+              _EngineClearLayerAdjustmentDart
+            >('engine_clear_layer_adjustment');
+      } catch (_) {
+        _setLayerAdjustment = null;
+        _clearLayerAdjustment = null;
+      }
       try {
         _setViewFlags = _lib
             .lookupFunction<_EngineSetViewFlagsNative, _EngineSetViewFlagsDart>(
@@ -695,6 +1107,38 @@ class CanvasEngineFfi {
       } catch (_) {
         _redo = null;
       }
+      try {
+        _setUndoLimits = _lib
+            .lookupFunction<
+              _EngineSetUndoLimitsNative,
+              _EngineSetUndoLimitsDart
+            >('engine_set_undo_limits');
+      } catch (_) {
+        _setUndoLimits = null;
+      }
+
+      // Optional undo transactions.
+      try {
+        _beginUndoTransaction = _lib
+            .lookupFunction<
+              _EngineBeginUndoTransactionNative,
+              _EngineBeginUndoTransactionDart
+            >('engine_begin_undo_transaction');
+        _commitUndoTransaction = _lib
+            .lookupFunction<
+              _EngineCommitUndoTransactionNative,
+              _EngineCommitUndoTransactionDart
+            >('engine_commit_undo_transaction');
+        _cancelUndoTransaction = _lib
+            .lookupFunction<
+              _EngineCancelUndoTransactionNative,
+              _EngineCancelUndoTransactionDart
+            >('engine_cancel_undo_transaction');
+      } catch (_) {
+        _beginUndoTransaction = null;
+        _commitUndoTransaction = null;
+        _cancelUndoTransaction = null;
+      }
 
       // Optional brush settings (color/size/etc).
       try {
@@ -763,6 +1207,66 @@ class CanvasEngineFfi {
       } catch (_) {
         _applyAntialias = null;
       }
+
+      // Optional pressure curves and device calibration.
+      try {
+        _setPressureCurve = _lib
+            .lookupFunction<
+              _EngineSetPressureCurveNative,
+              _EngineSetPressureCurveDart
+            >('engine_set_pressure_curve');
+        _setPressureCurveTable = _lib
+            .lookupFunction<
+              _EngineSetPressureCurveNative,
+              _EngineSetPressureCurveDart
+            >('engine_set_pressure_curve_table');
+      } catch (_) {
+        _setPressureCurve = null;
+        _setPressureCurveTable = null;
+      }
+      try {
+        _setDeviceCalibration = _lib
+            .lookupFunction<
+              _EngineSetPressureCurveNative,
+              _EngineSetPressureCurveDart
+            >('engine_set_device_calibration');
+      } catch (_) {
+        _setDeviceCalibration = null;
+      }
+
+      // Optional symmetry painting.
+      try {
+        _setSymmetry = _lib
+            .lookupFunction<_EngineSetSymmetryNative, _EngineSetSymmetryDart>(
+              'engine_set_symmetry',
+            );
+      } catch (_) {
+        _setSymmetry = null;
+      }
+
+      // Optional .rin project save/load.
+      try {
+        _encodeRin = _lib
+            .lookupFunction<_EngineEncodeRinNative, _EngineEncodeRinDart>(
+              'engine_encode_rin',
+            );
+        _freeRin = _lib
+            .lookupFunction<_EngineFreeRinNative, _EngineFreeRinDart>(
+              'engine_free_rin',
+            );
+      } catch (_) {
+        _encodeRin = null;
+        _freeRin = null;
+      }
+      try {
+        _createFromRin = _lib
+            .lookupFunction<
+              _EngineCreateFromRinNative,
+              _EngineCreateFromRinDart
+            >('engine_create_from_rin');
+      } catch (_) {
+        _createFromRin = null;
+      }
       try {
         _logPop = _lib.lookupFunction<_EngineLogPopNative, _EngineLogPopDart>(
           'engine_log_pop',
@@ -797,8 +1301,30 @@ class CanvasEngineFfi {
   late final _EngineSetLayerOpacityDart? _setLayerOpacity;
   late final _EngineSetLayerVisibleDart? _setLayerVisible;
   late final _EngineSetLayerClippingMaskDart? _setLayerClippingMask;
+  late final _EngineSetLayerAlphaLockDart? _setLayerAlphaLock;
   late final _EngineSetLayerBlendModeDart? _setLayerBlendMode;
   late final _EngineReorderLayerDart? _reorderLayer;
+  late final _EngineInsertLayerDart? _insertLayer;
+  late final _EngineRemoveLayerDart? _removeLayer;
+  late final _EngineCreateLayerGroupDart? _createLayerGroup;
+  late final _EngineDeleteLayerGroupDart? _deleteLayerGroup;
+  late final _EngineSetLayerGroupParentDart? _setLayerGroupParent;
+  late final _EngineMoveLayerToGroupDart? _moveLayerToGroup;
+  late final _EngineSetLayerGroupOpacityDart? _setLayerGroupOpacity;
+  late final _EngineSetLayerGroupVisibleDart? _setLayerGroupVisible;
+  late final _EngineSetLayerGroupBlendModeDart? _setLayerGroupBlendMode;
+  late final _EngineSetLayerGroupPassThroughDart? _setLayerGroupPassThrough;
+  late final _EngineSetLayerGroupCollapsedDart? _setLayerGroupCollapsed;
+  late final _EngineGetLayerGroupsDart? _getLayerGroups;
+  late final _EngineCreateLayerMaskDart? _createLayerMask;
+  late final _EngineDeleteLayerMaskDart? _deleteLayerMask;
+  late final _EngineSetLayerMaskEnabledDart? _setLayerMaskEnabled;
+  late final _EngineSetLayerMaskEditingDart? _setLayerMaskEditing;
+  late final _EngineInvertLayerMaskDart? _invertLayerMask;
+  late final _EngineApplyLayerMaskDart? _applyLayerMask;
+  late final _EngineReadLayerMaskDart? _readLayerMask;
+  late final _EngineSetLayerAdjustmentDart? _setLayerAdjustment;
+  late final _EngineClearLayerAdjustmentDart? _clearLayerAdjustment;
   late final _EngineSetViewFlagsDart? _setViewFlags;
   late final _EngineClearLayerDart? _clearLayer;
   late final _EngineFillLayerDart? _fillLayer;
@@ -815,6 +1341,10 @@ class CanvasEngineFfi {
   late final _EngineResetCanvasDart? _resetCanvas;
   late final _EngineUndoDart? _undo;
   late final _EngineRedoDart? _redo;
+  late final _EngineSetUndoLimitsDart? _setUndoLimits;
+  late final _EngineBeginUndoTransactionDart? _beginUndoTransaction;
+  late final _EngineCommitUndoTransactionDart? _commitUndoTransaction;
+  late final _EngineCancelUndoTransactionDart? _cancelUndoTransaction;
   late final _EngineSetBrushDart? _setBrush;
   late final _EngineSetBrushMaskDart? _setBrushMask;
   late final _EngineClearBrushMaskDart? _clearBrushMask;
@@ -823,12 +1353,25 @@ class CanvasEngineFfi {
   late final _EngineSprayEndDart? _sprayEnd;
   late final _EngineApplyFilterDart? _applyFilter;
   late final _EngineApplyAntialiasDart? _applyAntialias;
+  late final _EngineSetPressureCurveDart? _setPressureCurve;
+  late final _EngineSetPressureCurveDart? _setPressureCurveTable;
+  late final _EngineSetPressureCurveDart? _setDeviceCalibration;
+  late final _EngineSetSymmetryDart? _setSymmetry;
+  late final _EngineEncodeRinDart? _encodeRin;
+  late final _EngineFreeRinDart? _freeRin;
+  late final _EngineCreateFromRinDart? _createFromRin;
   late final _EngineLogPopDart? _logPop;
   late final _EngineLogFreeDart? _logFree;
 
   ffi.Pointer<ffi.Uint8>? _staging;
   int _stagingCapacityBytes = 0;
 
+  // Pending `encodeRin` calls, completed from the worker thread's callback.
+  final Map<int, Completer<Uint8List?>> _rinRequests =
+      <int, Completer<Uint8List?>>{};
+  int _nextRinRequestId = 1;
+  ffi.NativeCallable<_EngineEncodeRinDoneNative>? _rinEncodeDone;
+
   late final bool isSupported;
 
   bool get canCreateEngine =>
@@ -957,6 +1500,20 @@ class CanvasEngineFfi {
     fn(handle, layerIndex, clippingMask ? 1 : 0);
   }
 
+  /// Alpha-locked layers keep their transparency: strokes, spray, bucket
+  /// fill and filters only recolour pixels that already exist.
+  void setLayerAlphaLock({
+    required int handle,
+    required int layerIndex,
+    required bool locked,
+  }) {
+    final fn = _setLayerAlphaLock;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, layerIndex, locked ? 1 : 0);
+  }
+
   void setLayerBlendMode({
     required int handle,
     required int layerIndex,
@@ -984,6 +1541,265 @@ class CanvasEngineFfi {
     fn(handle, fromIndex, toIndex);
   }
 
+  /// Inserts a transparent layer at [layerIndex], moving the layers from
+  /// there on up by one. Undo removes it again.
+  void insertLayer({required int handle, required int layerIndex}) {
+    final fn = _insertLayer;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, layerIndex);
+  }
+
+  /// Removes the layer at [layerIndex]; undo brings it back. The last layer
+  /// is never removed.
+  void removeLayer({required int handle, required int layerIndex}) {
+    final fn = _removeLayer;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, layerIndex);
+  }
+
+  /// Creates a group inside [parentGroupId] (0 is the canvas) and returns
+  /// its id, or 0 when the engine could not create it.
+  int createLayerGroup({required int handle, int parentGroupId = 0}) {
+    final fn = _createLayerGroup;
+    if (!isSupported || fn == null || handle == 0) {
+      return 0;
+    }
+    return fn(handle, parentGroupId);
+  }
+
+  /// Deletes the group; its layers and subgroups move up to its parent.
+  void deleteLayerGroup({required int handle, required int groupId}) {
+    final fn = _deleteLayerGroup;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, groupId);
+  }
+
+  void setLayerGroupParent({
+    required int handle,
+    required int groupId,
+    required int parentGroupId,
+  }) {
+    final fn = _setLayerGroupParent;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, groupId, parentGroupId);
+  }
+
+  void moveLayerToGroup({
+    required int handle,
+    required int layerIndex,
+    required int groupId,
+  }) {
+    final fn = _moveLayerToGroup;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, layerIndex, groupId);
+  }
+
+  void setLayerGroupOpacity({
+    required int handle,
+    required int groupId,
+    required double opacity,
+  }) {
+    final fn = _setLayerGroupOpacity;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, groupId, opacity);
+  }
+
+  void setLayerGroupVisible({
+    required int handle,
+    required int groupId,
+    required bool visible,
+  }) {
+    final fn = _setLayerGroupVisible;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, groupId, visible ? 1 : 0);
+  }
+
+  void setLayerGroupBlendMode({
+    required int handle,
+    required int groupId,
+    required int blendModeIndex,
+  }) {
+    final fn = _setLayerGroupBlendMode;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, groupId, blendModeIndex);
+  }
+
+  void setLayerGroupPassThrough({
+    required int handle,
+    required int groupId,
+    required bool passThrough,
+  }) {
+    final fn = _setLayerGroupPassThrough;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, groupId, passThrough ? 1 : 0);
+  }
+
+  void setLayerGroupCollapsed({
+    required int handle,
+    required int groupId,
+    required bool collapsed,
+  }) {
+    final fn = _setLayerGroupCollapsed;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, groupId, collapsed ? 1 : 0);
+  }
+
+  /// The group tree as the engine stores it: the group count, then per group
+  /// its id, parent id, opacity (float bits), visible, blend mode index,
+  /// pass-through and collapsed flags, then the layer count and the group id
+  /// of every layer, bottom to top.
+  Uint32List? getLayerGroups({required int handle}) {
+    final fn = _getLayerGroups;
+    if (!isSupported || fn == null || handle == 0) {
+      return null;
+    }
+    int capacity = fn(handle, ffi.nullptr, 0);
+    // The tree may grow between the two calls; retry with its new length.
+    while (capacity > 0) {
+      final ffi.Pointer<ffi.Uint32> ptr = malloc.allocate<ffi.Uint32>(
+        capacity * ffi.sizeOf<ffi.Uint32>(),
+      );
+      try {
+        final int len = fn(handle, ptr, capacity);
+        if (len <= capacity) {
+          return Uint32List.fromList(ptr.asTypedList(len));
+        }
+        capacity = len;
+      } finally {
+        malloc.free(ptr);
+      }
+    }
+    return null;
+  }
+
+  void createLayerMask({required int handle, required int layerIndex}) {
+    final fn = _createLayerMask;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, layerIndex);
+  }
+
+  void deleteLayerMask({required int handle, required int layerIndex}) {
+    final fn = _deleteLayerMask;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, layerIndex);
+  }
+
+  void setLayerMaskEnabled({
+    required int handle,
+    required int layerIndex,
+    required bool enabled,
+  }) {
+    final fn = _setLayerMaskEnabled;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, layerIndex, enabled ? 1 : 0);
+  }
+
+  /// Routes brush strokes on the active layer to its mask, when it has one.
+  void setLayerMaskEditing({required int handle, required bool editing}) {
+    final fn = _setLayerMaskEditing;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, editing ? 1 : 0);
+  }
+
+  void invertLayerMask({required int handle, required int layerIndex}) {
+    final fn = _invertLayerMask;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, layerIndex);
+  }
+
+  /// Bakes the mask into the layer's alpha and removes it.
+  void applyLayerMask({required int handle, required int layerIndex}) {
+    final fn = _applyLayerMask;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, layerIndex);
+  }
+
+  /// The mask of [layerIndex], one coverage byte per pixel, or null when
+  /// the layer has none.
+  Uint8List? readLayerMask({
+    required int handle,
+    required int layerIndex,
+    required int width,
+    required int height,
+  }) {
+    final fn = _readLayerMask;
+    if (!isSupported || fn == null || handle == 0) {
+      return null;
+    }
+    if (width <= 0 || height <= 0) {
+      return null;
+    }
+    final int len = width * height;
+    final ffi.Pointer<ffi.Uint8> outPtr = malloc.allocate<ffi.Uint8>(len);
+    try {
+      if (fn(handle, layerIndex, outPtr, len) == 0) {
+        return null;
+      }
+      return Uint8List.fromList(outPtr.asTypedList(len));
+    } finally {
+      malloc.free(outPtr);
+    }
+  }
+
+  /// Turns [layerIndex] into an adjustment layer that runs [filterType] over
+  /// the layers below it. Parameters use the same units as [applyFilter].
+  void setLayerAdjustment({
+    required int handle,
+    required int layerIndex,
+    required int filterType,
+    double param0 = 0.0,
+    double param1 = 0.0,
+    double param2 = 0.0,
+    double param3 = 0.0,
+  }) {
+    final fn = _setLayerAdjustment;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, layerIndex, filterType, param0, param1, param2, param3);
+  }
+
+  /// Turns an adjustment layer back into a pixel layer.
+  void clearLayerAdjustment({required int handle, required int layerIndex}) {
+    final fn = _clearLayerAdjustment;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, layerIndex);
+  }
+
   void setViewFlags({
     required int handle,
     required bool mirror,
@@ -1390,6 +2206,52 @@ class CanvasEngineFfi {
     fn(handle);
   }
 
+  /// Keeps at most [maxSteps] undo steps, counting every history branch,
+  /// and [budgetBytes] of tile patches ready in VRAM (raw memory on the CPU
+  /// engine); older patches are compressed.
+  void setUndoLimits({
+    required int handle,
+    required int maxSteps,
+    required int budgetBytes,
+  }) {
+    final fn = _setUndoLimits;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(
+      handle,
+      maxSteps.clamp(1, 0xFFFFFFFF),
+      budgetBytes < 0 ? 0 : budgetBytes,
+    );
+  }
+
+  /// Pixel changes until [commitUndoTransaction], on any layer, become one
+  /// undo step. Beginning while a transaction is open joins it.
+  void beginUndoTransaction({required int handle}) {
+    final fn = _beginUndoTransaction;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle);
+  }
+
+  void commitUndoTransaction({required int handle}) {
+    final fn = _commitUndoTransaction;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle);
+  }
+
+  /// Ends the transaction and restores every pixel changed since it began.
+  void cancelUndoTransaction({required int handle}) {
+    final fn = _cancelUndoTransaction;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle);
+  }
+
   void setBrush({
     required int handle,
     required int colorArgb,
@@ -1669,6 +2531,157 @@ class CanvasEngineFfi {
     return result != 0;
   }
 
+  /// Saves the engine's layers as a `.rin` project. The readback and the
+  /// compression run off the UI thread; completes with `null` on failure.
+  /// [template] is an existing `.rin` file that supplies the document
+  /// metadata, layer ids, names and text blocks.
+  /// Sets one brush pressure curve from interleaved `(pressure, value)`
+  /// pairs. [target] is 0 size, 1 opacity, 2 flow, 3 hardness or 4 scatter;
+  /// null or empty [points] clears the curve.
+  void setPressureCurve({
+    required int handle,
+    required int target,
+    Float32List? points,
+  }) {
+    _sendCurve(_setPressureCurve, handle, target, points, 2);
+  }
+
+  /// Like [setPressureCurve], from a lookup table spread evenly over
+  /// pressures 0 to 1.
+  void setPressureCurveTable({
+    required int handle,
+    required int target,
+    Float32List? values,
+  }) {
+    _sendCurve(_setPressureCurveTable, handle, target, values, 1);
+  }
+
+  /// Sets the pressure calibration of the pointer [device] (Flutter's
+  /// `PointerEvent.device`). The engine keeps it across brush changes and
+  /// applies it before the brush curves; null or empty [points] clears it.
+  void setDeviceCalibration({
+    required int handle,
+    required int device,
+    Float32List? points,
+  }) {
+    _sendCurve(_setDeviceCalibration, handle, device, points, 2);
+  }
+
+  /// Repeats strokes around ([centerX], [centerY]) in canvas pixels. [mode]
+  /// 0 is off, 1 mirrors left to right, 2 top to bottom, 3 both, 4 is
+  /// [folds]-way radial symmetry and 5 a kaleidoscope that also mirrors every
+  /// radial copy. Kept across brush changes.
+  void setSymmetry({
+    required int handle,
+    required int mode,
+    required double centerX,
+    required double centerY,
+    int folds = 6,
+  }) {
+    final fn = _setSymmetry;
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    fn(handle, mode, centerX, centerY, folds);
+  }
+
+  void _sendCurve(
+    _EngineSetPressureCurveDart? fn,
+    int handle,
+    int key,
+    Float32List? values,
+    int stride,
+  ) {
+    if (!isSupported || fn == null || handle == 0) {
+      return;
+    }
+    final int count = (values?.length ?? 0) ~/ stride;
+    if (values == null || count == 0) {
+      fn(handle, key, ffi.nullptr, 0);
+      return;
+    }
+    final int floatCount = count * stride;
+    final ffi.Pointer<ffi.Float> ptr = malloc.allocate<ffi.Float>(
+      floatCount * ffi.sizeOf<ffi.Float>(),
+    );
+    ptr.asTypedList(floatCount).setRange(0, floatCount, values, 0);
+    try {
+      fn(handle, key, ptr, count);
+    } finally {
+      malloc.free(ptr);
+    }
+  }
+
+  Future<Uint8List?> encodeRin({required int handle, Uint8List? template}) {
+    final fn = _encodeRin;
+    if (!isSupported || fn == null || _freeRin == null || handle == 0) {
+      return Future<Uint8List?>.value(null);
+    }
+    final ffi.NativeCallable<_EngineEncodeRinDoneNative> onDone =
+        _rinEncodeDone ??=
+            ffi.NativeCallable<_EngineEncodeRinDoneNative>.listener(
+              _onRinEncoded,
+            );
+    final int requestId = _nextRinRequestId++;
+    final Completer<Uint8List?> completer = Completer<Uint8List?>();
+    _rinRequests[requestId] = completer;
+    final int templateLen = template?.length ?? 0;
+    final ffi.Pointer<ffi.Uint8> templatePtr = templateLen == 0
+        ? ffi.nullptr
+        : malloc.allocate<ffi.Uint8>(templateLen);
+    int queued = 0;
+    try {
+      if (templateLen > 0) {
+        templatePtr.asTypedList(templateLen).setAll(0, template!);
+      }
+      queued = fn(
+        handle,
+        templatePtr,
+        templateLen,
+        requestId,
+        onDone.nativeFunction,
+      );
+    } finally {
+      if (templatePtr != ffi.nullptr) {
+        malloc.free(templatePtr);
+      }
+    }
+    if (queued == 0) {
+      _rinRequests.remove(requestId);
+      return Future<Uint8List?>.value(null);
+    }
+    return completer.future;
+  }
+
+  void _onRinEncoded(int requestId, ffi.Pointer<ffi.Uint8> bytes, int len) {
+    final Completer<Uint8List?>? completer = _rinRequests.remove(requestId);
+    if (bytes == ffi.nullptr) {
+      completer?.complete(null);
+      return;
+    }
+    final Uint8List result = Uint8List.fromList(bytes.asTypedList(len));
+    _freeRin?.call(bytes, len);
+    completer?.complete(result);
+  }
+
+  /// Creates an engine sized to a `.rin` project and loads its layers,
+  /// masks, adjustment layers and groups. Returns 0 on failure.
+  int createEngineFromRin(Uint8List bytes) {
+    final fn = _createFromRin;
+    if (!isSupported || fn == null || bytes.isEmpty) {
+      return 0;
+    }
+    final ffi.Pointer<ffi.Uint8> ptr = malloc.allocate<ffi.Uint8>(
+      bytes.length,
+    );
+    ptr.asTypedList(bytes.length).setAll(0, bytes);
+    try {
+      return fn(ptr, bytes.length);
+    } finally {
+      malloc.free(ptr);
+    }
+  }
+
   ffi.Pointer<ffi.Uint8> _ensureStaging(int requiredBytes) {
     final ffi.Pointer<ffi.Uint8>? existing = _staging;
     if (existing != null && _stagingCapacityBytes >= requiredBytes) {
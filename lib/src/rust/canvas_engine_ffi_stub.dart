@@ -122,6 +122,12 @@ class CanvasEngineFfi {
     required bool clippingMask,
   }) {}
 
+  void setLayerAlphaLock({
+    required int handle,
+    required int layerIndex,
+    required bool locked,
+  }) {}
+
   void setLayerBlendMode({
     required int handle,
     required int layerIndex,
@@ -134,6 +140,95 @@ class CanvasEngineFfi {
     required int toIndex,
   }) {}
 
+  void insertLayer({required int handle, required int layerIndex}) {}
+
+  void removeLayer({required int handle, required int layerIndex}) {}
+
+  int createLayerGroup({required int handle, int parentGroupId = 0}) => 0;
+
+  void deleteLayerGroup({required int handle, required int groupId}) {}
+
+  void setLayerGroupParent({
+    required int handle,
+    required int groupId,
+    required int parentGroupId,
+  }) {}
+
+  void moveLayerToGroup({
+    required int handle,
+    required int layerIndex,
+    required int groupId,
+  }) {}
+
+  void setLayerGroupOpacity({
+    required int handle,
+    required int groupId,
+    required double opacity,
+  }) {}
+
+  void setLayerGroupVisible({
+    required int handle,
+    required int groupId,
+    required bool visible,
+  }) {}
+
+  void setLayerGroupBlendMode({
+    required int handle,
+    required int groupId,
+    required int blendModeIndex,
+  }) {}
+
+  void setLayerGroupPassThrough({
+    required int handle,
+    required int groupId,
+    required bool passThrough,
+  }) {}
+
+  void setLayerGroupCollapsed({
+    required int handle,
+    required int groupId,
+    required bool collapsed,
+  }) {}
+
+  Uint32List? getLayerGroups({required int handle}) => null;
+
+  void createLayerMask({required int handle, required int layerIndex}) {}
+
+  void deleteLayerMask({required int handle, required int layerIndex}) {}
+
+  void setLayerMaskEnabled({
+    required int handle,
+    required int layerIndex,
+    required bool enabled,
+  }) {}
+
+  void setLayerMaskEditing({required int handle, required bool editing}) {}
+
+  void invertLayerMask({required int handle, required int layerIndex}) {}
+
+  void applyLayerMask({required int handle, required int layerIndex}) {}
+
+  Uint8List? readLayerMask({
+    required int handle,
+    required int layerIndex,
+    required int width,
+    required int height,
+  }) {
+    return null;
+  }
+
+  void setLayerAdjustment({
+    required int handle,
+    required int layerIndex,
+    required int filterType,
+    double param0 = 0.0,
+    double param1 = 0.0,
+    double param2 = 0.0,
+    double param3 = 0.0,
+  }) {}
+
+  void clearLayerAdjustment({required int handle, required int layerIndex}) {}
+
   void setViewFlags({
     required int handle,
     required bool mirror,
@@ -244,4 +339,48 @@ class CanvasEngineFfi {
   void undo({required int handle}) {}
 
   void redo({required int handle}) {}
+
+  void setUndoLimits({
+    required int handle,
+    required int maxSteps,
+    required int budgetBytes,
+  }) {}
+
+  void beginUndoTransaction({required int handle}) {}
+
+  void commitUndoTransaction({required int handle}) {}
+
+  void cancelUndoTransaction({required int handle}) {}
+
+  void setPressureCurve({
+    required int handle,
+    required int target,
+    Float32List? points,
+  }) {}
+
+  void setPressureCurveTable({
+    required int handle,
+    required int target,
+    Float32List? values,
+  }) {}
+
+  void setDeviceCalibration({
+    required int handle,
+    required int device,
+    Float32List? points,
+  }) {}
+
+  void setSymmetry({
+    required int handle,
+    required int mode,
+    required double centerX,
+    required double centerY,
+    int folds = 6,
+  }) {}
+
+  Future<Uint8List?> encodeRin({required int handle, Uint8List? template}) {
+    return Future<Uint8List?>.value(null);
+  }
+
+  int createEngineFromRin(Uint8List bytes) => 0;
 }
@@ -10,6 +10,8 @@ import 'api/cpu_brush.dart';
 import 'api/cpu_filters.dart';
 import 'api/cpu_image.dart';
 import 'api/cpu_transform.dart';
+import 'api/engine_events.dart';
+import 'api/engine_history.dart';
 import 'api/gpu_brush.dart';
 import 'api/gpu_composite.dart';
 import 'api/image_ops.dart';
@@ -322,6 +324,14 @@ abstract class RustLibApi extends BaseApi {
     required BigInt overflowCapacity,
   });
 
+  Stream<EngineEvent> crateApiEngineEventsEngineEventStream({
+    required BigInt handle,
+  });
+
+  Future<List<EngineHistoryNode>> crateApiEngineHistoryEngineHistory({
+    required BigInt handle,
+  });
+
   Future<FloodFillRect> crateApiBucketFillFloodFillInPlace({
     required BigInt ptr,
     required int width,
@@ -399,6 +409,11 @@ abstract class RustLibApi extends BaseApi {
 
   Future<PsdDocument> crateApiPsdImportPsd({required List<int> bytes});
 
+  Uint8List? crateApiPsdPsdAdjustmentLayerInfo({
+    required int filterType,
+    required List<double> params,
+  });
+
   Future<void> crateApiSimpleInitApp();
 
   Future<Uint8List?> crateApiBucketFillMagicWandMask({
@@ -1632,6 +1647,74 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
         ],
       );
 
+  @override
+  Stream<EngineEvent> crateApiEngineEventsEngineEventStream({
+    required BigInt handle,
+  }) {
+    final sink = RustStreamSink<EngineEvent>();
+    unawaited(
+      handler.executeNormal(
+        NormalTask(
+          callFfi: (port_) {
+            final serializer = SseSerializer(generalizedFrbRustBinding);
+            sse_encode_u_64(handle, serializer);
+            sse_encode_StreamSink_engine_event_Sse(sink, serializer);
+            pdeCallFfi(
+              generalizedFrbRustBinding,
+              serializer,
+              funcId: 48,
+              port: port_,
+            );
+          },
+          codec: SseCodec(
+            decodeSuccessData: sse_decode_unit,
+            decodeErrorData: null,
+          ),
+          constMeta: kCrateApiEngineEventsEngineEventStreamConstMeta,
+          argValues: [handle, sink],
+          apiImpl: this,
+        ),
+      ),
+    );
+    return sink.stream;
+  }
+
+  TaskConstMeta get kCrateApiEngineEventsEngineEventStreamConstMeta =>
+      const TaskConstMeta(
+        debugName: "engine_event_stream",
+        argNames: ["handle", "sink"],
+      );
+
+  @override
+  Future<List<EngineHistoryNode>> crateApiEngineHistoryEngineHistory({
+    required BigInt handle,
+  }) {
+    return handler.executeNormal(
+      NormalTask(
+        callFfi: (port_) {
+          final serializer = SseSerializer(generalizedFrbRustBinding);
+          sse_encode_u_64(handle, serializer);
+          pdeCallFfi(
+            generalizedFrbRustBinding,
+            serializer,
+            funcId: 49,
+            port: port_,
+          );
+        },
+        codec: SseCodec(
+          decodeSuccessData: sse_decode_list_engine_history_node,
+          decodeErrorData: null,
+        ),
+        constMeta: kCrateApiEngineHistoryEngineHistoryConstMeta,
+        argValues: [handle],
+        apiImpl: this,
+      ),
+    );
+  }
+
+  TaskConstMeta get kCrateApiEngineHistoryEngineHistoryConstMeta =>
+      const TaskConstMeta(debugName: "engine_history", argNames: ["handle"]);
+
   @override
   Future<FloodFillRect> crateApiBucketFillFloodFillInPlace({
     required BigInt ptr,
@@ -2169,6 +2252,36 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
   TaskConstMeta get kCrateApiPsdImportPsdConstMeta =>
       const TaskConstMeta(debugName: "import_psd", argNames: ["bytes"]);
 
+  @override
+  Uint8List? crateApiPsdPsdAdjustmentLayerInfo({
+    required int filterType,
+    required List<double> params,
+  }) {
+    return handler.executeSync(
+      SyncTask(
+        callFfi: () {
+          final serializer = SseSerializer(generalizedFrbRustBinding);
+          sse_encode_u_32(filterType, serializer);
+          sse_encode_list_prim_f_32_loose(params, serializer);
+          return pdeCallFfi(generalizedFrbRustBinding, serializer, funcId: 47)!;
+        },
+        codec: SseCodec(
+          decodeSuccessData: sse_decode_opt_list_prim_u_8_strict,
+          decodeErrorData: null,
+        ),
+        constMeta: kCrateApiPsdPsdAdjustmentLayerInfoConstMeta,
+        argValues: [filterType, params],
+        apiImpl: this,
+      ),
+    );
+  }
+
+  TaskConstMeta get kCrateApiPsdPsdAdjustmentLayerInfoConstMeta =>
+      const TaskConstMeta(
+        debugName: "psd_adjustment_layer_info",
+        argNames: ["filterType", "params"],
+      );
+
   @override
   Future<void> crateApiSimpleInitApp() {
     return handler.executeNormal(
@@ -2578,6 +2691,20 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
   TaskConstMeta get kCrateApiWorkspaceWorkspaceStateDefaultConstMeta =>
       const TaskConstMeta(debugName: "workspace_state_default", argNames: []);
 
+  @protected
+  AnyhowException dco_decode_AnyhowException(dynamic raw) {
+    // Codec=Dco (DartCObject based), see doc to use other codecs
+    return AnyhowException(raw as String);
+  }
+
+  @protected
+  RustStreamSink<EngineEvent> dco_decode_StreamSink_engine_event_Sse(
+    dynamic raw,
+  ) {
+    // Codec=Dco (DartCObject based), see doc to use other codecs
+    throw UnimplementedError();
+  }
+
   @protected
   String dco_decode_String(dynamic raw) {
     // Codec=Dco (DartCObject based), see doc to use other codecs
@@ -2820,6 +2947,58 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     );
   }
 
+  @protected
+  EngineEvent dco_decode_engine_event(dynamic raw) {
+    // Codec=Dco (DartCObject based), see doc to use other codecs
+    final arr = raw as List<dynamic>;
+    if (arr.length != 11)
+      throw Exception('unexpected arr length: expect 11 but see ${arr.length}');
+    return EngineEvent(
+      kind: dco_decode_engine_event_kind(arr[0]),
+      layerIndex: dco_decode_u_32(arr[1]),
+      left: dco_decode_i_32(arr[2]),
+      top: dco_decode_i_32(arr[3]),
+      width: dco_decode_i_32(arr[4]),
+      height: dco_decode_i_32(arr[5]),
+      onMask: dco_decode_bool(arr[6]),
+      canUndo: dco_decode_bool(arr[7]),
+      canRedo: dco_decode_bool(arr[8]),
+      applied: dco_decode_bool(arr[9]),
+      message: dco_decode_String(arr[10]),
+    );
+  }
+
+  @protected
+  EngineEventKind dco_decode_engine_event_kind(dynamic raw) {
+    // Codec=Dco (DartCObject based), see doc to use other codecs
+    return EngineEventKind.values[raw as int];
+  }
+
+  @protected
+  EngineHistoryKind dco_decode_engine_history_kind(dynamic raw) {
+    // Codec=Dco (DartCObject based), see doc to use other codecs
+    return EngineHistoryKind.values[raw as int];
+  }
+
+  @protected
+  EngineHistoryNode dco_decode_engine_history_node(dynamic raw) {
+    // Codec=Dco (DartCObject based), see doc to use other codecs
+    final arr = raw as List<dynamic>;
+    if (arr.length != 9)
+      throw Exception('unexpected arr length: expect 9 but see ${arr.length}');
+    return EngineHistoryNode(
+      id: dco_decode_u_64(arr[0]),
+      parentId: dco_decode_u_64(arr[1]),
+      kind: dco_decode_engine_history_kind(arr[2]),
+      label: dco_decode_String(arr[3]),
+      current: dco_decode_bool(arr[4]),
+      onActiveBranch: dco_decode_bool(arr[5]),
+      thumbnailWidth: dco_decode_u_32(arr[6]),
+      thumbnailHeight: dco_decode_u_32(arr[7]),
+      thumbnail: dco_decode_list_prim_u_8_strict(arr[8]),
+    );
+  }
+
   @protected
   double dco_decode_f_32(dynamic raw) {
     // Codec=Dco (DartCObject based), see doc to use other codecs
@@ -2865,14 +3044,15 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
   GpuLayerData dco_decode_gpu_layer_data(dynamic raw) {
     // Codec=Dco (DartCObject based), see doc to use other codecs
     final arr = raw as List<dynamic>;
-    if (arr.length != 5)
-      throw Exception('unexpected arr length: expect 5 but see ${arr.length}');
+    if (arr.length != 6)
+      throw Exception('unexpected arr length: expect 6 but see ${arr.length}');
     return GpuLayerData(
       pixels: dco_decode_list_prim_u_32_strict(arr[0]),
       opacity: dco_decode_f_64(arr[1]),
       blendModeIndex: dco_decode_u_32(arr[2]),
       visible: dco_decode_bool(arr[3]),
       clippingMask: dco_decode_bool(arr[4]),
+      mask: dco_decode_opt_list_prim_u_8_strict(arr[5]),
     );
   }
 
@@ -2918,6 +3098,14 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     return (raw as List<dynamic>).map(dco_decode_cpu_brush_command).toList();
   }
 
+  @protected
+  List<EngineHistoryNode> dco_decode_list_engine_history_node(dynamic raw) {
+    // Codec=Dco (DartCObject based), see doc to use other codecs
+    return (raw as List<dynamic>)
+        .map(dco_decode_engine_history_node)
+        .toList();
+  }
+
   @protected
   List<GpuLayerData> dco_decode_list_gpu_layer_data(dynamic raw) {
     // Codec=Dco (DartCObject based), see doc to use other codecs
@@ -2978,6 +3166,12 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     return raw as Uint8List;
   }
 
+  @protected
+  List<PsdGroup> dco_decode_list_psd_group(dynamic raw) {
+    // Codec=Dco (DartCObject based), see doc to use other codecs
+    return (raw as List<dynamic>).map(dco_decode_psd_group).toList();
+  }
+
   @protected
   List<PsdLayer> dco_decode_list_psd_layer(dynamic raw) {
     // Codec=Dco (DartCObject based), see doc to use other codecs
@@ -3042,12 +3236,29 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
   PsdDocument dco_decode_psd_document(dynamic raw) {
     // Codec=Dco (DartCObject based), see doc to use other codecs
     final arr = raw as List<dynamic>;
-    if (arr.length != 3)
-      throw Exception('unexpected arr length: expect 3 but see ${arr.length}');
+    if (arr.length != 4)
+      throw Exception('unexpected arr length: expect 4 but see ${arr.length}');
     return PsdDocument(
       width: dco_decode_i_32(arr[0]),
       height: dco_decode_i_32(arr[1]),
       layers: dco_decode_list_psd_layer(arr[2]),
+      groups: dco_decode_list_psd_group(arr[3]),
+    );
+  }
+
+  @protected
+  PsdGroup dco_decode_psd_group(dynamic raw) {
+    // Codec=Dco (DartCObject based), see doc to use other codecs
+    final arr = raw as List<dynamic>;
+    if (arr.length != 6)
+      throw Exception('unexpected arr length: expect 6 but see ${arr.length}');
+    return PsdGroup(
+      id: dco_decode_u_32(arr[0]),
+      parentId: dco_decode_u_32(arr[1]),
+      name: dco_decode_String(arr[2]),
+      visible: dco_decode_bool(arr[3]),
+      opacity: dco_decode_u_8(arr[4]),
+      blendModeKey: dco_decode_String(arr[5]),
     );
   }
 
@@ -3055,8 +3266,8 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
   PsdLayer dco_decode_psd_layer(dynamic raw) {
     // Codec=Dco (DartCObject based), see doc to use other codecs
     final arr = raw as List<dynamic>;
-    if (arr.length != 10)
-      throw Exception('unexpected arr length: expect 10 but see ${arr.length}');
+    if (arr.length != 13)
+      throw Exception('unexpected arr length: expect 13 but see ${arr.length}');
     return PsdLayer(
       name: dco_decode_String(arr[0]),
       visible: dco_decode_bool(arr[1]),
@@ -3068,6 +3279,9 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
       bitmapHeight: dco_decode_i_32(arr[7]),
       bitmapLeft: dco_decode_i_32(arr[8]),
       bitmapTop: dco_decode_i_32(arr[9]),
+      parentId: dco_decode_u_32(arr[10]),
+      adjustmentFilterType: dco_decode_opt_box_autoadd_u_32(arr[11]),
+      adjustmentParams: dco_decode_list_prim_f_32_strict(arr[12]),
     );
   }
 
@@ -3126,6 +3340,21 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     );
   }
 
+  @protected
+  AnyhowException sse_decode_AnyhowException(SseDeserializer deserializer) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    var inner = sse_decode_String(deserializer);
+    return AnyhowException(inner);
+  }
+
+  @protected
+  RustStreamSink<EngineEvent> sse_decode_StreamSink_engine_event_Sse(
+    SseDeserializer deserializer,
+  ) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    throw UnimplementedError('Unreachable ()');
+  }
+
   @protected
   String sse_decode_String(SseDeserializer deserializer) {
     // Codec=Sse (Serialization based), see doc to use other codecs
@@ -3398,6 +3627,78 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     );
   }
 
+  @protected
+  EngineEvent sse_decode_engine_event(SseDeserializer deserializer) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    var var_kind = sse_decode_engine_event_kind(deserializer);
+    var var_layerIndex = sse_decode_u_32(deserializer);
+    var var_left = sse_decode_i_32(deserializer);
+    var var_top = sse_decode_i_32(deserializer);
+    var var_width = sse_decode_i_32(deserializer);
+    var var_height = sse_decode_i_32(deserializer);
+    var var_onMask = sse_decode_bool(deserializer);
+    var var_canUndo = sse_decode_bool(deserializer);
+    var var_canRedo = sse_decode_bool(deserializer);
+    var var_applied = sse_decode_bool(deserializer);
+    var var_message = sse_decode_String(deserializer);
+    return EngineEvent(
+      kind: var_kind,
+      layerIndex: var_layerIndex,
+      left: var_left,
+      top: var_top,
+      width: var_width,
+      height: var_height,
+      onMask: var_onMask,
+      canUndo: var_canUndo,
+      canRedo: var_canRedo,
+      applied: var_applied,
+      message: var_message,
+    );
+  }
+
+  @protected
+  EngineEventKind sse_decode_engine_event_kind(SseDeserializer deserializer) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    var inner = sse_decode_i_32(deserializer);
+    return EngineEventKind.values[inner];
+  }
+
+  @protected
+  EngineHistoryKind sse_decode_engine_history_kind(
+    SseDeserializer deserializer,
+  ) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    var inner = sse_decode_i_32(deserializer);
+    return EngineHistoryKind.values[inner];
+  }
+
+  @protected
+  EngineHistoryNode sse_decode_engine_history_node(
+    SseDeserializer deserializer,
+  ) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    var var_id = sse_decode_u_64(deserializer);
+    var var_parentId = sse_decode_u_64(deserializer);
+    var var_kind = sse_decode_engine_history_kind(deserializer);
+    var var_label = sse_decode_String(deserializer);
+    var var_current = sse_decode_bool(deserializer);
+    var var_onActiveBranch = sse_decode_bool(deserializer);
+    var var_thumbnailWidth = sse_decode_u_32(deserializer);
+    var var_thumbnailHeight = sse_decode_u_32(deserializer);
+    var var_thumbnail = sse_decode_list_prim_u_8_strict(deserializer);
+    return EngineHistoryNode(
+      id: var_id,
+      parentId: var_parentId,
+      kind: var_kind,
+      label: var_label,
+      current: var_current,
+      onActiveBranch: var_onActiveBranch,
+      thumbnailWidth: var_thumbnailWidth,
+      thumbnailHeight: var_thumbnailHeight,
+      thumbnail: var_thumbnail,
+    );
+  }
+
   @protected
   double sse_decode_f_32(SseDeserializer deserializer) {
     // Codec=Sse (Serialization based), see doc to use other codecs
@@ -3450,12 +3751,14 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     var var_blendModeIndex = sse_decode_u_32(deserializer);
     var var_visible = sse_decode_bool(deserializer);
     var var_clippingMask = sse_decode_bool(deserializer);
+    var var_mask = sse_decode_opt_list_prim_u_8_strict(deserializer);
     return GpuLayerData(
       pixels: var_pixels,
       opacity: var_opacity,
       blendModeIndex: var_blendModeIndex,
       visible: var_visible,
       clippingMask: var_clippingMask,
+      mask: var_mask,
     );
   }
 
@@ -3516,6 +3819,20 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     return ans_;
   }
 
+  @protected
+  List<EngineHistoryNode> sse_decode_list_engine_history_node(
+    SseDeserializer deserializer,
+  ) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+
+    var len_ = sse_decode_i_32(deserializer);
+    var ans_ = <EngineHistoryNode>[];
+    for (var idx_ = 0; idx_ < len_; ++idx_) {
+      ans_.add(sse_decode_engine_history_node(deserializer));
+    }
+    return ans_;
+  }
+
   @protected
   List<GpuLayerData> sse_decode_list_gpu_layer_data(
     SseDeserializer deserializer,
@@ -3598,6 +3915,18 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     return deserializer.buffer.getUint8List(len_);
   }
 
+  @protected
+  List<PsdGroup> sse_decode_list_psd_group(SseDeserializer deserializer) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+
+    var len_ = sse_decode_i_32(deserializer);
+    var ans_ = <PsdGroup>[];
+    for (var idx_ = 0; idx_ < len_; ++idx_) {
+      ans_.add(sse_decode_psd_group(deserializer));
+    }
+    return ans_;
+  }
+
   @protected
   List<PsdLayer> sse_decode_list_psd_layer(SseDeserializer deserializer) {
     // Codec=Sse (Serialization based), see doc to use other codecs
@@ -3722,10 +4051,31 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     var var_width = sse_decode_i_32(deserializer);
     var var_height = sse_decode_i_32(deserializer);
     var var_layers = sse_decode_list_psd_layer(deserializer);
+    var var_groups = sse_decode_list_psd_group(deserializer);
     return PsdDocument(
       width: var_width,
       height: var_height,
       layers: var_layers,
+      groups: var_groups,
+    );
+  }
+
+  @protected
+  PsdGroup sse_decode_psd_group(SseDeserializer deserializer) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    var var_id = sse_decode_u_32(deserializer);
+    var var_parentId = sse_decode_u_32(deserializer);
+    var var_name = sse_decode_String(deserializer);
+    var var_visible = sse_decode_bool(deserializer);
+    var var_opacity = sse_decode_u_8(deserializer);
+    var var_blendModeKey = sse_decode_String(deserializer);
+    return PsdGroup(
+      id: var_id,
+      parentId: var_parentId,
+      name: var_name,
+      visible: var_visible,
+      opacity: var_opacity,
+      blendModeKey: var_blendModeKey,
     );
   }
 
@@ -3742,6 +4092,9 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     var var_bitmapHeight = sse_decode_i_32(deserializer);
     var var_bitmapLeft = sse_decode_i_32(deserializer);
     var var_bitmapTop = sse_decode_i_32(deserializer);
+    var var_parentId = sse_decode_u_32(deserializer);
+    var var_adjustmentFilterType = sse_decode_opt_box_autoadd_u_32(deserializer);
+    var var_adjustmentParams = sse_decode_list_prim_f_32_strict(deserializer);
     return PsdLayer(
       name: var_name,
       visible: var_visible,
@@ -3753,6 +4106,9 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
       bitmapHeight: var_bitmapHeight,
       bitmapLeft: var_bitmapLeft,
       bitmapTop: var_bitmapTop,
+      parentId: var_parentId,
+      adjustmentFilterType: var_adjustmentFilterType,
+      adjustmentParams: var_adjustmentParams,
     );
   }
 
@@ -3802,6 +4158,32 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     return WorkspaceState(entries: var_entries, activeId: var_activeId);
   }
 
+  @protected
+  void sse_encode_AnyhowException(
+    AnyhowException self,
+    SseSerializer serializer,
+  ) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    throw UnimplementedError('Unreachable ()');
+  }
+
+  @protected
+  void sse_encode_StreamSink_engine_event_Sse(
+    RustStreamSink<EngineEvent> self,
+    SseSerializer serializer,
+  ) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    sse_encode_String(
+      self.setupAndSerialize(
+        codec: SseCodec(
+          decodeSuccessData: sse_decode_engine_event,
+          decodeErrorData: sse_decode_AnyhowException,
+        ),
+      ),
+      serializer,
+    );
+  }
+
   @protected
   void sse_encode_String(String self, SseSerializer serializer) {
     // Codec=Sse (Serialization based), see doc to use other codecs
@@ -4013,6 +4395,57 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     sse_encode_list_prim_u_32_strict(self.overflowColor, serializer);
   }
 
+  @protected
+  void sse_encode_engine_event(EngineEvent self, SseSerializer serializer) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    sse_encode_engine_event_kind(self.kind, serializer);
+    sse_encode_u_32(self.layerIndex, serializer);
+    sse_encode_i_32(self.left, serializer);
+    sse_encode_i_32(self.top, serializer);
+    sse_encode_i_32(self.width, serializer);
+    sse_encode_i_32(self.height, serializer);
+    sse_encode_bool(self.onMask, serializer);
+    sse_encode_bool(self.canUndo, serializer);
+    sse_encode_bool(self.canRedo, serializer);
+    sse_encode_bool(self.applied, serializer);
+    sse_encode_String(self.message, serializer);
+  }
+
+  @protected
+  void sse_encode_engine_event_kind(
+    EngineEventKind self,
+    SseSerializer serializer,
+  ) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    sse_encode_i_32(self.index, serializer);
+  }
+
+  @protected
+  void sse_encode_engine_history_kind(
+    EngineHistoryKind self,
+    SseSerializer serializer,
+  ) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    sse_encode_i_32(self.index, serializer);
+  }
+
+  @protected
+  void sse_encode_engine_history_node(
+    EngineHistoryNode self,
+    SseSerializer serializer,
+  ) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    sse_encode_u_64(self.id, serializer);
+    sse_encode_u_64(self.parentId, serializer);
+    sse_encode_engine_history_kind(self.kind, serializer);
+    sse_encode_String(self.label, serializer);
+    sse_encode_bool(self.current, serializer);
+    sse_encode_bool(self.onActiveBranch, serializer);
+    sse_encode_u_32(self.thumbnailWidth, serializer);
+    sse_encode_u_32(self.thumbnailHeight, serializer);
+    sse_encode_list_prim_u_8_strict(self.thumbnail, serializer);
+  }
+
   @protected
   void sse_encode_f_32(double self, SseSerializer serializer) {
     // Codec=Sse (Serialization based), see doc to use other codecs
@@ -4058,6 +4491,7 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     sse_encode_u_32(self.blendModeIndex, serializer);
     sse_encode_bool(self.visible, serializer);
     sse_encode_bool(self.clippingMask, serializer);
+    sse_encode_opt_list_prim_u_8_strict(self.mask, serializer);
   }
 
   @protected
@@ -4107,6 +4541,18 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     }
   }
 
+  @protected
+  void sse_encode_list_engine_history_node(
+    List<EngineHistoryNode> self,
+    SseSerializer serializer,
+  ) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    sse_encode_i_32(self.length, serializer);
+    for (final item in self) {
+      sse_encode_engine_history_node(item, serializer);
+    }
+  }
+
   @protected
   void sse_encode_list_gpu_layer_data(
     List<GpuLayerData> self,
@@ -4219,6 +4665,18 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     serializer.buffer.putUint8List(self);
   }
 
+  @protected
+  void sse_encode_list_psd_group(
+    List<PsdGroup> self,
+    SseSerializer serializer,
+  ) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    sse_encode_i_32(self.length, serializer);
+    for (final item in self) {
+      sse_encode_psd_group(item, serializer);
+    }
+  }
+
   @protected
   void sse_encode_list_psd_layer(
     List<PsdLayer> self,
@@ -4338,6 +4796,18 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     sse_encode_i_32(self.width, serializer);
     sse_encode_i_32(self.height, serializer);
     sse_encode_list_psd_layer(self.layers, serializer);
+    sse_encode_list_psd_group(self.groups, serializer);
+  }
+
+  @protected
+  void sse_encode_psd_group(PsdGroup self, SseSerializer serializer) {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    sse_encode_u_32(self.id, serializer);
+    sse_encode_u_32(self.parentId, serializer);
+    sse_encode_String(self.name, serializer);
+    sse_encode_bool(self.visible, serializer);
+    sse_encode_u_8(self.opacity, serializer);
+    sse_encode_String(self.blendModeKey, serializer);
   }
 
   @protected
@@ -4353,6 +4823,9 @@ class RustLibApiImpl extends RustLibApiImplPlatform implements RustLibApi {
     sse_encode_i_32(self.bitmapHeight, serializer);
     sse_encode_i_32(self.bitmapLeft, serializer);
     sse_encode_i_32(self.bitmapTop, serializer);
+    sse_encode_u_32(self.parentId, serializer);
+    sse_encode_opt_box_autoadd_u_32(self.adjustmentFilterType, serializer);
+    sse_encode_list_prim_f_32_strict(self.adjustmentParams, serializer);
   }
 
   @protected
@@ -13,6 +13,8 @@ import 'api/cpu_brush.dart';
 import 'api/cpu_filters.dart';
 import 'api/cpu_image.dart';
 import 'api/cpu_transform.dart';
+import 'api/engine_events.dart';
+import 'api/engine_history.dart';
 import 'api/gpu_brush.dart';
 import 'api/gpu_composite.dart';
 import 'api/image_ops.dart';
@@ -34,6 +36,14 @@ abstract class RustLibApiImplPlatform extends BaseApiImpl<RustLibWire> {
     required super.portManager,
   });
 
+  @protected
+  AnyhowException dco_decode_AnyhowException(dynamic raw);
+
+  @protected
+  RustStreamSink<EngineEvent> dco_decode_StreamSink_engine_event_Sse(
+    dynamic raw,
+  );
+
   @protected
   String dco_decode_String(dynamic raw);
 
@@ -95,6 +105,18 @@ abstract class RustLibApiImplPlatform extends BaseApiImpl<RustLibWire> {
     dynamic raw,
   );
 
+  @protected
+  EngineEvent dco_decode_engine_event(dynamic raw);
+
+  @protected
+  EngineEventKind dco_decode_engine_event_kind(dynamic raw);
+
+  @protected
+  EngineHistoryKind dco_decode_engine_history_kind(dynamic raw);
+
+  @protected
+  EngineHistoryNode dco_decode_engine_history_node(dynamic raw);
+
   @protected
   double dco_decode_f_32(dynamic raw);
 
@@ -125,6 +147,9 @@ abstract class RustLibApiImplPlatform extends BaseApiImpl<RustLibWire> {
   @protected
   List<CpuBrushCommand> dco_decode_list_cpu_brush_command(dynamic raw);
 
+  @protected
+  List<EngineHistoryNode> dco_decode_list_engine_history_node(dynamic raw);
+
   @protected
   List<GpuLayerData> dco_decode_list_gpu_layer_data(dynamic raw);
 
@@ -155,6 +180,9 @@ abstract class RustLibApiImplPlatform extends BaseApiImpl<RustLibWire> {
   @protected
   Uint8List dco_decode_list_prim_u_8_strict(dynamic raw);
 
+  @protected
+  List<PsdGroup> dco_decode_list_psd_group(dynamic raw);
+
   @protected
   List<PsdLayer> dco_decode_list_psd_layer(dynamic raw);
 
@@ -188,6 +216,9 @@ abstract class RustLibApiImplPlatform extends BaseApiImpl<RustLibWire> {
   @protected
   PsdDocument dco_decode_psd_document(dynamic raw);
 
+  @protected
+  PsdGroup dco_decode_psd_group(dynamic raw);
+
   @protected
   PsdLayer dco_decode_psd_layer(dynamic raw);
 
@@ -212,6 +243,14 @@ abstract class RustLibApiImplPlatform extends BaseApiImpl<RustLibWire> {
   @protected
   WorkspaceState dco_decode_workspace_state(dynamic raw);
 
+  @protected
+  AnyhowException sse_decode_AnyhowException(SseDeserializer deserializer);
+
+  @protected
+  RustStreamSink<EngineEvent> sse_decode_StreamSink_engine_event_Sse(
+    SseDeserializer deserializer,
+  );
+
   @protected
   String sse_decode_String(SseDeserializer deserializer);
 
@@ -283,6 +322,22 @@ abstract class RustLibApiImplPlatform extends BaseApiImpl<RustLibWire> {
     SseDeserializer deserializer,
   );
 
+  @protected
+  EngineEvent sse_decode_engine_event(SseDeserializer deserializer);
+
+  @protected
+  EngineEventKind sse_decode_engine_event_kind(SseDeserializer deserializer);
+
+  @protected
+  EngineHistoryKind sse_decode_engine_history_kind(
+    SseDeserializer deserializer,
+  );
+
+  @protected
+  EngineHistoryNode sse_decode_engine_history_node(
+    SseDeserializer deserializer,
+  );
+
   @protected
   double sse_decode_f_32(SseDeserializer deserializer);
 
@@ -315,6 +370,11 @@ abstract class RustLibApiImplPlatform extends BaseApiImpl<RustLibWire> {
     SseDeserializer deserializer,
   );
 
+  @protected
+  List<EngineHistoryNode> sse_decode_list_engine_history_node(
+    SseDeserializer deserializer,
+  );
+
   @protected
   List<GpuLayerData> sse_decode_list_gpu_layer_data(
     SseDeserializer deserializer,
@@ -347,6 +407,9 @@ abstract class RustLibApiImplPlatform extends BaseApiImpl<RustLibWire> {
   @protected
   Uint8List sse_decode_list_prim_u_8_strict(SseDeserializer deserializer);
 
+  @protected
+  List<PsdGroup> sse_decode_list_psd_group(SseDeserializer deserializer);
+
   @protected
   List<PsdLayer> sse_decode_list_psd_layer(SseDeserializer deserializer);
 
@@ -386,6 +449,9 @@ abstract class RustLibApiImplPlatform extends BaseApiImpl<RustLibWire> {
   @protected
   PsdDocument sse_decode_psd_document(SseDeserializer deserializer);
 
+  @protected
+  PsdGroup sse_decode_psd_group(SseDeserializer deserializer);
+
   @protected
   PsdLayer sse_decode_psd_layer(SseDeserializer deserializer);
 
@@ -410,6 +476,18 @@ abstract class RustLibApiImplPlatform extends BaseApiImpl<RustLibWire> {
   @protected
   WorkspaceState sse_decode_workspace_state(SseDeserializer deserializer);
 
+  @protected
+  void sse_encode_AnyhowException(
+    AnyhowException self,
+    SseSerializer serializer,
+  );
+
+  @protected
+  void sse_encode_StreamSink_engine_event_Sse(
+    RustStreamSink<EngineEvent> self,
+    SseSerializer serializer,
+  );
+
   @protected
   void sse_encode_String(String self, SseSerializer serializer);
 
@@ -500,6 +578,27 @@ abstract class RustLibApiImplPlatform extends BaseApiImpl<RustLibWire> {
     SseSerializer serializer,
   );
 
+  @protected
+  void sse_encode_engine_event(EngineEvent self, SseSerializer serializer);
+
+  @protected
+  void sse_encode_engine_event_kind(
+    EngineEventKind self,
+    SseSerializer serializer,
+  );
+
+  @protected
+  void sse_encode_engine_history_kind(
+    EngineHistoryKind self,
+    SseSerializer serializer,
+  );
+
+  @protected
+  void sse_encode_engine_history_node(
+    EngineHistoryNode self,
+    SseSerializer serializer,
+  );
+
   @protected
   void sse_encode_f_32(double self, SseSerializer serializer);
 
@@ -539,6 +638,12 @@ abstract class RustLibApiImplPlatform extends BaseApiImpl<RustLibWire> {
     SseSerializer serializer,
   );
 
+  @protected
+  void sse_encode_list_engine_history_node(
+    List<EngineHistoryNode> self,
+    SseSerializer serializer,
+  );
+
   @protected
   void sse_encode_list_gpu_layer_data(
     List<GpuLayerData> self,
@@ -596,6 +701,9 @@ abstract class RustLibApiImplPlatform extends BaseApiImpl<RustLibWire> {
     SseSerializer serializer,
   );
 
+  @protected
+  void sse_encode_list_psd_group(List<PsdGroup> self, SseSerializer serializer);
+
   @protected
   void sse_encode_list_psd_layer(List<PsdLayer> self, SseSerializer serializer);
 
@@ -641,6 +749,9 @@ abstract class RustLibApiImplPlatform extends BaseApiImpl<RustLibWire> {
   @protected
   void sse_encode_psd_document(PsdDocument self, SseSerializer serializer);
 
+  @protected
+  void sse_encode_psd_group(PsdGroup self, SseSerializer serializer);
+
   @protected
   void sse_encode_psd_layer(PsdLayer self, SseSerializer serializer);
 
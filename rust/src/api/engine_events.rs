@@ -0,0 +1,71 @@
+use crate::frb_generated::StreamSink;
+
+/// What an [`EngineEvent`] reports. Fields a kind does not use stay zero,
+/// `false` or empty.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EngineEventKind {
+    /// `can_undo` / `can_redo` changed.
+    UndoState,
+    /// A step was committed, undone or redone. The rect covers the pixels it
+    /// changed on `layer_index`; `on_mask` is set when they belong to the
+    /// layer's mask.
+    LayerDirty,
+    /// Tile-aligned bounds of `layer_index` after a change; `width` and
+    /// `height` are 0 once the layer is empty.
+    LayerBounds,
+    /// A bucket fill on `layer_index` finished; `applied` tells whether it
+    /// changed any pixels.
+    BucketFillFinished,
+    /// A filter or antialias pass on `layer_index` finished.
+    FilterFinished,
+    /// Undo or redo changed layer properties, the layer stack or the canvas;
+    /// `width` and `height` hold the canvas size afterwards.
+    LayersChanged,
+    /// A request that could not be carried out, in `message`.
+    Error,
+    /// A warning logged on the render thread, in `message`. The engine
+    /// carried on, so the app may only want to log it.
+    Warning,
+}
+
+#[derive(Clone, Debug)]
+pub struct EngineEvent {
+    pub kind: EngineEventKind,
+    pub layer_index: u32,
+    pub left: i32,
+    pub top: i32,
+    pub width: i32,
+    pub height: i32,
+    pub on_mask: bool,
+    pub can_undo: bool,
+    pub can_redo: bool,
+    pub applied: bool,
+    pub message: String,
+}
+
+impl EngineEvent {
+    pub(crate) fn new(kind: EngineEventKind) -> Self {
+        Self {
+            kind,
+            layer_index: 0,
+            left: 0,
+            top: 0,
+            width: 0,
+            height: 0,
+            on_mask: false,
+            can_undo: false,
+            can_redo: false,
+            applied: false,
+            message: String::new(),
+        }
+    }
+}
+
+/// Streams the events of engine `handle`. Subscribing again replaces the
+/// previous stream; the stream ends when the engine is disposed.
+pub fn engine_event_stream(handle: u64, sink: StreamSink<EngineEvent>) {
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+    crate::canvas_engine::subscribe_engine_events(handle, sink);
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+    drop((handle, sink));
+}
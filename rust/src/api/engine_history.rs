@@ -0,0 +1,43 @@
+/// What a [`EngineHistoryNode`] stands for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EngineHistoryKind {
+    /// The oldest state kept; it has no step of its own.
+    Start,
+    /// A stroke, fill, filter or other pixel change, possibly on several
+    /// layers.
+    Pixels,
+    /// A change of layer properties, the layer stack or the canvas.
+    Layers,
+}
+
+/// One state of an engine's undo history. States form a tree: a change
+/// made after undoing starts a new branch instead of dropping the old one.
+#[derive(Clone, Debug)]
+pub struct EngineHistoryNode {
+    pub id: u64,
+    /// 0 for the start node.
+    pub parent_id: u64,
+    pub kind: EngineHistoryKind,
+    /// Checkpoint name, empty when the state has none.
+    pub label: String,
+    pub current: bool,
+    /// Whether plain undo and redo reach the state.
+    pub on_active_branch: bool,
+    /// Premultiplied RGBA preview of the changed area; empty for nodes
+    /// without pixel changes.
+    pub thumbnail_width: u32,
+    pub thumbnail_height: u32,
+    pub thumbnail: Vec<u8>,
+}
+
+/// Lists every state of the undo history of engine `handle`; `parent_id`
+/// links them into a tree. Empty for unknown handles.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+pub fn engine_history(handle: u64) -> Vec<EngineHistoryNode> {
+    crate::canvas_engine::read_engine_history(handle)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+pub fn engine_history(_handle: u64) -> Vec<EngineHistoryNode> {
+    Vec::new()
+}
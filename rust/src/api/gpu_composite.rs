@@ -24,6 +24,8 @@ pub struct GpuLayerData {
     pub blend_mode_index: u32,
     pub visible: bool,
     pub clipping_mask: bool,
+    /// Optional grayscale layer mask, one byte per pixel (255 = fully shown).
+    pub mask: Option<Vec<u8>>,
 }
 
 #[cfg(target_family = "wasm")]
@@ -175,7 +177,15 @@ pub fn gpu_composite_layers(
     let converted: Vec<LayerData> = layers
         .into_iter()
         .map(|layer| LayerData {
-            pixels: layer.pixels,
+            pixels: match layer.mask {
+                Some(mask) if mask.len() == layer.pixels.len() => layer
+                    .pixels
+                    .iter()
+                    .zip(mask.iter())
+                    .map(|(&pixel, &value)| apply_mask_value(pixel, value))
+                    .collect(),
+                _ => layer.pixels,
+            },
             opacity: clamp_unit_f64_to_f32(layer.opacity),
             blend_mode: layer.blend_mode_index,
             visible: layer.visible,
@@ -232,6 +242,39 @@ pub fn gpu_compositor_dispose() {
     }
 }
 
+/// Deepest folder nesting the compositors honour; `LayerGroupSpan` builders
+/// drop anything nested further.
+pub(crate) const MAX_COMPOSITE_GROUP_DEPTH: usize = 8;
+
+/// One layer folder as the compositors see it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct CompositeGroup {
+    pub(crate) opacity: f32,
+    pub(crate) blend_mode_index: u32,
+    /// Children blend straight onto the backdrop instead of into an isolated
+    /// buffer; the group opacity then fades between backdrop and result.
+    pub(crate) pass_through: bool,
+}
+
+/// Folder structure around one layer of a flat, bottom-to-top layer list.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct LayerGroupSpan {
+    /// Groups that start at this layer, outermost first.
+    pub(crate) open: Vec<CompositeGroup>,
+    /// Number of groups that end right after this layer.
+    pub(crate) close: u32,
+    /// Set when an enclosing group is hidden.
+    pub(crate) hidden: bool,
+}
+
+/// Colour adjustment applied to everything composited below a layer, in the
+/// `color_filter` shader's terms: its filter kind and `params0` uniform.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct CompositeAdjustment {
+    pub(crate) kind: u32,
+    pub(crate) params: [f32; 4],
+}
+
 const EPS: f32 = 1.0e-7;
 
 #[flutter_rust_bridge::frb]
@@ -257,6 +300,38 @@ fn cpu_composite_layers_impl(
         return Ok(Vec::new());
     }
 
+    let mut out = vec![0u32; pixel_count];
+    cpu_composite_layers_region(
+        layers,
+        &[],
+        &[],
+        width,
+        height,
+        (0, 0, width, height),
+        &mut out,
+    );
+    Ok(out)
+}
+
+/// Composites the `(left, top, width, height)` region of `layers` into the
+/// matching pixels of `out`, a full `width * height` canvas buffer. Pixels
+/// outside the region are left untouched. `groups` holds one span per layer
+/// and may be empty for a flat layer list; likewise `adjustments` marks the
+/// layers that recolour the composite below them instead of adding pixels.
+pub(crate) fn cpu_composite_layers_region(
+    layers: &[GpuLayerData],
+    groups: &[LayerGroupSpan],
+    adjustments: &[Option<CompositeAdjustment>],
+    width: u32,
+    height: u32,
+    region: (u32, u32, u32, u32),
+    out: &mut [u32],
+) {
+    let pixel_count = (width as usize).saturating_mul(height as usize);
+    if pixel_count == 0 || out.len() != pixel_count {
+        return;
+    }
+
     let expected_len = pixel_count;
     let mut layer_slices: Vec<Option<&[u32]>> = Vec::with_capacity(layers.len());
     for layer in layers {
@@ -267,77 +342,308 @@ fn cpu_composite_layers_impl(
         }
     }
 
-    let mut out = vec![0u32; pixel_count];
-    for idx in 0..pixel_count {
-        let mut dst: u32 = 0;
-        let mut initialized = false;
-        let mut mask_alpha: f32 = 0.0;
+    let (left, top, region_width, region_height) = region;
+    let right = left.saturating_add(region_width).min(width);
+    let bottom = top.saturating_add(region_height).min(height);
+    for y in top.min(height)..bottom {
+        for x in left.min(width)..right {
+            let idx = (y as usize) * (width as usize) + (x as usize);
+            out[idx] = composite_pixel(layers, &layer_slices, groups, adjustments, idx);
+        }
+    }
+}
 
-        for (layer_index, layer) in layers.iter().enumerate() {
-            if !layer.visible {
-                continue;
-            }
+#[derive(Clone, Copy)]
+struct CompositeState {
+    dst: u32,
+    initialized: bool,
+    mask_alpha: f32,
+}
 
-            let opacity = clamp_unit_f64_to_f32(layer.opacity);
-            if opacity <= 0.0 {
-                if !layer.clipping_mask {
-                    mask_alpha = 0.0;
-                }
-                continue;
-            }
+impl CompositeState {
+    const EMPTY: Self = Self {
+        dst: 0,
+        initialized: false,
+        mask_alpha: 0.0,
+    };
+}
 
-            let src = match layer_slices[layer_index] {
-                Some(slice) => slice[idx],
-                None => 0,
-            };
-            let src_a_u8 = ((src >> 24) & 0xFF) as u32;
-            if src_a_u8 == 0 {
-                if !layer.clipping_mask {
-                    mask_alpha = 0.0;
-                }
-                continue;
+fn composite_pixel(
+    layers: &[GpuLayerData],
+    layer_slices: &[Option<&[u32]>],
+    groups: &[LayerGroupSpan],
+    adjustments: &[Option<CompositeAdjustment>],
+    idx: usize,
+) -> u32 {
+    let mut state = CompositeState::EMPTY;
+    let mut stack = [(CompositeState::EMPTY, None::<CompositeGroup>); MAX_COMPOSITE_GROUP_DEPTH];
+    let mut depth = 0usize;
+
+    for (layer_index, layer) in layers.iter().enumerate() {
+        let span = groups.get(layer_index);
+        for group in span.map(|span| span.open.as_slice()).unwrap_or(&[]) {
+            if depth < MAX_COMPOSITE_GROUP_DEPTH {
+                stack[depth] = (state, Some(*group));
+                depth += 1;
             }
-
-            let mut total_opacity = opacity;
-            if layer.clipping_mask {
-                if mask_alpha <= 0.0 {
-                    continue;
-                }
-                total_opacity *= mask_alpha;
-                if total_opacity <= 0.0 {
-                    continue;
-                }
+            if !group.pass_through {
+                state.dst = 0;
+                state.initialized = false;
             }
+            // Clipping masks never reach outside their own folder.
+            state.mask_alpha = 0.0;
+        }
 
-            let src_a = src_a_u8 as f32 / 255.0;
-            let mut effective_a = src_a * total_opacity;
-            if effective_a <= 0.0 {
-                if !layer.clipping_mask {
-                    mask_alpha = 0.0;
-                }
-                continue;
+        let hidden = span.is_some_and(|span| span.hidden);
+        if layer.visible && !hidden {
+            match adjustments.get(layer_index).copied().flatten() {
+                Some(adjustment) => adjust_composite(&mut state, layer, adjustment, idx),
+                None => composite_layer(&mut state, layer, layer_slices[layer_index], idx),
             }
-            effective_a = clamp01(effective_a);
+        }
 
-            if !layer.clipping_mask {
-                mask_alpha = effective_a;
+        for _ in 0..span.map_or(0, |span| span.close) {
+            if depth == 0 {
+                break;
+            }
+            depth -= 1;
+            if let (base, Some(group)) = stack[depth] {
+                state = close_group(base, state, group, idx);
             }
+        }
+    }
+
+    if state.initialized {
+        state.dst
+    } else {
+        0
+    }
+}
+
+fn composite_layer(
+    state: &mut CompositeState,
+    layer: &GpuLayerData,
+    slice: Option<&[u32]>,
+    idx: usize,
+) {
+    let opacity = clamp_unit_f64_to_f32(layer.opacity);
+    if opacity <= 0.0 {
+        if !layer.clipping_mask {
+            state.mask_alpha = 0.0;
+        }
+        return;
+    }
+
+    let src = match slice {
+        Some(slice) => slice[idx],
+        None => 0,
+    };
+    let src = match layer.mask.as_deref().and_then(|mask| mask.get(idx)) {
+        Some(&value) => apply_mask_value(src, value),
+        None => src,
+    };
+    let src_a_u8 = (src >> 24) & 0xFF;
+    if src_a_u8 == 0 {
+        if !layer.clipping_mask {
+            state.mask_alpha = 0.0;
+        }
+        return;
+    }
+
+    let mut total_opacity = opacity;
+    if layer.clipping_mask {
+        if state.mask_alpha <= 0.0 {
+            return;
+        }
+        total_opacity *= state.mask_alpha;
+        if total_opacity <= 0.0 {
+            return;
+        }
+    }
 
-            let effective_a_u8 = to_u8(effective_a);
-            let effective_color = (effective_a_u8 << 24) | (src & 0x00FFFFFF);
+    let src_a = src_a_u8 as f32 / 255.0;
+    let mut effective_a = src_a * total_opacity;
+    if effective_a <= 0.0 {
+        if !layer.clipping_mask {
+            state.mask_alpha = 0.0;
+        }
+        return;
+    }
+    effective_a = clamp01(effective_a);
+
+    if !layer.clipping_mask {
+        state.mask_alpha = effective_a;
+    }
+
+    let effective_a_u8 = to_u8(effective_a);
+    let effective_color = (effective_a_u8 << 24) | (src & 0x00FFFFFF);
+
+    if !state.initialized {
+        state.dst = effective_color;
+        state.initialized = true;
+    } else {
+        state.dst = blend_argb(state.dst, effective_color, layer.blend_mode_index, idx as u32);
+    }
+}
 
-            if !initialized {
-                dst = effective_color;
-                initialized = true;
+/// Recolours the composite below an adjustment layer. Opacity and the layer
+/// mask fade between the original and the adjusted colour, matching
+/// `apply_adjustment` in the present shader.
+fn adjust_composite(
+    state: &mut CompositeState,
+    layer: &GpuLayerData,
+    adjustment: CompositeAdjustment,
+    idx: usize,
+) {
+    if !state.initialized {
+        return;
+    }
+    let mut strength = clamp_unit_f64_to_f32(layer.opacity);
+    if let Some(&value) = layer.mask.as_deref().and_then(|mask| mask.get(idx)) {
+        strength *= value as f32 / 255.0;
+    }
+    let alpha = unpack_a(state.dst);
+    if strength <= 0.0 || alpha <= 0.0 {
+        return;
+    }
+    let rgb = [unpack_r(state.dst), unpack_g(state.dst), unpack_b(state.dst)];
+    let adjusted = adjust_color(adjustment.kind, rgb, adjustment.params);
+    let channel = |i: usize| rgb[i] + (adjusted[i] - rgb[i]) * strength;
+    state.dst = pack_argb(alpha, channel(0), channel(1), channel(2));
+}
+
+/// CPU twin of `adjust_color` in the present shader.
+fn adjust_color(kind: u32, rgb: [f32; 3], p: [f32; 4]) -> [f32; 3] {
+    let [r, g, b] = rgb;
+    match kind {
+        0 => {
+            let (h, s, v) = rgb_to_hsv(r, g, b);
+            let h = h + p[0];
+            hsv_to_rgb(h - h.floor(), clamp01(s + p[1]), clamp01(v + p[2]))
+        }
+        1 => rgb.map(|c| clamp01((c - 0.5) * p[1] + 0.5 + p[0])),
+        2 | 3 => {
+            let lum = r * 0.299 + g * 0.587 + b * 0.114;
+            let value = if kind == 2 {
+                clamp01(clamp01((lum - p[0]) * p[1]).powf(p[2]))
+            } else if lum >= p[0] {
+                1.0
             } else {
-                dst = blend_argb(dst, effective_color, layer.blend_mode_index, idx as u32);
-            }
+                0.0
+            };
+            [value; 3]
         }
+        5 => rgb.map(|c| 1.0 - c),
+        _ => rgb,
+    }
+}
 
-        out[idx] = if initialized { dst } else { 0 };
+pub(crate) fn rgb_to_hsv(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    let maxc = r.max(g).max(b);
+    let minc = r.min(g).min(b);
+    let delta = maxc - minc;
+    let mut h = 0.0;
+    if delta > EPS {
+        h = if maxc == r {
+            (g - b) / delta + if g < b { 6.0 } else { 0.0 }
+        } else if maxc == g {
+            (b - r) / delta + 2.0
+        } else {
+            (r - g) / delta + 4.0
+        };
+        h /= 6.0;
     }
+    let s = if maxc > EPS { delta / maxc } else { 0.0 };
+    (h, s, maxc)
+}
 
-    Ok(out)
+pub(crate) fn hsv_to_rgb(h: f32, s: f32, v: f32) -> [f32; 3] {
+    if s <= EPS {
+        return [v; 3];
+    }
+    let h6 = h * 6.0;
+    let i = h6.floor();
+    let f = h6 - i;
+    let p = v * (1.0 - s);
+    let q = v * (1.0 - s * f);
+    let t = v * (1.0 - s * (1.0 - f));
+    match (i as u32) % 6 {
+        0 => [v, t, p],
+        1 => [q, v, p],
+        2 => [p, v, t],
+        3 => [p, q, v],
+        4 => [t, p, v],
+        _ => [v, p, q],
+    }
+}
+
+/// Scales the alpha of an ARGB pixel by a grayscale mask value.
+pub(crate) fn apply_mask_value(pixel: u32, value: u8) -> u32 {
+    if value == 0xFF {
+        return pixel;
+    }
+    let alpha = (pixel >> 24) & 0xFF;
+    let masked = (alpha * value as u32 + 127) / 255;
+    (masked << 24) | (pixel & 0x00FF_FFFF)
+}
+
+/// Folds a finished group back into the state saved when it opened.
+fn close_group(
+    base: CompositeState,
+    inner: CompositeState,
+    group: CompositeGroup,
+    idx: usize,
+) -> CompositeState {
+    let opacity = clamp01(group.opacity);
+    if group.pass_through {
+        let base_dst = if base.initialized { base.dst } else { 0 };
+        let inner_dst = if inner.initialized { inner.dst } else { base_dst };
+        return CompositeState {
+            dst: mix_argb(base_dst, inner_dst, opacity),
+            initialized: base.initialized || inner.initialized,
+            mask_alpha: 0.0,
+        };
+    }
+
+    let alpha = if inner.initialized {
+        unpack_a(inner.dst) * opacity
+    } else {
+        0.0
+    };
+    if alpha <= 0.0 {
+        return CompositeState {
+            mask_alpha: 0.0,
+            ..base
+        };
+    }
+    let src = (to_u8(alpha) << 24) | (inner.dst & 0x00FF_FFFF);
+    let dst = if base.initialized {
+        blend_argb(base.dst, src, group.blend_mode_index, idx as u32)
+    } else {
+        src
+    };
+    CompositeState {
+        dst,
+        initialized: true,
+        mask_alpha: alpha,
+    }
+}
+
+/// Linear blend of two straight ARGB colours in premultiplied space.
+fn mix_argb(from: u32, to: u32, t: f32) -> u32 {
+    let fa = unpack_a(from);
+    let ta = unpack_a(to);
+    let a = fa + (ta - fa) * t;
+    if a <= 0.0 {
+        return 0;
+    }
+    let channel = |f: f32, c: f32| (f * fa + (c * ta - f * fa) * t) / a;
+    pack_argb(
+        a,
+        channel(unpack_r(from), unpack_r(to)),
+        channel(unpack_g(from), unpack_g(to)),
+        channel(unpack_b(from), unpack_b(to)),
+    )
 }
 
 fn clamp01(x: f32) -> f32 {
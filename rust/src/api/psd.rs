@@ -5,6 +5,17 @@ pub struct PsdDocument {
     pub width: i32,
     pub height: i32,
     pub layers: Vec<PsdLayer>,
+    /// Layer folders, bottom-to-top like `layers`.
+    pub groups: Vec<PsdGroup>,
+}
+
+pub struct PsdGroup {
+    pub id: u32,
+    pub parent_id: u32, // 0 = top level
+    pub name: String,
+    pub visible: bool,
+    pub opacity: u8,
+    pub blend_mode_key: String, // "pass" for pass-through folders
 }
 
 pub struct PsdLayer {
@@ -18,6 +29,110 @@ pub struct PsdLayer {
     pub bitmap_height: i32,
     pub bitmap_left: i32,
     pub bitmap_top: i32,
+    pub parent_id: u32, // 0 = top level
+    /// Canvas filter type of an adjustment layer; its bitmap is then empty.
+    pub adjustment_filter_type: Option<u32>,
+    /// The adjustment's four filter parameters, in `ApplyFilter` units.
+    pub adjustment_params: Vec<f32>,
+}
+
+// Canvas filter types (see `cpu_filters`) that have a Photoshop counterpart.
+const FILTER_TYPE_HUE_SATURATION: u32 = 0;
+const FILTER_TYPE_BRIGHTNESS_CONTRAST: u32 = 1;
+const FILTER_TYPE_BLACK_WHITE: u32 = 2;
+const FILTER_TYPE_BINARIZE: u32 = 7;
+const FILTER_TYPE_INVERT: u32 = 9;
+
+/// Photoshop hue ranges (reds..magentas) written into `hue2` blocks.
+const HUE_SATURATION_RANGES: [[i16; 4]; 6] = [
+    [315, 345, 15, 45],
+    [15, 45, 75, 105],
+    [75, 105, 135, 165],
+    [135, 165, 195, 225],
+    [195, 225, 255, 285],
+    [255, 285, 315, 345],
+];
+
+/// Maps a Photoshop adjustment layer block to a canvas filter type and its
+/// parameters. Adjustments without a counterpart return `None`.
+fn psd_adjustment_to_filter(key: &[u8; 4], data: &[u8]) -> Option<(u32, [f32; 4])> {
+    let read_i16 = |offset: usize| -> Option<f32> {
+        let bytes = data.get(offset..offset + 2)?;
+        Some(i16::from_be_bytes([bytes[0], bytes[1]]) as f32)
+    };
+    match key {
+        b"nvrt" => Some((FILTER_TYPE_INVERT, [0.0; 4])),
+        b"thrs" => Some((FILTER_TYPE_BINARIZE, [read_i16(0)?, 0.0, 0.0, 0.0])),
+        b"brit" => Some((
+            FILTER_TYPE_BRIGHTNESS_CONTRAST,
+            [read_i16(0)?.clamp(-100.0, 100.0), read_i16(2)?.clamp(-100.0, 100.0), 0.0, 0.0],
+        )),
+        // Version, colorize flag + padding, colorize values, then master values.
+        b"hue2" => {
+            if data.get(2).copied().unwrap_or(0) != 0 {
+                return None;
+            }
+            Some((
+                FILTER_TYPE_HUE_SATURATION,
+                [read_i16(10)?, read_i16(12)?, read_i16(14)?, 0.0],
+            ))
+        }
+        // Channel weights live in a descriptor; a plain luminance mix is the
+        // closest canvas equivalent.
+        b"blwh" => Some((FILTER_TYPE_BLACK_WHITE, [0.0, 100.0, 0.0, 0.0])),
+        _ => None,
+    }
+}
+
+/// Encodes an adjustment layer's filter as a complete Photoshop additional
+/// layer info block (`8BIM`, key, length, payload) for the PSD exporter.
+/// Returns `None` when the filter has no Photoshop counterpart.
+#[flutter_rust_bridge::frb(sync)]
+pub fn psd_adjustment_layer_info(filter_type: u32, params: Vec<f32>) -> Option<Vec<u8>> {
+    let param = |index: usize| params.get(index).copied().unwrap_or(0.0);
+    let word = |value: f32| (value.round() as i16).to_be_bytes();
+    let (key, mut data): (&[u8; 4], Vec<u8>) = match filter_type {
+        FILTER_TYPE_INVERT => (b"nvrt", Vec::new()),
+        FILTER_TYPE_BINARIZE => {
+            let mut data = word(param(0).clamp(1.0, 255.0)).to_vec();
+            data.extend_from_slice(&[0, 0]);
+            (b"thrs", data)
+        }
+        FILTER_TYPE_BRIGHTNESS_CONTRAST => {
+            let mut data = Vec::with_capacity(8);
+            data.extend_from_slice(&word(param(0).clamp(-100.0, 100.0)));
+            data.extend_from_slice(&word(param(1).clamp(-100.0, 100.0)));
+            data.extend_from_slice(&word(127.0)); // mean value
+            data.push(0); // Lab colour only
+            (b"brit", data)
+        }
+        FILTER_TYPE_HUE_SATURATION => {
+            let mut data = Vec::with_capacity(100);
+            data.extend_from_slice(&word(2.0)); // version
+            data.extend_from_slice(&[0, 0]); // colorize off + padding
+            data.extend_from_slice(&[0; 6]); // colorize hue/saturation/lightness
+            data.extend_from_slice(&word(param(0).clamp(-180.0, 180.0)));
+            data.extend_from_slice(&word(param(1).clamp(-100.0, 100.0)));
+            data.extend_from_slice(&word(param(2).clamp(-100.0, 100.0)));
+            for range in HUE_SATURATION_RANGES {
+                for value in range {
+                    data.extend_from_slice(&value.to_be_bytes());
+                }
+                data.extend_from_slice(&[0; 6]); // range hue/saturation/lightness
+            }
+            (b"hue2", data)
+        }
+        _ => return None,
+    };
+    if data.len() % 2 == 1 {
+        data.push(0);
+    }
+    let mut block = Vec::with_capacity(12 + data.len());
+    block.extend_from_slice(b"8BIM");
+    block.extend_from_slice(key);
+    block.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    block.extend_from_slice(&data);
+    Some(block)
 }
 
 pub fn import_psd(bytes: Vec<u8>) -> Result<PsdDocument, String> {
@@ -39,6 +154,25 @@ pub fn import_psd(bytes: Vec<u8>) -> Result<PsdDocument, String> {
     let layers: Vec<PsdLayer> = {
         let src_layers = psd.layers();
         let process_layer = |layer: &psd::PsdLayer| -> Option<PsdLayer> {
+            if let Some((key, data)) = layer.adjustment() {
+                let (filter_type, params) = psd_adjustment_to_filter(key, data)?;
+                return Some(PsdLayer {
+                    name: layer.name().to_string(),
+                    visible: layer.visible(),
+                    opacity: layer.opacity(),
+                    clipping_mask: layer.is_clipping_mask(),
+                    blend_mode_key: String::from_utf8_lossy(&layer.blend_mode_key()).to_string(),
+                    bitmap: Vec::new(),
+                    bitmap_width: 0,
+                    bitmap_height: 0,
+                    bitmap_left: 0,
+                    bitmap_top: 0,
+                    parent_id: layer.parent_id().unwrap_or(0),
+                    adjustment_filter_type: Some(filter_type),
+                    adjustment_params: params.to_vec(),
+                });
+            }
+
             let left: i32 = layer.layer_left();
             let top: i32 = layer.layer_top();
             let layer_width: i32 = layer.width() as i32;
@@ -158,6 +292,9 @@ pub fn import_psd(bytes: Vec<u8>) -> Result<PsdDocument, String> {
                 bitmap_height: clipped_height,
                 bitmap_left: clipped_left,
                 bitmap_top: clipped_top,
+                parent_id: layer.parent_id().unwrap_or(0),
+                adjustment_filter_type: None,
+                adjustment_params: Vec::new(),
             })
         };
 
@@ -181,9 +318,24 @@ pub fn import_psd(bytes: Vec<u8>) -> Result<PsdDocument, String> {
         }
     };
 
+    let groups: Vec<PsdGroup> = psd
+        .group_ids_in_order()
+        .iter()
+        .filter_map(|id| psd.groups().get(id))
+        .map(|group| PsdGroup {
+            id: group.id(),
+            parent_id: group.parent_id().unwrap_or(0),
+            name: group.name().to_string(),
+            visible: group.visible(),
+            opacity: group.opacity(),
+            blend_mode_key: String::from_utf8_lossy(&group.blend_mode_key()).to_string(),
+        })
+        .collect();
+
     Ok(PsdDocument {
         width,
         height,
         layers,
+        groups,
     })
 }
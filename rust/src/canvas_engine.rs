@@ -1,17 +1,54 @@
 mod ffi;
 mod types;
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+mod adjustments;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+mod alpha_lock;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+mod assist;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+mod cpu_engine;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+mod cpu_undo;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 mod engine;
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+mod events;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+mod groups;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+mod history;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+mod journal;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 mod layers;
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+mod masks;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 mod present;
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+mod pressure;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 mod preview;
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+mod rin;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 mod stroke;
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+mod symmetry;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+mod tile_codec;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+mod tiles;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 mod transform;
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 mod undo;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+mod velocity;
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+pub(crate) use events::subscribe_engine_events;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+pub(crate) use ffi::read_engine_history;
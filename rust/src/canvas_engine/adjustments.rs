@@ -0,0 +1,174 @@
+use crate::api::gpu_composite::CompositeAdjustment;
+use crate::gpu::filter_renderer::{
+    color_filter_params, FILTER_BINARIZE, FILTER_BLACK_WHITE, FILTER_BRIGHTNESS_CONTRAST,
+    FILTER_HUE_SATURATION, FILTER_INVERT,
+};
+
+use super::engine::{reorder_vec, EngineCommand};
+
+/// Whether `filter_type` can drive an adjustment layer: the per-pixel colour
+/// filters that leave alpha alone. Scan-paper drawing rewrites alpha, so it
+/// stays a destructive filter.
+fn is_adjustment_filter(filter_type: u32) -> bool {
+    matches!(
+        filter_type,
+        FILTER_HUE_SATURATION
+            | FILTER_BRIGHTNESS_CONTRAST
+            | FILTER_BLACK_WHITE
+            | FILTER_BINARIZE
+            | FILTER_INVERT
+    )
+}
+
+/// Settings of one adjustment layer: an `ApplyFilter` colour filter and the
+/// parameters it was set with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct LayerAdjustment {
+    pub(crate) filter_type: u32,
+    pub(crate) params: [f32; 4],
+}
+
+impl LayerAdjustment {
+    fn composite(self) -> Option<CompositeAdjustment> {
+        let [param0, param1, param2, _] = self.params;
+        color_filter_params(self.filter_type, param0, param1, param2)
+            .map(|(kind, params)| CompositeAdjustment { kind, params })
+    }
+}
+
+/// Adjustment settings of the engine's layers, index for index.
+///
+/// An adjustment layer's own pixels are ignored; the compositors run its
+/// filter over everything below it, faded by the layer's opacity and mask.
+/// Parameters use the same units as `ApplyFilter`.
+pub(crate) struct LayerAdjustments {
+    layers: Vec<Option<LayerAdjustment>>,
+}
+
+impl LayerAdjustments {
+    pub(crate) fn new(layer_count: usize) -> Self {
+        Self {
+            layers: vec![None; layer_count],
+        }
+    }
+
+    /// Turns every layer back into a pixel layer.
+    pub(crate) fn reset(&mut self, layer_count: usize) {
+        *self = Self::new(layer_count);
+    }
+
+    /// Follows a layer-count change. New layers start as pixel layers.
+    pub(crate) fn resize_layers(&mut self, layer_count: usize) {
+        self.layers.resize(layer_count, None);
+    }
+
+    pub(crate) fn reorder_layer(&mut self, from: usize, to: usize) {
+        reorder_vec(&mut self.layers, from, to);
+    }
+
+    pub(crate) fn set(&mut self, layer_index: usize, filter_type: u32, params: [f32; 4]) -> bool {
+        if !is_adjustment_filter(filter_type) {
+            return false;
+        }
+        match self.layers.get_mut(layer_index) {
+            Some(entry) => {
+                *entry = Some(LayerAdjustment {
+                    filter_type,
+                    params,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn clear(&mut self, layer_index: usize) -> bool {
+        match self.layers.get_mut(layer_index) {
+            Some(entry) => entry.take().is_some(),
+            None => false,
+        }
+    }
+
+    pub(crate) fn get(&self, layer_index: usize) -> Option<LayerAdjustment> {
+        self.layers.get(layer_index).copied().flatten()
+    }
+
+    /// Puts back an adjustment read with `get`, e.g. when undo restores a
+    /// removed layer.
+    pub(crate) fn restore(&mut self, layer_index: usize, adjustment: Option<LayerAdjustment>) {
+        if let Some(entry) = self.layers.get_mut(layer_index) {
+            *entry = adjustment;
+        }
+    }
+
+    /// Compositor view of the first `layer_count` layers, or an empty list
+    /// when no layer is an adjustment so callers can take the plain path.
+    pub(crate) fn composite(&self, layer_count: usize) -> Vec<Option<CompositeAdjustment>> {
+        if self.layers.iter().all(Option::is_none) {
+            return Vec::new();
+        }
+        let mut out: Vec<_> = self
+            .layers
+            .iter()
+            .map(|entry| entry.and_then(LayerAdjustment::composite))
+            .collect();
+        out.resize(layer_count, None);
+        out
+    }
+
+    /// Applies one of the adjustment-layer commands and reports whether the
+    /// composite may have changed. Any other command is ignored.
+    pub(crate) fn apply_command(&mut self, cmd: EngineCommand) -> bool {
+        match cmd {
+            EngineCommand::SetLayerAdjustment {
+                layer_index,
+                filter_type,
+                param0,
+                param1,
+                param2,
+                param3,
+            } => self.set(
+                layer_index as usize,
+                filter_type,
+                [param0, param1, param2, param3],
+            ),
+            EngineCommand::ClearLayerAdjustment { layer_index } => self.clear(layer_index as usize),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::gpu_composite::{cpu_composite_layers_region, GpuLayerData};
+
+    #[test]
+    fn adjustment_layers_filter_the_layers_below() {
+        let layer = |color: u32| GpuLayerData {
+            pixels: vec![color],
+            opacity: 1.0,
+            blend_mode_index: 0,
+            visible: true,
+            clipping_mask: false,
+            mask: None,
+        };
+        // The adjustment layer's own (transparent) pixels never show up.
+        let layers = [layer(0xFFFF_0000), layer(0x0000_0000)];
+        let mut adjustments = LayerAdjustments::new(layers.len());
+        let composite = |adjustments: &LayerAdjustments| {
+            let mut out = [0u32];
+            let adjustments = adjustments.composite(layers.len());
+            cpu_composite_layers_region(&layers, &[], &adjustments, 1, 1, (0, 0, 1, 1), &mut out);
+            out[0]
+        };
+        assert_eq!(composite(&adjustments), 0xFFFF_0000);
+
+        assert!(!adjustments.set(1, FILTER_INVERT + 100, [0.0; 4]));
+        assert!(adjustments.set(1, FILTER_INVERT, [0.0; 4]));
+        assert_eq!(composite(&adjustments), 0xFF00_FFFF);
+
+        assert!(adjustments.clear(1));
+        assert_eq!(composite(&adjustments), 0xFFFF_0000);
+    }
+}
@@ -0,0 +1,356 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use crate::gpu::layer_format::LAYER_TEXTURE_FORMAT;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct LockUniform {
+    width: u32,
+    height: u32,
+    _pad0: u32,
+    _pad1: u32,
+}
+
+/// Keeps the alpha of an alpha-locked layer through whole-layer edits
+/// (filters, antialiasing, bucket fills) without reading it back: `save`
+/// copies the layer aside before the edit and `restore` merges the two on
+/// the GPU afterwards.
+pub(crate) struct AlphaLockRenderer {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    saved: wgpu::Texture,
+    saved_view: wgpu::TextureView,
+    output: wgpu::Texture,
+    output_view: wgpu::TextureView,
+    width: u32,
+    height: u32,
+}
+
+impl AlphaLockRenderer {
+    pub(crate) fn new(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, String> {
+        let (width, height) = (width.max(1), height.max(1));
+        device_push_scopes(device.as_ref());
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("misa-rin alpha lock shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("alpha_lock.wgsl"))),
+        });
+
+        let sampled = |binding: u32| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("misa-rin alpha lock bgl"),
+            entries: &[
+                sampled(0),
+                sampled(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: LAYER_TEXTURE_FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<LockUniform>() as u64,
+                        ),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("misa-rin alpha lock pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("misa-rin alpha lock pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "main",
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("misa-rin alpha lock uniforms"),
+            size: std::mem::size_of::<LockUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(
+            &uniform_buffer,
+            0,
+            bytemuck::bytes_of(&LockUniform {
+                width,
+                height,
+                _pad0: 0,
+                _pad1: 0,
+            }),
+        );
+
+        let (saved, saved_view) = create_texture(
+            device.as_ref(),
+            width,
+            height,
+            "saved",
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        );
+        let (output, output_view) = create_texture(
+            device.as_ref(),
+            width,
+            height,
+            "output",
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        );
+
+        if let Some(err) = device_pop_scope(device.as_ref()) {
+            return Err(format!("alpha lock init failed: {err}"));
+        }
+        Ok(Self {
+            device,
+            queue,
+            pipeline,
+            bind_group_layout,
+            uniform_buffer,
+            saved,
+            saved_view,
+            output,
+            output_view,
+            width,
+            height,
+        })
+    }
+
+    /// Copies slice `layer_index` of `layer_texture` aside for `restore`.
+    pub(crate) fn save(
+        &self,
+        layer_texture: &wgpu::Texture,
+        layer_index: u32,
+    ) -> Result<(), String> {
+        device_push_scopes(self.device.as_ref());
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("misa-rin alpha lock save encoder"),
+            });
+        encoder.copy_texture_to_texture(
+            layer_slice(layer_texture, layer_index),
+            layer_slice(&self.saved, 0),
+            self.extent(),
+        );
+        self.queue.submit(Some(encoder.finish()));
+        match device_pop_scope(self.device.as_ref()) {
+            Some(err) => Err(format!("alpha lock save failed: {err}")),
+            None => Ok(()),
+        }
+    }
+
+    /// Rewrites slice `layer_index` of `layer_texture`, seen through
+    /// `layer_view`, with its current colours under the alpha last saved.
+    pub(crate) fn restore(
+        &self,
+        layer_texture: &wgpu::Texture,
+        layer_view: &wgpu::TextureView,
+        layer_index: u32,
+    ) -> Result<(), String> {
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("misa-rin alpha lock bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.saved_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(layer_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&self.output_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        device_push_scopes(self.device.as_ref());
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("misa-rin alpha lock restore encoder"),
+            });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("misa-rin alpha lock pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(self.width.div_ceil(8), self.height.div_ceil(8), 1);
+        }
+        encoder.copy_texture_to_texture(
+            layer_slice(&self.output, 0),
+            layer_slice(layer_texture, layer_index),
+            self.extent(),
+        );
+        self.queue.submit(Some(encoder.finish()));
+        match device_pop_scope(self.device.as_ref()) {
+            Some(err) => Err(format!("alpha lock restore failed: {err}")),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn extent(&self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        }
+    }
+}
+
+fn layer_slice(texture: &wgpu::Texture, layer_index: u32) -> wgpu::ImageCopyTexture<'_> {
+    wgpu::ImageCopyTexture {
+        texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d {
+            x: 0,
+            y: 0,
+            z: layer_index,
+        },
+        aspect: wgpu::TextureAspect::All,
+    }
+}
+
+fn create_texture(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    label_suffix: &str,
+    usage: wgpu::TextureUsages,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(&format!("misa-rin alpha lock {label_suffix}")),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: LAYER_TEXTURE_FORMAT,
+        usage,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+fn device_push_scopes(device: &wgpu::Device) {
+    device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+}
+
+fn device_pop_scope(device: &wgpu::Device) -> Option<wgpu::Error> {
+    let mut out: Option<wgpu::Error> = None;
+    for _ in 0..2 {
+        if let Some(err) = pollster::block_on(device.pop_error_scope()) {
+            out.get_or_insert(err);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas_engine::engine::{device_context, read_r32uint_region};
+    use crate::canvas_engine::layers::create_working;
+    use crate::cpu_brush::lock_alpha_texel;
+
+    fn write_layer(queue: &wgpu::Queue, texture: &wgpu::Texture, width: u32, pixels: &[u32]) {
+        let height = pixels.len() as u32 / width;
+        queue.write_texture(
+            layer_slice(texture, 0),
+            bytemuck::cast_slice(pixels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(width * 4),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    #[test]
+    fn restore_matches_the_cpu_alpha_lock() {
+        let ctx = match device_context() {
+            Ok(ctx) => ctx,
+            Err(err) => {
+                eprintln!("skipping GPU alpha lock test: {err}");
+                return;
+            }
+        };
+        let (device, queue) = (ctx.device.as_ref(), ctx.queue.as_ref());
+        let (width, height) = (8u32, 4u32);
+        let (texture, view, _) = create_working(device, width, height);
+        // Every pairing of transparent, translucent and opaque texels.
+        let alphas = [0x00u32, 0x40, 0xFF, 0x00, 0x80, 0xFF, 0x01, 0xC0];
+        let before: Vec<u32> = (0..width * height)
+            .map(|idx| alphas[(idx % 8) as usize] << 24 | (0x203040 + idx))
+            .collect();
+        let after: Vec<u32> = (0..width * height)
+            .map(|idx| alphas[((idx / 8 + idx) % 8) as usize] << 24 | (0xA0B0C0 - idx))
+            .collect();
+
+        let lock =
+            AlphaLockRenderer::new(ctx.device.clone(), ctx.queue.clone(), width, height).unwrap();
+        write_layer(queue, &texture, width, &before);
+        lock.save(&texture, 0).unwrap();
+        write_layer(queue, &texture, width, &after);
+        lock.restore(&texture, &view, 0).unwrap();
+
+        let restored =
+            read_r32uint_region(device, queue, &texture, 0, (0, 0, width, height)).unwrap();
+        let expected: Vec<u32> =
+            before.iter().zip(&after).map(|(&b, &a)| lock_alpha_texel(b, a)).collect();
+        assert_eq!(restored, expected);
+    }
+}
@@ -0,0 +1,40 @@
+struct LockConfig {
+  width: u32,
+  height: u32,
+  _pad0: u32,
+  _pad1: u32,
+};
+
+@group(0) @binding(0)
+var before_tex: texture_2d<f32>;
+
+@group(0) @binding(1)
+var after_tex: texture_2d<f32>;
+
+@group(0) @binding(2)
+var dst_tex: texture_storage_2d<rgba8unorm, write>;
+
+@group(0) @binding(3)
+var<uniform> cfg: LockConfig;
+
+fn to_u8(x: f32) -> u32 {
+  let v = floor(clamp(x, 0.0, 1.0) * 255.0 + 0.5);
+  return u32(clamp(v, 0.0, 255.0));
+}
+
+// The colour of `after` under the alpha of `before`; texels either side
+// left transparent keep `before`, as `lock_alpha_texel` does on the CPU.
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+  if (id.x >= cfg.width || id.y >= cfg.height) {
+    return;
+  }
+  let coord = vec2<i32>(i32(id.x), i32(id.y));
+  let before = textureLoad(before_tex, coord, 0);
+  let after = textureLoad(after_tex, coord, 0);
+  var out = before;
+  if (to_u8(before.w) != 0u && to_u8(after.w) != 0u) {
+    out = vec4<f32>(after.xyz, before.w);
+  }
+  textureStore(dst_tex, coord, out);
+}
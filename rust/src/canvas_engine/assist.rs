@@ -0,0 +1,443 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use crate::gpu::brush_renderer::Point2D;
+use crate::gpu::debug::{self, LogLevel};
+
+// How far the pointer has to travel before a perspective stroke picks its
+// direction, so hand jitter on the down point does not decide it.
+const PERSPECTIVE_LOCK_DISTANCE: f32 = 4.0;
+const DEFAULT_SNAP_TOLERANCE_DEGREES: f32 = 14.0;
+const ELLIPSE_OVERLAY_SEGMENTS: usize = 96;
+const PERSPECTIVE_OVERLAY_RAYS: usize = 24;
+const PARALLEL_OVERLAY_LINES: i32 = 8;
+
+/// A shape strokes are pulled onto. Coordinates are canvas pixels and angles
+/// radians.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) enum AssistGuide {
+    #[default]
+    Off,
+    /// Every stroke lies on the line through `a` and `b`.
+    Ruler { a: Point2D, b: Point2D },
+    /// Each stroke is a straight line at `angle` through its own start point.
+    Parallel { angle: f32 },
+    /// With `concentric` each stroke keeps the shape and centre but picks the
+    /// size that passes through its start point; otherwise it traces this
+    /// exact ellipse.
+    Ellipse {
+        center: Point2D,
+        radius_x: f32,
+        radius_y: f32,
+        rotation: f32,
+        concentric: bool,
+    },
+    /// Strokes lock to the horizontal, the vertical or the line towards one
+    /// of the vanishing points, whichever the first movement is closest to.
+    Perspective {
+        vanishing_points: [Point2D; 3],
+        count: usize,
+        tolerance_degrees: f32,
+    },
+}
+
+impl AssistGuide {
+    /// Builds a guide from the FFI layout of `kind`:
+    ///
+    /// - 0 off, no params
+    /// - 1 ruler `[ax, ay, bx, by]`
+    /// - 2 parallel `[angle]`
+    /// - 3 ellipse and 4 concentric ellipse `[cx, cy, rx, ry, rotation]`
+    /// - 5 perspective `[tolerance_degrees, x1, y1, x2?, y2?, x3?, y3?]`
+    pub(crate) fn from_params(kind: u32, params: &[f32]) -> Option<Self> {
+        if params.iter().any(|value| !value.is_finite()) {
+            return None;
+        }
+        let point = |i: usize| Point2D {
+            x: params[i],
+            y: params[i + 1],
+        };
+        match (kind, params.len()) {
+            (0, _) => Some(Self::Off),
+            (1, 4) => Some(Self::Ruler {
+                a: point(0),
+                b: point(2),
+            }),
+            (2, 1) => Some(Self::Parallel { angle: params[0] }),
+            (3 | 4, 5) if params[2] > 0.0 && params[3] > 0.0 => Some(Self::Ellipse {
+                center: point(0),
+                radius_x: params[2],
+                radius_y: params[3],
+                rotation: params[4],
+                concentric: kind == 4,
+            }),
+            (5, 3 | 5 | 7) => {
+                let count = (params.len() - 1) / 2;
+                let mut vanishing_points = [Point2D { x: 0.0, y: 0.0 }; 3];
+                for (i, vp) in vanishing_points.iter_mut().take(count).enumerate() {
+                    *vp = point(1 + i * 2);
+                }
+                let tolerance_degrees = if params[0] > 0.0 {
+                    params[0].min(180.0)
+                } else {
+                    DEFAULT_SNAP_TOLERANCE_DEGREES
+                };
+                Some(Self::Perspective {
+                    vanishing_points,
+                    count,
+                    tolerance_degrees,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        !matches!(self, Self::Off)
+    }
+
+    /// Guide lines for the present overlay as canvas-space segments.
+    pub(crate) fn overlay_lines(
+        &self,
+        canvas_width: u32,
+        canvas_height: u32,
+    ) -> Vec<(Point2D, Point2D)> {
+        let width = canvas_width as f32;
+        let height = canvas_height as f32;
+        // Long enough that every infinite line crosses the whole canvas.
+        let reach = (width * width + height * height).sqrt() * 2.0;
+        let line = |origin: Point2D, (dx, dy): (f32, f32)| {
+            (
+                Point2D {
+                    x: origin.x - dx * reach,
+                    y: origin.y - dy * reach,
+                },
+                Point2D {
+                    x: origin.x + dx * reach,
+                    y: origin.y + dy * reach,
+                },
+            )
+        };
+        match *self {
+            Self::Off => Vec::new(),
+            Self::Ruler { a, b } => match direction(a, b) {
+                Some(dir) => vec![line(a, dir)],
+                None => Vec::new(),
+            },
+            Self::Parallel { angle } => {
+                let (sin, cos) = angle.sin_cos();
+                let spacing = reach / 2.0 / PARALLEL_OVERLAY_LINES as f32;
+                (-PARALLEL_OVERLAY_LINES..=PARALLEL_OVERLAY_LINES)
+                    .map(|k| {
+                        let offset = k as f32 * spacing;
+                        let origin = Point2D {
+                            x: width * 0.5 - sin * offset,
+                            y: height * 0.5 + cos * offset,
+                        };
+                        line(origin, (cos, sin))
+                    })
+                    .collect()
+            }
+            Self::Ellipse {
+                center,
+                radius_x,
+                radius_y,
+                rotation,
+                concentric,
+            } => {
+                let scales: &[f32] = if concentric { &[0.5, 1.0, 1.5] } else { &[1.0] };
+                let mut lines = Vec::with_capacity(scales.len() * ELLIPSE_OVERLAY_SEGMENTS);
+                for scale in scales {
+                    let at = |i: usize| {
+                        let t = i as f32 / ELLIPSE_OVERLAY_SEGMENTS as f32 * TAU;
+                        ellipse_point(center, radius_x * scale, radius_y * scale, rotation, t)
+                    };
+                    lines.extend((0..ELLIPSE_OVERLAY_SEGMENTS).map(|i| (at(i), at(i + 1))));
+                }
+                lines
+            }
+            Self::Perspective {
+                vanishing_points,
+                count,
+                ..
+            } => {
+                let mut lines = vec![line(vanishing_points[0], (1.0, 0.0))];
+                for vp in vanishing_points.iter().take(count) {
+                    lines.extend((0..PERSPECTIVE_OVERLAY_RAYS).map(|i| {
+                        let (sin, cos) =
+                            (i as f32 / PERSPECTIVE_OVERLAY_RAYS as f32 * TAU).sin_cos();
+                        let end = Point2D {
+                            x: vp.x + cos * reach,
+                            y: vp.y + sin * reach,
+                        };
+                        (*vp, end)
+                    }));
+                }
+                lines
+            }
+        }
+    }
+}
+
+/// The guide settings the engine holds. Kept across `SetBrush`.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct StrokeAssist {
+    pub(crate) guide: AssistGuide,
+    pub(crate) show_overlay: bool,
+}
+
+impl StrokeAssist {
+    pub(crate) fn set(&mut self, kind: u32, params: &[f32], show_overlay: bool) {
+        match AssistGuide::from_params(kind, params) {
+            Some(guide) => {
+                self.guide = guide;
+                self.show_overlay = show_overlay;
+            }
+            None => debug::log(
+                LogLevel::Warn,
+                format_args!(
+                    "ignoring assist kind {kind} with {} unusable params",
+                    params.len()
+                ),
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Lock {
+    Line {
+        origin: Point2D,
+        dir: (f32, f32),
+    },
+    Ellipse {
+        center: Point2D,
+        radius_x: f32,
+        radius_y: f32,
+        rotation: f32,
+    },
+}
+
+/// Per-stroke state that projects raw samples onto the active guide. Guides
+/// that depend on where the stroke starts are fixed on the down point.
+pub(crate) struct AssistProjector {
+    anchor: Option<Point2D>,
+    lock: Option<Lock>,
+}
+
+impl AssistProjector {
+    pub(crate) fn new() -> Self {
+        Self {
+            anchor: None,
+            lock: None,
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.anchor = None;
+        self.lock = None;
+    }
+
+    pub(crate) fn project(&mut self, guide: &AssistGuide, pos: Point2D, is_down: bool) -> Point2D {
+        if !guide.is_active() {
+            return pos;
+        }
+        if is_down || self.anchor.is_none() {
+            self.anchor = Some(pos);
+            self.lock = initial_lock(guide, pos);
+        }
+        let anchor = self.anchor.unwrap_or(pos);
+        if self.lock.is_none() {
+            if let AssistGuide::Perspective {
+                vanishing_points,
+                count,
+                tolerance_degrees,
+            } = guide
+            {
+                self.lock =
+                    perspective_lock(anchor, pos, &vanishing_points[..*count], *tolerance_degrees);
+                if self.lock.is_none() {
+                    let moved = (pos.x - anchor.x).hypot(pos.y - anchor.y);
+                    return if moved < PERSPECTIVE_LOCK_DISTANCE {
+                        anchor
+                    } else {
+                        pos
+                    };
+                }
+            }
+        }
+        match self.lock {
+            Some(Lock::Line { origin, dir }) => project_on_line(origin, dir, pos),
+            Some(Lock::Ellipse {
+                center,
+                radius_x,
+                radius_y,
+                rotation,
+            }) => project_on_ellipse(center, radius_x, radius_y, rotation, pos),
+            None => pos,
+        }
+    }
+}
+
+fn initial_lock(guide: &AssistGuide, anchor: Point2D) -> Option<Lock> {
+    match *guide {
+        AssistGuide::Off | AssistGuide::Perspective { .. } => None,
+        AssistGuide::Ruler { a, b } => direction(a, b).map(|dir| Lock::Line { origin: a, dir }),
+        AssistGuide::Parallel { angle } => {
+            let (sin, cos) = angle.sin_cos();
+            Some(Lock::Line {
+                origin: anchor,
+                dir: (cos, sin),
+            })
+        }
+        AssistGuide::Ellipse {
+            center,
+            radius_x,
+            radius_y,
+            rotation,
+            concentric,
+        } => {
+            let scale = if concentric {
+                let (u, v) = to_ellipse_space(center, radius_x, radius_y, rotation, anchor);
+                let scale = u.hypot(v);
+                if scale <= 1e-4 {
+                    return None;
+                }
+                scale
+            } else {
+                1.0
+            };
+            Some(Lock::Ellipse {
+                center,
+                radius_x: radius_x * scale,
+                radius_y: radius_y * scale,
+                rotation,
+            })
+        }
+    }
+}
+
+/// Picks the guide direction closest to the movement so far, ignoring which
+/// way along the line it goes. `None` until the pointer has moved far enough
+/// or while no direction is within `tolerance_degrees`.
+fn perspective_lock(
+    anchor: Point2D,
+    pos: Point2D,
+    vanishing_points: &[Point2D],
+    tolerance_degrees: f32,
+) -> Option<Lock> {
+    let delta = (pos.x - anchor.x, pos.y - anchor.y);
+    let moved = delta.0.hypot(delta.1);
+    if moved < PERSPECTIVE_LOCK_DISTANCE {
+        return None;
+    }
+    let candidates = [(1.0, 0.0), (0.0, 1.0)].into_iter().chain(
+        vanishing_points
+            .iter()
+            .filter_map(|vp| direction(anchor, *vp)),
+    );
+    let mut best: Option<((f32, f32), f32)> = None;
+    for dir in candidates {
+        let cos = ((delta.0 * dir.0 + delta.1 * dir.1) / moved).abs().min(1.0);
+        let angle = cos.acos().to_degrees();
+        if best.is_none_or(|(_, best_angle)| angle < best_angle) {
+            best = Some((dir, angle));
+        }
+    }
+    let (dir, angle) = best?;
+    if tolerance_degrees < 179.9 && angle > tolerance_degrees {
+        return None;
+    }
+    Some(Lock::Line {
+        origin: anchor,
+        dir,
+    })
+}
+
+fn direction(from: Point2D, to: Point2D) -> Option<(f32, f32)> {
+    let (dx, dy) = (to.x - from.x, to.y - from.y);
+    let length = dx.hypot(dy);
+    if length < 1e-4 {
+        return None;
+    }
+    Some((dx / length, dy / length))
+}
+
+fn project_on_line(origin: Point2D, dir: (f32, f32), pos: Point2D) -> Point2D {
+    let t = (pos.x - origin.x) * dir.0 + (pos.y - origin.y) * dir.1;
+    Point2D {
+        x: origin.x + dir.0 * t,
+        y: origin.y + dir.1 * t,
+    }
+}
+
+/// `pos` in the frame where the ellipse is the unit circle.
+fn to_ellipse_space(
+    center: Point2D,
+    radius_x: f32,
+    radius_y: f32,
+    rotation: f32,
+    pos: Point2D,
+) -> (f32, f32) {
+    let (sin, cos) = rotation.sin_cos();
+    let (dx, dy) = (pos.x - center.x, pos.y - center.y);
+    (
+        (dx * cos + dy * sin) / radius_x,
+        (-dx * sin + dy * cos) / radius_y,
+    )
+}
+
+fn ellipse_point(center: Point2D, radius_x: f32, radius_y: f32, rotation: f32, t: f32) -> Point2D {
+    let (sin, cos) = rotation.sin_cos();
+    let (u, v) = (t.cos() * radius_x, t.sin() * radius_y);
+    Point2D {
+        x: center.x + u * cos - v * sin,
+        y: center.y + u * sin + v * cos,
+    }
+}
+
+/// Moves `pos` along its ray from the centre onto the ellipse. Not the
+/// nearest point on a flat ellipse, but it never jumps between arcs.
+fn project_on_ellipse(
+    center: Point2D,
+    radius_x: f32,
+    radius_y: f32,
+    rotation: f32,
+    pos: Point2D,
+) -> Point2D {
+    let (u, v) = to_ellipse_space(center, radius_x, radius_y, rotation, pos);
+    // Right on the centre every angle is as good; take the top of the ellipse.
+    let t = if u.hypot(v) <= 1e-6 {
+        -FRAC_PI_2
+    } else {
+        v.atan2(u)
+    };
+    ellipse_point(center, radius_x, radius_y, rotation, t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strokes_snap_to_parallel_concentric_and_perspective_guides() {
+        let mut projector = AssistProjector::new();
+
+        let parallel = AssistGuide::from_params(2, &[0.0]).unwrap();
+        projector.project(&parallel, Point2D { x: 10.0, y: 20.0 }, true);
+        let p = projector.project(&parallel, Point2D { x: 50.0, y: 27.0 }, false);
+        assert!((p.x - 50.0).abs() < 1e-4 && (p.y - 20.0).abs() < 1e-4);
+
+        let rings = AssistGuide::from_params(4, &[0.0, 0.0, 20.0, 10.0, 0.0]).unwrap();
+        projector.project(&rings, Point2D { x: 40.0, y: 0.0 }, true);
+        let p = projector.project(&rings, Point2D { x: 0.0, y: 5.0 }, false);
+        assert!(p.x.abs() < 1e-3 && (p.y - 20.0).abs() < 1e-3);
+
+        let perspective = AssistGuide::from_params(5, &[10.0, 100.0, 0.0]).unwrap();
+        let start = projector.project(&perspective, Point2D { x: 0.0, y: 100.0 }, true);
+        assert!(start.x == 0.0 && start.y == 100.0);
+        projector.project(&perspective, Point2D { x: 10.0, y: 89.0 }, false);
+        let p = projector.project(&perspective, Point2D { x: 40.0, y: 62.0 }, false);
+        // Locked onto the ray towards (100, 0), where x + y stays 100.
+        assert!((p.x + p.y - 100.0).abs() < 1e-3);
+    }
+}
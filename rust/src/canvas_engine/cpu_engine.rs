@@ -0,0 +1,3015 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::api::bucket_fill;
+use crate::api::gpu_composite::{apply_mask_value, cpu_composite_layers_region, GpuLayerData};
+use crate::cpu_brush::{
+    antialias_feather, cpu_brush_draw_points, dual_tip_dabs, hollow_composite, lock_alpha_pixels,
+    mix_pickup_sample, screentone_settings_from_params, BrushDrawParams, BrushPoint, DualBlend,
+    DualTip, DualTipSettings, GrainBlend, GrainSettings, GrainTexture, MixMode, MixReservoir,
+    MixSettings, PixelWindow, ScreentoneSettings, wet_composite, wet_density, WetSettings,
+};
+use crate::cpu_filters::{cpu_filters_apply_antialias, cpu_filters_apply_filter_rgba};
+use crate::gpu::blend_modes::map_canvas_blend_mode_index;
+use crate::gpu::brush_renderer::Point2D;
+use crate::gpu::debug::{self, LogLevel};
+use crate::gpu::filter_renderer::{
+    FILTER_FILL_EXPAND, FILTER_GAUSSIAN_BLUR, FILTER_HUE_SATURATION, FILTER_INVERT,
+    FILTER_LEAK_REMOVAL, FILTER_LINE_NARROW,
+};
+
+use super::cpu_undo::CpuUndoManager;
+use super::events::{EngineEventPublisher, EngineEventSink};
+use super::adjustments::{LayerAdjustment, LayerAdjustments};
+use super::groups::LayerGroups;
+use super::masks::{
+    invert_mask_texel, mask_gray, mask_stroke_settings, mask_texel_value, LayerMaskState,
+    MASK_REVEAL_ALL,
+};
+use super::journal::{
+    open_engine_journal, EngineJournal, JournalEntry, JournalLog, ReplayedCanvas,
+    JOURNAL_BACKEND_CPU,
+};
+use super::engine::{
+    brush_shape_index, build_streamline_preview, composite_layers_for_bucket_fill,
+    compute_spray_dirty_rect, ease_out_cubic, interpolate_streamline_points, register_engine,
+    remap_layer_index, reorder_vec, resample_scale_for_backlog, streamline_animation_duration,
+    translation_matrix, DeferredReads, EngineBackend, EngineCommand, EngineEntry,
+    EngineInputBatch, StreamlinePreview, VIEW_FLAG_BLACK_WHITE, VIEW_FLAG_MIRROR,
+};
+use super::rin::{EngineLayerSnapshot, EngineProjectSnapshot, RinMask};
+use super::stroke::{
+    apply_streamline, compute_dirty_rect_i32, compute_point_rotations, dab_alphas, dab_colors,
+    map_brush_shape, prepare_brush_samples, union_dirty_rect_i32, ColorDynamics, ColorJitter,
+    ColorMixSource, EngineBrushSettings, PenDynamics, PenState, SpringSettings, StrokeResampler,
+};
+use super::symmetry::{Symmetry, SymmetryMode};
+use super::tiles::TiledLayer;
+use super::velocity::{SimulatedPressure, SpeedPressureProfile};
+use super::types::EnginePoint;
+use super::undo::{LayerEdit, LayerProperties, UndoApplied, UndoTarget};
+
+// Matches the texture array limit the GPU backend can grow to.
+const CPU_MAX_LAYERS: usize = 256;
+// `cpu_brush` rasterizes the union bbox of every dab it is given, so long
+// strokes are fed in small batches to keep the scanned area tight.
+const CPU_STROKE_DAB_CHUNK: usize = 16;
+// Same cadence as the GPU streamline animation.
+const STREAMLINE_FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+const IDENTITY_MATRIX: [f32; 16] = [
+    1.0, 0.0, 0.0, 0.0, //
+    0.0, 1.0, 0.0, 0.0, //
+    0.0, 0.0, 1.0, 0.0, //
+    0.0, 0.0, 0.0, 1.0, //
+];
+
+struct CpuPresentTarget {
+    width: u32,
+    height: u32,
+    // Premultiplied BGRA8, laid out like the GPU present texture.
+    bytes: Vec<u8>,
+}
+
+/// Layer pixels plus the compositing state the present pass needs.
+pub(crate) struct CpuLayer {
+    pub(crate) tiles: TiledLayer,
+    // Only meaningful while `mask_state.present`; see `LayerMasks`.
+    pub(crate) mask: TiledLayer,
+    pub(crate) mask_state: LayerMaskState,
+    opacity: f64,
+    blend_mode_index: u32,
+    visible: bool,
+    clipping_mask: bool,
+    alpha_locked: bool,
+}
+
+impl CpuLayer {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            tiles: TiledLayer::new(width, height),
+            mask: TiledLayer::new(width, height),
+            mask_state: LayerMaskState::ABSENT,
+            opacity: 1.0,
+            blend_mode_index: 0,
+            visible: true,
+            clipping_mask: false,
+            alpha_locked: false,
+        }
+    }
+
+    fn raw_bytes(&self) -> u64 {
+        self.tiles.allocated_bytes() + self.mask.allocated_bytes()
+    }
+
+    pub(crate) fn pixels_mut(&mut self, target: UndoTarget) -> &mut TiledLayer {
+        match target {
+            UndoTarget::Layer => &mut self.tiles,
+            UndoTarget::Mask => &mut self.mask,
+        }
+    }
+
+    fn region_data(&self, region: (i32, i32, i32, i32)) -> GpuLayerData {
+        let mask = self.mask_state.applies().then(|| {
+            self.mask
+                .read_rect(region)
+                .into_iter()
+                .map(mask_texel_value)
+                .collect()
+        });
+        GpuLayerData {
+            pixels: self.tiles.read_rect(region),
+            opacity: self.opacity,
+            blend_mode_index: self.blend_mode_index,
+            visible: self.visible,
+            clipping_mask: self.clipping_mask,
+            mask,
+        }
+    }
+
+    fn properties(&self) -> LayerProperties {
+        LayerProperties {
+            opacity: self.opacity as f32,
+            visible: self.visible,
+            clipping_mask: self.clipping_mask,
+            alpha_locked: self.alpha_locked,
+            blend_mode: self.blend_mode_index,
+            mask_enabled: self.mask_state.enabled,
+        }
+    }
+
+    fn set_properties(&mut self, properties: LayerProperties) {
+        self.opacity = properties.opacity as f64;
+        self.visible = properties.visible;
+        self.clipping_mask = properties.clipping_mask;
+        self.alpha_locked = properties.alpha_locked;
+        self.blend_mode_index = properties.blend_mode;
+        if self.mask_state.present {
+            self.mask_state.enabled = properties.mask_enabled;
+        }
+    }
+}
+
+/// Undo step of the CPU engine that is not a pixel patch.
+pub(crate) type CpuLayerEdit = LayerEdit<CpuRemovedLayer, CpuCanvasSnapshot>;
+
+/// A layer taken out of the stack, with the state kept beside `layers`.
+pub(crate) struct CpuRemovedLayer {
+    layer: CpuLayer,
+    adjustment: Option<LayerAdjustment>,
+    group: u32,
+}
+
+/// Everything `ResizeCanvas` and `ResetCanvasWithLayers` replace.
+pub(crate) struct CpuCanvasSnapshot {
+    width: u32,
+    height: u32,
+    layers: Vec<CpuLayer>,
+    groups: LayerGroups,
+    adjustments: LayerAdjustments,
+}
+
+impl CpuRemovedLayer {
+    /// Tile memory the removed layer keeps while undo holds it.
+    pub(crate) fn raw_bytes(&self) -> u64 {
+        self.layer.raw_bytes()
+    }
+}
+
+impl CpuCanvasSnapshot {
+    /// Tile memory the canvas keeps while undo holds it.
+    pub(crate) fn raw_bytes(&self) -> u64 {
+        self.layers.iter().map(CpuLayer::raw_bytes).sum()
+    }
+}
+
+/// Software implementation of the canvas engine render thread. It accepts the
+/// same `EngineCommand`s and input batches as the wgpu backend, keeping layer
+/// pixels in sparse host-memory tiles and rasterizing through
+/// `cpu_brush`/`cpu_filters`.
+///
+/// Strokes are committed directly to the layer; the GPU backend's vector
+/// preview is compiled out (`ENABLE_STREAMLINE_VECTOR_PREVIEW`) and has no
+/// counterpart here.
+struct CpuEngineState {
+    canvas_width: u32,
+    canvas_height: u32,
+    // Layer order is bottom-to-top.
+    layers: Vec<CpuLayer>,
+    groups: LayerGroups,
+    adjustments: LayerAdjustments,
+    active_layer_index: usize,
+    view_flags: u32,
+    transform_matrix: [f32; 16],
+    transform_layer_index: u32,
+    transform_flags: u32,
+    brush_settings: EngineBrushSettings,
+    brush_mask: Option<(u32, u32, Vec<u8>)>,
+    grain_texture: Option<(u32, u32, Vec<u8>)>,
+    dual_mask: Option<(u32, u32, Vec<u8>)>,
+    /// Paint carried by a mixing brush, one per symmetry copy.
+    mix_reservoirs: Vec<MixReservoir>,
+    /// Pooled coverage of a watercolour stroke (in alpha) and the rect it
+    /// covers so far.
+    wet_mask: Option<TiledLayer>,
+    wet_rect: Option<(i32, i32, i32, i32)>,
+    hollow_mask: Option<HollowMask>,
+    streamline: Option<CpuStreamline>,
+    selection_mask: Option<Vec<u8>>,
+    spray_active_layer: Option<u32>,
+    mask_editing: bool,
+    stroke: StrokeResampler,
+    undo: CpuUndoManager,
+    present: Option<CpuPresentTarget>,
+    dirty: Option<(i32, i32, i32, i32)>,
+}
+
+/// Ring and core coverage a hollow stroke has pooled so far (in alpha), the
+/// counterpart of the GPU stroke mask.
+struct HollowMask {
+    outer: TiledLayer,
+    inner: TiledLayer,
+}
+
+/// A pen-up streamline settle in flight. Like the GPU `StreamlineAnimation`,
+/// the stroke is redrawn from its raw samples towards the smoothed ones, and
+/// its undo step stays open until the last frame.
+struct CpuStreamline {
+    start: Instant,
+    duration: Duration,
+    next_frame_at: Instant,
+    from_points: Vec<(Point2D, PenState)>,
+    to_points: Vec<(Point2D, PenState)>,
+    preview: Option<StreamlinePreview>,
+    layer_idx: usize,
+    target: UndoTarget,
+    brush_settings: EngineBrushSettings,
+}
+
+pub(crate) fn create_cpu_engine(width: u32, height: u32) -> Result<u64, String> {
+    if width == 0 || height == 0 {
+        return Err("engine_create: width/height must be > 0".to_string());
+    }
+
+    let (cmd_tx, cmd_rx) = mpsc::channel();
+    let (input_tx, input_rx) = mpsc::channel();
+    let input_queue_len = Arc::new(AtomicU64::new(0));
+    let frame_ready = Arc::new(AtomicBool::new(false));
+    let frame_in_flight = Arc::new(AtomicBool::new(false));
+
+    let journal = open_engine_journal(JOURNAL_BACKEND_CPU, width, height);
+    let thread_frame_ready = Arc::clone(&frame_ready);
+    let thread_input_queue_len = Arc::clone(&input_queue_len);
+    let events = EngineEventSink::default();
+    let thread_events = events.clone();
+    thread::Builder::new()
+        .name("misa-rin-canvas-cpu".to_string())
+        .spawn(move || {
+            cpu_render_thread_main(
+                CpuEngineState::new(width, height),
+                cmd_rx,
+                input_rx,
+                thread_frame_ready,
+                thread_input_queue_len,
+                journal,
+                thread_events,
+            )
+        })
+        .map_err(|err| format!("engine_create: cpu thread spawn failed: {err}"))?;
+
+    register_engine(EngineEntry {
+        backend: EngineBackend::Cpu,
+        mtl_device_ptr: 0,
+        frame_ready,
+        frame_in_flight,
+        cmd_tx,
+        input_tx,
+        input_queue_len,
+        events,
+        calibrations: Arc::default(),
+    })
+}
+
+fn is_deferred_read(cmd: &EngineCommand) -> bool {
+    matches!(
+        cmd,
+        EngineCommand::ReadLayer { .. }
+            | EngineCommand::ReadLayerPreview { .. }
+            | EngineCommand::ReadPresent { .. }
+            | EngineCommand::ReadProject { .. }
+    )
+}
+
+fn cpu_render_thread_main(
+    mut state: CpuEngineState,
+    cmd_rx: mpsc::Receiver<EngineCommand>,
+    input_rx: mpsc::Receiver<EngineInputBatch>,
+    frame_ready: Arc<AtomicBool>,
+    input_queue_len: Arc<AtomicU64>,
+    mut journal: Option<EngineJournal>,
+    events: EngineEventSink,
+) {
+    let mut deferred_reads = DeferredReads::default();
+    let mut event_publisher = EngineEventPublisher::new(events);
+    event_publisher.forward_thread_warnings();
+
+    loop {
+        while let Ok(cmd) = cmd_rx.try_recv() {
+            if state.present.is_none() && is_deferred_read(&cmd) {
+                deferred_reads.defer(cmd, input_queue_len.load(Ordering::Relaxed));
+                continue;
+            }
+            if let Some(journal) = journal.as_mut() {
+                journal.record_command(&cmd);
+            }
+            let (cmd, pending_operation) = event_publisher.watch_operation(cmd);
+            let stop = state.handle_command(cmd);
+            event_publisher.finish_operation(pending_operation);
+            if stop {
+                return;
+            }
+        }
+
+        let mut raw_points: Vec<EnginePoint> = Vec::new();
+        let mut timeout = if state.present.is_some() {
+            Duration::from_millis(4)
+        } else {
+            Duration::from_millis(16)
+        };
+        if let Some(settle) = state.streamline.as_ref() {
+            timeout = timeout.min(settle.next_frame_at.saturating_duration_since(Instant::now()));
+        }
+        match input_rx.recv_timeout(timeout) {
+            Ok(batch) => {
+                input_queue_len.fetch_sub(batch.points.len() as u64, Ordering::Relaxed);
+                deferred_reads.note_consumed(batch.points.len() as u64);
+                raw_points.extend(batch.points);
+                while let Ok(more) = input_rx.try_recv() {
+                    input_queue_len.fetch_sub(more.points.len() as u64, Ordering::Relaxed);
+                    deferred_reads.note_consumed(more.points.len() as u64);
+                    raw_points.extend(more.points);
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+
+        if state.present.is_none() {
+            while let Ok(cmd) = cmd_rx.try_recv() {
+                if is_deferred_read(&cmd) {
+                    deferred_reads.defer(cmd, input_queue_len.load(Ordering::Relaxed));
+                    continue;
+                }
+                if let Some(journal) = journal.as_mut() {
+                    journal.record_command(&cmd);
+                }
+                let (cmd, pending_operation) = event_publisher.watch_operation(cmd);
+                let stop = state.handle_command(cmd);
+                event_publisher.finish_operation(pending_operation);
+                if stop {
+                    return;
+                }
+            }
+        }
+
+        if !raw_points.is_empty() {
+            let backlog_points =
+                raw_points.len() as u64 + input_queue_len.load(Ordering::Relaxed);
+            if let Some(journal) = journal.as_mut() {
+                journal.record_input(backlog_points, &raw_points);
+            }
+            state
+                .stroke
+                .set_resample_scale(resample_scale_for_backlog(backlog_points));
+            state.consume_input(raw_points);
+        }
+        state.advance_streamline(Instant::now());
+
+        for cmd in deferred_reads.take_ready() {
+            if let Some(journal) = journal.as_mut() {
+                journal.record_command(&cmd);
+            }
+            let (cmd, pending_operation) = event_publisher.watch_operation(cmd);
+            let stop = state.handle_command(cmd);
+            event_publisher.finish_operation(pending_operation);
+            if stop {
+                return;
+            }
+        }
+
+        event_publisher.publish_undo_state(state.undo.can_undo(), state.undo.can_redo());
+        event_publisher.publish_layers_changed(
+            state.undo.take_layers_changed(),
+            state.canvas_width,
+            state.canvas_height,
+        );
+        event_publisher.publish_dirty(state.undo.take_dirty(), |layer_index| {
+            state
+                .layers
+                .get(layer_index as usize)
+                .and_then(|layer| layer.tiles.occupied_rect())
+        });
+
+        if state.render_present() {
+            frame_ready.store(true, Ordering::Release);
+        }
+    }
+}
+
+/// Feeds a software engine journal into a fresh software engine, in the order
+/// the original render thread processed it, and returns the resulting layers.
+///
+/// Replay runs synchronously on the caller's thread so command/input ordering
+/// never depends on channel timing. A settling streamline stroke jumps straight
+/// to its smoothed shape, which is where the animation ends anyway.
+pub(crate) fn replay_journal_on_cpu(log: JournalLog) -> ReplayedCanvas {
+    let mut state = CpuEngineState::new(log.width, log.height);
+    for record in log.records {
+        match record.entry {
+            JournalEntry::Command(cmd) => {
+                if state.handle_command(cmd) {
+                    break;
+                }
+            }
+            JournalEntry::Input {
+                backlog_points,
+                points,
+            } => {
+                if points.is_empty() {
+                    continue;
+                }
+                state
+                    .stroke
+                    .set_resample_scale(resample_scale_for_backlog(backlog_points));
+                state.consume_input(points);
+            }
+        }
+    }
+    state.finish_streamline();
+    let full = state.full_rect();
+    ReplayedCanvas {
+        width: state.canvas_width,
+        height: state.canvas_height,
+        layers: state
+            .layers
+            .iter()
+            .map(|layer| layer.region_data(full))
+            .collect(),
+    }
+}
+
+impl CpuEngineState {
+    fn new(canvas_width: u32, canvas_height: u32) -> Self {
+        let mut state = Self {
+            canvas_width,
+            canvas_height,
+            layers: vec![CpuLayer::new(canvas_width, canvas_height)],
+            groups: LayerGroups::new(1),
+            adjustments: LayerAdjustments::new(1),
+            active_layer_index: 0,
+            view_flags: 0,
+            transform_matrix: IDENTITY_MATRIX,
+            transform_layer_index: 0,
+            transform_flags: 0,
+            brush_settings: EngineBrushSettings::default(),
+            brush_mask: None,
+            grain_texture: None,
+            dual_mask: None,
+            mix_reservoirs: Vec::new(),
+            wet_mask: None,
+            wet_rect: None,
+            hollow_mask: None,
+            streamline: None,
+            selection_mask: None,
+            spray_active_layer: None,
+            mask_editing: false,
+            stroke: StrokeResampler::new(),
+            undo: CpuUndoManager::new(canvas_width, canvas_height),
+            present: None,
+            dirty: None,
+        };
+        // Layer 0 is the background fill layer (default white). Filling it
+        // here rather than on attach keeps anything loaded before the first
+        // present target.
+        state.fill_all_layers(0xFFFFFFFF);
+        state
+    }
+
+    fn full_rect(&self) -> (i32, i32, i32, i32) {
+        (0, 0, self.canvas_width as i32, self.canvas_height as i32)
+    }
+
+    fn mark_dirty(&mut self, rect: (i32, i32, i32, i32)) {
+        if rect.2 <= 0 || rect.3 <= 0 {
+            return;
+        }
+        self.dirty = union_dirty_rect_i32(self.dirty, rect);
+    }
+
+    fn mark_all_dirty(&mut self) {
+        self.mark_dirty(self.full_rect());
+    }
+
+    fn ensure_layer_index(&mut self, idx: usize) -> bool {
+        if idx < self.layers.len() {
+            return true;
+        }
+        if idx >= CPU_MAX_LAYERS {
+            debug::log(
+                LogLevel::Warn,
+                format_args!("CPU engine layer index {idx} exceeds limit {CPU_MAX_LAYERS}"),
+            );
+            return false;
+        }
+        while self.layers.len() <= idx {
+            self.layers
+                .push(CpuLayer::new(self.canvas_width, self.canvas_height));
+        }
+        self.groups.resize_layers(self.layers.len());
+        self.adjustments.resize_layers(self.layers.len());
+        true
+    }
+
+    fn fill_all_layers(&mut self, background_color_argb: u32) {
+        for (idx, layer) in self.layers.iter_mut().enumerate() {
+            let fill = if idx == 0 {
+                background_color_argb
+            } else {
+                0x00000000
+            };
+            layer.tiles.fill(fill);
+        }
+    }
+
+    fn clear_masks(&mut self) {
+        for layer in self.layers.iter_mut() {
+            layer.mask_state = LayerMaskState::ABSENT;
+        }
+        self.mask_editing = false;
+    }
+
+    /// Returns `true` when the render thread should stop.
+    fn handle_command(&mut self, cmd: EngineCommand) -> bool {
+        self.finish_streamline();
+        match cmd {
+            EngineCommand::Stop => return true,
+            EngineCommand::AttachPresentTexture { width, height, .. } => {
+                if width == 0 || height == 0 {
+                    debug::log(
+                        LogLevel::Warn,
+                        format_args!("CPU engine present attach ignored: size {width}x{height}"),
+                    );
+                    return false;
+                }
+                // The software backend always presents into host memory; the
+                // platform texture handle is only meaningful to the GPU path.
+                self.present = Some(CpuPresentTarget {
+                    width,
+                    height,
+                    bytes: vec![0; pixel_count(width, height) * 4],
+                });
+                self.mark_all_dirty();
+            }
+            #[cfg(target_os = "windows")]
+            EngineCommand::AttachPresentDxgi { reply, .. } => {
+                debug::log(
+                    LogLevel::Warn,
+                    format_args!("CPU engine cannot present into a DXGI surface"),
+                );
+                let _ = reply.send(None);
+            }
+            EngineCommand::ResetCanvas {
+                background_color_argb,
+            } => {
+                self.undo.reset();
+                self.groups.reset(self.layers.len());
+                self.adjustments.reset(self.layers.len());
+                self.clear_masks();
+                self.fill_all_layers(background_color_argb);
+                self.mark_all_dirty();
+            }
+            EngineCommand::ResetCanvasWithLayers {
+                layer_count,
+                background_color_argb,
+            } => {
+                let target_count = (layer_count.max(1) as usize).min(CPU_MAX_LAYERS);
+                let mut snapshot = self.blank_canvas(
+                    self.canvas_width,
+                    self.canvas_height,
+                    target_count,
+                    background_color_argb,
+                );
+                self.swap_canvas(&mut snapshot);
+                self.undo.record_edit(LayerEdit::Canvas(Box::new(snapshot)));
+            }
+            EngineCommand::ResizeCanvas {
+                width,
+                height,
+                layer_count,
+                background_color_argb,
+                reply,
+            } => {
+                let target_count = (layer_count.max(1) as usize).min(CPU_MAX_LAYERS);
+                let mut snapshot = self.blank_canvas(
+                    width.max(1),
+                    height.max(1),
+                    target_count,
+                    background_color_argb,
+                );
+                self.swap_canvas(&mut snapshot);
+                self.undo.record_edit(LayerEdit::Canvas(Box::new(snapshot)));
+                // Dart attaches a present target of the new size.
+                self.present = None;
+                self.dirty = None;
+                let _ = reply.send(true);
+            }
+            EngineCommand::FillLayer {
+                layer_index,
+                color_argb,
+            } => {
+                let idx = layer_index as usize;
+                if self.ensure_layer_index(idx) {
+                    self.begin_full_layer_undo(layer_index);
+                    self.layers[idx].tiles.fill(color_argb);
+                    self.undo.end_stroke(&self.layers[idx].tiles);
+                    self.mark_all_dirty();
+                }
+            }
+            EngineCommand::ClearLayer { layer_index } => {
+                let idx = layer_index as usize;
+                if self.ensure_layer_index(idx) {
+                    self.begin_full_layer_undo(layer_index);
+                    self.layers[idx].tiles.fill(0x00000000);
+                    self.undo.end_stroke(&self.layers[idx].tiles);
+                    self.mark_all_dirty();
+                }
+            }
+            EngineCommand::SetActiveLayer { layer_index } => {
+                let idx = layer_index as usize;
+                if self.ensure_layer_index(idx) {
+                    self.active_layer_index = idx;
+                }
+            }
+            EngineCommand::SetLayerOpacity {
+                layer_index,
+                opacity,
+            } => {
+                let idx = layer_index as usize;
+                if self.ensure_layer_index(idx) {
+                    let before = self.layers[idx].properties();
+                    self.layers[idx].opacity = if opacity.is_finite() {
+                        opacity.clamp(0.0, 1.0) as f64
+                    } else {
+                        0.0
+                    };
+                    self.record_layer_properties(idx, before);
+                    self.mark_all_dirty();
+                }
+            }
+            EngineCommand::SetLayerVisible {
+                layer_index,
+                visible,
+            } => {
+                let idx = layer_index as usize;
+                if self.ensure_layer_index(idx) {
+                    let before = self.layers[idx].properties();
+                    self.layers[idx].visible = visible;
+                    self.record_layer_properties(idx, before);
+                    self.mark_all_dirty();
+                }
+            }
+            EngineCommand::SetLayerClippingMask {
+                layer_index,
+                clipping_mask,
+            } => {
+                let idx = layer_index as usize;
+                if self.ensure_layer_index(idx) {
+                    let before = self.layers[idx].properties();
+                    self.layers[idx].clipping_mask = clipping_mask;
+                    self.record_layer_properties(idx, before);
+                    self.mark_all_dirty();
+                }
+            }
+            EngineCommand::SetLayerAlphaLock {
+                layer_index,
+                locked,
+            } => {
+                let idx = layer_index as usize;
+                if self.ensure_layer_index(idx) {
+                    let before = self.layers[idx].properties();
+                    self.layers[idx].alpha_locked = locked;
+                    self.record_layer_properties(idx, before);
+                }
+            }
+            EngineCommand::SetLayerBlendMode {
+                layer_index,
+                blend_mode_index,
+            } => {
+                let idx = layer_index as usize;
+                if self.ensure_layer_index(idx) {
+                    let before = self.layers[idx].properties();
+                    self.layers[idx].blend_mode_index =
+                        map_canvas_blend_mode_index(blend_mode_index).as_u32();
+                    self.record_layer_properties(idx, before);
+                    self.mark_all_dirty();
+                }
+            }
+            EngineCommand::ReorderLayer {
+                from_index,
+                to_index,
+            } => self.reorder_layer(from_index as usize, to_index as usize),
+            EngineCommand::InsertLayer { layer_index } => {
+                let top = self.layers.len();
+                if self.ensure_layer_index(top) {
+                    let idx = (layer_index as usize).min(top);
+                    self.move_layer(top, idx);
+                    self.undo.record_edit(LayerEdit::Remove {
+                        layer_index: idx as u32,
+                    });
+                }
+            }
+            EngineCommand::RemoveLayer { layer_index } => {
+                let idx = layer_index as usize;
+                if self.layers.len() > 1 && idx < self.layers.len() {
+                    let removed = self.take_layer(idx);
+                    self.undo.record_edit(LayerEdit::Insert {
+                        layer_index,
+                        layer: Box::new(removed),
+                    });
+                }
+            }
+            cmd @ (EngineCommand::CreateLayerGroup { .. }
+            | EngineCommand::DeleteLayerGroup { .. }
+            | EngineCommand::SetLayerGroupParent { .. }
+            | EngineCommand::MoveLayerToGroup { .. }
+            | EngineCommand::SetLayerGroupOpacity { .. }
+            | EngineCommand::SetLayerGroupVisible { .. }
+            | EngineCommand::SetLayerGroupBlendMode { .. }
+            | EngineCommand::SetLayerGroupPassThrough { .. }
+            | EngineCommand::SetLayerGroupCollapsed { .. }
+            | EngineCommand::GetLayerGroups { .. }) => {
+                if self.groups.apply_command(cmd) {
+                    self.mark_all_dirty();
+                }
+            }
+            cmd @ (EngineCommand::SetLayerAdjustment { .. }
+            | EngineCommand::ClearLayerAdjustment { .. }) => {
+                if self.adjustments.apply_command(cmd) {
+                    self.mark_all_dirty();
+                }
+            }
+            EngineCommand::CreateLayerMask { layer_index } => {
+                let Some(layer) = self.layers.get_mut(layer_index as usize) else {
+                    return false;
+                };
+                let before = layer.mask_state;
+                if before.present {
+                    return false;
+                }
+                layer.mask.fill(MASK_REVEAL_ALL);
+                layer.mask_state = LayerMaskState::CREATED;
+                self.undo.begin_target_stroke(layer_index, UndoTarget::Mask);
+                self.undo
+                    .record_mask_change(layer_index, before, LayerMaskState::CREATED);
+                self.undo.end_stroke(&layer.mask);
+                self.mark_all_dirty();
+            }
+            EngineCommand::DeleteLayerMask { layer_index } => {
+                let Some(layer) = self.layers.get_mut(layer_index as usize) else {
+                    return false;
+                };
+                let before = layer.mask_state;
+                if !before.present {
+                    return false;
+                }
+                layer.mask_state = LayerMaskState::ABSENT;
+                self.undo.begin_target_stroke(layer_index, UndoTarget::Mask);
+                self.undo
+                    .record_mask_change(layer_index, before, LayerMaskState::ABSENT);
+                self.undo.end_stroke(&layer.mask);
+                self.mark_all_dirty();
+            }
+            EngineCommand::SetLayerMaskEnabled {
+                layer_index,
+                enabled,
+            } => {
+                let idx = layer_index as usize;
+                let Some(layer) = self.layers.get_mut(idx) else {
+                    return false;
+                };
+                let state = layer.mask_state;
+                if !state.present || state.enabled == enabled {
+                    return false;
+                }
+                let before = layer.properties();
+                layer.mask_state.enabled = enabled;
+                self.record_layer_properties(idx, before);
+                self.mark_all_dirty();
+            }
+            EngineCommand::SetLayerMaskEditing { editing } => {
+                self.mask_editing = editing;
+            }
+            EngineCommand::InvertLayerMask { layer_index } => {
+                let full = self.full_rect();
+                let Some(layer) = self.layers.get_mut(layer_index as usize) else {
+                    return false;
+                };
+                if !layer.mask_state.present {
+                    return false;
+                }
+                self.undo.begin_target_stroke(layer_index, UndoTarget::Mask);
+                self.undo
+                    .capture_before_for_dirty_rect(&layer.mask, layer_index, full);
+                let texels: Vec<u32> = layer
+                    .mask
+                    .to_pixels()
+                    .into_iter()
+                    .map(invert_mask_texel)
+                    .collect();
+                layer.mask.set_pixels(&texels);
+                self.undo.end_stroke(&layer.mask);
+                self.mark_all_dirty();
+            }
+            EngineCommand::WriteLayerMask {
+                layer_index,
+                coverage,
+                reply,
+            } => {
+                let full = self.full_rect();
+                let expected_len = pixel_count(self.canvas_width, self.canvas_height);
+                let Some(layer) = self.layers.get_mut(layer_index as usize) else {
+                    let _ = reply.send(false);
+                    return false;
+                };
+                if !layer.mask_state.present || coverage.len() != expected_len {
+                    let _ = reply.send(false);
+                    return false;
+                }
+                self.undo.begin_target_stroke(layer_index, UndoTarget::Mask);
+                self.undo
+                    .capture_before_for_dirty_rect(&layer.mask, layer_index, full);
+                let texels: Vec<u32> = coverage.into_iter().map(mask_gray).collect();
+                layer.mask.set_pixels(&texels);
+                self.undo.end_stroke(&layer.mask);
+                self.mark_all_dirty();
+                let _ = reply.send(true);
+            }
+            EngineCommand::ApplyLayerMask { layer_index } => {
+                let full = self.full_rect();
+                let Some(layer) = self.layers.get_mut(layer_index as usize) else {
+                    return false;
+                };
+                let before = layer.mask_state;
+                if !before.present {
+                    return false;
+                }
+                self.undo.begin_stroke(layer_index);
+                self.undo
+                    .capture_before_for_dirty_rect(&layer.tiles, layer_index, full);
+                let mut pixels = layer.tiles.to_pixels();
+                for (pixel, texel) in pixels.iter_mut().zip(layer.mask.to_pixels()) {
+                    *pixel = apply_mask_value(*pixel, mask_texel_value(texel));
+                }
+                layer.tiles.set_pixels(&pixels);
+                layer.mask_state = LayerMaskState::ABSENT;
+                self.undo
+                    .record_mask_change(layer_index, before, LayerMaskState::ABSENT);
+                self.undo.end_stroke(&layer.tiles);
+                self.mark_all_dirty();
+            }
+            EngineCommand::ReadLayerMask { layer_index, reply } => {
+                let mask = self
+                    .layers
+                    .get(layer_index as usize)
+                    .filter(|layer| layer.mask_state.present)
+                    .map(|layer| {
+                        layer
+                            .mask
+                            .to_pixels()
+                            .into_iter()
+                            .map(mask_texel_value)
+                            .collect()
+                    });
+                let _ = reply.send(mask);
+            }
+            EngineCommand::SetViewFlags { view_flags } => {
+                let sanitized = view_flags & (VIEW_FLAG_MIRROR | VIEW_FLAG_BLACK_WHITE);
+                if self.view_flags != sanitized {
+                    self.view_flags = sanitized;
+                    self.mark_all_dirty();
+                }
+            }
+            EngineCommand::SetBrush {
+                color_argb,
+                base_radius,
+                use_pressure,
+                erase,
+                antialias_level,
+                brush_shape,
+                random_rotation,
+                smooth_rotation,
+                rotation_seed,
+                spacing,
+                hardness,
+                flow,
+                scatter,
+                rotation_jitter,
+                snap_to_pixel,
+                screentone_enabled,
+                screentone_spacing,
+                screentone_dot_size,
+                screentone_rotation,
+                screentone_softness,
+                screentone_shape,
+                hollow_enabled,
+                hollow_ratio,
+                hollow_erase_occluded,
+                streamline_strength,
+                smoothing_mode,
+                stabilizer_strength,
+            } => {
+                let settings = &mut self.brush_settings;
+                settings.color_argb = color_argb;
+                settings.base_radius = base_radius;
+                settings.use_pressure = use_pressure;
+                settings.erase = erase;
+                settings.antialias_level = antialias_level;
+                settings.shape = map_brush_shape(brush_shape);
+                settings.random_rotation = random_rotation;
+                settings.smooth_rotation = smooth_rotation;
+                settings.rotation_seed = rotation_seed;
+                settings.spacing = spacing;
+                settings.hardness = hardness;
+                settings.flow = flow;
+                settings.scatter = scatter;
+                settings.rotation_jitter = rotation_jitter;
+                settings.snap_to_pixel = snap_to_pixel;
+                settings.screentone_enabled = screentone_enabled;
+                settings.screentone_spacing = screentone_spacing;
+                settings.screentone_dot_size = screentone_dot_size;
+                settings.screentone_rotation = screentone_rotation;
+                settings.screentone_softness = screentone_softness;
+                settings.screentone_shape = map_brush_shape(screentone_shape);
+                settings.hollow_enabled = hollow_enabled;
+                settings.hollow_ratio = hollow_ratio;
+                settings.hollow_erase_occluded = hollow_erase_occluded;
+                settings.streamline_strength = streamline_strength;
+                settings.smoothing_mode = (smoothing_mode as u8).min(4);
+                settings.stabilizer_strength = stabilizer_strength;
+                settings.sanitize();
+            }
+            EngineCommand::SetBrushMask {
+                width,
+                height,
+                mask,
+            } => {
+                self.brush_settings.custom_mask_enabled = false;
+                if width == 0 || height == 0 || mask.is_empty() {
+                    self.brush_mask = None;
+                    return false;
+                }
+                if mask.len() != pixel_count(width, height) {
+                    debug::log(
+                        LogLevel::Warn,
+                        format_args!(
+                            "CPU engine brush mask size mismatch: got {}, expected {}",
+                            mask.len(),
+                            pixel_count(width, height)
+                        ),
+                    );
+                    self.brush_mask = None;
+                    return false;
+                }
+                self.brush_mask = Some((width, height, mask));
+                self.brush_settings.custom_mask_enabled = true;
+            }
+            EngineCommand::ClearBrushMask => {
+                self.brush_settings.custom_mask_enabled = false;
+                self.brush_mask = None;
+            }
+            EngineCommand::SetPenDynamics {
+                tilt_size,
+                tilt_opacity,
+                azimuth_angle,
+                barrel_angle,
+            } => {
+                self.brush_settings.dynamics = PenDynamics {
+                    tilt_size,
+                    tilt_opacity,
+                    azimuth_angle,
+                    barrel_angle,
+                };
+                self.brush_settings.dynamics.sanitize();
+            }
+            EngineCommand::SetPressureCurve { target, points } => {
+                self.brush_settings.pressure_curves.set(target, &points);
+            }
+            EngineCommand::SetSimulatedPressure {
+                profile,
+                tapered_caps,
+            } => {
+                self.brush_settings.simulated_pressure = SimulatedPressure {
+                    profile: SpeedPressureProfile::from_index(profile),
+                    tapered_caps,
+                };
+            }
+            EngineCommand::SetSpringStabilizer {
+                mass,
+                stiffness,
+                damping,
+            } => {
+                self.brush_settings.spring = SpringSettings {
+                    mass,
+                    stiffness,
+                    damping,
+                };
+                self.brush_settings.spring.sanitize();
+            }
+            EngineCommand::SetAssist {
+                kind,
+                params,
+                show_overlay,
+            } => self.brush_settings.assist.set(kind, &params, show_overlay),
+            EngineCommand::SetSymmetry {
+                mode,
+                center_x,
+                center_y,
+                folds,
+            } => {
+                self.brush_settings.symmetry = Symmetry {
+                    mode: SymmetryMode::from_index(mode),
+                    center: Point2D {
+                        x: center_x,
+                        y: center_y,
+                    },
+                    folds,
+                };
+                self.brush_settings.symmetry.sanitize();
+            }
+            EngineCommand::SetGrainTexture {
+                width,
+                height,
+                pixels,
+            } => {
+                self.grain_texture = None;
+                if width == 0 || height == 0 || pixels.is_empty() {
+                    return false;
+                }
+                if pixels.len() != pixel_count(width, height) {
+                    debug::log(
+                        LogLevel::Warn,
+                        format_args!(
+                            "CPU engine grain texture size mismatch: got {}, expected {}",
+                            pixels.len(),
+                            pixel_count(width, height)
+                        ),
+                    );
+                    return false;
+                }
+                self.grain_texture = Some((width, height, pixels));
+            }
+            EngineCommand::SetGrain {
+                enabled,
+                scale,
+                offset_x,
+                offset_y,
+                depth,
+                invert,
+                blend,
+            } => {
+                self.brush_settings.grain = GrainSettings {
+                    enabled,
+                    scale,
+                    offset_x,
+                    offset_y,
+                    depth,
+                    invert,
+                    blend: GrainBlend::from_index(blend),
+                };
+                self.brush_settings.grain.sanitize();
+            }
+            EngineCommand::SetMixing {
+                mode,
+                pickup,
+                dilution,
+                persistence,
+                extension,
+            } => {
+                self.brush_settings.mix = MixSettings {
+                    mode: MixMode::from_index(mode),
+                    pickup,
+                    dilution,
+                    persistence,
+                    extension,
+                };
+                self.brush_settings.mix.sanitize();
+            }
+            EngineCommand::SetWatercolor {
+                enabled,
+                edge,
+                granulation,
+                bleed,
+            } => {
+                self.brush_settings.wet = WetSettings {
+                    enabled,
+                    edge,
+                    granulation,
+                    bleed,
+                };
+                self.brush_settings.wet.sanitize();
+            }
+            EngineCommand::SetDualBrush {
+                enabled,
+                blend,
+                shape,
+                scale,
+                spacing,
+                scatter,
+                count,
+                size_jitter,
+            } => {
+                self.brush_settings.dual = DualTipSettings {
+                    enabled,
+                    blend: DualBlend::from_index(blend),
+                    shape,
+                    scale,
+                    spacing,
+                    scatter,
+                    count,
+                    size_jitter,
+                };
+                self.brush_settings.dual.sanitize();
+            }
+            EngineCommand::SetColorDynamics {
+                hue_jitter,
+                saturation_jitter,
+                value_jitter,
+                opacity_jitter,
+                stroke_hue_jitter,
+                stroke_saturation_jitter,
+                stroke_value_jitter,
+                stroke_opacity_jitter,
+                background_argb,
+                background_source,
+                background_amount,
+            } => {
+                self.brush_settings.color = ColorDynamics {
+                    dab: ColorJitter {
+                        hue: hue_jitter,
+                        saturation: saturation_jitter,
+                        value: value_jitter,
+                        opacity: opacity_jitter,
+                    },
+                    stroke: ColorJitter {
+                        hue: stroke_hue_jitter,
+                        saturation: stroke_saturation_jitter,
+                        value: stroke_value_jitter,
+                        opacity: stroke_opacity_jitter,
+                    },
+                    background_argb,
+                    background_source: ColorMixSource::from_index(background_source),
+                    background_amount,
+                };
+                self.brush_settings.color.sanitize();
+            }
+            EngineCommand::SetDualBrushMask {
+                width,
+                height,
+                mask,
+            } => {
+                self.dual_mask = None;
+                if width == 0 || height == 0 || mask.is_empty() {
+                    return false;
+                }
+                let expected = pixel_count(width, height).saturating_mul(2);
+                if mask.len() != expected {
+                    debug::log(
+                        LogLevel::Warn,
+                        format_args!(
+                            "CPU engine dual brush mask size mismatch: got {}, expected {}",
+                            mask.len(),
+                            expected
+                        ),
+                    );
+                    return false;
+                }
+                self.dual_mask = Some((width, height, mask));
+            }
+            EngineCommand::BeginSpray => {
+                let layer_idx = self.active_layer_index as u32;
+                self.spray_active_layer = Some(layer_idx);
+                self.undo.begin_stroke(layer_idx);
+            }
+            cmd @ EngineCommand::DrawSpray { .. } => self.draw_spray(cmd),
+            EngineCommand::EndSpray => {
+                if let Some(layer_idx) = self.spray_active_layer.take() {
+                    if let Some(layer) = self.layers.get(layer_idx as usize) {
+                        self.undo.end_stroke(&layer.tiles);
+                    }
+                }
+            }
+            EngineCommand::ApplyFilter {
+                layer_index,
+                filter_type,
+                param0,
+                param1,
+                param2,
+                param3,
+                reply,
+            } => {
+                let applied =
+                    self.apply_filter(layer_index, filter_type, param0, param1, param2, param3);
+                let _ = reply.send(applied);
+            }
+            EngineCommand::ApplyAntialias {
+                layer_index,
+                level,
+                reply,
+            } => {
+                let applied = self.apply_antialias(layer_index, level);
+                let _ = reply.send(applied);
+            }
+            cmd @ EngineCommand::BucketFill { .. } => self.bucket_fill(cmd),
+            EngineCommand::MagicWandMask {
+                layer_index,
+                start_x,
+                start_y,
+                sample_all_layers,
+                tolerance,
+                selection_mask,
+                reply,
+            } => {
+                let idx = layer_index as usize;
+                if !self.ensure_layer_index(idx) || !self.contains_point(start_x, start_y) {
+                    let _ = reply.send(None);
+                    return false;
+                }
+                let pixels = if sample_all_layers {
+                    self.composite_for_bucket_fill()
+                } else {
+                    self.layers[idx].tiles.to_pixels()
+                };
+                let mask = bucket_fill::magic_wand_mask(
+                    self.canvas_width as i32,
+                    self.canvas_height as i32,
+                    pixels,
+                    start_x,
+                    start_y,
+                    tolerance as i32,
+                    selection_mask,
+                );
+                let _ = reply.send(mask);
+            }
+            EngineCommand::ReadLayer { layer_index, reply } => {
+                let pixels = self
+                    .layers
+                    .get(layer_index as usize)
+                    .map(|layer| layer.tiles.to_pixels());
+                let _ = reply.send(pixels);
+            }
+            EngineCommand::ReadLayerPreview {
+                layer_index,
+                width,
+                height,
+                reply,
+            } => {
+                let _ = reply.send(self.read_layer_preview(layer_index, width, height));
+            }
+            EngineCommand::ReadPresent { reply } => {
+                let bytes = self.present.as_ref().and_then(|target| {
+                    if target.width == 0 || target.height == 0 {
+                        None
+                    } else {
+                        Some(target.bytes.clone())
+                    }
+                });
+                let _ = reply.send(bytes);
+            }
+            EngineCommand::ReadProject { reply } => {
+                let layers = self
+                    .layers
+                    .iter()
+                    .enumerate()
+                    .map(|(index, layer)| EngineLayerSnapshot {
+                        pixels: layer.tiles.to_pixels(),
+                        opacity: layer.opacity as f32,
+                        visible: layer.visible,
+                        clipping_mask: layer.clipping_mask,
+                        blend_mode_index: layer.blend_mode_index,
+                        alpha_locked: layer.alpha_locked,
+                        group: self.groups.layer_group(index),
+                        mask: layer.mask_state.present.then(|| RinMask {
+                            enabled: layer.mask_state.enabled,
+                            coverage: layer
+                                .mask
+                                .to_pixels()
+                                .into_iter()
+                                .map(mask_texel_value)
+                                .collect(),
+                        }),
+                        adjustment: self.adjustments.get(index),
+                    })
+                    .collect();
+                let _ = reply.send(Some(EngineProjectSnapshot {
+                    width: self.canvas_width,
+                    height: self.canvas_height,
+                    layers,
+                    groups: self.groups.groups().to_vec(),
+                }));
+            }
+            EngineCommand::WriteLayer {
+                layer_index,
+                pixels,
+                record_undo,
+                reply,
+            } => {
+                let idx = layer_index as usize;
+                if !self.ensure_layer_index(idx) {
+                    let _ = reply.send(false);
+                    return false;
+                }
+                let expected_len = pixel_count(self.canvas_width, self.canvas_height);
+                if expected_len == 0 || pixels.len() != expected_len {
+                    if expected_len > 0 {
+                        debug::log(
+                            LogLevel::Warn,
+                            format_args!(
+                                "layer write size mismatch: got {}, expected {}",
+                                pixels.len(),
+                                expected_len
+                            ),
+                        );
+                    }
+                    let _ = reply.send(false);
+                    return false;
+                }
+                if record_undo {
+                    self.begin_full_layer_undo(layer_index);
+                }
+                self.layers[idx].tiles.set_pixels(&pixels);
+                if record_undo {
+                    self.undo.end_stroke(&self.layers[idx].tiles);
+                }
+                self.mark_all_dirty();
+                let _ = reply.send(true);
+            }
+            EngineCommand::TranslateLayer {
+                layer_index,
+                delta_x,
+                delta_y,
+                reply,
+            } => {
+                if (layer_index as usize) >= self.layers.len() || (delta_x == 0 && delta_y == 0)
+                {
+                    let _ = reply.send(false);
+                    return false;
+                }
+                self.apply_layer_transform(layer_index, translation_matrix(delta_x, delta_y), false);
+                let _ = reply.send(true);
+            }
+            EngineCommand::SetLayerTransformPreview {
+                layer_index,
+                matrix,
+                enabled,
+                bilinear,
+            } => {
+                if enabled && (layer_index as usize) < self.layers.len() {
+                    self.transform_layer_index = layer_index;
+                    self.transform_flags = 1 | if bilinear { 2 } else { 0 };
+                    self.transform_matrix = matrix;
+                } else {
+                    self.transform_flags = 0;
+                }
+                self.mark_all_dirty();
+            }
+            EngineCommand::ApplyLayerTransform {
+                layer_index,
+                matrix,
+                bilinear,
+                reply,
+            } => {
+                if (layer_index as usize) >= self.layers.len() {
+                    let _ = reply.send(false);
+                    return false;
+                }
+                self.apply_layer_transform(layer_index, matrix, bilinear);
+                let _ = reply.send(true);
+            }
+            EngineCommand::GetLayerBounds { layer_index, reply } => {
+                let bounds = self
+                    .layers
+                    .get(layer_index as usize)
+                    .map(|layer| layer.tiles.bounds());
+                let _ = reply.send(bounds);
+            }
+            EngineCommand::SetSelectionMask { selection_mask } => {
+                let expected_len = pixel_count(self.canvas_width, self.canvas_height);
+                self.selection_mask = match selection_mask {
+                    Some(mask) if mask.len() == expected_len => Some(mask),
+                    Some(mask) => {
+                        if expected_len > 0 {
+                            debug::log(
+                                LogLevel::Warn,
+                                format_args!(
+                                    "selection mask size mismatch: got {}, expected {}",
+                                    mask.len(),
+                                    expected_len
+                                ),
+                            );
+                        }
+                        None
+                    }
+                    None => None,
+                };
+            }
+            cmd @ (EngineCommand::Undo | EngineCommand::Redo) => {
+                let redo = matches!(cmd, EngineCommand::Redo);
+                let applied = if redo {
+                    self.undo.redo(&mut self.layers)
+                } else {
+                    self.undo.undo(&mut self.layers)
+                };
+                match applied {
+                    UndoApplied::Nothing => {}
+                    UndoApplied::Pixels => self.mark_all_dirty(),
+                    UndoApplied::Edit(edit) => {
+                        match self.apply_layer_edit(edit) {
+                            Some(inverse) if redo => self.undo.finish_redo(inverse),
+                            Some(inverse) => self.undo.finish_undo(inverse),
+                            None => debug::log(
+                                LogLevel::Warn,
+                                format_args!(
+                                    "undo step does not match the current layers; dropped"
+                                ),
+                            ),
+                        }
+                        self.mark_all_dirty();
+                    }
+                }
+            }
+            EngineCommand::ClearUndoHistory => self.undo.reset(),
+            EngineCommand::SetUndoLimits {
+                max_steps,
+                budget_bytes,
+            } => self.undo.set_limits(max_steps as usize, budget_bytes),
+            EngineCommand::BeginUndoTransaction => self.undo.begin_transaction(),
+            EngineCommand::CommitUndoTransaction => self.undo.commit_transaction(),
+            EngineCommand::SetHistoryCheckpoint { name } => self.undo.set_checkpoint(name),
+            EngineCommand::PlanHistoryRoute { node_id, reply } => {
+                let _ = reply.send(self.undo.history_route(node_id));
+            }
+            EngineCommand::EnterHistoryBranch { node_id } => {
+                if !self.undo.enter_history_branch(node_id) {
+                    debug::log(
+                        LogLevel::Warn,
+                        format_args!("history node {node_id} does not start a branch"),
+                    );
+                }
+            }
+            EngineCommand::ReadHistory { reply } => {
+                let _ = reply.send(self.undo.history_nodes());
+            }
+            EngineCommand::CancelUndoTransaction => {
+                if self.undo.cancel_transaction(&mut self.layers) {
+                    self.mark_all_dirty();
+                }
+            }
+        }
+        false
+    }
+
+    fn reorder_layer(&mut self, from: usize, to: usize) {
+        let len = self.layers.len();
+        if len <= 1 || from >= len {
+            return;
+        }
+        let mut target = to.min(len);
+        if target > from {
+            target -= 1;
+        }
+        target = target.min(len - 1);
+        if target == from {
+            return;
+        }
+        self.move_layer(from, target);
+        self.undo.record_edit(LayerEdit::Reorder {
+            from: target as u32,
+            to: from as u32,
+        });
+    }
+
+    /// Moves the layer at `from` to `to`, both final positions.
+    fn move_layer(&mut self, from: usize, to: usize) {
+        if from == to {
+            return;
+        }
+        reorder_vec(&mut self.layers, from, to);
+        self.groups.reorder_layer(from, to);
+        self.adjustments.reorder_layer(from, to);
+        self.active_layer_index = remap_layer_index(self.active_layer_index, from, to);
+        self.transform_layer_index =
+            remap_layer_index(self.transform_layer_index as usize, from, to) as u32;
+        self.undo.reorder_layers(from as u32, to as u32);
+        self.mark_all_dirty();
+    }
+
+    fn take_layer(&mut self, idx: usize) -> CpuRemovedLayer {
+        let top = self.layers.len() - 1;
+        self.move_layer(idx, top);
+        let adjustment = self.adjustments.get(top);
+        let group = self.groups.layer_group(top);
+        let layer = self.layers.remove(top);
+        self.groups.resize_layers(top);
+        self.adjustments.resize_layers(top);
+        self.active_layer_index = self.active_layer_index.min(top.saturating_sub(1));
+        if self.transform_layer_index as usize >= top {
+            self.transform_layer_index = 0;
+        }
+        CpuRemovedLayer {
+            layer,
+            adjustment,
+            group,
+        }
+    }
+
+    fn put_layer(&mut self, removed: CpuRemovedLayer, idx: usize) -> bool {
+        let top = self.layers.len();
+        if top >= CPU_MAX_LAYERS {
+            return false;
+        }
+        self.layers.push(removed.layer);
+        self.groups.resize_layers(top + 1);
+        self.adjustments.resize_layers(top + 1);
+        self.adjustments.restore(top, removed.adjustment);
+        // The group may have been deleted since; the layer then stays at the root.
+        self.groups.set_layer_group(top, removed.group);
+        self.move_layer(top, idx.min(top));
+        true
+    }
+
+    fn record_layer_properties(&mut self, idx: usize, before: LayerProperties) {
+        let after = self.layers[idx].properties();
+        self.undo.record_layer_properties(idx as u32, before, after);
+    }
+
+    /// A `width` x `height` canvas of `layer_count` layers: layer 0 filled with
+    /// the background, the rest transparent. Layers keep the compositing
+    /// properties of the current ones at the same index.
+    fn blank_canvas(
+        &self,
+        width: u32,
+        height: u32,
+        layer_count: usize,
+        background_color_argb: u32,
+    ) -> CpuCanvasSnapshot {
+        let layers = (0..layer_count)
+            .map(|idx| {
+                let mut layer = CpuLayer::new(width, height);
+                if let Some(old) = self.layers.get(idx) {
+                    layer.set_properties(old.properties());
+                }
+                if idx == 0 {
+                    layer.tiles.fill(background_color_argb);
+                }
+                layer
+            })
+            .collect();
+        CpuCanvasSnapshot {
+            width,
+            height,
+            layers,
+            groups: LayerGroups::new(layer_count),
+            adjustments: LayerAdjustments::new(layer_count),
+        }
+    }
+
+    /// Swaps `snapshot` with the current canvas.
+    fn swap_canvas(&mut self, snapshot: &mut CpuCanvasSnapshot) {
+        let resized = (snapshot.width, snapshot.height) != (self.canvas_width, self.canvas_height);
+        std::mem::swap(&mut self.canvas_width, &mut snapshot.width);
+        std::mem::swap(&mut self.canvas_height, &mut snapshot.height);
+        std::mem::swap(&mut self.layers, &mut snapshot.layers);
+        std::mem::swap(&mut self.groups, &mut snapshot.groups);
+        std::mem::swap(&mut self.adjustments, &mut snapshot.adjustments);
+        self.mask_editing = false;
+        self.active_layer_index = self.active_layer_index.min(self.layers.len() - 1);
+        self.transform_layer_index = 0;
+        self.transform_flags = 0;
+        self.transform_matrix = IDENTITY_MATRIX;
+        if resized {
+            self.selection_mask = None;
+            self.spray_active_layer = None;
+            self.stroke = StrokeResampler::new();
+            self.present = None;
+            self.dirty = None;
+        }
+        self.undo
+            .set_canvas_size(self.canvas_width, self.canvas_height);
+        self.mark_all_dirty();
+    }
+
+    /// Applies an undo or redo edit and returns its inverse, or `None` when
+    /// it no longer fits the layers.
+    fn apply_layer_edit(&mut self, edit: CpuLayerEdit) -> Option<CpuLayerEdit> {
+        match edit {
+            LayerEdit::Properties {
+                layer_index,
+                properties,
+            } => {
+                let layer = self.layers.get_mut(layer_index as usize)?;
+                let current = layer.properties();
+                layer.set_properties(properties);
+                Some(LayerEdit::Properties {
+                    layer_index,
+                    properties: current,
+                })
+            }
+            LayerEdit::Reorder { from, to } => {
+                if from.max(to) as usize >= self.layers.len() {
+                    return None;
+                }
+                self.move_layer(from as usize, to as usize);
+                Some(LayerEdit::Reorder { from: to, to: from })
+            }
+            LayerEdit::Remove { layer_index } => {
+                if self.layers.len() <= 1 || layer_index as usize >= self.layers.len() {
+                    return None;
+                }
+                let removed = self.take_layer(layer_index as usize);
+                Some(LayerEdit::Insert {
+                    layer_index,
+                    layer: Box::new(removed),
+                })
+            }
+            LayerEdit::Insert { layer_index, layer } => {
+                let top = self.layers.len();
+                if !self.put_layer(*layer, layer_index as usize) {
+                    return None;
+                }
+                Some(LayerEdit::Remove {
+                    layer_index: (layer_index as usize).min(top) as u32,
+                })
+            }
+            LayerEdit::Canvas(mut snapshot) => {
+                self.swap_canvas(&mut snapshot);
+                Some(LayerEdit::Canvas(snapshot))
+            }
+        }
+    }
+
+    fn contains_point(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.canvas_width && (y as u32) < self.canvas_height
+    }
+
+    fn begin_full_layer_undo(&mut self, layer_index: u32) {
+        let full = self.full_rect();
+        self.undo.begin_stroke(layer_index);
+        if let Some(layer) = self.layers.get(layer_index as usize) {
+            self.undo
+                .capture_before_for_dirty_rect(&layer.tiles, layer_index, full);
+        }
+    }
+
+    fn composite_for_bucket_fill(&self) -> Vec<u32> {
+        let layers_pixels: Vec<Vec<u32>> =
+            self.layers.iter().map(|layer| layer.tiles.to_pixels()).collect();
+        let opacity: Vec<f32> = self.layers.iter().map(|layer| layer.opacity as f32).collect();
+        let visible: Vec<bool> = self.layers.iter().map(|layer| layer.visible).collect();
+        let clipping: Vec<bool> = self.layers.iter().map(|layer| layer.clipping_mask).collect();
+        composite_layers_for_bucket_fill(
+            self.canvas_width as usize,
+            self.canvas_height as usize,
+            &layers_pixels,
+            &opacity,
+            &visible,
+            &clipping,
+        )
+    }
+
+    fn consume_input(&mut self, raw_points: Vec<EnginePoint>) {
+        const FLAG_DOWN: u32 = 1;
+        const FLAG_UP: u32 = 4;
+
+        self.finish_streamline();
+        let layer_idx = self.active_layer_index;
+        if layer_idx >= self.layers.len() {
+            self.stroke = StrokeResampler::new();
+            return;
+        }
+        let (brush_settings, target) =
+            if self.mask_editing && self.layers[layer_idx].mask_state.present {
+                (mask_stroke_settings(self.brush_settings), UndoTarget::Mask)
+            } else {
+                (self.brush_settings, UndoTarget::Layer)
+            };
+        let mut segment: Vec<EnginePoint> = Vec::new();
+        for p in raw_points {
+            let is_down = (p.flags & FLAG_DOWN) != 0;
+            let is_up = (p.flags & FLAG_UP) != 0;
+
+            if is_down {
+                self.undo.begin_target_stroke(layer_idx as u32, target);
+                self.reset_stroke_paint();
+            } else {
+                self.undo.begin_stroke_if_needed(layer_idx as u32, target);
+            }
+
+            segment.push(p);
+
+            if is_up {
+                let emitted = self
+                    .stroke
+                    .consume_points(&brush_settings, std::mem::take(&mut segment));
+                self.draw_emitted_points(layer_idx, target, &brush_settings, &emitted);
+
+                if let Some(payload) = self.stroke.take_streamline_payload() {
+                    if payload.points.len() > 2 && payload.strength > 0.0001 {
+                        let smoothed = apply_streamline(&payload.points, payload.strength);
+                        if !smoothed.is_empty() && smoothed.len() == payload.points.len() {
+                            let now = Instant::now();
+                            self.streamline = Some(CpuStreamline {
+                                start: now,
+                                duration: streamline_animation_duration(payload.strength),
+                                next_frame_at: now + STREAMLINE_FRAME_INTERVAL,
+                                preview: build_streamline_preview(&payload.points, &smoothed),
+                                from_points: payload.points,
+                                to_points: smoothed,
+                                layer_idx,
+                                target,
+                                brush_settings,
+                            });
+                        }
+                    }
+                }
+                if self.streamline.is_none() {
+                    self.end_stroke(layer_idx, target, &brush_settings);
+                }
+            }
+        }
+
+        if !segment.is_empty() {
+            let emitted = self.stroke.consume_points(&brush_settings, segment);
+            self.draw_emitted_points(layer_idx, target, &brush_settings, &emitted);
+        }
+    }
+
+    /// Forgets paint a previous stroke left in the mixing reservoirs, the
+    /// watercolour pool and the hollow stroke mask.
+    fn reset_stroke_paint(&mut self) {
+        self.mix_reservoirs.clear();
+        self.wet_mask = None;
+        self.wet_rect = None;
+        self.hollow_mask = None;
+    }
+
+    fn end_stroke(
+        &mut self,
+        layer_idx: usize,
+        target: UndoTarget,
+        brush_settings: &EngineBrushSettings,
+    ) {
+        self.finish_wet_stroke(layer_idx, target, brush_settings);
+        self.undo.end_stroke(self.layers[layer_idx].pixels_mut(target));
+    }
+
+    /// Redraws the settling stroke at progress `t` over its pre-stroke
+    /// pixels.
+    fn render_streamline_frame(&mut self, t: f32) {
+        let Some(settle) = self.streamline.take() else {
+            return;
+        };
+        let (from, to) = match settle.preview.as_ref() {
+            Some((from, to)) if t < 0.999 => (from, to),
+            _ => (&settle.from_points, &settle.to_points),
+        };
+        let mut points = Vec::with_capacity(to.len());
+        interpolate_streamline_points(from, to, t, &mut points);
+        let pixels = self.layers[settle.layer_idx].pixels_mut(settle.target);
+        if self.undo.restore_current_before(pixels) {
+            self.mark_all_dirty();
+        }
+        self.reset_stroke_paint();
+        self.draw_emitted_points(
+            settle.layer_idx,
+            settle.target,
+            &settle.brush_settings,
+            &points,
+        );
+        self.streamline = Some(settle);
+    }
+
+    /// Jumps a settling stroke to its smoothed shape and closes its undo
+    /// step. Anything that touches the canvas calls this first.
+    fn finish_streamline(&mut self) {
+        if self.streamline.is_none() {
+            return;
+        }
+        self.render_streamline_frame(1.0);
+        if let Some(settle) = self.streamline.take() {
+            self.end_stroke(settle.layer_idx, settle.target, &settle.brush_settings);
+        }
+    }
+
+    fn advance_streamline(&mut self, now: Instant) {
+        let Some(settle) = self.streamline.as_mut() else {
+            return;
+        };
+        if now < settle.next_frame_at {
+            return;
+        }
+        settle.next_frame_at = now + STREAMLINE_FRAME_INTERVAL;
+        let elapsed = now.saturating_duration_since(settle.start).as_secs_f32();
+        let t = ease_out_cubic(elapsed / settle.duration.as_secs_f32());
+        if t >= 0.999 {
+            self.finish_streamline();
+        } else {
+            self.render_streamline_frame(t);
+        }
+    }
+
+    fn draw_emitted_points(
+        &mut self,
+        layer_idx: usize,
+        target: UndoTarget,
+        brush_settings: &EngineBrushSettings,
+        emitted: &[(Point2D, PenState)],
+    ) -> bool {
+        if emitted.is_empty() {
+            return false;
+        }
+        let mut drawn = false;
+        for (slot, copy) in brush_settings.symmetry.apply(emitted).iter().enumerate() {
+            drawn |= self.draw_symmetry_copy(layer_idx, target, brush_settings, slot, copy);
+        }
+        drawn
+    }
+
+    fn draw_symmetry_copy(
+        &mut self,
+        layer_idx: usize,
+        target: UndoTarget,
+        brush_settings: &EngineBrushSettings,
+        slot: usize,
+        emitted: &[(Point2D, PenState)],
+    ) -> bool {
+        let (points, radii) = prepare_brush_samples(brush_settings, emitted);
+        if points.is_empty() || points.len() != radii.len() {
+            return false;
+        }
+        let canvas_width = self.canvas_width;
+        let canvas_height = self.canvas_height;
+        let softness = brush_settings.stroke_softness(emitted);
+        let feather = antialias_feather(brush_settings.antialias_level);
+        let dirty_radii: Vec<f32> = radii
+            .iter()
+            .map(|r| r + feather.max(r * softness))
+            .collect();
+        let dirty = compute_dirty_rect_i32(&points, &dirty_radii, canvas_width, canvas_height);
+        if dirty.2 <= 0 || dirty.3 <= 0 {
+            return false;
+        }
+        let Some(layer) = self.layers.get_mut(layer_idx) else {
+            return false;
+        };
+        let lock_alpha = target == UndoTarget::Layer && layer.alpha_locked;
+        let pixels = layer.pixels_mut(target);
+        self.undo
+            .capture_before_for_dirty_rect(pixels, layer_idx as u32, dirty);
+        let mix_colors = if brush_settings.mixing() {
+            if self.mix_reservoirs.len() <= slot {
+                self.mix_reservoirs.resize(slot + 1, MixReservoir::default());
+            }
+            let reservoir = &mut self.mix_reservoirs[slot];
+            let undo = &self.undo;
+            let colors: Vec<[f32; 4]> = points
+                .iter()
+                .zip(radii.iter())
+                .map(|(point, radius)| {
+                    let sample = mix_pickup_sample(
+                        point.x,
+                        point.y,
+                        *radius,
+                        canvas_width,
+                        canvas_height,
+                        |x, y| undo.stroke_base_pixel(pixels, layer_idx as u32, x, y),
+                    );
+                    reservoir.step(&brush_settings.mix, brush_settings.color_argb, sample)
+                })
+                .collect();
+            Some(colors)
+        } else {
+            None
+        };
+
+        let rotations = compute_point_rotations(brush_settings, &points, emitted);
+        let origin = self.stroke.stroke_origin();
+        let alphas = dab_alphas(brush_settings, emitted, origin);
+        let colors = dab_colors(brush_settings, emitted, origin);
+        let brush_points: Vec<BrushPoint> = points
+            .iter()
+            .zip(radii.iter())
+            .enumerate()
+            .map(|(idx, (point, radius))| {
+                let (rot_sin, rot_cos) = rotations
+                    .as_ref()
+                    .map(|rots| (rots[idx].sin, rots[idx].cos))
+                    .unwrap_or((0.0, 1.0));
+                BrushPoint {
+                    x: point.x,
+                    y: point.y,
+                    radius: *radius,
+                    alpha: alphas.as_ref().map_or(1.0, |values| values[idx]),
+                    rot_sin,
+                    rot_cos,
+                }
+            })
+            .collect();
+        let dual_dabs = dual_tip_dabs(
+            &brush_settings.dual,
+            &brush_points,
+            brush_settings.rotation_seed,
+        );
+        let screentone = screentone_settings_from_params(
+            brush_settings.screentone_enabled as u8,
+            brush_settings.screentone_spacing,
+            brush_settings.screentone_dot_size,
+            brush_settings.screentone_rotation,
+            brush_settings.screentone_softness,
+            brush_shape_index(brush_settings.screentone_shape),
+        );
+        let custom_mask = if brush_settings.custom_mask_enabled {
+            self.brush_mask
+                .as_ref()
+                .map(|(width, height, mask)| (*width, *height, mask.as_slice()))
+        } else {
+            None
+        };
+
+        let params = BrushDrawParams {
+            color_argb: brush_settings.color_argb,
+            brush_shape: brush_shape_index(brush_settings.shape),
+            antialias_level: brush_settings.antialias_level,
+            softness,
+            erase: brush_settings.erase,
+            accumulate: true,
+            selection: self.selection_mask.as_deref(),
+            custom_mask,
+            screentone,
+            grain: self
+                .grain_texture
+                .as_ref()
+                .map(|(width, height, data)| {
+                    let texture = GrainTexture {
+                        width: *width,
+                        height: *height,
+                        data: data.as_slice(),
+                    };
+                    (brush_settings.grain, texture)
+                }),
+            mix_colors: mix_colors.as_deref(),
+            dual: brush_settings.dual.enabled.then(|| DualTip {
+                blend: brush_settings.dual.blend,
+                shape: brush_settings.dual.shape,
+                mask: self
+                    .dual_mask
+                    .as_ref()
+                    .map(|(width, height, mask)| (*width, *height, mask.as_slice())),
+                dabs: &dual_dabs,
+            }),
+            dab_colors: colors.as_deref(),
+            lock_alpha,
+        };
+        if brush_settings.watercolor() {
+            // Dabs pool in the mask; the layer shows the pool over the
+            // pre-stroke pixels, like the GPU stroke mask.
+            let mask = self
+                .wet_mask
+                .get_or_insert_with(|| TiledLayer::new(canvas_width, canvas_height));
+            let mask_params = BrushDrawParams {
+                color_argb: 0xFFFF_FFFF,
+                mix_colors: None,
+                lock_alpha: false,
+                ..params
+            };
+            if !draw_brush_points_in_rect(
+                mask,
+                canvas_width,
+                canvas_height,
+                dirty,
+                &brush_points,
+                &mask_params,
+            ) {
+                return false;
+            }
+            let coverage = mask.read_rect(dirty);
+            let row = dirty.2 as usize;
+            let out: Vec<u32> = coverage
+                .iter()
+                .enumerate()
+                .map(|(idx, texel)| {
+                    let x = (dirty.0 + (idx % row) as i32) as u32;
+                    let y = (dirty.1 + (idx / row) as i32) as u32;
+                    let base = self.undo.stroke_base_pixel(pixels, layer_idx as u32, x, y);
+                    let pooled = (texel >> 24) as f32 / 255.0;
+                    wet_composite(base, brush_settings.color_argb, pooled, lock_alpha)
+                })
+                .collect();
+            pixels.write_rect(dirty, &out);
+            self.wet_rect = union_dirty_rect_i32(self.wet_rect, dirty);
+            self.mark_dirty(dirty);
+            return true;
+        }
+        if let Some(ratio) = brush_settings.hollow_core() {
+            // Ring and core coverage pool over the whole stroke, so later
+            // dabs never paint over the core an earlier dab cut out.
+            let coverage = |scale: f32| {
+                let dabs: Vec<BrushPoint> = brush_points
+                    .iter()
+                    .map(|dab| BrushPoint {
+                        radius: dab.radius * scale,
+                        ..*dab
+                    })
+                    .collect();
+                let coverage_params = BrushDrawParams {
+                    color_argb: 0xFFFF_FFFF,
+                    mix_colors: None,
+                    dab_colors: None,
+                    lock_alpha: false,
+                    ..params
+                };
+                let mut scratch = TiledLayer::new(canvas_width, canvas_height);
+                draw_brush_points_in_rect(
+                    &mut scratch,
+                    canvas_width,
+                    canvas_height,
+                    dirty,
+                    &dabs,
+                    &coverage_params,
+                );
+                scratch.read_rect(dirty)
+            };
+            let outer = coverage(1.0);
+            let inner = coverage(ratio);
+            let mask = self.hollow_mask.get_or_insert_with(|| HollowMask {
+                outer: TiledLayer::new(canvas_width, canvas_height),
+                inner: TiledLayer::new(canvas_width, canvas_height),
+            });
+            let outer_union = pool_coverage(&mut mask.outer, dirty, &outer);
+            let hole = pool_coverage(&mut mask.inner, dirty, &inner);
+            let erase_occluded = brush_settings.hollow_erase_occluded;
+            let alpha = |texel: u32| (texel >> 24) as f32 / 255.0;
+            let row = dirty.2 as usize;
+            let mut out = pixels.read_rect(dirty);
+            for (idx, dst) in out.iter_mut().enumerate() {
+                let core = alpha(hole[idx]);
+                // Without erasing, the ring is rebuilt over the pre-stroke
+                // pixels so the core shows what was there before.
+                let (under, ring, erase) = if erase_occluded {
+                    (*dst, alpha(outer[idx]), core)
+                } else {
+                    let x = (dirty.0 + (idx % row) as i32) as u32;
+                    let y = (dirty.1 + (idx / row) as i32) as u32;
+                    let base = self.undo.stroke_base_pixel(pixels, layer_idx as u32, x, y);
+                    (base, alpha(outer_union[idx]), 0.0)
+                };
+                let paint = (ring - core).max(0.0);
+                *dst = hollow_composite(under, brush_settings.color_argb, paint, erase, lock_alpha);
+            }
+            pixels.write_rect(dirty, &out);
+            self.mark_dirty(dirty);
+            return true;
+        }
+        let drawn = draw_brush_points_in_rect(
+            pixels,
+            canvas_width,
+            canvas_height,
+            dirty,
+            &brush_points,
+            &params,
+        );
+        if drawn {
+            self.mark_dirty(dirty);
+        }
+        drawn
+    }
+
+    /// Rebuilds a pooled watercolour stroke with its wet edge, granulation
+    /// and bleed. Mirrors `BrushRenderer::finish_wet_stroke`.
+    fn finish_wet_stroke(
+        &mut self,
+        layer_idx: usize,
+        target: UndoTarget,
+        brush_settings: &EngineBrushSettings,
+    ) {
+        let (Some(mask), Some(pooled)) = (self.wet_mask.take(), self.wet_rect.take()) else {
+            return;
+        };
+        if !brush_settings.watercolor() {
+            return;
+        }
+        let canvas_width = self.canvas_width as i32;
+        let canvas_height = self.canvas_height as i32;
+        let reach = brush_settings.wet.reach() as i32;
+        let left = (pooled.0 - reach).max(0);
+        let top = (pooled.1 - reach).max(0);
+        let right = (pooled.0 + pooled.2 + reach).min(canvas_width);
+        let bottom = (pooled.1 + pooled.3 + reach).min(canvas_height);
+        if right <= left || bottom <= top {
+            return;
+        }
+        let rect = (left, top, right - left, bottom - top);
+        let Some(layer) = self.layers.get_mut(layer_idx) else {
+            return;
+        };
+        let lock_alpha = target == UndoTarget::Layer && layer.alpha_locked;
+        let pixels = layer.pixels_mut(target);
+        self.undo
+            .capture_before_for_dirty_rect(pixels, layer_idx as u32, rect);
+        let mask_at = |x: i32, y: i32| {
+            if x < 0 || y < 0 || x >= canvas_width || y >= canvas_height {
+                0.0
+            } else {
+                (mask.pixel(x as u32, y as u32) >> 24) as f32 / 255.0
+            }
+        };
+        let selection = self.selection_mask.as_deref();
+        let mut out = pixels.read_rect(rect);
+        for (idx, dst) in out.iter_mut().enumerate() {
+            let x = left + (idx as i32 % rect.2);
+            let y = top + (idx as i32 / rect.2);
+            let selected = selection.is_none_or(|sel| {
+                sel.get((y * canvas_width + x) as usize).copied().unwrap_or(0) != 0
+            });
+            if !selected {
+                continue;
+            }
+            let base = self
+                .undo
+                .stroke_base_pixel(pixels, layer_idx as u32, x as u32, y as u32);
+            let density = wet_density(&brush_settings.wet, x, y, mask_at);
+            *dst = wet_composite(base, brush_settings.color_argb, density, lock_alpha);
+        }
+        pixels.write_rect(rect, &out);
+        self.mark_dirty(rect);
+    }
+
+    fn draw_spray(&mut self, cmd: EngineCommand) {
+        let EngineCommand::DrawSpray {
+            points,
+            color_argb,
+            brush_shape,
+            erase,
+            antialias_level,
+            softness,
+            accumulate,
+        } = cmd
+        else {
+            return;
+        };
+        if points.is_empty() {
+            return;
+        }
+        let layer_idx = self
+            .spray_active_layer
+            .unwrap_or(self.active_layer_index as u32);
+        let idx = layer_idx as usize;
+        if !self.ensure_layer_index(idx) {
+            return;
+        }
+        let canvas_width = self.canvas_width;
+        let canvas_height = self.canvas_height;
+        let dirty = compute_spray_dirty_rect(
+            &points,
+            canvas_width,
+            canvas_height,
+            softness,
+            antialias_level,
+        );
+        let layer = &mut self.layers[idx];
+        self.undo.begin_stroke_if_needed(layer_idx, UndoTarget::Layer);
+        self.undo
+            .capture_before_for_dirty_rect(&layer.tiles, layer_idx, dirty);
+
+        let draw_softness = if softness.is_finite() {
+            softness.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let brush_points: Vec<BrushPoint> = points
+            .iter()
+            .map(|p| BrushPoint {
+                x: if p.x.is_finite() { p.x } else { 0.0 },
+                y: if p.y.is_finite() { p.y } else { 0.0 },
+                radius: if p.radius.is_finite() {
+                    p.radius.max(0.0)
+                } else {
+                    0.0
+                },
+                alpha: if p.alpha.is_finite() {
+                    p.alpha.clamp(0.0, 1.0)
+                } else {
+                    0.0
+                },
+                rot_sin: 0.0,
+                rot_cos: 1.0,
+            })
+            .collect();
+        let custom_mask = if self.brush_settings.custom_mask_enabled {
+            self.brush_mask
+                .as_ref()
+                .map(|(width, height, mask)| (*width, *height, mask.as_slice()))
+        } else {
+            None
+        };
+        let params = BrushDrawParams {
+            color_argb,
+            brush_shape: brush_shape_index(map_brush_shape(brush_shape)),
+            antialias_level,
+            softness: draw_softness,
+            erase,
+            accumulate,
+            selection: self.selection_mask.as_deref(),
+            custom_mask,
+            screentone: ScreentoneSettings::disabled(),
+            grain: None,
+            mix_colors: None,
+            dual: None,
+            dab_colors: None,
+            lock_alpha: layer.alpha_locked,
+        };
+        let drawn = draw_brush_points_in_rect(
+            &mut layer.tiles,
+            canvas_width,
+            canvas_height,
+            dirty,
+            &brush_points,
+            &params,
+        );
+        if drawn {
+            self.mark_dirty(dirty);
+        }
+    }
+
+    fn apply_filter(
+        &mut self,
+        layer_index: u32,
+        filter_type: u32,
+        param0: f32,
+        param1: f32,
+        param2: f32,
+        param3: f32,
+    ) -> bool {
+        let idx = layer_index as usize;
+        if !self.ensure_layer_index(idx) {
+            return false;
+        }
+        // Same early-outs as the GPU handler so both backends agree on which
+        // calls produce an undo step.
+        let mut param0 = param0;
+        match filter_type {
+            FILTER_GAUSSIAN_BLUR => {
+                if param0 <= 0.0 {
+                    return false;
+                }
+            }
+            FILTER_LINE_NARROW | FILTER_FILL_EXPAND | FILTER_LEAK_REMOVAL => {
+                let steps = param0.round().clamp(0.0, 20.0);
+                if steps == 0.0 {
+                    return false;
+                }
+                param0 = steps;
+            }
+            FILTER_HUE_SATURATION..=FILTER_INVERT => {}
+            _ => return false,
+        }
+
+        let width = self.canvas_width;
+        let height = self.canvas_height;
+        let before = self.layers[idx].tiles.to_pixels();
+        let mut rgba = argb_to_rgba_bytes(&before);
+        let len = rgba.len() as u64;
+        let mut run = |filter: u32| {
+            cpu_filters_apply_filter_rgba(
+                rgba.as_mut_ptr(),
+                len,
+                width,
+                height,
+                filter,
+                param0,
+                param1,
+                param2,
+                param3,
+            ) != 0
+        };
+        // The GPU leak removal is a morphological close: expand, then narrow.
+        let applied = if filter_type == FILTER_LEAK_REMOVAL {
+            run(FILTER_FILL_EXPAND) && run(FILTER_LINE_NARROW)
+        } else {
+            run(filter_type)
+        };
+        if !applied {
+            debug::log(
+                LogLevel::Warn,
+                format_args!("Filter apply failed: CPU filter {filter_type} rejected input"),
+            );
+            return false;
+        }
+
+        self.begin_full_layer_undo(layer_index);
+        let mut pixels = vec![0u32; pixel_count(width, height)];
+        rgba_bytes_to_argb(&rgba, &mut pixels);
+        if self.layers[idx].alpha_locked {
+            lock_alpha_pixels(&before, &mut pixels);
+        }
+        self.layers[idx].tiles.set_pixels(&pixels);
+        self.undo.end_stroke(&self.layers[idx].tiles);
+        self.mark_all_dirty();
+        true
+    }
+
+    fn apply_antialias(&mut self, layer_index: u32, level: u32) -> bool {
+        let idx = layer_index as usize;
+        if !self.ensure_layer_index(idx) {
+            return false;
+        }
+        self.begin_full_layer_undo(layer_index);
+        let layer = &mut self.layers[idx];
+        let mut pixels = layer.tiles.to_pixels();
+        let before = layer.alpha_locked.then(|| pixels.clone());
+        // A zero return only means nothing needed smoothing; the GPU path
+        // reports success in that case too.
+        let _ = cpu_filters_apply_antialias(
+            pixels.as_mut_ptr(),
+            pixels.len() as u64,
+            self.canvas_width,
+            self.canvas_height,
+            level,
+            0,
+        );
+        if let Some(before) = before {
+            lock_alpha_pixels(&before, &mut pixels);
+        }
+        layer.tiles.set_pixels(&pixels);
+        self.undo.end_stroke(&layer.tiles);
+        self.mark_all_dirty();
+        true
+    }
+
+    fn bucket_fill(&mut self, cmd: EngineCommand) {
+        let EngineCommand::BucketFill {
+            layer_index,
+            start_x,
+            start_y,
+            color_argb,
+            contiguous,
+            sample_all_layers,
+            tolerance,
+            fill_gap,
+            antialias_level,
+            swallow_colors,
+            selection_mask,
+            reply,
+        } = cmd
+        else {
+            return;
+        };
+        let idx = layer_index as usize;
+        if !self.ensure_layer_index(idx) || !self.contains_point(start_x, start_y) {
+            let _ = reply.send(false);
+            return;
+        }
+        let sample_pixels = if sample_all_layers {
+            Some(self.composite_for_bucket_fill())
+        } else {
+            None
+        };
+        let patch = bucket_fill::flood_fill_patch(
+            self.canvas_width as i32,
+            self.canvas_height as i32,
+            self.layers[idx].tiles.to_pixels(),
+            sample_pixels,
+            start_x,
+            start_y,
+            color_argb,
+            None,
+            contiguous,
+            tolerance as i32,
+            fill_gap as i32,
+            selection_mask,
+            if swallow_colors.is_empty() {
+                None
+            } else {
+                Some(swallow_colors)
+            },
+            antialias_level as i32,
+        );
+        let applied = self.write_fill_patch(layer_index, &patch);
+        let _ = reply.send(applied);
+    }
+
+    fn write_fill_patch(&mut self, layer_index: u32, patch: &bucket_fill::FloodFillPatch) -> bool {
+        if patch.width <= 0 || patch.height <= 0 {
+            return false;
+        }
+        let left = patch.left.max(0) as usize;
+        let top = patch.top.max(0) as usize;
+        let patch_width = patch.width as usize;
+        let patch_height = patch.height as usize;
+        if left + patch_width > self.canvas_width as usize
+            || top + patch_height > self.canvas_height as usize
+            || patch.pixels.len() != patch_width * patch_height
+        {
+            return false;
+        }
+
+        let rect = (patch.left, patch.top, patch.width, patch.height);
+        let layer = &mut self.layers[layer_index as usize];
+        self.undo.begin_stroke(layer_index);
+        self.undo
+            .capture_before_for_dirty_rect(&layer.tiles, layer_index, rect);
+        if layer.alpha_locked {
+            let mut pixels = patch.pixels.clone();
+            lock_alpha_pixels(&layer.tiles.read_rect(rect), &mut pixels);
+            layer.tiles.write_rect(rect, &pixels);
+        } else {
+            layer.tiles.write_rect(rect, &patch.pixels);
+        }
+        self.undo.end_stroke(&layer.tiles);
+        self.mark_dirty(rect);
+        true
+    }
+
+    fn apply_layer_transform(&mut self, layer_index: u32, matrix: [f32; 16], bilinear: bool) {
+        self.begin_full_layer_undo(layer_index);
+        let width = self.canvas_width;
+        let height = self.canvas_height;
+        let layer = &mut self.layers[layer_index as usize];
+        let transformed =
+            transform_layer_pixels(&layer.tiles.to_pixels(), width, height, &matrix, bilinear);
+        layer.tiles.set_pixels(&transformed);
+        self.undo.end_stroke(&layer.tiles);
+        self.mark_all_dirty();
+    }
+
+    fn read_layer_preview(&self, layer_index: u32, width: u32, height: u32) -> Option<Vec<u8>> {
+        let layer = self.layers.get(layer_index as usize)?;
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let scale_x = self.canvas_width as f32 / width as f32;
+        let scale_y = self.canvas_height as f32 / height as f32;
+        let mut out = Vec::with_capacity(pixel_count(width, height) * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let sx = scale_x * (x as f32 + 0.5);
+                let sy = scale_y * (y as f32 + 0.5);
+                let argb = nearest_texel(self.canvas_width, self.canvas_height, sx, sy)
+                    .map_or(0, |(ix, iy)| layer.tiles.pixel(ix, iy));
+                let (r, g, b, a) = premultiply_argb(argb);
+                out.extend_from_slice(&[r, g, b, a]);
+            }
+        }
+        Some(out)
+    }
+
+    /// Recomposites the dirty region into the present buffer. Returns `true`
+    /// when a new frame was produced.
+    fn render_present(&mut self) -> bool {
+        let Some(dirty) = self.dirty else {
+            return false;
+        };
+        if self.present.is_none() {
+            self.dirty = None;
+            return false;
+        }
+        let canvas_width = self.canvas_width;
+        let canvas_height = self.canvas_height;
+
+        let transform_idx = self.transform_layer_index as usize;
+        let transform_active =
+            (self.transform_flags & 1) != 0 && transform_idx < self.layers.len();
+        let dirty = if transform_active {
+            self.full_rect()
+        } else {
+            dirty
+        };
+        self.dirty = None;
+
+        let left = dirty.0.max(0) as u32;
+        let top = dirty.1.max(0) as u32;
+        let right = left.saturating_add(dirty.2.max(0) as u32).min(canvas_width);
+        let bottom = top.saturating_add(dirty.3.max(0) as u32).min(canvas_height);
+        if right <= left || bottom <= top {
+            return false;
+        }
+        let region_width = right - left;
+        let region_height = bottom - top;
+        let region = (left as i32, top as i32, region_width as i32, region_height as i32);
+
+        // Composite only the dirty region: each layer contributes a dense copy
+        // of just those pixels, so unpainted tiles are never expanded.
+        let mut region_layers: Vec<GpuLayerData> = self
+            .layers
+            .iter()
+            .map(|layer| layer.region_data(region))
+            .collect();
+        if transform_active {
+            let transformed = transform_layer_pixels(
+                &self.layers[transform_idx].tiles.to_pixels(),
+                canvas_width,
+                canvas_height,
+                &self.transform_matrix,
+                (self.transform_flags & 2) != 0,
+            );
+            // The preview path always recomposites the whole canvas.
+            region_layers[transform_idx].pixels = transformed;
+        }
+        let mut composite = vec![0u32; pixel_count(region_width, region_height)];
+        cpu_composite_layers_region(
+            &region_layers,
+            &self.groups.spans(region_layers.len()),
+            &self.adjustments.composite(region_layers.len()),
+            region_width,
+            region_height,
+            (0, 0, region_width, region_height),
+            &mut composite,
+        );
+
+        let mirror = (self.view_flags & VIEW_FLAG_MIRROR) != 0;
+        let black_white = (self.view_flags & VIEW_FLAG_BLACK_WHITE) != 0;
+        let Some(target) = self.present.as_mut() else {
+            return false;
+        };
+        for y in top..bottom.min(target.height) {
+            for x in left..right {
+                let out_x = if mirror { canvas_width - 1 - x } else { x };
+                if out_x >= target.width {
+                    continue;
+                }
+                let argb = composite
+                    [((y - top) as usize) * (region_width as usize) + (x - left) as usize];
+                let (mut r, mut g, mut b, a) = premultiply_argb(argb);
+                if black_white {
+                    let luma = (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32)
+                        .round()
+                        .clamp(0.0, 255.0) as u8;
+                    r = luma;
+                    g = luma;
+                    b = luma;
+                }
+                let offset = ((y as usize) * (target.width as usize) + out_x as usize) * 4;
+                target.bytes[offset..offset + 4].copy_from_slice(&[b, g, r, a]);
+            }
+        }
+        true
+    }
+}
+
+fn pixel_count(width: u32, height: u32) -> usize {
+    (width as usize).saturating_mul(height as usize)
+}
+
+/// Rasterizes dabs into `rect` of a tiled layer. The rect must cover every
+/// pixel the dabs can touch (the dirty rects from `stroke`/`engine` do).
+fn draw_brush_points_in_rect(
+    layer: &mut TiledLayer,
+    canvas_width: u32,
+    canvas_height: u32,
+    rect: (i32, i32, i32, i32),
+    points: &[BrushPoint],
+    params: &BrushDrawParams,
+) -> bool {
+    if rect.0 < 0 || rect.1 < 0 || rect.2 <= 0 || rect.3 <= 0 {
+        return false;
+    }
+    let window = PixelWindow {
+        left: rect.0 as u32,
+        top: rect.1 as u32,
+        width: rect.2 as u32,
+        height: rect.3 as u32,
+    };
+    let mut pixels = layer.read_rect(rect);
+    let mut drawn = false;
+    for (index, chunk) in points.chunks(CPU_STROKE_DAB_CHUNK).enumerate() {
+        let start = index * CPU_STROKE_DAB_CHUNK;
+        let chunk_params = BrushDrawParams {
+            mix_colors: params
+                .mix_colors
+                .and_then(|colors| colors.get(start..start + chunk.len())),
+            ..*params
+        };
+        drawn |= cpu_brush_draw_points(
+            &mut pixels,
+            canvas_width,
+            canvas_height,
+            window,
+            chunk,
+            &chunk_params,
+        );
+    }
+    if drawn {
+        layer.write_rect(rect, &pixels);
+        if params.erase {
+            layer.release_uniform_tiles(rect);
+        }
+    }
+    drawn
+}
+
+/// Merges `coverage` (in alpha) into the stroke-wide `mask` over `rect`,
+/// keeping the larger value per texel, and returns the merged texels.
+fn pool_coverage(mask: &mut TiledLayer, rect: (i32, i32, i32, i32), coverage: &[u32]) -> Vec<u32> {
+    let mut pooled = mask.read_rect(rect);
+    for (texel, &value) in pooled.iter_mut().zip(coverage) {
+        if value >> 24 > *texel >> 24 {
+            *texel = value;
+        }
+    }
+    mask.write_rect(rect, &pooled);
+    pooled
+}
+
+fn premultiply_argb(argb: u32) -> (u8, u8, u8, u8) {
+    let a = (argb >> 24) & 0xFF;
+    let premul = |c: u32| ((c * a + 127) / 255) as u8;
+    (
+        premul((argb >> 16) & 0xFF),
+        premul((argb >> 8) & 0xFF),
+        premul(argb & 0xFF),
+        a as u8,
+    )
+}
+
+fn argb_to_rgba_bytes(pixels: &[u32]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(pixels.len() * 4);
+    for &argb in pixels {
+        out.extend_from_slice(&[
+            (argb >> 16) as u8,
+            (argb >> 8) as u8,
+            argb as u8,
+            (argb >> 24) as u8,
+        ]);
+    }
+    out
+}
+
+fn rgba_bytes_to_argb(bytes: &[u8], pixels: &mut [u32]) {
+    for (dst, px) in pixels.iter_mut().zip(bytes.chunks_exact(4)) {
+        *dst = ((px[3] as u32) << 24)
+            | ((px[0] as u32) << 16)
+            | ((px[1] as u32) << 8)
+            | (px[2] as u32);
+    }
+}
+
+// The samplers below mirror `sample_nearest`/`sample_bilinear` in
+// canvas_present_rgba8.wgsl so previews and applied transforms match the GPU.
+
+fn nearest_texel(width: u32, height: u32, x: f32, y: f32) -> Option<(u32, u32)> {
+    // WGSL `round` is round-half-to-even.
+    let ix = (x - 0.5).round_ties_even();
+    let iy = (y - 0.5).round_ties_even();
+    if !(ix >= 0.0 && iy >= 0.0 && ix < width as f32 && iy < height as f32) {
+        return None;
+    }
+    Some((ix as u32, iy as u32))
+}
+
+fn sample_nearest(pixels: &[u32], width: u32, height: u32, x: f32, y: f32) -> u32 {
+    nearest_texel(width, height, x, y)
+        .map_or(0, |(ix, iy)| pixels[(iy as usize) * (width as usize) + ix as usize])
+}
+
+fn load_straight_rgba(pixels: &[u32], width: u32, height: u32, x: i64, y: i64) -> [f32; 4] {
+    if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+        return [0.0; 4];
+    }
+    let c = pixels[(y as usize) * (width as usize) + x as usize];
+    [
+        ((c >> 16) & 0xFF) as f32 / 255.0,
+        ((c >> 8) & 0xFF) as f32 / 255.0,
+        (c & 0xFF) as f32 / 255.0,
+        ((c >> 24) & 0xFF) as f32 / 255.0,
+    ]
+}
+
+fn sample_bilinear(pixels: &[u32], width: u32, height: u32, x: f32, y: f32) -> u32 {
+    let sx = x - 0.5;
+    let sy = y - 0.5;
+    if !sx.is_finite() || !sy.is_finite() {
+        return 0;
+    }
+    let x0 = sx.floor();
+    let y0 = sy.floor();
+    let fx = sx - x0;
+    let fy = sy - y0;
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let c00 = load_straight_rgba(pixels, width, height, x0, y0);
+    let c10 = load_straight_rgba(pixels, width, height, x0 + 1, y0);
+    let c01 = load_straight_rgba(pixels, width, height, x0, y0 + 1);
+    let c11 = load_straight_rgba(pixels, width, height, x0 + 1, y0 + 1);
+    let mut out = [0u32; 4];
+    for channel in 0..4 {
+        let top = c00[channel] + (c10[channel] - c00[channel]) * fx;
+        let bottom = c01[channel] + (c11[channel] - c01[channel]) * fx;
+        let value = top + (bottom - top) * fy;
+        out[channel] = (value.clamp(0.0, 1.0) * 255.0).round_ties_even() as u32;
+    }
+    (out[3] << 24) | (out[0] << 16) | (out[1] << 8) | out[2]
+}
+
+fn transform_layer_pixels(
+    pixels: &[u32],
+    width: u32,
+    height: u32,
+    matrix: &[f32; 16],
+    bilinear: bool,
+) -> Vec<u32> {
+    let mut out = Vec::with_capacity(pixel_count(width, height));
+    for y in 0..height {
+        let py = y as f32 + 0.5;
+        for x in 0..width {
+            let px = x as f32 + 0.5;
+            // Column-major, matching `matrix * vec4(pos, 0, 1)` in WGSL.
+            let sx = matrix[0] * px + matrix[4] * py + matrix[12];
+            let sy = matrix[1] * px + matrix[5] * py + matrix[13];
+            out.push(if bilinear {
+                sample_bilinear(pixels, width, height, sx, sy)
+            } else {
+                sample_nearest(pixels, width, height, sx, sy)
+            });
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::engine_events::{EngineEvent, EngineEventKind};
+    use crate::canvas_engine::engine::tests::{brush, point, read_layer};
+    use crate::canvas_engine::engine::{lookup_engine, remove_engine};
+    use crate::canvas_engine::ffi::load_rin_document;
+    use crate::canvas_engine::rin::{RinDocument, RinLayer};
+
+    #[test]
+    fn stroke_paints_and_undoes_without_gpu() {
+        let handle = create_cpu_engine(64, 64).unwrap();
+        let entry = lookup_engine(handle).unwrap();
+        entry
+            .cmd_tx
+            .send(EngineCommand::ResetCanvas {
+                background_color_argb: 0xFFFFFFFF,
+            })
+            .unwrap();
+        entry
+            .cmd_tx
+            .send(EngineCommand::SetBrush {
+                color_argb: 0xFF000000,
+                base_radius: 4.0,
+                use_pressure: false,
+                erase: false,
+                antialias_level: 1,
+                brush_shape: 0,
+                random_rotation: false,
+                smooth_rotation: false,
+                rotation_seed: 0,
+                spacing: 0.15,
+                hardness: 0.8,
+                flow: 1.0,
+                scatter: 0.0,
+                rotation_jitter: 0.0,
+                snap_to_pixel: false,
+                screentone_enabled: false,
+                screentone_spacing: 10.0,
+                screentone_dot_size: 0.6,
+                screentone_rotation: 45.0,
+                screentone_softness: 0.0,
+                screentone_shape: 0,
+                hollow_enabled: false,
+                hollow_ratio: 0.0,
+                hollow_erase_occluded: false,
+                streamline_strength: 0.0,
+                smoothing_mode: 0,
+                stabilizer_strength: 0.0,
+            })
+            .unwrap();
+
+        let points = vec![
+            point(8.0, 32.0, 1),
+            point(32.0, 32.0, 2),
+            point(56.0, 32.0, 4),
+        ];
+        entry.input_queue_len.fetch_add(points.len() as u64, Ordering::Relaxed);
+        entry.input_tx.send(EngineInputBatch { points }).unwrap();
+
+        let painted = read_layer(&entry, 0);
+        let center = painted[32 * 64 + 32];
+        assert_ne!(center, 0xFFFFFFFF, "stroke should darken the layer");
+        assert_eq!(painted[2 * 64 + 2], 0xFFFFFFFF);
+
+        entry.cmd_tx.send(EngineCommand::Undo).unwrap();
+        let restored = read_layer(&entry, 0);
+        assert!(restored.iter().all(|&px| px == 0xFFFFFFFF));
+
+        let entry = remove_engine(handle).unwrap();
+        let _ = entry.cmd_tx.send(EngineCommand::Stop);
+    }
+
+    #[test]
+    fn mask_paints_applies_and_undoes() {
+        let mut state = CpuEngineState::new(32, 16);
+        let read_mask = |state: &mut CpuEngineState| {
+            let (reply, rx) = mpsc::channel();
+            state.handle_command(EngineCommand::ReadLayerMask {
+                layer_index: 0,
+                reply,
+            });
+            rx.recv().unwrap()
+        };
+        let mask_enabled = |state: &CpuEngineState| state.layers[0].mask_state.enabled;
+
+        state.handle_command(EngineCommand::CreateLayerMask { layer_index: 0 });
+        assert_eq!(read_mask(&mut state), Some(vec![0xFF; 32 * 16]));
+
+        state.handle_command(EngineCommand::SetLayerMaskEditing { editing: true });
+        state.handle_command(brush(0xFF000000, 3.0));
+        state.consume_input(vec![point(4.0, 8.0, 1), point(8.0, 8.0, 2), point(12.0, 8.0, 4)]);
+        let painted = read_mask(&mut state).unwrap();
+        assert_eq!(painted[8 * 32 + 8], 0, "black should conceal");
+        assert_eq!(painted[8 * 32 + 28], 0xFF);
+        assert!(state.layers[0].tiles.to_pixels().iter().all(|&px| px == 0xFFFFFFFF));
+        state.handle_command(EngineCommand::SetLayerMaskEditing { editing: false });
+
+        state.handle_command(EngineCommand::SetLayerMaskEnabled {
+            layer_index: 0,
+            enabled: false,
+        });
+        assert!(!mask_enabled(&state));
+        state.handle_command(EngineCommand::Undo);
+        assert!(mask_enabled(&state), "undo should turn the mask back on");
+        assert_eq!(read_mask(&mut state).as_ref(), Some(&painted));
+
+        state.handle_command(EngineCommand::ApplyLayerMask { layer_index: 0 });
+        let applied = state.layers[0].tiles.to_pixels();
+        assert_eq!(applied[8 * 32 + 8] >> 24, 0);
+        assert_eq!(applied[8 * 32 + 28], 0xFFFFFFFF);
+        assert_eq!(read_mask(&mut state), None);
+
+        state.handle_command(EngineCommand::Undo);
+        assert!(state.layers[0].tiles.to_pixels().iter().all(|&px| px == 0xFFFFFFFF));
+        assert_eq!(read_mask(&mut state), Some(painted));
+
+        state.handle_command(EngineCommand::Undo);
+        assert_eq!(read_mask(&mut state), Some(vec![0xFF; 32 * 16]));
+    }
+
+    #[test]
+    fn alpha_lock_keeps_alpha_and_recolours() {
+        let handle = create_cpu_engine(32, 16).unwrap();
+        let entry = lookup_engine(handle).unwrap();
+        let send = |cmd: EngineCommand| entry.cmd_tx.send(cmd).unwrap();
+        let (reply, _written) = mpsc::channel();
+        send(EngineCommand::WriteLayer {
+            layer_index: 0,
+            pixels: vec![0x80FF0000; 32 * 16],
+            record_undo: false,
+            reply,
+        });
+        send(EngineCommand::SetLayerAlphaLock {
+            layer_index: 0,
+            locked: true,
+        });
+        send(brush(0xFF0000FF, 4.0));
+        let points = vec![point(4.0, 8.0, 1), point(16.0, 8.0, 2), point(28.0, 8.0, 4)];
+        entry.input_queue_len.fetch_add(points.len() as u64, Ordering::Relaxed);
+        entry.input_tx.send(EngineInputBatch { points }).unwrap();
+
+        let pixels = read_layer(&entry, 0);
+        assert_eq!(pixels[8 * 32 + 16], 0x800000FF);
+        assert_eq!(pixels[0], 0x80FF0000);
+
+        let entry = remove_engine(handle).unwrap();
+        let _ = entry.cmd_tx.send(EngineCommand::Stop);
+    }
+
+    #[test]
+    fn events_follow_a_stroke_and_an_undo() {
+        let handle = create_cpu_engine(32, 16).unwrap();
+        let entry = lookup_engine(handle).unwrap();
+        let events = entry.events.subscribe_channel();
+        let wait_for = |matches: &dyn Fn(&EngineEvent) -> bool| loop {
+            let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+            if matches(&event) {
+                return event;
+            }
+        };
+        let send = |cmd: EngineCommand| entry.cmd_tx.send(cmd).unwrap();
+
+        let initial = wait_for(&|event| event.kind == EngineEventKind::UndoState);
+        assert!(!initial.can_undo && !initial.can_redo);
+
+        send(brush(0xFF000000, 3.0));
+        let points = vec![point(4.0, 8.0, 1), point(16.0, 8.0, 2), point(28.0, 8.0, 4)];
+        entry.input_queue_len.fetch_add(points.len() as u64, Ordering::Relaxed);
+        entry.input_tx.send(EngineInputBatch { points }).unwrap();
+        wait_for(&|event| event.kind == EngineEventKind::UndoState && event.can_undo);
+        let dirty = wait_for(&|event| event.kind == EngineEventKind::LayerDirty);
+        assert_eq!(dirty.layer_index, 0);
+        assert!(!dirty.on_mask && dirty.width > 0 && dirty.height > 0);
+        let bounds = wait_for(&|event| event.kind == EngineEventKind::LayerBounds);
+        assert_eq!(bounds.layer_index, 0);
+
+        send(EngineCommand::SetLayerOpacity {
+            layer_index: 0,
+            opacity: 0.5,
+        });
+        send(EngineCommand::Undo);
+        wait_for(&|event| event.kind == EngineEventKind::UndoState && event.can_redo);
+        let changed = wait_for(&|event| event.kind == EngineEventKind::LayersChanged);
+        assert_eq!((changed.width, changed.height), (32, 16));
+
+        send(EngineCommand::SetPressureCurve {
+            target: 99,
+            points: vec![(0.0, 1.0)],
+        });
+        let logged = wait_for(&|event| {
+            matches!(event.kind, EngineEventKind::Warning | EngineEventKind::Error)
+        });
+        assert_eq!(logged.kind, EngineEventKind::Warning);
+        assert!(logged.message.contains("unknown pressure curve target"));
+
+        let entry = remove_engine(handle).unwrap();
+        let _ = entry.cmd_tx.send(EngineCommand::Stop);
+    }
+
+    #[test]
+    fn grain_is_anchored_to_the_canvas() {
+        // One dab per entry of `centers`; `depth` of `None` leaves grain off.
+        let dabs = |depth: Option<f32>, centers: &[f32]| {
+            let mut state = CpuEngineState::new(32, 16);
+            state.handle_command(EngineCommand::SetGrainTexture {
+                width: 4,
+                height: 4,
+                pixels: (0..16).map(|i| if i % 2 == 0 { 0 } else { 0xFF }).collect(),
+            });
+            if let Some(depth) = depth {
+                state.handle_command(EngineCommand::SetGrain {
+                    enabled: true,
+                    scale: 1.0,
+                    offset_x: 0.0,
+                    offset_y: 0.0,
+                    depth,
+                    invert: false,
+                    blend: 0,
+                });
+            }
+            state.handle_command(brush(0xFF000000, 4.0));
+            for &x in centers {
+                state.consume_input(vec![point(x, 8.0, 1), point(x, 8.0, 4)]);
+            }
+            state.layers[0].tiles.to_pixels()
+        };
+        // The left half, which a dab at x = 24 does not reach.
+        let left = |pixels: &[u32]| -> Vec<u32> {
+            pixels.chunks(32).flat_map(|row| row[..16].to_vec()).collect()
+        };
+
+        let grained = dabs(Some(1.0), &[8.0]);
+        let plain = dabs(None, &[8.0]);
+        assert_eq!(plain[8 * 32 + 8], 0xFF000000);
+        assert_ne!(grained, plain, "grain should show through the dab");
+        assert_eq!(left(&dabs(Some(1.0), &[24.0, 8.0])), left(&grained));
+        assert_eq!(dabs(Some(0.0), &[8.0]), plain);
+    }
+
+    #[test]
+    fn loaded_layers_survive_present_attach() {
+        let handle = create_cpu_engine(16, 16).unwrap();
+        let mut document = RinDocument::new(16, 16, 0);
+        for (id, fill) in [("paper", 0xFF336699), ("ink", 0xFFFF0000)] {
+            let mut layer = RinLayer::new(id.to_string(), id.to_string());
+            layer.fill_argb = Some(fill);
+            document.layers.push(layer);
+        }
+        load_rin_document(handle, &document).unwrap();
+
+        let entry = lookup_engine(handle).unwrap();
+        entry
+            .cmd_tx
+            .send(EngineCommand::AttachPresentTexture {
+                mtl_texture_ptr: 0,
+                width: 16,
+                height: 16,
+                bytes_per_row: 64,
+            })
+            .unwrap();
+        assert_eq!(read_layer(&entry, 0), vec![0xFF336699; 256]);
+        assert_eq!(read_layer(&entry, 1), vec![0xFFFF0000; 256]);
+
+        let entry = remove_engine(handle).unwrap();
+        let _ = entry.cmd_tx.send(EngineCommand::Stop);
+    }
+}
@@ -0,0 +1,651 @@
+use std::collections::HashMap;
+
+use super::cpu_engine::{CpuLayer, CpuLayerEdit};
+use super::engine::remap_layer_index;
+use super::history::{HistoryMove, HistoryThumbnail, UndoHistory};
+use crate::api::engine_history::{EngineHistoryKind, EngineHistoryNode};
+use crate::gpu::debug::{self, LogLevel};
+
+use super::masks::LayerMaskState;
+use super::tile_codec::{decode_texels, encode_texels};
+use super::tiles::TiledLayer;
+use super::undo::{
+    extends_property_step, LayerEdit, LayerProperties, UndoApplied, UndoDirtyRect, UndoTarget,
+    UNDO_BYTE_BUDGET, UNDO_STACK_LIMIT,
+};
+
+const UNDO_TILE_SIZE: u32 = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct UndoTileKey {
+    tx: u32,
+    ty: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct UndoTileRect {
+    left: u32,
+    top: u32,
+    width: u32,
+    height: u32,
+}
+
+impl UndoTileRect {
+    fn as_i32(self) -> (i32, i32, i32, i32) {
+        (
+            self.left as i32,
+            self.top as i32,
+            self.width as i32,
+            self.height as i32,
+        )
+    }
+}
+
+/// One side of a tile patch.
+enum UndoTileData {
+    Raw(Vec<u32>),
+    /// Compressed with `tile_codec` once the patch fell out of the budget.
+    Encoded(Vec<u8>),
+}
+
+impl UndoTileData {
+    fn raw_bytes(&self) -> u64 {
+        match self {
+            UndoTileData::Raw(texels) => 4 * texels.len() as u64,
+            UndoTileData::Encoded(_) => 0,
+        }
+    }
+
+    fn compress(&mut self) {
+        if let UndoTileData::Raw(texels) = self {
+            *self = UndoTileData::Encoded(encode_texels(texels));
+        }
+    }
+}
+
+struct UndoTilePatch {
+    rect: UndoTileRect,
+    before: UndoTileData,
+    after: UndoTileData,
+}
+
+impl UndoTilePatch {
+    /// Writes the before (or, for `redo`, after) pixels into `layer`.
+    fn apply(&self, layer: &mut TiledLayer, redo: bool) {
+        let rect = self.rect.as_i32();
+        match if redo { &self.after } else { &self.before } {
+            UndoTileData::Raw(texels) => layer.write_rect(rect, texels),
+            UndoTileData::Encoded(encoded) => {
+                let texel_count = self.rect.width as usize * self.rect.height as usize;
+                match decode_texels(encoded, texel_count) {
+                    Ok(texels) => layer.write_rect(rect, &texels),
+                    Err(err) => {
+                        debug::log(
+                            LogLevel::Warn,
+                            format_args!(
+                                "undo tile at ({}, {}) skipped: {err}",
+                                self.rect.left, self.rect.top
+                            ),
+                        );
+                        return;
+                    }
+                }
+            }
+        }
+        layer.release_uniform_tiles(rect);
+    }
+}
+
+struct UndoRecord {
+    layer_index: u32,
+    target: UndoTarget,
+    tiles: Vec<UndoTilePatch>,
+    mask_change: Option<(LayerMaskState, LayerMaskState)>,
+    thumbnail: Option<HistoryThumbnail>,
+}
+
+struct ActiveStrokeUndo {
+    layer_index: u32,
+    target: UndoTarget,
+    tiles: HashMap<UndoTileKey, (UndoTileRect, Vec<u32>)>,
+    mask_change: Option<(LayerMaskState, LayerMaskState)>,
+}
+
+enum UndoStep {
+    Pixels(Vec<UndoRecord>),
+    Edit(CpuLayerEdit),
+}
+
+/// Tile-based undo history for the CPU canvas engine. Mirrors `UndoManager`,
+/// but snapshots live in host memory instead of GPU textures.
+pub(crate) struct CpuUndoManager {
+    canvas_width: u32,
+    canvas_height: u32,
+    tile_size: u32,
+    max_steps: usize,
+    byte_budget: u64,
+    history: UndoHistory<UndoStep>,
+    current: Option<ActiveStrokeUndo>,
+    transaction: Option<Vec<UndoRecord>>,
+    dirty: Vec<UndoDirtyRect>,
+    layers_changed: bool,
+}
+
+impl UndoRecord {
+    fn raw_bytes(&self) -> u64 {
+        self.tiles
+            .iter()
+            .map(|tile| tile.before.raw_bytes() + tile.after.raw_bytes())
+            .sum()
+    }
+
+    fn compress(&mut self) {
+        for tile in &mut self.tiles {
+            tile.before.compress();
+            tile.after.compress();
+        }
+    }
+
+    fn apply(&self, layers: &mut [CpuLayer], redo: bool) {
+        let Some(layer) = layers.get_mut(self.layer_index as usize) else {
+            return;
+        };
+        let pixels = layer.pixels_mut(self.target);
+        for tile in &self.tiles {
+            tile.apply(pixels, redo);
+        }
+        if let Some((before, after)) = self.mask_change {
+            layer.mask_state = if redo { after } else { before };
+        }
+    }
+
+    fn thumbnail(&mut self) -> Result<HistoryThumbnail, String> {
+        if let Some(thumbnail) = &self.thumbnail {
+            return Ok(thumbnail.clone());
+        }
+        let mut tiles = Vec::with_capacity(self.tiles.len());
+        for tile in &self.tiles {
+            let texels = match &tile.after {
+                UndoTileData::Raw(texels) => texels.clone(),
+                UndoTileData::Encoded(encoded) => decode_texels(
+                    encoded,
+                    tile.rect.width as usize * tile.rect.height as usize,
+                )?,
+            };
+            let rect = tile.rect;
+            tiles.push(((rect.left, rect.top, rect.width, rect.height), texels));
+        }
+        let rect = self.dirty_rect().map_or((0, 0, 0, 0), |dirty| dirty.rect);
+        let thumbnail = HistoryThumbnail::from_tiles(rect, &tiles);
+        self.thumbnail = Some(thumbnail.clone());
+        Ok(thumbnail)
+    }
+
+    fn dirty_rect(&self) -> Option<UndoDirtyRect> {
+        UndoDirtyRect::covering(
+            self.layer_index,
+            self.target,
+            self.tiles
+                .iter()
+                .map(|tile| (tile.rect.left, tile.rect.top, tile.rect.width, tile.rect.height)),
+        )
+    }
+}
+
+impl CpuUndoManager {
+    pub(crate) fn new(canvas_width: u32, canvas_height: u32) -> Self {
+        Self {
+            canvas_width,
+            canvas_height,
+            tile_size: UNDO_TILE_SIZE,
+            max_steps: UNDO_STACK_LIMIT,
+            byte_budget: UNDO_BYTE_BUDGET,
+            history: UndoHistory::new(),
+            current: None,
+            transaction: None,
+            dirty: Vec::new(),
+            layers_changed: false,
+        }
+    }
+
+    pub(crate) fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    pub(crate) fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    pub(crate) fn take_dirty(&mut self) -> Vec<UndoDirtyRect> {
+        std::mem::take(&mut self.dirty)
+    }
+
+    pub(crate) fn take_layers_changed(&mut self) -> bool {
+        std::mem::take(&mut self.layers_changed)
+    }
+
+    pub(crate) fn set_canvas_size(&mut self, canvas_width: u32, canvas_height: u32) {
+        self.canvas_width = canvas_width;
+        self.canvas_height = canvas_height;
+        self.current = None;
+    }
+
+    pub(crate) fn begin_stroke(&mut self, layer_index: u32) {
+        self.begin_target_stroke(layer_index, UndoTarget::Layer);
+    }
+
+    pub(crate) fn begin_target_stroke(&mut self, layer_index: u32, target: UndoTarget) {
+        self.current = Some(ActiveStrokeUndo {
+            layer_index,
+            target,
+            tiles: HashMap::new(),
+            mask_change: None,
+        });
+    }
+
+    pub(crate) fn begin_stroke_if_needed(&mut self, layer_index: u32, target: UndoTarget) {
+        if self.current.is_none() {
+            self.begin_target_stroke(layer_index, target);
+        }
+    }
+
+    pub(crate) fn record_mask_change(
+        &mut self,
+        layer_index: u32,
+        before: LayerMaskState,
+        after: LayerMaskState,
+    ) {
+        let Some(active) = self.current.as_mut() else {
+            return;
+        };
+        if active.layer_index != layer_index || before == after {
+            return;
+        }
+        let before = active.mask_change.map_or(before, |(first, _)| first);
+        active.mask_change = Some((before, after));
+    }
+
+    pub(crate) fn cancel_stroke(&mut self) {
+        self.current = None;
+    }
+
+    /// Keeps at most `max_steps` steps, branches included, and at most
+    /// `byte_budget` bytes of uncompressed tiles. Older patches are
+    /// compressed; removed layers and replaced canvases can't be, so the
+    /// oldest steps go until they fit.
+    pub(crate) fn set_limits(&mut self, max_steps: usize, byte_budget: u64) {
+        self.max_steps = max_steps.max(1);
+        self.byte_budget = byte_budget;
+        self.history.trim(self.max_steps);
+        self.enforce_budget();
+    }
+
+    fn enforce_budget(&mut self) {
+        let raw_bytes = |step: &UndoStep| match step {
+            UndoStep::Pixels(records) => records.iter().map(UndoRecord::raw_bytes).sum(),
+            UndoStep::Edit(LayerEdit::Insert { layer, .. }) => layer.raw_bytes(),
+            UndoStep::Edit(LayerEdit::Canvas(canvas)) => canvas.raw_bytes(),
+            UndoStep::Edit(_) => 0,
+        };
+        let mut raw: u64 = self
+            .history
+            .steps_mut()
+            .map(|step| raw_bytes(step))
+            .sum();
+        for step in self.history.steps_mut() {
+            if raw <= self.byte_budget {
+                return;
+            }
+            if let UndoStep::Pixels(records) = step {
+                for record in records {
+                    raw -= record.raw_bytes();
+                    record.compress();
+                }
+            }
+        }
+        while raw > self.byte_budget {
+            if self.history.evict_oldest().is_none() {
+                break;
+            }
+            raw = self
+                .history
+                .steps_mut()
+                .map(|step| raw_bytes(step))
+                .sum();
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.history.clear();
+        self.current = None;
+        self.transaction = None;
+    }
+
+    pub(crate) fn begin_transaction(&mut self) {
+        self.transaction.get_or_insert_with(Vec::new);
+    }
+
+    pub(crate) fn commit_transaction(&mut self) {
+        self.close_transaction();
+        self.enforce_budget();
+    }
+
+    fn close_transaction(&mut self) {
+        if let Some(records) = self.transaction.take() {
+            if !records.is_empty() {
+                self.push_step(UndoStep::Pixels(records));
+            }
+        }
+    }
+
+    pub(crate) fn cancel_transaction(&mut self, layers: &mut [CpuLayer]) -> bool {
+        let Some(records) = self.transaction.take() else {
+            return false;
+        };
+        let mut restored = false;
+        if let Some(active) = self.current.take() {
+            if let Some(layer) = layers.get_mut(active.layer_index as usize) {
+                if let Some((before, _)) = active.mask_change {
+                    layer.mask_state = before;
+                    restored = true;
+                }
+                let pixels = layer.pixels_mut(active.target);
+                for (rect, before) in active.tiles.values() {
+                    pixels.write_rect(rect.as_i32(), before);
+                    pixels.release_uniform_tiles(rect.as_i32());
+                }
+                self.dirty.extend(UndoDirtyRect::covering(
+                    active.layer_index,
+                    active.target,
+                    active.tiles.values().map(|(rect, _)| {
+                        (rect.left, rect.top, rect.width, rect.height)
+                    }),
+                ));
+                restored |= !active.tiles.is_empty();
+            }
+        }
+        for record in records.iter().rev() {
+            record.apply(layers, false);
+        }
+        self.dirty.extend(records.iter().filter_map(UndoRecord::dirty_rect));
+        restored || !records.is_empty()
+    }
+
+    pub(crate) fn reorder_layers(&mut self, from: u32, to: u32) {
+        if from == to {
+            return;
+        }
+        if let Some(active) = self.current.as_mut() {
+            active.layer_index =
+                remap_layer_index(active.layer_index as usize, from as usize, to as usize) as u32;
+        }
+    }
+
+    pub(crate) fn record_layer_properties(
+        &mut self,
+        layer_index: u32,
+        before: LayerProperties,
+        after: LayerProperties,
+    ) {
+        if before == after {
+            return;
+        }
+        self.close_transaction();
+        let top = match self.history.last_undo() {
+            Some(UndoStep::Edit(edit)) => Some(edit),
+            _ => None,
+        };
+        // With redo steps pending the change starts a branch instead.
+        if !self.history.can_redo() && extends_property_step(top, layer_index, before, after) {
+            return;
+        }
+        self.record_edit(LayerEdit::Properties {
+            layer_index,
+            properties: before,
+        });
+    }
+
+    pub(crate) fn record_edit(&mut self, edit: CpuLayerEdit) {
+        self.close_transaction();
+        self.push_step(UndoStep::Edit(edit));
+        self.enforce_budget();
+    }
+
+    /// Names the current state of the history; an empty name removes it.
+    pub(crate) fn set_checkpoint(&mut self, name: String) {
+        self.close_transaction();
+        self.history.set_checkpoint(name);
+    }
+
+    /// Undo, redo and branch moves from the current state to history state
+    /// `id`, or `None` when it is not kept.
+    pub(crate) fn history_route(&mut self, id: u64) -> Option<Vec<HistoryMove>> {
+        self.close_transaction();
+        self.history.route_to(id)
+    }
+
+    /// Makes redo follow the branch starting with history state `id`.
+    pub(crate) fn enter_history_branch(&mut self, id: u64) -> bool {
+        self.cancel_stroke();
+        self.close_transaction();
+        self.history.enter(id)
+    }
+
+    pub(crate) fn history_nodes(&mut self) -> Vec<EngineHistoryNode> {
+        let nodes = self.history.nodes();
+        nodes
+            .into_iter()
+            .map(|node| {
+                let (kind, thumbnail) = match self.history.step_mut(node.id) {
+                    None => (EngineHistoryKind::Start, HistoryThumbnail::default()),
+                    Some(UndoStep::Edit(_)) => (EngineHistoryKind::Layers, HistoryThumbnail::default()),
+                    Some(UndoStep::Pixels(records)) => {
+                        let thumbnail = records
+                            .iter_mut()
+                            .find(|record| record.target == UndoTarget::Layer && !record.tiles.is_empty())
+                            .map_or(Ok(HistoryThumbnail::default()), UndoRecord::thumbnail)
+                            .unwrap_or_else(|err| {
+                                debug::log(LogLevel::Warn, format_args!("history thumbnail failed: {err}"));
+                                HistoryThumbnail::default()
+                            });
+                        (EngineHistoryKind::Pixels, thumbnail)
+                    }
+                };
+                node.into_api(kind, thumbnail)
+            })
+            .collect()
+    }
+
+    pub(crate) fn finish_undo(&mut self, edit: CpuLayerEdit) {
+        self.history.push_redo(UndoStep::Edit(edit));
+        self.layers_changed = true;
+        self.enforce_budget();
+    }
+
+    pub(crate) fn finish_redo(&mut self, edit: CpuLayerEdit) {
+        self.history.push_undo(UndoStep::Edit(edit));
+        self.layers_changed = true;
+        self.enforce_budget();
+    }
+
+    pub(crate) fn capture_before_for_dirty_rect(
+        &mut self,
+        layer: &TiledLayer,
+        layer_index: u32,
+        dirty: (i32, i32, i32, i32),
+    ) {
+        let canvas_width = self.canvas_width;
+        let canvas_height = self.canvas_height;
+        let tile_size = self.tile_size.max(1);
+
+        let Some(active) = self.current.as_mut() else {
+            return;
+        };
+        if active.layer_index != layer_index {
+            return;
+        }
+        let (left_i, top_i, width_i, height_i) = dirty;
+        if width_i <= 0 || height_i <= 0 {
+            return;
+        }
+
+        let left = (left_i.max(0) as u32).min(canvas_width);
+        let top = (top_i.max(0) as u32).min(canvas_height);
+        let right = (left_i.saturating_add(width_i).max(0) as u32).min(canvas_width);
+        let bottom = (top_i.saturating_add(height_i).max(0) as u32).min(canvas_height);
+        if right <= left || bottom <= top {
+            return;
+        }
+
+        let tx0 = left / tile_size;
+        let ty0 = top / tile_size;
+        let tx1 = right.saturating_sub(1) / tile_size;
+        let ty1 = bottom.saturating_sub(1) / tile_size;
+
+        for ty in ty0..=ty1 {
+            for tx in tx0..=tx1 {
+                let key = UndoTileKey { tx, ty };
+                if active.tiles.contains_key(&key) {
+                    continue;
+                }
+                let tile_left = tx.saturating_mul(tile_size);
+                let tile_top = ty.saturating_mul(tile_size);
+                if tile_left >= canvas_width || tile_top >= canvas_height {
+                    continue;
+                }
+                let rect = UndoTileRect {
+                    left: tile_left,
+                    top: tile_top,
+                    width: tile_size.min(canvas_width - tile_left),
+                    height: tile_size.min(canvas_height - tile_top),
+                };
+                let before = layer.read_rect(rect.as_i32());
+                active.tiles.insert(key, (rect, before));
+            }
+        }
+    }
+
+    pub(crate) fn end_stroke(&mut self, layer: &TiledLayer) {
+        let Some(active) = self.current.take() else {
+            return;
+        };
+        if active.tiles.is_empty() && active.mask_change.is_none() {
+            return;
+        }
+
+        let mut patches: Vec<UndoTilePatch> = Vec::with_capacity(active.tiles.len());
+        for (_, (rect, before)) in active.tiles {
+            let after = layer.read_rect(rect.as_i32());
+            patches.push(UndoTilePatch {
+                rect,
+                before: UndoTileData::Raw(before),
+                after: UndoTileData::Raw(after),
+            });
+        }
+
+        let record = UndoRecord {
+            layer_index: active.layer_index,
+            target: active.target,
+            tiles: patches,
+            mask_change: active.mask_change,
+            thumbnail: None,
+        };
+        self.dirty.extend(record.dirty_rect());
+        match self.transaction.as_mut() {
+            Some(records) => records.push(record),
+            None => {
+                self.push_step(UndoStep::Pixels(vec![record]));
+                self.enforce_budget();
+            }
+        }
+    }
+
+    fn push_step(&mut self, step: UndoStep) {
+        self.history.push(step, self.max_steps);
+    }
+
+    pub(crate) fn restore_current_before(&self, layer: &mut TiledLayer) -> bool {
+        let Some(active) = self.current.as_ref() else {
+            return false;
+        };
+        if active.tiles.is_empty() {
+            return false;
+        }
+        for (rect, before) in active.tiles.values() {
+            layer.write_rect(rect.as_i32(), before);
+            layer.release_uniform_tiles(rect.as_i32());
+        }
+        true
+    }
+
+    /// Pixel `(x, y)` of `layer` as it was when the current stroke began.
+    /// Tiles the stroke has not captured yet are still untouched.
+    pub(crate) fn stroke_base_pixel(
+        &self,
+        layer: &TiledLayer,
+        layer_index: u32,
+        x: u32,
+        y: u32,
+    ) -> u32 {
+        let tile_size = self.tile_size.max(1);
+        let captured = self
+            .current
+            .as_ref()
+            .filter(|active| active.layer_index == layer_index)
+            .and_then(|active| {
+                active.tiles.get(&UndoTileKey {
+                    tx: x / tile_size,
+                    ty: y / tile_size,
+                })
+            });
+        match captured {
+            Some((rect, before)) => {
+                let idx = ((y - rect.top) * rect.width + (x - rect.left)) as usize;
+                before.get(idx).copied().unwrap_or(0)
+            }
+            None => layer.pixel(x, y),
+        }
+    }
+
+    pub(crate) fn undo(&mut self, layers: &mut [CpuLayer]) -> UndoApplied<CpuLayerEdit> {
+        self.cancel_stroke();
+        self.close_transaction();
+        let records = match self.history.pop_undo() {
+            None => return UndoApplied::Nothing,
+            Some(UndoStep::Edit(edit)) => return UndoApplied::Edit(edit),
+            Some(UndoStep::Pixels(records)) => records,
+        };
+        if !records_fit(&records, layers.len()) {
+            return UndoApplied::Nothing;
+        }
+        for record in records.iter().rev() {
+            record.apply(layers, false);
+        }
+        self.dirty.extend(records.iter().filter_map(UndoRecord::dirty_rect));
+        self.history.push_redo(UndoStep::Pixels(records));
+        UndoApplied::Pixels
+    }
+
+    pub(crate) fn redo(&mut self, layers: &mut [CpuLayer]) -> UndoApplied<CpuLayerEdit> {
+        self.cancel_stroke();
+        self.close_transaction();
+        let records = match self.history.pop_redo() {
+            None => return UndoApplied::Nothing,
+            Some(UndoStep::Edit(edit)) => return UndoApplied::Edit(edit),
+            Some(UndoStep::Pixels(records)) => records,
+        };
+        if !records_fit(&records, layers.len()) {
+            return UndoApplied::Nothing;
+        }
+        for record in &records {
+            record.apply(layers, true);
+        }
+        self.dirty.extend(records.iter().filter_map(UndoRecord::dirty_rect));
+        self.history.push_undo(UndoStep::Pixels(records));
+        UndoApplied::Pixels
+    }
+}
+
+fn records_fit(records: &[UndoRecord], layer_count: usize) -> bool {
+    records.iter().all(|record| (record.layer_index as usize) < layer_count)
+}
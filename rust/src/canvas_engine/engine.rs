@@ -11,38 +11,64 @@ use metal::foreign_types::ForeignType;
 use wgpu_hal::api::Metal;
 
 use crate::api::bucket_fill;
+use crate::api::engine_history::EngineHistoryNode;
+use crate::api::gpu_composite::{apply_mask_value, GpuLayerData};
+use crate::cpu_brush::{
+    lock_alpha_pixels, lock_alpha_texel, DualBlend, DualTipSettings, GrainBlend, GrainSettings,
+    MixMode, MixSettings, WetSettings,
+};
 use crate::gpu::blend_modes::map_canvas_blend_mode_index;
 use crate::gpu::brush_renderer::{BrushRenderer, BrushShape, Color, Point2D, MAX_POINTS};
-use crate::gpu::bucket_fill_renderer::BucketFillRenderer;
+use crate::gpu::bucket_fill_renderer::{BucketFillRenderer, SampledLayers};
 use crate::gpu::debug::{self, LogLevel};
 use crate::gpu::filter_renderer::{
-    FilterRenderer, FILTER_BINARIZE, FILTER_BLACK_WHITE, FILTER_BRIGHTNESS_CONTRAST,
-    FILTER_FILL_EXPAND, FILTER_GAUSSIAN_BLUR, FILTER_HUE_SATURATION, FILTER_INVERT,
-    FILTER_LEAK_REMOVAL, FILTER_LINE_NARROW, FILTER_SCAN_PAPER_DRAWING,
+    color_filter_params, FilterRenderer, FILTER_FILL_EXPAND, FILTER_GAUSSIAN_BLUR,
+    FILTER_LEAK_REMOVAL, FILTER_LINE_NARROW,
 };
-use crate::gpu::layer_format::LAYER_TEXTURE_FORMAT;
 
-use super::layers::LayerTextures;
+use super::adjustments::{LayerAdjustment, LayerAdjustments};
+use super::alpha_lock::AlphaLockRenderer;
+use super::assist::StrokeAssist;
+use super::cpu_engine::create_cpu_engine;
+use super::events::{EngineEventPublisher, EngineEventSink};
+use super::groups::LayerGroups;
+use super::history::HistoryMove;
+use super::journal::{
+    open_engine_journal, EngineJournal, JournalEntry, JournalLog, ReplayedCanvas,
+    JOURNAL_BACKEND_GPU,
+};
+use super::layers::{texture_bytes, DetachedLayer, LayerStore};
+use super::masks::{
+    invert_mask_texel, mask_gray, mask_stroke_settings, mask_texel_value, LayerMaskState,
+    LayerMasks, MASK_REVEAL_ALL,
+};
 use super::present::{
-    attach_present_texture, create_present_params_buffer, create_present_transform_buffer,
-    copy_render_to_shared, signal_frame_ready, write_present_config, write_present_transform,
-    PresentRenderer, PresentTarget,
+    attach_present_texture, copy_render_to_shared, create_present_groups_buffer,
+    create_present_params_buffer, create_present_transform_buffer, signal_frame_ready,
+    write_present_config, write_present_transform, PresentRenderer, PresentTarget,
 };
+use super::pressure::DeviceCalibrations;
 use super::preview::{PreviewConfig, PreviewRenderer, PreviewSegment};
+use super::rin::{EngineLayerSnapshot, EngineProjectSnapshot, RinMask};
 #[cfg(target_os = "windows")]
 use super::present::create_dxgi_shared_present_target;
 use super::stroke::{
     apply_streamline, brush_random_rotation_radians, map_brush_shape, prepare_brush_samples,
-    EngineBrushSettings, StrokeResampler,
+    ColorDynamics, ColorJitter, ColorMixSource, EngineBrushSettings, PenDynamics, PenState,
+    SpringSettings, StrokeResampler,
 };
+use super::symmetry::{Symmetry, SymmetryMode};
 use super::transform::LayerTransformRenderer;
 use super::types::{EnginePoint, SprayPoint};
-use super::undo::UndoManager;
+use super::undo::{LayerEdit, LayerProperties, UndoApplied, UndoManager, UndoTarget};
+use super::velocity::{SimulatedPressure, SpeedPressureProfile};
 
 const INITIAL_LAYER_CAPACITY: usize = 4;
-const VIEW_FLAG_MIRROR: u32 = 1;
-const VIEW_FLAG_BLACK_WHITE: u32 = 2;
+pub(crate) const VIEW_FLAG_MIRROR: u32 = 1;
+pub(crate) const VIEW_FLAG_BLACK_WHITE: u32 = 2;
 const ENABLE_STREAMLINE_VECTOR_PREVIEW: bool = false;
+// Forces the software backend even when a GPU adapter is available.
+const CPU_ENGINE_ENV: &str = "MISA_RIN_RUST_CPU_ENGINE";
 static PIXEL_SAMPLE_LAST_MS: AtomicU64 = AtomicU64::new(0);
 const RESAMPLE_BACKLOG_SMALL: u64 = 24;
 const RESAMPLE_BACKLOG_MEDIUM: u64 = 64;
@@ -137,6 +163,12 @@ pub(crate) enum EngineCommand {
         layer_index: u32,
         clipping_mask: bool,
     },
+    /// Alpha-locked layers keep the alpha of every pixel through strokes,
+    /// spray, bucket fill and filters; only the colour changes.
+    SetLayerAlphaLock {
+        layer_index: u32,
+        locked: bool,
+    },
     SetLayerBlendMode {
         layer_index: u32,
         blend_mode_index: u32,
@@ -145,6 +177,102 @@ pub(crate) enum EngineCommand {
         from_index: u32,
         to_index: u32,
     },
+    /// Inserts a transparent layer at `layer_index`, moving the layers from
+    /// there on up by one.
+    InsertLayer {
+        layer_index: u32,
+    },
+    /// Removes the layer at `layer_index`. The last layer is never removed.
+    RemoveLayer {
+        layer_index: u32,
+    },
+    /// Replies with the new group id, or 0 when `parent_group_id` is unknown.
+    CreateLayerGroup {
+        parent_group_id: u32,
+        reply: mpsc::Sender<u32>,
+    },
+    DeleteLayerGroup {
+        group_id: u32,
+    },
+    SetLayerGroupParent {
+        group_id: u32,
+        parent_group_id: u32,
+    },
+    MoveLayerToGroup {
+        layer_index: u32,
+        group_id: u32,
+    },
+    SetLayerGroupOpacity {
+        group_id: u32,
+        opacity: f32,
+    },
+    SetLayerGroupVisible {
+        group_id: u32,
+        visible: bool,
+    },
+    SetLayerGroupBlendMode {
+        group_id: u32,
+        blend_mode_index: u32,
+    },
+    SetLayerGroupPassThrough {
+        group_id: u32,
+        pass_through: bool,
+    },
+    SetLayerGroupCollapsed {
+        group_id: u32,
+        collapsed: bool,
+    },
+    GetLayerGroups {
+        reply: mpsc::Sender<Vec<u32>>,
+    },
+    CreateLayerMask {
+        layer_index: u32,
+    },
+    DeleteLayerMask {
+        layer_index: u32,
+    },
+    SetLayerMaskEnabled {
+        layer_index: u32,
+        enabled: bool,
+    },
+    /// Routes brush strokes on the active layer to its mask, when it has one.
+    SetLayerMaskEditing {
+        editing: bool,
+    },
+    InvertLayerMask {
+        layer_index: u32,
+    },
+    /// Multiplies the layer's alpha by its mask, then removes the mask.
+    ApplyLayerMask {
+        layer_index: u32,
+    },
+    /// Replies with one coverage byte per pixel, or `None` without a mask.
+    ReadLayerMask {
+        layer_index: u32,
+        reply: mpsc::Sender<Option<Vec<u8>>>,
+    },
+    /// Replaces the texels of the layer's mask with `coverage`, one byte per
+    /// pixel. Replies `false` when the layer has no mask or the size is off.
+    WriteLayerMask {
+        layer_index: u32,
+        coverage: Vec<u8>,
+        reply: mpsc::Sender<bool>,
+    },
+    /// Turns the layer into an adjustment layer running `filter_type` (an
+    /// `ApplyFilter` colour filter) over everything below it, or updates the
+    /// parameters of an existing one.
+    SetLayerAdjustment {
+        layer_index: u32,
+        filter_type: u32,
+        param0: f32,
+        param1: f32,
+        param2: f32,
+        param3: f32,
+    },
+    /// Turns an adjustment layer back into a pixel layer.
+    ClearLayerAdjustment {
+        layer_index: u32,
+    },
     SetViewFlags {
         view_flags: u32,
     },
@@ -183,6 +311,121 @@ pub(crate) enum EngineCommand {
         mask: Vec<u8>,
     },
     ClearBrushMask,
+    /// Tilt and rotation dynamics; kept across `SetBrush`.
+    SetPenDynamics {
+        tilt_size: f32,
+        tilt_opacity: f32,
+        azimuth_angle: bool,
+        barrel_angle: bool,
+    },
+    /// Sets the `(pressure, value)` control points of one pressure curve;
+    /// see `PressureTarget` for `target`. Kept across `SetBrush`.
+    SetPressureCurve {
+        target: u32,
+        points: Vec<(f32, f32)>,
+    },
+    /// Speed-driven pressure and tapered caps; see `SpeedPressureProfile` for
+    /// `profile`. Kept across `SetBrush`.
+    SetSimulatedPressure {
+        profile: u32,
+        tapered_caps: bool,
+    },
+    /// Tunes the virtual tip of smoothing mode 4. Kept across `SetBrush`.
+    SetSpringStabilizer {
+        mass: f32,
+        stiffness: f32,
+        damping: f32,
+    },
+    /// Picks the guide strokes snap to; see `AssistGuide::from_params` for
+    /// the layout of `params`. Kept across `SetBrush`.
+    SetAssist {
+        kind: u32,
+        params: Vec<f32>,
+        show_overlay: bool,
+    },
+    /// Repeats strokes around (`center_x`, `center_y`): `mode` 1 mirrors
+    /// left to right, 2 top to bottom, 3 both, 4 turns `folds` copies and
+    /// 5 also mirrors each of them. Kept across `SetBrush`.
+    SetSymmetry {
+        mode: u32,
+        center_x: f32,
+        center_y: f32,
+        folds: u32,
+    },
+    /// Tileable grayscale paper texture, `width * height` bytes; empty
+    /// clears it.
+    SetGrainTexture {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+    /// How the brush picks up the paper texture. Kept across `SetBrush`.
+    SetGrain {
+        enabled: bool,
+        scale: f32,
+        offset_x: f32,
+        offset_y: f32,
+        depth: f32,
+        invert: bool,
+        blend: u32,
+    },
+    /// Colour-mixing brush family: `mode` 0 is off, 1 smudge, 2 blender and
+    /// 3 watercolour. The amounts run from 0 to 1. Kept across `SetBrush`.
+    SetMixing {
+        mode: u32,
+        pickup: f32,
+        dilution: f32,
+        persistence: f32,
+        extension: f32,
+    },
+    /// Watercolour pooling with wet edges applied when the stroke ends.
+    /// `edge` and `granulation` run from 0 to 1; `bleed` is in canvas
+    /// pixels. Kept across `SetBrush`.
+    SetWatercolor {
+        enabled: bool,
+        edge: f32,
+        granulation: f32,
+        bleed: f32,
+    },
+    /// Secondary tip of a dual brush. `blend` picks multiply, darken,
+    /// overlay, colour dodge, colour burn, linear burn or hard mix (0..6);
+    /// `shape` is a brush shape index used while no dual brush mask is set.
+    /// `scale` is relative to the brush radius, `spacing` to the secondary
+    /// diameter and `scatter` to the brush diameter. Kept across `SetBrush`.
+    SetDualBrush {
+        enabled: bool,
+        blend: u32,
+        shape: u32,
+        scale: f32,
+        spacing: f32,
+        scatter: f32,
+        count: u32,
+        size_jitter: f32,
+    },
+    /// Tip image of the dual brush in the `SetBrushMask` layout; empty
+    /// clears it.
+    SetDualBrushMask {
+        width: u32,
+        height: u32,
+        mask: Vec<u8>,
+    },
+    /// Colour dynamics. The jitter amounts run from 0 to 1, per dab and
+    /// once per stroke. `background_source` 0 is off, 1 pressure and 2
+    /// random, mixing towards `background_argb` by up to
+    /// `background_amount`. Kept across `SetBrush`.
+    SetColorDynamics {
+        hue_jitter: f32,
+        saturation_jitter: f32,
+        value_jitter: f32,
+        opacity_jitter: f32,
+        stroke_hue_jitter: f32,
+        stroke_saturation_jitter: f32,
+        stroke_value_jitter: f32,
+        stroke_opacity_jitter: f32,
+        background_argb: u32,
+        background_source: u32,
+        background_amount: f32,
+    },
     BeginSpray,
     DrawSpray {
         points: Vec<SprayPoint>,
@@ -244,6 +487,10 @@ pub(crate) enum EngineCommand {
     ReadPresent {
         reply: mpsc::Sender<Option<Vec<u8>>>,
     },
+    /// Every layer's pixels and compositing properties, for project saves.
+    ReadProject {
+        reply: mpsc::Sender<Option<EngineProjectSnapshot>>,
+    },
     WriteLayer {
         layer_index: u32,
         pixels: Vec<u32>,
@@ -277,6 +524,38 @@ pub(crate) enum EngineCommand {
     },
     Undo,
     Redo,
+    /// Forgets every undo and redo step, e.g. once a loaded project is set up.
+    ClearUndoHistory,
+    /// Caps the undo history, branches included, at `max_steps` steps; tile
+    /// patches past `budget_bytes` are compressed into system memory, oldest
+    /// first.
+    SetUndoLimits {
+        max_steps: u32,
+        budget_bytes: u64,
+    },
+    /// Collects the pixel changes of the following commands, on any layer,
+    /// into one undo step. Layer edits, undo and redo commit it early.
+    BeginUndoTransaction,
+    CommitUndoTransaction,
+    /// Ends the transaction and puts back every pixel changed since it began.
+    CancelUndoTransaction,
+    /// Names the current history state; an empty name removes the checkpoint.
+    SetHistoryCheckpoint {
+        name: String,
+    },
+    /// Replies with the undo, redo and branch moves that reach history node
+    /// `node_id`, or `None` when the node is no longer kept.
+    PlanHistoryRoute {
+        node_id: u64,
+        reply: mpsc::Sender<Option<Vec<HistoryMove>>>,
+    },
+    /// Points redo at the branch that starts with history node `node_id`.
+    EnterHistoryBranch {
+        node_id: u64,
+    },
+    ReadHistory {
+        reply: mpsc::Sender<Vec<EngineHistoryNode>>,
+    },
     Stop,
 }
 
@@ -284,13 +563,23 @@ pub(crate) struct EngineInputBatch {
     pub(crate) points: Vec<EnginePoint>,
 }
 
+/// Which renderer drives an engine handle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum EngineBackend {
+    Gpu,
+    Cpu,
+}
+
 pub(crate) struct EngineEntry {
+    pub(crate) backend: EngineBackend,
     pub(crate) mtl_device_ptr: usize,
     pub(crate) frame_ready: Arc<AtomicBool>,
     pub(crate) frame_in_flight: Arc<AtomicBool>,
     pub(crate) cmd_tx: mpsc::Sender<EngineCommand>,
     pub(crate) input_tx: mpsc::Sender<EngineInputBatch>,
     pub(crate) input_queue_len: Arc<AtomicU64>,
+    pub(crate) events: EngineEventSink,
+    pub(crate) calibrations: Arc<Mutex<DeviceCalibrations>>,
 }
 
 static ENGINES: OnceLock<Mutex<HashMap<u64, EngineEntry>>> = OnceLock::new();
@@ -300,35 +589,41 @@ fn engines() -> &'static Mutex<HashMap<u64, EngineEntry>> {
     ENGINES.get_or_init(|| Mutex::new(HashMap::new()))
 }
 
-struct EngineDeviceContext {
+pub(crate) struct EngineDeviceContext {
     _instance: wgpu::Instance,
     _adapter: wgpu::Adapter,
-    device: Arc<wgpu::Device>,
-    queue: Arc<wgpu::Queue>,
+    pub(crate) device: Arc<wgpu::Device>,
+    pub(crate) queue: Arc<wgpu::Queue>,
 }
 
 static DEVICE_CONTEXT: OnceLock<Result<EngineDeviceContext, String>> = OnceLock::new();
 
-fn device_context() -> Result<&'static EngineDeviceContext, String> {
+pub(crate) fn device_context() -> Result<&'static EngineDeviceContext, String> {
     let init_result = DEVICE_CONTEXT.get_or_init(|| {
-        let backends = if cfg!(any(target_os = "macos", target_os = "ios")) {
-            wgpu::Backends::METAL
+        let candidates: &[wgpu::Backends] = if cfg!(any(target_os = "macos", target_os = "ios")) {
+            &[wgpu::Backends::METAL]
         } else if cfg!(target_os = "windows") {
-            wgpu::Backends::DX12
+            &[wgpu::Backends::DX12]
         } else {
-            wgpu::Backends::PRIMARY
+            // Linux machines without a Vulkan driver still expose GL (Mesa
+            // llvmpipe included), which beats dropping to the CPU backend.
+            &[wgpu::Backends::PRIMARY, wgpu::Backends::GL]
         };
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends,
-            ..Default::default()
-        });
-
-        let adapter =
-            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: None,
-                force_fallback_adapter: false,
-            }))
+        let (instance, adapter) = candidates
+            .iter()
+            .find_map(|&backends| {
+                let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+                    backends,
+                    ..Default::default()
+                });
+                let adapter =
+                    pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                        power_preference: wgpu::PowerPreference::HighPerformance,
+                        compatible_surface: None,
+                        force_fallback_adapter: false,
+                    }))?;
+                Some((instance, adapter))
+            })
             .ok_or_else(|| "wgpu: no compatible adapter found".to_string())?;
 
         let required_features = wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
@@ -367,27 +662,59 @@ fn device_context() -> Result<&'static EngineDeviceContext, String> {
     }
 }
 
+/// Reads that arrived while no present target is attached. Each one waits
+/// for the input points queued ahead of it, not for the queue to run dry, so
+/// a steady pen stream cannot starve it.
+#[derive(Default)]
+pub(crate) struct DeferredReads {
+    pending: Vec<(u64, EngineCommand)>,
+    consumed_points: u64,
+}
+
+impl DeferredReads {
+    pub(crate) fn defer(&mut self, cmd: EngineCommand, queued_points: u64) {
+        self.pending.push((self.consumed_points + queued_points, cmd));
+    }
+
+    /// Counts points taken off the input channel; they are drawn before the
+    /// loop serves reads again.
+    pub(crate) fn note_consumed(&mut self, points: u64) {
+        self.consumed_points += points;
+    }
+
+    /// Reads whose preceding input has been consumed, in arrival order.
+    pub(crate) fn take_ready(&mut self) -> Vec<EngineCommand> {
+        let ready = self
+            .pending
+            .iter()
+            .take_while(|(after, _)| *after <= self.consumed_points)
+            .count();
+        self.pending.drain(..ready).map(|(_, cmd)| cmd).collect()
+    }
+}
+
 struct StreamlineAnimation {
     start: Instant,
     duration: Duration,
     next_frame_at: Instant,
     frame_interval: Duration,
     pending_first_frame: bool,
-    from_points: Vec<(Point2D, f32)>,
-    to_points: Vec<(Point2D, f32)>,
-    preview_from_points: Option<Vec<(Point2D, f32)>>,
-    preview_to_points: Option<Vec<(Point2D, f32)>>,
-    scratch: Vec<(Point2D, f32)>,
+    from_points: Vec<(Point2D, PenState)>,
+    to_points: Vec<(Point2D, PenState)>,
+    preview_from_points: Option<Vec<(Point2D, PenState)>>,
+    preview_to_points: Option<Vec<(Point2D, PenState)>>,
+    scratch: Vec<(Point2D, PenState)>,
     layer_index: u32,
     brush_settings: EngineBrushSettings,
     use_hollow_mask: bool,
     use_hollow_base: bool,
+    alpha_locked: bool,
 }
 
 struct PreviewStrokeState {
     layer_index: u32,
     brush_settings: EngineBrushSettings,
-    points: Vec<(Point2D, f32)>,
+    points: Vec<(Point2D, PenState)>,
     use_accumulate: bool,
 }
 
@@ -409,7 +736,7 @@ impl StreamlineAnimation {
     }
 }
 
-fn streamline_animation_duration(strength: f32) -> Duration {
+pub(crate) fn streamline_animation_duration(strength: f32) -> Duration {
     let s = if strength.is_finite() {
         strength.clamp(0.0, 1.0)
     } else {
@@ -586,13 +913,13 @@ fn maybe_log_layer_sample(
     );
 }
 
-fn ease_out_cubic(t: f32) -> f32 {
+pub(crate) fn ease_out_cubic(t: f32) -> f32 {
     let t = t.clamp(0.0, 1.0);
     let inv = 1.0 - t;
     1.0 - inv * inv * inv
 }
 
-fn resample_scale_for_backlog(backlog_points: u64) -> f32 {
+pub(crate) fn resample_scale_for_backlog(backlog_points: u64) -> f32 {
     let _ = backlog_points;
     1.0
 }
@@ -600,9 +927,9 @@ fn resample_scale_for_backlog(backlog_points: u64) -> f32 {
 const STREAMLINE_PREVIEW_MAX_POINTS: usize = 512;
 
 fn downsample_streamline_points(
-    points: &[(Point2D, f32)],
+    points: &[(Point2D, PenState)],
     target_count: usize,
-) -> Vec<(Point2D, f32)> {
+) -> Vec<(Point2D, PenState)> {
     let len = points.len();
     if len <= target_count {
         return points.to_vec();
@@ -619,10 +946,13 @@ fn downsample_streamline_points(
     output
 }
 
-fn build_streamline_preview(
-    from: &[(Point2D, f32)],
-    to: &[(Point2D, f32)],
-) -> Option<(Vec<(Point2D, f32)>, Vec<(Point2D, f32)>)> {
+/// Downsampled `from` and `to` samples of a long streamline animation.
+pub(crate) type StreamlinePreview = (Vec<(Point2D, PenState)>, Vec<(Point2D, PenState)>);
+
+pub(crate) fn build_streamline_preview(
+    from: &[(Point2D, PenState)],
+    to: &[(Point2D, PenState)],
+) -> Option<StreamlinePreview> {
     if from.len() != to.len() || from.len() <= STREAMLINE_PREVIEW_MAX_POINTS {
         return None;
     }
@@ -658,7 +988,7 @@ fn has_visible_layer_above(
     false
 }
 
-fn brush_shape_index(shape: BrushShape) -> u32 {
+pub(crate) fn brush_shape_index(shape: BrushShape) -> u32 {
     match shape {
         BrushShape::Circle => 0,
         BrushShape::Triangle => 1,
@@ -733,8 +1063,53 @@ fn build_preview_config(
     }
 }
 
+const ASSIST_OVERLAY_COLOR: u32 = 0xB03A_8DFF;
+const ASSIST_OVERLAY_RADIUS: f32 = 0.6;
+
+fn build_assist_overlay(
+    assist: &StrokeAssist,
+    canvas_width: u32,
+    canvas_height: u32,
+    view_flags: u32,
+) -> Option<(PreviewConfig, Vec<PreviewSegment>)> {
+    if !assist.show_overlay {
+        return None;
+    }
+    let segments: Vec<PreviewSegment> = assist
+        .guide
+        .overlay_lines(canvas_width, canvas_height)
+        .into_iter()
+        .map(|(a, b)| PreviewSegment {
+            p0: [a.x, a.y],
+            p1: [b.x, b.y],
+            r0: ASSIST_OVERLAY_RADIUS,
+            r1: ASSIST_OVERLAY_RADIUS,
+            rot_sin: 0.0,
+            rot_cos: 1.0,
+        })
+        .collect();
+    if segments.is_empty() {
+        return None;
+    }
+    let config = PreviewConfig {
+        canvas_width,
+        canvas_height,
+        brush_shape: 0,
+        antialias_level: 2,
+        color_argb: ASSIST_OVERLAY_COLOR,
+        erase_mode: 0,
+        mirror_x: ((view_flags & VIEW_FLAG_MIRROR) != 0) as u32,
+        _pad0: 0,
+        hollow_ratio: 0.0,
+        softness: 0.0,
+        layer_opacity: 1.0,
+        _pad1: 0.0,
+    };
+    Some((config, segments))
+}
+
 fn build_preview_segments(
-    samples: &[(Point2D, f32)],
+    samples: &[(Point2D, PenState)],
     brush_settings: &EngineBrushSettings,
 ) -> Vec<PreviewSegment> {
     if samples.is_empty() {
@@ -749,6 +1124,14 @@ fn build_preview_segments(
     let use_random = brush_settings.random_rotation
         && brush_settings.rotation_jitter > 0.0001
         && supports_rotation;
+    let use_pen = brush_settings.uses_pen_angle();
+    let pen_angle = |idx: usize| {
+        if use_pen {
+            brush_settings.dynamics.angle(samples[idx].1)
+        } else {
+            0.0
+        }
+    };
     let jitter = if brush_settings.rotation_jitter.is_finite() {
         brush_settings.rotation_jitter.clamp(0.0, 1.0)
     } else {
@@ -758,7 +1141,7 @@ fn build_preview_segments(
     if points.len() == 1 {
         let p0 = points[0];
         let radius = radii[0];
-        let mut rotation = 0.0;
+        let mut rotation = pen_angle(0);
         if use_random {
             rotation += brush_random_rotation_radians(p0, brush_settings.rotation_seed) * jitter;
         }
@@ -778,7 +1161,7 @@ fn build_preview_segments(
             (points[1].y - p0.y).atan2(points[1].x - p0.x)
         } else {
             0.0
-        };
+        } + pen_angle(0);
         if use_random {
             rotation += brush_random_rotation_radians(p0, brush_settings.rotation_seed) * jitter;
         }
@@ -800,7 +1183,7 @@ fn build_preview_segments(
             (p1.y - p0.y).atan2(p1.x - p0.x)
         } else {
             0.0
-        };
+        } + pen_angle(i);
         if use_random {
             rotation += brush_random_rotation_radians(p0, brush_settings.rotation_seed) * jitter;
         }
@@ -821,7 +1204,7 @@ fn build_preview_segments(
             (p0.y - points[last - 1].y).atan2(p0.x - points[last - 1].x)
         } else {
             0.0
-        };
+        } + pen_angle(last);
         if use_random {
             rotation += brush_random_rotation_radians(p0, brush_settings.rotation_seed) * jitter;
         }
@@ -852,7 +1235,7 @@ fn antialias_feather(level: u32) -> f32 {
     }
 }
 
-fn compute_spray_dirty_rect(
+pub(crate) fn compute_spray_dirty_rect(
     points: &[SprayPoint],
     canvas_width: u32,
     canvas_height: u32,
@@ -907,11 +1290,11 @@ fn compute_spray_dirty_rect(
     (left as i32, top as i32, width, height)
 }
 
-fn interpolate_streamline_points(
-    from: &[(Point2D, f32)],
-    to: &[(Point2D, f32)],
+pub(crate) fn interpolate_streamline_points(
+    from: &[(Point2D, PenState)],
+    to: &[(Point2D, PenState)],
     t: f32,
-    out: &mut Vec<(Point2D, f32)>,
+    out: &mut Vec<(Point2D, PenState)>,
 ) {
     out.clear();
     if from.len() != to.len() || from.is_empty() {
@@ -923,13 +1306,13 @@ fn interpolate_streamline_points(
     for (orig, smooth) in from.iter().zip(to.iter()) {
         let x = orig.0.x + (smooth.0.x - orig.0.x) * t;
         let y = orig.0.y + (smooth.0.y - orig.0.y) * t;
-        let blended = orig.1 + (smooth.1 - orig.1) * t;
-        let pres = if blended.is_finite() {
-            blended.clamp(0.0, 1.0)
+        let mut pen = orig.1.mix(smooth.1, t);
+        pen.pressure = if pen.pressure.is_finite() {
+            pen.pressure.clamp(0.0, 1.0)
         } else {
-            orig.1
+            orig.1.pressure
         };
-        out.push((Point2D { x, y }, pres));
+        out.push((Point2D { x, y }, pen));
     }
 }
 
@@ -940,9 +1323,9 @@ fn render_streamline_frame(
     brush: &mut Option<BrushRenderer>,
     device: &Arc<wgpu::Device>,
     queue: &Arc<wgpu::Queue>,
-    layers: &LayerTextures,
+    layers: &mut LayerStore,
+    layer_masks: &mut LayerMasks,
     undo_manager: &mut UndoManager,
-    layer_uniform: &mut Vec<Option<u32>>,
     canvas_width: u32,
     canvas_height: u32,
 ) -> bool {
@@ -956,15 +1339,9 @@ fn render_streamline_frame(
             return false;
         }
     };
+    brush_ref.set_alpha_lock(animation.alpha_locked);
     let layer_idx = animation.layer_index as usize;
-    let Some(active_layer_view) = layers
-        .layer_view(layer_idx)
-        .or_else(|| layers.layer_view(0))
-    else {
-        return false;
-    };
-    let layer_texture = layers.texture();
-    if !undo_manager.restore_current_before(device.as_ref(), queue.as_ref(), layer_texture) {
+    if !undo_manager.restore_current_before(device.as_ref(), queue.as_ref(), layers, layer_masks) {
         if debug::level() >= LogLevel::Info {
             debug::log(
                 LogLevel::Info,
@@ -973,13 +1350,19 @@ fn render_streamline_frame(
         }
         return false;
     }
-    if animation.use_hollow_mask {
+    let watercolor = animation.brush_settings.watercolor();
+    if animation.use_hollow_mask || watercolor {
         if let Err(err) = brush_ref.clear_stroke_mask() {
             debug::log(
                 LogLevel::Warn,
                 format_args!("Brush stroke mask clear failed: {err}"),
             );
         }
+    }
+    let capture_base = animation.use_hollow_base
+        || animation.brush_settings.mixing()
+        || watercolor;
+    if animation.use_hollow_mask || capture_base {
         brush_ref.begin_stroke_base_capture();
     }
     let (from_points, to_points) = if t >= 0.999 {
@@ -996,10 +1379,21 @@ fn render_streamline_frame(
     if animation.scratch.is_empty() {
         return false;
     }
+    let slot = match layers.checkout(device.as_ref(), queue.as_ref(), layer_idx) {
+        Ok(slot) => slot,
+        Err(err) => {
+            debug::log(
+                LogLevel::Warn,
+                format_args!("streamline layer checkout failed: {err}"),
+            );
+            return false;
+        }
+    };
+    let layer_texture = layers.texture();
+    let active_layer_view = layers.working_view();
+    let mut painted = Vec::new();
     let mut before_draw = |brush: &mut BrushRenderer, dirty_rect| {
-        if let Err(err) =
-            brush.prepare_layer_read(layer_texture, animation.layer_index, dirty_rect)
-        {
+        if let Err(err) = brush.prepare_layer_read(layer_texture, slot, dirty_rect) {
             debug::log(
                 LogLevel::Warn,
                 format_args!("Brush layer read prep failed: {err}"),
@@ -1010,12 +1404,12 @@ fn render_streamline_frame(
             queue.as_ref(),
             layer_texture,
             animation.layer_index,
+            slot,
             dirty_rect,
         );
-        if animation.use_hollow_base {
-            if let Err(err) =
-                brush.capture_stroke_base_region(layer_texture, animation.layer_index, dirty_rect)
-            {
+        painted.push(dirty_rect);
+        if capture_base {
+            if let Err(err) = brush.capture_stroke_base_region(layer_texture, slot, dirty_rect) {
                 debug::log(
                     LogLevel::Warn,
                     format_args!("Brush stroke base capture failed: {err}"),
@@ -1032,6 +1426,24 @@ fn render_streamline_frame(
         canvas_height,
         &mut before_draw,
     );
+    if t >= 0.999 {
+        painted.extend(finish_wet_stroke(
+            brush_ref,
+            &animation.brush_settings,
+            WetStrokeLayer {
+                view: active_layer_view,
+                texture: layer_texture,
+                index: animation.layer_index,
+                slice: slot,
+            },
+            undo_manager,
+            device.as_ref(),
+            queue.as_ref(),
+        ));
+    }
+    for rect in painted {
+        layers.mark_dirty(layer_idx, rect);
+    }
     if debug::level() >= LogLevel::Info {
         debug::log(
             LogLevel::Info,
@@ -1044,15 +1456,12 @@ fn render_streamline_frame(
         );
     }
     if drawn_any {
-        if let Some(entry) = layer_uniform.get_mut(layer_idx) {
-            *entry = None;
-        }
         if let Some(dirty) = stroke.last_tick_dirty() {
             maybe_log_layer_sample(
                 device.as_ref(),
                 queue.as_ref(),
-                layer_texture,
-                animation.layer_index,
+                layers.texture(),
+                slot,
                 canvas_width,
                 canvas_height,
                 dirty,
@@ -1068,11 +1477,11 @@ fn commit_preview_stroke(
     stroke: &mut StrokeResampler,
     brush: &mut Option<BrushRenderer>,
     brush_settings: &EngineBrushSettings,
-    points: &[(Point2D, f32)],
+    points: &[(Point2D, PenState)],
     layer_index: u32,
-    layers: &LayerTextures,
+    layers: &mut LayerStore,
+    layer_masks: &mut LayerMasks,
     undo_manager: &mut UndoManager,
-    layer_uniform: &mut Vec<Option<u32>>,
     device: &Arc<wgpu::Device>,
     queue: &Arc<wgpu::Queue>,
     canvas_width: u32,
@@ -1091,30 +1500,43 @@ fn commit_preview_stroke(
             return false;
         }
     };
+    // Vector previews never start on alpha-locked layers.
+    brush_ref.set_alpha_lock(false);
     let layer_idx = layer_index as usize;
-    let Some(active_layer_view) = layers
-        .layer_view(layer_idx)
-        .or_else(|| layers.layer_view(0))
-    else {
-        return false;
+    let slot = match layers.checkout(device.as_ref(), queue.as_ref(), layer_idx) {
+        Ok(slot) => slot,
+        Err(err) => {
+            debug::log(
+                LogLevel::Warn,
+                format_args!("preview stroke layer checkout failed: {err}"),
+            );
+            return false;
+        }
     };
     let layer_texture = layers.texture();
+    let active_layer_view = layers.working_view();
     undo_manager.begin_stroke(layer_index);
     let use_hollow_mask = brush_settings.hollow_enabled
         && !brush_settings.erase
         && brush_settings.hollow_ratio > 0.0001;
-    if use_hollow_mask {
+    let watercolor = brush_settings.watercolor();
+    if use_hollow_mask || watercolor {
         if let Err(err) = brush_ref.clear_stroke_mask() {
             debug::log(
                 LogLevel::Warn,
                 format_args!("Brush stroke mask clear failed: {err}"),
             );
         }
+    }
+    if use_hollow_mask || brush_settings.mixing() || watercolor {
         brush_ref.begin_stroke_base_capture();
     }
-    let use_hollow_base = use_hollow_mask && !brush_settings.hollow_erase_occluded;
+    let capture_base = (use_hollow_mask && !brush_settings.hollow_erase_occluded)
+        || brush_settings.mixing()
+        || watercolor;
+    let mut painted = Vec::new();
     let mut before_draw = |brush: &mut BrushRenderer, dirty_rect| {
-        if let Err(err) = brush.prepare_layer_read(layer_texture, layer_index, dirty_rect) {
+        if let Err(err) = brush.prepare_layer_read(layer_texture, slot, dirty_rect) {
             debug::log(
                 LogLevel::Warn,
                 format_args!("Brush layer read prep failed: {err}"),
@@ -1125,12 +1547,12 @@ fn commit_preview_stroke(
             queue.as_ref(),
             layer_texture,
             layer_index,
+            slot,
             dirty_rect,
         );
-        if use_hollow_base {
-            if let Err(err) =
-                brush.capture_stroke_base_region(layer_texture, layer_index, dirty_rect)
-            {
+        painted.push(dirty_rect);
+        if capture_base {
+            if let Err(err) = brush.capture_stroke_base_region(layer_texture, slot, dirty_rect) {
                 debug::log(
                     LogLevel::Warn,
                     format_args!("Brush stroke base capture failed: {err}"),
@@ -1147,19 +1569,91 @@ fn commit_preview_stroke(
         canvas_height,
         &mut before_draw,
     );
-    undo_manager.end_stroke(device.as_ref(), queue.as_ref(), layer_texture);
-    if drawn_any {
-        if let Some(entry) = layer_uniform.get_mut(layer_idx) {
-            *entry = None;
-        }
+    painted.extend(finish_wet_stroke(
+        brush_ref,
+        brush_settings,
+        WetStrokeLayer {
+            view: active_layer_view,
+            texture: layer_texture,
+            index: layer_index,
+            slice: slot,
+        },
+        undo_manager,
+        device.as_ref(),
+        queue.as_ref(),
+    ));
+    for rect in painted {
+        layers.mark_dirty(layer_idx, rect);
     }
+    undo_manager.end_stroke(device.as_ref(), queue.as_ref(), layers, layer_masks);
     drawn_any
 }
 
+/// The layer a watercolour stroke is finished on: `index` in the layer
+/// stack, held in slice `slice` of `texture`.
+struct WetStrokeLayer<'a> {
+    view: &'a wgpu::TextureView,
+    texture: &'a wgpu::Texture,
+    index: u32,
+    slice: u32,
+}
+
+/// Ends a watercolour stroke and returns the rect it painted. The finishing
+/// pass reaches past the dabs, so the undo tiles and stroke base are grown
+/// to cover it first.
+fn finish_wet_stroke(
+    brush: &mut BrushRenderer,
+    brush_settings: &EngineBrushSettings,
+    layer: WetStrokeLayer,
+    undo_manager: &mut UndoManager,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Option<(i32, i32, i32, i32)> {
+    if !brush_settings.watercolor() {
+        return None;
+    }
+    let rect = brush.wet_stroke_rect(brush_settings.wet.reach())?;
+    undo_manager.capture_before_for_dirty_rect(
+        device,
+        queue,
+        layer.texture,
+        layer.index,
+        layer.slice,
+        rect,
+    );
+    if let Err(err) = brush.capture_stroke_base_region(layer.texture, layer.slice, rect) {
+        debug::log(
+            LogLevel::Warn,
+            format_args!("Brush stroke base capture failed: {err}"),
+        );
+    }
+    if let Err(err) = brush.finish_wet_stroke(layer.view, rect) {
+        debug::log(
+            LogLevel::Warn,
+            format_args!("Brush watercolour finish failed: {err}"),
+        );
+    }
+    Some(rect)
+}
+
+/// View and texture a brush stroke paints into: the checked out working
+/// texture of the layer, or of its mask while the mask is edited.
+fn stroke_surface<'a>(
+    layers: &'a LayerStore,
+    layer_masks: &'a LayerMasks,
+    mask_editing: bool,
+) -> (&'a wgpu::TextureView, &'a wgpu::Texture) {
+    if mask_editing {
+        (layer_masks.working_view(), layer_masks.texture())
+    } else {
+        (layers.working_view(), layers.texture())
+    }
+}
+
 fn spawn_render_thread(
     device: Arc<wgpu::Device>,
     queue: Arc<wgpu::Queue>,
-    layer_textures: LayerTextures,
+    layer_store: LayerStore,
     cmd_rx: mpsc::Receiver<EngineCommand>,
     input_rx: mpsc::Receiver<EngineInputBatch>,
     frame_ready: Arc<AtomicBool>,
@@ -1167,6 +1661,8 @@ fn spawn_render_thread(
     input_queue_len: Arc<AtomicU64>,
     canvas_width: u32,
     canvas_height: u32,
+    journal: Option<EngineJournal>,
+    events: EngineEventSink,
 ) {
     let _ = thread::Builder::new()
         .name("misa-rin-canvas-render".to_string())
@@ -1174,7 +1670,7 @@ fn spawn_render_thread(
             render_thread_main(
                 device,
                 queue,
-                layer_textures,
+                layer_store,
                 cmd_rx,
                 input_rx,
                 frame_ready,
@@ -1182,6 +1678,8 @@ fn spawn_render_thread(
                 input_queue_len,
                 canvas_width,
                 canvas_height,
+                journal,
+                events,
             )
         });
 }
@@ -1189,7 +1687,7 @@ fn spawn_render_thread(
 fn render_thread_main(
     device: Arc<wgpu::Device>,
     queue: Arc<wgpu::Queue>,
-    layer_textures: LayerTextures,
+    layer_store: LayerStore,
     cmd_rx: mpsc::Receiver<EngineCommand>,
     input_rx: mpsc::Receiver<EngineInputBatch>,
     frame_ready: Arc<AtomicBool>,
@@ -1197,18 +1695,25 @@ fn render_thread_main(
     input_queue_len: Arc<AtomicU64>,
     canvas_width: u32,
     canvas_height: u32,
+    mut journal: Option<EngineJournal>,
+    events: EngineEventSink,
 ) {
     let mut canvas_width = canvas_width;
     let mut canvas_height = canvas_height;
     let mut present: Option<PresentTarget> = None;
-    let mut layers = layer_textures;
-    if layers.capacity() == 0 {
-        return;
-    }
+    let mut layers = layer_store;
     // Layer order is bottom-to-top.
     let mut layer_count: usize = 1;
     let mut active_layer_index: usize = 0;
-    let mut layer_uniform: Vec<Option<u32>> = vec![None; layer_count];
+    let mut layer_groups = LayerGroups::new(layer_count);
+    let mut layer_adjustments = LayerAdjustments::new(layer_count);
+    let mut layer_masks =
+        LayerMasks::new(device.as_ref(), canvas_width, canvas_height, layer_count);
+    // Layer 0 is the background fill layer (default white). Filling it here
+    // rather than on attach keeps a project loaded before the first present
+    // target; layers added later start transparent.
+    layers.truncate(0);
+    layers.push_layer(0xFFFFFFFF);
 
     let mut brush: Option<BrushRenderer> = None;
     let mut brush_settings = EngineBrushSettings::default();
@@ -1220,6 +1725,7 @@ fn render_thread_main(
     let mut layer_opacity: Vec<f32> = vec![1.0; layer_count];
     let mut layer_visible: Vec<bool> = vec![true; layer_count];
     let mut layer_clipping_mask: Vec<bool> = vec![false; layer_count];
+    let mut layer_alpha_lock: Vec<bool> = vec![false; layer_count];
     let mut layer_blend_mode: Vec<u32> = vec![0; layer_count];
     let mut view_flags: u32 = 0;
     let present_config_buffer = device.create_buffer(&wgpu::BufferDescriptor {
@@ -1249,10 +1755,22 @@ fn render_thread_main(
                 return;
             }
         };
+    let mut present_groups_buffer =
+        match create_present_groups_buffer(device.as_ref(), present_params_capacity) {
+            Ok(buffer) => buffer,
+            Err(err) => {
+                debug::log(
+                    LogLevel::Warn,
+                    format_args!("Present groups buffer init failed: {err}"),
+                );
+                return;
+            }
+        };
     write_present_config(
         queue.as_ref(),
         &present_config_buffer,
         &present_params_buffer,
+        &present_groups_buffer,
         layer_count,
         view_flags,
         transform_layer_index,
@@ -1261,19 +1779,25 @@ fn render_thread_main(
         &layer_visible,
         &layer_clipping_mask,
         &layer_blend_mode,
+        &layer_masks.enabled_flags(),
+        &layer_adjustments.composite(layer_count),
+        &layer_groups.spans(layer_count),
     );
     write_present_transform(queue.as_ref(), &present_transform_buffer, transform_matrix);
     let mut present_bind_group = present_renderer.create_bind_group(
         device.as_ref(),
-        layers.array_view(),
+        &layers,
         &present_config_buffer,
         &present_params_buffer,
         &present_transform_buffer,
+        &present_groups_buffer,
+        &layer_masks,
     );
 
     let mut stroke = StrokeResampler::new();
     let mut undo_manager = UndoManager::new(canvas_width, canvas_height);
     let mut transform_renderer: Option<LayerTransformRenderer> = None;
+    let mut alpha_lock_renderer: Option<AlphaLockRenderer> = None;
     let mut streamline_animation: Option<StreamlineAnimation> = None;
     let mut preview_renderer: Option<PreviewRenderer> = None;
     let mut preview_state: Option<PreviewStrokeState> = None;
@@ -1281,7 +1805,9 @@ fn render_thread_main(
     let mut spray_active_layer: Option<u32> = None;
     let mut pending_present = false;
     let mut pending_present_since: Option<Instant> = None;
-    let mut deferred_reads: Vec<EngineCommand> = Vec::new();
+    let mut deferred_reads = DeferredReads::default();
+    let mut event_publisher = EngineEventPublisher::new(events);
+    event_publisher.forward_thread_warnings();
 
     loop {
         let mut needs_render = false;
@@ -1292,9 +1818,10 @@ fn render_thread_main(
                     EngineCommand::ReadLayer { .. }
                         | EngineCommand::ReadLayerPreview { .. }
                         | EngineCommand::ReadPresent { .. }
+                        | EngineCommand::ReadProject { .. }
                 )
             {
-                deferred_reads.push(cmd);
+                deferred_reads.defer(cmd, input_queue_len.load(Ordering::Relaxed));
                 continue;
             }
             if let Some(mut animation) = streamline_animation.take() {
@@ -1305,9 +1832,9 @@ fn render_thread_main(
                         &state.brush_settings,
                         &animation.to_points,
                         state.layer_index,
-                        &layers,
+                        &mut layers,
+                        &mut layer_masks,
                         &mut undo_manager,
-                        &mut layer_uniform,
                         &device,
                         &queue,
                         canvas_width,
@@ -1324,22 +1851,27 @@ fn render_thread_main(
                         &mut brush,
                         &device,
                         &queue,
-                        &layers,
+                        &mut layers,
+                        &mut layer_masks,
                         &mut undo_manager,
-                        &mut layer_uniform,
                         canvas_width,
                         canvas_height,
                     );
-                    undo_manager
-                        .end_stroke(device.as_ref(), queue.as_ref(), layers.texture());
+                    undo_manager.end_stroke(
+                        device.as_ref(),
+                        queue.as_ref(),
+                        &mut layers,
+                        &mut layer_masks,
+                    );
                     if frame_drawn {
-                        if let Some(entry) = layer_uniform.get_mut(active_layer_index) {
-                            *entry = None;
-                        }
                         needs_render = true;
                     }
                 }
             }
+            if let Some(journal) = journal.as_mut() {
+                journal.record_command(&cmd);
+            }
+            let (cmd, pending_operation) = event_publisher.watch_operation(cmd);
             let outcome = handle_engine_command(
                 &device,
                 &queue,
@@ -1353,8 +1885,11 @@ fn render_thread_main(
                 &mut layer_opacity,
                 &mut layer_visible,
                 &mut layer_clipping_mask,
+                &mut layer_alpha_lock,
                 &mut layer_blend_mode,
-                &mut layer_uniform,
+                &mut layer_groups,
+                &mut layer_masks,
+                &mut layer_adjustments,
                 &mut view_flags,
                 &present_renderer,
                 &present_config_buffer,
@@ -1364,8 +1899,10 @@ fn render_thread_main(
                 &mut transform_flags,
                 &mut present_params_buffer,
                 &mut present_params_capacity,
+                &mut present_groups_buffer,
                 &mut present_bind_group,
                 &mut transform_renderer,
+                &mut alpha_lock_renderer,
                 &mut brush,
                 &mut brush_settings,
                 &mut stroke,
@@ -1375,6 +1912,7 @@ fn render_thread_main(
                 canvas_width,
                 canvas_height,
             );
+            event_publisher.finish_operation(pending_operation);
             if outcome.stop {
                 return;
             }
@@ -1406,9 +1944,11 @@ fn render_thread_main(
         match input_rx.recv_timeout(next_timeout) {
             Ok(batch) => {
                 input_queue_len.fetch_sub(batch.points.len() as u64, Ordering::Relaxed);
+                deferred_reads.note_consumed(batch.points.len() as u64);
                 batches.push(batch);
                 while let Ok(more) = input_rx.try_recv() {
                     input_queue_len.fetch_sub(more.points.len() as u64, Ordering::Relaxed);
+                    deferred_reads.note_consumed(more.points.len() as u64);
                     batches.push(more);
                 }
             }
@@ -1423,8 +1963,9 @@ fn render_thread_main(
                     EngineCommand::ReadLayer { .. }
                         | EngineCommand::ReadLayerPreview { .. }
                         | EngineCommand::ReadPresent { .. }
+                        | EngineCommand::ReadProject { .. }
                 ) {
-                    deferred_reads.push(cmd);
+                    deferred_reads.defer(cmd, input_queue_len.load(Ordering::Relaxed));
                     continue;
                 }
                 if let Some(mut animation) = streamline_animation.take() {
@@ -1435,9 +1976,9 @@ fn render_thread_main(
                             &state.brush_settings,
                             &animation.to_points,
                             state.layer_index,
-                            &layers,
+                            &mut layers,
+                            &mut layer_masks,
                             &mut undo_manager,
-                            &mut layer_uniform,
                             &device,
                             &queue,
                             canvas_width,
@@ -1454,22 +1995,27 @@ fn render_thread_main(
                             &mut brush,
                             &device,
                             &queue,
-                            &layers,
+                            &mut layers,
+                            &mut layer_masks,
                             &mut undo_manager,
-                            &mut layer_uniform,
                             canvas_width,
                             canvas_height,
                         );
-                        undo_manager
-                            .end_stroke(device.as_ref(), queue.as_ref(), layers.texture());
+                        undo_manager.end_stroke(
+                            device.as_ref(),
+                            queue.as_ref(),
+                            &mut layers,
+                            &mut layer_masks,
+                        );
                         if frame_drawn {
-                            if let Some(entry) = layer_uniform.get_mut(active_layer_index) {
-                                *entry = None;
-                            }
                             needs_render = true;
                         }
                     }
                 }
+                if let Some(journal) = journal.as_mut() {
+                    journal.record_command(&cmd);
+                }
+                let (cmd, pending_operation) = event_publisher.watch_operation(cmd);
                 let outcome = handle_engine_command(
                     &device,
                     &queue,
@@ -1483,8 +2029,11 @@ fn render_thread_main(
                     &mut layer_opacity,
                     &mut layer_visible,
                     &mut layer_clipping_mask,
+                    &mut layer_alpha_lock,
                     &mut layer_blend_mode,
-                    &mut layer_uniform,
+                    &mut layer_groups,
+                    &mut layer_masks,
+                    &mut layer_adjustments,
                     &mut view_flags,
                     &present_renderer,
                     &present_config_buffer,
@@ -1494,8 +2043,10 @@ fn render_thread_main(
                     &mut transform_flags,
                     &mut present_params_buffer,
                     &mut present_params_capacity,
+                    &mut present_groups_buffer,
                     &mut present_bind_group,
                     &mut transform_renderer,
+                    &mut alpha_lock_renderer,
                     &mut brush,
                     &mut brush_settings,
                     &mut stroke,
@@ -1505,6 +2056,7 @@ fn render_thread_main(
                     canvas_width,
                     canvas_height,
                 );
+                event_publisher.finish_operation(pending_operation);
                 if outcome.stop {
                     return;
                 }
@@ -1526,6 +2078,9 @@ fn render_thread_main(
             }
             let backlog_points =
                 raw_points.len() as u64 + input_queue_len.load(Ordering::Relaxed);
+            if let Some(journal) = journal.as_mut() {
+                journal.record_input(backlog_points, &raw_points);
+            }
             stroke.set_resample_scale(resample_scale_for_backlog(backlog_points));
             let layer_blend_mode_value =
                 layer_blend_mode.get(active_layer_index).copied().unwrap_or(0);
@@ -1535,7 +2090,15 @@ fn render_thread_main(
                 &layer_opacity,
                 layer_count,
             );
+            let mask_editing = layer_masks.editing_layer(active_layer_index);
+            let alpha_locked = !mask_editing
+                && layer_alpha_lock
+                    .get(active_layer_index)
+                    .copied()
+                    .unwrap_or(false);
             let preview_allowed = present.is_some()
+                && !mask_editing
+                && !alpha_locked
                 && can_use_vector_preview(
                     &brush_settings,
                     selection_mask_active,
@@ -1568,9 +2131,9 @@ fn render_thread_main(
                             &state.brush_settings,
                             &animation.to_points,
                             state.layer_index,
-                            &layers,
+                            &mut layers,
+                            &mut layer_masks,
                             &mut undo_manager,
-                            &mut layer_uniform,
                             &device,
                             &queue,
                             canvas_width,
@@ -1587,20 +2150,18 @@ fn render_thread_main(
                             &mut brush,
                             &device,
                             &queue,
-                            &layers,
+                            &mut layers,
+                            &mut layer_masks,
                             &mut undo_manager,
-                            &mut layer_uniform,
                             canvas_width,
                             canvas_height,
                         );
                         undo_manager.end_stroke(
                             device.as_ref(),
                             queue.as_ref(),
-                            layers.texture(),
+                            &mut layers,
+                            &mut layer_masks,
                         );
-                        if let Some(entry) = layer_uniform.get_mut(active_layer_index) {
-                            *entry = None;
-                        }
                         needs_render = true;
                     }
                 }
@@ -1697,6 +2258,7 @@ fn render_thread_main(
                                         brush_settings,
                                         use_hollow_mask: false,
                                         use_hollow_base: false,
+                                        alpha_locked,
                                     });
                                 }
                             }
@@ -1707,9 +2269,9 @@ fn render_thread_main(
                                 &state.brush_settings,
                                 &state.points,
                                 state.layer_index,
-                                &layers,
+                                &mut layers,
+                                &mut layer_masks,
                                 &mut undo_manager,
-                                &mut layer_uniform,
                                 &device,
                                 &queue,
                                 canvas_width,
@@ -1754,13 +2316,40 @@ fn render_thread_main(
                         continue;
                     }
                 };
-
-                let active_layer_view = layers
-                    .layer_view(active_layer_index)
-                    .or_else(|| layers.layer_view(0))
-                    .expect("layers non-empty");
-                let layer_texture = layers.texture();
+                brush_ref.set_alpha_lock(alpha_locked);
+
+                // While a mask is being edited the same stroke path paints
+                // into the mask texture instead of the layer.
+                let (brush_settings, undo_target, slot) = if mask_editing {
+                    (
+                        mask_stroke_settings(brush_settings),
+                        UndoTarget::Mask,
+                        layer_masks.checkout(
+                            device.as_ref(),
+                            queue.as_ref(),
+                            active_layer_index,
+                        ),
+                    )
+                } else {
+                    (
+                        brush_settings,
+                        UndoTarget::Layer,
+                        layers.checkout(device.as_ref(), queue.as_ref(), active_layer_index),
+                    )
+                };
+                let slot = match slot {
+                    Ok(slot) => slot,
+                    Err(err) => {
+                        debug::log(
+                            LogLevel::Warn,
+                            format_args!("stroke layer checkout failed: {err}"),
+                        );
+                        stroke = StrokeResampler::new();
+                        continue;
+                    }
+                };
                 let mut segment: Vec<EnginePoint> = Vec::new();
+                let mut painted = Vec::new();
                 let mut drawn_any = false;
 
                 for p in raw_points {
@@ -1768,21 +2357,26 @@ fn render_thread_main(
                     let is_up = (p.flags & FLAG_UP) != 0;
 
                     if is_down {
-                        undo_manager.begin_stroke(active_layer_index as u32);
+                        undo_manager
+                            .begin_target_stroke(active_layer_index as u32, undo_target);
                         let use_hollow_mask = brush_settings.hollow_enabled
                             && !brush_settings.erase
                             && brush_settings.hollow_ratio > 0.0001;
-                        if use_hollow_mask {
+                        let watercolor = brush_settings.watercolor();
+                        if use_hollow_mask || watercolor {
                             if let Err(err) = brush_ref.clear_stroke_mask() {
                                 debug::log(
                                     LogLevel::Warn,
                                     format_args!("Brush stroke mask clear failed: {err}"),
                                 );
                             }
+                        }
+                        if use_hollow_mask || brush_settings.mixing() || watercolor {
                             brush_ref.begin_stroke_base_capture();
                         }
                     } else {
-                        undo_manager.begin_stroke_if_needed(active_layer_index as u32);
+                        undo_manager
+                            .begin_stroke_if_needed(active_layer_index as u32, undo_target);
                     }
 
                     segment.push(p);
@@ -1794,13 +2388,18 @@ fn render_thread_main(
                             && brush_settings.hollow_ratio > 0.0001;
                         let use_hollow_base =
                             use_hollow_mask && !brush_settings.hollow_erase_occluded;
+                        let capture_base = use_hollow_base
+                            || brush_settings.mixing()
+                            || brush_settings.watercolor();
                         let mut defer_end_stroke = false;
+                        let (active_layer_view, layer_texture) =
+                            stroke_surface(&layers, &layer_masks, mask_editing);
                         let segment_drawn = {
                             let mut before_draw =
                                 |brush: &mut BrushRenderer, dirty_rect| {
                                     if let Err(err) = brush.prepare_layer_read(
                                         layer_texture,
-                                        layer_idx,
+                                        slot,
                                         dirty_rect,
                                     ) {
                                         debug::log(
@@ -1815,13 +2414,15 @@ fn render_thread_main(
                                         queue.as_ref(),
                                         layer_texture,
                                         layer_idx,
+                                        slot,
                                         dirty_rect,
                                     );
-                                    if use_hollow_base {
+                                    painted.push(dirty_rect);
+                                    if capture_base {
                                         if let Err(err) =
                                             brush.capture_stroke_base_region(
                                                 layer_texture,
-                                                layer_idx,
+                                                slot,
                                                 dirty_rect,
                                             )
                                         {
@@ -1850,7 +2451,7 @@ fn render_thread_main(
                                     device.as_ref(),
                                     queue.as_ref(),
                                     layer_texture,
-                                    layer_idx,
+                                    slot,
                                     canvas_width,
                                     canvas_height,
                                     dirty,
@@ -1913,16 +2514,31 @@ fn render_thread_main(
                                         brush_settings,
                                         use_hollow_mask,
                                         use_hollow_base,
+                                        alpha_locked,
                                     });
                                     defer_end_stroke = true;
                                 }
                             }
                         }
                         if !defer_end_stroke {
+                            painted.extend(finish_wet_stroke(
+                                brush_ref,
+                                &brush_settings,
+                                WetStrokeLayer {
+                                    view: active_layer_view,
+                                    texture: layer_texture,
+                                    index: layer_idx,
+                                    slice: slot,
+                                },
+                                &mut undo_manager,
+                                device.as_ref(),
+                                queue.as_ref(),
+                            ));
                             undo_manager.end_stroke(
                                 device.as_ref(),
                                 queue.as_ref(),
-                                layer_texture,
+                                &mut layers,
+                                &mut layer_masks,
                             );
                         }
                     }
@@ -1930,13 +2546,16 @@ fn render_thread_main(
 
                 if !segment.is_empty() {
                     let layer_idx = active_layer_index as u32;
-                    let use_hollow_base = brush_settings.hollow_enabled
+                    let capture_base = (brush_settings.hollow_enabled
                         && !brush_settings.erase
                         && brush_settings.hollow_ratio > 0.0001
-                        && !brush_settings.hollow_erase_occluded;
+                        && !brush_settings.hollow_erase_occluded)
+                        || brush_settings.mixing()
+                        || brush_settings.watercolor();
+                    let (active_layer_view, layer_texture) =
+                        stroke_surface(&layers, &layer_masks, mask_editing);
                     let mut before_draw = |brush: &mut BrushRenderer, dirty_rect| {
-                        if let Err(err) =
-                            brush.prepare_layer_read(layer_texture, layer_idx, dirty_rect)
+                        if let Err(err) = brush.prepare_layer_read(layer_texture, slot, dirty_rect)
                         {
                             debug::log(
                                 LogLevel::Warn,
@@ -1950,12 +2569,14 @@ fn render_thread_main(
                             queue.as_ref(),
                             layer_texture,
                             layer_idx,
+                            slot,
                             dirty_rect,
                         );
-                        if use_hollow_base {
+                        painted.push(dirty_rect);
+                        if capture_base {
                             if let Err(err) = brush.capture_stroke_base_region(
                                 layer_texture,
-                                layer_idx,
+                                slot,
                                 dirty_rect,
                             ) {
                                 debug::log(
@@ -1982,7 +2603,7 @@ fn render_thread_main(
                                 device.as_ref(),
                                 queue.as_ref(),
                                 layer_texture,
-                                layer_idx,
+                                slot,
                                 canvas_width,
                                 canvas_height,
                                 dirty,
@@ -1994,9 +2615,11 @@ fn render_thread_main(
                     drawn_any |= segment_drawn;
                 }
 
-                if drawn_any {
-                    if let Some(entry) = layer_uniform.get_mut(active_layer_index) {
-                        *entry = None;
+                for rect in painted {
+                    if mask_editing {
+                        layer_masks.mark_dirty(active_layer_index, rect);
+                    } else {
+                        layers.mark_dirty(active_layer_index, rect);
                     }
                 }
                 needs_render |= drawn_any;
@@ -2054,9 +2677,9 @@ fn render_thread_main(
                                 &state.brush_settings,
                                 &animation.to_points,
                                 state.layer_index,
-                                &layers,
+                                &mut layers,
+                                &mut layer_masks,
                                 &mut undo_manager,
-                                &mut layer_uniform,
                                 &device,
                                 &queue,
                                 canvas_width,
@@ -2076,9 +2699,9 @@ fn render_thread_main(
                         &mut brush,
                         &device,
                         &queue,
-                        &layers,
+                        &mut layers,
+                        &mut layer_masks,
                         &mut undo_manager,
-                        &mut layer_uniform,
                         canvas_width,
                         canvas_height,
                     );
@@ -2089,7 +2712,8 @@ fn render_thread_main(
                         undo_manager.end_stroke(
                             device.as_ref(),
                             queue.as_ref(),
-                            layers.texture(),
+                            &mut layers,
+                            &mut layer_masks,
                         );
                         clear_animation = true;
                     }
@@ -2100,58 +2724,73 @@ fn render_thread_main(
             streamline_animation = None;
         }
 
-        if !deferred_reads.is_empty()
-            && input_queue_len.load(Ordering::Relaxed) == 0
-        {
-            let pending = std::mem::take(&mut deferred_reads);
-            for cmd in pending {
-                let outcome = handle_engine_command(
-                    &device,
-                    &queue,
-                    &mut present,
-                    cmd,
-                    &mut bucket_fill_renderer,
-                    &mut filter_renderer,
-                    &mut layers,
-                    &mut layer_count,
-                    &mut active_layer_index,
-                    &mut layer_opacity,
-                    &mut layer_visible,
-                    &mut layer_clipping_mask,
-                    &mut layer_blend_mode,
-                    &mut layer_uniform,
-                    &mut view_flags,
-                    &present_renderer,
-                    &present_config_buffer,
-                    &present_transform_buffer,
-                    &mut transform_matrix,
-                    &mut transform_layer_index,
-                    &mut transform_flags,
-                    &mut present_params_buffer,
-                    &mut present_params_capacity,
-                    &mut present_bind_group,
-                    &mut transform_renderer,
-                    &mut brush,
-                    &mut brush_settings,
-                    &mut stroke,
-                    &mut selection_mask_active,
-                    &mut spray_active_layer,
-                    &mut undo_manager,
-                    canvas_width,
-                    canvas_height,
-                );
-                if outcome.stop {
-                    return;
-                }
-                if let Some((new_width, new_height)) = outcome.new_canvas_size {
-                    canvas_width = new_width;
-                    canvas_height = new_height;
-                    stroke = StrokeResampler::new();
-                }
-                needs_render |= outcome.needs_render;
+        for cmd in deferred_reads.take_ready() {
+            if let Some(journal) = journal.as_mut() {
+                journal.record_command(&cmd);
+            }
+            let (cmd, pending_operation) = event_publisher.watch_operation(cmd);
+            let outcome = handle_engine_command(
+                &device,
+                &queue,
+                &mut present,
+                cmd,
+                &mut bucket_fill_renderer,
+                &mut filter_renderer,
+                &mut layers,
+                &mut layer_count,
+                &mut active_layer_index,
+                &mut layer_opacity,
+                &mut layer_visible,
+                &mut layer_clipping_mask,
+                &mut layer_alpha_lock,
+                &mut layer_blend_mode,
+                &mut layer_groups,
+                &mut layer_masks,
+                &mut layer_adjustments,
+                &mut view_flags,
+                &present_renderer,
+                &present_config_buffer,
+                &present_transform_buffer,
+                &mut transform_matrix,
+                &mut transform_layer_index,
+                &mut transform_flags,
+                &mut present_params_buffer,
+                &mut present_params_capacity,
+                &mut present_groups_buffer,
+                &mut present_bind_group,
+                &mut transform_renderer,
+                &mut alpha_lock_renderer,
+                &mut brush,
+                &mut brush_settings,
+                &mut stroke,
+                &mut selection_mask_active,
+                &mut spray_active_layer,
+                &mut undo_manager,
+                canvas_width,
+                canvas_height,
+            );
+            event_publisher.finish_operation(pending_operation);
+            if outcome.stop {
+                return;
+            }
+            if let Some((new_width, new_height)) = outcome.new_canvas_size {
+                canvas_width = new_width;
+                canvas_height = new_height;
+                stroke = StrokeResampler::new();
             }
+            needs_render |= outcome.needs_render;
         }
 
+        event_publisher.publish_undo_state(undo_manager.can_undo(), undo_manager.can_redo());
+        event_publisher.publish_layers_changed(
+            undo_manager.take_layers_changed(),
+            canvas_width,
+            canvas_height,
+        );
+        event_publisher.publish_dirty(undo_manager.take_dirty(), |layer_index| {
+            layers.occupied_rect(layer_index as usize)
+        });
+
         if needs_render {
             if !pending_present {
                 pending_present_since = Some(Instant::now());
@@ -2206,58 +2845,106 @@ fn render_thread_main(
                             ),
                         );
                     }
-                    if let Some(state) = preview_state.as_ref() {
+                    // The compositor reads layers through the atlas and masks
+                    // through their slices, so pending writes go back first; a
+                    // grown atlas or mask array needs a new bind group.
+                    if let Err(err) = layers.flush(device.as_ref(), queue.as_ref()) {
+                        debug::log(LogLevel::Warn, format_args!("Layer flush failed: {err}"));
+                    }
+                    if let Err(err) = layer_masks.flush(device.as_ref(), queue.as_ref()) {
+                        debug::log(
+                            LogLevel::Warn,
+                            format_args!("Layer mask flush failed: {err}"),
+                        );
+                    }
+                    if layers.take_bindings_changed() | layer_masks.take_bindings_changed() {
+                        present_bind_group = present_renderer.create_bind_group(
+                            device.as_ref(),
+                            &layers,
+                            &present_config_buffer,
+                            &present_params_buffer,
+                            &present_transform_buffer,
+                            &present_groups_buffer,
+                            &layer_masks,
+                        );
+                    }
+                    let assist_overlay = build_assist_overlay(
+                        &brush_settings.assist,
+                        canvas_width,
+                        canvas_height,
+                        view_flags,
+                    );
+                    if preview_state.is_some() || assist_overlay.is_some() {
                         present_renderer.render_base(
                             device.as_ref(),
                             queue.as_ref(),
                             &present_bind_group,
                             target.render_view(),
                         );
-                        let layer_idx = state.layer_index as usize;
-                        let layer_visible_value =
-                            layer_visible.get(layer_idx).copied().unwrap_or(true);
-                        let layer_opacity_value =
-                            layer_opacity.get(layer_idx).copied().unwrap_or(1.0);
-                        if layer_visible_value && layer_opacity_value > 0.0001 {
-                            let preview_points: &[(
-                                Point2D,
-                                f32,
-                            )] = if let Some(animation) = streamline_animation.as_ref() {
-                                if animation.scratch.is_empty() {
-                                    state.points.as_slice()
+                        if let Some(state) = preview_state.as_ref() {
+                            let layer_idx = state.layer_index as usize;
+                            let layer_visible_value =
+                                layer_visible.get(layer_idx).copied().unwrap_or(true);
+                            let layer_opacity_value =
+                                layer_opacity.get(layer_idx).copied().unwrap_or(1.0);
+                            if layer_visible_value && layer_opacity_value > 0.0001 {
+                                let preview_points: &[(
+                                    Point2D,
+                                    PenState,
+                                )] = if let Some(animation) = streamline_animation.as_ref() {
+                                    if animation.scratch.is_empty() {
+                                        state.points.as_slice()
+                                    } else {
+                                        animation.scratch.as_slice()
+                                    }
                                 } else {
-                                    animation.scratch.as_slice()
-                                }
-                            } else {
-                                state.points.as_slice()
-                            };
-                            if !preview_points.is_empty() {
-                                let segments = build_preview_segments(
-                                    preview_points,
-                                    &state.brush_settings,
-                                );
-                                if !segments.is_empty() {
-                                    let preview = preview_renderer.get_or_insert_with(
-                                        || PreviewRenderer::new(device.as_ref()),
-                                    );
-                                    let config = build_preview_config(
-                                        &state.brush_settings,
-                                        canvas_width,
-                                        canvas_height,
-                                        view_flags,
-                                        layer_opacity_value,
-                                    );
-                                    preview.render(
-                                        device.as_ref(),
-                                        queue.as_ref(),
-                                        target.render_view(),
-                                        config,
-                                        &segments,
-                                        state.use_accumulate,
-                                    );
+                                    state.points.as_slice()
+                                };
+                                if !preview_points.is_empty() {
+                                    let segments: Vec<PreviewSegment> = state
+                                        .brush_settings
+                                        .symmetry
+                                        .apply(preview_points)
+                                        .iter()
+                                        .flat_map(|copy| {
+                                            build_preview_segments(copy, &state.brush_settings)
+                                        })
+                                        .collect();
+                                    if !segments.is_empty() {
+                                        let preview = preview_renderer.get_or_insert_with(
+                                            || PreviewRenderer::new(device.as_ref()),
+                                        );
+                                        let config = build_preview_config(
+                                            &state.brush_settings,
+                                            canvas_width,
+                                            canvas_height,
+                                            view_flags,
+                                            layer_opacity_value,
+                                        );
+                                        preview.render(
+                                            device.as_ref(),
+                                            queue.as_ref(),
+                                            target.render_view(),
+                                            config,
+                                            &segments,
+                                            state.use_accumulate,
+                                        );
+                                    }
                                 }
                             }
                         }
+                        if let Some((config, segments)) = assist_overlay {
+                            let preview = preview_renderer
+                                .get_or_insert_with(|| PreviewRenderer::new(device.as_ref()));
+                            preview.render(
+                                device.as_ref(),
+                                queue.as_ref(),
+                                target.render_view(),
+                                config,
+                                &segments,
+                                true,
+                            );
+                        }
                         if target.shared_texture().is_some() {
                             let mut encoder = device.create_command_encoder(
                                 &wgpu::CommandEncoderDescriptor {
@@ -2299,14 +2986,17 @@ fn handle_engine_command(
     cmd: EngineCommand,
     bucket_fill_renderer: &mut Option<BucketFillRenderer>,
     filter_renderer: &mut Option<FilterRenderer>,
-    layers: &mut LayerTextures,
+    layers: &mut LayerStore,
     layer_count: &mut usize,
     active_layer_index: &mut usize,
     layer_opacity: &mut Vec<f32>,
     layer_visible: &mut Vec<bool>,
     layer_clipping_mask: &mut Vec<bool>,
+    layer_alpha_lock: &mut Vec<bool>,
     layer_blend_mode: &mut Vec<u32>,
-    layer_uniform: &mut Vec<Option<u32>>,
+    layer_groups: &mut LayerGroups,
+    layer_masks: &mut LayerMasks,
+    layer_adjustments: &mut LayerAdjustments,
     present_view_flags: &mut u32,
     present_renderer: &PresentRenderer,
     present_config_buffer: &wgpu::Buffer,
@@ -2316,8 +3006,10 @@ fn handle_engine_command(
     transform_flags: &mut u32,
     present_params_buffer: &mut wgpu::Buffer,
     present_params_capacity: &mut usize,
+    present_groups_buffer: &mut wgpu::Buffer,
     present_bind_group: &mut wgpu::BindGroup,
     transform_renderer: &mut Option<LayerTransformRenderer>,
+    alpha_lock_renderer: &mut Option<AlphaLockRenderer>,
     brush: &mut Option<BrushRenderer>,
     brush_settings: &mut EngineBrushSettings,
     stroke: &mut StrokeResampler,
@@ -2337,7 +3029,7 @@ fn handle_engine_command(
         }
         let old_count = *layer_count;
         let new_count = idx + 1;
-        let resized = match layers.ensure_capacity(device, queue, new_count) {
+        let resized = match layers.ensure_capacity(device, new_count) {
             Ok(Some(_)) => true,
             Ok(None) => false,
             Err(err) => {
@@ -2350,15 +3042,23 @@ fn handle_engine_command(
         };
         if resized {
             *present_params_capacity = layers.capacity();
-            match create_present_params_buffer(device, *present_params_capacity) {
-                Ok(buffer) => {
-                    *present_params_buffer = buffer;
+            let buffers = create_present_params_buffer(device, *present_params_capacity)
+                .and_then(|params| {
+                    create_present_groups_buffer(device, *present_params_capacity)
+                        .map(|groups| (params, groups))
+                });
+            match buffers {
+                Ok((params, groups)) => {
+                    *present_params_buffer = params;
+                    *present_groups_buffer = groups;
                     *present_bind_group = present_renderer.create_bind_group(
                         device,
-                        layers.array_view(),
+                        layers,
                         present_config_buffer,
                         present_params_buffer,
                         present_transform_buffer,
+                        present_groups_buffer,
+                        layer_masks,
                     );
                 }
                 Err(err) => {
@@ -2380,23 +3080,18 @@ fn handle_engine_command(
         if new_count > layer_clipping_mask.len() {
             layer_clipping_mask.resize(new_count, false);
         }
+        if new_count > layer_alpha_lock.len() {
+            layer_alpha_lock.resize(new_count, false);
+        }
         if new_count > layer_blend_mode.len() {
             layer_blend_mode.resize(new_count, 0);
         }
-        if new_count > layer_uniform.len() {
-            layer_uniform.resize(new_count, None);
-        }
+        layer_groups.resize_layers(new_count);
+        layer_masks.resize_layers(new_count);
+        layer_adjustments.resize_layers(new_count);
 
-        for layer in old_count..new_count {
-            fill_r32uint_texture(
-                queue,
-                layers.texture(),
-                canvas_width,
-                canvas_height,
-                layer as u32,
-                0x00000000,
-            );
-            layer_uniform[layer] = Some(0x00000000);
+        for _ in old_count..new_count {
+            layers.push_layer(0x00000000);
         }
 
         *layer_count = new_count;
@@ -2404,6 +3099,7 @@ fn handle_engine_command(
             queue,
             present_config_buffer,
             present_params_buffer,
+            present_groups_buffer,
             *layer_count,
             *present_view_flags,
             transform_layer_index,
@@ -2412,6 +3108,9 @@ fn handle_engine_command(
             layer_visible,
             layer_clipping_mask,
             layer_blend_mode,
+            &layer_masks.enabled_flags(),
+            &layer_adjustments.composite(*layer_count),
+            &layer_groups.spans(*layer_count),
         );
         true
     };
@@ -2430,7 +3129,10 @@ fn handle_engine_command(
             height,
             bytes_per_row,
         } => {
-            if mtl_texture_ptr == 0 || width == 0 || height == 0 {
+            // Linux presents into an engine-owned offscreen texture, so no
+            // external handle is required there.
+            let requires_external = !cfg!(target_os = "linux");
+            if (requires_external && mtl_texture_ptr == 0) || width == 0 || height == 0 {
                 debug::log(
                     LogLevel::Warn,
                     format_args!(
@@ -2453,23 +3155,6 @@ fn handle_engine_command(
                         "Present texture attached: ptr=0x{mtl_texture_ptr:x} size={width}x{height} bytes_per_row={bytes_per_row}"
                     ),
                 );
-                // Initialize layers so the first composite is deterministic.
-                // Layer 0 is the background fill layer (default white), others start transparent.
-                layer_uniform.resize(*layer_count, None);
-                for idx in 0..*layer_count {
-                    let fill = if idx == 0 { 0xFFFFFFFF } else { 0x00000000 };
-                    fill_r32uint_texture(
-                        queue,
-                        layers.texture(),
-                        canvas_width,
-                        canvas_height,
-                        idx as u32,
-                        fill,
-                    );
-                    if let Some(entry) = layer_uniform.get_mut(idx) {
-                        *entry = Some(fill);
-                    }
-                }
                 // Request one render so Flutter gets an actual composited frame immediately.
                 return EngineCommandOutcome {
                     stop: false,
@@ -2525,23 +3210,6 @@ fn handle_engine_command(
                                 ),
                             );
                         }
-                        // Initialize layers so the first composite is deterministic.
-                        // Layer 0 is the background fill layer (default white), others start transparent.
-                        layer_uniform.resize(*layer_count, None);
-                        for idx in 0..*layer_count {
-                            let fill = if idx == 0 { 0xFFFFFFFF } else { 0x00000000 };
-                            fill_r32uint_texture(
-                                queue,
-                                layers.texture(),
-                                canvas_width,
-                                canvas_height,
-                                idx as u32,
-                                fill,
-                            );
-                            if let Some(entry) = layer_uniform.get_mut(idx) {
-                                *entry = Some(fill);
-                            }
-                        }
                         let _ = reply.send(handle);
                         return EngineCommandOutcome {
                             stop: false,
@@ -2576,26 +3244,35 @@ fn handle_engine_command(
         } => {
             // Reset undo history so a fresh canvas doesn't "undo" back into the previous one.
             undo.reset();
+            layer_groups.reset(*layer_count);
+            layer_masks.reset(*layer_count);
+            layer_adjustments.reset(*layer_count);
             // Layer 0 is background fill; everything above starts transparent.
-            layer_uniform.resize(*layer_count, None);
             for idx in 0..*layer_count {
                 let fill = if idx == 0 {
                     background_color_argb
                 } else {
                     0x00000000
                 };
-                fill_r32uint_texture(
-                    queue,
-                    layers.texture(),
-                    canvas_width,
-                    canvas_height,
-                    idx as u32,
-                    fill,
-                );
-                if let Some(entry) = layer_uniform.get_mut(idx) {
-                    *entry = Some(fill);
-                }
+                layers.fill(device, queue, idx, fill);
             }
+            write_present_config(
+                queue,
+                present_config_buffer,
+                present_params_buffer,
+                present_groups_buffer,
+                *layer_count,
+                *present_view_flags,
+                *transform_layer_index,
+                *transform_flags,
+                layer_opacity,
+                layer_visible,
+                layer_clipping_mask,
+                layer_blend_mode,
+                &layer_masks.enabled_flags(),
+                &layer_adjustments.composite(*layer_count),
+                &layer_groups.spans(*layer_count),
+            );
             return EngineCommandOutcome {
                 stop: false,
                 needs_render: present.is_some(),
@@ -2607,59 +3284,62 @@ fn handle_engine_command(
             background_color_argb,
         } => {
             let target_count: usize = (requested_count.max(1)) as usize;
-            if target_count > *layer_count {
-                let idx = target_count - 1;
-                if !ensure_layer_index(layer_count, idx, *transform_layer_index, *transform_flags) {
+            let mut stack = EngineLayerStack {
+                layers,
+                layer_masks,
+                layer_count,
+                active_layer_index,
+                transform_layer_index,
+                layer_opacity,
+                layer_visible,
+                layer_clipping_mask,
+                layer_alpha_lock,
+                layer_blend_mode,
+                layer_groups,
+                layer_adjustments,
+                undo,
+                canvas_width,
+                canvas_height,
+            };
+            let snapshot = EngineCanvasSnapshot::blank(
+                device.as_ref(),
+                (canvas_width, canvas_height),
+                target_count,
+                background_color_argb,
+                &stack,
+            );
+            let mut snapshot = match snapshot {
+                Ok(snapshot) => snapshot,
+                Err(err) => {
+                    debug::log(
+                        LogLevel::Warn,
+                        format_args!("ResetCanvasWithLayers: {err}"),
+                    );
                     return EngineCommandOutcome {
                         stop: false,
                         needs_render: false,
                         new_canvas_size: None,
                     };
                 }
-            }
-            *layer_count = target_count;
-            layer_opacity.resize(target_count, 1.0);
-            layer_visible.resize(target_count, true);
-            layer_clipping_mask.resize(target_count, false);
-            layer_blend_mode.resize(target_count, 0);
-            layer_uniform.resize(target_count, None);
-
-            for idx in 0..target_count {
-                let fill = if idx == 0 {
-                    background_color_argb
-                } else {
-                    0x00000000
-                };
-                fill_r32uint_texture(
-                    queue,
-                    layers.texture(),
-                    canvas_width,
-                    canvas_height,
-                    idx as u32,
-                    fill,
-                );
-                if let Some(entry) = layer_uniform.get_mut(idx) {
-                    *entry = Some(fill);
-                }
-            }
-
-            *active_layer_index = (*active_layer_index).min(target_count.saturating_sub(1));
-            *transform_layer_index = 0;
-            *transform_flags = 0;
-            write_present_config(
+            };
+            stack.swap_canvas(
+                device,
                 queue,
-                present_config_buffer,
-                present_params_buffer,
-                *layer_count,
-                *present_view_flags,
-                *transform_layer_index,
-                *transform_flags,
-                layer_opacity,
-                layer_visible,
-                layer_clipping_mask,
-                layer_blend_mode,
+                &mut snapshot,
+                EnginePresentBindings {
+                    renderer: present_renderer,
+                    config_buffer: present_config_buffer,
+                    transform_buffer: present_transform_buffer,
+                    params_buffer: present_params_buffer,
+                    params_capacity: present_params_capacity,
+                    groups_buffer: present_groups_buffer,
+                    bind_group: present_bind_group,
+                    transform_matrix,
+                    transform_flags,
+                    view_flags: *present_view_flags,
+                },
             );
-            undo.reset();
+            undo.record_edit(device, queue, LayerEdit::Canvas(Box::new(snapshot)));
             return EngineCommandOutcome {
                 stop: false,
                 needs_render: present.is_some(),
@@ -2676,101 +3356,61 @@ fn handle_engine_command(
             let new_width = width.max(1);
             let new_height = height.max(1);
             let target_layer_count = (requested_count.max(1)) as usize;
-            let target_capacity = layers.capacity().max(target_layer_count).max(1);
-            let new_layers =
-                match LayerTextures::new(device.as_ref(), new_width, new_height, target_capacity) {
-                    Ok(layers) => layers,
-                    Err(err) => {
-                        let _ = reply.send(false);
-                        debug::log(
-                            LogLevel::Warn,
-                            format_args!("ResizeCanvas: layer init failed: {err}"),
-                        );
-                        return EngineCommandOutcome {
-                            stop: false,
-                            needs_render: false,
-                            new_canvas_size: None,
-                        };
-                    }
-                };
-            let new_params_capacity = new_layers.capacity();
-            let new_params_buffer =
-                match create_present_params_buffer(device.as_ref(), new_params_capacity) {
-                    Ok(buffer) => buffer,
-                    Err(err) => {
-                        let _ = reply.send(false);
-                        debug::log(
-                            LogLevel::Warn,
-                            format_args!("ResizeCanvas: present params buffer init failed: {err}"),
-                        );
-                        return EngineCommandOutcome {
-                            stop: false,
-                            needs_render: false,
-                            new_canvas_size: None,
-                        };
-                    }
-                };
-
-            layer_opacity.resize(target_layer_count, 1.0);
-            layer_visible.resize(target_layer_count, true);
-            layer_clipping_mask.resize(target_layer_count, false);
-            layer_blend_mode.resize(target_layer_count, 0);
-            layer_uniform.resize(target_layer_count, None);
-
-            for idx in 0..target_layer_count {
-                let fill = if idx == 0 {
-                    background_color_argb
-                } else {
-                    0x00000000
-                };
-                fill_r32uint_texture(
-                    queue,
-                    new_layers.texture(),
-                    new_width,
-                    new_height,
-                    idx as u32,
-                    fill,
-                );
-                if let Some(entry) = layer_uniform.get_mut(idx) {
-                    *entry = Some(fill);
-                }
-            }
-
-            *layer_count = target_layer_count;
-            *present_params_capacity = new_layers.capacity();
-            *present_params_buffer = new_params_buffer;
-            *present_bind_group = present_renderer.create_bind_group(
-                device,
-                new_layers.array_view(),
-                present_config_buffer,
-                present_params_buffer,
-                present_transform_buffer,
-            );
-            *layers = new_layers;
-            *active_layer_index = (*active_layer_index).min(target_layer_count.saturating_sub(1));
-            *transform_layer_index = 0;
-            *transform_flags = 0;
-            *transform_matrix = [
-                1.0, 0.0, 0.0, 0.0, //
-                0.0, 1.0, 0.0, 0.0, //
-                0.0, 0.0, 1.0, 0.0, //
-                0.0, 0.0, 0.0, 1.0, //
-            ];
-            write_present_transform(queue.as_ref(), present_transform_buffer, *transform_matrix);
-            write_present_config(
-                queue,
-                present_config_buffer,
-                present_params_buffer,
-                *layer_count,
-                *present_view_flags,
-                *transform_layer_index,
-                *transform_flags,
+            let mut stack = EngineLayerStack {
+                layers,
+                layer_masks,
+                layer_count,
+                active_layer_index,
+                transform_layer_index,
                 layer_opacity,
                 layer_visible,
                 layer_clipping_mask,
+                layer_alpha_lock,
                 layer_blend_mode,
+                layer_groups,
+                layer_adjustments,
+                undo,
+                canvas_width,
+                canvas_height,
+            };
+            let snapshot = EngineCanvasSnapshot::blank(
+                device.as_ref(),
+                (new_width, new_height),
+                target_layer_count,
+                background_color_argb,
+                &stack,
+            );
+            let mut snapshot = match snapshot {
+                Ok(snapshot) => snapshot,
+                Err(err) => {
+                    let _ = reply.send(false);
+                    debug::log(LogLevel::Warn, format_args!("ResizeCanvas: {err}"));
+                    return EngineCommandOutcome {
+                        stop: false,
+                        needs_render: false,
+                        new_canvas_size: None,
+                    };
+                }
+            };
+            stack.swap_canvas(
+                device,
+                queue,
+                &mut snapshot,
+                EnginePresentBindings {
+                    renderer: present_renderer,
+                    config_buffer: present_config_buffer,
+                    transform_buffer: present_transform_buffer,
+                    params_buffer: present_params_buffer,
+                    params_capacity: present_params_capacity,
+                    groups_buffer: present_groups_buffer,
+                    bind_group: present_bind_group,
+                    transform_matrix,
+                    transform_flags,
+                    view_flags: *present_view_flags,
+                },
             );
-            *undo = UndoManager::new(new_width, new_height);
+            undo.record_edit(device, queue, LayerEdit::Canvas(Box::new(snapshot)));
+            // Dart attaches a present target of the new size.
             *present = None;
             *bucket_fill_renderer = None;
             *transform_renderer = None;
@@ -2787,18 +3427,28 @@ fn handle_engine_command(
             color_argb,
         } => {
             let idx = layer_index as usize;
-            if ensure_layer_index(layer_count, idx, *transform_layer_index, *transform_flags) {
-                fill_r32uint_texture(
+            let slot = if ensure_layer_index(
+                layer_count,
+                idx,
+                *transform_layer_index,
+                *transform_flags,
+            ) {
+                checkout_layer(device, queue, layers, idx)
+            } else {
+                None
+            };
+            if let Some(slot) = slot {
+                undo.begin_stroke(layer_index);
+                undo.capture_before_for_dirty_rect(
+                    device,
                     queue,
                     layers.texture(),
-                    canvas_width,
-                    canvas_height,
-                    idx as u32,
-                    color_argb,
+                    layer_index,
+                    slot,
+                    (0, 0, canvas_width as i32, canvas_height as i32),
                 );
-                if let Some(entry) = layer_uniform.get_mut(idx) {
-                    *entry = Some(color_argb);
-                }
+                layers.fill(device, queue, idx, color_argb);
+                undo.end_stroke(device, queue, layers, layer_masks);
                 return EngineCommandOutcome {
                     stop: false,
                     needs_render: present.is_some(),
@@ -2808,18 +3458,28 @@ fn handle_engine_command(
         }
         EngineCommand::ClearLayer { layer_index } => {
             let idx = layer_index as usize;
-            if ensure_layer_index(layer_count, idx, *transform_layer_index, *transform_flags) {
-                fill_r32uint_texture(
+            let slot = if ensure_layer_index(
+                layer_count,
+                idx,
+                *transform_layer_index,
+                *transform_flags,
+            ) {
+                checkout_layer(device, queue, layers, idx)
+            } else {
+                None
+            };
+            if let Some(slot) = slot {
+                undo.begin_stroke(layer_index);
+                undo.capture_before_for_dirty_rect(
+                    device,
                     queue,
                     layers.texture(),
-                    canvas_width,
-                    canvas_height,
-                    idx as u32,
-                    0x00000000,
+                    layer_index,
+                    slot,
+                    (0, 0, canvas_width as i32, canvas_height as i32),
                 );
-                if let Some(entry) = layer_uniform.get_mut(idx) {
-                    *entry = Some(0x00000000);
-                }
+                layers.fill(device, queue, idx, 0x00000000);
+                undo.end_stroke(device, queue, layers, layer_masks);
                 return EngineCommandOutcome {
                     stop: false,
                     needs_render: present.is_some(),
@@ -2839,15 +3499,37 @@ fn handle_engine_command(
         } => {
             let idx = layer_index as usize;
             if ensure_layer_index(layer_count, idx, *transform_layer_index, *transform_flags) {
+                let before = layer_properties(
+                    idx,
+                    layer_opacity,
+                    layer_visible,
+                    layer_clipping_mask,
+                    layer_alpha_lock,
+                    layer_blend_mode,
+                    layer_masks,
+                );
                 layer_opacity[idx] = if opacity.is_finite() {
                     opacity.clamp(0.0, 1.0)
                 } else {
                     0.0
                 };
+                let after = layer_properties(
+                    idx,
+                    layer_opacity,
+                    layer_visible,
+                    layer_clipping_mask,
+                    layer_alpha_lock,
+                    layer_blend_mode,
+                    layer_masks,
+                );
+                if let (Some(before), Some(after)) = (before, after) {
+                    undo.record_layer_properties(layer_index, before, after);
+                }
                 write_present_config(
                     queue,
                     present_config_buffer,
                     present_params_buffer,
+                    present_groups_buffer,
                     *layer_count,
                     *present_view_flags,
                     *transform_layer_index,
@@ -2856,6 +3538,9 @@ fn handle_engine_command(
                     layer_visible,
                     layer_clipping_mask,
                     layer_blend_mode,
+                    &layer_masks.enabled_flags(),
+                    &layer_adjustments.composite(*layer_count),
+                    &layer_groups.spans(*layer_count),
                 );
                 return EngineCommandOutcome {
                     stop: false,
@@ -2870,11 +3555,33 @@ fn handle_engine_command(
         } => {
             let idx = layer_index as usize;
             if ensure_layer_index(layer_count, idx, *transform_layer_index, *transform_flags) {
+                let before = layer_properties(
+                    idx,
+                    layer_opacity,
+                    layer_visible,
+                    layer_clipping_mask,
+                    layer_alpha_lock,
+                    layer_blend_mode,
+                    layer_masks,
+                );
                 layer_visible[idx] = visible;
+                let after = layer_properties(
+                    idx,
+                    layer_opacity,
+                    layer_visible,
+                    layer_clipping_mask,
+                    layer_alpha_lock,
+                    layer_blend_mode,
+                    layer_masks,
+                );
+                if let (Some(before), Some(after)) = (before, after) {
+                    undo.record_layer_properties(layer_index, before, after);
+                }
                 write_present_config(
                     queue,
                     present_config_buffer,
                     present_params_buffer,
+                    present_groups_buffer,
                     *layer_count,
                     *present_view_flags,
                     *transform_layer_index,
@@ -2883,6 +3590,9 @@ fn handle_engine_command(
                     layer_visible,
                     layer_clipping_mask,
                     layer_blend_mode,
+                    &layer_masks.enabled_flags(),
+                    &layer_adjustments.composite(*layer_count),
+                    &layer_groups.spans(*layer_count),
                 );
                 return EngineCommandOutcome {
                     stop: false,
@@ -2897,11 +3607,33 @@ fn handle_engine_command(
         } => {
             let idx = layer_index as usize;
             if ensure_layer_index(layer_count, idx, *transform_layer_index, *transform_flags) {
+                let before = layer_properties(
+                    idx,
+                    layer_opacity,
+                    layer_visible,
+                    layer_clipping_mask,
+                    layer_alpha_lock,
+                    layer_blend_mode,
+                    layer_masks,
+                );
                 layer_clipping_mask[idx] = clipping_mask;
+                let after = layer_properties(
+                    idx,
+                    layer_opacity,
+                    layer_visible,
+                    layer_clipping_mask,
+                    layer_alpha_lock,
+                    layer_blend_mode,
+                    layer_masks,
+                );
+                if let (Some(before), Some(after)) = (before, after) {
+                    undo.record_layer_properties(layer_index, before, after);
+                }
                 write_present_config(
                     queue,
                     present_config_buffer,
                     present_params_buffer,
+                    present_groups_buffer,
                     *layer_count,
                     *present_view_flags,
                     *transform_layer_index,
@@ -2910,6 +3642,9 @@ fn handle_engine_command(
                     layer_visible,
                     layer_clipping_mask,
                     layer_blend_mode,
+                    &layer_masks.enabled_flags(),
+                    &layer_adjustments.composite(*layer_count),
+                    &layer_groups.spans(*layer_count),
                 );
                 return EngineCommandOutcome {
                     stop: false,
@@ -2918,17 +3653,296 @@ fn handle_engine_command(
                 };
             }
         }
+        EngineCommand::SetLayerAlphaLock {
+            layer_index,
+            locked,
+        } => {
+            let idx = layer_index as usize;
+            if ensure_layer_index(layer_count, idx, *transform_layer_index, *transform_flags) {
+                let before = layer_properties(
+                    idx,
+                    layer_opacity,
+                    layer_visible,
+                    layer_clipping_mask,
+                    layer_alpha_lock,
+                    layer_blend_mode,
+                    layer_masks,
+                );
+                layer_alpha_lock[idx] = locked;
+                let after = layer_properties(
+                    idx,
+                    layer_opacity,
+                    layer_visible,
+                    layer_clipping_mask,
+                    layer_alpha_lock,
+                    layer_blend_mode,
+                    layer_masks,
+                );
+                if let (Some(before), Some(after)) = (before, after) {
+                    undo.record_layer_properties(layer_index, before, after);
+                }
+            }
+        }
         EngineCommand::SetLayerBlendMode {
             layer_index,
             blend_mode_index,
         } => {
             let idx = layer_index as usize;
             if ensure_layer_index(layer_count, idx, *transform_layer_index, *transform_flags) {
+                let before = layer_properties(
+                    idx,
+                    layer_opacity,
+                    layer_visible,
+                    layer_clipping_mask,
+                    layer_alpha_lock,
+                    layer_blend_mode,
+                    layer_masks,
+                );
                 layer_blend_mode[idx] = map_canvas_blend_mode_index(blend_mode_index).as_u32();
+                let after = layer_properties(
+                    idx,
+                    layer_opacity,
+                    layer_visible,
+                    layer_clipping_mask,
+                    layer_alpha_lock,
+                    layer_blend_mode,
+                    layer_masks,
+                );
+                if let (Some(before), Some(after)) = (before, after) {
+                    undo.record_layer_properties(layer_index, before, after);
+                }
+                write_present_config(
+                    queue,
+                    present_config_buffer,
+                    present_params_buffer,
+                    present_groups_buffer,
+                    *layer_count,
+                    *present_view_flags,
+                    *transform_layer_index,
+                    *transform_flags,
+                    layer_opacity,
+                    layer_visible,
+                    layer_clipping_mask,
+                    layer_blend_mode,
+                    &layer_masks.enabled_flags(),
+                    &layer_adjustments.composite(*layer_count),
+                    &layer_groups.spans(*layer_count),
+                );
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: present.is_some(),
+                    new_canvas_size: None,
+                };
+            }
+        }
+        EngineCommand::ReorderLayer {
+            from_index,
+            to_index,
+        } => {
+            let len = *layer_count;
+            if len <= 1 {
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            }
+            let from = from_index as usize;
+            if from >= len {
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            }
+            let mut target = to_index as usize;
+            if target > len {
+                target = len;
+            }
+            if target > from {
+                target = target.saturating_sub(1);
+            }
+            if target >= len {
+                target = len.saturating_sub(1);
+            }
+            if target == from {
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            }
+
+            let mut stack = EngineLayerStack {
+                layers,
+                layer_masks,
+                layer_count,
+                active_layer_index,
+                transform_layer_index,
+                layer_opacity,
+                layer_visible,
+                layer_clipping_mask,
+                layer_alpha_lock,
+                layer_blend_mode,
+                layer_groups,
+                layer_adjustments,
+                undo,
+                canvas_width,
+                canvas_height,
+            };
+            stack.move_layer(from, target);
+            undo.record_edit(device, queue, LayerEdit::Reorder {
+                from: target as u32,
+                to: from as u32,
+            });
+
+            write_present_config(
+                queue,
+                present_config_buffer,
+                present_params_buffer,
+                present_groups_buffer,
+                *layer_count,
+                *present_view_flags,
+                *transform_layer_index,
+                *transform_flags,
+                layer_opacity,
+                layer_visible,
+                layer_clipping_mask,
+                layer_blend_mode,
+                &layer_masks.enabled_flags(),
+                &layer_adjustments.composite(*layer_count),
+                &layer_groups.spans(*layer_count),
+            );
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: present.is_some(),
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::InsertLayer { layer_index } => {
+            let top = *layer_count;
+            if !ensure_layer_index(layer_count, top, *transform_layer_index, *transform_flags) {
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            }
+            let idx = (layer_index as usize).min(top);
+            let mut stack = EngineLayerStack {
+                layers,
+                layer_masks,
+                layer_count,
+                active_layer_index,
+                transform_layer_index,
+                layer_opacity,
+                layer_visible,
+                layer_clipping_mask,
+                layer_alpha_lock,
+                layer_blend_mode,
+                layer_groups,
+                layer_adjustments,
+                undo,
+                canvas_width,
+                canvas_height,
+            };
+            stack.move_layer(top, idx);
+            undo.record_edit(device, queue, LayerEdit::Remove {
+                layer_index: idx as u32,
+            });
+            write_present_config(
+                queue,
+                present_config_buffer,
+                present_params_buffer,
+                present_groups_buffer,
+                *layer_count,
+                *present_view_flags,
+                *transform_layer_index,
+                *transform_flags,
+                layer_opacity,
+                layer_visible,
+                layer_clipping_mask,
+                layer_blend_mode,
+                &layer_masks.enabled_flags(),
+                &layer_adjustments.composite(*layer_count),
+                &layer_groups.spans(*layer_count),
+            );
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: present.is_some(),
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::RemoveLayer { layer_index } => {
+            let idx = layer_index as usize;
+            if *layer_count <= 1 || idx >= *layer_count {
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            }
+            let mut stack = EngineLayerStack {
+                layers,
+                layer_masks,
+                layer_count,
+                active_layer_index,
+                transform_layer_index,
+                layer_opacity,
+                layer_visible,
+                layer_clipping_mask,
+                layer_alpha_lock,
+                layer_blend_mode,
+                layer_groups,
+                layer_adjustments,
+                undo,
+                canvas_width,
+                canvas_height,
+            };
+            let removed = stack.take_layer(device, queue, idx);
+            undo.record_edit(device, queue, LayerEdit::Insert {
+                layer_index,
+                layer: Box::new(removed),
+            });
+            write_present_config(
+                queue,
+                present_config_buffer,
+                present_params_buffer,
+                present_groups_buffer,
+                *layer_count,
+                *present_view_flags,
+                *transform_layer_index,
+                *transform_flags,
+                layer_opacity,
+                layer_visible,
+                layer_clipping_mask,
+                layer_blend_mode,
+                &layer_masks.enabled_flags(),
+                &layer_adjustments.composite(*layer_count),
+                &layer_groups.spans(*layer_count),
+            );
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: present.is_some(),
+                new_canvas_size: None,
+            };
+        }
+        cmd @ (EngineCommand::CreateLayerGroup { .. }
+        | EngineCommand::DeleteLayerGroup { .. }
+        | EngineCommand::SetLayerGroupParent { .. }
+        | EngineCommand::MoveLayerToGroup { .. }
+        | EngineCommand::SetLayerGroupOpacity { .. }
+        | EngineCommand::SetLayerGroupVisible { .. }
+        | EngineCommand::SetLayerGroupBlendMode { .. }
+        | EngineCommand::SetLayerGroupPassThrough { .. }
+        | EngineCommand::SetLayerGroupCollapsed { .. }
+        | EngineCommand::GetLayerGroups { .. }) => {
+            if layer_groups.apply_command(cmd) {
                 write_present_config(
                     queue,
                     present_config_buffer,
                     present_params_buffer,
+                    present_groups_buffer,
                     *layer_count,
                     *present_view_flags,
                     *transform_layer_index,
@@ -2937,6 +3951,9 @@ fn handle_engine_command(
                     layer_visible,
                     layer_clipping_mask,
                     layer_blend_mode,
+                    &layer_masks.enabled_flags(),
+                    &layer_adjustments.composite(*layer_count),
+                    &layer_groups.spans(*layer_count),
                 );
                 return EngineCommandOutcome {
                     stop: false,
@@ -2945,73 +3962,379 @@ fn handle_engine_command(
                 };
             }
         }
-        EngineCommand::ReorderLayer {
-            from_index,
-            to_index,
+        cmd @ (EngineCommand::SetLayerAdjustment { .. }
+        | EngineCommand::ClearLayerAdjustment { .. }) => {
+            if layer_adjustments.apply_command(cmd) {
+                write_present_config(
+                    queue,
+                    present_config_buffer,
+                    present_params_buffer,
+                    present_groups_buffer,
+                    *layer_count,
+                    *present_view_flags,
+                    *transform_layer_index,
+                    *transform_flags,
+                    layer_opacity,
+                    layer_visible,
+                    layer_clipping_mask,
+                    layer_blend_mode,
+                    &layer_masks.enabled_flags(),
+                    &layer_adjustments.composite(*layer_count),
+                    &layer_groups.spans(*layer_count),
+                );
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: present.is_some(),
+                    new_canvas_size: None,
+                };
+            }
+        }
+        EngineCommand::CreateLayerMask { layer_index } => {
+            let idx = layer_index as usize;
+            let before = layer_masks.state(idx);
+            if idx >= *layer_count || before.present {
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            }
+            // A new mask reveals everything without texels of its own, so
+            // creating one only records the state change.
+            undo.begin_target_stroke(layer_index, UndoTarget::Mask);
+            undo.record_mask_change(layer_index, before, LayerMaskState::CREATED);
+            undo.end_stroke(device, queue, layers, layer_masks);
+            layer_masks.set_state(idx, LayerMaskState::CREATED);
+            write_present_config(
+                queue,
+                present_config_buffer,
+                present_params_buffer,
+                present_groups_buffer,
+                *layer_count,
+                *present_view_flags,
+                *transform_layer_index,
+                *transform_flags,
+                layer_opacity,
+                layer_visible,
+                layer_clipping_mask,
+                layer_blend_mode,
+                &layer_masks.enabled_flags(),
+                &layer_adjustments.composite(*layer_count),
+                &layer_groups.spans(*layer_count),
+            );
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: present.is_some(),
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::DeleteLayerMask { layer_index } => {
+            let idx = layer_index as usize;
+            let before = layer_masks.state(idx);
+            if idx >= *layer_count || !before.present {
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            }
+            undo.begin_target_stroke(layer_index, UndoTarget::Mask);
+            // Deleting frees the mask's texels, so undo keeps them.
+            if layer_masks.painted(idx) {
+                match layer_masks.checkout(device, queue, idx) {
+                    Ok(slot) => undo.capture_before_for_dirty_rect(
+                        device,
+                        queue,
+                        layer_masks.texture(),
+                        layer_index,
+                        slot,
+                        (0, 0, canvas_width as i32, canvas_height as i32),
+                    ),
+                    Err(err) => debug::log(
+                        LogLevel::Warn,
+                        format_args!("layer mask delete capture failed: {err}"),
+                    ),
+                }
+            }
+            undo.record_mask_change(layer_index, before, LayerMaskState::ABSENT);
+            undo.end_stroke(device, queue, layers, layer_masks);
+            layer_masks.set_state(idx, LayerMaskState::ABSENT);
+            write_present_config(
+                queue,
+                present_config_buffer,
+                present_params_buffer,
+                present_groups_buffer,
+                *layer_count,
+                *present_view_flags,
+                *transform_layer_index,
+                *transform_flags,
+                layer_opacity,
+                layer_visible,
+                layer_clipping_mask,
+                layer_blend_mode,
+                &layer_masks.enabled_flags(),
+                &layer_adjustments.composite(*layer_count),
+                &layer_groups.spans(*layer_count),
+            );
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: present.is_some(),
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::SetLayerMaskEnabled {
+            layer_index,
+            enabled,
+        } => {
+            let idx = layer_index as usize;
+            let state = layer_masks.state(idx);
+            if idx >= *layer_count || !state.present || state.enabled == enabled {
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            }
+            let before = layer_properties(
+                idx,
+                layer_opacity,
+                layer_visible,
+                layer_clipping_mask,
+                layer_alpha_lock,
+                layer_blend_mode,
+                layer_masks,
+            );
+            layer_masks.set_enabled(idx, enabled);
+            let after = layer_properties(
+                idx,
+                layer_opacity,
+                layer_visible,
+                layer_clipping_mask,
+                layer_alpha_lock,
+                layer_blend_mode,
+                layer_masks,
+            );
+            if let (Some(before), Some(after)) = (before, after) {
+                undo.record_layer_properties(layer_index, before, after);
+            }
+            write_present_config(
+                queue,
+                present_config_buffer,
+                present_params_buffer,
+                present_groups_buffer,
+                *layer_count,
+                *present_view_flags,
+                *transform_layer_index,
+                *transform_flags,
+                layer_opacity,
+                layer_visible,
+                layer_clipping_mask,
+                layer_blend_mode,
+                &layer_masks.enabled_flags(),
+                &layer_adjustments.composite(*layer_count),
+                &layer_groups.spans(*layer_count),
+            );
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: present.is_some(),
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::SetLayerMaskEditing { editing } => {
+            layer_masks.set_editing(editing);
+        }
+        EngineCommand::InvertLayerMask { layer_index } => {
+            let idx = layer_index as usize;
+            if idx >= *layer_count || !layer_masks.state(idx).present {
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            }
+            let full = (0, 0, canvas_width as i32, canvas_height as i32);
+            let result = layer_masks.checkout(device, queue, idx).and_then(|slot| {
+                let mut texels = read_r32uint_layer(
+                    device,
+                    queue,
+                    layer_masks.texture(),
+                    canvas_width,
+                    canvas_height,
+                    slot,
+                )?;
+                for texel in texels.iter_mut() {
+                    *texel = invert_mask_texel(*texel);
+                }
+                undo.begin_target_stroke(layer_index, UndoTarget::Mask);
+                undo.capture_before_for_dirty_rect(
+                    device,
+                    queue,
+                    layer_masks.texture(),
+                    layer_index,
+                    slot,
+                    full,
+                );
+                let written = write_r32uint_layer(
+                    queue,
+                    layer_masks.texture(),
+                    canvas_width,
+                    canvas_height,
+                    slot,
+                    &texels,
+                );
+                layer_masks.mark_dirty(idx, full);
+                undo.end_stroke(device, queue, layers, layer_masks);
+                written
+            });
+            if let Err(err) = result {
+                debug::log(LogLevel::Warn, format_args!("layer mask invert failed: {err}"));
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            }
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: present.is_some(),
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::WriteLayerMask {
+            layer_index,
+            coverage,
+            reply,
         } => {
-            let len = *layer_count;
-            if len <= 1 {
+            let idx = layer_index as usize;
+            let expected_len = (canvas_width as usize).saturating_mul(canvas_height as usize);
+            if idx >= *layer_count
+                || !layer_masks.state(idx).present
+                || coverage.len() != expected_len
+            {
+                let _ = reply.send(false);
                 return EngineCommandOutcome {
                     stop: false,
                     needs_render: false,
                     new_canvas_size: None,
                 };
             }
-            let from = from_index as usize;
-            if from >= len {
+            let full = (0, 0, canvas_width as i32, canvas_height as i32);
+            let texels: Vec<u32> = coverage.into_iter().map(mask_gray).collect();
+            let result = layer_masks.checkout(device, queue, idx).and_then(|slot| {
+                undo.begin_target_stroke(layer_index, UndoTarget::Mask);
+                undo.capture_before_for_dirty_rect(
+                    device,
+                    queue,
+                    layer_masks.texture(),
+                    layer_index,
+                    slot,
+                    full,
+                );
+                let written = write_r32uint_layer(
+                    queue,
+                    layer_masks.texture(),
+                    canvas_width,
+                    canvas_height,
+                    slot,
+                    &texels,
+                );
+                layer_masks.mark_dirty(idx, full);
+                undo.end_stroke(device, queue, layers, layer_masks);
+                written
+            });
+            if let Err(err) = result {
+                debug::log(LogLevel::Warn, format_args!("layer mask write failed: {err}"));
+                let _ = reply.send(false);
                 return EngineCommandOutcome {
                     stop: false,
                     needs_render: false,
                     new_canvas_size: None,
                 };
             }
-            let mut target = to_index as usize;
-            if target > len {
-                target = len;
-            }
-            if target > from {
-                target = target.saturating_sub(1);
-            }
-            if target >= len {
-                target = len.saturating_sub(1);
-            }
-            if target == from {
+            let _ = reply.send(true);
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: present.is_some(),
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::ApplyLayerMask { layer_index } => {
+            let idx = layer_index as usize;
+            let before = layer_masks.state(idx);
+            if idx >= *layer_count || !before.present {
                 return EngineCommandOutcome {
                     stop: false,
                     needs_render: false,
                     new_canvas_size: None,
                 };
             }
+            let full = (0, 0, canvas_width as i32, canvas_height as i32);
+            let read = |texture: &wgpu::Texture, slice: u32| {
+                read_r32uint_layer(device, queue, texture, canvas_width, canvas_height, slice)
+            };
+            // The mask's texels go away with it, so undo keeps them in a
+            // record of their own, in the same step as the layer's pixels.
+            let own_transaction = !undo.in_transaction();
+            undo.begin_transaction();
+            let result = layer_masks.checkout(device, queue, idx).and_then(|mask_slot| {
+                let mask = read(layer_masks.texture(), mask_slot)?;
+                undo.begin_target_stroke(layer_index, UndoTarget::Mask);
+                if layer_masks.painted(idx) {
+                    undo.capture_before_for_dirty_rect(
+                        device,
+                        queue,
+                        layer_masks.texture(),
+                        layer_index,
+                        mask_slot,
+                        full,
+                    );
+                }
+                undo.record_mask_change(layer_index, before, LayerMaskState::ABSENT);
+                undo.end_stroke(device, queue, layers, layer_masks);
 
-            reorder_vec(layer_opacity, from, target);
-            reorder_vec(layer_visible, from, target);
-            reorder_vec(layer_clipping_mask, from, target);
-            reorder_vec(layer_blend_mode, from, target);
-            reorder_vec(layer_uniform, from, target);
-
-            reorder_layer_textures(
-                device.as_ref(),
-                queue.as_ref(),
-                layers.texture(),
-                canvas_width,
-                canvas_height,
-                from,
-                target,
-            );
-
-            *active_layer_index = remap_layer_index(*active_layer_index, from, target);
-            if (*transform_layer_index as usize) < *layer_count {
-                *transform_layer_index =
-                    remap_layer_index(*transform_layer_index as usize, from, target) as u32;
-            } else {
-                *transform_layer_index = 0;
+                let slot = layers.checkout(device, queue, idx)?;
+                let mut pixels = read(layers.texture(), slot)?;
+                for (pixel, texel) in pixels.iter_mut().zip(mask.iter()) {
+                    *pixel = apply_mask_value(*pixel, mask_texel_value(*texel));
+                }
+                undo.begin_stroke(layer_index);
+                undo.capture_before_for_dirty_rect(
+                    device,
+                    queue,
+                    layers.texture(),
+                    layer_index,
+                    slot,
+                    full,
+                );
+                let written = write_r32uint_layer(
+                    queue,
+                    layers.texture(),
+                    canvas_width,
+                    canvas_height,
+                    slot,
+                    &pixels,
+                );
+                layers.mark_layer_rewritten(idx);
+                undo.end_stroke(device, queue, layers, layer_masks);
+                written
+            });
+            if own_transaction {
+                undo.commit_transaction(device, queue);
             }
-            undo.reorder_layers(from as u32, target as u32);
-
+            if let Err(err) = result {
+                debug::log(LogLevel::Warn, format_args!("layer mask apply failed: {err}"));
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            }
+            layer_masks.set_state(idx, LayerMaskState::ABSENT);
             write_present_config(
                 queue,
                 present_config_buffer,
                 present_params_buffer,
+                present_groups_buffer,
                 *layer_count,
                 *present_view_flags,
                 *transform_layer_index,
@@ -3020,6 +4343,9 @@ fn handle_engine_command(
                 layer_visible,
                 layer_clipping_mask,
                 layer_blend_mode,
+                &layer_masks.enabled_flags(),
+                &layer_adjustments.composite(*layer_count),
+                &layer_groups.spans(*layer_count),
             );
             return EngineCommandOutcome {
                 stop: false,
@@ -3027,6 +4353,26 @@ fn handle_engine_command(
                 new_canvas_size: None,
             };
         }
+        EngineCommand::ReadLayerMask { layer_index, reply } => {
+            let idx = layer_index as usize;
+            if idx >= *layer_count || !layer_masks.state(idx).present {
+                let _ = reply.send(None);
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            }
+            match read_mask_layer(device, queue, layer_masks, idx) {
+                Ok(texels) => {
+                    let _ = reply.send(Some(texels.into_iter().map(mask_texel_value).collect()));
+                }
+                Err(err) => {
+                    debug::log(LogLevel::Warn, format_args!("layer mask readback failed: {err}"));
+                    let _ = reply.send(None);
+                }
+            }
+        }
         EngineCommand::SetViewFlags { view_flags } => {
             let sanitized = view_flags & (VIEW_FLAG_MIRROR | VIEW_FLAG_BLACK_WHITE);
             if *present_view_flags != sanitized {
@@ -3035,6 +4381,7 @@ fn handle_engine_command(
                     queue,
                     present_config_buffer,
                     present_params_buffer,
+                    present_groups_buffer,
                     *layer_count,
                     *present_view_flags,
                     *transform_layer_index,
@@ -3043,6 +4390,9 @@ fn handle_engine_command(
                     layer_visible,
                     layer_clipping_mask,
                     layer_blend_mode,
+                    &layer_masks.enabled_flags(),
+                    &layer_adjustments.composite(*layer_count),
+                    &layer_groups.spans(*layer_count),
                 );
                 return EngineCommandOutcome {
                     stop: false,
@@ -3105,7 +4455,7 @@ fn handle_engine_command(
             brush_settings.hollow_ratio = hollow_ratio;
             brush_settings.hollow_erase_occluded = hollow_erase_occluded;
             brush_settings.streamline_strength = streamline_strength;
-            brush_settings.smoothing_mode = (smoothing_mode as u8).min(3);
+            brush_settings.smoothing_mode = (smoothing_mode as u8).min(4);
             brush_settings.stabilizer_strength = stabilizer_strength;
             brush_settings.sanitize();
             if brush.is_none() {
@@ -3176,6 +4526,297 @@ fn handle_engine_command(
                 new_canvas_size: None,
             };
         }
+        EngineCommand::SetPenDynamics {
+            tilt_size,
+            tilt_opacity,
+            azimuth_angle,
+            barrel_angle,
+        } => {
+            brush_settings.dynamics = PenDynamics {
+                tilt_size,
+                tilt_opacity,
+                azimuth_angle,
+                barrel_angle,
+            };
+            brush_settings.dynamics.sanitize();
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: false,
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::SetPressureCurve { target, points } => {
+            brush_settings.pressure_curves.set(target, &points);
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: false,
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::SetSimulatedPressure {
+            profile,
+            tapered_caps,
+        } => {
+            brush_settings.simulated_pressure = SimulatedPressure {
+                profile: SpeedPressureProfile::from_index(profile),
+                tapered_caps,
+            };
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: false,
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::SetSpringStabilizer {
+            mass,
+            stiffness,
+            damping,
+        } => {
+            brush_settings.spring = SpringSettings {
+                mass,
+                stiffness,
+                damping,
+            };
+            brush_settings.spring.sanitize();
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: false,
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::SetAssist {
+            kind,
+            params,
+            show_overlay,
+        } => {
+            brush_settings.assist.set(kind, &params, show_overlay);
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: true,
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::SetSymmetry {
+            mode,
+            center_x,
+            center_y,
+            folds,
+        } => {
+            brush_settings.symmetry = Symmetry {
+                mode: SymmetryMode::from_index(mode),
+                center: Point2D {
+                    x: center_x,
+                    y: center_y,
+                },
+                folds,
+            };
+            brush_settings.symmetry.sanitize();
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: false,
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::SetGrainTexture {
+            width,
+            height,
+            pixels,
+        } => {
+            if width == 0 || height == 0 || pixels.is_empty() {
+                if let Some(renderer) = brush.as_mut() {
+                    renderer.clear_grain_texture();
+                }
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            }
+            match ensure_brush(brush, device, queue, canvas_width, canvas_height) {
+                Ok(brush_ref) => {
+                    if let Err(err) = brush_ref.set_grain_texture(width, height, &pixels) {
+                        debug::log(
+                            LogLevel::Warn,
+                            format_args!("BrushRenderer set grain texture failed: {err}"),
+                        );
+                    }
+                }
+                Err(err) => debug::log(
+                    LogLevel::Warn,
+                    format_args!("BrushRenderer init failed: {err}"),
+                ),
+            }
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: false,
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::SetGrain {
+            enabled,
+            scale,
+            offset_x,
+            offset_y,
+            depth,
+            invert,
+            blend,
+        } => {
+            brush_settings.grain = GrainSettings {
+                enabled,
+                scale,
+                offset_x,
+                offset_y,
+                depth,
+                invert,
+                blend: GrainBlend::from_index(blend),
+            };
+            brush_settings.grain.sanitize();
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: false,
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::SetMixing {
+            mode,
+            pickup,
+            dilution,
+            persistence,
+            extension,
+        } => {
+            brush_settings.mix = MixSettings {
+                mode: MixMode::from_index(mode),
+                pickup,
+                dilution,
+                persistence,
+                extension,
+            };
+            brush_settings.mix.sanitize();
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: false,
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::SetWatercolor {
+            enabled,
+            edge,
+            granulation,
+            bleed,
+        } => {
+            brush_settings.wet = WetSettings {
+                enabled,
+                edge,
+                granulation,
+                bleed,
+            };
+            brush_settings.wet.sanitize();
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: false,
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::SetDualBrush {
+            enabled,
+            blend,
+            shape,
+            scale,
+            spacing,
+            scatter,
+            count,
+            size_jitter,
+        } => {
+            brush_settings.dual = DualTipSettings {
+                enabled,
+                blend: DualBlend::from_index(blend),
+                shape,
+                scale,
+                spacing,
+                scatter,
+                count,
+                size_jitter,
+            };
+            brush_settings.dual.sanitize();
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: false,
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::SetColorDynamics {
+            hue_jitter,
+            saturation_jitter,
+            value_jitter,
+            opacity_jitter,
+            stroke_hue_jitter,
+            stroke_saturation_jitter,
+            stroke_value_jitter,
+            stroke_opacity_jitter,
+            background_argb,
+            background_source,
+            background_amount,
+        } => {
+            brush_settings.color = ColorDynamics {
+                dab: ColorJitter {
+                    hue: hue_jitter,
+                    saturation: saturation_jitter,
+                    value: value_jitter,
+                    opacity: opacity_jitter,
+                },
+                stroke: ColorJitter {
+                    hue: stroke_hue_jitter,
+                    saturation: stroke_saturation_jitter,
+                    value: stroke_value_jitter,
+                    opacity: stroke_opacity_jitter,
+                },
+                background_argb,
+                background_source: ColorMixSource::from_index(background_source),
+                background_amount,
+            };
+            brush_settings.color.sanitize();
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: false,
+                new_canvas_size: None,
+            };
+        }
+        EngineCommand::SetDualBrushMask {
+            width,
+            height,
+            mask,
+        } => {
+            if width == 0 || height == 0 || mask.is_empty() {
+                if let Some(renderer) = brush.as_mut() {
+                    renderer.clear_dual_mask();
+                }
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            }
+            match ensure_brush(brush, device, queue, canvas_width, canvas_height) {
+                Ok(brush_ref) => {
+                    if let Err(err) = brush_ref.set_dual_mask(width, height, &mask) {
+                        debug::log(
+                            LogLevel::Warn,
+                            format_args!("BrushRenderer set dual brush mask failed: {err}"),
+                        );
+                        brush_ref.clear_dual_mask();
+                    }
+                }
+                Err(err) => debug::log(
+                    LogLevel::Warn,
+                    format_args!("BrushRenderer init failed: {err}"),
+                ),
+            }
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: false,
+                new_canvas_size: None,
+            };
+        }
         EngineCommand::BeginSpray => {
             let layer_idx = *active_layer_index as u32;
             *spray_active_layer = Some(layer_idx);
@@ -3231,7 +4872,15 @@ fn handle_engine_command(
                     };
                 }
             };
-
+
+            let Some(slot) = checkout_layer(device, queue, layers, idx) else {
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            };
+            brush_ref.set_alpha_lock(layer_alpha_lock.get(idx).copied().unwrap_or(false));
             let dirty = compute_spray_dirty_rect(
                 &points,
                 canvas_width,
@@ -3239,20 +4888,22 @@ fn handle_engine_command(
                 softness,
                 antialias_level,
             );
-            if let Err(err) = brush_ref.prepare_layer_read(layers.texture(), layer_idx, dirty) {
+            if let Err(err) = brush_ref.prepare_layer_read(layers.texture(), slot, dirty) {
                 debug::log(
                     LogLevel::Warn,
                     format_args!("Brush layer read prep failed: {err}"),
                 );
             }
-            undo.begin_stroke_if_needed(layer_idx);
+            undo.begin_stroke_if_needed(layer_idx, UndoTarget::Layer);
             undo.capture_before_for_dirty_rect(
                 device.as_ref(),
                 queue.as_ref(),
                 layers.texture(),
                 layer_idx,
+                slot,
                 dirty,
             );
+            layers.mark_dirty(idx, dirty);
 
             let shape = map_brush_shape(brush_shape);
             let draw_softness = if softness.is_finite() {
@@ -3281,10 +4932,7 @@ fn handle_engine_command(
                 alphas.push(alpha);
             }
 
-            let layer_view = layers
-                .layer_view(idx)
-                .or_else(|| layers.layer_view(0))
-                .expect("layers non-empty");
+            let layer_view = layers.working_view();
 
             let mut any_drawn = false;
             let mut start = 0usize;
@@ -3323,11 +4971,6 @@ fn handle_engine_command(
                 start = end;
             }
 
-            if any_drawn {
-                if let Some(entry) = layer_uniform.get_mut(idx) {
-                    *entry = None;
-                }
-            }
             return EngineCommandOutcome {
                 stop: false,
                 needs_render: any_drawn && present.is_some(),
@@ -3336,7 +4979,7 @@ fn handle_engine_command(
         }
         EngineCommand::EndSpray => {
             if spray_active_layer.is_some() {
-                undo.end_stroke(device.as_ref(), queue.as_ref(), layers.texture());
+                undo.end_stroke(device.as_ref(), queue.as_ref(), layers, layer_masks);
             }
             *spray_active_layer = None;
         }
@@ -3366,7 +5009,7 @@ fn handle_engine_command(
                     new_canvas_size: None,
                 };
             }
-            let Some(layer_view) = layers.layer_view(idx) else {
+            let Some(slot) = checkout_layer(device, queue, layers, idx) else {
                 let _ = reply.send(false);
                 return EngineCommandOutcome {
                     stop: false,
@@ -3374,6 +5017,7 @@ fn handle_engine_command(
                     new_canvas_size: None,
                 };
             };
+            let layer_view = layers.working_view();
 
             let renderer = match ensure_filter_renderer(
                 filter_renderer,
@@ -3397,154 +5041,30 @@ fn handle_engine_command(
                 }
             };
 
+            let alpha_lock = match save_locked_alpha(
+                alpha_lock_renderer,
+                layer_alpha_lock.get(idx).copied().unwrap_or(false),
+                device,
+                queue,
+                layers.texture(),
+                (canvas_width, canvas_height),
+                slot,
+            ) {
+                Ok(lock) => lock,
+                Err(err) => {
+                    debug::log(LogLevel::Warn, format_args!("alpha lock save failed: {err}"));
+                    let _ = reply.send(false);
+                    return EngineCommandOutcome {
+                        stop: false,
+                        needs_render: false,
+                        new_canvas_size: None,
+                    };
+                }
+            };
+
             let mut applied = false;
             let mut result: Result<(), String> = Ok(());
             match filter_type {
-                FILTER_HUE_SATURATION => {
-                    let params0 = [
-                        param0 / 360.0,
-                        param1 / 100.0,
-                        param2 / 100.0,
-                        0.0,
-                    ];
-                    undo.begin_stroke(layer_index);
-                    undo.capture_before_for_dirty_rect(
-                        device,
-                        queue,
-                        layers.texture(),
-                        layer_index,
-                        (0, 0, canvas_width as i32, canvas_height as i32),
-                    );
-                    result = renderer.apply_color_filter(
-                        layers.texture(),
-                        layer_view,
-                        layer_index,
-                        0,
-                        params0,
-                        [0.0; 4],
-                    );
-                    applied = result.is_ok();
-                }
-                FILTER_BRIGHTNESS_CONTRAST => {
-                    let brightness = param0 / 100.0;
-                    let contrast = (1.0 + param1 / 100.0).max(0.0);
-                    let params0 = [brightness, contrast, 0.0, 0.0];
-                    undo.begin_stroke(layer_index);
-                    undo.capture_before_for_dirty_rect(
-                        device,
-                        queue,
-                        layers.texture(),
-                        layer_index,
-                        (0, 0, canvas_width as i32, canvas_height as i32),
-                    );
-                    result = renderer.apply_color_filter(
-                        layers.texture(),
-                        layer_view,
-                        layer_index,
-                        1,
-                        params0,
-                        [0.0; 4],
-                    );
-                    applied = result.is_ok();
-                }
-                FILTER_BLACK_WHITE => {
-                    let black = (param0 / 100.0).clamp(0.0, 1.0);
-                    let white = (param1 / 100.0).clamp(0.0, 1.0);
-                    let safe_white = (black + 0.01).max(white);
-                    let inv_range = 1.0 / (safe_white - black).max(1.0e-4);
-                    let gamma = 2.0_f32.powf(param2.clamp(-100.0, 100.0) / 100.0);
-                    let params0 = [black, inv_range, gamma, 0.0];
-                    undo.begin_stroke(layer_index);
-                    undo.capture_before_for_dirty_rect(
-                        device,
-                        queue,
-                        layers.texture(),
-                        layer_index,
-                        (0, 0, canvas_width as i32, canvas_height as i32),
-                    );
-                    result = renderer.apply_color_filter(
-                        layers.texture(),
-                        layer_view,
-                        layer_index,
-                        2,
-                        params0,
-                        [0.0; 4],
-                    );
-                    applied = result.is_ok();
-                }
-                FILTER_SCAN_PAPER_DRAWING => {
-                    let black = (param0 / 100.0).clamp(0.0, 1.0);
-                    let white = (param1 / 100.0).clamp(0.0, 1.0);
-                    let safe_white = (black + 0.01).max(white);
-                    let inv_range = 1.0 / (safe_white - black).max(1.0e-4);
-                    let gamma = 2.0_f32.powf(param2.clamp(-100.0, 100.0) / 100.0);
-                    let tone_enabled = if param0.abs() > 1.0e-4
-                        || (param1 - 100.0).abs() > 1.0e-4
-                        || param2.abs() > 1.0e-4
-                    {
-                        1.0
-                    } else {
-                        0.0
-                    };
-                    let params0 = [black, inv_range, gamma, tone_enabled];
-                    undo.begin_stroke(layer_index);
-                    undo.capture_before_for_dirty_rect(
-                        device,
-                        queue,
-                        layers.texture(),
-                        layer_index,
-                        (0, 0, canvas_width as i32, canvas_height as i32),
-                    );
-                    result = renderer.apply_color_filter(
-                        layers.texture(),
-                        layer_view,
-                        layer_index,
-                        4,
-                        params0,
-                        [0.0; 4],
-                    );
-                    applied = result.is_ok();
-                }
-                FILTER_BINARIZE => {
-                    let threshold = param0.clamp(0.0, 255.0) / 255.0;
-                    let params0 = [threshold, 0.0, 0.0, 0.0];
-                    undo.begin_stroke(layer_index);
-                    undo.capture_before_for_dirty_rect(
-                        device,
-                        queue,
-                        layers.texture(),
-                        layer_index,
-                        (0, 0, canvas_width as i32, canvas_height as i32),
-                    );
-                    result = renderer.apply_color_filter(
-                        layers.texture(),
-                        layer_view,
-                        layer_index,
-                        3,
-                        params0,
-                        [0.0; 4],
-                    );
-                    applied = result.is_ok();
-                }
-                FILTER_INVERT => {
-                    undo.begin_stroke(layer_index);
-                    undo.capture_before_for_dirty_rect(
-                        device,
-                        queue,
-                        layers.texture(),
-                        layer_index,
-                        (0, 0, canvas_width as i32, canvas_height as i32),
-                    );
-                    result = renderer.apply_color_filter(
-                        layers.texture(),
-                        layer_view,
-                        layer_index,
-                        5,
-                        [0.0; 4],
-                        [0.0; 4],
-                    );
-                    applied = result.is_ok();
-                }
                 FILTER_GAUSSIAN_BLUR => {
                     let radius = param0;
                     if radius <= 0.0 {
@@ -3561,6 +5081,7 @@ fn handle_engine_command(
                         queue,
                         layers.texture(),
                         layer_index,
+                        slot,
                         (0, 0, canvas_width as i32, canvas_height as i32),
                     );
                     result = renderer.apply_gaussian_blur(layer_view, radius);
@@ -3582,12 +5103,13 @@ fn handle_engine_command(
                         queue,
                         layers.texture(),
                         layer_index,
+                        slot,
                         (0, 0, canvas_width as i32, canvas_height as i32),
                     );
                     result = renderer.apply_morphology(
                         layers.texture(),
                         layer_view,
-                        layer_index,
+                        slot,
                         steps,
                         false,
                     );
@@ -3609,12 +5131,13 @@ fn handle_engine_command(
                         queue,
                         layers.texture(),
                         layer_index,
+                        slot,
                         (0, 0, canvas_width as i32, canvas_height as i32),
                     );
                     result = renderer.apply_morphology(
                         layers.texture(),
                         layer_view,
-                        layer_index,
+                        slot,
                         steps,
                         true,
                     );
@@ -3636,12 +5159,13 @@ fn handle_engine_command(
                         queue,
                         layers.texture(),
                         layer_index,
+                        slot,
                         (0, 0, canvas_width as i32, canvas_height as i32),
                     );
                     result = renderer.apply_morphology(
                         layers.texture(),
                         layer_view,
-                        layer_index,
+                        slot,
                         steps,
                         true,
                     );
@@ -3649,21 +5173,50 @@ fn handle_engine_command(
                         result = renderer.apply_morphology(
                             layers.texture(),
                             layer_view,
-                            layer_index,
+                            slot,
                             steps,
                             false,
                         );
                     }
                     applied = result.is_ok();
                 }
-                _ => {}
+                _ => {
+                    if let Some((kind, params0)) =
+                        color_filter_params(filter_type, param0, param1, param2)
+                    {
+                        undo.begin_stroke(layer_index);
+                        undo.capture_before_for_dirty_rect(
+                            device,
+                            queue,
+                            layers.texture(),
+                            layer_index,
+                            slot,
+                            (0, 0, canvas_width as i32, canvas_height as i32),
+                        );
+                        result = renderer.apply_color_filter(
+                            layers.texture(),
+                            layer_view,
+                            slot,
+                            kind,
+                            params0,
+                            [0.0; 4],
+                        );
+                        applied = result.is_ok();
+                    }
+                }
             }
 
             if applied {
-                undo.end_stroke(device, queue, layers.texture());
-                if let Some(entry) = layer_uniform.get_mut(idx) {
-                    *entry = None;
+                if let Some(lock) = alpha_lock {
+                    if let Err(err) = lock.restore(layers.texture(), layer_view, slot) {
+                        debug::log(
+                            LogLevel::Warn,
+                            format_args!("alpha lock restore failed: {err}"),
+                        );
+                    }
                 }
+                layers.mark_layer_rewritten(idx);
+                undo.end_stroke(device, queue, layers, layer_masks);
                 let _ = reply.send(true);
                 return EngineCommandOutcome {
                     stop: false,
@@ -3705,7 +5258,7 @@ fn handle_engine_command(
                     new_canvas_size: None,
                 };
             }
-            let Some(layer_view) = layers.layer_view(idx) else {
+            let Some(slot) = checkout_layer(device, queue, layers, idx) else {
                 let _ = reply.send(false);
                 return EngineCommandOutcome {
                     stop: false,
@@ -3713,6 +5266,7 @@ fn handle_engine_command(
                     new_canvas_size: None,
                 };
             };
+            let layer_view = layers.working_view();
 
             let renderer = match ensure_filter_renderer(
                 filter_renderer,
@@ -3736,20 +5290,48 @@ fn handle_engine_command(
                 }
             };
 
+            let alpha_lock = match save_locked_alpha(
+                alpha_lock_renderer,
+                layer_alpha_lock.get(idx).copied().unwrap_or(false),
+                device,
+                queue,
+                layers.texture(),
+                (canvas_width, canvas_height),
+                slot,
+            ) {
+                Ok(lock) => lock,
+                Err(err) => {
+                    debug::log(LogLevel::Warn, format_args!("alpha lock save failed: {err}"));
+                    let _ = reply.send(false);
+                    return EngineCommandOutcome {
+                        stop: false,
+                        needs_render: false,
+                        new_canvas_size: None,
+                    };
+                }
+            };
+
             undo.begin_stroke(layer_index);
             undo.capture_before_for_dirty_rect(
                 device,
                 queue,
                 layers.texture(),
                 layer_index,
+                slot,
                 (0, 0, canvas_width as i32, canvas_height as i32),
             );
             let result = renderer.apply_antialias(layer_view, level);
             if result.is_ok() {
-                undo.end_stroke(device, queue, layers.texture());
-                if let Some(entry) = layer_uniform.get_mut(idx) {
-                    *entry = None;
+                if let Some(lock) = alpha_lock {
+                    if let Err(err) = lock.restore(layers.texture(), layer_view, slot) {
+                        debug::log(
+                            LogLevel::Warn,
+                            format_args!("alpha lock restore failed: {err}"),
+                        );
+                    }
                 }
+                layers.mark_layer_rewritten(idx);
+                undo.end_stroke(device, queue, layers, layer_masks);
                 let _ = reply.send(true);
                 return EngineCommandOutcome {
                     stop: false,
@@ -3813,9 +5395,24 @@ fn handle_engine_command(
                 };
             }
 
+            let Some(slot) = checkout_layer(device, queue, layers, idx) else {
+                let _ = reply.send(false);
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            };
+
+            let alpha_locked = layer_alpha_lock.get(idx).copied().unwrap_or(false);
             // Fast path: uniform layer with no selection mask can be filled directly.
             if !sample_all_layers && selection_mask.is_none() {
-                if let Some(base_color) = layer_uniform.get(idx).copied().flatten() {
+                if let Some(base_color) = layers.uniform(idx) {
+                    let color_argb = if alpha_locked {
+                        lock_alpha_texel(base_color, color_argb)
+                    } else {
+                        color_argb
+                    };
                     if base_color == color_argb {
                         let _ = reply.send(false);
                         return EngineCommandOutcome {
@@ -3830,20 +5427,11 @@ fn handle_engine_command(
                         queue,
                         layers.texture(),
                         layer_index,
+                        slot,
                         (0, 0, canvas_width as i32, canvas_height as i32),
                     );
-                    fill_r32uint_texture(
-                        queue,
-                        layers.texture(),
-                        canvas_width,
-                        canvas_height,
-                        layer_index,
-                        color_argb,
-                    );
-                    undo.end_stroke(device, queue, layers.texture());
-                    if let Some(entry) = layer_uniform.get_mut(idx) {
-                        *entry = Some(color_argb);
-                    }
+                    layers.fill(device, queue, idx, color_argb);
+                    undo.end_stroke(device, queue, layers, layer_masks);
                     let _ = reply.send(true);
                     return EngineCommandOutcome {
                         stop: false,
@@ -3866,13 +5454,38 @@ fn handle_engine_command(
             }
 
             if let Some(renderer) = bucket_fill_renderer.as_mut() {
-                let Some(layer_view) = layers.layer_view(idx) else {
+                // The fill samples every layer, this one included, through the
+                // atlas.
+                if let Err(err) = layers.flush(device, queue) {
+                    debug::log(LogLevel::Warn, format_args!("bucket fill flush failed: {err}"));
                     let _ = reply.send(false);
                     return EngineCommandOutcome {
                         stop: false,
                         needs_render: false,
                         new_canvas_size: None,
                     };
+                }
+                let layer_view = layers.working_view();
+
+                let alpha_lock = match save_locked_alpha(
+                    alpha_lock_renderer,
+                    alpha_locked,
+                    device,
+                    queue,
+                    layers.texture(),
+                    (canvas_width, canvas_height),
+                    slot,
+                ) {
+                    Ok(lock) => lock,
+                    Err(err) => {
+                        debug::log(LogLevel::Warn, format_args!("alpha lock save failed: {err}"));
+                        let _ = reply.send(false);
+                        return EngineCommandOutcome {
+                            stop: false,
+                            needs_render: false,
+                            new_canvas_size: None,
+                        };
+                    }
                 };
 
                 undo.begin_stroke(layer_index);
@@ -3881,12 +5494,16 @@ fn handle_engine_command(
                     queue,
                     layers.texture(),
                     layer_index,
+                    slot,
                     (0, 0, canvas_width as i32, canvas_height as i32),
                 );
 
                 let applied = match renderer.bucket_fill(
                     layer_view,
-                    layers.array_view(),
+                    SampledLayers {
+                        atlas: layers.atlas_view(),
+                        pages: layers.page_table_view(),
+                    },
                     layer_index,
                     *layer_count,
                     layer_opacity,
@@ -3916,10 +5533,16 @@ fn handle_engine_command(
                 };
 
                 if applied {
-                    undo.end_stroke(device, queue, layers.texture());
-                    if let Some(entry) = layer_uniform.get_mut(idx) {
-                        *entry = None;
+                    if let Some(lock) = alpha_lock {
+                        if let Err(err) = lock.restore(layers.texture(), layer_view, slot) {
+                            debug::log(
+                                LogLevel::Warn,
+                                format_args!("alpha lock restore failed: {err}"),
+                            );
+                        }
                     }
+                    layers.mark_layer_rewritten(idx);
+                    undo.end_stroke(device, queue, layers, layer_masks);
                 } else {
                     undo.cancel_stroke();
                 }
@@ -3935,14 +5558,7 @@ fn handle_engine_command(
             let (active_pixels, sample_pixels) = if sample_all_layers {
                 let mut layers_pixels: Vec<Vec<u32>> = Vec::with_capacity(*layer_count);
                 for layer_idx in 0..*layer_count {
-                    match read_r32uint_layer(
-                        device,
-                        queue,
-                        layers.texture(),
-                        canvas_width,
-                        canvas_height,
-                        layer_idx as u32,
-                    ) {
+                    match read_store_layer(device, queue, layers, layer_idx) {
                         Ok(pixels) => layers_pixels.push(pixels),
                         Err(err) => {
                             debug::log(
@@ -3969,14 +5585,7 @@ fn handle_engine_command(
                 let active = std::mem::take(&mut layers_pixels[idx]);
                 (active, Some(sample))
             } else {
-                match read_r32uint_layer(
-                    device,
-                    queue,
-                    layers.texture(),
-                    canvas_width,
-                    canvas_height,
-                    idx as u32,
-                ) {
+                match read_store_layer(device, queue, layers, idx) {
                     Ok(pixels) => (pixels, None),
                     Err(err) => {
                         debug::log(
@@ -3993,7 +5602,9 @@ fn handle_engine_command(
                 }
             };
 
-            let patch = bucket_fill::flood_fill_patch(
+            // The fill consumes the layer; keep what the lock needs from it.
+            let locked_before = alpha_locked.then(|| active_pixels.clone());
+            let mut patch = bucket_fill::flood_fill_patch(
                 canvas_width as i32,
                 canvas_height as i32,
                 active_pixels,
@@ -4036,6 +5647,13 @@ fn handle_engine_command(
                 };
             }
 
+            if let Some(before) = locked_before.as_deref() {
+                for (row, pixels) in patch.pixels.chunks_mut(width as usize).enumerate() {
+                    let start = (top as usize + row) * canvas_width as usize + left as usize;
+                    lock_alpha_pixels(&before[start..start + pixels.len()], pixels);
+                }
+            }
+
             let bytes_per_row_unpadded = match width.checked_mul(4) {
                 Some(v) => v,
                 None => {
@@ -4077,12 +5695,22 @@ fn handle_engine_command(
                 }
             };
 
+            // Reading the other layers checked them out in turn.
+            let Some(slot) = checkout_layer(device, queue, layers, idx) else {
+                let _ = reply.send(false);
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            };
             undo.begin_stroke(layer_index);
             undo.capture_before_for_dirty_rect(
                 device,
                 queue,
                 layers.texture(),
                 layer_index,
+                slot,
                 (patch.left, patch.top, patch.width, patch.height),
             );
 
@@ -4093,14 +5721,12 @@ fn handle_engine_command(
                 top,
                 width,
                 height,
-                layer_index,
+                slot,
                 bytes_per_row_padded,
                 &packed,
             );
-            undo.end_stroke(device, queue, layers.texture());
-            if let Some(entry) = layer_uniform.get_mut(idx) {
-                *entry = None;
-            }
+            layers.mark_rewritten(idx, (patch.left, patch.top, patch.width, patch.height));
+            undo.end_stroke(device, queue, layers, layer_masks);
 
             let _ = reply.send(true);
             return EngineCommandOutcome {
@@ -4151,14 +5777,7 @@ fn handle_engine_command(
             let pixels = if sample_all_layers {
                 let mut layers_pixels: Vec<Vec<u32>> = Vec::with_capacity(*layer_count);
                 for layer_idx in 0..*layer_count {
-                    match read_r32uint_layer(
-                        device,
-                        queue,
-                        layers.texture(),
-                        canvas_width,
-                        canvas_height,
-                        layer_idx as u32,
-                    ) {
+                    match read_store_layer(device, queue, layers, layer_idx) {
                         Ok(pixels) => layers_pixels.push(pixels),
                         Err(err) => {
                             debug::log(
@@ -4183,14 +5802,7 @@ fn handle_engine_command(
                     layer_clipping_mask,
                 )
             } else {
-                match read_r32uint_layer(
-                    device,
-                    queue,
-                    layers.texture(),
-                    canvas_width,
-                    canvas_height,
-                    idx as u32,
-                ) {
+                match read_store_layer(device, queue, layers, idx) {
                     Ok(pixels) => pixels,
                     Err(err) => {
                         debug::log(
@@ -4241,14 +5853,7 @@ fn handle_engine_command(
                     new_canvas_size: None,
                 };
             }
-            match read_r32uint_layer(
-                device,
-                queue,
-                layers.texture(),
-                canvas_width,
-                canvas_height,
-                layer_index,
-            ) {
+            match read_store_layer(device, queue, layers, idx) {
                 Ok(pixels) => {
                     let _ = reply.send(Some(pixels));
                 }
@@ -4303,13 +5908,15 @@ fn handle_engine_command(
             });
             let preview_view = preview_texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-            let layer_view = layers.texture().create_view(&wgpu::TextureViewDescriptor {
-                label: Some("misa-rin layer preview array view"),
-                dimension: Some(wgpu::TextureViewDimension::D2Array),
-                base_array_layer: layer_index,
-                array_layer_count: Some(1),
-                ..Default::default()
-            });
+            if let Err(err) = layers.flush(device, queue) {
+                debug::log(LogLevel::Warn, format_args!("layer preview flush failed: {err}"));
+                let _ = reply.send(None);
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            }
 
             let scale_x = canvas_width as f32 / width as f32;
             let scale_y = canvas_height as f32 / height as f32;
@@ -4317,11 +5924,14 @@ fn handle_engine_command(
                 scale_x, 0.0, 0.0, 0.0, 0.0, scale_y, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0,
                 0.0, 1.0,
             ];
-            let preview_layer_opacity = [1.0f32];
-            let preview_layer_visible = [true];
-            let preview_layer_clipping = [false];
-            let preview_layer_blend_mode = [0u32];
-            let preview_transform_layer = 0u32;
+            // Composite the real stack with every layer below `idx` hidden.
+            let preview_count = idx + 1;
+            let preview_layer_opacity = vec![1.0f32; preview_count];
+            let mut preview_layer_visible = vec![false; preview_count];
+            preview_layer_visible[idx] = true;
+            let preview_layer_clipping = vec![false; preview_count];
+            let preview_layer_blend_mode = vec![0u32; preview_count];
+            let preview_transform_layer = layer_index;
             let preview_transform_flags = 1u32;
 
             write_present_transform(queue, present_transform_buffer, preview_matrix);
@@ -4329,7 +5939,8 @@ fn handle_engine_command(
                 queue,
                 present_config_buffer,
                 present_params_buffer,
-                1,
+                present_groups_buffer,
+                preview_count,
                 0,
                 preview_transform_layer,
                 preview_transform_flags,
@@ -4337,14 +5948,19 @@ fn handle_engine_command(
                 &preview_layer_visible,
                 &preview_layer_clipping,
                 &preview_layer_blend_mode,
+                &vec![false; preview_count],
+                &[],
+                &[],
             );
 
             let preview_bind_group = present_renderer.create_bind_group(
                 device,
-                &layer_view,
+                layers,
                 present_config_buffer,
                 present_params_buffer,
                 present_transform_buffer,
+                present_groups_buffer,
+                layer_masks,
             );
             present_renderer.render_base(device, queue, &preview_bind_group, &preview_view);
 
@@ -4365,6 +5981,7 @@ fn handle_engine_command(
                 queue,
                 present_config_buffer,
                 present_params_buffer,
+                present_groups_buffer,
                 *layer_count,
                 *present_view_flags,
                 *transform_layer_index,
@@ -4373,6 +5990,9 @@ fn handle_engine_command(
                 layer_visible,
                 layer_clipping_mask,
                 layer_blend_mode,
+                &layer_masks.enabled_flags(),
+                &layer_adjustments.composite(*layer_count),
+                &layer_groups.spans(*layer_count),
             );
 
             let _ = reply.send(preview_bytes);
@@ -4420,6 +6040,71 @@ fn handle_engine_command(
                 new_canvas_size: None,
             };
         }
+        EngineCommand::ReadProject { reply } => {
+            let mut snapshot = EngineProjectSnapshot {
+                width: canvas_width,
+                height: canvas_height,
+                layers: Vec::with_capacity(*layer_count),
+                groups: layer_groups.groups().to_vec(),
+            };
+            for idx in 0..*layer_count {
+                let pixels = match read_store_layer(device, queue, layers, idx) {
+                    Ok(pixels) => pixels,
+                    Err(err) => {
+                        debug::log(
+                            LogLevel::Warn,
+                            format_args!("project readback failed at layer {idx}: {err}"),
+                        );
+                        let _ = reply.send(None);
+                        return EngineCommandOutcome {
+                            stop: false,
+                            needs_render: false,
+                            new_canvas_size: None,
+                        };
+                    }
+                };
+                let mask_state = layer_masks.state(idx);
+                let mask = if mask_state.present {
+                    match read_mask_layer(device, queue, layer_masks, idx) {
+                        Ok(texels) => Some(RinMask {
+                            enabled: mask_state.enabled,
+                            coverage: texels.into_iter().map(mask_texel_value).collect(),
+                        }),
+                        Err(err) => {
+                            debug::log(
+                                LogLevel::Warn,
+                                format_args!("project mask readback failed at layer {idx}: {err}"),
+                            );
+                            let _ = reply.send(None);
+                            return EngineCommandOutcome {
+                                stop: false,
+                                needs_render: false,
+                                new_canvas_size: None,
+                            };
+                        }
+                    }
+                } else {
+                    None
+                };
+                snapshot.layers.push(EngineLayerSnapshot {
+                    pixels,
+                    opacity: layer_opacity.get(idx).copied().unwrap_or(1.0),
+                    visible: layer_visible.get(idx).copied().unwrap_or(true),
+                    clipping_mask: layer_clipping_mask.get(idx).copied().unwrap_or(false),
+                    blend_mode_index: layer_blend_mode.get(idx).copied().unwrap_or(0),
+                    alpha_locked: layer_alpha_lock.get(idx).copied().unwrap_or(false),
+                    group: layer_groups.layer_group(idx),
+                    mask,
+                    adjustment: layer_adjustments.get(idx),
+                });
+            }
+            let _ = reply.send(Some(snapshot));
+            return EngineCommandOutcome {
+                stop: false,
+                needs_render: false,
+                new_canvas_size: None,
+            };
+        }
         EngineCommand::WriteLayer {
             layer_index,
             pixels,
@@ -4494,6 +6179,14 @@ fn handle_engine_command(
                     };
                 }
             };
+            let Some(slot) = checkout_layer(device, queue, layers, idx) else {
+                let _ = reply.send(false);
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            };
             if record_undo {
                 undo.begin_stroke(layer_index);
                 undo.capture_before_for_dirty_rect(
@@ -4501,6 +6194,7 @@ fn handle_engine_command(
                     queue,
                     layers.texture(),
                     layer_index,
+                    slot,
                     (0, 0, canvas_width as i32, canvas_height as i32),
                 );
             }
@@ -4511,15 +6205,13 @@ fn handle_engine_command(
                 0,
                 canvas_width,
                 canvas_height,
-                layer_index,
+                slot,
                 bytes_per_row_padded,
                 &packed,
             );
+            layers.mark_layer_rewritten(idx);
             if record_undo {
-                undo.end_stroke(device, queue, layers.texture());
-            }
-            if let Some(entry) = layer_uniform.get_mut(idx) {
-                *entry = None;
+                undo.end_stroke(device, queue, layers, layer_masks);
             }
             let _ = reply.send(true);
             return EngineCommandOutcome {
@@ -4559,6 +6251,14 @@ fn handle_engine_command(
                     new_canvas_size: None,
                 };
             }
+            let Some(slot) = checkout_layer(device, queue, layers, idx) else {
+                let _ = reply.send(false);
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            };
             if transform_renderer.is_none() {
                 if let Err(err) = ensure_transform_renderer(transform_renderer, device, queue) {
                     debug::log(
@@ -4575,14 +6275,15 @@ fn handle_engine_command(
                     queue,
                     layers.texture(),
                     layer_index,
+                    slot,
                     (0, 0, canvas_width as i32, canvas_height as i32),
                 );
                 if let Err(err) = renderer.apply_transform(
-                    layers.array_view(),
+                    layers.working_array_view(),
                     layers.texture(),
                     canvas_width,
                     canvas_height,
-                    layer_index,
+                    slot,
                     matrix,
                     false,
                 ) {
@@ -4598,10 +6299,8 @@ fn handle_engine_command(
                         new_canvas_size: None,
                     };
                 }
-                undo.end_stroke(device, queue, layers.texture());
-                if let Some(entry) = layer_uniform.get_mut(idx) {
-                    *entry = None;
-                }
+                layers.mark_layer_rewritten(idx);
+                undo.end_stroke(device, queue, layers, layer_masks);
                 let _ = reply.send(true);
                 return EngineCommandOutcome {
                     stop: false,
@@ -4609,14 +6308,7 @@ fn handle_engine_command(
                     new_canvas_size: None,
                 };
             }
-            let pixels = match read_r32uint_layer(
-                device,
-                queue,
-                layers.texture(),
-                canvas_width,
-                canvas_height,
-                layer_index,
-            ) {
+            let pixels = match read_store_layer(device, queue, layers, idx) {
                 Ok(pixels) => pixels,
                 Err(err) => {
                     debug::log(
@@ -4707,6 +6399,7 @@ fn handle_engine_command(
                 queue,
                 layers.texture(),
                 layer_index,
+                slot,
                 (0, 0, canvas_width as i32, canvas_height as i32),
             );
             write_r32uint_region(
@@ -4716,14 +6409,12 @@ fn handle_engine_command(
                 0,
                 canvas_width,
                 canvas_height,
-                layer_index,
+                slot,
                 bytes_per_row_padded,
                 &packed,
             );
-            undo.end_stroke(device, queue, layers.texture());
-            if let Some(entry) = layer_uniform.get_mut(idx) {
-                *entry = None;
-            }
+            layers.mark_layer_rewritten(idx);
+            undo.end_stroke(device, queue, layers, layer_masks);
             let _ = reply.send(true);
             return EngineCommandOutcome {
                 stop: false,
@@ -4749,6 +6440,7 @@ fn handle_engine_command(
                 queue,
                 present_config_buffer,
                 present_params_buffer,
+                present_groups_buffer,
                 *layer_count,
                 *present_view_flags,
                 *transform_layer_index,
@@ -4757,6 +6449,9 @@ fn handle_engine_command(
                 layer_visible,
                 layer_clipping_mask,
                 layer_blend_mode,
+                &layer_masks.enabled_flags(),
+                &layer_adjustments.composite(*layer_count),
+                &layer_groups.spans(*layer_count),
             );
             return EngineCommandOutcome {
                 stop: false,
@@ -4803,20 +6498,29 @@ fn handle_engine_command(
                     new_canvas_size: None,
                 };
             };
+            let Some(slot) = checkout_layer(device, queue, layers, idx) else {
+                let _ = reply.send(false);
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            };
             undo.begin_stroke(layer_index);
             undo.capture_before_for_dirty_rect(
                 device,
                 queue,
                 layers.texture(),
                 layer_index,
+                slot,
                 (0, 0, canvas_width as i32, canvas_height as i32),
             );
             let result = renderer.apply_transform(
-                layers.array_view(),
+                layers.working_array_view(),
                 layers.texture(),
                 canvas_width,
                 canvas_height,
-                layer_index,
+                slot,
                 matrix,
                 bilinear,
             );
@@ -4833,10 +6537,8 @@ fn handle_engine_command(
                     new_canvas_size: None,
                 };
             }
-            undo.end_stroke(device, queue, layers.texture());
-            if let Some(entry) = layer_uniform.get_mut(idx) {
-                *entry = None;
-            }
+            layers.mark_layer_rewritten(idx);
+            undo.end_stroke(device, queue, layers, layer_masks);
             let _ = reply.send(true);
             return EngineCommandOutcome {
                 stop: false,
@@ -4854,13 +6556,32 @@ fn handle_engine_command(
                     new_canvas_size: None,
                 };
             }
-            let pixels = match read_r32uint_layer(
+            // Only the tiles that may hold paint are read back; a layer with
+            // no occupied tiles is empty without touching the GPU.
+            let Some((left, top, right, bottom)) = layers.occupied_rect(idx) else {
+                let _ = reply.send(Some((0, 0, canvas_width as i32, canvas_height as i32)));
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            };
+            let Some(slot) = checkout_layer(device, queue, layers, idx) else {
+                let _ = reply.send(None);
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            };
+            let region_width = right - left;
+            let region_height = bottom - top;
+            let pixels = match read_r32uint_region(
                 device,
                 queue,
                 layers.texture(),
-                canvas_width,
-                canvas_height,
-                layer_index,
+                slot,
+                (left, top, region_width, region_height),
             ) {
                 Ok(pixels) => pixels,
                 Err(err) => {
@@ -4884,8 +6605,8 @@ fn handle_engine_command(
                     new_canvas_size: None,
                 };
             }
-            let width = canvas_width as i32;
-            let height = canvas_height as i32;
+            let width = region_width as i32;
+            let height = region_height as i32;
             let mut min_x = width;
             let mut min_y = height;
             let mut max_x = -1;
@@ -4911,11 +6632,17 @@ fn handle_engine_command(
                     }
                 }
             }
-            if max_x < min_x || max_y < min_y {
-                let _ = reply.send(Some((0, 0, width, height)));
+            let bounds = if max_x < min_x || max_y < min_y {
+                None
             } else {
-                let _ = reply.send(Some((min_x, min_y, max_x + 1, max_y + 1)));
-            }
+                let (left, top) = (left as i32, top as i32);
+                Some((left + min_x, top + min_y, left + max_x + 1, top + max_y + 1))
+            };
+            // Let the next flush hand back the tiles the scan found empty.
+            let region = (left as i32, top as i32, region_width as i32, region_height as i32);
+            layers.mark_rewritten(idx, region);
+            let full = (0, 0, canvas_width as i32, canvas_height as i32);
+            let _ = reply.send(Some(bounds.unwrap_or(full)));
             return EngineCommandOutcome {
                 stop: false,
                 needs_render: false,
@@ -4962,29 +6689,274 @@ fn handle_engine_command(
                 }
             }
         }
-        EngineCommand::Undo => {
-            let applied = undo.undo(device, queue, layers.texture(), *layer_count);
-            if applied {
-                for entry in layer_uniform.iter_mut() {
-                    *entry = None;
+        cmd @ (EngineCommand::Undo | EngineCommand::Redo) => {
+            let redo = matches!(cmd, EngineCommand::Redo);
+            // A removed layer goes back into a slot grown before its step
+            // leaves the stack.
+            if undo.next_edit_inserts_layer(redo)
+                && !ensure_layer_index(
+                    layer_count,
+                    *layer_count,
+                    *transform_layer_index,
+                    *transform_flags,
+                )
+            {
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
+            }
+            let applied = if redo {
+                undo.redo(device, queue, layers, layer_masks, *layer_count)
+            } else {
+                undo.undo(device, queue, layers, layer_masks, *layer_count)
+            };
+            let mut new_canvas_size = None;
+            match applied {
+                UndoApplied::Nothing => {
+                    return EngineCommandOutcome {
+                        stop: false,
+                        needs_render: false,
+                        new_canvas_size: None,
+                    };
+                }
+                UndoApplied::Pixels => {}
+                UndoApplied::Edit(edit) => {
+                    let inverse = match edit {
+                        LayerEdit::Properties {
+                            layer_index,
+                            properties,
+                        } => {
+                            let idx = layer_index as usize;
+                            let current = layer_properties(
+                                idx,
+                                layer_opacity,
+                                layer_visible,
+                                layer_clipping_mask,
+                                layer_alpha_lock,
+                                layer_blend_mode,
+                                layer_masks,
+                            );
+                            set_layer_properties(
+                                idx,
+                                properties,
+                                layer_opacity,
+                                layer_visible,
+                                layer_clipping_mask,
+                                layer_alpha_lock,
+                                layer_blend_mode,
+                            );
+                            layer_masks.set_enabled(idx, properties.mask_enabled);
+                            current.map(|current| LayerEdit::Properties {
+                                layer_index,
+                                properties: current,
+                            })
+                        }
+                        LayerEdit::Reorder { from, to }
+                            if (from.max(to) as usize) < *layer_count =>
+                        {
+                            let mut stack = EngineLayerStack {
+                                layers,
+                                layer_masks,
+                                layer_count,
+                                active_layer_index,
+                                transform_layer_index,
+                                layer_opacity,
+                                layer_visible,
+                                layer_clipping_mask,
+                                layer_alpha_lock,
+                                layer_blend_mode,
+                                layer_groups,
+                                layer_adjustments,
+                                undo,
+                                canvas_width,
+                                canvas_height,
+                            };
+                            stack.move_layer(from as usize, to as usize);
+                            Some(LayerEdit::Reorder { from: to, to: from })
+                        }
+                        LayerEdit::Remove { layer_index }
+                            if *layer_count > 1 && (layer_index as usize) < *layer_count =>
+                        {
+                            let mut stack = EngineLayerStack {
+                                layers,
+                                layer_masks,
+                                layer_count,
+                                active_layer_index,
+                                transform_layer_index,
+                                layer_opacity,
+                                layer_visible,
+                                layer_clipping_mask,
+                                layer_alpha_lock,
+                                layer_blend_mode,
+                                layer_groups,
+                                layer_adjustments,
+                                undo,
+                                canvas_width,
+                                canvas_height,
+                            };
+                            let removed = stack.take_layer(device, queue, layer_index as usize);
+                            Some(LayerEdit::Insert {
+                                layer_index,
+                                layer: Box::new(removed),
+                            })
+                        }
+                        LayerEdit::Insert { layer_index, layer } => {
+                            let top = *layer_count - 1;
+                            let mut stack = EngineLayerStack {
+                                layers,
+                                layer_masks,
+                                layer_count,
+                                active_layer_index,
+                                transform_layer_index,
+                                layer_opacity,
+                                layer_visible,
+                                layer_clipping_mask,
+                                layer_alpha_lock,
+                                layer_blend_mode,
+                                layer_groups,
+                                layer_adjustments,
+                                undo,
+                                canvas_width,
+                                canvas_height,
+                            };
+                            stack.put_layer(device, queue, *layer, layer_index as usize);
+                            Some(LayerEdit::Remove {
+                                layer_index: (layer_index as usize).min(top) as u32,
+                            })
+                        }
+                        LayerEdit::Canvas(mut snapshot) => {
+                            let mut stack = EngineLayerStack {
+                                layers,
+                                layer_masks,
+                                layer_count,
+                                active_layer_index,
+                                transform_layer_index,
+                                layer_opacity,
+                                layer_visible,
+                                layer_clipping_mask,
+                                layer_alpha_lock,
+                                layer_blend_mode,
+                                layer_groups,
+                                layer_adjustments,
+                                undo,
+                                canvas_width,
+                                canvas_height,
+                            };
+                            let size = stack.swap_canvas(
+                                device,
+                                queue,
+                                &mut snapshot,
+                                EnginePresentBindings {
+                                    renderer: present_renderer,
+                                    config_buffer: present_config_buffer,
+                                    transform_buffer: present_transform_buffer,
+                                    params_buffer: present_params_buffer,
+                                    params_capacity: present_params_capacity,
+                                    groups_buffer: present_groups_buffer,
+                                    bind_group: present_bind_group,
+                                    transform_matrix,
+                                    transform_flags,
+                                    view_flags: *present_view_flags,
+                                },
+                            );
+                            if size != (canvas_width, canvas_height) {
+                                *present = None;
+                                *bucket_fill_renderer = None;
+                                *transform_renderer = None;
+                                new_canvas_size = Some(size);
+                            }
+                            Some(LayerEdit::Canvas(snapshot))
+                        }
+                        LayerEdit::Reorder { .. } | LayerEdit::Remove { .. } => None,
+                    };
+                    match inverse {
+                        Some(inverse) if redo => undo.finish_redo(device, queue, inverse),
+                        Some(inverse) => undo.finish_undo(device, queue, inverse),
+                        None => debug::log(
+                            LogLevel::Warn,
+                            format_args!("undo step does not match the current layers; dropped"),
+                        ),
+                    }
                 }
             }
+            // The step may have created, removed or toggled a mask, or moved
+            // layers around.
+            write_present_config(
+                queue,
+                present_config_buffer,
+                present_params_buffer,
+                present_groups_buffer,
+                *layer_count,
+                *present_view_flags,
+                *transform_layer_index,
+                *transform_flags,
+                layer_opacity,
+                layer_visible,
+                layer_clipping_mask,
+                layer_blend_mode,
+                &layer_masks.enabled_flags(),
+                &layer_adjustments.composite(*layer_count),
+                &layer_groups.spans(*layer_count),
+            );
             return EngineCommandOutcome {
                 stop: false,
-                needs_render: applied && present.is_some(),
-                new_canvas_size: None,
+                needs_render: present.is_some(),
+                new_canvas_size,
             };
         }
-        EngineCommand::Redo => {
-            let applied = undo.redo(device, queue, layers.texture(), *layer_count);
-            if applied {
-                for entry in layer_uniform.iter_mut() {
-                    *entry = None;
-                }
+        EngineCommand::ClearUndoHistory => undo.reset(),
+        EngineCommand::SetUndoLimits {
+            max_steps,
+            budget_bytes,
+        } => undo.set_limits(device, queue, max_steps as usize, budget_bytes),
+        EngineCommand::BeginUndoTransaction => undo.begin_transaction(),
+        EngineCommand::CommitUndoTransaction => undo.commit_transaction(device, queue),
+        EngineCommand::SetHistoryCheckpoint { name } => undo.set_checkpoint(name),
+        EngineCommand::PlanHistoryRoute { node_id, reply } => {
+            let _ = reply.send(undo.history_route(node_id));
+        }
+        EngineCommand::EnterHistoryBranch { node_id } => {
+            if !undo.enter_history_branch(node_id) {
+                debug::log(
+                    LogLevel::Warn,
+                    format_args!("history node {node_id} does not start a branch"),
+                );
+            }
+        }
+        EngineCommand::ReadHistory { reply } => {
+            let _ = reply.send(undo.history_nodes(device, queue));
+        }
+        EngineCommand::CancelUndoTransaction => {
+            if !undo.cancel_transaction(device, queue, layers, layer_masks) {
+                return EngineCommandOutcome {
+                    stop: false,
+                    needs_render: false,
+                    new_canvas_size: None,
+                };
             }
+            // Restored steps may have toggled masks.
+            write_present_config(
+                queue,
+                present_config_buffer,
+                present_params_buffer,
+                present_groups_buffer,
+                *layer_count,
+                *present_view_flags,
+                *transform_layer_index,
+                *transform_flags,
+                layer_opacity,
+                layer_visible,
+                layer_clipping_mask,
+                layer_blend_mode,
+                &layer_masks.enabled_flags(),
+                &layer_adjustments.composite(*layer_count),
+                &layer_groups.spans(*layer_count),
+            );
             return EngineCommandOutcome {
                 stop: false,
-                needs_render: applied && present.is_some(),
+                needs_render: present.is_some(),
                 new_canvas_size: None,
             };
         }
@@ -4996,6 +6968,54 @@ fn handle_engine_command(
     }
 }
 
+/// Reads every pixel of layer `idx`, checking it out of `layers`.
+fn read_store_layer(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layers: &mut LayerStore,
+    idx: usize,
+) -> Result<Vec<u32>, String> {
+    let slot = layers.checkout(device, queue, idx)?;
+    let (width, height) = layers.size();
+    read_r32uint_layer(device, queue, layers.texture(), width, height, slot)
+}
+
+/// Texels of the mask of `idx`; a mask that was never painted is not
+/// checked out.
+fn read_mask_layer(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    masks: &mut LayerMasks,
+    idx: usize,
+) -> Result<Vec<u32>, String> {
+    let (width, height) = masks.size();
+    if !masks.painted(idx) {
+        return Ok(vec![MASK_REVEAL_ALL; (width as usize) * (height as usize)]);
+    }
+    let slot = masks.checkout(device, queue, idx)?;
+    read_r32uint_layer(device, queue, masks.texture(), width, height, slot)
+}
+
+/// Checks layer `idx` out of `layers` for writing and returns its slice in
+/// the working texture; failures are logged.
+fn checkout_layer(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layers: &mut LayerStore,
+    idx: usize,
+) -> Option<u32> {
+    match layers.checkout(device, queue, idx) {
+        Ok(slot) => Some(slot),
+        Err(err) => {
+            debug::log(
+                LogLevel::Warn,
+                format_args!("Layer {idx} checkout failed: {err}"),
+            );
+            None
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 struct EngineCommandOutcome {
     stop: bool,
@@ -5003,7 +7023,7 @@ struct EngineCommandOutcome {
     new_canvas_size: Option<(u32, u32)>,
 }
 
-fn remap_layer_index(index: usize, from: usize, to: usize) -> usize {
+pub(crate) fn remap_layer_index(index: usize, from: usize, to: usize) -> usize {
     if from == to {
         return index;
     }
@@ -5019,107 +7039,427 @@ fn remap_layer_index(index: usize, from: usize, to: usize) -> usize {
             return index + 1;
         }
     }
-    index
-}
-
-fn reorder_vec<T>(vec: &mut Vec<T>, from: usize, to: usize) {
-    if from == to || from >= vec.len() {
-        return;
-    }
-    let item = vec.remove(from);
-    let insert_at = to.min(vec.len());
-    vec.insert(insert_at, item);
-}
+    index
+}
+
+pub(crate) fn reorder_vec<T>(vec: &mut Vec<T>, from: usize, to: usize) {
+    if from == to || from >= vec.len() {
+        return;
+    }
+    let item = vec.remove(from);
+    let insert_at = to.min(vec.len());
+    vec.insert(insert_at, item);
+}
+
+/// Undo step of the GPU engine that is not a pixel patch.
+pub(crate) type EngineLayerEdit = LayerEdit<EngineRemovedLayer, EngineCanvasSnapshot>;
+
+/// A layer taken out of the layer array, held by undo until it is put back.
+pub(crate) struct EngineRemovedLayer {
+    pixels: DetachedLayer,
+    // Only held while the layer's mask was painted.
+    mask: Option<wgpu::Texture>,
+    mask_state: LayerMaskState,
+    properties: LayerProperties,
+    adjustment: Option<LayerAdjustment>,
+    group: u32,
+}
+
+impl EngineRemovedLayer {
+    /// VRAM the removed layer keeps while undo holds it.
+    pub(crate) fn resident_bytes(&self) -> u64 {
+        self.pixels.resident_bytes() + self.mask.as_ref().map_or(0, |mask| texture_bytes(mask, 1))
+    }
+}
+
+/// Everything `ResizeCanvas` and `ResetCanvasWithLayers` replace, so undo can
+/// swap the previous canvas back in.
+pub(crate) struct EngineCanvasSnapshot {
+    width: u32,
+    height: u32,
+    layers: LayerStore,
+    masks: LayerMasks,
+    layer_count: usize,
+    opacity: Vec<f32>,
+    visible: Vec<bool>,
+    clipping_mask: Vec<bool>,
+    alpha_lock: Vec<bool>,
+    blend_mode: Vec<u32>,
+    groups: LayerGroups,
+    adjustments: LayerAdjustments,
+    params_buffer: wgpu::Buffer,
+    groups_buffer: wgpu::Buffer,
+    params_capacity: usize,
+}
+
+impl EngineCanvasSnapshot {
+    /// VRAM the canvas keeps while undo holds it.
+    pub(crate) fn resident_bytes(&self) -> u64 {
+        self.layers.resident_bytes() + self.masks.resident_bytes()
+    }
+
+    /// A `width` x `height` canvas of `layer_count` layers: layer 0 filled
+    /// with the background, the rest transparent. Layers keep the compositing
+    /// properties of the current ones in `stack` at the same index.
+    fn blank(
+        device: &wgpu::Device,
+        (width, height): (u32, u32),
+        layer_count: usize,
+        background_color_argb: u32,
+        stack: &EngineLayerStack,
+    ) -> Result<Self, String> {
+        let capacity = stack.layers.capacity().max(layer_count);
+        let layers = LayerStore::new(device, width, height, capacity)
+            .map_err(|err| format!("layer init failed: {err}"))?;
+        let params_capacity = layers.capacity();
+        let params_buffer = create_present_params_buffer(device, params_capacity)
+            .map_err(|err| format!("present params buffer init failed: {err}"))?;
+        let groups_buffer = create_present_groups_buffer(device, params_capacity)
+            .map_err(|err| format!("present groups buffer init failed: {err}"))?;
+
+        let mut snapshot = Self {
+            width,
+            height,
+            layers,
+            masks: LayerMasks::new(device, width, height, layer_count),
+            layer_count,
+            opacity: Vec::with_capacity(layer_count),
+            visible: Vec::with_capacity(layer_count),
+            clipping_mask: Vec::with_capacity(layer_count),
+            alpha_lock: Vec::with_capacity(layer_count),
+            blend_mode: Vec::with_capacity(layer_count),
+            groups: LayerGroups::new(layer_count),
+            adjustments: LayerAdjustments::new(layer_count),
+            params_buffer,
+            groups_buffer,
+            params_capacity,
+        };
+        for idx in 0..layer_count {
+            let fill = if idx == 0 {
+                background_color_argb
+            } else {
+                0x00000000
+            };
+            snapshot.layers.push_layer(fill);
+            let layer_properties = stack.properties(idx).unwrap_or(LayerProperties {
+                opacity: 1.0,
+                visible: true,
+                clipping_mask: false,
+                alpha_locked: false,
+                blend_mode: 0,
+                mask_enabled: false,
+            });
+            snapshot.opacity.push(layer_properties.opacity);
+            snapshot.visible.push(layer_properties.visible);
+            snapshot.clipping_mask.push(layer_properties.clipping_mask);
+            snapshot.alpha_lock.push(layer_properties.alpha_locked);
+            snapshot.blend_mode.push(layer_properties.blend_mode);
+        }
+        Ok(snapshot)
+    }
+}
+
+fn layer_properties(
+    idx: usize,
+    layer_opacity: &[f32],
+    layer_visible: &[bool],
+    layer_clipping_mask: &[bool],
+    layer_alpha_lock: &[bool],
+    layer_blend_mode: &[u32],
+    layer_masks: &LayerMasks,
+) -> Option<LayerProperties> {
+    Some(LayerProperties {
+        opacity: *layer_opacity.get(idx)?,
+        visible: *layer_visible.get(idx)?,
+        clipping_mask: *layer_clipping_mask.get(idx)?,
+        alpha_locked: *layer_alpha_lock.get(idx)?,
+        blend_mode: *layer_blend_mode.get(idx)?,
+        mask_enabled: layer_masks.state(idx).enabled,
+    })
+}
+
+fn set_layer_properties(
+    idx: usize,
+    properties: LayerProperties,
+    layer_opacity: &mut [f32],
+    layer_visible: &mut [bool],
+    layer_clipping_mask: &mut [bool],
+    layer_alpha_lock: &mut [bool],
+    layer_blend_mode: &mut [u32],
+) {
+    if idx >= layer_opacity.len() {
+        return;
+    }
+    layer_opacity[idx] = properties.opacity;
+    layer_visible[idx] = properties.visible;
+    layer_clipping_mask[idx] = properties.clipping_mask;
+    layer_alpha_lock[idx] = properties.alpha_locked;
+    layer_blend_mode[idx] = properties.blend_mode;
+}
+
+/// The layer array of the render thread together with every piece of
+/// per-layer state that travels with a layer when it moves, is removed or
+/// is put back, and the undo history whose layer indices follow it.
+struct EngineLayerStack<'a> {
+    layers: &'a mut LayerStore,
+    layer_masks: &'a mut LayerMasks,
+    layer_count: &'a mut usize,
+    active_layer_index: &'a mut usize,
+    transform_layer_index: &'a mut u32,
+    layer_opacity: &'a mut Vec<f32>,
+    layer_visible: &'a mut Vec<bool>,
+    layer_clipping_mask: &'a mut Vec<bool>,
+    layer_alpha_lock: &'a mut Vec<bool>,
+    layer_blend_mode: &'a mut Vec<u32>,
+    layer_groups: &'a mut LayerGroups,
+    layer_adjustments: &'a mut LayerAdjustments,
+    undo: &'a mut UndoManager,
+    canvas_width: u32,
+    canvas_height: u32,
+}
+
+/// The present pass bindings that have to follow the layer array when the
+/// whole canvas is swapped.
+struct EnginePresentBindings<'a> {
+    renderer: &'a PresentRenderer,
+    config_buffer: &'a wgpu::Buffer,
+    transform_buffer: &'a wgpu::Buffer,
+    params_buffer: &'a mut wgpu::Buffer,
+    params_capacity: &'a mut usize,
+    groups_buffer: &'a mut wgpu::Buffer,
+    bind_group: &'a mut wgpu::BindGroup,
+    transform_matrix: &'a mut [f32; 16],
+    transform_flags: &'a mut u32,
+    view_flags: u32,
+}
+
+impl EngineLayerStack<'_> {
+    fn properties(&self, idx: usize) -> Option<LayerProperties> {
+        layer_properties(
+            idx,
+            self.layer_opacity,
+            self.layer_visible,
+            self.layer_clipping_mask,
+            self.layer_alpha_lock,
+            self.layer_blend_mode,
+            self.layer_masks,
+        )
+    }
+
+    fn set_properties(&mut self, idx: usize, properties: LayerProperties) {
+        set_layer_properties(
+            idx,
+            properties,
+            self.layer_opacity,
+            self.layer_visible,
+            self.layer_clipping_mask,
+            self.layer_alpha_lock,
+            self.layer_blend_mode,
+        );
+        self.layer_masks.set_enabled(idx, properties.mask_enabled);
+    }
+
+    /// Moves the layer at `from` to `to` (both final positions) together
+    /// with all of its per-layer state. The caller rewrites the present
+    /// config.
+    fn move_layer(&mut self, from: usize, to: usize) {
+        if from == to {
+            return;
+        }
+        reorder_vec(self.layer_opacity, from, to);
+        reorder_vec(self.layer_visible, from, to);
+        reorder_vec(self.layer_clipping_mask, from, to);
+        reorder_vec(self.layer_alpha_lock, from, to);
+        reorder_vec(self.layer_blend_mode, from, to);
+        self.layer_groups.reorder_layer(from, to);
+        self.layer_masks.reorder_layer(from, to);
+        self.layer_adjustments.reorder_layer(from, to);
+
+        self.layers.move_layer(from, to);
+
+        *self.active_layer_index = remap_layer_index(*self.active_layer_index, from, to);
+        let transform_layer_index = *self.transform_layer_index as usize;
+        *self.transform_layer_index = if transform_layer_index < *self.layer_count {
+            remap_layer_index(transform_layer_index, from, to) as u32
+        } else {
+            0
+        };
+        self.undo.reorder_layers(from as u32, to as u32);
+    }
+
+    /// Takes the layer at `idx` out of the array, moving the layers above it
+    /// down by one.
+    fn take_layer(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        idx: usize,
+    ) -> EngineRemovedLayer {
+        let top = *self.layer_count - 1;
+        self.move_layer(idx, top);
+        let mask_state = self.layer_masks.state(top);
+        let mask = self.layer_masks.take(device, queue, top).unwrap_or_else(|err| {
+            debug::log(
+                LogLevel::Warn,
+                format_args!("layer {top} mask detach failed: {err}"),
+            );
+            None
+        });
+        let pixels = self.layers.take(device, queue, top).unwrap_or_else(|err| {
+            debug::log(
+                LogLevel::Warn,
+                format_args!("layer {top} detach failed: {err}"),
+            );
+            DetachedLayer::transparent()
+        });
+        let removed = EngineRemovedLayer {
+            pixels,
+            mask,
+            mask_state,
+            properties: self.properties(top).unwrap_or(LayerProperties {
+                opacity: 1.0,
+                visible: true,
+                clipping_mask: false,
+                alpha_locked: false,
+                blend_mode: 0,
+                mask_enabled: false,
+            }),
+            adjustment: self.layer_adjustments.get(top),
+            group: self.layer_groups.layer_group(top),
+        };
+        // Shrink the per-layer state too, so a layer added later starts from
+        // defaults instead of inheriting this one's.
+        self.layer_opacity.truncate(top);
+        self.layer_visible.truncate(top);
+        self.layer_clipping_mask.truncate(top);
+        self.layer_alpha_lock.truncate(top);
+        self.layer_blend_mode.truncate(top);
+        self.layer_groups.resize_layers(top);
+        self.layer_masks.resize_layers(top);
+        self.layer_adjustments.resize_layers(top);
+        *self.layer_count = top;
+        *self.active_layer_index = (*self.active_layer_index).min(top.saturating_sub(1));
+        if *self.transform_layer_index as usize >= top {
+            *self.transform_layer_index = 0;
+        }
+        removed
+    }
 
-fn reorder_layer_textures(
-    device: &wgpu::Device,
-    queue: &wgpu::Queue,
-    texture: &wgpu::Texture,
-    width: u32,
-    height: u32,
-    from: usize,
-    to: usize,
-) {
-    if from == to || width == 0 || height == 0 {
-        return;
+    /// Puts `layer` back at `idx`. The caller has just grown the array by the
+    /// top slot this fills before moving it down.
+    fn put_layer(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layer: EngineRemovedLayer,
+        idx: usize,
+    ) {
+        let top = *self.layer_count - 1;
+        if let Err(err) = self.layers.restore(device, queue, top, layer.pixels) {
+            debug::log(
+                LogLevel::Warn,
+                format_args!("layer {top} restore failed: {err}"),
+            );
+        }
+        if let Err(err) = self.layer_masks.restore(device, queue, top, layer.mask) {
+            debug::log(
+                LogLevel::Warn,
+                format_args!("layer {top} mask restore failed: {err}"),
+            );
+        }
+        self.layer_masks.set_state(top, layer.mask_state);
+        self.set_properties(top, layer.properties);
+        self.layer_adjustments.restore(top, layer.adjustment);
+        // The group may have been deleted since; the layer then stays at the
+        // root.
+        self.layer_groups.set_layer_group(top, layer.group);
+        self.move_layer(top, idx.min(top));
     }
 
-    let scratch = device.create_texture(&wgpu::TextureDescriptor {
-        label: Some("misa-rin layer reorder scratch"),
-        size: wgpu::Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        },
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format: LAYER_TEXTURE_FORMAT,
-        usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
-        view_formats: &[],
-    });
-    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-        label: Some("misa-rin layer reorder encoder"),
-    });
-    let extent = wgpu::Extent3d {
-        width,
-        height,
-        depth_or_array_layers: 1,
-    };
-    let copy_layer = |encoder: &mut wgpu::CommandEncoder,
-                      src_texture: &wgpu::Texture,
-                      src_layer: u32,
-                      dst_texture: &wgpu::Texture,
-                      dst_layer: u32| {
-        encoder.copy_texture_to_texture(
-            wgpu::ImageCopyTexture {
-                texture: src_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d {
-                    x: 0,
-                    y: 0,
-                    z: src_layer,
-                },
-                aspect: wgpu::TextureAspect::All,
-            },
-            wgpu::ImageCopyTexture {
-                texture: dst_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d {
-                    x: 0,
-                    y: 0,
-                    z: dst_layer,
-                },
-                aspect: wgpu::TextureAspect::All,
-            },
-            extent,
-        );
-    };
-
-    copy_layer(&mut encoder, texture, from as u32, &scratch, 0);
-    if from < to {
-        for idx in from..to {
-            copy_layer(
-                &mut encoder,
-                texture,
-                (idx + 1) as u32,
-                texture,
-                idx as u32,
+    /// Swaps `snapshot` with the engine's canvas and rebinds the present pass
+    /// to it. Returns the new canvas size; the caller drops the renderers
+    /// sized for the old one when it changed.
+    fn swap_canvas(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        snapshot: &mut EngineCanvasSnapshot,
+        present: EnginePresentBindings,
+    ) -> (u32, u32) {
+        // The outgoing store is kept by undo, so it gives its working
+        // texture back.
+        if let Err(err) = self.layers.park(device, queue) {
+            debug::log(
+                LogLevel::Warn,
+                format_args!("layer store park failed: {err}"),
             );
         }
-    } else {
-        for idx in (to..from).rev() {
-            copy_layer(
-                &mut encoder,
-                texture,
-                idx as u32,
-                texture,
-                (idx + 1) as u32,
+        if let Err(err) = self.layer_masks.park(device, queue) {
+            debug::log(
+                LogLevel::Warn,
+                format_args!("layer mask park failed: {err}"),
             );
         }
+        std::mem::swap(self.layers, &mut snapshot.layers);
+        std::mem::swap(self.layer_masks, &mut snapshot.masks);
+        std::mem::swap(self.layer_count, &mut snapshot.layer_count);
+        std::mem::swap(self.layer_opacity, &mut snapshot.opacity);
+        std::mem::swap(self.layer_visible, &mut snapshot.visible);
+        std::mem::swap(self.layer_clipping_mask, &mut snapshot.clipping_mask);
+        std::mem::swap(self.layer_alpha_lock, &mut snapshot.alpha_lock);
+        std::mem::swap(self.layer_blend_mode, &mut snapshot.blend_mode);
+        std::mem::swap(self.layer_groups, &mut snapshot.groups);
+        std::mem::swap(self.layer_adjustments, &mut snapshot.adjustments);
+        std::mem::swap(present.params_buffer, &mut snapshot.params_buffer);
+        std::mem::swap(present.groups_buffer, &mut snapshot.groups_buffer);
+        std::mem::swap(present.params_capacity, &mut snapshot.params_capacity);
+        let new_size = (snapshot.width, snapshot.height);
+        snapshot.width = self.canvas_width;
+        snapshot.height = self.canvas_height;
+        (self.canvas_width, self.canvas_height) = new_size;
+        // Mask editing is a mode of the session, not of the canvas.
+        self.layer_masks.set_editing(false);
+
+        *present.bind_group = present.renderer.create_bind_group(
+            device,
+            self.layers,
+            present.config_buffer,
+            present.params_buffer,
+            present.transform_buffer,
+            present.groups_buffer,
+            self.layer_masks,
+        );
+        *self.active_layer_index =
+            (*self.active_layer_index).min(self.layer_count.saturating_sub(1));
+        *self.transform_layer_index = 0;
+        *present.transform_flags = 0;
+        *present.transform_matrix = [
+            1.0, 0.0, 0.0, 0.0, //
+            0.0, 1.0, 0.0, 0.0, //
+            0.0, 0.0, 1.0, 0.0, //
+            0.0, 0.0, 0.0, 1.0, //
+        ];
+        write_present_transform(queue, present.transform_buffer, *present.transform_matrix);
+        write_present_config(
+            queue,
+            present.config_buffer,
+            present.params_buffer,
+            present.groups_buffer,
+            *self.layer_count,
+            present.view_flags,
+            *self.transform_layer_index,
+            *present.transform_flags,
+            self.layer_opacity,
+            self.layer_visible,
+            self.layer_clipping_mask,
+            self.layer_blend_mode,
+            &self.layer_masks.enabled_flags(),
+            &self.layer_adjustments.composite(*self.layer_count),
+            &self.layer_groups.spans(*self.layer_count),
+        );
+        self.undo.set_canvas_size(new_size.0, new_size.1);
+        new_size
     }
-    copy_layer(&mut encoder, &scratch, 0, texture, to as u32);
-    queue.submit(Some(encoder.finish()));
 }
 
 fn ensure_brush<'a>(
@@ -5161,6 +7501,35 @@ fn ensure_filter_renderer<'a>(
     Ok(renderer_ref)
 }
 
+/// Saves layer `slot` for an edit to an alpha-locked layer, creating the
+/// renderer on first use. `None` when the layer is not locked.
+fn save_locked_alpha<'a>(
+    alpha_lock_renderer: &'a mut Option<AlphaLockRenderer>,
+    locked: bool,
+    device: &Arc<wgpu::Device>,
+    queue: &Arc<wgpu::Queue>,
+    texture: &wgpu::Texture,
+    canvas_size: (u32, u32),
+    slot: u32,
+) -> Result<Option<&'a AlphaLockRenderer>, String> {
+    if !locked {
+        return Ok(None);
+    }
+    if alpha_lock_renderer.as_ref().map(AlphaLockRenderer::size) != Some(canvas_size) {
+        *alpha_lock_renderer = Some(AlphaLockRenderer::new(
+            device.clone(),
+            queue.clone(),
+            canvas_size.0,
+            canvas_size.1,
+        )?);
+    }
+    let renderer = alpha_lock_renderer
+        .as_ref()
+        .ok_or_else(|| "alpha lock renderer missing after init".to_string())?;
+    renderer.save(texture, slot)?;
+    Ok(Some(renderer))
+}
+
 fn ensure_transform_renderer<'a>(
     transform_renderer: &'a mut Option<LayerTransformRenderer>,
     device: &Arc<wgpu::Device>,
@@ -5175,7 +7544,7 @@ fn ensure_transform_renderer<'a>(
         .ok_or_else(|| "transform renderer missing after init".to_string())
 }
 
-fn translation_matrix(delta_x: i32, delta_y: i32) -> [f32; 16] {
+pub(crate) fn translation_matrix(delta_x: i32, delta_y: i32) -> [f32; 16] {
     let tx = -(delta_x as f32);
     let ty = -(delta_y as f32);
     [
@@ -5186,67 +7555,6 @@ fn translation_matrix(delta_x: i32, delta_y: i32) -> [f32; 16] {
     ]
 }
 
-fn fill_r32uint_texture(
-    queue: &wgpu::Queue,
-    texture: &wgpu::Texture,
-    width: u32,
-    height: u32,
-    layer_index: u32,
-    value: u32,
-) {
-    if width == 0 || height == 0 {
-        return;
-    }
-
-    const BYTES_PER_PIXEL: u32 = 4;
-    const COPY_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
-    // Keep temporary allocations bounded even for very large canvases.
-    const MAX_CHUNK_BYTES: usize = 4 * 1024 * 1024;
-
-    let bytes_per_row_unpadded = width.saturating_mul(BYTES_PER_PIXEL);
-    let bytes_per_row_padded = align_up_u32(bytes_per_row_unpadded, COPY_BYTES_PER_ROW_ALIGNMENT);
-    if bytes_per_row_padded == 0 {
-        return;
-    }
-
-    let row_bytes = bytes_per_row_padded as usize;
-    let rows_per_chunk = (MAX_CHUNK_BYTES / row_bytes).max(1) as u32;
-    let texels_per_row = (bytes_per_row_padded / BYTES_PER_PIXEL) as usize;
-
-    let mut y: u32 = 0;
-    while y < height {
-        let chunk_h = (height - y).min(rows_per_chunk);
-        let texel_count = texels_per_row.saturating_mul(chunk_h as usize);
-        let data: Vec<u32> = vec![value; texel_count];
-
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d {
-                    x: 0,
-                    y,
-                    z: layer_index,
-                },
-                aspect: wgpu::TextureAspect::All,
-            },
-            bytemuck::cast_slice(&data),
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(bytes_per_row_padded),
-                rows_per_image: Some(chunk_h),
-            },
-            wgpu::Extent3d {
-                width,
-                height: chunk_h,
-                depth_or_array_layers: 1,
-            },
-        );
-
-        y = y.saturating_add(chunk_h);
-    }
-}
-
 fn align_up_u32(value: u32, alignment: u32) -> u32 {
     if alignment == 0 {
         return value;
@@ -5267,6 +7575,45 @@ fn read_r32uint_layer(
     height: u32,
     layer_index: u32,
 ) -> Result<Vec<u32>, String> {
+    read_r32uint_region(device, queue, texture, layer_index, (0, 0, width, height))
+}
+
+fn write_r32uint_layer(
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    width: u32,
+    height: u32,
+    layer_index: u32,
+    pixels: &[u32],
+) -> Result<(), String> {
+    let bytes_per_row_unpadded = width
+        .checked_mul(4)
+        .ok_or_else(|| "layer row size overflow".to_string())?;
+    let bytes_per_row_padded = align_up_u32(bytes_per_row_unpadded, 256);
+    let packed = pack_u32_rows_with_padding(pixels, width, height, bytes_per_row_padded)?;
+    write_r32uint_region(
+        queue,
+        texture,
+        0,
+        0,
+        width,
+        height,
+        layer_index,
+        bytes_per_row_padded,
+        &packed,
+    );
+    Ok(())
+}
+
+/// Reads back `(left, top, width, height)` of one layer as tightly packed rows.
+pub(crate) fn read_r32uint_region(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    layer_index: u32,
+    rect: (u32, u32, u32, u32),
+) -> Result<Vec<u32>, String> {
+    let (left, top, width, height) = rect;
     if width == 0 || height == 0 {
         return Ok(Vec::new());
     }
@@ -5276,14 +7623,14 @@ fn read_r32uint_layer(
 
     let bytes_per_row_unpadded = width
         .checked_mul(BYTES_PER_PIXEL)
-        .ok_or_else(|| "read_r32uint_layer: bytes_per_row overflow".to_string())?;
+        .ok_or_else(|| "read_r32uint_region: bytes_per_row overflow".to_string())?;
     let bytes_per_row_padded = align_up_u32(bytes_per_row_unpadded, COPY_BYTES_PER_ROW_ALIGNMENT);
     if bytes_per_row_padded == 0 {
-        return Err("read_r32uint_layer: bytes_per_row_padded == 0".to_string());
+        return Err("read_r32uint_region: bytes_per_row_padded == 0".to_string());
     }
     let readback_size = (bytes_per_row_padded as u64)
         .checked_mul(height as u64)
-        .ok_or_else(|| "read_r32uint_layer: readback_size overflow".to_string())?;
+        .ok_or_else(|| "read_r32uint_region: readback_size overflow".to_string())?;
 
     let readback = device.create_buffer(&wgpu::BufferDescriptor {
         label: Some("misa-rin canvas layer readback"),
@@ -5300,8 +7647,8 @@ fn read_r32uint_layer(
             texture,
             mip_level: 0,
             origin: wgpu::Origin3d {
-                x: 0,
-                y: 0,
+                x: left,
+                y: top,
                 z: layer_index,
             },
             aspect: wgpu::TextureAspect::All,
@@ -5331,8 +7678,8 @@ fn read_r32uint_layer(
 
     let map_status: Result<(), String> = match rx.recv() {
         Ok(Ok(())) => Ok(()),
-        Ok(Err(e)) => Err(format!("read_r32uint_layer: map_async failed: {e:?}")),
-        Err(e) => Err(format!("read_r32uint_layer: map_async channel failed: {e}")),
+        Ok(Err(e)) => Err(format!("read_r32uint_region: map_async failed: {e:?}")),
+        Err(e) => Err(format!("read_r32uint_region: map_async channel failed: {e}")),
     };
 
     let mut result: Option<Vec<u32>> = None;
@@ -5584,7 +7931,7 @@ fn unpack_u8_rows_without_padding(
     Ok(out)
 }
 
-fn composite_layers_for_bucket_fill(
+pub(crate) fn composite_layers_for_bucket_fill(
     width: usize,
     height: usize,
     layers_pixels: &[Vec<u32>],
@@ -5738,7 +8085,23 @@ pub(crate) fn create_engine(width: u32, height: u32) -> Result<u64, String> {
         return Err("engine_create: width/height must be > 0".to_string());
     }
 
-    let ctx = device_context()?;
+    if std::env::var_os(CPU_ENGINE_ENV).is_some() {
+        debug::log(
+            LogLevel::Info,
+            format_args!("engine_create: {CPU_ENGINE_ENV} set, using CPU backend"),
+        );
+        return create_cpu_engine(width, height);
+    }
+    let ctx = match device_context() {
+        Ok(ctx) => ctx,
+        Err(err) => {
+            debug::log(
+                LogLevel::Warn,
+                format_args!("engine_create: GPU unavailable ({err}), falling back to CPU backend"),
+            );
+            return create_cpu_engine(width, height);
+        }
+    };
 
     let mtl_device_ptr = mtl_device_ptr(ctx.device.as_ref()) as usize;
     #[cfg(any(target_os = "macos", target_os = "ios"))]
@@ -5746,7 +8109,7 @@ pub(crate) fn create_engine(width: u32, height: u32) -> Result<u64, String> {
         return Err("wgpu: failed to extract underlying MTLDevice".to_string());
     }
 
-    let layers = LayerTextures::new(ctx.device.as_ref(), width, height, INITIAL_LAYER_CAPACITY)
+    let layers = LayerStore::new(ctx.device.as_ref(), width, height, INITIAL_LAYER_CAPACITY)
         .map_err(|err| format!("engine_create: layer init failed: {err}"))?;
 
     let (cmd_tx, cmd_rx) = mpsc::channel();
@@ -5754,6 +8117,8 @@ pub(crate) fn create_engine(width: u32, height: u32) -> Result<u64, String> {
     let input_queue_len = Arc::new(AtomicU64::new(0));
     let frame_ready = Arc::new(AtomicBool::new(false));
     let frame_in_flight = Arc::new(AtomicBool::new(false));
+    let journal = open_engine_journal(JOURNAL_BACKEND_GPU, width, height);
+    let events = EngineEventSink::default();
     spawn_render_thread(
         Arc::clone(&ctx.device),
         Arc::clone(&ctx.queue),
@@ -5765,24 +8130,120 @@ pub(crate) fn create_engine(width: u32, height: u32) -> Result<u64, String> {
         Arc::clone(&input_queue_len),
         width,
         height,
+        journal,
+        events.clone(),
+    );
+
+    register_engine(EngineEntry {
+        backend: EngineBackend::Gpu,
+        mtl_device_ptr,
+        frame_ready,
+        frame_in_flight,
+        cmd_tx,
+        input_tx,
+        input_queue_len,
+        events,
+        calibrations: Arc::default(),
+    })
+}
+
+/// Feeds a GPU engine journal into a fresh GPU engine, in the order the
+/// original render thread processed it, and returns the resulting layers.
+///
+/// Every input batch is followed by a read that waits for it to be consumed,
+/// so the render thread sees commands and input in the recorded order.
+pub(crate) fn replay_journal_on_gpu(log: JournalLog) -> Result<ReplayedCanvas, String> {
+    let ctx = device_context().map_err(|err| {
+        format!("journal was recorded on the GPU engine and no GPU is available: {err}")
+    })?;
+    let layers =
+        LayerStore::new(ctx.device.as_ref(), log.width, log.height, INITIAL_LAYER_CAPACITY)
+        .map_err(|err| format!("journal replay: layer init failed: {err}"))?;
+    let (cmd_tx, cmd_rx) = mpsc::channel();
+    let (input_tx, input_rx) = mpsc::channel();
+    let input_queue_len = Arc::new(AtomicU64::new(0));
+    spawn_render_thread(
+        Arc::clone(&ctx.device),
+        Arc::clone(&ctx.queue),
+        layers,
+        cmd_rx,
+        input_rx,
+        Arc::new(AtomicBool::new(false)),
+        Arc::new(AtomicBool::new(false)),
+        Arc::clone(&input_queue_len),
+        log.width,
+        log.height,
+        None,
+        EngineEventSink::default(),
     );
+    let lost = || "journal replay: GPU render thread stopped".to_string();
+
+    for record in log.records {
+        match record.entry {
+            JournalEntry::Command(EngineCommand::Stop) => break,
+            JournalEntry::Command(cmd) => cmd_tx.send(cmd).map_err(|_| lost())?,
+            JournalEntry::Input { points, .. } => {
+                if points.is_empty() {
+                    continue;
+                }
+                input_queue_len.fetch_add(points.len() as u64, Ordering::Relaxed);
+                input_tx
+                    .send(EngineInputBatch { points })
+                    .map_err(|_| lost())?;
+                // Without a present target this replies `None` as soon as the
+                // batch has been drawn.
+                let (reply, rx) = mpsc::channel();
+                cmd_tx
+                    .send(EngineCommand::ReadPresent { reply })
+                    .map_err(|_| lost())?;
+                rx.recv().map_err(|_| lost())?;
+            }
+        }
+    }
+
+    // Any command settles a streamline stroke that is still animating.
+    let (reply, rx) = mpsc::channel();
+    cmd_tx
+        .send(EngineCommand::GetLayerBounds { layer_index: 0, reply })
+        .map_err(|_| lost())?;
+    rx.recv().map_err(|_| lost())?;
+    let (reply, rx) = mpsc::channel();
+    cmd_tx
+        .send(EngineCommand::ReadProject { reply })
+        .map_err(|_| lost())?;
+    let snapshot = rx
+        .recv()
+        .map_err(|_| lost())?
+        .ok_or_else(|| "journal replay: project readback failed".to_string())?;
+    let _ = cmd_tx.send(EngineCommand::Stop);
+
+    Ok(ReplayedCanvas {
+        width: snapshot.width,
+        height: snapshot.height,
+        layers: snapshot
+            .layers
+            .into_iter()
+            .map(|layer| GpuLayerData {
+                pixels: layer.pixels,
+                opacity: layer.opacity as f64,
+                blend_mode_index: layer.blend_mode_index,
+                visible: layer.visible,
+                clipping_mask: layer.clipping_mask,
+                mask: layer
+                    .mask
+                    .filter(|mask| mask.enabled)
+                    .map(|mask| mask.coverage),
+            })
+            .collect(),
+    })
+}
 
+pub(crate) fn register_engine(entry: EngineEntry) -> Result<u64, String> {
     let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
     let mut guard = engines()
         .lock()
         .map_err(|_| "engine registry lock poisoned".to_string())?;
-    guard.insert(
-        handle,
-        EngineEntry {
-            mtl_device_ptr,
-            frame_ready,
-            frame_in_flight,
-            cmd_tx,
-            input_tx,
-            input_queue_len,
-        },
-    );
-
+    guard.insert(handle, entry);
     Ok(handle)
 }
 
@@ -5793,12 +8254,15 @@ pub(crate) fn lookup_engine(handle: u64) -> Option<EngineEntry> {
     let guard = engines().lock().ok()?;
     let entry = guard.get(&handle)?;
     Some(EngineEntry {
+        backend: entry.backend,
         mtl_device_ptr: entry.mtl_device_ptr,
         frame_ready: Arc::clone(&entry.frame_ready),
         frame_in_flight: Arc::clone(&entry.frame_in_flight),
         cmd_tx: entry.cmd_tx.clone(),
         input_tx: entry.input_tx.clone(),
         input_queue_len: Arc::clone(&entry.input_queue_len),
+        events: entry.events.clone(),
+        calibrations: Arc::clone(&entry.calibrations),
     })
 }
 
@@ -5809,3 +8273,526 @@ pub(crate) fn remove_engine(handle: u64) -> Option<EngineEntry> {
     let mut guard = engines().lock().ok()?;
     guard.remove(&handle)
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn read_layer(entry: &EngineEntry, layer_index: u32) -> Vec<u32> {
+        let (reply, rx) = mpsc::channel();
+        entry
+            .cmd_tx
+            .send(EngineCommand::ReadLayer { layer_index, reply })
+            .unwrap();
+        rx.recv_timeout(Duration::from_secs(5)).unwrap().unwrap()
+    }
+
+    pub(crate) fn point(x: f32, y: f32, flags: u32) -> EnginePoint {
+        EnginePoint {
+            x,
+            y,
+            pressure: 1.0,
+            _pad0: 0.0,
+            timestamp_us: 0,
+            flags,
+            pointer_id: 0,
+            tilt: 0.0,
+            azimuth: 0.0,
+            rotation: 0.0,
+            device: 0,
+        }
+    }
+
+    /// `SetBrush` for a hard round brush with every option off; tests
+    /// override the fields they exercise.
+    pub(crate) fn brush(color_argb: u32, base_radius: f32) -> EngineCommand {
+        EngineCommand::SetBrush {
+            color_argb,
+            base_radius,
+            use_pressure: false,
+            erase: false,
+            antialias_level: 1,
+            brush_shape: 0,
+            random_rotation: false,
+            smooth_rotation: false,
+            rotation_seed: 0,
+            spacing: 0.15,
+            hardness: 1.0,
+            flow: 1.0,
+            scatter: 0.0,
+            rotation_jitter: 0.0,
+            snap_to_pixel: false,
+            screentone_enabled: false,
+            screentone_spacing: 10.0,
+            screentone_dot_size: 0.6,
+            screentone_rotation: 45.0,
+            screentone_softness: 0.0,
+            screentone_shape: 0,
+            hollow_enabled: false,
+            hollow_ratio: 0.0,
+            hollow_erase_occluded: false,
+            streamline_strength: 0.0,
+            smoothing_mode: 0,
+            stabilizer_strength: 0.0,
+        }
+    }
+
+    /// A GPU engine when an adapter is available, then a CPU engine, so
+    /// behaviour tests cover both backends. Stop each with `stop_engine`.
+    pub(crate) fn test_engines(width: u32, height: u32) -> Vec<u64> {
+        let mut handles = Vec::new();
+        match device_context() {
+            Ok(_) => handles.push(create_engine(width, height).unwrap()),
+            Err(err) => eprintln!("skipping the GPU engine: {err}"),
+        }
+        handles.push(create_cpu_engine(width, height).unwrap());
+        handles
+    }
+
+    pub(crate) fn stop_engine(handle: u64) {
+        let _ = remove_engine(handle).unwrap().cmd_tx.send(EngineCommand::Stop);
+    }
+
+    pub(crate) fn push_points(entry: &EngineEntry, points: Vec<EnginePoint>) {
+        entry
+            .input_queue_len
+            .fetch_add(points.len() as u64, Ordering::Relaxed);
+        entry.input_tx.send(EngineInputBatch { points }).unwrap();
+    }
+
+    #[test]
+    fn deferred_reads_wait_only_for_input_queued_ahead() {
+        let mut reads = DeferredReads::default();
+        reads.defer(EngineCommand::Undo, 3);
+        reads.note_consumed(2);
+        assert!(reads.take_ready().is_empty());
+        // Input queued after the read does not hold it back.
+        reads.note_consumed(40);
+        assert_eq!(reads.take_ready().len(), 1);
+        reads.defer(EngineCommand::Redo, 0);
+        assert_eq!(reads.take_ready().len(), 1);
+    }
+
+    #[test]
+    fn gpu_and_cpu_engines_draw_the_same_strokes() {
+        if let Err(err) = device_context() {
+            eprintln!("skipping: no GPU adapter ({err})");
+            return;
+        }
+        let paint = |handle: u64| {
+            let entry = lookup_engine(handle).unwrap();
+            let send = |cmd: EngineCommand| entry.cmd_tx.send(cmd).unwrap();
+            send(EngineCommand::ResetCanvas {
+                background_color_argb: 0xFFFFFFFF,
+            });
+            send(brush(0xFF203040, 3.0));
+            push_points(
+                &entry,
+                vec![point(6.0, 10.0, 1), point(30.0, 14.0, 2), point(58.0, 10.0, 4)],
+            );
+            // Commands run ahead of queued input; reading syncs the two.
+            read_layer(&entry, 0);
+            let mut hollow = brush(0xFFC02020, 6.0);
+            if let EngineCommand::SetBrush {
+                hollow_enabled,
+                hollow_ratio,
+                ..
+            } = &mut hollow
+            {
+                *hollow_enabled = true;
+                *hollow_ratio = 0.5;
+            }
+            send(hollow);
+            push_points(
+                &entry,
+                vec![point(8.0, 30.0, 1), point(32.0, 26.0, 2), point(56.0, 30.0, 4)],
+            );
+            let pixels = read_layer(&entry, 0);
+            let _ = remove_engine(handle).unwrap().cmd_tx.send(EngineCommand::Stop);
+            (entry.backend, pixels)
+        };
+        let (gpu_backend, gpu) = paint(create_engine(64, 40).unwrap());
+        let (cpu_backend, cpu) = paint(create_cpu_engine(64, 40).unwrap());
+        assert_eq!((gpu_backend, cpu_backend), (EngineBackend::Gpu, EngineBackend::Cpu));
+
+        let channel_diff = |a: u32, b: u32| {
+            (0..4)
+                .map(|shift| ((a >> (shift * 8)) & 0xFF).abs_diff((b >> (shift * 8)) & 0xFF))
+                .max()
+                .unwrap()
+        };
+        // The backends rasterize independently; edges may round differently.
+        for (idx, (&g, &c)) in gpu.iter().zip(&cpu).enumerate() {
+            assert!(
+                channel_diff(g, c) <= 16,
+                "pixel ({}, {}): gpu {g:08x} cpu {c:08x}",
+                idx % 64,
+                idx / 64
+            );
+        }
+        let core = 29 * 64 + 20;
+        assert_eq!((gpu[core], cpu[core]), (0xFFFFFFFF, 0xFFFFFFFF), "hollow core");
+        assert_ne!(gpu[23 * 64 + 20], 0xFFFFFFFF, "hollow ring");
+    }
+
+    #[test]
+    fn alpha_locked_bucket_fills_keep_the_layer_alpha() {
+        if let Err(err) = device_context() {
+            eprintln!("skipping: no GPU adapter ({err})");
+            return;
+        }
+        let (width, height) = (16u32, 8u32);
+        let handle = create_engine(width, height).unwrap();
+        let entry = lookup_engine(handle).unwrap();
+        let send = |cmd: EngineCommand| entry.cmd_tx.send(cmd).unwrap();
+        // Alternating opaque and translucent columns left of x = 6,
+        // transparent right of it.
+        let alpha = |idx: usize| match idx % width as usize {
+            0..=5 if idx.is_multiple_of(2) => 0xFF00_0000,
+            0..=5 => 0x8000_0000,
+            _ => 0,
+        };
+        let pixels = (0..(width * height) as usize)
+            .map(|idx| if alpha(idx) == 0 { 0 } else { alpha(idx) | 0x2040C0 })
+            .collect();
+        let (reply, rx) = mpsc::channel();
+        send(EngineCommand::WriteLayer {
+            layer_index: 0,
+            pixels,
+            record_undo: false,
+            reply,
+        });
+        assert!(rx.recv_timeout(Duration::from_secs(5)).unwrap());
+        send(EngineCommand::SetLayerAlphaLock {
+            layer_index: 0,
+            locked: true,
+        });
+
+        let (reply, rx) = mpsc::channel();
+        send(EngineCommand::BucketFill {
+            layer_index: 0,
+            start_x: 0,
+            start_y: 0,
+            color_argb: 0xFF00FF00,
+            contiguous: false,
+            sample_all_layers: false,
+            tolerance: 255,
+            fill_gap: 0,
+            antialias_level: 0,
+            swallow_colors: Vec::new(),
+            selection_mask: None,
+            reply,
+        });
+        assert!(rx.recv_timeout(Duration::from_secs(5)).unwrap());
+        for (idx, &pixel) in read_layer(&entry, 0).iter().enumerate() {
+            let want = if alpha(idx) == 0 { 0 } else { alpha(idx) | 0x00FF00 };
+            assert_eq!(pixel, want, "pixel ({}, {})", idx % 16, idx / 16);
+        }
+        let _ = remove_engine(handle).unwrap().cmd_tx.send(EngineCommand::Stop);
+    }
+
+    #[test]
+    fn smudge_drags_pre_stroke_colour() {
+        for handle in test_engines(64, 16) {
+            let entry = lookup_engine(handle).unwrap();
+            let send = |cmd: EngineCommand| entry.cmd_tx.send(cmd).unwrap();
+            send(EngineCommand::ResetCanvas {
+                background_color_argb: 0xFFFFFFFF,
+            });
+            send(brush(0xFF000000, 6.0));
+            push_points(
+                &entry,
+                vec![point(4.0, 8.0, 1), point(8.0, 8.0, 2), point(12.0, 8.0, 4)],
+            );
+            // Input and commands arrive on separate channels; let the stroke land.
+            assert_eq!(read_layer(&entry, 0)[8 * 64 + 8], 0xFF000000);
+
+            send(EngineCommand::SetMixing {
+                mode: 1,
+                pickup: 0.1,
+                dilution: 0.0,
+                persistence: 0.0,
+                extension: 0.0,
+            });
+            push_points(
+                &entry,
+                vec![point(8.0, 8.0, 1), point(24.0, 8.0, 2), point(40.0, 8.0, 4)],
+            );
+            let smudged = read_layer(&entry, 0);
+            let backend = entry.backend;
+            assert!(smudged[8 * 64 + 24] & 0xFF < 0xC0, "{backend:?}: black not dragged right");
+            assert_eq!(smudged[8 * 64 + 60], 0xFFFFFFFF, "{backend:?}");
+            stop_engine(handle);
+        }
+    }
+
+    #[test]
+    fn watercolour_wet_edge_darkens_the_rim() {
+        for handle in test_engines(64, 32) {
+            let entry = lookup_engine(handle).unwrap();
+            let send = |cmd: EngineCommand| entry.cmd_tx.send(cmd).unwrap();
+            send(EngineCommand::ResetCanvas {
+                background_color_argb: 0xFFFFFFFF,
+            });
+            send(brush(0xFF000000, 8.0));
+            send(EngineCommand::SetWatercolor {
+                enabled: true,
+                edge: 1.0,
+                granulation: 0.0,
+                bleed: 0.0,
+            });
+            push_points(
+                &entry,
+                vec![point(8.0, 16.0, 1), point(32.0, 16.0, 2), point(56.0, 16.0, 4)],
+            );
+            let pixels = read_layer(&entry, 0);
+            let backend = entry.backend;
+            let middle = pixels[16 * 64 + 32] & 0xFF;
+            let rim = pixels[9 * 64 + 32] & 0xFF;
+            assert!(middle > 0x40 && middle < 0xC0, "{backend:?}: wash {middle:#x} not thinned");
+            assert!(rim < middle, "{backend:?}: rim {rim:#x} not darker than {middle:#x}");
+            assert_eq!(pixels[2 * 64 + 32], 0xFFFFFFFF, "{backend:?}");
+            stop_engine(handle);
+        }
+    }
+
+    #[test]
+    fn dual_tip_multiply_leaves_gaps_between_secondary_dabs() {
+        for handle in test_engines(64, 32) {
+            let entry = lookup_engine(handle).unwrap();
+            let send = |cmd: EngineCommand| entry.cmd_tx.send(cmd).unwrap();
+            send(EngineCommand::ResetCanvas {
+                background_color_argb: 0xFFFFFFFF,
+            });
+            send(brush(0xFF000000, 8.0));
+            // Secondary dabs of radius 2 every 10 pixels.
+            send(EngineCommand::SetDualBrush {
+                enabled: true,
+                blend: 0,
+                shape: 0,
+                scale: 0.25,
+                spacing: 2.5,
+                scatter: 0.0,
+                count: 1,
+                size_jitter: 0.0,
+            });
+            push_points(
+                &entry,
+                vec![point(8.0, 16.0, 1), point(32.0, 16.0, 2), point(56.0, 16.0, 4)],
+            );
+            let pixels = read_layer(&entry, 0);
+            let backend = entry.backend;
+            let row = &pixels[16 * 64 + 8..16 * 64 + 56];
+            assert!(row.iter().any(|&p| p & 0xFF < 0x40), "{backend:?}: no secondary dabs");
+            assert!(row.contains(&0xFFFFFFFF), "{backend:?}: gaps between dabs painted");
+            assert_eq!(pixels[10 * 64 + 32], 0xFFFFFFFF, "{backend:?}");
+            stop_engine(handle);
+        }
+    }
+
+    #[test]
+    fn pen_tilt_grows_dabs_with_tilt_size() {
+        for handle in test_engines(64, 32) {
+            let entry = lookup_engine(handle).unwrap();
+            let send = |cmd: EngineCommand| entry.cmd_tx.send(cmd).unwrap();
+            send(brush(0xFF000000, 3.0));
+            send(EngineCommand::SetPenDynamics {
+                tilt_size: 1.0,
+                tilt_opacity: 0.0,
+                azimuth_angle: false,
+                barrel_angle: false,
+            });
+            // Painted height of a stroke across the middle column.
+            let paint = |tilt: f32| {
+                send(EngineCommand::ResetCanvas {
+                    background_color_argb: 0xFFFFFFFF,
+                });
+                let points = [(8.0, 1), (32.0, 2), (56.0, 4)]
+                    .into_iter()
+                    .map(|(x, flags)| EnginePoint {
+                        tilt,
+                        ..point(x, 16.0, flags)
+                    })
+                    .collect();
+                push_points(&entry, points);
+                let pixels = read_layer(&entry, 0);
+                (0..32).filter(|&y| pixels[y * 64 + 32] != 0xFFFFFFFF).count()
+            };
+            let upright = paint(0.0);
+            let tilted = paint(std::f32::consts::FRAC_PI_2);
+            let backend = entry.backend;
+            assert!(upright >= 4, "{backend:?}: upright stroke painted {upright}");
+            assert!(tilted > upright * 2, "{backend:?}: tilt widened {upright} to {tilted}");
+            stop_engine(handle);
+        }
+    }
+
+    #[test]
+    fn hue_jitter_varies_dabs_and_repeats_on_replay() {
+        for handle in test_engines(64, 32) {
+            let entry = lookup_engine(handle).unwrap();
+            let send = |cmd: EngineCommand| entry.cmd_tx.send(cmd).unwrap();
+            let mut red = brush(0xFFFF0000, 4.0);
+            if let EngineCommand::SetBrush {
+                rotation_seed,
+                spacing,
+                ..
+            } = &mut red
+            {
+                *rotation_seed = 7;
+                *spacing = 0.5;
+            }
+            send(red);
+            send(EngineCommand::SetColorDynamics {
+                hue_jitter: 1.0,
+                saturation_jitter: 0.0,
+                value_jitter: 0.0,
+                opacity_jitter: 0.0,
+                stroke_hue_jitter: 0.0,
+                stroke_saturation_jitter: 0.0,
+                stroke_value_jitter: 0.0,
+                stroke_opacity_jitter: 0.0,
+                background_argb: 0,
+                background_source: 0,
+                background_amount: 0.0,
+            });
+            let paint = || {
+                send(EngineCommand::ResetCanvas {
+                    background_color_argb: 0xFFFFFFFF,
+                });
+                push_points(
+                    &entry,
+                    vec![point(8.0, 16.0, 1), point(32.0, 16.0, 2), point(56.0, 16.0, 4)],
+                );
+                read_layer(&entry, 0)
+            };
+            let pixels = paint();
+            let backend = entry.backend;
+            let row = &pixels[16 * 64 + 8..16 * 64 + 56];
+            assert!(row.iter().all(|&p| p >> 24 == 0xFF), "{backend:?}");
+            assert!(
+                row.iter().any(|&p| (p >> 8) & 0xFF > (p >> 16) & 0xFF),
+                "{backend:?}: every dab stayed red"
+            );
+            assert_eq!(pixels, paint(), "{backend:?}: the seed should repeat the stroke");
+            stop_engine(handle);
+        }
+    }
+
+    /// A `size` x `size` store of `layer_count` layers whose every texel
+    /// differs, so each tile holds an atlas slot.
+    fn painted_store(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: u32,
+        layer_count: usize,
+    ) -> LayerStore {
+        let mut layers = LayerStore::new(device, size, size, layer_count).unwrap();
+        let texels: Vec<u32> = (0..size * size).map(|idx| 0xFF00_0000 | idx).collect();
+        for idx in 0..layer_count {
+            layers.push_layer(0);
+            let slot = layers.checkout(device, queue, idx).unwrap();
+            write_r32uint_layer(queue, layers.texture(), size, size, slot, &texels).unwrap();
+            layers.mark_layer_rewritten(idx);
+        }
+        layers.park(device, queue).unwrap();
+        layers
+    }
+
+    #[test]
+    fn resizes_and_layer_deletes_stay_within_the_undo_budget() {
+        let ctx = match device_context() {
+            Ok(ctx) => ctx,
+            Err(err) => {
+                eprintln!("skipping: no GPU adapter ({err})");
+                return;
+            }
+        };
+        let (device, queue) = (ctx.device.as_ref(), ctx.queue.as_ref());
+        let budget = 8 * 1024 * 1024;
+        let mut undo = UndoManager::new(256, 256);
+        undo.set_limits(device, queue, 50, budget);
+        for round in 0..8u32 {
+            let size = 256 + 64 * (round % 3);
+            let mut layers = painted_store(device, queue, size, 3);
+            // Deleting layer 1 keeps its tiles for undo.
+            let pixels = layers.take(device, queue, 1).unwrap();
+            undo.record_edit(
+                device,
+                queue,
+                LayerEdit::Insert {
+                    layer_index: 1,
+                    layer: Box::new(EngineRemovedLayer {
+                        pixels,
+                        mask: None,
+                        mask_state: LayerMaskState::ABSENT,
+                        properties: LayerProperties {
+                            opacity: 1.0,
+                            visible: true,
+                            clipping_mask: false,
+                            alpha_locked: false,
+                            blend_mode: 0,
+                            mask_enabled: false,
+                        },
+                        adjustment: None,
+                        group: 0,
+                    }),
+                },
+            );
+            assert!(undo.resident_bytes() <= budget, "round {round} after delete");
+
+            // Resizing keeps the whole replaced canvas.
+            let snapshot = EngineCanvasSnapshot {
+                width: size,
+                height: size,
+                layers,
+                masks: LayerMasks::new(device, size, size, 2),
+                layer_count: 2,
+                opacity: vec![1.0; 2],
+                visible: vec![true; 2],
+                clipping_mask: vec![false; 2],
+                alpha_lock: vec![false; 2],
+                blend_mode: vec![0; 2],
+                groups: LayerGroups::new(2),
+                adjustments: LayerAdjustments::new(2),
+                params_buffer: create_present_params_buffer(device, 2).unwrap(),
+                groups_buffer: create_present_groups_buffer(device, 2).unwrap(),
+                params_capacity: 2,
+            };
+            assert!(snapshot.resident_bytes() > 0);
+            undo.record_edit(device, queue, LayerEdit::Canvas(Box::new(snapshot)));
+            assert!(undo.resident_bytes() <= budget, "round {round} after resize");
+        }
+        assert!(undo.can_undo(), "the newest steps that fit are kept");
+    }
+
+    // Linux desktop builds attach no native surface; the engine presents
+    // into its own offscreen target and reads that back.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn ffi_engine_presents_offscreen_without_a_surface() {
+        use crate::canvas_engine::ffi::{
+            engine_attach_present_texture, engine_create, engine_dispose, engine_poll_frame_ready,
+            engine_read_present,
+        };
+
+        if let Err(err) = device_context() {
+            eprintln!("skipping: no GPU adapter ({err})");
+            return;
+        }
+        let handle = engine_create(8, 8);
+        assert_ne!(handle, 0, "engine_create should not be a stub on Linux");
+        assert_eq!(lookup_engine(handle).unwrap().backend, EngineBackend::Gpu);
+        engine_attach_present_texture(handle, std::ptr::null_mut(), 8, 8, 32);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !engine_poll_frame_ready(handle) {
+            assert!(Instant::now() < deadline, "no frame was presented");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let mut bytes = vec![0u8; 8 * 8 * 4];
+        assert_eq!(engine_read_present(handle, bytes.as_mut_ptr(), bytes.len()), 1);
+        assert!(bytes.iter().all(|&b| b == 0xFF), "the white background should present");
+        engine_dispose(handle);
+    }
+}
@@ -0,0 +1,285 @@
+use std::sync::{mpsc, Arc, Mutex};
+
+use crate::api::engine_events::{EngineEvent, EngineEventKind};
+use crate::frb_generated::StreamSink;
+use crate::gpu::debug;
+
+use super::engine::{lookup_engine, EngineCommand};
+use super::undo::{UndoDirtyRect, UndoTarget};
+
+/// Where a subscription delivers events.
+enum EventTarget {
+    Dart(StreamSink<EngineEvent>),
+    /// An in-process receiver, for tests that drain what the engine sends.
+    #[cfg(test)]
+    Channel(mpsc::Sender<EngineEvent>),
+}
+
+impl EventTarget {
+    /// Whether the receiving end is still open.
+    fn add(&self, event: EngineEvent) -> bool {
+        match self {
+            Self::Dart(sink) => sink.add(event).is_ok(),
+            #[cfg(test)]
+            Self::Channel(tx) => tx.send(event).is_ok(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Subscriber {
+    sink: Option<EventTarget>,
+    // Bumped per subscription so the render thread can replay its state.
+    generation: u64,
+}
+
+/// Dart-side subscriber of one engine, shared between its registry entry
+/// and its render thread.
+#[derive(Clone, Default)]
+pub(crate) struct EngineEventSink(Arc<Mutex<Subscriber>>);
+
+impl EngineEventSink {
+    fn subscribe(&self, sink: EventTarget) {
+        if let Ok(mut guard) = self.0.lock() {
+            guard.sink = Some(sink);
+            guard.generation += 1;
+        }
+    }
+
+    /// Subscribes an in-process receiver in place of Dart.
+    #[cfg(test)]
+    pub(crate) fn subscribe_channel(&self) -> mpsc::Receiver<EngineEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribe(EventTarget::Channel(tx));
+        rx
+    }
+
+    /// Generation of the current subscription, `None` while nobody listens.
+    fn generation(&self) -> Option<u64> {
+        let guard = self.0.lock().ok()?;
+        guard.sink.as_ref().map(|_| guard.generation)
+    }
+
+    fn send(&self, event: EngineEvent) {
+        let Ok(mut guard) = self.0.lock() else {
+            return;
+        };
+        // Dart closed the stream; stop encoding events nobody reads.
+        if guard.sink.as_ref().is_some_and(|sink| !sink.add(event)) {
+            guard.sink = None;
+        }
+    }
+}
+
+pub(crate) fn subscribe_engine_events(handle: u64, sink: StreamSink<EngineEvent>) {
+    match lookup_engine(handle) {
+        Some(entry) => entry.events.subscribe(EventTarget::Dart(sink)),
+        None => {
+            let mut event = EngineEvent::new(EngineEventKind::Error);
+            event.message = format!("engine_event_stream: unknown engine handle {handle}");
+            let _ = sink.add(event);
+        }
+    }
+}
+
+/// A bucket fill or filter whose reply is routed through the publisher so
+/// its completion can be reported.
+pub(crate) struct PendingOperation {
+    kind: EngineEventKind,
+    layer_index: u32,
+    reply: mpsc::Sender<bool>,
+    result: mpsc::Receiver<bool>,
+}
+
+/// Render-thread side of [`EngineEventSink`]: turns engine state changes
+/// into events, skipping the work while nobody listens.
+pub(crate) struct EngineEventPublisher {
+    sink: EngineEventSink,
+    // Last undo state sent, and to which subscription.
+    undo_state: Option<(u64, bool, bool)>,
+}
+
+impl EngineEventPublisher {
+    pub(crate) fn new(sink: EngineEventSink) -> Self {
+        Self {
+            sink,
+            undo_state: None,
+        }
+    }
+
+    /// Reports warnings logged on the calling thread as warning events.
+    pub(crate) fn forward_thread_warnings(&self) {
+        let sink = self.sink.clone();
+        debug::set_thread_warn_hook(Some(Box::new(move |message| {
+            let mut event = EngineEvent::new(EngineEventKind::Warning);
+            event.message = message.to_string();
+            sink.send(event);
+        })));
+    }
+
+    pub(crate) fn watch_operation(
+        &self,
+        cmd: EngineCommand,
+    ) -> (EngineCommand, Option<PendingOperation>) {
+        if self.sink.generation().is_none() {
+            return (cmd, None);
+        }
+        let (tx, result) = mpsc::channel();
+        let (cmd, kind, layer_index, reply) = match cmd {
+            EngineCommand::BucketFill {
+                layer_index,
+                start_x,
+                start_y,
+                color_argb,
+                contiguous,
+                sample_all_layers,
+                tolerance,
+                fill_gap,
+                antialias_level,
+                swallow_colors,
+                selection_mask,
+                reply,
+            } => (
+                EngineCommand::BucketFill {
+                    layer_index,
+                    start_x,
+                    start_y,
+                    color_argb,
+                    contiguous,
+                    sample_all_layers,
+                    tolerance,
+                    fill_gap,
+                    antialias_level,
+                    swallow_colors,
+                    selection_mask,
+                    reply: tx,
+                },
+                EngineEventKind::BucketFillFinished,
+                layer_index,
+                reply,
+            ),
+            EngineCommand::ApplyFilter {
+                layer_index,
+                filter_type,
+                param0,
+                param1,
+                param2,
+                param3,
+                reply,
+            } => (
+                EngineCommand::ApplyFilter {
+                    layer_index,
+                    filter_type,
+                    param0,
+                    param1,
+                    param2,
+                    param3,
+                    reply: tx,
+                },
+                EngineEventKind::FilterFinished,
+                layer_index,
+                reply,
+            ),
+            EngineCommand::ApplyAntialias {
+                layer_index,
+                level,
+                reply,
+            } => (
+                EngineCommand::ApplyAntialias {
+                    layer_index,
+                    level,
+                    reply: tx,
+                },
+                EngineEventKind::FilterFinished,
+                layer_index,
+                reply,
+            ),
+            other => return (other, None),
+        };
+        let pending = PendingOperation {
+            kind,
+            layer_index,
+            reply,
+            result,
+        };
+        (cmd, Some(pending))
+    }
+
+    /// Hands the result of a watched operation to its original caller and
+    /// publishes it. Handlers reply before returning, so the result is ready.
+    pub(crate) fn finish_operation(&self, pending: Option<PendingOperation>) {
+        let Some(pending) = pending else {
+            return;
+        };
+        let applied = pending.result.try_recv().unwrap_or(false);
+        let _ = pending.reply.send(applied);
+        let mut event = EngineEvent::new(pending.kind);
+        event.layer_index = pending.layer_index;
+        event.applied = applied;
+        self.sink.send(event);
+    }
+
+    pub(crate) fn publish_undo_state(&mut self, can_undo: bool, can_redo: bool) {
+        let Some(generation) = self.sink.generation() else {
+            return;
+        };
+        let state = (generation, can_undo, can_redo);
+        if self.undo_state == Some(state) {
+            return;
+        }
+        self.undo_state = Some(state);
+        let mut event = EngineEvent::new(EngineEventKind::UndoState);
+        event.can_undo = can_undo;
+        event.can_redo = can_redo;
+        self.sink.send(event);
+    }
+
+    pub(crate) fn publish_layers_changed(&self, changed: bool, width: u32, height: u32) {
+        if !changed {
+            return;
+        }
+        let mut event = EngineEvent::new(EngineEventKind::LayersChanged);
+        event.width = width as i32;
+        event.height = height as i32;
+        self.sink.send(event);
+    }
+
+    /// Publishes the drained undo dirty rects, followed by the new bounds of
+    /// each layer whose pixels changed. `bounds` returns the layer's
+    /// `(left, top, right, bottom)`, or `None` when it is empty.
+    pub(crate) fn publish_dirty(
+        &self,
+        dirty: Vec<UndoDirtyRect>,
+        bounds: impl Fn(u32) -> Option<(u32, u32, u32, u32)>,
+    ) {
+        if dirty.is_empty() || self.sink.generation().is_none() {
+            return;
+        }
+        let mut touched_layers: Vec<u32> = Vec::new();
+        for rect in dirty {
+            let (left, top, width, height) = rect.rect;
+            let mut event = EngineEvent::new(EngineEventKind::LayerDirty);
+            event.layer_index = rect.layer_index;
+            event.left = left as i32;
+            event.top = top as i32;
+            event.width = width as i32;
+            event.height = height as i32;
+            event.on_mask = rect.target == UndoTarget::Mask;
+            self.sink.send(event);
+            if rect.target == UndoTarget::Layer && !touched_layers.contains(&rect.layer_index) {
+                touched_layers.push(rect.layer_index);
+            }
+        }
+        for layer_index in touched_layers {
+            let mut event = EngineEvent::new(EngineEventKind::LayerBounds);
+            event.layer_index = layer_index;
+            if let Some((left, top, right, bottom)) = bounds(layer_index) {
+                event.left = left as i32;
+                event.top = top as i32;
+                event.width = right.saturating_sub(left) as i32;
+                event.height = bottom.saturating_sub(top) as i32;
+            }
+            self.sink.send(event);
+        }
+    }
+}
@@ -3,24 +3,39 @@ use std::os::raw::c_char;
 
 use super::types::{EnginePoint, SprayPoint};
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 use super::engine::{create_engine, lookup_engine, remove_engine, EngineCommand, EngineInputBatch};
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+use super::journal::{replay_journal, set_journal_dir, write_layer_dump};
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+use super::groups::ROOT_LAYER_GROUP;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+use super::history::HistoryMove;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+use super::pressure::PressureCurve;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+use super::rin::{decode_rin, encode_rin, RinDocument};
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+use crate::api::engine_history::EngineHistoryNode;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 use crate::gpu::debug::{self, LogLevel};
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
-use std::ffi::CString;
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+use std::ffi::{CStr, CString};
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+use std::path::PathBuf;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 use std::collections::HashMap;
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 use std::sync::atomic::{AtomicU64, Ordering};
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 use std::sync::{Mutex, OnceLock};
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 use std::sync::mpsc;
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 use std::time::{SystemTime, UNIX_EPOCH};
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_create(width: u32, height: u32) -> u64 {
     match create_engine(width, height) {
@@ -38,13 +53,13 @@ pub extern "C" fn engine_create(width: u32, height: u32) -> u64 {
     }
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_create(_width: u32, _height: u32) -> u64 {
     0
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_get_mtl_device(handle: u64) -> *mut c_void {
     lookup_engine(handle)
@@ -52,13 +67,13 @@ pub extern "C" fn engine_get_mtl_device(handle: u64) -> *mut c_void {
         .unwrap_or(std::ptr::null_mut())
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_get_mtl_device(_handle: u64) -> *mut c_void {
     std::ptr::null_mut()
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_attach_present_texture(
     handle: u64,
@@ -78,7 +93,7 @@ pub extern "C" fn engine_attach_present_texture(
     });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_attach_present_texture(
     _handle: u64,
@@ -170,7 +185,7 @@ pub extern "C" fn engine_create_present_dxgi_surface(
     std::ptr::null_mut()
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_dispose(handle: u64) {
     let Some(entry) = remove_engine(handle) else {
@@ -184,11 +199,11 @@ pub extern "C" fn engine_dispose(handle: u64) {
     let _ = entry.cmd_tx.send(EngineCommand::Stop);
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_dispose(_handle: u64) {}
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[derive(Default)]
 struct FrameReadyPollStats {
     last_log_ms: u64,
@@ -196,21 +211,21 @@ struct FrameReadyPollStats {
     ready_count: u64,
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 static FRAME_READY_STATS: OnceLock<Mutex<HashMap<u64, FrameReadyPollStats>>> = OnceLock::new();
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 static LAST_INPUT_LOG_MS: AtomicU64 = AtomicU64::new(0);
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 static LAST_BRUSH_LOG_MS: AtomicU64 = AtomicU64::new(0);
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 static LAST_FILL_LOG_MS: AtomicU64 = AtomicU64::new(0);
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 fn frame_ready_stats() -> &'static Mutex<HashMap<u64, FrameReadyPollStats>> {
     FRAME_READY_STATS.get_or_init(|| Mutex::new(HashMap::new()))
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 fn now_ms() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -218,7 +233,7 @@ fn now_ms() -> u64 {
         .as_millis() as u64
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_poll_frame_ready(handle: u64) -> bool {
     let Some(entry) = lookup_engine(handle) else {
@@ -251,13 +266,13 @@ pub extern "C" fn engine_poll_frame_ready(handle: u64) -> bool {
     ready
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_poll_frame_ready(_handle: u64) -> bool {
     false
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_push_points(handle: u64, points: *const EnginePoint, len: usize) {
     let Some(entry) = lookup_engine(handle) else {
@@ -269,6 +284,9 @@ pub extern "C" fn engine_push_points(handle: u64, points: *const EnginePoint, le
     let slice = unsafe { std::slice::from_raw_parts(points, len) };
     let mut owned: Vec<EnginePoint> = Vec::with_capacity(len);
     owned.extend_from_slice(slice);
+    if let Ok(calibrations) = entry.calibrations.lock() {
+        calibrations.calibrate(&mut owned);
+    }
     let queue_len = entry
         .input_queue_len
         .fetch_add(len as u64, Ordering::Relaxed)
@@ -326,11 +344,11 @@ pub extern "C" fn engine_push_points(handle: u64, points: *const EnginePoint, le
     }
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_push_points(_handle: u64, _points: *const EnginePoint, _len: usize) {}
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_get_input_queue_len(handle: u64) -> u64 {
     lookup_engine(handle)
@@ -338,19 +356,19 @@ pub extern "C" fn engine_get_input_queue_len(handle: u64) -> u64 {
         .unwrap_or(0)
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_set_log_level(level: u32) {
     debug::set_level_from_u32(level);
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_is_valid(handle: u64) -> u8 {
     if lookup_engine(handle).is_some() { 1 } else { 0 }
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_log_pop() -> *mut c_char {
     match debug::pop_log_line() {
@@ -359,7 +377,7 @@ pub extern "C" fn engine_log_pop() -> *mut c_char {
     }
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_log_free(ptr: *mut c_char) {
     if !ptr.is_null() {
@@ -367,33 +385,90 @@ pub extern "C" fn engine_log_free(ptr: *mut c_char) {
     }
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+fn path_from_c_str(ptr: *const c_char) -> Option<PathBuf> {
+    if ptr.is_null() {
+        return None;
+    }
+    let path = unsafe { CStr::from_ptr(ptr) }.to_str().ok()?;
+    if path.is_empty() {
+        return None;
+    }
+    Some(PathBuf::from(path))
+}
+
+/// Journals every engine created after this call into `dir`. Passing null or
+/// an empty string stops journaling new engines (unless
+/// `MISA_RIN_RUST_JOURNAL_DIR` is set).
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_journal_dir(dir: *const c_char) {
+    set_journal_dir(path_from_c_str(dir));
+}
+
+/// Replays a journal on the backend that recorded it and writes the final
+/// layers to `dump_path`. Returns 0 on failure, including a GPU journal on a
+/// machine without a GPU adapter.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_replay_journal(journal_path: *const c_char, dump_path: *const c_char) -> u8 {
+    let (Some(journal_path), Some(dump_path)) =
+        (path_from_c_str(journal_path), path_from_c_str(dump_path))
+    else {
+        return 0;
+    };
+    let result = replay_journal(&journal_path).and_then(|canvas| {
+        write_layer_dump(&dump_path, canvas.width, canvas.height, &canvas.layers)
+    });
+    match result {
+        Ok(()) => 1,
+        Err(err) => {
+            debug::log(LogLevel::Warn, format_args!("engine_replay_journal failed: {err}"));
+            0
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_get_input_queue_len(_handle: u64) -> u64 {
     0
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_set_log_level(_level: u32) {}
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_is_valid(_handle: u64) -> u8 {
     0
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_log_pop() -> *mut c_char {
     std::ptr::null_mut()
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_log_free(_ptr: *mut c_char) {}
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_journal_dir(_dir: *const c_char) {}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_replay_journal(
+    _journal_path: *const c_char,
+    _dump_path: *const c_char,
+) -> u8 {
+    0
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_set_active_layer(handle: u64, layer_index: u32) {
     let Some(entry) = lookup_engine(handle) else {
@@ -404,11 +479,11 @@ pub extern "C" fn engine_set_active_layer(handle: u64, layer_index: u32) {
         .send(EngineCommand::SetActiveLayer { layer_index });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_set_active_layer(_handle: u64, _layer_index: u32) {}
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_set_layer_opacity(handle: u64, layer_index: u32, opacity: f32) {
     let Some(entry) = lookup_engine(handle) else {
@@ -420,11 +495,11 @@ pub extern "C" fn engine_set_layer_opacity(handle: u64, layer_index: u32, opacit
     });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_set_layer_opacity(_handle: u64, _layer_index: u32, _opacity: f32) {}
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_set_layer_visible(handle: u64, layer_index: u32, visible: bool) {
     let Some(entry) = lookup_engine(handle) else {
@@ -436,11 +511,11 @@ pub extern "C" fn engine_set_layer_visible(handle: u64, layer_index: u32, visibl
     });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_set_layer_visible(_handle: u64, _layer_index: u32, _visible: bool) {}
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_set_layer_clipping_mask(
     handle: u64,
@@ -456,7 +531,7 @@ pub extern "C" fn engine_set_layer_clipping_mask(
     });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_set_layer_clipping_mask(
     _handle: u64,
@@ -465,7 +540,25 @@ pub extern "C" fn engine_set_layer_clipping_mask(
 ) {
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+/// Alpha-locked layers keep their transparency: strokes, spray, bucket fill
+/// and filters only change the colour of pixels that already exist.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_alpha_lock(handle: u64, layer_index: u32, locked: bool) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetLayerAlphaLock {
+        layer_index,
+        locked,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_alpha_lock(_handle: u64, _layer_index: u32, _locked: bool) {}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_set_layer_blend_mode(
     handle: u64,
@@ -481,7 +574,7 @@ pub extern "C" fn engine_set_layer_blend_mode(
     });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_set_layer_blend_mode(
     _handle: u64,
@@ -490,7 +583,7 @@ pub extern "C" fn engine_set_layer_blend_mode(
 ) {
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_reorder_layer(handle: u64, from_index: u32, to_index: u32) {
     let Some(entry) = lookup_engine(handle) else {
@@ -502,144 +595,1085 @@ pub extern "C" fn engine_reorder_layer(handle: u64, from_index: u32, to_index: u
     });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_reorder_layer(_handle: u64, _from_index: u32, _to_index: u32) {}
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
-pub extern "C" fn engine_set_view_flags(handle: u64, view_flags: u32) {
+pub extern "C" fn engine_insert_layer(handle: u64, layer_index: u32) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::InsertLayer { layer_index });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_insert_layer(_handle: u64, _layer_index: u32) {}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_remove_layer(handle: u64, layer_index: u32) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::RemoveLayer { layer_index });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_remove_layer(_handle: u64, _layer_index: u32) {}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_create_layer_group(handle: u64, parent_group_id: u32) -> u32 {
+    let Some(entry) = lookup_engine(handle) else {
+        return 0;
+    };
+    let (tx, rx) = mpsc::channel();
+    if entry
+        .cmd_tx
+        .send(EngineCommand::CreateLayerGroup {
+            parent_group_id,
+            reply: tx,
+        })
+        .is_err()
+    {
+        return 0;
+    }
+    rx.recv().unwrap_or(0)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_create_layer_group(_handle: u64, _parent_group_id: u32) -> u32 {
+    0
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_delete_layer_group(handle: u64, group_id: u32) {
     let Some(entry) = lookup_engine(handle) else {
         return;
     };
     let _ = entry
         .cmd_tx
-        .send(EngineCommand::SetViewFlags { view_flags });
+        .send(EngineCommand::DeleteLayerGroup { group_id });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
-pub extern "C" fn engine_set_view_flags(_handle: u64, _view_flags: u32) {}
+pub extern "C" fn engine_delete_layer_group(_handle: u64, _group_id: u32) {}
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
-pub extern "C" fn engine_set_brush(
+pub extern "C" fn engine_set_layer_group_parent(handle: u64, group_id: u32, parent_group_id: u32) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetLayerGroupParent {
+        group_id,
+        parent_group_id,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_group_parent(
+    _handle: u64,
+    _group_id: u32,
+    _parent_group_id: u32,
+) {
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_move_layer_to_group(handle: u64, layer_index: u32, group_id: u32) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::MoveLayerToGroup {
+        layer_index,
+        group_id,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_move_layer_to_group(_handle: u64, _layer_index: u32, _group_id: u32) {}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_group_opacity(handle: u64, group_id: u32, opacity: f32) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry
+        .cmd_tx
+        .send(EngineCommand::SetLayerGroupOpacity { group_id, opacity });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_group_opacity(_handle: u64, _group_id: u32, _opacity: f32) {}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_group_visible(handle: u64, group_id: u32, visible: bool) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry
+        .cmd_tx
+        .send(EngineCommand::SetLayerGroupVisible { group_id, visible });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_group_visible(_handle: u64, _group_id: u32, _visible: bool) {}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_group_blend_mode(
     handle: u64,
-    color_argb: u32,
-    base_radius: f32,
-    use_pressure: u8,
-    erase: u8,
-    antialias_level: u32,
-    brush_shape: u32,
-    random_rotation: u8,
-    smooth_rotation: u8,
-    rotation_seed: u32,
-    spacing: f32,
-    hardness: f32,
-    flow: f32,
-    scatter: f32,
-    rotation_jitter: f32,
-    snap_to_pixel: u8,
-    screentone_enabled: u8,
-    screentone_spacing: f32,
-    screentone_dot_size: f32,
-    screentone_rotation: f32,
-    screentone_softness: f32,
-    screentone_shape: u32,
-    hollow_enabled: u8,
-    hollow_ratio: f32,
-    hollow_erase_occluded: u8,
-    streamline_strength: f32,
-    smoothing_mode: u32,
-    stabilizer_strength: f32,
+    group_id: u32,
+    blend_mode_index: u32,
 ) {
     let Some(entry) = lookup_engine(handle) else {
         return;
     };
-    if debug::level() >= LogLevel::Info {
-        let now = now_ms();
-        let last = LAST_BRUSH_LOG_MS.load(Ordering::Relaxed);
-        if now.saturating_sub(last) >= 500 {
-            LAST_BRUSH_LOG_MS.store(now, Ordering::Relaxed);
-            debug::log(
-                LogLevel::Info,
-                format_args!(
-                    "engine_set_brush handle={handle} color=0x{color_argb:08X} radius={base_radius:.2} erase={} shape={} aa={antialias_level} pressure={}",
-                    erase != 0,
-                    brush_shape,
-                    use_pressure != 0
-                ),
-            );
-        }
+    let _ = entry.cmd_tx.send(EngineCommand::SetLayerGroupBlendMode {
+        group_id,
+        blend_mode_index,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_group_blend_mode(
+    _handle: u64,
+    _group_id: u32,
+    _blend_mode_index: u32,
+) {
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_group_pass_through(
+    handle: u64,
+    group_id: u32,
+    pass_through: bool,
+) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetLayerGroupPassThrough {
+        group_id,
+        pass_through,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_group_pass_through(
+    _handle: u64,
+    _group_id: u32,
+    _pass_through: bool,
+) {
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_group_collapsed(handle: u64, group_id: u32, collapsed: bool) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetLayerGroupCollapsed {
+        group_id,
+        collapsed,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_group_collapsed(_handle: u64, _group_id: u32, _collapsed: bool) {
+}
+
+/// Copies the group tree snapshot into `out_ptr` when it fits and returns its
+/// length either way, so callers can retry with a larger buffer.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_get_layer_groups(handle: u64, out_ptr: *mut u32, out_len: usize) -> usize {
+    let Some(entry) = lookup_engine(handle) else {
+        return 0;
+    };
+    let (tx, rx) = mpsc::channel();
+    if entry
+        .cmd_tx
+        .send(EngineCommand::GetLayerGroups { reply: tx })
+        .is_err()
+    {
+        return 0;
     }
-    let _ = entry.cmd_tx.send(EngineCommand::SetBrush {
-        color_argb,
-        base_radius,
-        use_pressure: use_pressure != 0,
-        erase: erase != 0,
-        antialias_level,
-        brush_shape,
-        random_rotation: random_rotation != 0,
-        smooth_rotation: smooth_rotation != 0,
-        rotation_seed,
+    let Ok(snapshot) = rx.recv() else {
+        return 0;
+    };
+    if !out_ptr.is_null() && out_len >= snapshot.len() {
+        let out_slice = unsafe { std::slice::from_raw_parts_mut(out_ptr, snapshot.len()) };
+        out_slice.copy_from_slice(&snapshot);
+    }
+    snapshot.len()
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_get_layer_groups(
+    _handle: u64,
+    _out_ptr: *mut u32,
+    _out_len: usize,
+) -> usize {
+    0
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_create_layer_mask(handle: u64, layer_index: u32) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry
+        .cmd_tx
+        .send(EngineCommand::CreateLayerMask { layer_index });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_create_layer_mask(_handle: u64, _layer_index: u32) {}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_delete_layer_mask(handle: u64, layer_index: u32) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry
+        .cmd_tx
+        .send(EngineCommand::DeleteLayerMask { layer_index });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_delete_layer_mask(_handle: u64, _layer_index: u32) {}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_mask_enabled(handle: u64, layer_index: u32, enabled: bool) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetLayerMaskEnabled {
+        layer_index,
+        enabled,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_mask_enabled(_handle: u64, _layer_index: u32, _enabled: bool) {}
+
+/// Routes brush strokes on a layer that has a mask to the mask instead.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_mask_editing(handle: u64, editing: bool) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry
+        .cmd_tx
+        .send(EngineCommand::SetLayerMaskEditing { editing });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_mask_editing(_handle: u64, _editing: bool) {}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_invert_layer_mask(handle: u64, layer_index: u32) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry
+        .cmd_tx
+        .send(EngineCommand::InvertLayerMask { layer_index });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_invert_layer_mask(_handle: u64, _layer_index: u32) {}
+
+/// Bakes the mask into the layer's alpha and removes it.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_apply_layer_mask(handle: u64, layer_index: u32) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry
+        .cmd_tx
+        .send(EngineCommand::ApplyLayerMask { layer_index });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_apply_layer_mask(_handle: u64, _layer_index: u32) {}
+
+/// Copies the mask coverage (one byte per pixel) into `out_ptr`. Returns
+/// false when the layer has no mask or the buffer is too small.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_read_layer_mask(
+    handle: u64,
+    layer_index: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> bool {
+    if out_ptr.is_null() {
+        return false;
+    }
+    let Some(entry) = lookup_engine(handle) else {
+        return false;
+    };
+    let (tx, rx) = mpsc::channel();
+    if entry
+        .cmd_tx
+        .send(EngineCommand::ReadLayerMask {
+            layer_index,
+            reply: tx,
+        })
+        .is_err()
+    {
+        return false;
+    }
+    let Ok(Some(mask)) = rx.recv() else {
+        return false;
+    };
+    if out_len < mask.len() {
+        return false;
+    }
+    let out_slice = unsafe { std::slice::from_raw_parts_mut(out_ptr, mask.len()) };
+    out_slice.copy_from_slice(&mask);
+    true
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_read_layer_mask(
+    _handle: u64,
+    _layer_index: u32,
+    _out_ptr: *mut u8,
+    _out_len: usize,
+) -> bool {
+    false
+}
+
+/// Turns the layer into an adjustment layer that runs `filter_type` over the
+/// layers below it. Parameters use the same units as `engine_apply_filter`.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_adjustment(
+    handle: u64,
+    layer_index: u32,
+    filter_type: u32,
+    param0: f32,
+    param1: f32,
+    param2: f32,
+    param3: f32,
+) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetLayerAdjustment {
+        layer_index,
+        filter_type,
+        param0,
+        param1,
+        param2,
+        param3,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_layer_adjustment(
+    _handle: u64,
+    _layer_index: u32,
+    _filter_type: u32,
+    _param0: f32,
+    _param1: f32,
+    _param2: f32,
+    _param3: f32,
+) {
+}
+
+/// Turns an adjustment layer back into a pixel layer.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_clear_layer_adjustment(handle: u64, layer_index: u32) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry
+        .cmd_tx
+        .send(EngineCommand::ClearLayerAdjustment { layer_index });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_clear_layer_adjustment(_handle: u64, _layer_index: u32) {}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_view_flags(handle: u64, view_flags: u32) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry
+        .cmd_tx
+        .send(EngineCommand::SetViewFlags { view_flags });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_view_flags(_handle: u64, _view_flags: u32) {}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_brush(
+    handle: u64,
+    color_argb: u32,
+    base_radius: f32,
+    use_pressure: u8,
+    erase: u8,
+    antialias_level: u32,
+    brush_shape: u32,
+    random_rotation: u8,
+    smooth_rotation: u8,
+    rotation_seed: u32,
+    spacing: f32,
+    hardness: f32,
+    flow: f32,
+    scatter: f32,
+    rotation_jitter: f32,
+    snap_to_pixel: u8,
+    screentone_enabled: u8,
+    screentone_spacing: f32,
+    screentone_dot_size: f32,
+    screentone_rotation: f32,
+    screentone_softness: f32,
+    screentone_shape: u32,
+    hollow_enabled: u8,
+    hollow_ratio: f32,
+    hollow_erase_occluded: u8,
+    streamline_strength: f32,
+    smoothing_mode: u32,
+    stabilizer_strength: f32,
+) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    if debug::level() >= LogLevel::Info {
+        let now = now_ms();
+        let last = LAST_BRUSH_LOG_MS.load(Ordering::Relaxed);
+        if now.saturating_sub(last) >= 500 {
+            LAST_BRUSH_LOG_MS.store(now, Ordering::Relaxed);
+            debug::log(
+                LogLevel::Info,
+                format_args!(
+                    "engine_set_brush handle={handle} color=0x{color_argb:08X} radius={base_radius:.2} erase={} shape={} aa={antialias_level} pressure={}",
+                    erase != 0,
+                    brush_shape,
+                    use_pressure != 0
+                ),
+            );
+        }
+    }
+    let _ = entry.cmd_tx.send(EngineCommand::SetBrush {
+        color_argb,
+        base_radius,
+        use_pressure: use_pressure != 0,
+        erase: erase != 0,
+        antialias_level,
+        brush_shape,
+        random_rotation: random_rotation != 0,
+        smooth_rotation: smooth_rotation != 0,
+        rotation_seed,
+        spacing,
+        hardness,
+        flow,
+        scatter,
+        rotation_jitter,
+        snap_to_pixel: snap_to_pixel != 0,
+        screentone_enabled: screentone_enabled != 0,
+        screentone_spacing,
+        screentone_dot_size,
+        screentone_rotation,
+        screentone_softness,
+        screentone_shape,
+        hollow_enabled: hollow_enabled != 0,
+        hollow_ratio,
+        hollow_erase_occluded: hollow_erase_occluded != 0,
+        streamline_strength,
+        smoothing_mode,
+        stabilizer_strength,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_brush(
+    _handle: u64,
+    _color_argb: u32,
+    _base_radius: f32,
+    _use_pressure: u8,
+    _erase: u8,
+    _antialias_level: u32,
+    _brush_shape: u32,
+    _random_rotation: u8,
+    _smooth_rotation: u8,
+    _rotation_seed: u32,
+    _spacing: f32,
+    _hardness: f32,
+    _flow: f32,
+    _scatter: f32,
+    _rotation_jitter: f32,
+    _snap_to_pixel: u8,
+    _screentone_enabled: u8,
+    _screentone_spacing: f32,
+    _screentone_dot_size: f32,
+    _screentone_rotation: f32,
+    _screentone_softness: f32,
+    _screentone_shape: u32,
+    _hollow_enabled: u8,
+    _hollow_ratio: f32,
+    _hollow_erase_occluded: u8,
+    _streamline_strength: f32,
+    _smoothing_mode: u32,
+    _stabilizer_strength: f32,
+) {
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_brush_mask(
+    handle: u64,
+    width: u32,
+    height: u32,
+    mask_ptr: *const u8,
+    mask_len: usize,
+) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    if mask_ptr.is_null() || mask_len == 0 || width == 0 || height == 0 {
+        let _ = entry.cmd_tx.send(EngineCommand::ClearBrushMask);
+        return;
+    }
+    let expected_len = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|v| v.checked_mul(2));
+    if expected_len.is_none() || expected_len != Some(mask_len) {
+        let _ = entry.cmd_tx.send(EngineCommand::ClearBrushMask);
+        return;
+    }
+    let mask = unsafe { std::slice::from_raw_parts(mask_ptr, mask_len).to_vec() };
+    let _ = entry
+        .cmd_tx
+        .send(EngineCommand::SetBrushMask { width, height, mask });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_brush_mask(
+    _handle: u64,
+    _width: u32,
+    _height: u32,
+    _mask_ptr: *const u8,
+    _mask_len: usize,
+) {
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_clear_brush_mask(handle: u64) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::ClearBrushMask);
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_clear_brush_mask(_handle: u64) {}
+
+/// `tilt_size` and `tilt_opacity` run from -1 to 1; 0 turns them off.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_pen_dynamics(
+    handle: u64,
+    tilt_size: f32,
+    tilt_opacity: f32,
+    azimuth_angle: u8,
+    barrel_angle: u8,
+) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetPenDynamics {
+        tilt_size,
+        tilt_opacity,
+        azimuth_angle: azimuth_angle != 0,
+        barrel_angle: barrel_angle != 0,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_pen_dynamics(
+    _handle: u64,
+    _tilt_size: f32,
+    _tilt_opacity: f32,
+    _azimuth_angle: u8,
+    _barrel_angle: u8,
+) {
+}
+
+/// Sets one pressure curve from `point_count` interleaved `(pressure, value)`
+/// pairs. `target` is 0 size, 1 opacity, 2 flow, 3 hardness or 4 scatter;
+/// no points clears the curve.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_pressure_curve(
+    handle: u64,
+    target: u32,
+    points_ptr: *const f32,
+    point_count: usize,
+) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let points: Vec<(f32, f32)> = if points_ptr.is_null() || point_count == 0 {
+        Vec::new()
+    } else {
+        let values = unsafe { std::slice::from_raw_parts(points_ptr, point_count * 2) };
+        values.chunks_exact(2).map(|pair| (pair[0], pair[1])).collect()
+    };
+    let _ = entry
+        .cmd_tx
+        .send(EngineCommand::SetPressureCurve { target, points });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_pressure_curve(
+    _handle: u64,
+    _target: u32,
+    _points_ptr: *const f32,
+    _point_count: usize,
+) {
+}
+
+/// Like `engine_set_pressure_curve`, but from a lookup table spread evenly
+/// over pressures 0 to 1.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_pressure_curve_table(
+    handle: u64,
+    target: u32,
+    values_ptr: *const f32,
+    values_len: usize,
+) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let values: &[f32] = if values_ptr.is_null() || values_len == 0 {
+        &[]
+    } else {
+        unsafe { std::slice::from_raw_parts(values_ptr, values_len) }
+    };
+    let points = PressureCurve::table_points(values);
+    let _ = entry
+        .cmd_tx
+        .send(EngineCommand::SetPressureCurve { target, points });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_pressure_curve_table(
+    _handle: u64,
+    _target: u32,
+    _values_ptr: *const f32,
+    _values_len: usize,
+) {
+}
+
+/// Sets the pressure calibration of input `device` (the `device` of the
+/// pushed points) from `point_count` interleaved `(pressure, value)` pairs.
+/// It outlives brush changes; no points clears it.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_device_calibration(
+    handle: u64,
+    device: u32,
+    points_ptr: *const f32,
+    point_count: usize,
+) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let points: Vec<(f32, f32)> = if points_ptr.is_null() || point_count == 0 {
+        Vec::new()
+    } else {
+        let values = unsafe { std::slice::from_raw_parts(points_ptr, point_count * 2) };
+        values.chunks_exact(2).map(|pair| (pair[0], pair[1])).collect()
+    };
+    if let Ok(mut calibrations) = entry.calibrations.lock() {
+        calibrations.set(device, &points);
+    };
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_device_calibration(
+    _handle: u64,
+    _device: u32,
+    _points_ptr: *const f32,
+    _point_count: usize,
+) {
+}
+
+/// `profile` 0 keeps device pressure, 1 thins fast strokes and 2 thickens
+/// them. `tapered_caps` fades the stroke in and runs a short tail at release.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_simulated_pressure(handle: u64, profile: u32, tapered_caps: u8) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetSimulatedPressure {
+        profile,
+        tapered_caps: tapered_caps != 0,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_simulated_pressure(_handle: u64, _profile: u32, _tapered_caps: u8) {}
+
+/// Mass, spring stiffness and damping of the virtual tip used by smoothing
+/// mode 4, in pixels and seconds.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_spring_stabilizer(
+    handle: u64,
+    mass: f32,
+    stiffness: f32,
+    damping: f32,
+) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetSpringStabilizer {
+        mass,
+        stiffness,
+        damping,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_spring_stabilizer(
+    _handle: u64,
+    _mass: f32,
+    _stiffness: f32,
+    _damping: f32,
+) {
+}
+
+/// Sets the guide strokes snap to and whether the present pass draws it.
+/// `kind` 0 turns snapping off, 1 is a ruler `[ax, ay, bx, by]`, 2 parallel
+/// lines `[angle]`, 3 an ellipse and 4 concentric ellipses
+/// `[cx, cy, rx, ry, rotation]`, and 5 perspective
+/// `[tolerance_degrees, x1, y1, ...]` with one to three vanishing points.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_assist(
+    handle: u64,
+    kind: u32,
+    params_ptr: *const f32,
+    params_len: usize,
+    show_overlay: u8,
+) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let params: Vec<f32> = if params_ptr.is_null() || params_len == 0 {
+        Vec::new()
+    } else {
+        unsafe { std::slice::from_raw_parts(params_ptr, params_len) }.to_vec()
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetAssist {
+        kind,
+        params,
+        show_overlay: show_overlay != 0,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_assist(
+    _handle: u64,
+    _kind: u32,
+    _params_ptr: *const f32,
+    _params_len: usize,
+    _show_overlay: u8,
+) {
+}
+
+/// Repeats strokes around (`center_x`, `center_y`). `mode` 0 is off, 1 mirrors
+/// left to right, 2 top to bottom, 3 both, 4 is `folds`-way radial symmetry
+/// and 5 a kaleidoscope that also mirrors every radial copy.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_symmetry(
+    handle: u64,
+    mode: u32,
+    center_x: f32,
+    center_y: f32,
+    folds: u32,
+) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetSymmetry {
+        mode,
+        center_x,
+        center_y,
+        folds,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_symmetry(
+    _handle: u64,
+    _mode: u32,
+    _center_x: f32,
+    _center_y: f32,
+    _folds: u32,
+) {
+}
+
+/// Uploads the paper texture brushes with grain sample, one grayscale byte
+/// per texel. A null pointer or empty size clears it.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_grain_texture(
+    handle: u64,
+    width: u32,
+    height: u32,
+    pixels_ptr: *const u8,
+    pixels_len: usize,
+) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let expected_len = (width as usize).checked_mul(height as usize);
+    let pixels = if pixels_ptr.is_null() || pixels_len == 0 || expected_len != Some(pixels_len) {
+        Vec::new()
+    } else {
+        unsafe { std::slice::from_raw_parts(pixels_ptr, pixels_len).to_vec() }
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetGrainTexture {
+        width,
+        height,
+        pixels,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_grain_texture(
+    _handle: u64,
+    _width: u32,
+    _height: u32,
+    _pixels_ptr: *const u8,
+    _pixels_len: usize,
+) {
+}
+
+/// `scale` is canvas pixels per texel and `depth` runs from 0 to 1. `blend`
+/// 0 multiplies, 1 subtracts and 2 treats the grain as a height map.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_grain(
+    handle: u64,
+    enabled: u8,
+    scale: f32,
+    offset_x: f32,
+    offset_y: f32,
+    depth: f32,
+    invert: u8,
+    blend: u32,
+) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetGrain {
+        enabled: enabled != 0,
+        scale,
+        offset_x,
+        offset_y,
+        depth,
+        invert: invert != 0,
+        blend,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_grain(
+    _handle: u64,
+    _enabled: u8,
+    _scale: f32,
+    _offset_x: f32,
+    _offset_y: f32,
+    _depth: f32,
+    _invert: u8,
+    _blend: u32,
+) {
+}
+
+/// `mode` 0 turns mixing off, 1 smudges, 2 blends and 3 paints watercolour.
+/// `pickup`, `dilution`, `persistence` and `extension` run from 0 to 1.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_mixing(
+    handle: u64,
+    mode: u32,
+    pickup: f32,
+    dilution: f32,
+    persistence: f32,
+    extension: f32,
+) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetMixing {
+        mode,
+        pickup,
+        dilution,
+        persistence,
+        extension,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_mixing(
+    _handle: u64,
+    _mode: u32,
+    _pickup: f32,
+    _dilution: f32,
+    _persistence: f32,
+    _extension: f32,
+) {
+}
+
+/// Watercolour pooling for the following strokes. `edge` and `granulation`
+/// run from 0 to 1; `bleed` is how far the wash spreads, in canvas pixels.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_watercolor(
+    handle: u64,
+    enabled: u8,
+    edge: f32,
+    granulation: f32,
+    bleed: f32,
+) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetWatercolor {
+        enabled: enabled != 0,
+        edge,
+        granulation,
+        bleed,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_watercolor(
+    _handle: u64,
+    _enabled: u8,
+    _edge: f32,
+    _granulation: f32,
+    _bleed: f32,
+) {
+}
+
+/// Secondary tip of a dual brush; see `EngineCommand::SetDualBrush` for the
+/// units and blend indices.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_dual_brush(
+    handle: u64,
+    enabled: u8,
+    blend: u32,
+    shape: u32,
+    scale: f32,
+    spacing: f32,
+    scatter: f32,
+    count: u32,
+    size_jitter: f32,
+) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetDualBrush {
+        enabled: enabled != 0,
+        blend,
+        shape,
+        scale,
         spacing,
-        hardness,
-        flow,
         scatter,
-        rotation_jitter,
-        snap_to_pixel: snap_to_pixel != 0,
-        screentone_enabled: screentone_enabled != 0,
-        screentone_spacing,
-        screentone_dot_size,
-        screentone_rotation,
-        screentone_softness,
-        screentone_shape,
-        hollow_enabled: hollow_enabled != 0,
-        hollow_ratio,
-        hollow_erase_occluded: hollow_erase_occluded != 0,
-        streamline_strength,
-        smoothing_mode,
-        stabilizer_strength,
+        count,
+        size_jitter,
     });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
-pub extern "C" fn engine_set_brush(
+pub extern "C" fn engine_set_dual_brush(
     _handle: u64,
-    _color_argb: u32,
-    _base_radius: f32,
-    _use_pressure: u8,
-    _erase: u8,
-    _antialias_level: u32,
-    _brush_shape: u32,
-    _random_rotation: u8,
-    _smooth_rotation: u8,
-    _rotation_seed: u32,
+    _enabled: u8,
+    _blend: u32,
+    _shape: u32,
+    _scale: f32,
     _spacing: f32,
-    _hardness: f32,
-    _flow: f32,
     _scatter: f32,
-    _rotation_jitter: f32,
-    _snap_to_pixel: u8,
-    _screentone_enabled: u8,
-    _screentone_spacing: f32,
-    _screentone_dot_size: f32,
-    _screentone_rotation: f32,
-    _screentone_softness: f32,
-    _screentone_shape: u32,
-    _hollow_enabled: u8,
-    _hollow_ratio: f32,
-    _hollow_erase_occluded: u8,
-    _streamline_strength: f32,
-    _smoothing_mode: u32,
-    _stabilizer_strength: f32,
+    _count: u32,
+    _size_jitter: f32,
 ) {
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+/// Tip image of the dual brush, two bytes per texel like the brush mask. A
+/// null pointer or empty size clears it.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
-pub extern "C" fn engine_set_brush_mask(
+pub extern "C" fn engine_set_dual_brush_mask(
     handle: u64,
     width: u32,
     height: u32,
@@ -649,26 +1683,24 @@ pub extern "C" fn engine_set_brush_mask(
     let Some(entry) = lookup_engine(handle) else {
         return;
     };
-    if mask_ptr.is_null() || mask_len == 0 || width == 0 || height == 0 {
-        let _ = entry.cmd_tx.send(EngineCommand::ClearBrushMask);
-        return;
-    }
     let expected_len = (width as usize)
         .checked_mul(height as usize)
-        .and_then(|v| v.checked_mul(2));
-    if expected_len.is_none() || expected_len != Some(mask_len) {
-        let _ = entry.cmd_tx.send(EngineCommand::ClearBrushMask);
-        return;
-    }
-    let mask = unsafe { std::slice::from_raw_parts(mask_ptr, mask_len).to_vec() };
-    let _ = entry
-        .cmd_tx
-        .send(EngineCommand::SetBrushMask { width, height, mask });
+        .and_then(|value| value.checked_mul(2));
+    let mask = if mask_ptr.is_null() || mask_len == 0 || expected_len != Some(mask_len) {
+        Vec::new()
+    } else {
+        unsafe { std::slice::from_raw_parts(mask_ptr, mask_len).to_vec() }
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetDualBrushMask {
+        width,
+        height,
+        mask,
+    });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
-pub extern "C" fn engine_set_brush_mask(
+pub extern "C" fn engine_set_dual_brush_mask(
     _handle: u64,
     _width: u32,
     _height: u32,
@@ -677,20 +1709,61 @@ pub extern "C" fn engine_set_brush_mask(
 ) {
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+/// Colour dynamics for the following strokes; see
+/// `EngineCommand::SetColorDynamics`.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
-pub extern "C" fn engine_clear_brush_mask(handle: u64) {
+pub extern "C" fn engine_set_color_dynamics(
+    handle: u64,
+    hue_jitter: f32,
+    saturation_jitter: f32,
+    value_jitter: f32,
+    opacity_jitter: f32,
+    stroke_hue_jitter: f32,
+    stroke_saturation_jitter: f32,
+    stroke_value_jitter: f32,
+    stroke_opacity_jitter: f32,
+    background_argb: u32,
+    background_source: u32,
+    background_amount: f32,
+) {
     let Some(entry) = lookup_engine(handle) else {
         return;
     };
-    let _ = entry.cmd_tx.send(EngineCommand::ClearBrushMask);
+    let _ = entry.cmd_tx.send(EngineCommand::SetColorDynamics {
+        hue_jitter,
+        saturation_jitter,
+        value_jitter,
+        opacity_jitter,
+        stroke_hue_jitter,
+        stroke_saturation_jitter,
+        stroke_value_jitter,
+        stroke_opacity_jitter,
+        background_argb,
+        background_source,
+        background_amount,
+    });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
-pub extern "C" fn engine_clear_brush_mask(_handle: u64) {}
+pub extern "C" fn engine_set_color_dynamics(
+    _handle: u64,
+    _hue_jitter: f32,
+    _saturation_jitter: f32,
+    _value_jitter: f32,
+    _opacity_jitter: f32,
+    _stroke_hue_jitter: f32,
+    _stroke_saturation_jitter: f32,
+    _stroke_value_jitter: f32,
+    _stroke_opacity_jitter: f32,
+    _background_argb: u32,
+    _background_source: u32,
+    _background_amount: f32,
+) {
+}
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_spray_begin(handle: u64) {
     let Some(entry) = lookup_engine(handle) else {
@@ -699,11 +1772,11 @@ pub extern "C" fn engine_spray_begin(handle: u64) {
     let _ = entry.cmd_tx.send(EngineCommand::BeginSpray);
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_spray_begin(_handle: u64) {}
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_spray_draw(
     handle: u64,
@@ -752,7 +1825,7 @@ pub extern "C" fn engine_spray_draw(
     });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_spray_draw(
     _handle: u64,
@@ -767,7 +1840,7 @@ pub extern "C" fn engine_spray_draw(
 ) {
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_spray_end(handle: u64) {
     let Some(entry) = lookup_engine(handle) else {
@@ -776,11 +1849,11 @@ pub extern "C" fn engine_spray_end(handle: u64) {
     let _ = entry.cmd_tx.send(EngineCommand::EndSpray);
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_spray_end(_handle: u64) {}
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_apply_filter(
     handle: u64,
@@ -816,7 +1889,7 @@ pub extern "C" fn engine_apply_filter(
     }
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_apply_filter(
     _handle: u64,
@@ -830,7 +1903,7 @@ pub extern "C" fn engine_apply_filter(
     0
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_apply_antialias(
     handle: u64,
@@ -858,7 +1931,7 @@ pub extern "C" fn engine_apply_antialias(
     }
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_apply_antialias(
     _handle: u64,
@@ -868,7 +1941,7 @@ pub extern "C" fn engine_apply_antialias(
     0
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_clear_layer(handle: u64, layer_index: u32) {
     let Some(entry) = lookup_engine(handle) else {
@@ -877,11 +1950,11 @@ pub extern "C" fn engine_clear_layer(handle: u64, layer_index: u32) {
     let _ = entry.cmd_tx.send(EngineCommand::ClearLayer { layer_index });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_clear_layer(_handle: u64, _layer_index: u32) {}
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_fill_layer(handle: u64, layer_index: u32, color_argb: u32) {
     let Some(entry) = lookup_engine(handle) else {
@@ -906,11 +1979,11 @@ pub extern "C" fn engine_fill_layer(handle: u64, layer_index: u32, color_argb: u
     });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_fill_layer(_handle: u64, _layer_index: u32, _color_argb: u32) {}
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_bucket_fill(
     handle: u64,
@@ -978,7 +2051,7 @@ pub extern "C" fn engine_bucket_fill(
     }
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_bucket_fill(
     _handle: u64,
@@ -999,7 +2072,7 @@ pub extern "C" fn engine_bucket_fill(
     0
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_magic_wand_mask(
     handle: u64,
@@ -1058,7 +2131,7 @@ pub extern "C" fn engine_magic_wand_mask(
     }
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_magic_wand_mask(
     _handle: u64,
@@ -1075,7 +2148,7 @@ pub extern "C" fn engine_magic_wand_mask(
     0
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_read_layer(
     handle: u64,
@@ -1116,7 +2189,7 @@ pub extern "C" fn engine_read_layer(
     }
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_read_layer(
     _handle: u64,
@@ -1127,7 +2200,7 @@ pub extern "C" fn engine_read_layer(
     0
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_read_layer_preview(
     handle: u64,
@@ -1178,7 +2251,7 @@ pub extern "C" fn engine_read_layer_preview(
     }
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_read_layer_preview(
     _handle: u64,
@@ -1191,7 +2264,7 @@ pub extern "C" fn engine_read_layer_preview(
     0
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_read_present(
     handle: u64,
@@ -1224,7 +2297,7 @@ pub extern "C" fn engine_read_present(
     }
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_read_present(
     _handle: u64,
@@ -1234,7 +2307,7 @@ pub extern "C" fn engine_read_present(
     0
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_write_layer(
     handle: u64,
@@ -1277,7 +2350,7 @@ pub extern "C" fn engine_write_layer(
     }
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_write_layer(
     _handle: u64,
@@ -1289,7 +2362,7 @@ pub extern "C" fn engine_write_layer(
     0
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_translate_layer(
     handle: u64,
@@ -1327,7 +2400,7 @@ pub extern "C" fn engine_translate_layer(
     }
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_translate_layer(
     _handle: u64,
@@ -1338,7 +2411,7 @@ pub extern "C" fn engine_translate_layer(
     0
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_set_layer_transform_preview(
     handle: u64,
@@ -1372,7 +2445,7 @@ pub extern "C" fn engine_set_layer_transform_preview(
     1
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_set_layer_transform_preview(
     _handle: u64,
@@ -1385,7 +2458,7 @@ pub extern "C" fn engine_set_layer_transform_preview(
     0
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_apply_layer_transform(
     handle: u64,
@@ -1429,7 +2502,7 @@ pub extern "C" fn engine_apply_layer_transform(
     }
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_apply_layer_transform(
     _handle: u64,
@@ -1441,7 +2514,7 @@ pub extern "C" fn engine_apply_layer_transform(
     0
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_get_layer_bounds(
     handle: u64,
@@ -1482,7 +2555,7 @@ pub extern "C" fn engine_get_layer_bounds(
     }
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_get_layer_bounds(
     _handle: u64,
@@ -1493,7 +2566,7 @@ pub extern "C" fn engine_get_layer_bounds(
     0
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_set_selection_mask(
     handle: u64,
@@ -1514,7 +2587,7 @@ pub extern "C" fn engine_set_selection_mask(
         .send(EngineCommand::SetSelectionMask { selection_mask });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_set_selection_mask(
     _handle: u64,
@@ -1523,7 +2596,7 @@ pub extern "C" fn engine_set_selection_mask(
 ) {
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_reset_canvas(handle: u64, background_color_argb: u32) {
     let Some(entry) = lookup_engine(handle) else {
@@ -1534,11 +2607,11 @@ pub extern "C" fn engine_reset_canvas(handle: u64, background_color_argb: u32) {
     });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_reset_canvas(_handle: u64, _background_color_argb: u32) {}
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_reset_canvas_with_layers(
     handle: u64,
@@ -1560,7 +2633,7 @@ pub extern "C" fn engine_reset_canvas_with_layers(
     });
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_reset_canvas_with_layers(
     _handle: u64,
@@ -1569,7 +2642,7 @@ pub extern "C" fn engine_reset_canvas_with_layers(
 ) {
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_resize_canvas(
     handle: u64,
@@ -1610,7 +2683,7 @@ pub extern "C" fn engine_resize_canvas(
     }
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_resize_canvas(
     _handle: u64,
@@ -1622,7 +2695,283 @@ pub extern "C" fn engine_resize_canvas(
     0
 }
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+fn now_us() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as i64
+}
+
+/// Completion of `engine_encode_rin`, called once on a worker thread with the
+/// request's id. `bytes` is null on failure; otherwise it holds `len` bytes
+/// that must go back to `engine_free_rin`.
+pub type EngineEncodeRinCallback = extern "C" fn(request_id: u64, bytes: *mut u8, len: usize);
+
+/// Saves the engine's layers as a `.rin` project without blocking the caller:
+/// the layers are read back on the render thread and compressed on a worker
+/// thread, which then calls `on_done`. `template_ptr` may point at an
+/// existing `.rin` file (bitmaps optional) that supplies the document
+/// metadata, layer ids, names, lock flags and text blocks; it is copied
+/// before this returns. Returns 0 when nothing was queued, in which case
+/// `on_done` is never called.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_encode_rin(
+    handle: u64,
+    template_ptr: *const u8,
+    template_len: usize,
+    request_id: u64,
+    on_done: Option<EngineEncodeRinCallback>,
+) -> u8 {
+    let Some(on_done) = on_done else {
+        return 0;
+    };
+    let Some(entry) = lookup_engine(handle) else {
+        return 0;
+    };
+    let template = if template_ptr.is_null() || template_len == 0 {
+        None
+    } else {
+        Some(unsafe { std::slice::from_raw_parts(template_ptr, template_len) }.to_vec())
+    };
+    let cmd_tx = entry.cmd_tx;
+    let spawned = std::thread::Builder::new()
+        .name("misa-rin-encode".to_string())
+        .spawn(move || match encode_engine_rin(&cmd_tx, template.as_deref()) {
+            Some(bytes) => {
+                let len = bytes.len();
+                on_done(request_id, Box::into_raw(bytes) as *mut u8, len);
+            }
+            None => on_done(request_id, std::ptr::null_mut(), 0),
+        });
+    match spawned {
+        Ok(_) => 1,
+        Err(err) => {
+            debug::log(LogLevel::Warn, format_args!("engine_encode_rin: {err}"));
+            0
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_encode_rin(
+    _handle: u64,
+    _template_ptr: *const u8,
+    _template_len: usize,
+    _request_id: u64,
+    _on_done: Option<EngineEncodeRinCallback>,
+) -> u8 {
+    0
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+fn encode_engine_rin(
+    cmd_tx: &mpsc::Sender<EngineCommand>,
+    template: Option<&[u8]>,
+) -> Option<Box<[u8]>> {
+    let now = now_us();
+    let template = match template.map(decode_rin).transpose() {
+        Ok(template) => template,
+        Err(err) => {
+            debug::log(LogLevel::Warn, format_args!("engine_encode_rin template: {err}"));
+            return None;
+        }
+    };
+    let (tx, rx) = mpsc::channel();
+    cmd_tx.send(EngineCommand::ReadProject { reply: tx }).ok()?;
+    let snapshot = rx.recv().ok()??;
+    let mut document =
+        template.unwrap_or_else(|| RinDocument::new(snapshot.width, snapshot.height, now));
+    document.apply_engine_snapshot(snapshot, now);
+    Some(encode_rin(&document).into_boxed_slice())
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_free_rin(ptr: *mut u8, len: usize) {
+    if !ptr.is_null() {
+        unsafe {
+            drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)));
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_free_rin(_ptr: *mut u8, _len: usize) {}
+
+/// Creates an engine sized to a `.rin` project and loads its layers, layer
+/// properties, masks, adjustment layers and groups. Returns 0 when the file
+/// cannot be read.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_create_from_rin(bytes_ptr: *const u8, bytes_len: usize) -> u64 {
+    if bytes_ptr.is_null() || bytes_len == 0 {
+        return 0;
+    }
+    let bytes = unsafe { std::slice::from_raw_parts(bytes_ptr, bytes_len) };
+    let document = match decode_rin(bytes) {
+        Ok(document) => document,
+        Err(err) => {
+            debug::log(LogLevel::Warn, format_args!("engine_create_from_rin: {err}"));
+            return 0;
+        }
+    };
+    let (width, height) = document.canvas_size();
+    let handle = engine_create(width, height);
+    if handle == 0 {
+        return 0;
+    }
+    if let Err(err) = load_rin_document(handle, &document) {
+        debug::log(LogLevel::Warn, format_args!("engine_create_from_rin: {err}"));
+        engine_dispose(handle);
+        return 0;
+    }
+    handle
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_create_from_rin(_bytes_ptr: *const u8, _bytes_len: usize) -> u64 {
+    0
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+pub(crate) fn load_rin_document(handle: u64, document: &RinDocument) -> Result<(), String> {
+    let entry = lookup_engine(handle).ok_or("engine disappeared")?;
+    let send = |cmd: EngineCommand| {
+        entry
+            .cmd_tx
+            .send(cmd)
+            .map_err(|_| "engine thread stopped".to_string())
+    };
+    let (width, height) = document.canvas_size();
+    send(EngineCommand::ResetCanvasWithLayers {
+        layer_count: document.layers.len().max(1) as u32,
+        background_color_argb: document.background_argb,
+    })?;
+    // The engine hands out its own group ids, so every group is created at
+    // the root first and nested once all of them exist.
+    let mut group_ids = HashMap::new();
+    for group in &document.groups {
+        let (tx, rx) = mpsc::channel();
+        send(EngineCommand::CreateLayerGroup {
+            parent_group_id: ROOT_LAYER_GROUP,
+            reply: tx,
+        })?;
+        let id = rx.recv().unwrap_or(ROOT_LAYER_GROUP);
+        if id == ROOT_LAYER_GROUP {
+            return Err(format!("group {} could not be created", group.id));
+        }
+        group_ids.insert(group.id, id);
+    }
+    let engine_group = |id: u32| group_ids.get(&id).copied().unwrap_or(ROOT_LAYER_GROUP);
+    for group in &document.groups {
+        let group_id = engine_group(group.id);
+        send(EngineCommand::SetLayerGroupParent {
+            group_id,
+            parent_group_id: engine_group(group.parent),
+        })?;
+        send(EngineCommand::SetLayerGroupOpacity {
+            group_id,
+            opacity: group.opacity,
+        })?;
+        send(EngineCommand::SetLayerGroupVisible {
+            group_id,
+            visible: group.visible,
+        })?;
+        send(EngineCommand::SetLayerGroupBlendMode {
+            group_id,
+            blend_mode_index: group.blend_mode_index,
+        })?;
+        send(EngineCommand::SetLayerGroupPassThrough {
+            group_id,
+            pass_through: group.pass_through,
+        })?;
+        send(EngineCommand::SetLayerGroupCollapsed {
+            group_id,
+            collapsed: group.collapsed,
+        })?;
+    }
+    for (index, layer) in document.layers.iter().enumerate() {
+        let layer_index = index as u32;
+        let (tx, rx) = mpsc::channel();
+        send(EngineCommand::WriteLayer {
+            layer_index,
+            pixels: layer.canvas_pixels(width, height),
+            record_undo: false,
+            reply: tx,
+        })?;
+        if !rx.recv().unwrap_or(false) {
+            return Err(format!("layer {index} write rejected"));
+        }
+        send(EngineCommand::SetLayerOpacity {
+            layer_index,
+            opacity: layer.opacity,
+        })?;
+        send(EngineCommand::SetLayerVisible {
+            layer_index,
+            visible: layer.visible,
+        })?;
+        send(EngineCommand::SetLayerClippingMask {
+            layer_index,
+            clipping_mask: layer.clipping_mask,
+        })?;
+        send(EngineCommand::SetLayerBlendMode {
+            layer_index,
+            blend_mode_index: layer.blend_mode as u32,
+        })?;
+        send(EngineCommand::SetLayerAlphaLock {
+            layer_index,
+            locked: layer.alpha_locked,
+        })?;
+        if layer.group != ROOT_LAYER_GROUP {
+            send(EngineCommand::MoveLayerToGroup {
+                layer_index,
+                group_id: engine_group(layer.group),
+            })?;
+        }
+        if let Some(mask) = layer.mask.as_ref() {
+            send(EngineCommand::CreateLayerMask { layer_index })?;
+            // A fresh mask already reveals everything.
+            if mask.coverage.iter().any(|&value| value != u8::MAX) {
+                let (tx, rx) = mpsc::channel();
+                send(EngineCommand::WriteLayerMask {
+                    layer_index,
+                    coverage: mask.coverage.clone(),
+                    reply: tx,
+                })?;
+                if !rx.recv().unwrap_or(false) {
+                    return Err(format!("layer {index} mask write rejected"));
+                }
+            }
+            if !mask.enabled {
+                send(EngineCommand::SetLayerMaskEnabled {
+                    layer_index,
+                    enabled: false,
+                })?;
+            }
+        }
+        if let Some(adjustment) = layer.adjustment {
+            let [param0, param1, param2, param3] = adjustment.params;
+            send(EngineCommand::SetLayerAdjustment {
+                layer_index,
+                filter_type: adjustment.filter_type,
+                param0,
+                param1,
+                param2,
+                param3,
+            })?;
+        }
+    }
+    // The loaded project is where history starts.
+    send(EngineCommand::ClearUndoHistory)?;
+    Ok(())
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_undo(handle: u64) {
     let Some(entry) = lookup_engine(handle) else {
@@ -1631,11 +2980,11 @@ pub extern "C" fn engine_undo(handle: u64) {
     let _ = entry.cmd_tx.send(EngineCommand::Undo);
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_undo(_handle: u64) {}
 
-#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
 #[no_mangle]
 pub extern "C" fn engine_redo(handle: u64) {
     let Some(entry) = lookup_engine(handle) else {
@@ -1644,6 +2993,143 @@ pub extern "C" fn engine_redo(handle: u64) {
     let _ = entry.cmd_tx.send(EngineCommand::Redo);
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
 #[no_mangle]
 pub extern "C" fn engine_redo(_handle: u64) {}
+
+/// Keeps at most `max_steps` undo steps, counting every history branch, and
+/// `budget_bytes` of tile patches ready in VRAM (raw memory on the CPU
+/// engine); older patches are compressed.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_undo_limits(handle: u64, max_steps: u32, budget_bytes: u64) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetUndoLimits {
+        max_steps,
+        budget_bytes,
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_undo_limits(_handle: u64, _max_steps: u32, _budget_bytes: u64) {}
+
+/// Undo transaction: pixel changes until the commit become one undo step.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_begin_undo_transaction(handle: u64) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::BeginUndoTransaction);
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_begin_undo_transaction(_handle: u64) {}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_commit_undo_transaction(handle: u64) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::CommitUndoTransaction);
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_commit_undo_transaction(_handle: u64) {}
+
+/// Ends the undo transaction and restores every pixel changed since it began.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_cancel_undo_transaction(handle: u64) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::CancelUndoTransaction);
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_cancel_undo_transaction(_handle: u64) {}
+
+/// Names the current history state so it can be found again; null or an
+/// empty name removes the checkpoint.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_set_history_checkpoint(handle: u64, name: *const c_char) {
+    let Some(entry) = lookup_engine(handle) else {
+        return;
+    };
+    let name = if name.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned()
+    };
+    let _ = entry.cmd_tx.send(EngineCommand::SetHistoryCheckpoint { name });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_set_history_checkpoint(_handle: u64, _name: *const c_char) {}
+
+/// Undoes and redoes, switching branches where needed, until the canvas shows
+/// history node `node_id`. Returns 1 when the node was reached.
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn engine_jump_to_history_node(handle: u64, node_id: u64) -> u8 {
+    let Some(entry) = lookup_engine(handle) else {
+        return 0;
+    };
+    let (tx, rx) = mpsc::channel();
+    if entry
+        .cmd_tx
+        .send(EngineCommand::PlanHistoryRoute {
+            node_id,
+            reply: tx,
+        })
+        .is_err()
+    {
+        return 0;
+    }
+    let Ok(Some(moves)) = rx.recv() else {
+        return 0;
+    };
+    for step in moves {
+        let command = match step {
+            HistoryMove::Undo => EngineCommand::Undo,
+            HistoryMove::Redo => EngineCommand::Redo,
+            HistoryMove::Enter(node_id) => EngineCommand::EnterHistoryBranch { node_id },
+        };
+        if entry.cmd_tx.send(command).is_err() {
+            return 0;
+        }
+    }
+    1
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux")))]
+#[no_mangle]
+pub extern "C" fn engine_jump_to_history_node(_handle: u64, _node_id: u64) -> u8 {
+    0
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios", target_os = "linux"))]
+pub(crate) fn read_engine_history(handle: u64) -> Vec<EngineHistoryNode> {
+    let Some(entry) = lookup_engine(handle) else {
+        return Vec::new();
+    };
+    let (tx, rx) = mpsc::channel();
+    if entry
+        .cmd_tx
+        .send(EngineCommand::ReadHistory { reply: tx })
+        .is_err()
+    {
+        return Vec::new();
+    }
+    rx.recv().unwrap_or_default()
+}
@@ -0,0 +1,355 @@
+use crate::api::gpu_composite::{CompositeGroup, LayerGroupSpan, MAX_COMPOSITE_GROUP_DEPTH};
+use crate::gpu::blend_modes::map_canvas_blend_mode_index;
+
+use super::engine::{reorder_vec, EngineCommand};
+
+/// Group id of the canvas itself. Layers and groups without a folder belong
+/// to it, and it never appears in a `LayerGroupSpan`.
+pub(crate) const ROOT_LAYER_GROUP: u32 = 0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct LayerGroup {
+    pub(crate) id: u32,
+    pub(crate) parent: u32,
+    pub(crate) opacity: f32,
+    pub(crate) visible: bool,
+    pub(crate) blend_mode_index: u32,
+    pub(crate) pass_through: bool,
+    pub(crate) collapsed: bool,
+}
+
+/// Layer folder tree on top of the engine's flat, bottom-to-top layer list.
+///
+/// Every layer records the group it sits in. Members of a group are expected
+/// to be adjacent in the layer list; if a reorder splits them, each adjacent
+/// run composites as its own copy of the group.
+pub(crate) struct LayerGroups {
+    groups: Vec<LayerGroup>,
+    layer_parents: Vec<u32>,
+    next_id: u32,
+}
+
+impl LayerGroups {
+    pub(crate) fn new(layer_count: usize) -> Self {
+        Self {
+            groups: Vec::new(),
+            layer_parents: vec![ROOT_LAYER_GROUP; layer_count],
+            next_id: ROOT_LAYER_GROUP + 1,
+        }
+    }
+
+    /// Drops every group; all `layer_count` layers go back to the root.
+    pub(crate) fn reset(&mut self, layer_count: usize) {
+        *self = Self::new(layer_count);
+    }
+
+    /// Follows a layer-count change. New layers start at the root.
+    pub(crate) fn resize_layers(&mut self, layer_count: usize) {
+        self.layer_parents.resize(layer_count, ROOT_LAYER_GROUP);
+    }
+
+    /// Mirrors `ReorderLayer`: the layer keeps its group while it moves.
+    pub(crate) fn reorder_layer(&mut self, from: usize, to: usize) {
+        reorder_vec(&mut self.layer_parents, from, to);
+    }
+
+    pub(crate) fn create_group(&mut self, parent: u32) -> Option<u32> {
+        if !self.exists(parent) {
+            return None;
+        }
+        let id = self.next_id;
+        self.next_id = self.next_id.checked_add(1)?;
+        self.groups.push(LayerGroup {
+            id,
+            parent,
+            opacity: 1.0,
+            visible: true,
+            blend_mode_index: 0,
+            pass_through: true,
+            collapsed: false,
+        });
+        Some(id)
+    }
+
+    /// Removes a group; its layers and child groups move up to its parent.
+    pub(crate) fn delete_group(&mut self, id: u32) -> bool {
+        let Some(position) = self.groups.iter().position(|group| group.id == id) else {
+            return false;
+        };
+        let parent = self.groups.remove(position).parent;
+        for group in self.groups.iter_mut().filter(|group| group.parent == id) {
+            group.parent = parent;
+        }
+        for layer_parent in self.layer_parents.iter_mut().filter(|p| **p == id) {
+            *layer_parent = parent;
+        }
+        true
+    }
+
+    /// Nests group `id` inside `parent`. Refuses moves that would create a
+    /// cycle.
+    pub(crate) fn set_group_parent(&mut self, id: u32, parent: u32) -> bool {
+        if id == ROOT_LAYER_GROUP || !self.exists(id) || !self.exists(parent) {
+            return false;
+        }
+        let mut cursor = parent;
+        while cursor != ROOT_LAYER_GROUP {
+            if cursor == id {
+                return false;
+            }
+            cursor = self
+                .find(cursor)
+                .map_or(ROOT_LAYER_GROUP, |group| group.parent);
+        }
+        if let Some(group) = self.find_mut(id) {
+            group.parent = parent;
+        }
+        true
+    }
+
+    pub(crate) fn set_layer_group(&mut self, layer_index: usize, group: u32) -> bool {
+        if !self.exists(group) {
+            return false;
+        }
+        match self.layer_parents.get_mut(layer_index) {
+            Some(parent) => {
+                *parent = group;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Every group, in creation order.
+    pub(crate) fn groups(&self) -> &[LayerGroup] {
+        &self.groups
+    }
+
+    /// Group `layer_index` sits in; the root when it is out of range.
+    pub(crate) fn layer_group(&self, layer_index: usize) -> u32 {
+        self.layer_parents
+            .get(layer_index)
+            .copied()
+            .unwrap_or(ROOT_LAYER_GROUP)
+    }
+
+    pub(crate) fn set_opacity(&mut self, id: u32, opacity: f32) -> bool {
+        let opacity = if opacity.is_finite() {
+            opacity.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        self.update(id, |group| group.opacity = opacity)
+    }
+
+    pub(crate) fn set_visible(&mut self, id: u32, visible: bool) -> bool {
+        self.update(id, |group| group.visible = visible)
+    }
+
+    pub(crate) fn set_blend_mode(&mut self, id: u32, blend_mode_index: u32) -> bool {
+        self.update(id, |group| group.blend_mode_index = blend_mode_index)
+    }
+
+    pub(crate) fn set_pass_through(&mut self, id: u32, pass_through: bool) -> bool {
+        self.update(id, |group| group.pass_through = pass_through)
+    }
+
+    /// Folded state for the layer panel; it does not affect compositing.
+    pub(crate) fn set_collapsed(&mut self, id: u32, collapsed: bool) -> bool {
+        self.update(id, |group| group.collapsed = collapsed)
+    }
+
+    /// Applies one of the layer-group commands and reports whether the
+    /// composite may have changed. Any other command is ignored.
+    pub(crate) fn apply_command(&mut self, cmd: EngineCommand) -> bool {
+        match cmd {
+            EngineCommand::CreateLayerGroup {
+                parent_group_id,
+                reply,
+            } => {
+                let id = self.create_group(parent_group_id);
+                let _ = reply.send(id.unwrap_or(ROOT_LAYER_GROUP));
+                false
+            }
+            EngineCommand::DeleteLayerGroup { group_id } => self.delete_group(group_id),
+            EngineCommand::SetLayerGroupParent {
+                group_id,
+                parent_group_id,
+            } => self.set_group_parent(group_id, parent_group_id),
+            EngineCommand::MoveLayerToGroup {
+                layer_index,
+                group_id,
+            } => self.set_layer_group(layer_index as usize, group_id),
+            EngineCommand::SetLayerGroupOpacity { group_id, opacity } => {
+                self.set_opacity(group_id, opacity)
+            }
+            EngineCommand::SetLayerGroupVisible { group_id, visible } => {
+                self.set_visible(group_id, visible)
+            }
+            EngineCommand::SetLayerGroupBlendMode {
+                group_id,
+                blend_mode_index,
+            } => self.set_blend_mode(group_id, blend_mode_index),
+            EngineCommand::SetLayerGroupPassThrough {
+                group_id,
+                pass_through,
+            } => self.set_pass_through(group_id, pass_through),
+            EngineCommand::SetLayerGroupCollapsed {
+                group_id,
+                collapsed,
+            } => {
+                self.set_collapsed(group_id, collapsed);
+                false
+            }
+            EngineCommand::GetLayerGroups { reply } => {
+                let _ = reply.send(self.snapshot());
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Flat description of the tree for the host:
+    /// `[group_count, (id, parent, opacity_bits, visible, blend_mode_index,
+    /// pass_through, collapsed) * group_count, layer_count, parent * layer_count]`.
+    pub(crate) fn snapshot(&self) -> Vec<u32> {
+        let mut out = Vec::with_capacity(2 + self.groups.len() * 7 + self.layer_parents.len());
+        out.push(self.groups.len() as u32);
+        for group in &self.groups {
+            out.extend_from_slice(&[
+                group.id,
+                group.parent,
+                group.opacity.to_bits(),
+                group.visible as u32,
+                group.blend_mode_index,
+                group.pass_through as u32,
+                group.collapsed as u32,
+            ]);
+        }
+        out.push(self.layer_parents.len() as u32);
+        out.extend_from_slice(&self.layer_parents);
+        out
+    }
+
+    /// Compositor spans for the first `layer_count` layers, or an empty list
+    /// when there are no groups so callers can take the flat path.
+    pub(crate) fn spans(&self, layer_count: usize) -> Vec<LayerGroupSpan> {
+        if self.groups.is_empty() {
+            return Vec::new();
+        }
+        let mut spans: Vec<LayerGroupSpan> = vec![LayerGroupSpan::default(); layer_count];
+        let mut previous: Vec<u32> = Vec::new();
+        for index in 0..layer_count {
+            let parent = self
+                .layer_parents
+                .get(index)
+                .copied()
+                .unwrap_or(ROOT_LAYER_GROUP);
+            let (chain, hidden) = self.chain(parent);
+            let shared = previous
+                .iter()
+                .zip(chain.iter())
+                .take_while(|(a, b)| a == b)
+                .count();
+            if index > 0 {
+                spans[index - 1].close = (previous.len() - shared) as u32;
+            }
+            spans[index].hidden = hidden;
+            spans[index].open = chain[shared..]
+                .iter()
+                .filter_map(|&id| self.find(id))
+                .map(|group| CompositeGroup {
+                    opacity: group.opacity,
+                    blend_mode_index: map_canvas_blend_mode_index(group.blend_mode_index).as_u32(),
+                    pass_through: group.pass_through,
+                })
+                .collect();
+            previous = chain;
+        }
+        if let Some(last) = spans.last_mut() {
+            last.close = previous.len() as u32;
+        }
+        spans
+    }
+
+    fn exists(&self, id: u32) -> bool {
+        id == ROOT_LAYER_GROUP || self.find(id).is_some()
+    }
+
+    fn find(&self, id: u32) -> Option<&LayerGroup> {
+        self.groups.iter().find(|group| group.id == id)
+    }
+
+    fn find_mut(&mut self, id: u32) -> Option<&mut LayerGroup> {
+        self.groups.iter_mut().find(|group| group.id == id)
+    }
+
+    fn update(&mut self, id: u32, apply: impl FnOnce(&mut LayerGroup)) -> bool {
+        match self.find_mut(id) {
+            Some(group) => {
+                apply(group);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Ancestors of `group`, outermost first and capped at the compositor
+    /// depth, plus whether any of them (including dropped ones) hides it.
+    fn chain(&self, group: u32) -> (Vec<u32>, bool) {
+        let mut chain = Vec::new();
+        let mut hidden = false;
+        let mut cursor = group;
+        while let Some(group) = self.find(cursor) {
+            hidden |= !group.visible || group.opacity <= 0.0;
+            chain.push(group.id);
+            cursor = group.parent;
+        }
+        chain.reverse();
+        chain.truncate(MAX_COMPOSITE_GROUP_DEPTH);
+        (chain, hidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::gpu_composite::{cpu_composite_layers_region, GpuLayerData};
+
+    #[test]
+    fn pass_through_groups_blend_with_the_backdrop() {
+        let layer = |color: u32, blend_mode_index: u32| GpuLayerData {
+            pixels: vec![color],
+            opacity: 1.0,
+            blend_mode_index: map_canvas_blend_mode_index(blend_mode_index).as_u32(),
+            visible: true,
+            clipping_mask: false,
+            mask: None,
+        };
+        // Red backdrop with a multiplied grey layer inside a folder.
+        let layers = [layer(0xFFFF_0000, 0), layer(0xFF80_8080, 1)];
+        let mut groups = LayerGroups::new(layers.len());
+        let group = groups.create_group(ROOT_LAYER_GROUP).unwrap();
+        assert!(groups.set_layer_group(1, group));
+
+        let composite = |groups: &LayerGroups| {
+            let mut out = [0u32];
+            cpu_composite_layers_region(
+                &layers,
+                &groups.spans(2),
+                &[],
+                1,
+                1,
+                (0, 0, 1, 1),
+                &mut out,
+            );
+            out[0]
+        };
+        assert_eq!(composite(&groups), 0xFF80_0000);
+
+        // Isolated, the multiply only sees the empty folder, so the grey
+        // lands on the backdrop through the folder's normal blend.
+        assert!(groups.set_pass_through(group, false));
+        assert_eq!(composite(&groups), 0xFF80_8080);
+    }
+}
@@ -0,0 +1,527 @@
+//! Undo history shared by the GPU and CPU undo managers.
+//!
+//! The history is a tree of canvas states. The undo stack is the path from
+//! the root to the current state and the redo stack the branch a redo
+//! follows. A step recorded while redo steps exist stashes them as a branch
+//! instead of dropping them; [`UndoHistory::route_to`] finds the undo, redo
+//! and branch moves that reach any stored state.
+
+use crate::api::engine_history::{EngineHistoryKind, EngineHistoryNode};
+
+/// Longest edge of a history thumbnail, in pixels.
+const THUMBNAIL_SIZE: u32 = 64;
+
+/// A history step, keyed by the state it leads to.
+struct HistoryEntry<S> {
+    id: u64,
+    step: S,
+}
+
+struct HistoryBranch<S> {
+    parent: u64,
+    // Redo order: the last entry is the child of `parent`.
+    entries: Vec<HistoryEntry<S>>,
+}
+
+/// One move of a route through the history.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum HistoryMove {
+    Undo,
+    Redo,
+    /// Makes the branch starting with this state the one redo follows.
+    Enter(u64),
+}
+
+/// A state of the history, as listed for a history panel.
+pub(crate) struct HistoryNode {
+    pub(crate) id: u64,
+    /// 0 for the root.
+    pub(crate) parent_id: u64,
+    pub(crate) label: String,
+    pub(crate) current: bool,
+    /// Reachable with plain undo and redo, without switching branches.
+    pub(crate) on_active_branch: bool,
+}
+
+impl HistoryNode {
+    pub(crate) fn into_api(
+        self,
+        kind: EngineHistoryKind,
+        thumbnail: HistoryThumbnail,
+    ) -> EngineHistoryNode {
+        EngineHistoryNode {
+            id: self.id,
+            parent_id: self.parent_id,
+            kind,
+            label: self.label,
+            current: self.current,
+            on_active_branch: self.on_active_branch,
+            thumbnail_width: thumbnail.width,
+            thumbnail_height: thumbnail.height,
+            thumbnail: thumbnail.rgba,
+        }
+    }
+}
+
+/// `(left, top, width, height)` in canvas pixels.
+pub(crate) type HistoryRect = (u32, u32, u32, u32);
+
+/// Premultiplied RGBA preview of the area a step changed.
+#[derive(Clone, Default)]
+pub(crate) struct HistoryThumbnail {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) rgba: Vec<u8>,
+}
+
+impl HistoryThumbnail {
+    /// Samples ARGB tiles covering `rect` down to at most
+    /// [`THUMBNAIL_SIZE`] pixels per edge.
+    pub(crate) fn from_tiles(rect: HistoryRect, tiles: &[(HistoryRect, Vec<u32>)]) -> Self {
+        let (left, top, width, height) = rect;
+        if width == 0 || height == 0 {
+            return Self::default();
+        }
+        let scale = (width.max(height) as f32 / THUMBNAIL_SIZE as f32).max(1.0);
+        let thumb_width = ((width as f32 / scale).round() as u32).max(1);
+        let thumb_height = ((height as f32 / scale).round() as u32).max(1);
+        let mut rgba = Vec::with_capacity(thumb_width as usize * thumb_height as usize * 4);
+        for ty in 0..thumb_height {
+            for tx in 0..thumb_width {
+                let x = left + ((tx as f32 + 0.5) * scale) as u32;
+                let y = top + ((ty as f32 + 0.5) * scale) as u32;
+                let argb = tiles
+                    .iter()
+                    .find(|((l, t, w, h), _)| x >= *l && x < l + w && y >= *t && y < t + h)
+                    .map_or(0, |((l, t, w, _), texels)| {
+                        texels
+                            .get(((y - t) * w + (x - l)) as usize)
+                            .copied()
+                            .unwrap_or(0)
+                    });
+                let a = argb >> 24;
+                let premul = |c: u32| ((c * a + 127) / 255) as u8;
+                rgba.extend_from_slice(&[
+                    premul((argb >> 16) & 0xFF),
+                    premul((argb >> 8) & 0xFF),
+                    premul(argb & 0xFF),
+                    a as u8,
+                ]);
+            }
+        }
+        Self {
+            width: thumb_width,
+            height: thumb_height,
+            rgba,
+        }
+    }
+}
+
+pub(crate) struct UndoHistory<S> {
+    next_id: u64,
+    root: u64,
+    undo: Vec<HistoryEntry<S>>,
+    redo: Vec<HistoryEntry<S>>,
+    branches: Vec<HistoryBranch<S>>,
+    checkpoints: Vec<(u64, String)>,
+    // State of the step handed out by the last pop, so its inverse returns
+    // to the same node.
+    popped: u64,
+}
+
+impl<S> UndoHistory<S> {
+    pub(crate) fn new() -> Self {
+        Self {
+            next_id: 2,
+            root: 1,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            branches: Vec::new(),
+            checkpoints: Vec::new(),
+            popped: 0,
+        }
+    }
+
+    pub(crate) fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub(crate) fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub(crate) fn current(&self) -> u64 {
+        self.undo.last().map_or(self.root, |entry| entry.id)
+    }
+
+    pub(crate) fn last_undo(&self) -> Option<&S> {
+        self.undo.last().map(|entry| &entry.step)
+    }
+
+    pub(crate) fn last_redo(&self) -> Option<&S> {
+        self.redo.last().map(|entry| &entry.step)
+    }
+
+    /// Forgets every state; the current one becomes a new root.
+    pub(crate) fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.branches.clear();
+        self.checkpoints.clear();
+        self.root = self.allocate_id();
+    }
+
+    /// Records a new step from the current state. Pending redo steps are
+    /// kept as a branch; see [`Self::trim`] for what `max_steps` drops.
+    pub(crate) fn push(&mut self, step: S, max_steps: usize) {
+        if !self.redo.is_empty() {
+            self.branches.push(HistoryBranch {
+                parent: self.current(),
+                entries: std::mem::take(&mut self.redo),
+            });
+        }
+        let id = self.allocate_id();
+        self.undo.push(HistoryEntry { id, step });
+        self.trim(max_steps);
+    }
+
+    // Number of stored steps, counting every branch.
+    fn len(&self) -> usize {
+        let branched: usize = self.branches.iter().map(|branch| branch.entries.len()).sum();
+        self.undo.len() + self.redo.len() + branched
+    }
+
+    /// Evicts steps in [`Self::evict_oldest`] order until at most
+    /// `max_steps` are stored, branches included.
+    pub(crate) fn trim(&mut self, max_steps: usize) {
+        while self.len() > max_steps {
+            if self.evict_oldest().is_none() {
+                break;
+            }
+        }
+    }
+
+    /// Drops the stored step furthest from the current state and returns it:
+    /// the leaf of the oldest branch, then the bottom of the undo stack, then
+    /// the far end of the redo stack. Branches left without a parent go too,
+    /// so callers tracking a total should recount afterwards.
+    pub(crate) fn evict_oldest(&mut self) -> Option<S> {
+        if let Some(branch) = self.branches.first_mut() {
+            let entry = branch.entries.remove(0);
+            if branch.entries.is_empty() {
+                self.branches.remove(0);
+            }
+            self.prune();
+            return Some(entry.step);
+        }
+        let entry = if !self.undo.is_empty() {
+            let entry = self.undo.remove(0);
+            self.root = entry.id;
+            entry
+        } else if !self.redo.is_empty() {
+            self.redo.remove(0)
+        } else {
+            return None;
+        };
+        self.prune();
+        Some(entry.step)
+    }
+
+    // Drops branches and checkpoints whose states are no longer reachable.
+    fn prune(&mut self) {
+        let mut reachable: Vec<u64> = std::iter::once(self.root)
+            .chain(self.undo.iter().map(|entry| entry.id))
+            .chain(self.redo.iter().map(|entry| entry.id))
+            .collect();
+        let mut pending = std::mem::take(&mut self.branches);
+        loop {
+            let (attached, detached): (Vec<_>, Vec<_>) = pending
+                .into_iter()
+                .partition(|branch| reachable.contains(&branch.parent));
+            if attached.is_empty() {
+                break;
+            }
+            for branch in attached {
+                reachable.extend(branch.entries.iter().map(|entry| entry.id));
+                self.branches.push(branch);
+            }
+            pending = detached;
+        }
+        self.checkpoints.retain(|(id, _)| reachable.contains(id));
+    }
+
+    pub(crate) fn pop_undo(&mut self) -> Option<S> {
+        let entry = self.undo.pop()?;
+        self.popped = entry.id;
+        Some(entry.step)
+    }
+
+    pub(crate) fn pop_redo(&mut self) -> Option<S> {
+        let entry = self.redo.pop()?;
+        self.popped = entry.id;
+        Some(entry.step)
+    }
+
+    /// Stores an undone step (or its inverse) as the next redo step.
+    pub(crate) fn push_redo(&mut self, step: S) {
+        let id = std::mem::take(&mut self.popped);
+        self.redo.push(HistoryEntry { id, step });
+    }
+
+    /// Stores a redone step (or its inverse) back on the undo stack.
+    pub(crate) fn push_undo(&mut self, step: S) {
+        let id = std::mem::take(&mut self.popped);
+        self.undo.push(HistoryEntry { id, step });
+    }
+
+    /// Every stored step, the ones furthest from the current state first.
+    pub(crate) fn steps_mut(&mut self) -> impl Iterator<Item = &mut S> {
+        self.branches
+            .iter_mut()
+            .flat_map(|branch| branch.entries.iter_mut())
+            .chain(self.undo.iter_mut())
+            .chain(self.redo.iter_mut())
+            .map(|entry| &mut entry.step)
+    }
+
+    pub(crate) fn step_mut(&mut self, id: u64) -> Option<&mut S> {
+        self.branches
+            .iter_mut()
+            .flat_map(|branch| branch.entries.iter_mut())
+            .chain(self.undo.iter_mut())
+            .chain(self.redo.iter_mut())
+            .find(|entry| entry.id == id)
+            .map(|entry| &mut entry.step)
+    }
+
+    /// Names the current state; an empty name removes the checkpoint.
+    pub(crate) fn set_checkpoint(&mut self, name: String) {
+        let id = self.current();
+        self.checkpoints.retain(|(checkpoint, _)| *checkpoint != id);
+        if !name.is_empty() {
+            self.checkpoints.push((id, name));
+        }
+    }
+
+    /// Makes the branch starting with state `id` the one redo follows.
+    /// Returns whether it now is.
+    pub(crate) fn enter(&mut self, id: u64) -> bool {
+        if self.redo.last().is_some_and(|entry| entry.id == id) {
+            return true;
+        }
+        let current = self.current();
+        let Some(index) = self.branches.iter().position(|branch| {
+            branch.parent == current && branch.entries.last().is_some_and(|entry| entry.id == id)
+        }) else {
+            return false;
+        };
+        let branch = self.branches.remove(index);
+        if !self.redo.is_empty() {
+            self.branches.push(HistoryBranch {
+                parent: current,
+                entries: std::mem::take(&mut self.redo),
+            });
+        }
+        self.redo = branch.entries;
+        true
+    }
+
+    /// `(state, parent)` of every stored state.
+    fn parents(&self) -> Vec<(u64, u64)> {
+        let mut parents = vec![(self.root, 0)];
+        let mut parent = self.root;
+        for entry in &self.undo {
+            parents.push((entry.id, parent));
+            parent = entry.id;
+        }
+        let chains = std::iter::once((self.current(), &self.redo)).chain(
+            self.branches
+                .iter()
+                .map(|branch| (branch.parent, &branch.entries)),
+        );
+        for (mut parent, entries) in chains {
+            for entry in entries.iter().rev() {
+                parents.push((entry.id, parent));
+                parent = entry.id;
+            }
+        }
+        parents
+    }
+
+    /// Moves that lead from the current state to `target`, or `None` when
+    /// it is not stored.
+    pub(crate) fn route_to(&self, target: u64) -> Option<Vec<HistoryMove>> {
+        let parents = self.parents();
+        let parent_of = |id: u64| parents.iter().find(|(node, _)| *node == id).map(|(_, p)| *p);
+        parent_of(target)?;
+        let path: Vec<u64> = std::iter::once(self.root)
+            .chain(self.undo.iter().map(|entry| entry.id))
+            .collect();
+        // Walk up from the target to the current path.
+        let mut descent: Vec<u64> = Vec::new();
+        let mut node = target;
+        while !path.contains(&node) {
+            descent.push(node);
+            node = parent_of(node)?;
+        }
+        let undo_count = path.len() - 1 - path.iter().position(|id| *id == node)?;
+        let mut moves = vec![HistoryMove::Undo; undo_count];
+        for id in descent.into_iter().rev() {
+            moves.push(HistoryMove::Enter(id));
+            moves.push(HistoryMove::Redo);
+        }
+        Some(moves)
+    }
+
+    pub(crate) fn nodes(&self) -> Vec<HistoryNode> {
+        let current = self.current();
+        let active: Vec<u64> = self
+            .undo
+            .iter()
+            .chain(&self.redo)
+            .map(|entry| entry.id)
+            .chain(std::iter::once(self.root))
+            .collect();
+        self.parents()
+            .into_iter()
+            .map(|(id, parent_id)| HistoryNode {
+                id,
+                parent_id,
+                label: self
+                    .checkpoints
+                    .iter()
+                    .find(|(checkpoint, _)| *checkpoint == id)
+                    .map(|(_, name)| name.clone())
+                    .unwrap_or_default(),
+                current: id == current,
+                on_active_branch: active.contains(&id),
+            })
+            .collect()
+    }
+
+    fn allocate_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::canvas_engine::engine::tests::{read_layer, stop_engine, test_engines};
+    use crate::canvas_engine::engine::{lookup_engine, EngineCommand};
+
+    #[test]
+    fn new_step_after_undo_keeps_a_branch() {
+        let mut history: UndoHistory<&str> = UndoHistory::new();
+        history.push("a", 50);
+        history.push("b", 50);
+        let b = history.current();
+        let step = history.pop_undo().unwrap();
+        history.push_redo(step);
+        history.push("c", 50);
+        history.set_checkpoint("after c".to_string());
+        let c = history.current();
+
+        assert_eq!(
+            history.route_to(b),
+            Some(vec![HistoryMove::Undo, HistoryMove::Enter(b), HistoryMove::Redo])
+        );
+        let step = history.pop_undo().unwrap();
+        history.push_redo(step);
+        assert!(history.enter(b));
+        let step = history.pop_redo().unwrap();
+        history.push_undo(step);
+        assert_eq!(history.current(), b);
+        assert_eq!(history.route_to(c).map(|moves| moves.len()), Some(3));
+        let labelled = history.nodes().into_iter().find(|node| node.label == "after c");
+        assert_eq!(labelled.map(|node| node.id), Some(c));
+    }
+
+    #[test]
+    fn eviction_takes_branches_then_the_oldest_undo_step() {
+        let mut history: UndoHistory<&str> = UndoHistory::new();
+        history.push("a", 50);
+        history.push("b", 50);
+        let step = history.pop_undo().unwrap();
+        history.push_redo(step);
+        history.push("c", 50);
+        history.push("d", 50);
+
+        assert_eq!(history.evict_oldest(), Some("b"));
+        assert_eq!(history.evict_oldest(), Some("a"));
+        assert_eq!(history.nodes().len(), 3);
+        assert_eq!(history.route_to(history.root), Some(vec![HistoryMove::Undo; 2]));
+        assert_eq!(history.evict_oldest(), Some("c"));
+        assert_eq!(history.evict_oldest(), Some("d"));
+        assert_eq!(history.evict_oldest(), None);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn step_limit_counts_branch_steps() {
+        let mut history: UndoHistory<&str> = UndoHistory::new();
+        history.push("a", 3);
+        // Each push after an undo stashes the undone step as a branch.
+        for step in ["b", "c", "d"] {
+            history.push(step, 3);
+            let undone = history.pop_undo().unwrap();
+            history.push_redo(undone);
+        }
+        let mut kept: Vec<&str> = history.steps_mut().map(|step| *step).collect();
+        assert_eq!(kept, ["c", "a", "d"]);
+        assert_eq!(history.nodes().len(), 4);
+
+        history.trim(1);
+        kept = history.steps_mut().map(|step| *step).collect();
+        assert_eq!(kept, ["d"]);
+        assert!(history.can_redo() && !history.can_undo());
+    }
+
+    #[test]
+    fn engines_return_to_a_branch_through_its_route() {
+        for handle in test_engines(16, 16) {
+            let entry = lookup_engine(handle).unwrap();
+            let send = |cmd: EngineCommand| entry.cmd_tx.send(cmd).unwrap();
+            let read_history = || {
+                let (reply, rx) = mpsc::channel();
+                send(EngineCommand::ReadHistory { reply });
+                rx.recv().unwrap()
+            };
+            let current = || read_history().into_iter().find(|node| node.current).unwrap();
+            send(EngineCommand::FillLayer {
+                layer_index: 0,
+                color_argb: 0xFFFF0000,
+            });
+            let red = current();
+            assert!(red.thumbnail_width > 0 && !red.thumbnail.is_empty());
+            send(EngineCommand::Undo);
+            send(EngineCommand::FillLayer {
+                layer_index: 0,
+                color_argb: 0xFF0000FF,
+            });
+            let backend = entry.backend;
+            let nodes = read_history();
+            let stashed = nodes.iter().find(|node| node.id == red.id).unwrap();
+            assert!(!stashed.on_active_branch, "{backend:?}: red fill should be a branch");
+
+            let (reply, rx) = mpsc::channel();
+            send(EngineCommand::PlanHistoryRoute {
+                node_id: red.id,
+                reply,
+            });
+            let route = rx.recv().unwrap();
+            let expected = vec![HistoryMove::Undo, HistoryMove::Enter(red.id), HistoryMove::Redo];
+            assert_eq!(route, Some(expected), "{backend:?}");
+            send(EngineCommand::Undo);
+            send(EngineCommand::EnterHistoryBranch { node_id: red.id });
+            send(EngineCommand::Redo);
+            assert_eq!(read_layer(&entry, 0), vec![0xFFFF0000; 256], "{backend:?}");
+            assert_eq!(current().id, red.id, "{backend:?}");
+            stop_engine(handle);
+        }
+    }
+}
@@ -0,0 +1,1910 @@
+//! Opt-in session journal for the canvas engine.
+//!
+//! When a journal directory is configured (`engine_set_journal_dir` or the
+//! `MISA_RIN_RUST_JOURNAL_DIR` environment variable), every engine created
+//! afterwards writes a binary log of the commands and input batches its render
+//! thread processes, in processing order. `replay_journal` feeds such a log
+//! into a fresh engine of the backend that recorded it so a reported session
+//! can be reproduced exactly. GPU journals replay on the GPU engine and are
+//! refused when no adapter is available, since the software engine does not
+//! rasterize bit-identically.
+//!
+//! File layout (all integers little-endian):
+//!
+//! ```text
+//! header : b"MRJOURNL" | version u32 | backend u32 | width u32 | height u32
+//! record : kind u8 | elapsed_us u64 | payload_len u32 | payload
+//! ```
+//!
+//! `kind` is `RECORD_COMMAND` or `RECORD_INPUT`. Command payloads start with a
+//! `u16` opcode followed by the command fields; reply senders and platform
+//! texture handles are not recorded. Input payloads hold the render thread's
+//! input backlog (which drives the stroke resample scale) followed by the
+//! `EnginePoint`s of the merged batch. Version 1 journals predate the pen
+//! tilt, azimuth and rotation axes; their points replay with those at 0.
+//! Version 3 journals may carry the layer mask commands; version 1 and 2
+//! journals are still read, they just never contain them.
+
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::api::gpu_composite::{apply_mask_value, GpuLayerData};
+use crate::gpu::debug::{self, LogLevel};
+
+use super::cpu_engine::replay_journal_on_cpu;
+use super::engine::{replay_journal_on_gpu, EngineCommand};
+use super::types::{EnginePoint, SprayPoint};
+
+const JOURNAL_MAGIC: &[u8; 8] = b"MRJOURNL";
+const JOURNAL_VERSION: u32 = 3;
+// Last version whose input records carry no pen axes.
+const JOURNAL_VERSION_NO_PEN_AXES: u32 = 1;
+const JOURNAL_DIR_ENV: &str = "MISA_RIN_RUST_JOURNAL_DIR";
+
+const LAYER_DUMP_MAGIC: &[u8; 8] = b"MRLAYERS";
+// Version 2 bakes enabled layer masks into the dumped alpha.
+const LAYER_DUMP_VERSION: u32 = 2;
+
+const RECORD_COMMAND: u8 = 0;
+const RECORD_INPUT: u8 = 1;
+
+pub(crate) const JOURNAL_BACKEND_GPU: u32 = 0;
+pub(crate) const JOURNAL_BACKEND_CPU: u32 = 1;
+
+const FLAG_UP: u32 = 4;
+
+static JOURNAL_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+static JOURNAL_SEQ: AtomicU64 = AtomicU64::new(0);
+
+mod opcode {
+    pub(super) const ATTACH_PRESENT_TEXTURE: u16 = 1;
+    pub(super) const ATTACH_PRESENT_DXGI: u16 = 2;
+    pub(super) const RESET_CANVAS: u16 = 3;
+    pub(super) const RESET_CANVAS_WITH_LAYERS: u16 = 4;
+    pub(super) const RESIZE_CANVAS: u16 = 5;
+    pub(super) const FILL_LAYER: u16 = 6;
+    pub(super) const CLEAR_LAYER: u16 = 7;
+    pub(super) const SET_ACTIVE_LAYER: u16 = 8;
+    pub(super) const SET_LAYER_OPACITY: u16 = 9;
+    pub(super) const SET_LAYER_VISIBLE: u16 = 10;
+    pub(super) const SET_LAYER_CLIPPING_MASK: u16 = 11;
+    pub(super) const SET_LAYER_BLEND_MODE: u16 = 12;
+    pub(super) const REORDER_LAYER: u16 = 13;
+    pub(super) const SET_VIEW_FLAGS: u16 = 14;
+    pub(super) const SET_BRUSH: u16 = 15;
+    pub(super) const SET_BRUSH_MASK: u16 = 16;
+    pub(super) const CLEAR_BRUSH_MASK: u16 = 17;
+    pub(super) const BEGIN_SPRAY: u16 = 18;
+    pub(super) const DRAW_SPRAY: u16 = 19;
+    pub(super) const END_SPRAY: u16 = 20;
+    pub(super) const APPLY_FILTER: u16 = 21;
+    pub(super) const APPLY_ANTIALIAS: u16 = 22;
+    pub(super) const BUCKET_FILL: u16 = 23;
+    pub(super) const MAGIC_WAND_MASK: u16 = 24;
+    pub(super) const READ_LAYER: u16 = 25;
+    pub(super) const READ_LAYER_PREVIEW: u16 = 26;
+    pub(super) const READ_PRESENT: u16 = 27;
+    pub(super) const WRITE_LAYER: u16 = 28;
+    pub(super) const TRANSLATE_LAYER: u16 = 29;
+    pub(super) const SET_LAYER_TRANSFORM_PREVIEW: u16 = 30;
+    pub(super) const APPLY_LAYER_TRANSFORM: u16 = 31;
+    pub(super) const GET_LAYER_BOUNDS: u16 = 32;
+    pub(super) const SET_SELECTION_MASK: u16 = 33;
+    pub(super) const UNDO: u16 = 34;
+    pub(super) const REDO: u16 = 35;
+    pub(super) const STOP: u16 = 36;
+    pub(super) const CREATE_LAYER_GROUP: u16 = 37;
+    pub(super) const DELETE_LAYER_GROUP: u16 = 38;
+    pub(super) const SET_LAYER_GROUP_PARENT: u16 = 39;
+    pub(super) const MOVE_LAYER_TO_GROUP: u16 = 40;
+    pub(super) const SET_LAYER_GROUP_OPACITY: u16 = 41;
+    pub(super) const SET_LAYER_GROUP_VISIBLE: u16 = 42;
+    pub(super) const SET_LAYER_GROUP_BLEND_MODE: u16 = 43;
+    pub(super) const SET_LAYER_GROUP_PASS_THROUGH: u16 = 44;
+    pub(super) const SET_LAYER_GROUP_COLLAPSED: u16 = 45;
+    pub(super) const GET_LAYER_GROUPS: u16 = 46;
+    pub(super) const CREATE_LAYER_MASK: u16 = 47;
+    pub(super) const DELETE_LAYER_MASK: u16 = 48;
+    pub(super) const SET_LAYER_MASK_ENABLED: u16 = 49;
+    pub(super) const SET_LAYER_MASK_EDITING: u16 = 50;
+    pub(super) const INVERT_LAYER_MASK: u16 = 51;
+    pub(super) const APPLY_LAYER_MASK: u16 = 52;
+    pub(super) const READ_LAYER_MASK: u16 = 53;
+    pub(super) const SET_LAYER_ADJUSTMENT: u16 = 54;
+    pub(super) const CLEAR_LAYER_ADJUSTMENT: u16 = 55;
+    pub(super) const SET_LAYER_ALPHA_LOCK: u16 = 56;
+    pub(super) const READ_PROJECT: u16 = 57;
+    pub(super) const INSERT_LAYER: u16 = 58;
+    pub(super) const REMOVE_LAYER: u16 = 59;
+    pub(super) const CLEAR_UNDO_HISTORY: u16 = 60;
+    pub(super) const SET_UNDO_LIMITS: u16 = 61;
+    pub(super) const BEGIN_UNDO_TRANSACTION: u16 = 62;
+    pub(super) const COMMIT_UNDO_TRANSACTION: u16 = 63;
+    pub(super) const CANCEL_UNDO_TRANSACTION: u16 = 64;
+    pub(super) const SET_HISTORY_CHECKPOINT: u16 = 65;
+    pub(super) const PLAN_HISTORY_ROUTE: u16 = 66;
+    pub(super) const ENTER_HISTORY_BRANCH: u16 = 67;
+    pub(super) const READ_HISTORY: u16 = 68;
+    pub(super) const SET_PEN_DYNAMICS: u16 = 69;
+    pub(super) const SET_PRESSURE_CURVE: u16 = 70;
+    pub(super) const SET_SIMULATED_PRESSURE: u16 = 71;
+    pub(super) const SET_SPRING_STABILIZER: u16 = 72;
+    pub(super) const SET_ASSIST: u16 = 73;
+    pub(super) const SET_SYMMETRY: u16 = 74;
+    pub(super) const SET_GRAIN_TEXTURE: u16 = 75;
+    pub(super) const SET_GRAIN: u16 = 76;
+    pub(super) const SET_MIXING: u16 = 77;
+    pub(super) const SET_WATERCOLOR: u16 = 78;
+    pub(super) const SET_DUAL_BRUSH: u16 = 79;
+    pub(super) const SET_DUAL_BRUSH_MASK: u16 = 80;
+    pub(super) const SET_COLOR_DYNAMICS: u16 = 81;
+    pub(super) const WRITE_LAYER_MASK: u16 = 82;
+}
+
+/// Sets the directory new engines write their journal into. `None` falls back
+/// to `MISA_RIN_RUST_JOURNAL_DIR`; journaling is off when neither is set.
+pub(crate) fn set_journal_dir(dir: Option<PathBuf>) {
+    if let Ok(mut guard) = JOURNAL_DIR.lock() {
+        *guard = dir;
+    }
+}
+
+fn journal_dir() -> Option<PathBuf> {
+    let configured = JOURNAL_DIR.lock().ok().and_then(|guard| guard.clone());
+    configured.or_else(|| std::env::var_os(JOURNAL_DIR_ENV).map(PathBuf::from))
+}
+
+/// Opens a journal for a newly created engine if journaling is enabled.
+/// Failures are logged and leave the engine running without a journal.
+pub(crate) fn open_engine_journal(backend: u32, width: u32, height: u32) -> Option<EngineJournal> {
+    let dir = journal_dir()?;
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let seq = JOURNAL_SEQ.fetch_add(1, Ordering::Relaxed);
+    let path = dir.join(format!("misa-rin-{now_ms}-{seq}.mrj"));
+    match EngineJournal::create(&path, backend, width, height) {
+        Ok(journal) => {
+            debug::log(
+                LogLevel::Info,
+                format_args!("engine journal recording to {}", path.display()),
+            );
+            Some(journal)
+        }
+        Err(err) => {
+            debug::log(
+                LogLevel::Warn,
+                format_args!("engine journal open failed ({}): {err}", path.display()),
+            );
+            None
+        }
+    }
+}
+
+pub(crate) struct EngineJournal {
+    writer: BufWriter<File>,
+    started: Instant,
+    failed: bool,
+}
+
+impl EngineJournal {
+    fn create(path: &Path, backend: u32, width: u32, height: u32) -> Result<Self, String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+        }
+        let file = File::create(path).map_err(|err| err.to_string())?;
+        let mut writer = BufWriter::new(file);
+        let mut header = ByteWriter::default();
+        header.bytes(JOURNAL_MAGIC);
+        header.u32(JOURNAL_VERSION);
+        header.u32(backend);
+        header.u32(width);
+        header.u32(height);
+        writer
+            .write_all(&header.buf)
+            .and_then(|_| writer.flush())
+            .map_err(|err| err.to_string())?;
+        Ok(Self {
+            writer,
+            started: Instant::now(),
+            failed: false,
+        })
+    }
+
+    pub(crate) fn record_command(&mut self, cmd: &EngineCommand) {
+        let mut payload = ByteWriter::default();
+        encode_command(&mut payload, cmd);
+        self.write_record(RECORD_COMMAND, &payload.buf);
+        // Commands are sparse compared to input; flushing keeps the log usable
+        // if the app dies right after the action being reported.
+        self.flush();
+    }
+
+    pub(crate) fn record_input(&mut self, backlog_points: u64, points: &[EnginePoint]) {
+        let mut payload = ByteWriter::default();
+        payload.u64(backlog_points);
+        payload.u32(points.len() as u32);
+        for p in points {
+            payload.f32(p.x);
+            payload.f32(p.y);
+            payload.f32(p.pressure);
+            payload.u64(p.timestamp_us);
+            payload.u32(p.flags);
+            payload.u32(p.pointer_id);
+            payload.f32(p.tilt);
+            payload.f32(p.azimuth);
+            payload.f32(p.rotation);
+        }
+        self.write_record(RECORD_INPUT, &payload.buf);
+        if points.iter().any(|p| (p.flags & FLAG_UP) != 0) {
+            self.flush();
+        }
+    }
+
+    fn write_record(&mut self, kind: u8, payload: &[u8]) {
+        if self.failed {
+            return;
+        }
+        let elapsed_us = self.started.elapsed().as_micros() as u64;
+        let mut head = ByteWriter::default();
+        head.u8(kind);
+        head.u64(elapsed_us);
+        head.u32(payload.len() as u32);
+        let result = self
+            .writer
+            .write_all(&head.buf)
+            .and_then(|_| self.writer.write_all(payload));
+        if let Err(err) = result {
+            self.fail(err);
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.failed {
+            return;
+        }
+        if let Err(err) = self.writer.flush() {
+            self.fail(err);
+        }
+    }
+
+    fn fail(&mut self, err: std::io::Error) {
+        self.failed = true;
+        debug::log(
+            LogLevel::Warn,
+            format_args!("engine journal write failed, recording stopped: {err}"),
+        );
+    }
+}
+
+pub(crate) enum JournalEntry {
+    Command(EngineCommand),
+    Input {
+        backlog_points: u64,
+        points: Vec<EnginePoint>,
+    },
+}
+
+pub(crate) struct JournalRecord {
+    pub(crate) elapsed_us: u64,
+    pub(crate) entry: JournalEntry,
+}
+
+pub(crate) struct JournalLog {
+    pub(crate) version: u32,
+    pub(crate) backend: u32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) records: Vec<JournalRecord>,
+}
+
+pub(crate) fn read_journal(path: &Path) -> Result<JournalLog, String> {
+    let mut bytes = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|err| format!("journal read failed ({}): {err}", path.display()))?;
+    decode_journal(&bytes)
+}
+
+fn decode_journal(bytes: &[u8]) -> Result<JournalLog, String> {
+    let mut reader = ByteReader::new(bytes);
+    if reader.take(JOURNAL_MAGIC.len())? != JOURNAL_MAGIC {
+        return Err("journal: bad magic".to_string());
+    }
+    let version = reader.u32()?;
+    if !(JOURNAL_VERSION_NO_PEN_AXES..=JOURNAL_VERSION).contains(&version) {
+        return Err(format!("journal: unsupported version {version}"));
+    }
+    let backend = reader.u32()?;
+    let width = reader.u32()?;
+    let height = reader.u32()?;
+    if width == 0 || height == 0 {
+        return Err("journal: canvas size must be > 0".to_string());
+    }
+
+    let mut records = Vec::new();
+    while !reader.is_empty() {
+        // A session that crashed mid-write leaves a partial trailing record;
+        // everything before it is still worth replaying.
+        let head = reader.u8().and_then(|kind| Ok((kind, reader.u64()?, reader.u32()?)));
+        let Ok((kind, elapsed_us, payload_len)) = head else {
+            debug::log(LogLevel::Warn, format_args!("journal: truncated record header"));
+            break;
+        };
+        let Ok(payload) = reader.take(payload_len as usize) else {
+            debug::log(LogLevel::Warn, format_args!("journal: truncated record payload"));
+            break;
+        };
+        let mut payload = ByteReader::new(payload);
+        let entry = match kind {
+            RECORD_COMMAND => JournalEntry::Command(decode_command(&mut payload)?),
+            RECORD_INPUT => {
+                let backlog_points = payload.u64()?;
+                let count = payload.u32()? as usize;
+                let mut points = Vec::with_capacity(count.min(payload.remaining() / 28));
+                for _ in 0..count {
+                    let mut point = EnginePoint {
+                        x: payload.f32()?,
+                        y: payload.f32()?,
+                        pressure: payload.f32()?,
+                        _pad0: 0.0,
+                        timestamp_us: payload.u64()?,
+                        flags: payload.u32()?,
+                        pointer_id: payload.u32()?,
+                        tilt: 0.0,
+                        azimuth: 0.0,
+                        rotation: 0.0,
+                        device: 0,
+                    };
+                    if version != JOURNAL_VERSION_NO_PEN_AXES {
+                        point.tilt = payload.f32()?;
+                        point.azimuth = payload.f32()?;
+                        point.rotation = payload.f32()?;
+                    }
+                    points.push(point);
+                }
+                JournalEntry::Input {
+                    backlog_points,
+                    points,
+                }
+            }
+            other => return Err(format!("journal: unknown record kind {other}")),
+        };
+        records.push(JournalRecord { elapsed_us, entry });
+    }
+
+    Ok(JournalLog {
+        version,
+        backend,
+        width,
+        height,
+        records,
+    })
+}
+
+pub(crate) struct ReplayedCanvas {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) layers: Vec<GpuLayerData>,
+}
+
+/// Replays a journal on the backend that recorded it and returns the
+/// resulting layers. A GPU journal is refused rather than replayed in software
+/// when no adapter is available.
+pub(crate) fn replay_journal(path: &Path) -> Result<ReplayedCanvas, String> {
+    let log = read_journal(path)?;
+    let (version, backend) = (log.version, log.backend);
+    let record_count = log.records.len();
+    let span_us = log.records.last().map(|record| record.elapsed_us).unwrap_or(0);
+    let canvas = match backend {
+        JOURNAL_BACKEND_CPU => replay_journal_on_cpu(log),
+        JOURNAL_BACKEND_GPU => replay_journal_on_gpu(log)?,
+        other => return Err(format!("journal: unknown backend {other}")),
+    };
+    debug::log(
+        LogLevel::Info,
+        format_args!(
+            "journal replay: version={version} backend={backend} records={record_count} \
+             span={:.1}s layers={}",
+            span_us as f64 / 1_000_000.0,
+            canvas.layers.len()
+        ),
+    );
+    Ok(canvas)
+}
+
+/// Writes replayed layers as a flat dump:
+///
+/// ```text
+/// b"MRLAYERS" | version u32 | width u32 | height u32 | layer_count u32
+/// per layer: opacity f32 | blend_mode u32 | visible u8 | clipping u8 | ARGB u32 * w * h
+/// ```
+///
+/// Enabled layer masks are baked into the dumped alpha.
+pub(crate) fn write_layer_dump(
+    path: &Path,
+    width: u32,
+    height: u32,
+    layers: &[GpuLayerData],
+) -> Result<(), String> {
+    let mut out = ByteWriter::default();
+    out.bytes(LAYER_DUMP_MAGIC);
+    out.u32(LAYER_DUMP_VERSION);
+    out.u32(width);
+    out.u32(height);
+    out.u32(layers.len() as u32);
+    for layer in layers {
+        out.f32(layer.opacity as f32);
+        out.u32(layer.blend_mode_index);
+        out.bool(layer.visible);
+        out.bool(layer.clipping_mask);
+        match layer.mask.as_deref() {
+            Some(mask) if mask.len() == layer.pixels.len() => {
+                let masked: Vec<u32> = layer
+                    .pixels
+                    .iter()
+                    .zip(mask)
+                    .map(|(&pixel, &value)| apply_mask_value(pixel, value))
+                    .collect();
+                out.u32_slice(&masked);
+            }
+            _ => out.u32_slice(&layer.pixels),
+        }
+    }
+    std::fs::write(path, &out.buf)
+        .map_err(|err| format!("layer dump write failed ({}): {err}", path.display()))
+}
+
+fn encode_command(out: &mut ByteWriter, cmd: &EngineCommand) {
+    match cmd {
+        EngineCommand::AttachPresentTexture {
+            width,
+            height,
+            bytes_per_row,
+            ..
+        } => {
+            out.u16(opcode::ATTACH_PRESENT_TEXTURE);
+            out.u32(*width);
+            out.u32(*height);
+            out.u32(*bytes_per_row);
+        }
+        #[cfg(target_os = "windows")]
+        EngineCommand::AttachPresentDxgi { width, height, .. } => {
+            out.u16(opcode::ATTACH_PRESENT_DXGI);
+            out.u32(*width);
+            out.u32(*height);
+        }
+        EngineCommand::ResetCanvas {
+            background_color_argb,
+        } => {
+            out.u16(opcode::RESET_CANVAS);
+            out.u32(*background_color_argb);
+        }
+        EngineCommand::ResetCanvasWithLayers {
+            layer_count,
+            background_color_argb,
+        } => {
+            out.u16(opcode::RESET_CANVAS_WITH_LAYERS);
+            out.u32(*layer_count);
+            out.u32(*background_color_argb);
+        }
+        EngineCommand::ResizeCanvas {
+            width,
+            height,
+            layer_count,
+            background_color_argb,
+            ..
+        } => {
+            out.u16(opcode::RESIZE_CANVAS);
+            out.u32(*width);
+            out.u32(*height);
+            out.u32(*layer_count);
+            out.u32(*background_color_argb);
+        }
+        EngineCommand::FillLayer {
+            layer_index,
+            color_argb,
+        } => {
+            out.u16(opcode::FILL_LAYER);
+            out.u32(*layer_index);
+            out.u32(*color_argb);
+        }
+        EngineCommand::ClearLayer { layer_index } => {
+            out.u16(opcode::CLEAR_LAYER);
+            out.u32(*layer_index);
+        }
+        EngineCommand::SetActiveLayer { layer_index } => {
+            out.u16(opcode::SET_ACTIVE_LAYER);
+            out.u32(*layer_index);
+        }
+        EngineCommand::SetLayerOpacity {
+            layer_index,
+            opacity,
+        } => {
+            out.u16(opcode::SET_LAYER_OPACITY);
+            out.u32(*layer_index);
+            out.f32(*opacity);
+        }
+        EngineCommand::SetLayerVisible {
+            layer_index,
+            visible,
+        } => {
+            out.u16(opcode::SET_LAYER_VISIBLE);
+            out.u32(*layer_index);
+            out.bool(*visible);
+        }
+        EngineCommand::SetLayerClippingMask {
+            layer_index,
+            clipping_mask,
+        } => {
+            out.u16(opcode::SET_LAYER_CLIPPING_MASK);
+            out.u32(*layer_index);
+            out.bool(*clipping_mask);
+        }
+        EngineCommand::SetLayerAlphaLock {
+            layer_index,
+            locked,
+        } => {
+            out.u16(opcode::SET_LAYER_ALPHA_LOCK);
+            out.u32(*layer_index);
+            out.bool(*locked);
+        }
+        EngineCommand::SetLayerBlendMode {
+            layer_index,
+            blend_mode_index,
+        } => {
+            out.u16(opcode::SET_LAYER_BLEND_MODE);
+            out.u32(*layer_index);
+            out.u32(*blend_mode_index);
+        }
+        EngineCommand::ReorderLayer {
+            from_index,
+            to_index,
+        } => {
+            out.u16(opcode::REORDER_LAYER);
+            out.u32(*from_index);
+            out.u32(*to_index);
+        }
+        EngineCommand::InsertLayer { layer_index } => {
+            out.u16(opcode::INSERT_LAYER);
+            out.u32(*layer_index);
+        }
+        EngineCommand::RemoveLayer { layer_index } => {
+            out.u16(opcode::REMOVE_LAYER);
+            out.u32(*layer_index);
+        }
+        EngineCommand::CreateLayerGroup {
+            parent_group_id, ..
+        } => {
+            out.u16(opcode::CREATE_LAYER_GROUP);
+            out.u32(*parent_group_id);
+        }
+        EngineCommand::DeleteLayerGroup { group_id } => {
+            out.u16(opcode::DELETE_LAYER_GROUP);
+            out.u32(*group_id);
+        }
+        EngineCommand::SetLayerGroupParent {
+            group_id,
+            parent_group_id,
+        } => {
+            out.u16(opcode::SET_LAYER_GROUP_PARENT);
+            out.u32(*group_id);
+            out.u32(*parent_group_id);
+        }
+        EngineCommand::MoveLayerToGroup {
+            layer_index,
+            group_id,
+        } => {
+            out.u16(opcode::MOVE_LAYER_TO_GROUP);
+            out.u32(*layer_index);
+            out.u32(*group_id);
+        }
+        EngineCommand::SetLayerGroupOpacity { group_id, opacity } => {
+            out.u16(opcode::SET_LAYER_GROUP_OPACITY);
+            out.u32(*group_id);
+            out.f32(*opacity);
+        }
+        EngineCommand::SetLayerGroupVisible { group_id, visible } => {
+            out.u16(opcode::SET_LAYER_GROUP_VISIBLE);
+            out.u32(*group_id);
+            out.bool(*visible);
+        }
+        EngineCommand::SetLayerGroupBlendMode {
+            group_id,
+            blend_mode_index,
+        } => {
+            out.u16(opcode::SET_LAYER_GROUP_BLEND_MODE);
+            out.u32(*group_id);
+            out.u32(*blend_mode_index);
+        }
+        EngineCommand::SetLayerGroupPassThrough {
+            group_id,
+            pass_through,
+        } => {
+            out.u16(opcode::SET_LAYER_GROUP_PASS_THROUGH);
+            out.u32(*group_id);
+            out.bool(*pass_through);
+        }
+        EngineCommand::SetLayerGroupCollapsed {
+            group_id,
+            collapsed,
+        } => {
+            out.u16(opcode::SET_LAYER_GROUP_COLLAPSED);
+            out.u32(*group_id);
+            out.bool(*collapsed);
+        }
+        EngineCommand::GetLayerGroups { .. } => out.u16(opcode::GET_LAYER_GROUPS),
+        EngineCommand::CreateLayerMask { layer_index } => {
+            out.u16(opcode::CREATE_LAYER_MASK);
+            out.u32(*layer_index);
+        }
+        EngineCommand::DeleteLayerMask { layer_index } => {
+            out.u16(opcode::DELETE_LAYER_MASK);
+            out.u32(*layer_index);
+        }
+        EngineCommand::SetLayerMaskEnabled {
+            layer_index,
+            enabled,
+        } => {
+            out.u16(opcode::SET_LAYER_MASK_ENABLED);
+            out.u32(*layer_index);
+            out.bool(*enabled);
+        }
+        EngineCommand::SetLayerMaskEditing { editing } => {
+            out.u16(opcode::SET_LAYER_MASK_EDITING);
+            out.bool(*editing);
+        }
+        EngineCommand::InvertLayerMask { layer_index } => {
+            out.u16(opcode::INVERT_LAYER_MASK);
+            out.u32(*layer_index);
+        }
+        EngineCommand::ApplyLayerMask { layer_index } => {
+            out.u16(opcode::APPLY_LAYER_MASK);
+            out.u32(*layer_index);
+        }
+        EngineCommand::ReadLayerMask { layer_index, .. } => {
+            out.u16(opcode::READ_LAYER_MASK);
+            out.u32(*layer_index);
+        }
+        EngineCommand::WriteLayerMask {
+            layer_index,
+            coverage,
+            ..
+        } => {
+            out.u16(opcode::WRITE_LAYER_MASK);
+            out.u32(*layer_index);
+            out.u8_vec(coverage);
+        }
+        EngineCommand::SetLayerAdjustment {
+            layer_index,
+            filter_type,
+            param0,
+            param1,
+            param2,
+            param3,
+        } => {
+            out.u16(opcode::SET_LAYER_ADJUSTMENT);
+            out.u32(*layer_index);
+            out.u32(*filter_type);
+            out.f32(*param0);
+            out.f32(*param1);
+            out.f32(*param2);
+            out.f32(*param3);
+        }
+        EngineCommand::ClearLayerAdjustment { layer_index } => {
+            out.u16(opcode::CLEAR_LAYER_ADJUSTMENT);
+            out.u32(*layer_index);
+        }
+        EngineCommand::SetViewFlags { view_flags } => {
+            out.u16(opcode::SET_VIEW_FLAGS);
+            out.u32(*view_flags);
+        }
+        EngineCommand::SetBrush {
+            color_argb,
+            base_radius,
+            use_pressure,
+            erase,
+            antialias_level,
+            brush_shape,
+            random_rotation,
+            smooth_rotation,
+            rotation_seed,
+            spacing,
+            hardness,
+            flow,
+            scatter,
+            rotation_jitter,
+            snap_to_pixel,
+            screentone_enabled,
+            screentone_spacing,
+            screentone_dot_size,
+            screentone_rotation,
+            screentone_softness,
+            screentone_shape,
+            hollow_enabled,
+            hollow_ratio,
+            hollow_erase_occluded,
+            streamline_strength,
+            smoothing_mode,
+            stabilizer_strength,
+        } => {
+            out.u16(opcode::SET_BRUSH);
+            out.u32(*color_argb);
+            out.f32(*base_radius);
+            out.bool(*use_pressure);
+            out.bool(*erase);
+            out.u32(*antialias_level);
+            out.u32(*brush_shape);
+            out.bool(*random_rotation);
+            out.bool(*smooth_rotation);
+            out.u32(*rotation_seed);
+            out.f32(*spacing);
+            out.f32(*hardness);
+            out.f32(*flow);
+            out.f32(*scatter);
+            out.f32(*rotation_jitter);
+            out.bool(*snap_to_pixel);
+            out.bool(*screentone_enabled);
+            out.f32(*screentone_spacing);
+            out.f32(*screentone_dot_size);
+            out.f32(*screentone_rotation);
+            out.f32(*screentone_softness);
+            out.u32(*screentone_shape);
+            out.bool(*hollow_enabled);
+            out.f32(*hollow_ratio);
+            out.bool(*hollow_erase_occluded);
+            out.f32(*streamline_strength);
+            out.u32(*smoothing_mode);
+            out.f32(*stabilizer_strength);
+        }
+        EngineCommand::SetBrushMask {
+            width,
+            height,
+            mask,
+        } => {
+            out.u16(opcode::SET_BRUSH_MASK);
+            out.u32(*width);
+            out.u32(*height);
+            out.u8_vec(mask);
+        }
+        EngineCommand::ClearBrushMask => out.u16(opcode::CLEAR_BRUSH_MASK),
+        EngineCommand::SetPenDynamics {
+            tilt_size,
+            tilt_opacity,
+            azimuth_angle,
+            barrel_angle,
+        } => {
+            out.u16(opcode::SET_PEN_DYNAMICS);
+            out.f32(*tilt_size);
+            out.f32(*tilt_opacity);
+            out.bool(*azimuth_angle);
+            out.bool(*barrel_angle);
+        }
+        EngineCommand::SetPressureCurve { target, points } => {
+            out.u16(opcode::SET_PRESSURE_CURVE);
+            out.u32(*target);
+            out.u32(points.len() as u32);
+            for (x, y) in points {
+                out.f32(*x);
+                out.f32(*y);
+            }
+        }
+        EngineCommand::SetSimulatedPressure {
+            profile,
+            tapered_caps,
+        } => {
+            out.u16(opcode::SET_SIMULATED_PRESSURE);
+            out.u32(*profile);
+            out.bool(*tapered_caps);
+        }
+        EngineCommand::SetSpringStabilizer {
+            mass,
+            stiffness,
+            damping,
+        } => {
+            out.u16(opcode::SET_SPRING_STABILIZER);
+            out.f32(*mass);
+            out.f32(*stiffness);
+            out.f32(*damping);
+        }
+        EngineCommand::SetAssist {
+            kind,
+            params,
+            show_overlay,
+        } => {
+            out.u16(opcode::SET_ASSIST);
+            out.u32(*kind);
+            out.u32(params.len() as u32);
+            for value in params {
+                out.f32(*value);
+            }
+            out.bool(*show_overlay);
+        }
+        EngineCommand::SetSymmetry {
+            mode,
+            center_x,
+            center_y,
+            folds,
+        } => {
+            out.u16(opcode::SET_SYMMETRY);
+            out.u32(*mode);
+            out.f32(*center_x);
+            out.f32(*center_y);
+            out.u32(*folds);
+        }
+        EngineCommand::SetGrainTexture {
+            width,
+            height,
+            pixels,
+        } => {
+            out.u16(opcode::SET_GRAIN_TEXTURE);
+            out.u32(*width);
+            out.u32(*height);
+            out.u8_vec(pixels);
+        }
+        EngineCommand::SetGrain {
+            enabled,
+            scale,
+            offset_x,
+            offset_y,
+            depth,
+            invert,
+            blend,
+        } => {
+            out.u16(opcode::SET_GRAIN);
+            out.bool(*enabled);
+            out.f32(*scale);
+            out.f32(*offset_x);
+            out.f32(*offset_y);
+            out.f32(*depth);
+            out.bool(*invert);
+            out.u32(*blend);
+        }
+        EngineCommand::SetMixing {
+            mode,
+            pickup,
+            dilution,
+            persistence,
+            extension,
+        } => {
+            out.u16(opcode::SET_MIXING);
+            out.u32(*mode);
+            out.f32(*pickup);
+            out.f32(*dilution);
+            out.f32(*persistence);
+            out.f32(*extension);
+        }
+        EngineCommand::SetWatercolor {
+            enabled,
+            edge,
+            granulation,
+            bleed,
+        } => {
+            out.u16(opcode::SET_WATERCOLOR);
+            out.bool(*enabled);
+            out.f32(*edge);
+            out.f32(*granulation);
+            out.f32(*bleed);
+        }
+        EngineCommand::SetDualBrush {
+            enabled,
+            blend,
+            shape,
+            scale,
+            spacing,
+            scatter,
+            count,
+            size_jitter,
+        } => {
+            out.u16(opcode::SET_DUAL_BRUSH);
+            out.bool(*enabled);
+            out.u32(*blend);
+            out.u32(*shape);
+            out.f32(*scale);
+            out.f32(*spacing);
+            out.f32(*scatter);
+            out.u32(*count);
+            out.f32(*size_jitter);
+        }
+        EngineCommand::SetDualBrushMask {
+            width,
+            height,
+            mask,
+        } => {
+            out.u16(opcode::SET_DUAL_BRUSH_MASK);
+            out.u32(*width);
+            out.u32(*height);
+            out.u8_vec(mask);
+        }
+        EngineCommand::SetColorDynamics {
+            hue_jitter,
+            saturation_jitter,
+            value_jitter,
+            opacity_jitter,
+            stroke_hue_jitter,
+            stroke_saturation_jitter,
+            stroke_value_jitter,
+            stroke_opacity_jitter,
+            background_argb,
+            background_source,
+            background_amount,
+        } => {
+            out.u16(opcode::SET_COLOR_DYNAMICS);
+            out.f32(*hue_jitter);
+            out.f32(*saturation_jitter);
+            out.f32(*value_jitter);
+            out.f32(*opacity_jitter);
+            out.f32(*stroke_hue_jitter);
+            out.f32(*stroke_saturation_jitter);
+            out.f32(*stroke_value_jitter);
+            out.f32(*stroke_opacity_jitter);
+            out.u32(*background_argb);
+            out.u32(*background_source);
+            out.f32(*background_amount);
+        }
+        EngineCommand::BeginSpray => out.u16(opcode::BEGIN_SPRAY),
+        EngineCommand::DrawSpray {
+            points,
+            color_argb,
+            brush_shape,
+            erase,
+            antialias_level,
+            softness,
+            accumulate,
+        } => {
+            out.u16(opcode::DRAW_SPRAY);
+            out.u32(points.len() as u32);
+            for p in points {
+                out.f32(p.x);
+                out.f32(p.y);
+                out.f32(p.radius);
+                out.f32(p.alpha);
+            }
+            out.u32(*color_argb);
+            out.u32(*brush_shape);
+            out.bool(*erase);
+            out.u32(*antialias_level);
+            out.f32(*softness);
+            out.bool(*accumulate);
+        }
+        EngineCommand::EndSpray => out.u16(opcode::END_SPRAY),
+        EngineCommand::ApplyFilter {
+            layer_index,
+            filter_type,
+            param0,
+            param1,
+            param2,
+            param3,
+            ..
+        } => {
+            out.u16(opcode::APPLY_FILTER);
+            out.u32(*layer_index);
+            out.u32(*filter_type);
+            out.f32(*param0);
+            out.f32(*param1);
+            out.f32(*param2);
+            out.f32(*param3);
+        }
+        EngineCommand::ApplyAntialias {
+            layer_index, level, ..
+        } => {
+            out.u16(opcode::APPLY_ANTIALIAS);
+            out.u32(*layer_index);
+            out.u32(*level);
+        }
+        EngineCommand::BucketFill {
+            layer_index,
+            start_x,
+            start_y,
+            color_argb,
+            contiguous,
+            sample_all_layers,
+            tolerance,
+            fill_gap,
+            antialias_level,
+            swallow_colors,
+            selection_mask,
+            ..
+        } => {
+            out.u16(opcode::BUCKET_FILL);
+            out.u32(*layer_index);
+            out.i32(*start_x);
+            out.i32(*start_y);
+            out.u32(*color_argb);
+            out.bool(*contiguous);
+            out.bool(*sample_all_layers);
+            out.u8(*tolerance);
+            out.u8(*fill_gap);
+            out.u8(*antialias_level);
+            out.u32(swallow_colors.len() as u32);
+            out.u32_slice(swallow_colors);
+            out.opt_u8_vec(selection_mask.as_deref());
+        }
+        EngineCommand::MagicWandMask {
+            layer_index,
+            start_x,
+            start_y,
+            sample_all_layers,
+            tolerance,
+            selection_mask,
+            ..
+        } => {
+            out.u16(opcode::MAGIC_WAND_MASK);
+            out.u32(*layer_index);
+            out.i32(*start_x);
+            out.i32(*start_y);
+            out.bool(*sample_all_layers);
+            out.u8(*tolerance);
+            out.opt_u8_vec(selection_mask.as_deref());
+        }
+        EngineCommand::ReadLayer { layer_index, .. } => {
+            out.u16(opcode::READ_LAYER);
+            out.u32(*layer_index);
+        }
+        EngineCommand::ReadLayerPreview {
+            layer_index,
+            width,
+            height,
+            ..
+        } => {
+            out.u16(opcode::READ_LAYER_PREVIEW);
+            out.u32(*layer_index);
+            out.u32(*width);
+            out.u32(*height);
+        }
+        EngineCommand::ReadPresent { .. } => out.u16(opcode::READ_PRESENT),
+        EngineCommand::ReadProject { .. } => out.u16(opcode::READ_PROJECT),
+        EngineCommand::WriteLayer {
+            layer_index,
+            pixels,
+            record_undo,
+            ..
+        } => {
+            out.u16(opcode::WRITE_LAYER);
+            out.u32(*layer_index);
+            out.bool(*record_undo);
+            out.u32(pixels.len() as u32);
+            out.u32_slice(pixels);
+        }
+        EngineCommand::TranslateLayer {
+            layer_index,
+            delta_x,
+            delta_y,
+            ..
+        } => {
+            out.u16(opcode::TRANSLATE_LAYER);
+            out.u32(*layer_index);
+            out.i32(*delta_x);
+            out.i32(*delta_y);
+        }
+        EngineCommand::SetLayerTransformPreview {
+            layer_index,
+            matrix,
+            enabled,
+            bilinear,
+        } => {
+            out.u16(opcode::SET_LAYER_TRANSFORM_PREVIEW);
+            out.u32(*layer_index);
+            out.matrix(matrix);
+            out.bool(*enabled);
+            out.bool(*bilinear);
+        }
+        EngineCommand::ApplyLayerTransform {
+            layer_index,
+            matrix,
+            bilinear,
+            ..
+        } => {
+            out.u16(opcode::APPLY_LAYER_TRANSFORM);
+            out.u32(*layer_index);
+            out.matrix(matrix);
+            out.bool(*bilinear);
+        }
+        EngineCommand::GetLayerBounds { layer_index, .. } => {
+            out.u16(opcode::GET_LAYER_BOUNDS);
+            out.u32(*layer_index);
+        }
+        EngineCommand::SetSelectionMask { selection_mask } => {
+            out.u16(opcode::SET_SELECTION_MASK);
+            out.opt_u8_vec(selection_mask.as_deref());
+        }
+        EngineCommand::Undo => out.u16(opcode::UNDO),
+        EngineCommand::Redo => out.u16(opcode::REDO),
+        EngineCommand::ClearUndoHistory => out.u16(opcode::CLEAR_UNDO_HISTORY),
+        EngineCommand::SetUndoLimits {
+            max_steps,
+            budget_bytes,
+        } => {
+            out.u16(opcode::SET_UNDO_LIMITS);
+            out.u32(*max_steps);
+            out.u64(*budget_bytes);
+        }
+        EngineCommand::BeginUndoTransaction => out.u16(opcode::BEGIN_UNDO_TRANSACTION),
+        EngineCommand::CommitUndoTransaction => out.u16(opcode::COMMIT_UNDO_TRANSACTION),
+        EngineCommand::CancelUndoTransaction => out.u16(opcode::CANCEL_UNDO_TRANSACTION),
+        EngineCommand::SetHistoryCheckpoint { name } => {
+            out.u16(opcode::SET_HISTORY_CHECKPOINT);
+            out.u8_vec(name.as_bytes());
+        }
+        EngineCommand::PlanHistoryRoute { node_id, .. } => {
+            out.u16(opcode::PLAN_HISTORY_ROUTE);
+            out.u64(*node_id);
+        }
+        EngineCommand::EnterHistoryBranch { node_id } => {
+            out.u16(opcode::ENTER_HISTORY_BRANCH);
+            out.u64(*node_id);
+        }
+        EngineCommand::ReadHistory { .. } => out.u16(opcode::READ_HISTORY),
+        EngineCommand::Stop => out.u16(opcode::STOP),
+    }
+}
+
+/// Rebuilds a recorded command. Replies go to channels whose receivers are
+/// already dropped, so the handlers' `reply.send` calls are harmless no-ops.
+fn decode_command(input: &mut ByteReader) -> Result<EngineCommand, String> {
+    let op = input.u16()?;
+    let cmd = match op {
+        opcode::ATTACH_PRESENT_TEXTURE => EngineCommand::AttachPresentTexture {
+            mtl_texture_ptr: 0,
+            width: input.u32()?,
+            height: input.u32()?,
+            bytes_per_row: input.u32()?,
+        },
+        opcode::ATTACH_PRESENT_DXGI => {
+            let width = input.u32()?;
+            let height = input.u32()?;
+            #[cfg(target_os = "windows")]
+            {
+                EngineCommand::AttachPresentDxgi {
+                    width,
+                    height,
+                    reply: detached_reply(),
+                }
+            }
+            #[cfg(not(target_os = "windows"))]
+            {
+                EngineCommand::AttachPresentTexture {
+                    mtl_texture_ptr: 0,
+                    width,
+                    height,
+                    bytes_per_row: width.saturating_mul(4),
+                }
+            }
+        }
+        opcode::RESET_CANVAS => EngineCommand::ResetCanvas {
+            background_color_argb: input.u32()?,
+        },
+        opcode::RESET_CANVAS_WITH_LAYERS => EngineCommand::ResetCanvasWithLayers {
+            layer_count: input.u32()?,
+            background_color_argb: input.u32()?,
+        },
+        opcode::RESIZE_CANVAS => EngineCommand::ResizeCanvas {
+            width: input.u32()?,
+            height: input.u32()?,
+            layer_count: input.u32()?,
+            background_color_argb: input.u32()?,
+            reply: detached_reply(),
+        },
+        opcode::FILL_LAYER => EngineCommand::FillLayer {
+            layer_index: input.u32()?,
+            color_argb: input.u32()?,
+        },
+        opcode::CLEAR_LAYER => EngineCommand::ClearLayer {
+            layer_index: input.u32()?,
+        },
+        opcode::SET_ACTIVE_LAYER => EngineCommand::SetActiveLayer {
+            layer_index: input.u32()?,
+        },
+        opcode::SET_LAYER_OPACITY => EngineCommand::SetLayerOpacity {
+            layer_index: input.u32()?,
+            opacity: input.f32()?,
+        },
+        opcode::SET_LAYER_VISIBLE => EngineCommand::SetLayerVisible {
+            layer_index: input.u32()?,
+            visible: input.bool()?,
+        },
+        opcode::SET_LAYER_CLIPPING_MASK => EngineCommand::SetLayerClippingMask {
+            layer_index: input.u32()?,
+            clipping_mask: input.bool()?,
+        },
+        opcode::SET_LAYER_ALPHA_LOCK => EngineCommand::SetLayerAlphaLock {
+            layer_index: input.u32()?,
+            locked: input.bool()?,
+        },
+        opcode::SET_LAYER_BLEND_MODE => EngineCommand::SetLayerBlendMode {
+            layer_index: input.u32()?,
+            blend_mode_index: input.u32()?,
+        },
+        opcode::REORDER_LAYER => EngineCommand::ReorderLayer {
+            from_index: input.u32()?,
+            to_index: input.u32()?,
+        },
+        opcode::INSERT_LAYER => EngineCommand::InsertLayer {
+            layer_index: input.u32()?,
+        },
+        opcode::REMOVE_LAYER => EngineCommand::RemoveLayer {
+            layer_index: input.u32()?,
+        },
+        opcode::SET_VIEW_FLAGS => EngineCommand::SetViewFlags {
+            view_flags: input.u32()?,
+        },
+        opcode::SET_BRUSH => EngineCommand::SetBrush {
+            color_argb: input.u32()?,
+            base_radius: input.f32()?,
+            use_pressure: input.bool()?,
+            erase: input.bool()?,
+            antialias_level: input.u32()?,
+            brush_shape: input.u32()?,
+            random_rotation: input.bool()?,
+            smooth_rotation: input.bool()?,
+            rotation_seed: input.u32()?,
+            spacing: input.f32()?,
+            hardness: input.f32()?,
+            flow: input.f32()?,
+            scatter: input.f32()?,
+            rotation_jitter: input.f32()?,
+            snap_to_pixel: input.bool()?,
+            screentone_enabled: input.bool()?,
+            screentone_spacing: input.f32()?,
+            screentone_dot_size: input.f32()?,
+            screentone_rotation: input.f32()?,
+            screentone_softness: input.f32()?,
+            screentone_shape: input.u32()?,
+            hollow_enabled: input.bool()?,
+            hollow_ratio: input.f32()?,
+            hollow_erase_occluded: input.bool()?,
+            streamline_strength: input.f32()?,
+            smoothing_mode: input.u32()?,
+            stabilizer_strength: input.f32()?,
+        },
+        opcode::SET_BRUSH_MASK => EngineCommand::SetBrushMask {
+            width: input.u32()?,
+            height: input.u32()?,
+            mask: input.u8_vec()?,
+        },
+        opcode::CLEAR_BRUSH_MASK => EngineCommand::ClearBrushMask,
+        opcode::SET_PEN_DYNAMICS => EngineCommand::SetPenDynamics {
+            tilt_size: input.f32()?,
+            tilt_opacity: input.f32()?,
+            azimuth_angle: input.bool()?,
+            barrel_angle: input.bool()?,
+        },
+        opcode::SET_PRESSURE_CURVE => {
+            let target = input.u32()?;
+            let len = input.u32()? as usize;
+            let mut points = Vec::with_capacity(len.min(input.remaining() / 8));
+            for _ in 0..len {
+                points.push((input.f32()?, input.f32()?));
+            }
+            EngineCommand::SetPressureCurve { target, points }
+        }
+        opcode::SET_SIMULATED_PRESSURE => EngineCommand::SetSimulatedPressure {
+            profile: input.u32()?,
+            tapered_caps: input.bool()?,
+        },
+        opcode::SET_SPRING_STABILIZER => EngineCommand::SetSpringStabilizer {
+            mass: input.f32()?,
+            stiffness: input.f32()?,
+            damping: input.f32()?,
+        },
+        opcode::SET_ASSIST => {
+            let kind = input.u32()?;
+            let len = input.u32()? as usize;
+            let mut params = Vec::with_capacity(len.min(input.remaining() / 4));
+            for _ in 0..len {
+                params.push(input.f32()?);
+            }
+            EngineCommand::SetAssist {
+                kind,
+                params,
+                show_overlay: input.bool()?,
+            }
+        }
+        opcode::SET_SYMMETRY => EngineCommand::SetSymmetry {
+            mode: input.u32()?,
+            center_x: input.f32()?,
+            center_y: input.f32()?,
+            folds: input.u32()?,
+        },
+        opcode::SET_GRAIN_TEXTURE => EngineCommand::SetGrainTexture {
+            width: input.u32()?,
+            height: input.u32()?,
+            pixels: input.u8_vec()?,
+        },
+        opcode::SET_GRAIN => EngineCommand::SetGrain {
+            enabled: input.bool()?,
+            scale: input.f32()?,
+            offset_x: input.f32()?,
+            offset_y: input.f32()?,
+            depth: input.f32()?,
+            invert: input.bool()?,
+            blend: input.u32()?,
+        },
+        opcode::SET_MIXING => EngineCommand::SetMixing {
+            mode: input.u32()?,
+            pickup: input.f32()?,
+            dilution: input.f32()?,
+            persistence: input.f32()?,
+            extension: input.f32()?,
+        },
+        opcode::SET_WATERCOLOR => EngineCommand::SetWatercolor {
+            enabled: input.bool()?,
+            edge: input.f32()?,
+            granulation: input.f32()?,
+            bleed: input.f32()?,
+        },
+        opcode::SET_DUAL_BRUSH => EngineCommand::SetDualBrush {
+            enabled: input.bool()?,
+            blend: input.u32()?,
+            shape: input.u32()?,
+            scale: input.f32()?,
+            spacing: input.f32()?,
+            scatter: input.f32()?,
+            count: input.u32()?,
+            size_jitter: input.f32()?,
+        },
+        opcode::SET_DUAL_BRUSH_MASK => EngineCommand::SetDualBrushMask {
+            width: input.u32()?,
+            height: input.u32()?,
+            mask: input.u8_vec()?,
+        },
+        opcode::SET_COLOR_DYNAMICS => EngineCommand::SetColorDynamics {
+            hue_jitter: input.f32()?,
+            saturation_jitter: input.f32()?,
+            value_jitter: input.f32()?,
+            opacity_jitter: input.f32()?,
+            stroke_hue_jitter: input.f32()?,
+            stroke_saturation_jitter: input.f32()?,
+            stroke_value_jitter: input.f32()?,
+            stroke_opacity_jitter: input.f32()?,
+            background_argb: input.u32()?,
+            background_source: input.u32()?,
+            background_amount: input.f32()?,
+        },
+        opcode::BEGIN_SPRAY => EngineCommand::BeginSpray,
+        opcode::DRAW_SPRAY => {
+            let count = input.u32()? as usize;
+            let mut points = Vec::with_capacity(count.min(input.remaining() / 16));
+            for _ in 0..count {
+                points.push(SprayPoint {
+                    x: input.f32()?,
+                    y: input.f32()?,
+                    radius: input.f32()?,
+                    alpha: input.f32()?,
+                });
+            }
+            EngineCommand::DrawSpray {
+                points,
+                color_argb: input.u32()?,
+                brush_shape: input.u32()?,
+                erase: input.bool()?,
+                antialias_level: input.u32()?,
+                softness: input.f32()?,
+                accumulate: input.bool()?,
+            }
+        }
+        opcode::END_SPRAY => EngineCommand::EndSpray,
+        opcode::APPLY_FILTER => EngineCommand::ApplyFilter {
+            layer_index: input.u32()?,
+            filter_type: input.u32()?,
+            param0: input.f32()?,
+            param1: input.f32()?,
+            param2: input.f32()?,
+            param3: input.f32()?,
+            reply: detached_reply(),
+        },
+        opcode::APPLY_ANTIALIAS => EngineCommand::ApplyAntialias {
+            layer_index: input.u32()?,
+            level: input.u32()?,
+            reply: detached_reply(),
+        },
+        opcode::BUCKET_FILL => EngineCommand::BucketFill {
+            layer_index: input.u32()?,
+            start_x: input.i32()?,
+            start_y: input.i32()?,
+            color_argb: input.u32()?,
+            contiguous: input.bool()?,
+            sample_all_layers: input.bool()?,
+            tolerance: input.u8()?,
+            fill_gap: input.u8()?,
+            antialias_level: input.u8()?,
+            swallow_colors: input.u32_vec()?,
+            selection_mask: input.opt_u8_vec()?,
+            reply: detached_reply(),
+        },
+        opcode::MAGIC_WAND_MASK => EngineCommand::MagicWandMask {
+            layer_index: input.u32()?,
+            start_x: input.i32()?,
+            start_y: input.i32()?,
+            sample_all_layers: input.bool()?,
+            tolerance: input.u8()?,
+            selection_mask: input.opt_u8_vec()?,
+            reply: detached_reply(),
+        },
+        opcode::READ_LAYER => EngineCommand::ReadLayer {
+            layer_index: input.u32()?,
+            reply: detached_reply(),
+        },
+        opcode::READ_LAYER_PREVIEW => EngineCommand::ReadLayerPreview {
+            layer_index: input.u32()?,
+            width: input.u32()?,
+            height: input.u32()?,
+            reply: detached_reply(),
+        },
+        opcode::READ_PRESENT => EngineCommand::ReadPresent {
+            reply: detached_reply(),
+        },
+        opcode::READ_PROJECT => EngineCommand::ReadProject {
+            reply: detached_reply(),
+        },
+        opcode::WRITE_LAYER => EngineCommand::WriteLayer {
+            layer_index: input.u32()?,
+            record_undo: input.bool()?,
+            pixels: input.u32_vec()?,
+            reply: detached_reply(),
+        },
+        opcode::TRANSLATE_LAYER => EngineCommand::TranslateLayer {
+            layer_index: input.u32()?,
+            delta_x: input.i32()?,
+            delta_y: input.i32()?,
+            reply: detached_reply(),
+        },
+        opcode::SET_LAYER_TRANSFORM_PREVIEW => EngineCommand::SetLayerTransformPreview {
+            layer_index: input.u32()?,
+            matrix: input.matrix()?,
+            enabled: input.bool()?,
+            bilinear: input.bool()?,
+        },
+        opcode::APPLY_LAYER_TRANSFORM => EngineCommand::ApplyLayerTransform {
+            layer_index: input.u32()?,
+            matrix: input.matrix()?,
+            bilinear: input.bool()?,
+            reply: detached_reply(),
+        },
+        opcode::GET_LAYER_BOUNDS => EngineCommand::GetLayerBounds {
+            layer_index: input.u32()?,
+            reply: detached_reply(),
+        },
+        opcode::SET_SELECTION_MASK => EngineCommand::SetSelectionMask {
+            selection_mask: input.opt_u8_vec()?,
+        },
+        opcode::UNDO => EngineCommand::Undo,
+        opcode::REDO => EngineCommand::Redo,
+        opcode::CLEAR_UNDO_HISTORY => EngineCommand::ClearUndoHistory,
+        opcode::SET_UNDO_LIMITS => EngineCommand::SetUndoLimits {
+            max_steps: input.u32()?,
+            budget_bytes: input.u64()?,
+        },
+        opcode::BEGIN_UNDO_TRANSACTION => EngineCommand::BeginUndoTransaction,
+        opcode::COMMIT_UNDO_TRANSACTION => EngineCommand::CommitUndoTransaction,
+        opcode::CANCEL_UNDO_TRANSACTION => EngineCommand::CancelUndoTransaction,
+        opcode::SET_HISTORY_CHECKPOINT => EngineCommand::SetHistoryCheckpoint {
+            name: String::from_utf8(input.u8_vec()?)
+                .map_err(|_| "journal: checkpoint name is not UTF-8".to_string())?,
+        },
+        opcode::PLAN_HISTORY_ROUTE => EngineCommand::PlanHistoryRoute {
+            node_id: input.u64()?,
+            reply: detached_reply(),
+        },
+        opcode::ENTER_HISTORY_BRANCH => EngineCommand::EnterHistoryBranch {
+            node_id: input.u64()?,
+        },
+        opcode::READ_HISTORY => EngineCommand::ReadHistory {
+            reply: detached_reply(),
+        },
+        opcode::STOP => EngineCommand::Stop,
+        opcode::CREATE_LAYER_GROUP => EngineCommand::CreateLayerGroup {
+            parent_group_id: input.u32()?,
+            reply: detached_reply(),
+        },
+        opcode::DELETE_LAYER_GROUP => EngineCommand::DeleteLayerGroup {
+            group_id: input.u32()?,
+        },
+        opcode::SET_LAYER_GROUP_PARENT => EngineCommand::SetLayerGroupParent {
+            group_id: input.u32()?,
+            parent_group_id: input.u32()?,
+        },
+        opcode::MOVE_LAYER_TO_GROUP => EngineCommand::MoveLayerToGroup {
+            layer_index: input.u32()?,
+            group_id: input.u32()?,
+        },
+        opcode::SET_LAYER_GROUP_OPACITY => EngineCommand::SetLayerGroupOpacity {
+            group_id: input.u32()?,
+            opacity: input.f32()?,
+        },
+        opcode::SET_LAYER_GROUP_VISIBLE => EngineCommand::SetLayerGroupVisible {
+            group_id: input.u32()?,
+            visible: input.bool()?,
+        },
+        opcode::SET_LAYER_GROUP_BLEND_MODE => EngineCommand::SetLayerGroupBlendMode {
+            group_id: input.u32()?,
+            blend_mode_index: input.u32()?,
+        },
+        opcode::SET_LAYER_GROUP_PASS_THROUGH => EngineCommand::SetLayerGroupPassThrough {
+            group_id: input.u32()?,
+            pass_through: input.bool()?,
+        },
+        opcode::SET_LAYER_GROUP_COLLAPSED => EngineCommand::SetLayerGroupCollapsed {
+            group_id: input.u32()?,
+            collapsed: input.bool()?,
+        },
+        opcode::GET_LAYER_GROUPS => EngineCommand::GetLayerGroups {
+            reply: detached_reply(),
+        },
+        opcode::CREATE_LAYER_MASK => EngineCommand::CreateLayerMask {
+            layer_index: input.u32()?,
+        },
+        opcode::DELETE_LAYER_MASK => EngineCommand::DeleteLayerMask {
+            layer_index: input.u32()?,
+        },
+        opcode::SET_LAYER_MASK_ENABLED => EngineCommand::SetLayerMaskEnabled {
+            layer_index: input.u32()?,
+            enabled: input.bool()?,
+        },
+        opcode::SET_LAYER_MASK_EDITING => EngineCommand::SetLayerMaskEditing {
+            editing: input.bool()?,
+        },
+        opcode::INVERT_LAYER_MASK => EngineCommand::InvertLayerMask {
+            layer_index: input.u32()?,
+        },
+        opcode::APPLY_LAYER_MASK => EngineCommand::ApplyLayerMask {
+            layer_index: input.u32()?,
+        },
+        opcode::READ_LAYER_MASK => EngineCommand::ReadLayerMask {
+            layer_index: input.u32()?,
+            reply: detached_reply(),
+        },
+        opcode::WRITE_LAYER_MASK => EngineCommand::WriteLayerMask {
+            layer_index: input.u32()?,
+            coverage: input.u8_vec()?,
+            reply: detached_reply(),
+        },
+        opcode::SET_LAYER_ADJUSTMENT => EngineCommand::SetLayerAdjustment {
+            layer_index: input.u32()?,
+            filter_type: input.u32()?,
+            param0: input.f32()?,
+            param1: input.f32()?,
+            param2: input.f32()?,
+            param3: input.f32()?,
+        },
+        opcode::CLEAR_LAYER_ADJUSTMENT => EngineCommand::ClearLayerAdjustment {
+            layer_index: input.u32()?,
+        },
+        other => return Err(format!("journal: unknown command opcode {other}")),
+    };
+    Ok(cmd)
+}
+
+fn detached_reply<T>() -> mpsc::Sender<T> {
+    mpsc::channel().0
+}
+
+#[derive(Default)]
+struct ByteWriter {
+    buf: Vec<u8>,
+}
+
+impl ByteWriter {
+    fn bytes(&mut self, value: &[u8]) {
+        self.buf.extend_from_slice(value);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.buf.push(value as u8);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32_slice(&mut self, values: &[u32]) {
+        self.buf.reserve(values.len() * 4);
+        for value in values {
+            self.u32(*value);
+        }
+    }
+
+    fn u8_vec(&mut self, values: &[u8]) {
+        self.u32(values.len() as u32);
+        self.bytes(values);
+    }
+
+    fn opt_u8_vec(&mut self, values: Option<&[u8]>) {
+        match values {
+            Some(values) => {
+                self.bool(true);
+                self.u8_vec(values);
+            }
+            None => self.bool(false),
+        }
+    }
+
+    fn matrix(&mut self, matrix: &[f32; 16]) {
+        for value in matrix {
+            self.f32(*value);
+        }
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.offset
+    }
+
+    fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.remaining() < len {
+            return Err("journal: unexpected end of data".to_string());
+        }
+        let slice = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn u32_vec(&mut self) -> Result<Vec<u32>, String> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len.saturating_mul(4))?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect())
+    }
+
+    fn u8_vec(&mut self) -> Result<Vec<u8>, String> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn opt_u8_vec(&mut self) -> Result<Option<Vec<u8>>, String> {
+        if self.bool()? {
+            Ok(Some(self.u8_vec()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn matrix(&mut self) -> Result<[f32; 16], String> {
+        let mut out = [0.0f32; 16];
+        for value in out.iter_mut() {
+            *value = self.f32()?;
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas_engine::engine::device_context;
+
+    fn point(x: f32, y: f32, flags: u32) -> EnginePoint {
+        EnginePoint {
+            x,
+            y,
+            pressure: 1.0,
+            _pad0: 0.0,
+            timestamp_us: 0,
+            flags,
+            pointer_id: 7,
+            tilt: 0.0,
+            azimuth: 0.0,
+            rotation: 0.0,
+            device: 0,
+        }
+    }
+
+    #[test]
+    fn recorded_session_replays_into_same_layers() {
+        let path = std::env::temp_dir().join(format!(
+            "misa-rin-journal-test-{}.mrj",
+            std::process::id()
+        ));
+        let mut journal = EngineJournal::create(&path, JOURNAL_BACKEND_CPU, 32, 32).unwrap();
+        journal.record_command(&EngineCommand::ResetCanvasWithLayers {
+            layer_count: 2,
+            background_color_argb: 0xFFFFFFFF,
+        });
+        journal.record_command(&EngineCommand::FillLayer {
+            layer_index: 1,
+            color_argb: 0xFFFF0000,
+        });
+        journal.record_command(&EngineCommand::ReorderLayer {
+            from_index: 1,
+            to_index: 0,
+        });
+        journal.record_command(&EngineCommand::SetActiveLayer { layer_index: 0 });
+        journal.record_command(&EngineCommand::ReadLayer {
+            layer_index: 0,
+            reply: detached_reply(),
+        });
+        let mut tilted = point(28.0, 16.0, 4);
+        tilted.tilt = 0.5;
+        tilted.azimuth = -1.25;
+        journal.record_input(3, &[point(4.0, 16.0, 1), point(16.0, 16.0, 2), tilted]);
+        drop(journal);
+
+        let log = read_journal(&path).unwrap();
+        assert_eq!((log.width, log.height), (32, 32));
+        assert_eq!(log.records.len(), 6);
+        match &log.records[5].entry {
+            JournalEntry::Input {
+                backlog_points,
+                points,
+            } => {
+                assert_eq!(*backlog_points, 3);
+                assert_eq!(points.len(), 3);
+                assert_eq!(points[2].flags, 4);
+                assert_eq!(points[2].pointer_id, 7);
+                assert_eq!((points[2].tilt, points[2].azimuth), (0.5, -1.25));
+            }
+            JournalEntry::Command(_) => panic!("expected an input record"),
+        }
+
+        let canvas = replay_journal(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(canvas.layers.len(), 2);
+        // The red layer moved to the bottom and received the default white stroke.
+        let bottom = &canvas.layers[0].pixels;
+        assert_eq!(bottom[0], 0xFFFF0000);
+        assert_ne!(bottom[16 * 32 + 16], 0xFFFF0000);
+        assert!(canvas.layers[1].pixels.iter().all(|&px| px == 0xFFFFFFFF));
+    }
+
+    #[test]
+    fn hollow_streamline_session_replays_its_core_on_its_backend() {
+        let replay = |backend: u32, hollow: bool| {
+            let path = std::env::temp_dir().join(format!(
+                "misa-rin-journal-hollow-{backend}-{hollow}-{}.mrj",
+                std::process::id()
+            ));
+            let mut journal = EngineJournal::create(&path, backend, 32, 32).unwrap();
+            journal.record_command(&EngineCommand::ResetCanvas {
+                background_color_argb: 0xFFFFFFFF,
+            });
+            journal.record_command(&EngineCommand::SetBrush {
+                color_argb: 0xFF000000,
+                base_radius: 4.0,
+                use_pressure: false,
+                erase: false,
+                antialias_level: 1,
+                brush_shape: 0,
+                random_rotation: false,
+                smooth_rotation: false,
+                rotation_seed: 0,
+                spacing: 0.15,
+                hardness: 1.0,
+                flow: 1.0,
+                scatter: 0.0,
+                rotation_jitter: 0.0,
+                snap_to_pixel: false,
+                screentone_enabled: false,
+                screentone_spacing: 10.0,
+                screentone_dot_size: 0.6,
+                screentone_rotation: 45.0,
+                screentone_softness: 0.0,
+                screentone_shape: 0,
+                hollow_enabled: hollow,
+                hollow_ratio: 0.6,
+                hollow_erase_occluded: hollow,
+                streamline_strength: 0.5,
+                smoothing_mode: 0,
+                stabilizer_strength: 0.0,
+            });
+            journal.record_input(
+                3,
+                &[point(4.0, 16.0, 1), point(16.0, 16.0, 2), point(28.0, 16.0, 4)],
+            );
+            drop(journal);
+            let canvas = replay_journal(&path).unwrap();
+            let _ = std::fs::remove_file(&path);
+            canvas.layers[0].pixels.clone()
+        };
+
+        // The settled stroke erases its core out of the white background.
+        assert_eq!(replay(JOURNAL_BACKEND_CPU, true)[16 * 32 + 16] >> 24, 0);
+        assert_eq!(replay(JOURNAL_BACKEND_CPU, false)[16 * 32 + 16], 0xFF000000);
+        if let Err(err) = device_context() {
+            eprintln!("skipping GPU replay: no GPU adapter ({err})");
+            return;
+        }
+        assert_eq!(replay(JOURNAL_BACKEND_GPU, true)[16 * 32 + 16] >> 24, 0);
+        assert_eq!(replay(JOURNAL_BACKEND_GPU, false)[16 * 32 + 16], 0xFF000000);
+    }
+
+    #[test]
+    fn decodes_older_versions_and_rejects_unknown_backends() {
+        let header = |version: u32, backend: u32| {
+            let mut out = ByteWriter::default();
+            out.bytes(JOURNAL_MAGIC);
+            out.u32(version);
+            out.u32(backend);
+            out.u32(8);
+            out.u32(8);
+            out.buf
+        };
+        for version in [JOURNAL_VERSION_NO_PEN_AXES, 2, JOURNAL_VERSION] {
+            assert_eq!(decode_journal(&header(version, 0)).unwrap().version, version);
+        }
+        assert!(decode_journal(&header(JOURNAL_VERSION + 1, 0)).is_err());
+
+        let path = std::env::temp_dir().join(format!(
+            "misa-rin-journal-backend-{}.mrj",
+            std::process::id()
+        ));
+        std::fs::write(&path, header(JOURNAL_VERSION, 9)).unwrap();
+        let result = replay_journal(&path);
+        let _ = std::fs::remove_file(&path);
+        assert!(result.is_err());
+    }
+}
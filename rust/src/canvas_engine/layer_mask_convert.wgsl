@@ -0,0 +1,38 @@
+// Moves a layer mask between its single-channel slice and the Rgba8 texture
+// strokes and undo write while the mask is checked out.
+
+struct ConvertConfig {
+  slot: u32,
+  _pad0: u32,
+  _pad1: u32,
+  _pad2: u32,
+};
+
+@group(0) @binding(0)
+var mask_tex: texture_2d_array<f32>;
+
+@group(0) @binding(1)
+var<uniform> cfg: ConvertConfig;
+
+@group(0) @binding(2)
+var working_tex: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+  let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+  return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// The mask as the opaque gray mask strokes paint.
+@fragment
+fn expand_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+  let v = textureLoad(mask_tex, vec2<i32>(pos.xy), i32(cfg.slot), 0).x;
+  return vec4<f32>(v, v, v, 1.0);
+}
+
+// Gray weighted by alpha, as `mask_texel_value` reads a working texel.
+@fragment
+fn pack_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+  let texel = textureLoad(working_tex, vec2<i32>(pos.xy), 0);
+  return vec4<f32>(texel.x * texel.w, 0.0, 0.0, 1.0);
+}
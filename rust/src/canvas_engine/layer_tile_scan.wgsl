@@ -0,0 +1,63 @@
+// Finds the tiles of the working layer that differ from the layer's fill
+// colour. One workgroup scans one 256x256 tile; each invocation covers a
+// 16x16 block of it.
+
+struct ScanConfig {
+  fill: u32,
+  tile_count: u32,
+  _pad0: u32,
+  _pad1: u32,
+};
+
+@group(0) @binding(0)
+var working_tex: texture_2d<f32>;
+
+@group(0) @binding(1)
+var<uniform> cfg: ScanConfig;
+
+// Tile coordinates, x in the low and y in the high 16 bits.
+@group(0) @binding(2)
+var<storage, read> tiles: array<u32>;
+
+// Non-zero once a texel of the tile differs from the fill.
+@group(0) @binding(3)
+var<storage, read_write> differs: array<atomic<u32>>;
+
+fn to_u8(x: f32) -> u32 {
+  let v = floor(clamp(x, 0.0, 1.0) * 255.0 + 0.5);
+  return u32(clamp(v, 0.0, 255.0));
+}
+
+fn unpack_u32(v: vec4<f32>) -> u32 {
+  let b = to_u8(v.x);
+  let g = to_u8(v.y);
+  let r = to_u8(v.z);
+  let a = to_u8(v.w);
+  return (a << 24u) | (r << 16u) | (g << 8u) | b;
+}
+
+@compute @workgroup_size(16, 16, 1)
+fn scan_main(
+  @builtin(workgroup_id) group: vec3<u32>,
+  @builtin(local_invocation_id) local: vec3<u32>,
+) {
+  if (group.x >= cfg.tile_count) {
+    return;
+  }
+  let tile = tiles[group.x];
+  let origin = vec2<u32>(tile & 0xFFFFu, tile >> 16u) * 256u + local.xy * 16u;
+  let dims = textureDimensions(working_tex);
+  for (var y = 0u; y < 16u; y = y + 1u) {
+    for (var x = 0u; x < 16u; x = x + 1u) {
+      let coord = origin + vec2<u32>(x, y);
+      if (coord.x >= dims.x || coord.y >= dims.y) {
+        continue;
+      }
+      let texel = unpack_u32(textureLoad(working_tex, vec2<i32>(coord), 0));
+      if (texel != cfg.fill) {
+        atomicOr(&differs[group.x], 1u);
+        return;
+      }
+    }
+  }
+}
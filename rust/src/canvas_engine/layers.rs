@@ -1,69 +1,132 @@
+use std::borrow::Cow;
+use std::sync::mpsc;
+
 use crate::gpu::layer_format::LAYER_TEXTURE_FORMAT;
 
-pub(crate) struct LayerTextures {
-    texture: wgpu::Texture,
-    array_view: wgpu::TextureView,
-    layer_views: Vec<wgpu::TextureView>,
+use super::engine::{remap_layer_index, reorder_vec};
+use super::tiles::LAYER_TILE_SIZE;
+
+/// Page table entry of a tile without an atlas slot; it reads as the fill.
+const NO_TILE: u32 = u32::MAX;
+/// Largest edge of an atlas page, in tiles.
+const MAX_PAGE_TILES: u32 = 8;
+/// Header row of the page table: width, height, tiles_x, page_tiles.
+const PAGE_TABLE_HEADER: u32 = 4;
+const BYTES_PER_TEXEL: u32 = 4;
+
+/// Tiles of one layer: the colour of every tile without a slot, and the
+/// atlas slot of each tile, row-major.
+struct LayerPages {
+    fill: u32,
+    slots: Vec<u32>,
+}
+
+/// Pixels of a layer taken out of a `LayerStore`: its fill and one texture
+/// per tile that held its own pixels.
+pub(crate) struct DetachedLayer {
+    fill: u32,
+    tiles: Vec<(usize, wgpu::Texture)>,
+}
+
+impl DetachedLayer {
+    pub(crate) fn transparent() -> Self {
+        Self {
+            fill: 0,
+            tiles: Vec::new(),
+        }
+    }
+
+    /// VRAM held by the layer's tiles.
+    pub(crate) fn resident_bytes(&self) -> u64 {
+        self.tiles
+            .iter()
+            .map(|(_, texture)| texture_bytes(texture, BYTES_PER_TEXEL))
+            .sum()
+    }
+}
+
+/// Sparse GPU layer storage. Every layer is a grid of 256-pixel tiles; a
+/// tile that differs from the layer's fill colour owns a slot in a shared
+/// atlas, and a page table maps (layer, tile) to its slot. The compositor
+/// and bucket fill read layers through the page table, so empty and
+/// uniform layers cost a page table row instead of a canvas of texels.
+///
+/// Brushes, filters and transforms write a full-canvas working texture
+/// instead. `checkout` loads one layer into it, writers report what they
+/// touched with `mark_dirty`/`mark_rewritten`, and `flush` copies those
+/// tiles back, dropping the ones that returned to the fill colour.
+pub(crate) struct LayerStore {
     width: u32,
     height: u32,
+    tiles_x: u32,
+    tiles_y: u32,
+    page_tiles: u32,
+    layers: Vec<LayerPages>,
+    atlas: wgpu::Texture,
+    atlas_view: wgpu::TextureView,
+    // 0 while `atlas` is a placeholder that holds no slot yet.
+    pages: u32,
+    next_slot: u32,
+    free_slots: Vec<u32>,
+    page_table: wgpu::Texture,
+    page_table_view: wgpu::TextureView,
     capacity: usize,
+    table_dirty: bool,
+    working: wgpu::Texture,
+    working_view: wgpu::TextureView,
+    working_array_view: wgpu::TextureView,
+    // False while `working` is a placeholder.
+    working_ready: bool,
+    checked_out: Option<usize>,
+    dirty: Vec<bool>,
+    rescan: Vec<bool>,
+    scanner: Option<TileScanner>,
+    bindings_changed: bool,
 }
 
-impl LayerTextures {
+impl LayerStore {
+    /// An empty store for a `width` x `height` canvas with page table rows
+    /// for `capacity` layers. Nothing canvas-sized is allocated until a
+    /// layer is checked out or a tile gets a slot.
     pub(crate) fn new(
         device: &wgpu::Device,
         width: u32,
         height: u32,
         capacity: usize,
     ) -> Result<Self, String> {
+        let tiles_x = width.div_ceil(LAYER_TILE_SIZE).max(1);
+        let tiles_y = height.div_ceil(LAYER_TILE_SIZE).max(1);
         let capacity = capacity.max(1);
-        let max_layers = device.limits().max_texture_array_layers as usize;
-        if capacity > max_layers {
-            return Err(format!(
-                "layer capacity {capacity} exceeds device max {max_layers}"
-            ));
-        }
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("misa-rin layer array (Rgba8Unorm)"),
-            size: wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: capacity as u32,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: LAYER_TEXTURE_FORMAT,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::COPY_DST
-                | wgpu::TextureUsages::COPY_SRC
-                | wgpu::TextureUsages::STORAGE_BINDING,
-            view_formats: &[],
-        });
-        let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
-            dimension: Some(wgpu::TextureViewDimension::D2Array),
-            base_array_layer: 0,
-            array_layer_count: Some(capacity as u32),
-            ..Default::default()
-        });
-        let mut layer_views = Vec::with_capacity(capacity);
-        for idx in 0..capacity {
-            let view = texture.create_view(&wgpu::TextureViewDescriptor {
-                dimension: Some(wgpu::TextureViewDimension::D2),
-                base_array_layer: idx as u32,
-                array_layer_count: Some(1),
-                ..Default::default()
-            });
-            layer_views.push(view);
-        }
-
+        let (page_table, page_table_view) =
+            create_page_table(device, tiles_x * tiles_y, capacity)?;
+        let (atlas, atlas_view) = create_atlas(device, 1, 1);
+        let (working, working_view, working_array_view) = create_working(device, 1, 1);
+        let tile_count = (tiles_x * tiles_y) as usize;
         Ok(Self {
-            texture,
-            array_view,
-            layer_views,
             width,
             height,
+            tiles_x,
+            tiles_y,
+            page_tiles: tiles_x.max(tiles_y).min(MAX_PAGE_TILES),
+            layers: Vec::new(),
+            atlas,
+            atlas_view,
+            pages: 0,
+            next_slot: 0,
+            free_slots: Vec::new(),
+            page_table,
+            page_table_view,
             capacity,
+            table_dirty: true,
+            working,
+            working_view,
+            working_array_view,
+            working_ready: false,
+            checked_out: None,
+            dirty: vec![false; tile_count],
+            rescan: vec![false; tile_count],
+            scanner: None,
+            bindings_changed: false,
         })
     }
 
@@ -71,104 +134,962 @@ impl LayerTextures {
         self.capacity
     }
 
-    pub(crate) fn texture(&self) -> &wgpu::Texture {
-        &self.texture
-    }
-
-    pub(crate) fn array_view(&self) -> &wgpu::TextureView {
-        &self.array_view
+    /// VRAM held by the atlas and the working texture.
+    pub(crate) fn resident_bytes(&self) -> u64 {
+        let atlas = if self.pages > 0 {
+            texture_bytes(&self.atlas, BYTES_PER_TEXEL)
+        } else {
+            0
+        };
+        let working = if self.working_ready {
+            texture_bytes(&self.working, BYTES_PER_TEXEL)
+        } else {
+            0
+        };
+        atlas + working
     }
 
-    pub(crate) fn layer_view(&self, index: usize) -> Option<&wgpu::TextureView> {
-        self.layer_views.get(index)
+    pub(crate) fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
     }
 
+    /// Grows the page table to hold `required` layers. Returns the old
+    /// capacity when it grew.
     pub(crate) fn ensure_capacity(
         &mut self,
         device: &wgpu::Device,
-        queue: &wgpu::Queue,
         required: usize,
     ) -> Result<Option<usize>, String> {
         if required <= self.capacity {
             return Ok(None);
         }
-
-        let max_layers = device.limits().max_texture_array_layers as usize;
         let mut next_capacity = self.capacity.max(1);
         while next_capacity < required {
             next_capacity = next_capacity.saturating_mul(2).max(required);
         }
-        if next_capacity > max_layers {
+        let (page_table, page_table_view) =
+            create_page_table(device, self.tiles_x * self.tiles_y, next_capacity)?;
+        let old_capacity = self.capacity;
+        self.page_table = page_table;
+        self.page_table_view = page_table_view;
+        self.capacity = next_capacity;
+        self.table_dirty = true;
+        self.bindings_changed = true;
+        Ok(Some(old_capacity))
+    }
+
+    /// Adds a layer of `fill` on top.
+    pub(crate) fn push_layer(&mut self, fill: u32) {
+        self.layers.push(LayerPages {
+            fill,
+            slots: vec![NO_TILE; (self.tiles_x * self.tiles_y) as usize],
+        });
+        self.table_dirty = true;
+    }
+
+    /// Drops every layer from `len` up.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        if len >= self.layers.len() {
+            return;
+        }
+        for pages in self.layers.drain(len..) {
+            self.free_slots
+                .extend(pages.slots.into_iter().filter(|&slot| slot != NO_TILE));
+        }
+        if self.checked_out.is_some_and(|index| index >= len) {
+            self.checked_out = None;
+        }
+        self.table_dirty = true;
+    }
+
+    /// Makes every pixel of `index` `color` and releases its tiles.
+    pub(crate) fn fill(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        index: usize,
+        color: u32,
+    ) {
+        let Some(pages) = self.layers.get_mut(index) else {
+            return;
+        };
+        pages.fill = color;
+        for slot in pages.slots.iter_mut().filter(|slot| **slot != NO_TILE) {
+            self.free_slots.push(*slot);
+            *slot = NO_TILE;
+        }
+        self.table_dirty = true;
+        if self.checked_out == Some(index) {
+            self.dirty.fill(false);
+            self.rescan.fill(false);
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("misa-rin layer store fill encoder"),
+            });
+            clear_texture(&mut encoder, &self.working_view, color);
+            queue.submit(Some(encoder.finish()));
+        }
+    }
+
+    /// The colour of every pixel of `index`, or `None` once it holds tiles
+    /// of its own.
+    pub(crate) fn uniform(&self, index: usize) -> Option<u32> {
+        let pages = self.layers.get(index)?;
+        let painted = pages.slots.iter().any(|&slot| slot != NO_TILE)
+            || (self.checked_out == Some(index) && self.dirty.contains(&true));
+        (!painted).then_some(pages.fill)
+    }
+
+    /// Pixel rect `(left, top, right, bottom)` covering every tile of
+    /// `index` that may hold visible pixels, clipped to the canvas.
+    pub(crate) fn occupied_rect(&self, index: usize) -> Option<(u32, u32, u32, u32)> {
+        let pages = self.layers.get(index)?;
+        if (pages.fill >> 24) != 0 {
+            return Some((0, 0, self.width, self.height));
+        }
+        let checked_out = self.checked_out == Some(index);
+        let mut rect: Option<(u32, u32, u32, u32)> = None;
+        for (tile, &slot) in pages.slots.iter().enumerate() {
+            if slot == NO_TILE && !(checked_out && self.dirty[tile]) {
+                continue;
+            }
+            let (tx, ty) = (tile as u32 % self.tiles_x, tile as u32 / self.tiles_x);
+            rect = Some(match rect {
+                Some((x0, y0, x1, y1)) => (x0.min(tx), y0.min(ty), x1.max(tx), y1.max(ty)),
+                None => (tx, ty, tx, ty),
+            });
+        }
+        let (tx0, ty0, tx1, ty1) = rect?;
+        Some((
+            tx0 * LAYER_TILE_SIZE,
+            ty0 * LAYER_TILE_SIZE,
+            ((tx1 + 1) * LAYER_TILE_SIZE).min(self.width),
+            ((ty1 + 1) * LAYER_TILE_SIZE).min(self.height),
+        ))
+    }
+
+    /// Loads layer `index` into the working texture and returns the array
+    /// slice to address it by. The previous layer is flushed first.
+    pub(crate) fn checkout(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        index: usize,
+    ) -> Result<u32, String> {
+        if index >= self.layers.len() {
             return Err(format!(
-                "layer capacity {next_capacity} exceeds device max {max_layers}"
+                "layer {index} out of range ({} layers)",
+                self.layers.len()
             ));
         }
+        if self.checked_out == Some(index) {
+            return Ok(0);
+        }
+        self.flush(device, queue)?;
+        if !self.working_ready {
+            let (working, working_view, working_array_view) =
+                create_working(device, self.width, self.height);
+            self.working = working;
+            self.working_view = working_view;
+            self.working_array_view = working_array_view;
+            self.working_ready = true;
+        }
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("misa-rin layer store checkout encoder"),
+        });
+        clear_texture(&mut encoder, &self.working_view, self.layers[index].fill);
+        for (tile, &slot) in self.layers[index].slots.iter().enumerate() {
+            if slot == NO_TILE {
+                continue;
+            }
+            let (x, y, width, height) = self.tile_rect(tile);
+            encoder.copy_texture_to_texture(
+                self.slot_copy(slot),
+                wgpu::ImageCopyTexture {
+                    texture: &self.working,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x, y, z: 0 },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                tile_extent(width, height),
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+        self.checked_out = Some(index);
+        self.dirty.fill(false);
+        self.rescan.fill(false);
+        Ok(0)
+    }
 
-        let old_capacity = self.capacity;
-        let new_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("misa-rin layer array (Rgba8Unorm)"),
-            size: wgpu::Extent3d {
-                width: self.width,
-                height: self.height,
-                depth_or_array_layers: next_capacity as u32,
+    /// The working texture; only the slice `checkout` returned is valid.
+    pub(crate) fn texture(&self) -> &wgpu::Texture {
+        &self.working
+    }
+
+    pub(crate) fn working_view(&self) -> &wgpu::TextureView {
+        &self.working_view
+    }
+
+    pub(crate) fn working_array_view(&self) -> &wgpu::TextureView {
+        &self.working_array_view
+    }
+
+    /// Records that `(left, top, width, height)` of the checked out layer
+    /// `index` was painted.
+    pub(crate) fn mark_dirty(&mut self, index: usize, rect: (i32, i32, i32, i32)) {
+        self.mark_tiles(index, rect, false);
+    }
+
+    /// Records that `(left, top, width, height)` of the checked out layer
+    /// `index` was rewritten, so its tiles may have returned to the fill.
+    pub(crate) fn mark_rewritten(&mut self, index: usize, rect: (i32, i32, i32, i32)) {
+        self.mark_tiles(index, rect, true);
+    }
+
+    /// `mark_rewritten` for the whole canvas.
+    pub(crate) fn mark_layer_rewritten(&mut self, index: usize) {
+        let rect = (0, 0, self.width as i32, self.height as i32);
+        self.mark_tiles(index, rect, true);
+    }
+
+    fn mark_tiles(&mut self, index: usize, rect: (i32, i32, i32, i32), rescan: bool) {
+        if self.checked_out != Some(index) {
+            return;
+        }
+        let (left, top, rect_width, rect_height) = rect;
+        if rect_width <= 0 || rect_height <= 0 {
+            return;
+        }
+        let x0 = (left.max(0) as u32).min(self.width);
+        let y0 = (top.max(0) as u32).min(self.height);
+        let x1 = (left.saturating_add(rect_width).max(0) as u32).min(self.width);
+        let y1 = (top.saturating_add(rect_height).max(0) as u32).min(self.height);
+        if x1 <= x0 || y1 <= y0 {
+            return;
+        }
+        for ty in y0 / LAYER_TILE_SIZE..=(y1 - 1) / LAYER_TILE_SIZE {
+            for tx in x0 / LAYER_TILE_SIZE..=(x1 - 1) / LAYER_TILE_SIZE {
+                let tile = (ty * self.tiles_x + tx) as usize;
+                self.dirty[tile] = true;
+                self.rescan[tile] |= rescan;
+            }
+        }
+    }
+
+    /// Copies the dirty tiles of the checked out layer back to the atlas and
+    /// uploads the page table. Readers of the atlas flush first.
+    pub(crate) fn flush(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<(), String> {
+        if let Some(index) = self.checked_out {
+            self.flush_tiles(device, queue, index)?;
+        }
+        if self.table_dirty {
+            self.upload_page_table(queue);
+            self.table_dirty = false;
+        }
+        Ok(())
+    }
+
+    fn flush_tiles(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        index: usize,
+    ) -> Result<(), String> {
+        let dirty: Vec<usize> = (0..self.dirty.len()).filter(|&tile| self.dirty[tile]).collect();
+        if dirty.is_empty() {
+            return Ok(());
+        }
+        let rescan: Vec<usize> = dirty.iter().copied().filter(|&tile| self.rescan[tile]).collect();
+        let fill = self.layers[index].fill;
+        let mut uniform = vec![false; self.dirty.len()];
+        for (tile, differs) in rescan.iter().zip(self.scan_tiles(device, queue, &rescan, fill)?) {
+            uniform[*tile] = !differs;
+        }
+        let mut copies: Vec<(usize, u32)> = Vec::with_capacity(dirty.len());
+        for &tile in &dirty {
+            let slot = self.layers[index].slots[tile];
+            if uniform[tile] {
+                if slot != NO_TILE {
+                    self.free_slots.push(slot);
+                    self.layers[index].slots[tile] = NO_TILE;
+                    self.table_dirty = true;
+                }
+                continue;
+            }
+            let slot = if slot == NO_TILE {
+                let slot = self.allocate_slot(device, queue)?;
+                self.layers[index].slots[tile] = slot;
+                self.table_dirty = true;
+                slot
+            } else {
+                slot
+            };
+            copies.push((tile, slot));
+        }
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("misa-rin layer store flush encoder"),
+        });
+        for (tile, slot) in copies {
+            let (x, y, width, height) = self.tile_rect(tile);
+            encoder.copy_texture_to_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &self.working,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x, y, z: 0 },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                self.slot_copy(slot),
+                tile_extent(width, height),
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+        self.dirty.fill(false);
+        self.rescan.fill(false);
+        Ok(())
+    }
+
+    /// Flushes and releases the working texture, for a store that is set
+    /// aside, e.g. a canvas kept by undo.
+    pub(crate) fn park(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<(), String> {
+        self.flush(device, queue)?;
+        let (working, working_view, working_array_view) = create_working(device, 1, 1);
+        self.working = working;
+        self.working_view = working_view;
+        self.working_array_view = working_array_view;
+        self.working_ready = false;
+        self.checked_out = None;
+        Ok(())
+    }
+
+    /// Whether the atlas or page table was replaced since the last call, so
+    /// bind groups holding their views must be recreated.
+    pub(crate) fn take_bindings_changed(&mut self) -> bool {
+        std::mem::take(&mut self.bindings_changed)
+    }
+
+    pub(crate) fn atlas_view(&self) -> &wgpu::TextureView {
+        &self.atlas_view
+    }
+
+    pub(crate) fn page_table_view(&self) -> &wgpu::TextureView {
+        &self.page_table_view
+    }
+
+    /// Moves the layer at `from` to `to`, both final positions.
+    pub(crate) fn move_layer(&mut self, from: usize, to: usize) {
+        if from == to || from >= self.layers.len() {
+            return;
+        }
+        reorder_vec(&mut self.layers, from, to);
+        self.checked_out = self
+            .checked_out
+            .map(|index| remap_layer_index(index, from, to));
+        self.table_dirty = true;
+    }
+
+    /// Takes layer `index` out of the store; the layers above move down.
+    pub(crate) fn take(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        index: usize,
+    ) -> Result<DetachedLayer, String> {
+        if index >= self.layers.len() {
+            return Err(format!("layer {index} out of range"));
+        }
+        self.flush(device, queue)?;
+        self.checked_out = match self.checked_out {
+            Some(checked) if checked == index => None,
+            Some(checked) if checked > index => Some(checked - 1),
+            checked => checked,
+        };
+        let pages = self.layers.remove(index);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("misa-rin layer store take encoder"),
+        });
+        let mut tiles = Vec::new();
+        for (tile, &slot) in pages.slots.iter().enumerate() {
+            if slot == NO_TILE {
+                continue;
+            }
+            let (_, _, width, height) = self.tile_rect(tile);
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("misa-rin detached layer tile"),
+                size: tile_extent(width, height),
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: LAYER_TEXTURE_FORMAT,
+                usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            });
+            encoder.copy_texture_to_texture(
+                self.slot_copy(slot),
+                texture.as_image_copy(),
+                tile_extent(width, height),
+            );
+            tiles.push((tile, texture));
+            self.free_slots.push(slot);
+        }
+        queue.submit(Some(encoder.finish()));
+        self.table_dirty = true;
+        Ok(DetachedLayer {
+            fill: pages.fill,
+            tiles,
+        })
+    }
+
+    /// Replaces the pixels of layer `index` with `layer`.
+    pub(crate) fn restore(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        index: usize,
+        layer: DetachedLayer,
+    ) -> Result<(), String> {
+        if index >= self.layers.len() {
+            return Err(format!("layer {index} out of range"));
+        }
+        self.fill(device, queue, index, layer.fill);
+        // Slots are allocated before encoding: growing the atlas replaces it.
+        let mut copies = Vec::with_capacity(layer.tiles.len());
+        for (tile, texture) in &layer.tiles {
+            if *tile >= self.layers[index].slots.len() {
+                continue;
+            }
+            let slot = self.allocate_slot(device, queue)?;
+            self.layers[index].slots[*tile] = slot;
+            copies.push((*tile, slot, texture));
+        }
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("misa-rin layer store restore encoder"),
+        });
+        for (tile, slot, texture) in copies {
+            let (_, _, width, height) = self.tile_rect(tile);
+            encoder.copy_texture_to_texture(
+                texture.as_image_copy(),
+                self.slot_copy(slot),
+                tile_extent(width, height),
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+        // `fill` left nothing dirty, so the working copy can simply go stale.
+        if self.checked_out == Some(index) {
+            self.checked_out = None;
+        }
+        self.table_dirty = true;
+        Ok(())
+    }
+
+    fn tile_rect(&self, tile: usize) -> (u32, u32, u32, u32) {
+        let x = (tile as u32 % self.tiles_x) * LAYER_TILE_SIZE;
+        let y = (tile as u32 / self.tiles_x) * LAYER_TILE_SIZE;
+        (
+            x,
+            y,
+            LAYER_TILE_SIZE.min(self.width.saturating_sub(x)).max(1),
+            LAYER_TILE_SIZE.min(self.height.saturating_sub(y)).max(1),
+        )
+    }
+
+    fn slot_copy(&self, slot: u32) -> wgpu::ImageCopyTexture<'_> {
+        let per_page = self.page_tiles * self.page_tiles;
+        let local = slot % per_page;
+        wgpu::ImageCopyTexture {
+            texture: &self.atlas,
+            mip_level: 0,
+            origin: wgpu::Origin3d {
+                x: (local % self.page_tiles) * LAYER_TILE_SIZE,
+                y: (local / self.page_tiles) * LAYER_TILE_SIZE,
+                z: slot / per_page,
             },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: LAYER_TEXTURE_FORMAT,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::COPY_DST
-                | wgpu::TextureUsages::COPY_SRC
-                | wgpu::TextureUsages::STORAGE_BINDING,
-            view_formats: &[],
+            aspect: wgpu::TextureAspect::All,
+        }
+    }
+
+    fn allocate_slot(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<u32, String> {
+        if let Some(slot) = self.free_slots.pop() {
+            return Ok(slot);
+        }
+        if self.next_slot >= self.pages * self.page_tiles * self.page_tiles {
+            self.grow_atlas(device, queue)?;
+        }
+        let slot = self.next_slot;
+        self.next_slot += 1;
+        Ok(slot)
+    }
+
+    fn grow_atlas(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<(), String> {
+        let max_pages = device.limits().max_texture_array_layers;
+        // GL backs single-layer textures with plain 2D ones, which array
+        // views then read as zero, so the atlas starts at two pages.
+        let pages = self.pages.saturating_mul(2).clamp(2, max_pages);
+        if pages <= self.pages {
+            return Err(format!("layer atlas is full ({} pages)", self.pages));
+        }
+        let page_size = self.page_tiles * LAYER_TILE_SIZE;
+        let (atlas, atlas_view) = create_atlas(device, page_size, pages);
+        if self.pages > 0 {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("misa-rin layer atlas grow encoder"),
+            });
+            encoder.copy_texture_to_texture(
+                self.atlas.as_image_copy(),
+                atlas.as_image_copy(),
+                wgpu::Extent3d {
+                    width: page_size,
+                    height: page_size,
+                    depth_or_array_layers: self.pages,
+                },
+            );
+            queue.submit(Some(encoder.finish()));
+        }
+        self.atlas = atlas;
+        self.atlas_view = atlas_view;
+        self.pages = pages;
+        self.bindings_changed = true;
+        Ok(())
+    }
+
+    fn upload_page_table(&self, queue: &wgpu::Queue) {
+        let columns = page_table_columns(self.tiles_x * self.tiles_y);
+        let mut table = vec![0u32; columns as usize * (self.layers.len() + 1)];
+        table[..PAGE_TABLE_HEADER as usize].copy_from_slice(&[
+            self.width,
+            self.height,
+            self.tiles_x,
+            self.page_tiles,
+        ]);
+        for (row, pages) in table[columns as usize..]
+            .chunks_exact_mut(columns as usize)
+            .zip(&self.layers)
+        {
+            row[0] = pages.fill;
+            row[1..=pages.slots.len()].copy_from_slice(&pages.slots);
+        }
+        queue.write_texture(
+            self.page_table.as_image_copy(),
+            bytemuck::cast_slice(&table),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(columns * BYTES_PER_TEXEL),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: columns,
+                height: self.layers.len() as u32 + 1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// For each of `tiles`, whether the working texture differs from `fill`
+    /// anywhere in it.
+    fn scan_tiles(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        tiles: &[usize],
+        fill: u32,
+    ) -> Result<Vec<bool>, String> {
+        if tiles.is_empty() {
+            return Ok(Vec::new());
+        }
+        let coords: Vec<u32> = tiles
+            .iter()
+            .map(|&tile| (tile as u32 % self.tiles_x) | ((tile as u32 / self.tiles_x) << 16))
+            .collect();
+        let scanner = self.scanner.get_or_insert_with(|| TileScanner::new(device));
+        scanner.scan(device, queue, &self.working_view, &coords, fill)
+    }
+}
+
+/// Compute pass behind `LayerStore::flush` that finds rewritten tiles equal
+/// to the layer's fill.
+struct TileScanner {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    config_buffer: wgpu::Buffer,
+}
+
+impl TileScanner {
+    fn new(device: &wgpu::Device) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("misa-rin layer tile scan shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("layer_tile_scan.wgsl"))),
         });
+        let storage = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("misa-rin layer tile scan bgl"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(2, true),
+                storage(3, false),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("misa-rin layer tile scan pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("misa-rin layer tile scan pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "scan_main",
+        });
+        let config_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("misa-rin layer tile scan config"),
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            pipeline,
+            bind_group_layout,
+            config_buffer,
+        }
+    }
 
+    fn scan(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        working_view: &wgpu::TextureView,
+        coords: &[u32],
+        fill: u32,
+    ) -> Result<Vec<bool>, String> {
+        let size = std::mem::size_of_val(coords) as u64;
+        let tiles_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("misa-rin layer tile scan tiles"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let differs_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("misa-rin layer tile scan results"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("misa-rin layer tile scan readback"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&tiles_buffer, 0, bytemuck::cast_slice(coords));
+        queue.write_buffer(
+            &self.config_buffer,
+            0,
+            bytemuck::cast_slice(&[fill, coords.len() as u32, 0, 0]),
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("misa-rin layer tile scan bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(working_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.config_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: tiles_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: differs_buffer.as_entire_binding(),
+                },
+            ],
+        });
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("misa-rin layer array resize encoder"),
+            label: Some("misa-rin layer tile scan encoder"),
         });
-        encoder.copy_texture_to_texture(
-            wgpu::ImageCopyTexture {
-                texture: &self.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
+        encoder.clear_buffer(&differs_buffer, 0, None);
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("misa-rin layer tile scan pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(coords.len() as u32, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&differs_buffer, 0, &readback, 0, size);
+        queue.submit(Some(encoder.finish()));
+
+        let slice = readback.slice(..);
+        let (tx, rx) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |res| {
+            let _ = tx.send(res);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        match rx.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => return Err(format!("layer tile scan map failed: {err:?}")),
+            Err(err) => return Err(format!("layer tile scan channel failed: {err}")),
+        }
+        let differs = {
+            let mapped = slice.get_mapped_range();
+            let flags: &[u32] = bytemuck::cast_slice(&mapped);
+            flags.iter().map(|&flag| flag != 0).collect()
+        };
+        readback.unmap();
+        Ok(differs)
+    }
+}
+
+fn page_table_columns(tile_count: u32) -> u32 {
+    (tile_count + 1).max(PAGE_TABLE_HEADER)
+}
+
+fn create_page_table(
+    device: &wgpu::Device,
+    tile_count: u32,
+    capacity: usize,
+) -> Result<(wgpu::Texture, wgpu::TextureView), String> {
+    let columns = page_table_columns(tile_count);
+    let rows = capacity as u32 + 1;
+    let max_dimension = device.limits().max_texture_dimension_2d;
+    if columns > max_dimension || rows > max_dimension {
+        return Err(format!(
+            "layer page table {columns}x{rows} exceeds device max {max_dimension}"
+        ));
+    }
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("misa-rin layer page table"),
+        size: wgpu::Extent3d {
+            width: columns,
+            height: rows,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R32Uint,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    Ok((texture, view))
+}
+
+fn create_atlas(
+    device: &wgpu::Device,
+    page_size: u32,
+    pages: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("misa-rin layer tile atlas"),
+        size: wgpu::Extent3d {
+            width: page_size,
+            height: page_size,
+            depth_or_array_layers: pages,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: LAYER_TEXTURE_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    });
+    (texture, view)
+}
+
+pub(crate) fn create_working(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) -> (wgpu::Texture, wgpu::TextureView, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("misa-rin layer working texture"),
+        size: wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: LAYER_TEXTURE_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2),
+        ..Default::default()
+    });
+    let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    });
+    (texture, view, array_view)
+}
+
+/// Bytes of every texel of `texture`, all array layers included.
+pub(crate) fn texture_bytes(texture: &wgpu::Texture, bytes_per_texel: u32) -> u64 {
+    let size = texture.size();
+    size.width as u64
+        * size.height as u64
+        * size.depth_or_array_layers as u64
+        * bytes_per_texel as u64
+}
+
+fn tile_extent(width: u32, height: u32) -> wgpu::Extent3d {
+    wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    }
+}
+
+/// Clears `view` to the ARGB `color`. Layer texels keep B, G, R, A in the
+/// x, y, z, w channels.
+pub(crate) fn clear_texture(
+    encoder: &mut wgpu::CommandEncoder,
+    view: &wgpu::TextureView,
+    color: u32,
+) {
+    let channel = |shift: u32| ((color >> shift) & 0xFF) as f64 / 255.0;
+    let _ = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("misa-rin layer clear pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color {
+                    r: channel(0),
+                    g: channel(8),
+                    b: channel(16),
+                    a: channel(24),
+                }),
+                store: wgpu::StoreOp::Store,
             },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas_engine::engine::{device_context, read_r32uint_region};
+
+    fn allocated(store: &LayerStore, index: usize) -> usize {
+        store.layers[index]
+            .slots
+            .iter()
+            .filter(|&&slot| slot != NO_TILE)
+            .count()
+    }
+
+    fn write_block(queue: &wgpu::Queue, store: &LayerStore, slot: u32, color: u32) {
+        queue.write_texture(
             wgpu::ImageCopyTexture {
-                texture: &new_texture,
+                texture: store.texture(),
                 mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
+                origin: wgpu::Origin3d { x: 300, y: 10, z: slot },
                 aspect: wgpu::TextureAspect::All,
             },
-            wgpu::Extent3d {
-                width: self.width,
-                height: self.height,
-                depth_or_array_layers: old_capacity as u32,
+            bytemuck::cast_slice(&[color; 16]),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * BYTES_PER_TEXEL),
+                rows_per_image: None,
             },
+            tile_extent(4, 4),
         );
-        queue.submit(Some(encoder.finish()));
+    }
 
-        let array_view = new_texture.create_view(&wgpu::TextureViewDescriptor {
-            dimension: Some(wgpu::TextureViewDimension::D2Array),
-            base_array_layer: 0,
-            array_layer_count: Some(next_capacity as u32),
-            ..Default::default()
-        });
-        let mut layer_views = Vec::with_capacity(next_capacity);
-        for idx in 0..next_capacity {
-            let view = new_texture.create_view(&wgpu::TextureViewDescriptor {
-                dimension: Some(wgpu::TextureViewDimension::D2),
-                base_array_layer: idx as u32,
-                array_layer_count: Some(1),
-                ..Default::default()
-            });
-            layer_views.push(view);
-        }
+    #[test]
+    fn painted_tiles_take_slots_and_blank_ones_give_them_back() {
+        let ctx = match device_context() {
+            Ok(ctx) => ctx,
+            Err(err) => {
+                eprintln!("skipping GPU layer store test: {err}");
+                return;
+            }
+        };
+        let (device, queue) = (ctx.device.as_ref(), ctx.queue.as_ref());
+        let mut store = LayerStore::new(device, 2048, 2048, 2).unwrap();
+        store.push_layer(0);
+        store.push_layer(0);
 
-        self.texture = new_texture;
-        self.array_view = array_view;
-        self.layer_views = layer_views;
-        self.capacity = next_capacity;
+        let slot = store.checkout(device, queue, 1).unwrap();
+        write_block(queue, &store, slot, 0xFFFF_0000);
+        store.mark_dirty(1, (300, 10, 4, 4));
+        store.flush(device, queue).unwrap();
+        assert_eq!(allocated(&store, 1), 1);
+        assert_eq!(allocated(&store, 0), 0);
+        assert_eq!(store.occupied_rect(1), Some((256, 0, 512, 256)));
+        assert_eq!(store.uniform(0), Some(0));
 
-        Ok(Some(old_capacity))
+        // Checking layer 1 out again rebuilds it from its single tile.
+        store.checkout(device, queue, 0).unwrap();
+        let slot = store.checkout(device, queue, 1).unwrap();
+        let pixels =
+            read_r32uint_region(device, queue, store.texture(), slot, (299, 10, 2, 1)).unwrap();
+        assert_eq!(pixels, [0, 0xFFFF_0000]);
+
+        // Erasing the block hands the tile back on the next flush.
+        write_block(queue, &store, slot, 0);
+        store.mark_rewritten(1, (300, 10, 4, 4));
+        store.flush(device, queue).unwrap();
+        assert_eq!(allocated(&store, 1), 0);
+        assert_eq!(store.uniform(1), Some(0));
+        assert_eq!(store.occupied_rect(1), None);
     }
 }
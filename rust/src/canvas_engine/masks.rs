@@ -0,0 +1,835 @@
+use std::borrow::Cow;
+
+use crate::gpu::layer_format::LAYER_TEXTURE_FORMAT;
+
+use super::layers::{clear_texture, create_working, texture_bytes};
+use super::stroke::EngineBrushSettings;
+
+/// Mask texel that reveals the layer; masks nobody painted read as it.
+pub(crate) const MASK_REVEAL_ALL: u32 = 0xFFFF_FFFF;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct LayerMaskState {
+    pub(crate) present: bool,
+    pub(crate) enabled: bool,
+}
+
+impl LayerMaskState {
+    pub(crate) const ABSENT: Self = Self {
+        present: false,
+        enabled: false,
+    };
+    pub(crate) const CREATED: Self = Self {
+        present: true,
+        enabled: true,
+    };
+
+    /// Whether the compositor should multiply the layer by its mask.
+    pub(crate) fn applies(self) -> bool {
+        self.present && self.enabled
+    }
+}
+
+/// Mask strokes paint the brush colour's luminance as an opaque gray.
+pub(crate) fn mask_brush_color(color_argb: u32) -> u32 {
+    let r = (color_argb >> 16) & 0xFF;
+    let g = (color_argb >> 8) & 0xFF;
+    let b = color_argb & 0xFF;
+    let luma = (r * 299 + g * 587 + b * 114 + 500) / 1000;
+    mask_gray(luma as u8)
+}
+
+/// Brush settings for a stroke that lands on a mask. Streamline and hollow
+/// strokes are redrawn through the layer paths, so they are turned off.
+pub(crate) fn mask_stroke_settings(settings: EngineBrushSettings) -> EngineBrushSettings {
+    EngineBrushSettings {
+        color_argb: mask_brush_color(settings.color_argb),
+        hollow_enabled: false,
+        streamline_strength: 0.0,
+        ..settings
+    }
+}
+
+pub(crate) fn mask_gray(value: u8) -> u32 {
+    let v = value as u32;
+    0xFF00_0000 | (v << 16) | (v << 8) | v
+}
+
+/// Coverage stored in a mask texel. Brush antialiasing may leave partially
+/// transparent texels, so the gray is weighted by alpha.
+pub(crate) fn mask_texel_value(texel: u32) -> u8 {
+    let gray = texel & 0xFF;
+    let alpha = (texel >> 24) & 0xFF;
+    ((gray * alpha + 127) / 255) as u8
+}
+
+pub(crate) fn invert_mask_texel(texel: u32) -> u32 {
+    mask_gray(255 - mask_texel_value(texel))
+}
+
+/// Slot table entry of a mask without a slice; it reveals the whole layer.
+const NO_MASK: u32 = u32::MAX;
+const MASK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// Per-layer masks of the GPU engine. A mask only gets texels once it is
+/// painted: a single-channel slice of a shared array, found through a slot
+/// table indexed by layer. Layers without a mask, and masks nobody painted,
+/// cost nothing canvas-sized.
+///
+/// Strokes, undo and readbacks work on an Rgba8 copy instead, so they share
+/// the texel format of layers: `checkout` expands one mask into it, writers
+/// report what they touched with `mark_dirty`, and `flush` packs that back
+/// into the mask's slice.
+pub(crate) struct LayerMasks {
+    width: u32,
+    height: u32,
+    states: Vec<LayerMaskState>,
+    slots: Vec<u32>,
+    editing: bool,
+    array: wgpu::Texture,
+    array_view: wgpu::TextureView,
+    // 0 while `array` is a placeholder that holds no slice yet.
+    array_slices: u32,
+    next_slot: u32,
+    free_slots: Vec<u32>,
+    slot_table: wgpu::Texture,
+    slot_table_view: wgpu::TextureView,
+    slot_table_len: u32,
+    table_dirty: bool,
+    working: wgpu::Texture,
+    working_view: wgpu::TextureView,
+    // False while `working` is a placeholder.
+    working_ready: bool,
+    checked_out: Option<usize>,
+    // `(left, top, right, bottom)` written since the checkout or last flush.
+    dirty: Option<(u32, u32, u32, u32)>,
+    converter: Option<MaskConverter>,
+    bindings_changed: bool,
+}
+
+impl LayerMasks {
+    pub(crate) fn new(device: &wgpu::Device, width: u32, height: u32, layer_count: usize) -> Self {
+        let (array, array_view) = create_mask_array(device, 1, 1, 1);
+        let (slot_table, slot_table_view) = create_slot_table(device, 1);
+        let (working, working_view, _) = create_working(device, 1, 1);
+        Self {
+            width,
+            height,
+            states: vec![LayerMaskState::ABSENT; layer_count],
+            slots: vec![NO_MASK; layer_count],
+            editing: false,
+            array,
+            array_view,
+            array_slices: 0,
+            next_slot: 0,
+            free_slots: Vec::new(),
+            slot_table,
+            slot_table_view,
+            slot_table_len: 1,
+            table_dirty: true,
+            working,
+            working_view,
+            working_ready: false,
+            checked_out: None,
+            dirty: None,
+            converter: None,
+            bindings_changed: false,
+        }
+    }
+
+    pub(crate) fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// VRAM held by painted masks and the working texture.
+    pub(crate) fn resident_bytes(&self) -> u64 {
+        let array = if self.array_slices > 0 {
+            texture_bytes(&self.array, 1)
+        } else {
+            0
+        };
+        let working = if self.working_ready {
+            texture_bytes(&self.working, 4)
+        } else {
+            0
+        };
+        array + working
+    }
+
+    /// The Rgba8 copy of the checked out mask; its only slice is 0.
+    pub(crate) fn texture(&self) -> &wgpu::Texture {
+        &self.working
+    }
+
+    pub(crate) fn working_view(&self) -> &wgpu::TextureView {
+        &self.working_view
+    }
+
+    pub(crate) fn array_view(&self) -> &wgpu::TextureView {
+        &self.array_view
+    }
+
+    pub(crate) fn slot_table_view(&self) -> &wgpu::TextureView {
+        &self.slot_table_view
+    }
+
+    pub(crate) fn reset(&mut self, layer_count: usize) {
+        self.resize_layers(0);
+        self.resize_layers(layer_count);
+        self.editing = false;
+    }
+
+    pub(crate) fn resize_layers(&mut self, layer_count: usize) {
+        for index in layer_count..self.slots.len() {
+            self.release(index);
+        }
+        self.states.resize(layer_count, LayerMaskState::ABSENT);
+        self.slots.resize(layer_count, NO_MASK);
+        self.table_dirty = true;
+    }
+
+    pub(crate) fn reorder_layer(&mut self, from: usize, to: usize) {
+        super::engine::reorder_vec(&mut self.states, from, to);
+        super::engine::reorder_vec(&mut self.slots, from, to);
+        self.checked_out = self
+            .checked_out
+            .map(|index| super::engine::remap_layer_index(index, from, to));
+        self.table_dirty = true;
+    }
+
+    pub(crate) fn state(&self, layer_index: usize) -> LayerMaskState {
+        self.states
+            .get(layer_index)
+            .copied()
+            .unwrap_or(LayerMaskState::ABSENT)
+    }
+
+    /// Sets the state of the mask of `layer_index`; a mask that goes away
+    /// gives its slice back.
+    pub(crate) fn set_state(&mut self, layer_index: usize, state: LayerMaskState) {
+        let Some(entry) = self.states.get_mut(layer_index) else {
+            return;
+        };
+        *entry = state;
+        if !state.present {
+            self.release(layer_index);
+        }
+    }
+
+    /// Turns the mask of `layer_index` on or off; a layer without one is
+    /// left alone.
+    pub(crate) fn set_enabled(&mut self, layer_index: usize, enabled: bool) {
+        if let Some(entry) = self.states.get_mut(layer_index).filter(|state| state.present) {
+            entry.enabled = enabled;
+        }
+    }
+
+    pub(crate) fn set_editing(&mut self, editing: bool) {
+        self.editing = editing;
+    }
+
+    /// Whether brush strokes on `layer_index` should go to its mask.
+    pub(crate) fn editing_layer(&self, layer_index: usize) -> bool {
+        self.editing && self.state(layer_index).present
+    }
+
+    pub(crate) fn enabled_flags(&self) -> Vec<bool> {
+        self.states.iter().map(|state| state.applies()).collect()
+    }
+
+    /// Whether the mask of `layer_index` holds texels of its own, instead of
+    /// revealing everything.
+    pub(crate) fn painted(&self, layer_index: usize) -> bool {
+        self.slots.get(layer_index).is_some_and(|&slot| slot != NO_MASK)
+            || (self.checked_out == Some(layer_index) && self.dirty.is_some())
+    }
+
+    /// Loads the mask of `index` into the working texture and returns the
+    /// slice to address it by. The previous mask is flushed first.
+    pub(crate) fn checkout(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        index: usize,
+    ) -> Result<u32, String> {
+        if index >= self.states.len() {
+            return Err(format!(
+                "mask {index} out of range ({} layers)",
+                self.states.len()
+            ));
+        }
+        if self.checked_out == Some(index) {
+            return Ok(0);
+        }
+        self.flush(device, queue)?;
+        if !self.working_ready {
+            let (working, working_view, _) = create_working(device, self.width, self.height);
+            self.working = working;
+            self.working_view = working_view;
+            self.working_ready = true;
+        }
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("misa-rin layer mask checkout encoder"),
+        });
+        match self.slots[index] {
+            NO_MASK => clear_texture(&mut encoder, &self.working_view, MASK_REVEAL_ALL),
+            slot => {
+                let converter = self
+                    .converter
+                    .get_or_insert_with(|| MaskConverter::new(device));
+                converter.expand(
+                    device,
+                    queue,
+                    &mut encoder,
+                    &self.array_view,
+                    slot,
+                    &self.working_view,
+                );
+            }
+        }
+        queue.submit(Some(encoder.finish()));
+        self.checked_out = Some(index);
+        self.dirty = None;
+        Ok(0)
+    }
+
+    /// Records that `(left, top, width, height)` of the checked out mask
+    /// `index` was written.
+    pub(crate) fn mark_dirty(&mut self, index: usize, rect: (i32, i32, i32, i32)) {
+        if self.checked_out != Some(index) {
+            return;
+        }
+        let (left, top, rect_width, rect_height) = rect;
+        if rect_width <= 0 || rect_height <= 0 {
+            return;
+        }
+        let x0 = (left.max(0) as u32).min(self.width);
+        let y0 = (top.max(0) as u32).min(self.height);
+        let x1 = (left.saturating_add(rect_width).max(0) as u32).min(self.width);
+        let y1 = (top.saturating_add(rect_height).max(0) as u32).min(self.height);
+        if x1 <= x0 || y1 <= y0 {
+            return;
+        }
+        self.dirty = Some(match self.dirty {
+            Some((l, t, r, b)) => (l.min(x0), t.min(y0), r.max(x1), b.max(y1)),
+            None => (x0, y0, x1, y1),
+        });
+    }
+
+    /// Packs what was written to the checked out mask into its slice and
+    /// uploads the slot table. Readers of the array flush first.
+    pub(crate) fn flush(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<(), String> {
+        if let (Some(index), Some(rect)) = (self.checked_out, self.dirty) {
+            let (slot, rect) = match self.slots[index] {
+                // A new slice holds stale texels, so all of it is packed.
+                NO_MASK => {
+                    let slot = self.allocate_slot(device, queue)?;
+                    self.slots[index] = slot;
+                    self.table_dirty = true;
+                    (slot, (0, 0, self.width, self.height))
+                }
+                slot => (slot, rect),
+            };
+            let target = self.slice_view(slot);
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("misa-rin layer mask flush encoder"),
+            });
+            let converter = self
+                .converter
+                .get_or_insert_with(|| MaskConverter::new(device));
+            converter.pack(device, &mut encoder, &self.working_view, &target, rect);
+            queue.submit(Some(encoder.finish()));
+            self.dirty = None;
+        }
+        if self.table_dirty {
+            self.upload_slot_table(device, queue);
+            self.table_dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Flushes and releases the working texture, for masks that are set
+    /// aside, e.g. a canvas kept by undo.
+    pub(crate) fn park(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<(), String> {
+        self.flush(device, queue)?;
+        let (working, working_view, _) = create_working(device, 1, 1);
+        self.working = working;
+        self.working_view = working_view;
+        self.working_ready = false;
+        self.checked_out = None;
+        Ok(())
+    }
+
+    /// Whether the array or slot table was replaced since the last call, so
+    /// bind groups holding their views must be recreated.
+    pub(crate) fn take_bindings_changed(&mut self) -> bool {
+        std::mem::take(&mut self.bindings_changed)
+    }
+
+    /// Takes the texels of the mask of `index` out, leaving it revealing
+    /// everything; `None` when it had none.
+    pub(crate) fn take(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        index: usize,
+    ) -> Result<Option<wgpu::Texture>, String> {
+        self.flush(device, queue)?;
+        let Some(&slot) = self.slots.get(index).filter(|&&slot| slot != NO_MASK) else {
+            return Ok(None);
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("misa-rin detached layer mask"),
+            size: self.extent(1),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: MASK_FORMAT,
+            usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("misa-rin layer mask take encoder"),
+        });
+        encoder.copy_texture_to_texture(
+            self.slice_copy(slot),
+            texture.as_image_copy(),
+            self.extent(1),
+        );
+        queue.submit(Some(encoder.finish()));
+        self.release(index);
+        Ok(Some(texture))
+    }
+
+    /// Gives the mask of `index` the texels `take` returned.
+    pub(crate) fn restore(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        index: usize,
+        texture: Option<wgpu::Texture>,
+    ) -> Result<(), String> {
+        if index >= self.slots.len() {
+            return Err(format!("mask {index} out of range"));
+        }
+        self.release(index);
+        let Some(texture) = texture else {
+            return Ok(());
+        };
+        let slot = self.allocate_slot(device, queue)?;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("misa-rin layer mask restore encoder"),
+        });
+        encoder.copy_texture_to_texture(
+            texture.as_image_copy(),
+            self.slice_copy(slot),
+            self.extent(1),
+        );
+        queue.submit(Some(encoder.finish()));
+        self.slots[index] = slot;
+        self.table_dirty = true;
+        Ok(())
+    }
+
+    /// Drops the texels of the mask of `index`, checked out copy included.
+    fn release(&mut self, index: usize) {
+        if self.checked_out == Some(index) {
+            self.checked_out = None;
+            self.dirty = None;
+        }
+        if let Some(slot) = self.slots.get_mut(index).filter(|slot| **slot != NO_MASK) {
+            self.free_slots.push(*slot);
+            *slot = NO_MASK;
+            self.table_dirty = true;
+        }
+    }
+
+    fn extent(&self, depth: u32) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: depth,
+        }
+    }
+
+    fn slice_copy(&self, slot: u32) -> wgpu::ImageCopyTexture<'_> {
+        wgpu::ImageCopyTexture {
+            texture: &self.array,
+            mip_level: 0,
+            origin: wgpu::Origin3d { x: 0, y: 0, z: slot },
+            aspect: wgpu::TextureAspect::All,
+        }
+    }
+
+    fn slice_view(&self, slot: u32) -> wgpu::TextureView {
+        self.array.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("misa-rin layer mask slice view"),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_array_layer: slot,
+            array_layer_count: Some(1),
+            ..Default::default()
+        })
+    }
+
+    fn allocate_slot(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<u32, String> {
+        if let Some(slot) = self.free_slots.pop() {
+            return Ok(slot);
+        }
+        if self.next_slot >= self.array_slices {
+            self.grow_array(device, queue)?;
+        }
+        let slot = self.next_slot;
+        self.next_slot += 1;
+        Ok(slot)
+    }
+
+    fn grow_array(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<(), String> {
+        let max_slices = device.limits().max_texture_array_layers;
+        // GL backs single-layer textures with plain 2D ones, which array
+        // views then read as zero, so the array starts at two slices.
+        let slices = self.array_slices.saturating_mul(2).clamp(2, max_slices);
+        if slices <= self.array_slices {
+            return Err(format!("layer mask array is full ({} masks)", self.array_slices));
+        }
+        let (array, array_view) = create_mask_array(device, self.width, self.height, slices);
+        if self.array_slices > 0 {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("misa-rin layer mask array grow encoder"),
+            });
+            encoder.copy_texture_to_texture(
+                self.array.as_image_copy(),
+                array.as_image_copy(),
+                self.extent(self.array_slices),
+            );
+            queue.submit(Some(encoder.finish()));
+        }
+        self.array = array;
+        self.array_view = array_view;
+        self.array_slices = slices;
+        self.bindings_changed = true;
+        Ok(())
+    }
+
+    fn upload_slot_table(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let len = self.slots.len().max(1) as u32;
+        if len > self.slot_table_len {
+            let table_len = len.next_power_of_two();
+            let (slot_table, slot_table_view) = create_slot_table(device, table_len);
+            self.slot_table = slot_table;
+            self.slot_table_view = slot_table_view;
+            self.slot_table_len = table_len;
+            self.bindings_changed = true;
+        }
+        let mut table = vec![NO_MASK; self.slot_table_len as usize];
+        table[..self.slots.len()].copy_from_slice(&self.slots);
+        queue.write_texture(
+            self.slot_table.as_image_copy(),
+            bytemuck::cast_slice(&table),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(self.slot_table_len * 4),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: self.slot_table_len,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+fn create_mask_array(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    slices: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("misa-rin layer mask array (R8Unorm)"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: slices,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: MASK_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    });
+    (texture, view)
+}
+
+fn create_slot_table(device: &wgpu::Device, len: u32) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("misa-rin layer mask slot table"),
+        size: wgpu::Extent3d {
+            width: len,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R32Uint,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+/// Render passes behind `LayerMasks::checkout` and `LayerMasks::flush`.
+struct MaskConverter {
+    expand_pipeline: wgpu::RenderPipeline,
+    expand_layout: wgpu::BindGroupLayout,
+    pack_pipeline: wgpu::RenderPipeline,
+    pack_layout: wgpu::BindGroupLayout,
+    config_buffer: wgpu::Buffer,
+}
+
+impl MaskConverter {
+    fn new(device: &wgpu::Device) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("misa-rin layer mask convert shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "layer_mask_convert.wgsl"
+            ))),
+        });
+        let texture_entry = |binding: u32, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let expand_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("misa-rin layer mask expand bgl"),
+            entries: &[
+                texture_entry(0, wgpu::TextureViewDimension::D2Array),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pack_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("misa-rin layer mask pack bgl"),
+            entries: &[texture_entry(2, wgpu::TextureViewDimension::D2)],
+        });
+        let pipeline = |label, layout: &wgpu::BindGroupLayout, entry_point, format| {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let expand_pipeline = pipeline(
+            "misa-rin layer mask expand pipeline",
+            &expand_layout,
+            "expand_main",
+            LAYER_TEXTURE_FORMAT,
+        );
+        let pack_pipeline = pipeline(
+            "misa-rin layer mask pack pipeline",
+            &pack_layout,
+            "pack_main",
+            MASK_FORMAT,
+        );
+        let config_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("misa-rin layer mask convert config"),
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            expand_pipeline,
+            expand_layout,
+            pack_pipeline,
+            pack_layout,
+            config_buffer,
+        }
+    }
+
+    fn expand(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        array_view: &wgpu::TextureView,
+        slot: u32,
+        target: &wgpu::TextureView,
+    ) {
+        queue.write_buffer(&self.config_buffer, 0, bytemuck::cast_slice(&[slot, 0, 0, 0]));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("misa-rin layer mask expand bind group"),
+            layout: &self.expand_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(array_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.config_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let mut pass = begin_convert_pass(encoder, target, wgpu::LoadOp::Clear(wgpu::Color::BLACK));
+        pass.set_pipeline(&self.expand_pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    fn pack(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        working_view: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+        rect: (u32, u32, u32, u32),
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("misa-rin layer mask pack bind group"),
+            layout: &self.pack_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(working_view),
+            }],
+        });
+        let (left, top, right, bottom) = rect;
+        let mut pass = begin_convert_pass(encoder, target, wgpu::LoadOp::Load);
+        pass.set_pipeline(&self.pack_pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.set_scissor_rect(left, top, right - left, bottom - top);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+fn begin_convert_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    target: &'a wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("misa-rin layer mask convert pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas_engine::engine::{device_context, read_r32uint_region};
+
+    #[test]
+    fn masks_take_a_slice_once_painted_and_give_it_back_when_deleted() {
+        let ctx = match device_context() {
+            Ok(ctx) => ctx,
+            Err(err) => {
+                eprintln!("skipping GPU layer mask test: {err}");
+                return;
+            }
+        };
+        let (device, queue) = (ctx.device.as_ref(), ctx.queue.as_ref());
+        let mut masks = LayerMasks::new(device, 64, 64, 3);
+        masks.set_state(1, LayerMaskState::CREATED);
+        masks.flush(device, queue).unwrap();
+        assert_eq!(masks.array_slices, 0);
+
+        let slot = masks.checkout(device, queue, 1).unwrap();
+        assert!(!masks.painted(1));
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: masks.texture(),
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: 8, y: 8, z: slot },
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&[mask_gray(0x40); 16]),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(16),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: 4,
+                height: 4,
+                depth_or_array_layers: 1,
+            },
+        );
+        masks.mark_dirty(1, (8, 8, 4, 4));
+        masks.flush(device, queue).unwrap();
+        assert_eq!(masks.slots, [NO_MASK, 0, NO_MASK]);
+
+        // Checking mask 1 out again expands it from its slice.
+        masks.set_state(2, LayerMaskState::CREATED);
+        masks.checkout(device, queue, 2).unwrap();
+        let slot = masks.checkout(device, queue, 1).unwrap();
+        let texels =
+            read_r32uint_region(device, queue, masks.texture(), slot, (7, 8, 2, 1)).unwrap();
+        assert_eq!(texels.into_iter().map(mask_texel_value).collect::<Vec<_>>(), [0xFF, 0x40]);
+
+        masks.set_state(1, LayerMaskState::ABSENT);
+        assert!(!masks.painted(1));
+        assert_eq!(masks.free_slots, [0]);
+    }
+}
@@ -6,8 +6,12 @@ use std::sync::atomic::AtomicU64;
 #[cfg(target_os = "windows")]
 use std::time::Instant;
 
+use crate::api::gpu_composite::{CompositeAdjustment, LayerGroupSpan, MAX_COMPOSITE_GROUP_DEPTH};
 use crate::gpu::debug::{self, LogLevel};
 
+use super::layers::LayerStore;
+use super::masks::LayerMasks;
+
 #[cfg(any(target_os = "macos", target_os = "ios"))]
 use metal::foreign_types::ForeignType;
 #[cfg(any(target_os = "macos", target_os = "ios"))]
@@ -105,6 +109,23 @@ struct PresentLayerParams {
     visible: f32,
     clipping_mask: f32,
     blend_mode: u32,
+    // Groups opening before / closing after this layer; the opened groups'
+    // params are consumed from the group buffer in order.
+    group_open: u32,
+    group_close: u32,
+    mask_enabled: u32,
+    // Colour filter kind + 1 for adjustment layers, 0 for pixel layers.
+    adjustment: u32,
+    adjustment_params: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct PresentGroupParams {
+    opacity: f32,
+    blend_mode: u32,
+    pass_through: u32,
+    _pad0: u32,
 }
 
 #[repr(C)]
@@ -117,6 +138,7 @@ pub(crate) fn write_present_config(
     queue: &wgpu::Queue,
     header_buffer: &wgpu::Buffer,
     params_buffer: &wgpu::Buffer,
+    groups_buffer: &wgpu::Buffer,
     layer_count: usize,
     view_flags: u32,
     transform_layer: u32,
@@ -125,6 +147,9 @@ pub(crate) fn write_present_config(
     layer_visible: &[bool],
     layer_clipping_mask: &[bool],
     layer_blend_mode: &[u32],
+    layer_mask_enabled: &[bool],
+    layer_adjustments: &[Option<CompositeAdjustment>],
+    layer_groups: &[LayerGroupSpan],
 ) {
     let header = PresentCompositeHeader {
         layer_count: layer_count as u32,
@@ -139,6 +164,7 @@ pub(crate) fn write_present_config(
     }
 
     let mut params: Vec<PresentLayerParams> = Vec::with_capacity(layer_count);
+    let mut groups: Vec<PresentGroupParams> = Vec::new();
     for i in 0..layer_count {
         let raw_opacity = layer_opacity.get(i).copied().unwrap_or(1.0);
         let opacity = if raw_opacity.is_finite() {
@@ -146,7 +172,9 @@ pub(crate) fn write_present_config(
         } else {
             0.0
         };
-        let visible = if *layer_visible.get(i).unwrap_or(&true) {
+        let span = layer_groups.get(i);
+        let hidden_by_group = span.is_some_and(|span| span.hidden);
+        let visible = if *layer_visible.get(i).unwrap_or(&true) && !hidden_by_group {
             1.0
         } else {
             0.0
@@ -157,14 +185,40 @@ pub(crate) fn write_present_config(
             0.0
         };
         let blend_mode = layer_blend_mode.get(i).copied().unwrap_or(0);
+        let (group_open, group_close) = match span {
+            Some(span) => {
+                for group in &span.open {
+                    groups.push(PresentGroupParams {
+                        opacity: group.opacity.clamp(0.0, 1.0),
+                        blend_mode: group.blend_mode_index,
+                        pass_through: group.pass_through as u32,
+                        _pad0: 0,
+                    });
+                }
+                (span.open.len() as u32, span.close)
+            }
+            None => (0, 0),
+        };
+        let (adjustment, adjustment_params) = match layer_adjustments.get(i).copied().flatten() {
+            Some(adjustment) => (adjustment.kind + 1, adjustment.params),
+            None => (0, [0.0; 4]),
+        };
         params.push(PresentLayerParams {
             opacity,
             visible,
             clipping_mask,
             blend_mode,
+            group_open,
+            group_close,
+            mask_enabled: layer_mask_enabled.get(i).copied().unwrap_or(false) as u32,
+            adjustment,
+            adjustment_params,
         });
     }
     queue.write_buffer(params_buffer, 0, bytemuck::cast_slice(&params));
+    if !groups.is_empty() {
+        queue.write_buffer(groups_buffer, 0, bytemuck::cast_slice(&groups));
+    }
 }
 
 pub(crate) fn write_present_transform(
@@ -212,6 +266,31 @@ pub(crate) fn create_present_params_buffer(
     }))
 }
 
+/// Group params for `capacity` layers. Each layer opens at most
+/// `MAX_COMPOSITE_GROUP_DEPTH` groups, which bounds the total.
+pub(crate) fn create_present_groups_buffer(
+    device: &wgpu::Device,
+    capacity: usize,
+) -> Result<wgpu::Buffer, String> {
+    let size = capacity
+        .max(1)
+        .checked_mul(MAX_COMPOSITE_GROUP_DEPTH)
+        .and_then(|count| count.checked_mul(std::mem::size_of::<PresentGroupParams>()))
+        .ok_or_else(|| "layer group buffer size overflow".to_string())?;
+    let max_storage_binding = device.limits().max_storage_buffer_binding_size as u64;
+    if (size as u64) > max_storage_binding {
+        return Err(format!(
+            "layer group buffer too large: {size} bytes (max {max_storage_binding})"
+        ));
+    }
+    Ok(device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("misa-rin present layer groups"),
+        size: size as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    }))
+}
+
 impl PresentRenderer {
     pub(crate) fn new(device: &wgpu::Device) -> Self {
         let shader_source = include_str!("../canvas_present_rgba8.wgsl");
@@ -268,6 +347,46 @@ impl PresentRenderer {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: layer_sample_type,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Uint,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Uint,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -317,10 +436,12 @@ impl PresentRenderer {
     pub(crate) fn create_bind_group(
         &self,
         device: &wgpu::Device,
-        layer_view: &wgpu::TextureView,
+        layers: &LayerStore,
         config_buffer: &wgpu::Buffer,
         params_buffer: &wgpu::Buffer,
         transform_buffer: &wgpu::Buffer,
+        groups_buffer: &wgpu::Buffer,
+        masks: &LayerMasks,
     ) -> wgpu::BindGroup {
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("misa-rin present renderer bind group"),
@@ -328,7 +449,7 @@ impl PresentRenderer {
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(layer_view),
+                    resource: wgpu::BindingResource::TextureView(layers.atlas_view()),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
@@ -342,6 +463,22 @@ impl PresentRenderer {
                     binding: 3,
                     resource: transform_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: groups_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(masks.array_view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::TextureView(layers.page_table_view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::TextureView(masks.slot_table_view()),
+                },
             ],
         })
     }
@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+
+use crate::canvas_engine::types::EnginePoint;
+use crate::gpu::debug::{self, LogLevel};
+
+const CURVE_SAMPLES: usize = 33;
+
+/// A pressure response sampled into a small lookup table, so brush settings
+/// stay `Copy` and evaluating a dab costs one lerp.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PressureCurve {
+    samples: [f32; CURVE_SAMPLES],
+}
+
+impl PressureCurve {
+    /// Fits a monotone cubic through `(pressure, value)` control points, so an
+    /// S-curve never overshoots between them. Coordinates are clamped to
+    /// `0..=1` and the ends are held flat past the first and last point.
+    pub(crate) fn from_points(points: &[(f32, f32)]) -> Option<Self> {
+        let mut knots: Vec<(f32, f32)> = points
+            .iter()
+            .filter(|(x, y)| x.is_finite() && y.is_finite())
+            .map(|(x, y)| (x.clamp(0.0, 1.0), y.clamp(0.0, 1.0)))
+            .collect();
+        knots.sort_by(|a, b| a.0.total_cmp(&b.0));
+        // The last point wins when two share a pressure.
+        knots.reverse();
+        knots.dedup_by(|later, earlier| (later.0 - earlier.0).abs() < 1e-6);
+        knots.reverse();
+        match knots.len() {
+            0 => return None,
+            1 => {
+                return Some(Self {
+                    samples: [knots[0].1; CURVE_SAMPLES],
+                })
+            }
+            _ => {}
+        }
+
+        let slopes: Vec<f32> = knots
+            .windows(2)
+            .map(|pair| (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0))
+            .collect();
+        let mut tangents = vec![0.0f32; knots.len()];
+        tangents[0] = slopes[0];
+        tangents[knots.len() - 1] = slopes[slopes.len() - 1];
+        for k in 1..knots.len() - 1 {
+            if slopes[k - 1] * slopes[k] > 0.0 {
+                tangents[k] = (slopes[k - 1] + slopes[k]) * 0.5;
+            }
+        }
+        // Fritsch-Carlson: limit the tangents so every span stays monotone.
+        for (k, &slope) in slopes.iter().enumerate() {
+            if slope == 0.0 {
+                tangents[k] = 0.0;
+                tangents[k + 1] = 0.0;
+                continue;
+            }
+            let a = tangents[k] / slope;
+            let b = tangents[k + 1] / slope;
+            let length = (a * a + b * b).sqrt();
+            if length > 3.0 {
+                let scale = 3.0 / length;
+                tangents[k] = scale * a * slope;
+                tangents[k + 1] = scale * b * slope;
+            }
+        }
+
+        let mut samples = [0.0f32; CURVE_SAMPLES];
+        let mut span = 0usize;
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let x = i as f32 / (CURVE_SAMPLES - 1) as f32;
+            let (first, last) = (knots[0], knots[knots.len() - 1]);
+            if x <= first.0 {
+                *sample = first.1;
+                continue;
+            }
+            if x >= last.0 {
+                *sample = last.1;
+                continue;
+            }
+            while knots[span + 1].0 < x {
+                span += 1;
+            }
+            let (x0, y0) = knots[span];
+            let (x1, y1) = knots[span + 1];
+            let h = x1 - x0;
+            let t = (x - x0) / h;
+            let t2 = t * t;
+            let t3 = t2 * t;
+            let value = (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+                + (t3 - 2.0 * t2 + t) * h * tangents[span]
+                + (-2.0 * t3 + 3.0 * t2) * y1
+                + (t3 - t2) * h * tangents[span + 1];
+            *sample = value.clamp(0.0, 1.0);
+        }
+        Some(Self { samples })
+    }
+
+    /// Treats `values` as a lookup table spread evenly over `0..=1`.
+    pub(crate) fn table_points(values: &[f32]) -> Vec<(f32, f32)> {
+        match values.len() {
+            0 => Vec::new(),
+            1 => vec![(0.0, values[0])],
+            len => values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| (i as f32 / (len - 1) as f32, *value))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn eval(&self, pressure: f32) -> f32 {
+        let p = if pressure.is_finite() {
+            pressure.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let pos = p * (CURVE_SAMPLES - 1) as f32;
+        let index = (pos.floor() as usize).min(CURVE_SAMPLES - 2);
+        let t = pos - index as f32;
+        let a = self.samples[index];
+        let b = self.samples[index + 1];
+        a + (b - a) * t
+    }
+}
+
+/// What a pressure curve drives. The indices are the FFI `target` values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PressureTarget {
+    Size,
+    Opacity,
+    Flow,
+    Hardness,
+    Scatter,
+}
+
+impl PressureTarget {
+    fn from_index(index: u32) -> Option<Self> {
+        match index {
+            0 => Some(Self::Size),
+            1 => Some(Self::Opacity),
+            2 => Some(Self::Flow),
+            3 => Some(Self::Hardness),
+            4 => Some(Self::Scatter),
+            _ => None,
+        }
+    }
+}
+
+/// Response curves held by the brush settings. An unset curve leaves its
+/// target alone, except size, which keeps the built-in linear response.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct PressureCurves {
+    size: Option<PressureCurve>,
+    opacity: Option<PressureCurve>,
+    flow: Option<PressureCurve>,
+    hardness: Option<PressureCurve>,
+    scatter: Option<PressureCurve>,
+}
+
+impl PressureCurves {
+    /// Replaces one curve; no points clears it.
+    pub(crate) fn set(&mut self, target: u32, points: &[(f32, f32)]) {
+        let Some(target) = PressureTarget::from_index(target) else {
+            debug::log(
+                LogLevel::Warn,
+                format_args!("unknown pressure curve target {target}"),
+            );
+            return;
+        };
+        let curve = if points.is_empty() {
+            None
+        } else {
+            match PressureCurve::from_points(points) {
+                Some(curve) => Some(curve),
+                None => {
+                    debug::log(
+                        LogLevel::Warn,
+                        format_args!("pressure curve for {target:?} has no usable points"),
+                    );
+                    return;
+                }
+            }
+        };
+        *self.slot_mut(target) = curve;
+    }
+
+    fn slot_mut(&mut self, target: PressureTarget) -> &mut Option<PressureCurve> {
+        match target {
+            PressureTarget::Size => &mut self.size,
+            PressureTarget::Opacity => &mut self.opacity,
+            PressureTarget::Flow => &mut self.flow,
+            PressureTarget::Hardness => &mut self.hardness,
+            PressureTarget::Scatter => &mut self.scatter,
+        }
+    }
+
+    fn slot(&self, target: PressureTarget) -> Option<&PressureCurve> {
+        match target {
+            PressureTarget::Size => self.size.as_ref(),
+            PressureTarget::Opacity => self.opacity.as_ref(),
+            PressureTarget::Flow => self.flow.as_ref(),
+            PressureTarget::Hardness => self.hardness.as_ref(),
+            PressureTarget::Scatter => self.scatter.as_ref(),
+        }
+    }
+
+    /// The curve's output for `pressure`, or `None` when `target` has no
+    /// curve.
+    pub(crate) fn response(&self, target: PressureTarget, pressure: f32) -> Option<f32> {
+        self.slot(target).map(|curve| curve.eval(pressure))
+    }
+
+    pub(crate) fn has(&self, target: PressureTarget) -> bool {
+        self.slot(target).is_some()
+    }
+}
+
+/// Pressure calibration per input device, held by the engine rather than the
+/// brush so switching brushes keeps it. Points are calibrated as they are
+/// queued, before any brush curve sees them.
+#[derive(Debug, Default)]
+pub(crate) struct DeviceCalibrations {
+    curves: HashMap<u32, PressureCurve>,
+}
+
+impl DeviceCalibrations {
+    /// Replaces the curve for `device`; no points clears it.
+    pub(crate) fn set(&mut self, device: u32, points: &[(f32, f32)]) {
+        if points.is_empty() {
+            self.curves.remove(&device);
+            return;
+        }
+        match PressureCurve::from_points(points) {
+            Some(curve) => {
+                self.curves.insert(device, curve);
+            }
+            None => debug::log(
+                LogLevel::Warn,
+                format_args!("calibration for device {device} has no usable points"),
+            ),
+        }
+    }
+
+    pub(crate) fn calibrate(&self, points: &mut [EnginePoint]) {
+        if self.curves.is_empty() {
+            return;
+        }
+        for point in points {
+            if let Some(curve) = self.curves.get(&point.device) {
+                point.pressure = curve.eval(point.pressure);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas_engine::engine::tests::{
+        brush, point, read_layer, stop_engine, test_engines,
+    };
+    use crate::canvas_engine::engine::{lookup_engine, EngineCommand, EngineEntry};
+    use crate::canvas_engine::ffi::{engine_push_points, engine_set_device_calibration};
+    use crate::canvas_engine::types::EnginePoint;
+
+    #[test]
+    fn curve_follows_points_without_overshoot() {
+        let linear = PressureCurve::from_points(&[(0.0, 0.0), (1.0, 1.0)]).unwrap();
+        assert!((linear.eval(0.37) - 0.37).abs() < 1e-4);
+
+        let s_curve =
+            PressureCurve::from_points(&[(0.0, 0.0), (0.25, 0.05), (0.75, 0.95), (1.0, 1.0)])
+                .unwrap();
+        assert!((s_curve.eval(0.25) - 0.05).abs() < 1e-4);
+        let mut previous = 0.0;
+        for i in 0..=100 {
+            let value = s_curve.eval(i as f32 / 100.0);
+            assert!(value >= previous - 1e-6 && value <= 1.0);
+            previous = value;
+        }
+
+        let table =
+            PressureCurve::from_points(&PressureCurve::table_points(&[0.2, 0.2, 1.0])).unwrap();
+        assert!((table.eval(0.25) - 0.2).abs() < 1e-4);
+        assert!((table.eval(1.0) - 1.0).abs() < 1e-4);
+    }
+
+    /// Painted height, down the middle column, of a half-pressure stroke
+    /// from `device`, pushed the way the app pushes input.
+    fn stroke_height(entry: &EngineEntry, handle: u64, device: u32) -> usize {
+        entry
+            .cmd_tx
+            .send(EngineCommand::ResetCanvas {
+                background_color_argb: 0xFFFFFFFF,
+            })
+            .unwrap();
+        let points: Vec<EnginePoint> = [(8.0, 1), (32.0, 2), (56.0, 4)]
+            .into_iter()
+            .map(|(x, flags)| EnginePoint {
+                pressure: 0.5,
+                device,
+                ..point(x, 16.0, flags)
+            })
+            .collect();
+        engine_push_points(handle, points.as_ptr(), points.len());
+        let pixels = read_layer(entry, 0);
+        (0..32)
+            .filter(|&y| pixels[y * 64 + 32] != 0xFFFFFFFF)
+            .count()
+    }
+
+    fn pressure_brush() -> EngineCommand {
+        let mut pressed = brush(0xFF000000, 6.0);
+        if let EngineCommand::SetBrush { use_pressure, .. } = &mut pressed {
+            *use_pressure = true;
+        }
+        pressed
+    }
+
+    #[test]
+    fn size_curve_sets_the_stroke_width() {
+        for handle in test_engines(64, 32) {
+            let entry = lookup_engine(handle).unwrap();
+            let send = |cmd: EngineCommand| entry.cmd_tx.send(cmd).unwrap();
+            send(pressure_brush());
+            let linear = stroke_height(&entry, handle, 0);
+            send(EngineCommand::SetPressureCurve {
+                target: 0,
+                points: vec![(0.0, 1.0), (1.0, 1.0)],
+            });
+            let full = stroke_height(&entry, handle, 0);
+            let backend = entry.backend;
+            assert!(linear >= 4, "{backend:?}: half pressure painted {linear}");
+            assert!(
+                full > linear + 3,
+                "{backend:?}: flat curve widened {linear} to {full}"
+            );
+            stop_engine(handle);
+        }
+    }
+
+    #[test]
+    fn device_calibration_outlives_brush_changes() {
+        for handle in test_engines(64, 32) {
+            let entry = lookup_engine(handle).unwrap();
+            let send = |cmd: EngineCommand| entry.cmd_tx.send(cmd).unwrap();
+            send(pressure_brush());
+            let linear = stroke_height(&entry, handle, 0);
+            let full = [0.0, 1.0, 1.0, 1.0];
+            engine_set_device_calibration(handle, 3, full.as_ptr(), 2);
+            send(pressure_brush());
+            let backend = entry.backend;
+            assert_eq!(stroke_height(&entry, handle, 0), linear, "{backend:?}");
+            let calibrated = stroke_height(&entry, handle, 3);
+            assert!(
+                calibrated > linear + 3,
+                "{backend:?}: {linear} to {calibrated}"
+            );
+            // The brush curve reads the calibrated pressure.
+            send(EngineCommand::SetPressureCurve {
+                target: 0,
+                points: vec![(0.0, 0.0), (0.75, 0.0), (1.0, 1.0)],
+            });
+            assert!(stroke_height(&entry, handle, 3) > linear + 3, "{backend:?}");
+            assert!(stroke_height(&entry, handle, 0) < linear, "{backend:?}");
+            stop_engine(handle);
+        }
+    }
+}
@@ -0,0 +1,1007 @@
+//! `.rin` project files, the binary format of `project_binary_codec.dart`.
+//!
+//! Everything is big-endian: the `MISARIN` magic, a u16 version, document
+//! metadata, the layer records, the perspective guide and the preview image.
+//! Strings carry a u32 byte length; bitmaps are premultiplied RGBA, stored
+//! zlib-compressed when that is smaller. Files from v4 on are readable; files
+//! are always written as v9.
+//!
+//! Engine state the Dart codec has no fields for (alpha lock, layer masks,
+//! adjustment layers and layer groups) follows the preview as a tagged
+//! trailer. The Dart reader stops at the preview and never sees it.
+
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use miniz_oxide::inflate::decompress_to_vec_zlib;
+
+use crate::api::image_ops::convert_pixels_to_rgba;
+
+use super::adjustments::LayerAdjustment;
+use super::groups::{LayerGroup, ROOT_LAYER_GROUP};
+
+const MAGIC: &[u8; 7] = b"MISARIN";
+const VERSION: u16 = 9;
+const MIN_SUPPORTED_VERSION: u16 = 4;
+const ENGINE_STATE_TAG: &[u8; 4] = b"ENGN";
+
+const COMPRESSION_RAW: u8 = 0;
+const COMPRESSION_ZLIB: u8 = 1;
+const ZLIB_LEVEL: u8 = 6;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RinDocument {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    /// Microseconds since the Unix epoch.
+    pub(crate) created_at_us: i64,
+    pub(crate) updated_at_us: i64,
+    pub(crate) width: f32,
+    pub(crate) height: f32,
+    pub(crate) background_argb: u32,
+    /// `CanvasCreationLogic.index`; v4/v5 files read as single-threaded (0).
+    pub(crate) creation_logic: u8,
+    pub(crate) layers: Vec<RinLayer>,
+    pub(crate) perspective_guide: Option<RinPerspectiveGuide>,
+    /// Encoded preview image (PNG), passed through untouched.
+    pub(crate) preview: Option<Vec<u8>>,
+    /// Layer folders, referenced by `RinLayer::group`.
+    pub(crate) groups: Vec<LayerGroup>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RinLayer {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) visible: bool,
+    pub(crate) opacity: f32,
+    pub(crate) locked: bool,
+    pub(crate) clipping_mask: bool,
+    /// `CanvasLayerBlendMode.index`, the same numbering the engine uses.
+    pub(crate) blend_mode: u8,
+    pub(crate) fill_argb: Option<u32>,
+    pub(crate) bitmap: Option<RinBitmap>,
+    pub(crate) text: Option<RinText>,
+    pub(crate) alpha_locked: bool,
+    /// Id of the folder the layer sits in, or `ROOT_LAYER_GROUP`.
+    pub(crate) group: u32,
+    pub(crate) mask: Option<RinMask>,
+    pub(crate) adjustment: Option<LayerAdjustment>,
+}
+
+/// A layer mask: one coverage byte per canvas pixel, 255 revealing the layer.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RinMask {
+    pub(crate) enabled: bool,
+    pub(crate) coverage: Vec<u8>,
+}
+
+/// A straight-alpha ARGB rectangle placed at `left`/`top` on the canvas.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RinBitmap {
+    pub(crate) left: i32,
+    pub(crate) top: i32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) pixels: Vec<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RinText {
+    pub(crate) origin_x: f32,
+    pub(crate) origin_y: f32,
+    pub(crate) font_size: f32,
+    pub(crate) line_height: f32,
+    pub(crate) letter_spacing: f32,
+    pub(crate) max_width: Option<f32>,
+    pub(crate) align: u8,
+    pub(crate) orientation: u8,
+    pub(crate) antialias: bool,
+    pub(crate) stroke_enabled: bool,
+    pub(crate) stroke_width: f32,
+    pub(crate) color_argb: u32,
+    pub(crate) stroke_color_argb: u32,
+    pub(crate) font_family: String,
+    pub(crate) text: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RinPerspectiveGuide {
+    pub(crate) mode: u8,
+    pub(crate) enabled: bool,
+    pub(crate) visible: bool,
+    pub(crate) horizon_y: f32,
+    pub(crate) vp1: (f32, f32),
+    pub(crate) vp2: Option<(f32, f32)>,
+    pub(crate) vp3: Option<(f32, f32)>,
+    pub(crate) snap_angle_degrees: f32,
+}
+
+/// Layer state read back from an engine on its render thread.
+pub(crate) struct EngineLayerSnapshot {
+    pub(crate) pixels: Vec<u32>,
+    pub(crate) opacity: f32,
+    pub(crate) visible: bool,
+    pub(crate) clipping_mask: bool,
+    pub(crate) blend_mode_index: u32,
+    pub(crate) alpha_locked: bool,
+    pub(crate) group: u32,
+    pub(crate) mask: Option<RinMask>,
+    pub(crate) adjustment: Option<LayerAdjustment>,
+}
+
+pub(crate) struct EngineProjectSnapshot {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) layers: Vec<EngineLayerSnapshot>,
+    pub(crate) groups: Vec<LayerGroup>,
+}
+
+impl RinDocument {
+    pub(crate) fn new(width: u32, height: u32, now_us: i64) -> Self {
+        Self {
+            id: format!("project_{:x}", now_us),
+            name: "未命名项目".to_string(),
+            created_at_us: now_us,
+            updated_at_us: now_us,
+            width: width as f32,
+            height: height as f32,
+            background_argb: 0xFFFF_FFFF,
+            creation_logic: 0,
+            layers: Vec::new(),
+            perspective_guide: None,
+            preview: None,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Canvas size in pixels, rounded like the Dart side does.
+    pub(crate) fn canvas_size(&self) -> (u32, u32) {
+        let round = |value: f32| {
+            if value.is_finite() {
+                value.round().max(1.0) as u32
+            } else {
+                1
+            }
+        };
+        (round(self.width), round(self.height))
+    }
+
+    /// Replaces the pixels, the engine-owned layer properties and the groups
+    /// with the snapshot. Ids, names, lock flags and text blocks of existing
+    /// layers are kept; layers the document does not know yet get fresh ids.
+    pub(crate) fn apply_engine_snapshot(&mut self, snapshot: EngineProjectSnapshot, now_us: i64) {
+        self.width = snapshot.width as f32;
+        self.height = snapshot.height as f32;
+        self.updated_at_us = now_us;
+        self.groups = snapshot.groups;
+        self.layers.truncate(snapshot.layers.len());
+        for (index, layer) in snapshot.layers.into_iter().enumerate() {
+            if index == self.layers.len() {
+                self.layers.push(RinLayer::new(
+                    format!("layer_{:x}_{:x}", now_us, index),
+                    format!("图层 {}", index + 1),
+                ));
+            }
+            let entry = &mut self.layers[index];
+            entry.visible = layer.visible;
+            entry.opacity = layer.opacity;
+            entry.clipping_mask = layer.clipping_mask;
+            entry.blend_mode = layer.blend_mode_index.min(u8::MAX as u32) as u8;
+            // The engine pixels already contain any fill.
+            entry.fill_argb = None;
+            entry.bitmap = RinBitmap::from_canvas(&layer.pixels, snapshot.width, snapshot.height);
+            entry.alpha_locked = layer.alpha_locked;
+            entry.group = layer.group;
+            entry.mask = layer.mask;
+            entry.adjustment = layer.adjustment;
+        }
+    }
+
+    /// Whether anything needs the engine-state trailer.
+    fn has_engine_state(&self) -> bool {
+        !self.groups.is_empty()
+            || self.layers.iter().any(|layer| {
+                layer.alpha_locked
+                    || layer.group != ROOT_LAYER_GROUP
+                    || layer.mask.is_some()
+                    || layer.adjustment.is_some()
+            })
+    }
+}
+
+impl RinLayer {
+    pub(crate) fn new(id: String, name: String) -> Self {
+        Self {
+            id,
+            name,
+            visible: true,
+            opacity: 1.0,
+            locked: false,
+            clipping_mask: false,
+            blend_mode: 0,
+            fill_argb: None,
+            bitmap: None,
+            text: None,
+            alpha_locked: false,
+            group: ROOT_LAYER_GROUP,
+            mask: None,
+            adjustment: None,
+        }
+    }
+
+    /// Full-canvas ARGB pixels of the layer. As in the Dart loader, a bitmap
+    /// wins over the fill colour.
+    pub(crate) fn canvas_pixels(&self, width: u32, height: u32) -> Vec<u32> {
+        let Some(bitmap) = self.bitmap.as_ref() else {
+            let fill = self.fill_argb.unwrap_or(0);
+            return vec![fill; width as usize * height as usize];
+        };
+        let mut pixels = vec![0u32; width as usize * height as usize];
+        let start_x = bitmap.left.max(0) as i64;
+        let end_x = (bitmap.left as i64 + bitmap.width as i64).min(width as i64);
+        let start_y = bitmap.top.max(0) as i64;
+        let end_y = (bitmap.top as i64 + bitmap.height as i64).min(height as i64);
+        if start_x >= end_x || start_y >= end_y {
+            return pixels;
+        }
+        let copy_width = (end_x - start_x) as usize;
+        for y in start_y..end_y {
+            let src_row = (y - bitmap.top as i64) as usize * bitmap.width as usize;
+            let src = src_row + (start_x - bitmap.left as i64) as usize;
+            let dst = y as usize * width as usize + start_x as usize;
+            pixels[dst..dst + copy_width].copy_from_slice(&bitmap.pixels[src..src + copy_width]);
+        }
+        pixels
+    }
+}
+
+impl RinBitmap {
+    /// Crops a full-canvas layer to its non-transparent bounds, or `None` when
+    /// the layer is empty.
+    fn from_canvas(pixels: &[u32], width: u32, height: u32) -> Option<Self> {
+        let width = width as usize;
+        let height = height as usize;
+        if pixels.len() != width * height {
+            return None;
+        }
+        let mut min_x = width;
+        let mut min_y = height;
+        let mut max_x = 0;
+        let mut max_y = 0;
+        for (y, row) in pixels.chunks_exact(width).enumerate() {
+            let Some(first) = row.iter().position(|&p| p >> 24 != 0) else {
+                continue;
+            };
+            let last = row.iter().rposition(|&p| p >> 24 != 0).unwrap_or(first);
+            min_x = min_x.min(first);
+            max_x = max_x.max(last);
+            min_y = min_y.min(y);
+            max_y = y;
+        }
+        if min_y == height {
+            return None;
+        }
+        let crop_width = max_x - min_x + 1;
+        let crop_height = max_y - min_y + 1;
+        let mut cropped = Vec::with_capacity(crop_width * crop_height);
+        for y in min_y..=max_y {
+            let row = y * width;
+            cropped.extend_from_slice(&pixels[row + min_x..row + max_x + 1]);
+        }
+        Some(Self {
+            left: min_x as i32,
+            top: min_y as i32,
+            width: crop_width as u32,
+            height: crop_height as u32,
+            pixels: cropped,
+        })
+    }
+}
+
+pub(crate) fn encode_rin(document: &RinDocument) -> Vec<u8> {
+    let mut out = ByteWriter::default();
+    out.bytes(MAGIC);
+    out.u16(VERSION);
+
+    out.string(&document.id);
+    out.string(&document.name);
+    out.i64(document.created_at_us);
+    out.i64(document.updated_at_us);
+    out.f32(document.width);
+    out.f32(document.height);
+    out.u32(document.background_argb);
+    out.u8(document.creation_logic);
+
+    out.u32(document.layers.len() as u32);
+    for layer in &document.layers {
+        out.string(&layer.id);
+        out.string(&layer.name);
+        out.bool(layer.visible);
+        out.f32(layer.opacity);
+        out.bool(layer.locked);
+        out.bool(layer.clipping_mask);
+        out.u8(layer.blend_mode);
+
+        out.bool(layer.fill_argb.is_some());
+        if let Some(fill) = layer.fill_argb {
+            out.u32(fill);
+        }
+
+        out.bool(layer.bitmap.is_some());
+        if let Some(bitmap) = layer.bitmap.as_ref() {
+            out.i32(bitmap.left);
+            out.i32(bitmap.top);
+            out.u32(bitmap.width);
+            out.u32(bitmap.height);
+            out.blob(&convert_pixels_to_rgba(bitmap.pixels.clone()));
+        }
+
+        out.bool(layer.text.is_some());
+        if let Some(text) = layer.text.as_ref() {
+            write_text(&mut out, text);
+        }
+    }
+
+    out.bool(document.perspective_guide.is_some());
+    if let Some(guide) = document.perspective_guide.as_ref() {
+        write_perspective_guide(&mut out, guide);
+    }
+
+    match document.preview.as_deref() {
+        Some(preview) if !preview.is_empty() => {
+            out.bool(true);
+            out.blob(preview);
+        }
+        _ => out.bool(false),
+    }
+
+    if document.has_engine_state() {
+        write_engine_state(&mut out, document);
+    }
+    out.buf
+}
+
+pub(crate) fn decode_rin(bytes: &[u8]) -> Result<RinDocument, String> {
+    let mut input = ByteReader::new(bytes);
+    if input.take(MAGIC.len())? != MAGIC {
+        return Err("rin: bad magic".to_string());
+    }
+    let version = input.u16()?;
+    if !(MIN_SUPPORTED_VERSION..=VERSION).contains(&version) {
+        return Err(format!("rin: unsupported version {version}"));
+    }
+
+    let id = input.string()?;
+    let name = input.string()?;
+    let created_at_us = input.i64()?;
+    let updated_at_us = input.i64()?;
+    let width = input.f32()?;
+    let height = input.f32()?;
+    let background_argb = input.u32()?;
+    let creation_logic = if version >= 6 { input.u8()? } else { 0 };
+
+    let layer_count = input.u32()?;
+    let mut layers = Vec::new();
+    for _ in 0..layer_count {
+        let mut layer = RinLayer::new(input.string()?, input.string()?);
+        layer.visible = input.bool()?;
+        layer.opacity = input.f32()?;
+        layer.locked = input.bool()?;
+        layer.clipping_mask = input.bool()?;
+        layer.blend_mode = input.u8()?;
+        if input.bool()? {
+            layer.fill_argb = Some(input.u32()?);
+        }
+        if input.bool()? {
+            let (left, top) = if version >= 5 {
+                (input.i32()?, input.i32()?)
+            } else {
+                (0, 0)
+            };
+            let bitmap_width = input.u32()?;
+            let bitmap_height = input.u32()?;
+            let rgba = input.blob()?;
+            let expected = bitmap_width as usize * bitmap_height as usize * 4;
+            if rgba.len() != expected {
+                return Err(format!(
+                    "rin: bitmap of layer {} holds {} bytes, expected {expected}",
+                    layer.id,
+                    rgba.len()
+                ));
+            }
+            layer.bitmap = Some(RinBitmap {
+                left,
+                top,
+                width: bitmap_width,
+                height: bitmap_height,
+                pixels: premultiplied_rgba_to_pixels(&rgba),
+            });
+        }
+        if version >= 7 && input.bool()? {
+            layer.text = Some(read_text(&mut input, version)?);
+        }
+        layers.push(layer);
+    }
+
+    let perspective_guide = if version >= 9 && input.bool()? {
+        Some(read_perspective_guide(&mut input)?)
+    } else {
+        None
+    };
+    let preview = if input.bool()? {
+        Some(input.blob()?)
+    } else {
+        None
+    };
+
+    let mut document = RinDocument {
+        id,
+        name,
+        created_at_us,
+        updated_at_us,
+        width,
+        height,
+        background_argb,
+        creation_logic,
+        layers,
+        perspective_guide,
+        preview,
+        groups: Vec::new(),
+    };
+    if !input.is_empty() {
+        read_engine_state(&mut input, &mut document)?;
+    }
+    Ok(document)
+}
+
+/// Inverse of `convert_pixels_to_rgba`: the stored bitmaps are premultiplied.
+fn premultiplied_rgba_to_pixels(rgba: &[u8]) -> Vec<u32> {
+    rgba.chunks_exact(4)
+        .map(|texel| {
+            let a = texel[3] as u32;
+            if a == 0 {
+                return 0;
+            }
+            let channel = |value: u8| {
+                if a == 255 {
+                    value as u32
+                } else {
+                    ((value as u32 * 255 + a / 2) / a).min(255)
+                }
+            };
+            (a << 24) | (channel(texel[0]) << 16) | (channel(texel[1]) << 8) | channel(texel[2])
+        })
+        .collect()
+}
+
+fn write_engine_state(out: &mut ByteWriter, document: &RinDocument) {
+    out.bytes(ENGINE_STATE_TAG);
+    out.u32(document.groups.len() as u32);
+    for group in &document.groups {
+        out.u32(group.id);
+        out.u32(group.parent);
+        out.f32(group.opacity);
+        out.bool(group.visible);
+        out.u32(group.blend_mode_index);
+        out.bool(group.pass_through);
+        out.bool(group.collapsed);
+    }
+    // One record per layer, in the order of the layer records.
+    for layer in &document.layers {
+        out.bool(layer.alpha_locked);
+        out.u32(layer.group);
+        out.bool(layer.mask.is_some());
+        if let Some(mask) = layer.mask.as_ref() {
+            out.bool(mask.enabled);
+            out.blob(&mask.coverage);
+        }
+        out.bool(layer.adjustment.is_some());
+        if let Some(adjustment) = layer.adjustment {
+            out.u32(adjustment.filter_type);
+            for param in adjustment.params {
+                out.f32(param);
+            }
+        }
+    }
+}
+
+fn read_engine_state(input: &mut ByteReader, document: &mut RinDocument) -> Result<(), String> {
+    if input.take(ENGINE_STATE_TAG.len())? != ENGINE_STATE_TAG {
+        return Err("rin: unknown data after the preview".to_string());
+    }
+    let group_count = input.u32()?;
+    for _ in 0..group_count {
+        document.groups.push(LayerGroup {
+            id: input.u32()?,
+            parent: input.u32()?,
+            opacity: input.f32()?,
+            visible: input.bool()?,
+            blend_mode_index: input.u32()?,
+            pass_through: input.bool()?,
+            collapsed: input.bool()?,
+        });
+    }
+    let (width, height) = document.canvas_size();
+    let canvas_len = width as usize * height as usize;
+    for layer in document.layers.iter_mut() {
+        layer.alpha_locked = input.bool()?;
+        layer.group = input.u32()?;
+        if input.bool()? {
+            let enabled = input.bool()?;
+            let coverage = input.blob()?;
+            if coverage.len() != canvas_len {
+                return Err(format!(
+                    "rin: mask of layer {} holds {} bytes, expected {canvas_len}",
+                    layer.id,
+                    coverage.len()
+                ));
+            }
+            layer.mask = Some(RinMask { enabled, coverage });
+        }
+        if input.bool()? {
+            let filter_type = input.u32()?;
+            let mut params = [0.0; 4];
+            for param in params.iter_mut() {
+                *param = input.f32()?;
+            }
+            layer.adjustment = Some(LayerAdjustment {
+                filter_type,
+                params,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn write_text(out: &mut ByteWriter, text: &RinText) {
+    out.f32(text.origin_x);
+    out.f32(text.origin_y);
+    out.f32(text.font_size);
+    out.f32(text.line_height);
+    out.f32(text.letter_spacing);
+    out.bool(text.max_width.is_some());
+    if let Some(max_width) = text.max_width {
+        out.f32(max_width);
+    }
+    out.u8(text.align);
+    out.u8(text.orientation);
+    out.bool(text.antialias);
+    out.bool(text.stroke_enabled);
+    out.f32(text.stroke_width);
+    out.u32(text.color_argb);
+    out.u32(text.stroke_color_argb);
+    out.string(&text.font_family);
+    out.string(&text.text);
+}
+
+fn read_text(input: &mut ByteReader, version: u16) -> Result<RinText, String> {
+    let origin_x = input.f32()?;
+    let origin_y = input.f32()?;
+    let font_size = input.f32()?;
+    let line_height = input.f32()?;
+    // Before v8 this field was a horizontal offset folded into the origin.
+    let spacing_field = input.f32()?;
+    let max_width = if input.bool()? {
+        Some(input.f32()?)
+    } else {
+        None
+    };
+    let (origin_x, letter_spacing) = if version >= 8 {
+        (origin_x, spacing_field)
+    } else {
+        (origin_x + spacing_field, 0.0)
+    };
+    Ok(RinText {
+        origin_x,
+        origin_y,
+        font_size,
+        line_height,
+        letter_spacing,
+        max_width,
+        align: input.u8()?,
+        orientation: input.u8()?,
+        antialias: input.bool()?,
+        stroke_enabled: input.bool()?,
+        stroke_width: input.f32()?,
+        color_argb: input.u32()?,
+        stroke_color_argb: input.u32()?,
+        font_family: input.string()?,
+        text: input.string()?,
+    })
+}
+
+fn write_perspective_guide(out: &mut ByteWriter, guide: &RinPerspectiveGuide) {
+    out.u8(guide.mode);
+    out.bool(guide.enabled);
+    out.bool(guide.visible);
+    out.f32(guide.horizon_y);
+    out.f32(guide.vp1.0);
+    out.f32(guide.vp1.1);
+    for point in [guide.vp2, guide.vp3] {
+        out.bool(point.is_some());
+        if let Some((x, y)) = point {
+            out.f32(x);
+            out.f32(y);
+        }
+    }
+    out.f32(guide.snap_angle_degrees);
+}
+
+fn read_perspective_guide(input: &mut ByteReader) -> Result<RinPerspectiveGuide, String> {
+    let mode = input.u8()?;
+    let enabled = input.bool()?;
+    let visible = input.bool()?;
+    let horizon_y = input.f32()?;
+    let vp1 = (input.f32()?, input.f32()?);
+    let vp2 = if input.bool()? {
+        Some((input.f32()?, input.f32()?))
+    } else {
+        None
+    };
+    let vp3 = if input.bool()? {
+        Some((input.f32()?, input.f32()?))
+    } else {
+        None
+    };
+    Ok(RinPerspectiveGuide {
+        mode,
+        enabled,
+        visible,
+        horizon_y,
+        vp1,
+        vp2,
+        vp3,
+        snap_angle_degrees: input.f32()?,
+    })
+}
+
+#[derive(Default)]
+struct ByteWriter {
+    buf: Vec<u8>,
+}
+
+impl ByteWriter {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    fn i64(&mut self, value: i64) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes(value.as_bytes());
+    }
+
+    /// Compression byte, length and data; zlib only when it saves space.
+    fn blob(&mut self, raw: &[u8]) {
+        let compressed = compress_to_vec_zlib(raw, ZLIB_LEVEL);
+        let (compression, stored) = if compressed.len() < raw.len() {
+            (COMPRESSION_ZLIB, compressed.as_slice())
+        } else {
+            (COMPRESSION_RAW, raw)
+        };
+        self.u8(compression);
+        self.u32(stored.len() as u32);
+        self.bytes(stored);
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.offset >= self.bytes.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| format!("rin: truncated at byte {}", self.offset))?;
+        let slice = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, String> {
+        Ok(i64::from_be_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_be_bytes(self.array()?))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|err| format!("rin: invalid string: {err}"))
+    }
+
+    fn blob(&mut self) -> Result<Vec<u8>, String> {
+        let compression = self.u8()?;
+        let len = self.u32()? as usize;
+        let stored = self.take(len)?;
+        match compression {
+            COMPRESSION_ZLIB => decompress_to_vec_zlib(stored)
+                .map_err(|err| format!("rin: zlib data corrupt: {err:?}")),
+            _ => Ok(stored.to_vec()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Mutex};
+    use std::thread::{self, ThreadId};
+
+    use super::*;
+    use crate::canvas_engine::cpu_engine::create_cpu_engine;
+    use crate::canvas_engine::engine::tests::{stop_engine, test_engines};
+    use crate::canvas_engine::engine::{create_engine, lookup_engine, EngineBackend, EngineCommand};
+    use crate::canvas_engine::ffi::{engine_encode_rin, engine_free_rin, load_rin_document};
+    use crate::gpu::filter_renderer::FILTER_INVERT;
+
+    fn read_project(handle: u64) -> EngineProjectSnapshot {
+        let entry = lookup_engine(handle).unwrap();
+        let (reply, rx) = mpsc::channel();
+        entry.cmd_tx.send(EngineCommand::ReadProject { reply }).unwrap();
+        rx.recv().unwrap().unwrap()
+    }
+
+    #[test]
+    fn engine_snapshot_round_trips_through_rin() {
+        let mut pixels = vec![0u32; 4 * 3];
+        pixels[5] = 0xFF12_3456;
+        pixels[6] = 0x80FF_0000;
+        let snapshot = EngineProjectSnapshot {
+            width: 4,
+            height: 3,
+            layers: vec![
+                EngineLayerSnapshot {
+                    pixels: vec![0xFFFF_FFFF; 4 * 3],
+                    opacity: 1.0,
+                    visible: true,
+                    clipping_mask: false,
+                    blend_mode_index: 0,
+                    alpha_locked: false,
+                    group: ROOT_LAYER_GROUP,
+                    mask: None,
+                    adjustment: None,
+                },
+                EngineLayerSnapshot {
+                    pixels: pixels.clone(),
+                    opacity: 0.5,
+                    visible: false,
+                    clipping_mask: true,
+                    blend_mode_index: 12,
+                    alpha_locked: false,
+                    group: ROOT_LAYER_GROUP,
+                    mask: None,
+                    adjustment: None,
+                },
+            ],
+            groups: Vec::new(),
+        };
+        let mut document = RinDocument::new(1, 1, 1_000);
+        document
+            .layers
+            .push(RinLayer::new("bg".into(), "背景".into()));
+        document.apply_engine_snapshot(snapshot, 2_000);
+
+        let layer = &document.layers[1];
+        let bitmap = layer.bitmap.as_ref().unwrap();
+        assert_eq!(
+            (bitmap.left, bitmap.top, bitmap.width, bitmap.height),
+            (1, 1, 2, 1)
+        );
+        assert_eq!(document.layers[0].id, "bg");
+
+        let decoded = decode_rin(&encode_rin(&document)).unwrap();
+        assert_eq!(decoded.canvas_size(), (4, 3));
+        assert_eq!(decoded.layers.len(), 2);
+        let layer = &decoded.layers[1];
+        assert_eq!(
+            (layer.opacity, layer.visible, layer.clipping_mask),
+            (0.5, false, true)
+        );
+        assert_eq!(layer.blend_mode, 12);
+        // Translucent texels go through premultiplied RGBA and come back intact.
+        let restored = layer.canvas_pixels(4, 3);
+        assert_eq!(restored[5], 0xFF12_3456);
+        assert_eq!(restored[6], 0x80FF_0000);
+        assert_eq!(restored.iter().filter(|&&p| p != 0).count(), 2);
+    }
+
+    #[test]
+    fn masks_groups_and_adjustments_round_trip_through_rin() {
+        for handle in test_engines(8, 4) {
+            masks_groups_and_adjustments_round_trip(handle);
+        }
+    }
+
+    fn masks_groups_and_adjustments_round_trip(handle: u64) {
+        let entry = lookup_engine(handle).unwrap();
+        let send = |cmd: EngineCommand| entry.cmd_tx.send(cmd).unwrap();
+        let create_group = |parent_group_id: u32| {
+            let (reply, rx) = mpsc::channel();
+            send(EngineCommand::CreateLayerGroup {
+                parent_group_id,
+                reply,
+            });
+            rx.recv().unwrap()
+        };
+        send(EngineCommand::ResetCanvasWithLayers {
+            layer_count: 3,
+            background_color_argb: 0xFFFF_FFFF,
+        });
+        let outer = create_group(ROOT_LAYER_GROUP);
+        let inner = create_group(outer);
+        send(EngineCommand::SetLayerGroupOpacity {
+            group_id: inner,
+            opacity: 0.25,
+        });
+        send(EngineCommand::SetLayerGroupPassThrough {
+            group_id: inner,
+            pass_through: false,
+        });
+        send(EngineCommand::SetLayerGroupCollapsed {
+            group_id: outer,
+            collapsed: true,
+        });
+        send(EngineCommand::MoveLayerToGroup {
+            layer_index: 1,
+            group_id: inner,
+        });
+        send(EngineCommand::MoveLayerToGroup {
+            layer_index: 2,
+            group_id: outer,
+        });
+        send(EngineCommand::SetLayerAlphaLock {
+            layer_index: 1,
+            locked: true,
+        });
+        send(EngineCommand::CreateLayerMask { layer_index: 1 });
+        let coverage: Vec<u8> = (0..32).map(|value| value * 8).collect();
+        let (reply, written) = mpsc::channel();
+        send(EngineCommand::WriteLayerMask {
+            layer_index: 1,
+            coverage: coverage.clone(),
+            reply,
+        });
+        assert!(written.recv().unwrap());
+        send(EngineCommand::CreateLayerMask { layer_index: 2 });
+        send(EngineCommand::SetLayerMaskEnabled {
+            layer_index: 2,
+            enabled: false,
+        });
+        send(EngineCommand::SetLayerAdjustment {
+            layer_index: 2,
+            filter_type: FILTER_INVERT,
+            param0: 0.0,
+            param1: 0.0,
+            param2: 0.0,
+            param3: 0.0,
+        });
+
+        let mut document = RinDocument::new(8, 4, 1_000);
+        document.apply_engine_snapshot(read_project(handle), 2_000);
+        let backend = entry.backend;
+        stop_engine(handle);
+        assert_eq!(document.groups.len(), 2);
+        assert_eq!(document.groups[1].parent, outer);
+        let masked = &document.layers[1];
+        assert!(masked.alpha_locked);
+        assert_eq!(masked.group, inner);
+        assert_eq!(
+            masked.mask,
+            Some(RinMask {
+                enabled: true,
+                coverage,
+            })
+        );
+        assert_eq!(document.layers[2].mask.as_ref().map(|mask| mask.enabled), Some(false));
+
+        let decoded = decode_rin(&encode_rin(&document)).unwrap();
+        assert_eq!(decoded, document, "{backend:?}");
+
+        // Loading the file into a fresh engine gives back the same project.
+        let handle = match backend {
+            EngineBackend::Gpu => create_engine(8, 4),
+            EngineBackend::Cpu => create_cpu_engine(8, 4),
+        }
+        .unwrap();
+        load_rin_document(handle, &decoded).unwrap();
+        let mut reloaded = RinDocument::new(8, 4, 1_000);
+        reloaded.apply_engine_snapshot(read_project(handle), 2_000);
+        stop_engine(handle);
+        assert_eq!(reloaded, document, "{backend:?}");
+    }
+
+    type Encoded = (u64, Option<Vec<u8>>, ThreadId);
+    static ENCODED: Mutex<Option<mpsc::Sender<Encoded>>> = Mutex::new(None);
+
+    extern "C" fn on_encoded(request_id: u64, bytes: *mut u8, len: usize) {
+        let data = (!bytes.is_null()).then(|| {
+            let data = unsafe { std::slice::from_raw_parts(bytes, len) }.to_vec();
+            engine_free_rin(bytes, len);
+            data
+        });
+        if let Some(tx) = ENCODED.lock().unwrap().as_ref() {
+            let _ = tx.send((request_id, data, thread::current().id()));
+        }
+    }
+
+    #[test]
+    fn encode_runs_off_the_calling_thread() {
+        let (tx, rx) = mpsc::channel();
+        *ENCODED.lock().unwrap() = Some(tx);
+        let handle = create_cpu_engine(4, 2).unwrap();
+        let queued = engine_encode_rin(handle, std::ptr::null(), 0, 7, Some(on_encoded));
+        assert_eq!(queued, 1);
+        let (request_id, bytes, thread) = rx.recv().unwrap();
+        stop_engine(handle);
+        assert_eq!(request_id, 7);
+        assert_ne!(thread, thread::current().id());
+        let document = decode_rin(&bytes.unwrap()).unwrap();
+        assert_eq!(document.canvas_size(), (4, 2));
+    }
+}
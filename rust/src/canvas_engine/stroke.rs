@@ -1,9 +1,19 @@
+use crate::api::gpu_composite::{hsv_to_rgb, rgb_to_hsv};
+use crate::cpu_brush::{
+    dual_tip_dabs, BrushPoint, DualTipSettings, GrainSettings, MixMode, MixSettings, WetSettings,
+};
 use crate::gpu::brush_renderer::{
     BrushRenderer, BrushShape, Color, Point2D, PointRotation, MAX_POINTS,
 };
 use crate::gpu::debug::{self, LogLevel};
 
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use super::assist::{AssistProjector, StrokeAssist};
+use super::pressure::{PressureCurves, PressureTarget};
+use super::symmetry::Symmetry;
 use super::types::EnginePoint;
+use super::velocity::{PressureSimulator, SimulatedPressure};
 
 #[derive(Clone, Copy, Debug)]
 pub(crate) struct EngineBrushSettings {
@@ -35,6 +45,260 @@ pub(crate) struct EngineBrushSettings {
     pub(crate) smoothing_mode: u8,
     pub(crate) stabilizer_strength: f32,
     pub(crate) custom_mask_enabled: bool,
+    pub(crate) dynamics: PenDynamics,
+    pub(crate) pressure_curves: PressureCurves,
+    pub(crate) simulated_pressure: SimulatedPressure,
+    pub(crate) spring: SpringSettings,
+    pub(crate) assist: StrokeAssist,
+    pub(crate) symmetry: Symmetry,
+    pub(crate) grain: GrainSettings,
+    pub(crate) mix: MixSettings,
+    pub(crate) wet: WetSettings,
+    pub(crate) dual: DualTipSettings,
+    pub(crate) color: ColorDynamics,
+}
+
+/// The virtual pen tip of the spring stabilizer: a mass pulled towards the
+/// cursor by a spring and slowed by a damper. Units are pixels and seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct SpringSettings {
+    pub(crate) mass: f32,
+    pub(crate) stiffness: f32,
+    pub(crate) damping: f32,
+}
+
+impl Default for SpringSettings {
+    fn default() -> Self {
+        // Critically damped, settling in roughly a quarter of a second.
+        Self {
+            mass: 1.0,
+            stiffness: 400.0,
+            damping: 40.0,
+        }
+    }
+}
+
+// Keep `omega * dt` and `damping / mass * dt` below 1 so the fixed-step
+// integration cannot blow up, whatever the artist dials in.
+const SPRING_MAX_OMEGA: f32 = 1.0 / SPRING_STEP_SECONDS;
+const SPRING_MAX_DAMPING_RATE: f32 = 1.0 / SPRING_STEP_SECONDS;
+
+impl SpringSettings {
+    pub(crate) fn sanitize(&mut self) {
+        let defaults = Self::default();
+        let finite_or = |value: f32, fallback: f32| {
+            if value.is_finite() {
+                value
+            } else {
+                fallback
+            }
+        };
+        self.mass = finite_or(self.mass, defaults.mass).clamp(0.05, 50.0);
+        self.stiffness = finite_or(self.stiffness, defaults.stiffness)
+            .clamp(1.0, self.mass * SPRING_MAX_OMEGA * SPRING_MAX_OMEGA);
+        self.damping = finite_or(self.damping, defaults.damping)
+            .clamp(0.0, self.mass * SPRING_MAX_DAMPING_RATE);
+    }
+}
+
+/// How pen tilt and rotation shape the dabs; everything is off by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct PenDynamics {
+    /// `-1..=1`: positive grows the dab as the pen tilts, negative shrinks it.
+    pub(crate) tilt_size: f32,
+    /// `-1..=1`: positive fades the dab as the pen tilts, negative as it
+    /// stands upright.
+    pub(crate) tilt_opacity: f32,
+    /// Non-round tips follow the direction the pen leans, like a flat nib.
+    pub(crate) azimuth_angle: bool,
+    /// Non-round tips turn with the barrel.
+    pub(crate) barrel_angle: bool,
+}
+
+// Size change at full tilt with `tilt_size` at +-1, in powers of two.
+const TILT_SIZE_OCTAVES: f32 = 1.5;
+
+impl PenDynamics {
+    pub(crate) fn sanitize(&mut self) {
+        let amount = |value: f32| if value.is_finite() { value.clamp(-1.0, 1.0) } else { 0.0 };
+        self.tilt_size = amount(self.tilt_size);
+        self.tilt_opacity = amount(self.tilt_opacity);
+    }
+
+    fn size_factor(&self, pen: PenState) -> f32 {
+        if self.tilt_size == 0.0 {
+            return 1.0;
+        }
+        (self.tilt_size * tilt_amount(pen) * TILT_SIZE_OCTAVES).exp2()
+    }
+
+    fn uses_opacity(&self) -> bool {
+        self.tilt_opacity != 0.0
+    }
+
+    fn opacity(&self, pen: PenState) -> f32 {
+        let tilt = tilt_amount(pen);
+        if self.tilt_opacity >= 0.0 {
+            1.0 - self.tilt_opacity * tilt
+        } else {
+            1.0 + self.tilt_opacity * (1.0 - tilt)
+        }
+    }
+
+    fn uses_angle(&self) -> bool {
+        self.azimuth_angle || self.barrel_angle
+    }
+
+    /// Stamp angle contributed by the pen, in radians.
+    pub(crate) fn angle(&self, pen: PenState) -> f32 {
+        let mut angle = 0.0;
+        if self.azimuth_angle {
+            angle += pen.azimuth;
+        }
+        if self.barrel_angle {
+            angle += pen.rotation;
+        }
+        angle
+    }
+}
+
+fn tilt_amount(pen: PenState) -> f32 {
+    (pen.tilt / FRAC_PI_2).clamp(0.0, 1.0)
+}
+
+/// Random spread of the brush colour, each amount from 0 to 1. Hue 1 swings
+/// half way round the colour wheel either side; opacity 1 may fade a dab out.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct ColorJitter {
+    pub(crate) hue: f32,
+    pub(crate) saturation: f32,
+    pub(crate) value: f32,
+    pub(crate) opacity: f32,
+}
+
+impl ColorJitter {
+    fn sanitize(&mut self) {
+        let amount = |value: f32| if value.is_finite() { value.clamp(0.0, 1.0) } else { 0.0 };
+        self.hue = amount(self.hue);
+        self.saturation = amount(self.saturation);
+        self.value = amount(self.value);
+        self.opacity = amount(self.opacity);
+    }
+
+    fn uses_color(&self) -> bool {
+        self.hue > 0.0 || self.saturation > 0.0 || self.value > 0.0
+    }
+
+    /// Hue, saturation and value offsets plus an opacity factor.
+    fn sample(&self, at: Point2D, seed: u32, salt: u32) -> ([f32; 3], f32) {
+        let signed = |k: u32| brush_random_unit(at, seed, salt.wrapping_add(k)) * 2.0 - 1.0;
+        let offsets = [
+            signed(0) * self.hue * 0.5,
+            signed(1) * self.saturation,
+            signed(2) * self.value,
+        ];
+        let opacity = 1.0 - brush_random_unit(at, seed, salt.wrapping_add(3)) * self.opacity;
+        (offsets, opacity)
+    }
+}
+
+/// What pulls a dab from the brush colour towards the background colour.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum ColorMixSource {
+    #[default]
+    Off,
+    /// Full pressure paints the brush colour, no pressure the background.
+    Pressure,
+    Random,
+}
+
+impl ColorMixSource {
+    pub(crate) fn from_index(index: u32) -> Self {
+        match index {
+            1 => Self::Pressure,
+            2 => Self::Random,
+            _ => Self::Off,
+        }
+    }
+}
+
+/// Colour dynamics: jitter drawn per dab and once per stroke, and mixing
+/// with a background colour. Randomness is keyed on dab positions (and the
+/// stroke's first point) with `rotation_seed`, so replays repeat it. Mixing
+/// and watercolour brushes keep their own colour but take the opacity.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct ColorDynamics {
+    pub(crate) dab: ColorJitter,
+    pub(crate) stroke: ColorJitter,
+    /// Only the RGB is used; opacity stays the brush colour's.
+    pub(crate) background_argb: u32,
+    pub(crate) background_source: ColorMixSource,
+    pub(crate) background_amount: f32,
+}
+
+const DAB_COLOR_SALT: u32 = 0x636f_6c00;
+const STROKE_COLOR_SALT: u32 = 0x636f_6c10;
+
+impl ColorDynamics {
+    pub(crate) fn sanitize(&mut self) {
+        self.dab.sanitize();
+        self.stroke.sanitize();
+        self.background_amount = if self.background_amount.is_finite() {
+            self.background_amount.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+    }
+
+    fn uses_color(&self) -> bool {
+        self.dab.uses_color()
+            || self.stroke.uses_color()
+            || (self.background_source != ColorMixSource::Off && self.background_amount > 0.0)
+    }
+
+    fn uses_opacity(&self) -> bool {
+        self.dab.opacity > 0.0 || self.stroke.opacity > 0.0
+    }
+
+    /// Opacity factor of a dab at `at` in the stroke starting at `origin`.
+    fn opacity(&self, origin: Point2D, at: Point2D, seed: u32) -> f32 {
+        let (_, stroke) = self.stroke.sample(origin, seed, STROKE_COLOR_SALT);
+        let (_, dab) = self.dab.sample(at, seed, DAB_COLOR_SALT);
+        stroke * dab
+    }
+
+    /// Straight ARGB of a dab; the alpha is `color_argb`'s.
+    fn color(
+        &self,
+        color_argb: u32,
+        origin: Point2D,
+        at: Point2D,
+        pen: PenState,
+        seed: u32,
+    ) -> u32 {
+        let channel = |argb: u32, shift: u32| ((argb >> shift) & 0xFF) as f32 / 255.0;
+        let rgb = |argb: u32| [channel(argb, 16), channel(argb, 8), channel(argb, 0)];
+        let mut color = rgb(color_argb);
+        let t = match self.background_source {
+            ColorMixSource::Off => 0.0,
+            ColorMixSource::Pressure => 1.0 - pen.pressure.clamp(0.0, 1.0),
+            ColorMixSource::Random => brush_random_unit(at, seed, DAB_COLOR_SALT + 4),
+        } * self.background_amount;
+        for (c, bg) in color.iter_mut().zip(rgb(self.background_argb)) {
+            *c += (bg - *c) * t;
+        }
+        let (stroke, _) = self.stroke.sample(origin, seed, STROKE_COLOR_SALT);
+        let (dab, _) = self.dab.sample(at, seed, DAB_COLOR_SALT);
+        let (h, s, v) = rgb_to_hsv(color[0], color[1], color[2]);
+        let h = h + stroke[0] + dab[0];
+        let [r, g, b] = hsv_to_rgb(
+            h - h.floor(),
+            (s + stroke[1] + dab[1]).clamp(0.0, 1.0),
+            (v + stroke[2] + dab[2]).clamp(0.0, 1.0),
+        );
+        let byte = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u32;
+        (color_argb & 0xFF00_0000) | (byte(r) << 16) | (byte(g) << 8) | byte(b)
+    }
 }
 
 impl Default for EngineBrushSettings {
@@ -68,6 +332,17 @@ impl Default for EngineBrushSettings {
             smoothing_mode: 1,
             stabilizer_strength: 0.0,
             custom_mask_enabled: false,
+            dynamics: PenDynamics::default(),
+            pressure_curves: PressureCurves::default(),
+            simulated_pressure: SimulatedPressure::default(),
+            spring: SpringSettings::default(),
+            assist: StrokeAssist::default(),
+            symmetry: Symmetry::default(),
+            grain: GrainSettings::default(),
+            mix: MixSettings::default(),
+            wet: WetSettings::default(),
+            dual: DualTipSettings::default(),
+            color: ColorDynamics::default(),
         }
     }
 }
@@ -140,30 +415,89 @@ impl EngineBrushSettings {
         } else {
             self.stabilizer_strength = self.stabilizer_strength.clamp(0.0, 1.0);
         }
-        if self.smoothing_mode > 3 {
+        if self.smoothing_mode > 4 {
             self.smoothing_mode = 1;
         }
         self.antialias_level = self.antialias_level.clamp(0, 9);
+        self.dynamics.sanitize();
+        self.spring.sanitize();
         self.color_argb = apply_flow_to_argb(self.color_argb, self.flow);
     }
 
     pub(crate) fn radius_from_pressure(&self, pressure: f32) -> f32 {
-        brush_radius_from_pressure(pressure, self.base_radius, self.use_pressure)
+        brush_radius_from_pressure(
+            pressure,
+            self.base_radius,
+            self.uses_pressure(),
+            &self.pressure_curves,
+        )
+    }
+
+    /// Simulated pressure counts as pressure even when the device's is off.
+    fn uses_pressure(&self) -> bool {
+        self.use_pressure || self.simulated_pressure.is_active()
+    }
+
+    /// Output of the curve for `target`, or `None` when pressure is off or
+    /// the brush has no curve for it.
+    pub(crate) fn pressure_response(&self, target: PressureTarget, pressure: f32) -> Option<f32> {
+        if !self.uses_pressure() {
+            return None;
+        }
+        self.pressure_curves.response(target, pressure)
     }
 
     pub(crate) fn softness(&self) -> f32 {
         (1.0 - self.hardness).clamp(0.0, 1.0)
     }
 
+    /// Softness for one batch of dabs. The renderer takes a single softness
+    /// per draw, so the hardness curve follows the batch's mean pressure.
+    pub(crate) fn stroke_softness(&self, emitted: &[(Point2D, PenState)]) -> f32 {
+        if emitted.is_empty() || !self.pressure_curves.has(PressureTarget::Hardness) {
+            return self.softness();
+        }
+        let total: f32 = emitted.iter().map(|(_, pen)| pen.pressure).sum();
+        let mean = total / emitted.len() as f32;
+        match self.pressure_response(PressureTarget::Hardness, mean) {
+            Some(factor) => (1.0 - self.hardness * factor).clamp(0.0, 1.0),
+            None => self.softness(),
+        }
+    }
+
     pub(crate) fn supports_rotation(&self) -> bool {
         self.custom_mask_enabled || !matches!(self.shape, BrushShape::Circle)
     }
 
+    /// Mixing brushes paint with colour picked up from the pre-stroke layer,
+    /// so the engine has to capture it. Erasing never mixes.
+    pub(crate) fn mixing(&self) -> bool {
+        self.mix.mode != MixMode::Off && !self.erase
+    }
+
+    /// Watercolour strokes pool in the stroke mask and are rebuilt from the
+    /// pre-stroke layer; mixing brushes take precedence.
+    pub(crate) fn watercolor(&self) -> bool {
+        self.wet.enabled && !self.erase && !self.mixing()
+    }
+
+    /// Inner radius scale of a hollow stroke, or `None` when the stroke
+    /// paints solid. Erasers, mixing and watercolour brushes never hollow out.
+    pub(crate) fn hollow_core(&self) -> Option<f32> {
+        let hollow = self.hollow_enabled && !self.erase && !self.mixing() && !self.watercolor();
+        (hollow && self.hollow_ratio > 0.0001).then_some(self.hollow_ratio)
+    }
+
+    pub(crate) fn uses_pen_angle(&self) -> bool {
+        self.dynamics.uses_angle() && self.supports_rotation()
+    }
+
     fn smoothing_mode(&self) -> SmoothingMode {
         match self.smoothing_mode {
             1 => SmoothingMode::Simple,
             2 => SmoothingMode::Weighted,
             3 => SmoothingMode::Stabilizer,
+            4 => SmoothingMode::Spring,
             _ => SmoothingMode::None,
         }
     }
@@ -175,17 +509,63 @@ enum SmoothingMode {
     Simple,
     Weighted,
     Stabilizer,
+    Spring,
 }
 
 #[derive(Clone, Copy, Debug)]
 struct StrokeSample {
     pos: Point2D,
-    pressure: f32,
+    pen: PenState,
     timestamp_us: u64,
 }
 
+/// Pen axes that travel with every stroke position, see [`EnginePoint`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PenState {
+    pub(crate) pressure: f32,
+    pub(crate) tilt: f32,
+    pub(crate) azimuth: f32,
+    pub(crate) rotation: f32,
+}
+
+impl PenState {
+    pub(crate) fn from_pressure(pressure: f32) -> Self {
+        Self {
+            pressure,
+            tilt: 0.0,
+            azimuth: 0.0,
+            rotation: 0.0,
+        }
+    }
+
+    fn from_point(point: &EnginePoint) -> Self {
+        let finite_or_zero = |value: f32| if value.is_finite() { value } else { 0.0 };
+        Self {
+            pressure: finite_or_zero(point.pressure).clamp(0.0, 1.0),
+            tilt: finite_or_zero(point.tilt).clamp(0.0, FRAC_PI_2),
+            azimuth: finite_or_zero(point.azimuth),
+            rotation: finite_or_zero(point.rotation),
+        }
+    }
+
+    /// Interpolates towards `other`; angles turn the shorter way round.
+    pub(crate) fn mix(self, other: Self, t: f32) -> Self {
+        Self {
+            pressure: self.pressure + (other.pressure - self.pressure) * t,
+            tilt: self.tilt + (other.tilt - self.tilt) * t,
+            azimuth: mix_angle(self.azimuth, other.azimuth, t),
+            rotation: mix_angle(self.rotation, other.rotation, t),
+        }
+    }
+}
+
+fn mix_angle(from: f32, to: f32, t: f32) -> f32 {
+    let delta = (to - from + PI).rem_euclid(TAU) - PI;
+    from + delta * t
+}
+
 pub(crate) struct StreamlinePayload {
-    pub(crate) points: Vec<(Point2D, f32)>,
+    pub(crate) points: Vec<(Point2D, PenState)>,
     pub(crate) strength: f32,
 }
 
@@ -193,7 +573,7 @@ struct ConsumedStrokePoints {
     points_len: usize,
     down_count: usize,
     up_count: usize,
-    emitted: Vec<(Point2D, f32)>,
+    emitted: Vec<(Point2D, PenState)>,
 }
 
 const MAX_SMOOTH_HISTORY: usize = 256;
@@ -370,7 +750,7 @@ impl KritaStabilizer {
             } else if let Some(prev) = self.last_painted {
                 for item in self.queue.iter_mut() {
                     item.pos = prev.pos;
-                    item.pressure = prev.pressure;
+                    item.pen = prev.pen;
                 }
             }
         }
@@ -400,6 +780,140 @@ impl KritaStabilizer {
     }
 }
 
+const SPRING_STEP_US: u64 = 2_000;
+const SPRING_STEP_SECONDS: f32 = SPRING_STEP_US as f32 / 1_000_000.0;
+// Events without a usable timestamp count as one frame at 120 Hz.
+const SPRING_FALLBACK_GAP_US: u64 = 8_000;
+const SPRING_MAX_STEPS: u64 = 2_000;
+const SPRING_MIN_EMIT_DISTANCE: f32 = 0.5;
+const SPRING_SETTLE_DISTANCE: f32 = 0.25;
+const SPRING_SETTLE_SPEED: f32 = 5.0;
+
+/// Runs the [`SpringSettings`] tip at a fixed timestep between input events.
+struct SpringStabilizer {
+    tip: Point2D,
+    velocity: Point2D,
+    cursor: Option<StrokeSample>,
+    carry_us: u64,
+    last_output: Option<Point2D>,
+}
+
+impl SpringStabilizer {
+    fn new() -> Self {
+        Self {
+            tip: Point2D { x: 0.0, y: 0.0 },
+            velocity: Point2D { x: 0.0, y: 0.0 },
+            cursor: None,
+            carry_us: 0,
+            last_output: None,
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    fn process(
+        &mut self,
+        sample: StrokeSample,
+        is_down: bool,
+        is_up: bool,
+        settings: SpringSettings,
+    ) -> Vec<StrokeSample> {
+        let previous = match self.cursor {
+            Some(previous) if !is_down => previous,
+            _ => {
+                self.reset();
+                self.tip = sample.pos;
+                self.cursor = Some(sample);
+                self.last_output = Some(sample.pos);
+                return vec![sample];
+            }
+        };
+        self.cursor = Some(sample);
+
+        let mut output = Vec::new();
+        let elapsed = match sample.timestamp_us.saturating_sub(previous.timestamp_us) {
+            0 => SPRING_FALLBACK_GAP_US,
+            elapsed => elapsed,
+        };
+        let total = self.carry_us + elapsed;
+        let steps = total / SPRING_STEP_US;
+        self.carry_us = if steps > SPRING_MAX_STEPS { 0 } else { total % SPRING_STEP_US };
+        let steps = steps.min(SPRING_MAX_STEPS);
+        for i in 1..=steps {
+            let t = i as f32 / steps as f32;
+            let target = Point2D {
+                x: previous.pos.x + (sample.pos.x - previous.pos.x) * t,
+                y: previous.pos.y + (sample.pos.y - previous.pos.y) * t,
+            };
+            self.step(target, settings);
+            let stepped = StrokeSample {
+                pos: self.tip,
+                pen: previous.pen.mix(sample.pen, t),
+                timestamp_us: previous.timestamp_us + i * SPRING_STEP_US,
+            };
+            self.push(stepped, i == steps, &mut output);
+        }
+
+        if is_up {
+            self.catch_up(sample, settings, &mut output);
+        }
+        output
+    }
+
+    /// Lets the tip settle on the release point so the end of the line is
+    /// drawn instead of dropped.
+    fn catch_up(
+        &mut self,
+        sample: StrokeSample,
+        settings: SpringSettings,
+        output: &mut Vec<StrokeSample>,
+    ) {
+        for i in 1..=SPRING_MAX_STEPS {
+            let speed = point_distance(self.velocity, Point2D { x: 0.0, y: 0.0 });
+            let settled = point_distance(sample.pos, self.tip) <= SPRING_SETTLE_DISTANCE;
+            if settled && speed <= SPRING_SETTLE_SPEED {
+                break;
+            }
+            self.step(sample.pos, settings);
+            let stepped = StrokeSample {
+                pos: self.tip,
+                pen: sample.pen,
+                timestamp_us: sample.timestamp_us + i * SPRING_STEP_US,
+            };
+            self.push(stepped, false, output);
+        }
+        self.tip = sample.pos;
+        self.velocity = Point2D { x: 0.0, y: 0.0 };
+        self.push(sample, true, output);
+    }
+
+    fn step(&mut self, target: Point2D, settings: SpringSettings) {
+        // Semi-implicit Euler: update velocity first, then move with it.
+        let dt = SPRING_STEP_SECONDS;
+        let ax = (settings.stiffness * (target.x - self.tip.x) - settings.damping * self.velocity.x)
+            / settings.mass;
+        let ay = (settings.stiffness * (target.y - self.tip.y) - settings.damping * self.velocity.y)
+            / settings.mass;
+        self.velocity.x += ax * dt;
+        self.velocity.y += ay * dt;
+        self.tip.x += self.velocity.x * dt;
+        self.tip.y += self.velocity.y * dt;
+    }
+
+    // Skips steps that barely moved; the resampler fills in dabs anyway.
+    fn push(&mut self, sample: StrokeSample, force: bool, output: &mut Vec<StrokeSample>) {
+        if let Some(last) = self.last_output {
+            if !force && point_distance(last, sample.pos) < SPRING_MIN_EMIT_DISTANCE {
+                return;
+            }
+        }
+        self.last_output = Some(sample.pos);
+        output.push(sample);
+    }
+}
+
 fn stabilizer_sample_size(strength: f32) -> usize {
     let s = if strength.is_finite() {
         strength.clamp(0.0, 1.0)
@@ -434,7 +948,7 @@ fn stabilizer_average(
         result.pos.x = result.pos.x * k + item.pos.x * (1.0 / i);
         result.pos.y = result.pos.y * k + item.pos.y * (1.0 / i);
         if STABILIZER_SMOOTH_PRESSURE {
-            result.pressure = result.pressure * k + item.pressure * (1.0 / i);
+            result.pen = result.pen.mix(item.pen, 1.0 / i);
         }
         i += 1.0;
     }
@@ -443,10 +957,10 @@ fn stabilizer_average(
 
 pub(crate) struct StrokeResampler {
     last_emitted: Option<Point2D>,
-    last_pressure: f32,
+    last_pen: PenState,
     last_tick_dirty: Option<(i32, i32, i32, i32)>,
     last_tick_point: Option<Point2D>,
-    streamline_points: Vec<(Point2D, f32)>,
+    streamline_points: Vec<(Point2D, PenState)>,
     streamline_active: bool,
     streamline_strength: f32,
     resample_scale: f32,
@@ -458,13 +972,17 @@ pub(crate) struct StrokeResampler {
     smooth_previous: Option<StrokeSample>,
     smooth_last_raw: Option<StrokeSample>,
     stabilizer: KritaStabilizer,
+    spring: SpringStabilizer,
+    pressure_simulator: PressureSimulator,
+    assist: AssistProjector,
+    stroke_origin: Option<Point2D>,
 }
 
 impl StrokeResampler {
     pub(crate) fn new() -> Self {
         Self {
             last_emitted: None,
-            last_pressure: 1.0,
+            last_pen: PenState::from_pressure(1.0),
             last_tick_dirty: None,
             last_tick_point: None,
             streamline_points: Vec::new(),
@@ -479,6 +997,10 @@ impl StrokeResampler {
             smooth_previous: None,
             smooth_last_raw: None,
             stabilizer: KritaStabilizer::new(),
+            spring: SpringStabilizer::new(),
+            pressure_simulator: PressureSimulator::new(),
+            assist: AssistProjector::new(),
+            stroke_origin: None,
         }
     }
 
@@ -490,6 +1012,12 @@ impl StrokeResampler {
         self.last_tick_point
     }
 
+    /// First point the current stroke emitted; per-stroke colour jitter is
+    /// keyed on it so every redraw of the stroke repeats it.
+    pub(crate) fn stroke_origin(&self) -> Point2D {
+        self.stroke_origin.unwrap_or(Point2D { x: 0.0, y: 0.0 })
+    }
+
     pub(crate) fn set_resample_scale(&mut self, scale: f32) {
         let scale = if scale.is_finite() {
             scale.clamp(1.0, 8.0)
@@ -526,26 +1054,26 @@ impl StrokeResampler {
         self.streamline_points.clear();
     }
 
-    fn record_streamline_points(&mut self, emitted: &[(Point2D, f32)]) {
+    fn record_streamline_points(&mut self, emitted: &[(Point2D, PenState)]) {
         if !self.streamline_active || emitted.is_empty() {
             return;
         }
-        for (point, pressure) in emitted {
-            if let Some((last_point, last_pressure)) = self.streamline_points.last() {
+        for (point, pen) in emitted {
+            if let Some((last_point, last_pen)) = self.streamline_points.last() {
                 let dx = point.x - last_point.x;
                 let dy = point.y - last_point.y;
                 let dist2 = dx * dx + dy * dy;
-                if dist2 <= 1.0e-6 && (pressure - last_pressure).abs() <= 1.0e-4 {
+                if dist2 <= 1.0e-6 && (pen.pressure - last_pen.pressure).abs() <= 1.0e-4 {
                     continue;
                 }
             }
-            self.streamline_points.push((*point, *pressure));
+            self.streamline_points.push((*point, *pen));
         }
     }
 
     fn reset_for_new_stroke(&mut self) {
         self.last_emitted = None;
-        self.last_pressure = 1.0;
+        self.last_pen = PenState::from_pressure(1.0);
         self.smooth_history.clear();
         self.smooth_distance_history.clear();
         self.smooth_have_tangent = false;
@@ -554,20 +1082,31 @@ impl StrokeResampler {
         self.smooth_previous = None;
         self.smooth_last_raw = None;
         self.stabilizer.reset();
+        self.spring.reset();
+        self.pressure_simulator.reset();
+        self.assist.reset();
+        self.stroke_origin = None;
     }
 
-    fn emit_point(&mut self, point: Point2D, pressure: f32, emitted: &mut Vec<(Point2D, f32)>) {
+    fn emit_point(
+        &mut self,
+        point: Point2D,
+        pen: PenState,
+        emitted: &mut Vec<(Point2D, PenState)>,
+    ) {
         if let Some(last) = self.last_emitted {
             let dx = point.x - last.x;
             let dy = point.y - last.y;
-            if dx * dx + dy * dy <= 1.0e-6 && (pressure - self.last_pressure).abs() <= 1.0e-4
+            if dx * dx + dy * dy <= 1.0e-6
+                && (pen.pressure - self.last_pen.pressure).abs() <= 1.0e-4
             {
                 return;
             }
         }
-        emitted.push((point, pressure));
+        emitted.push((point, pen));
+        self.stroke_origin.get_or_insert(point);
         self.last_emitted = Some(point);
-        self.last_pressure = pressure;
+        self.last_pen = pen;
     }
 
     fn emit_line_segment(
@@ -576,14 +1115,14 @@ impl StrokeResampler {
         end: StrokeSample,
         include_end: bool,
         brush_settings: &EngineBrushSettings,
-        emitted: &mut Vec<(Point2D, f32)>,
+        emitted: &mut Vec<(Point2D, PenState)>,
     ) {
         if let Some(last) = self.last_emitted {
             if point_distance(last, start.pos) > 1.0e-3 {
-                self.emit_point(start.pos, start.pressure, emitted);
+                self.emit_point(start.pos, start.pen, emitted);
             }
         } else {
-            self.emit_point(start.pos, start.pressure, emitted);
+            self.emit_point(start.pos, start.pen, emitted);
         }
 
         let prev = start.pos;
@@ -592,13 +1131,13 @@ impl StrokeResampler {
         let dist = (dx * dx + dy * dy).sqrt();
         if !dist.is_finite() || dist <= 0.0001 {
             if include_end {
-                self.emit_point(end.pos, end.pressure, emitted);
+                self.emit_point(end.pos, end.pen, emitted);
             }
             return;
         }
 
-        let radius_prev = brush_settings.radius_from_pressure(start.pressure);
-        let radius_next = brush_settings.radius_from_pressure(end.pressure);
+        let radius_prev = brush_settings.radius_from_pressure(start.pen.pressure);
+        let radius_next = brush_settings.radius_from_pressure(end.pen.pressure);
         let mut step = resample_step_from_radius(
             (radius_prev + radius_next) * 0.5,
             brush_settings.spacing,
@@ -613,16 +1152,15 @@ impl StrokeResampler {
         while traveled + step <= dist {
             traveled += step;
             let t = (traveled / dist).clamp(0.0, 1.0);
-            let interp_pressure = start.pressure + (end.pressure - start.pressure) * t;
             let sample = Point2D {
                 x: prev.x + dir_x * traveled,
                 y: prev.y + dir_y * traveled,
             };
-            self.emit_point(sample, interp_pressure, emitted);
+            self.emit_point(sample, start.pen.mix(end.pen, t), emitted);
         }
 
         if include_end {
-            self.emit_point(end.pos, end.pressure, emitted);
+            self.emit_point(end.pos, end.pen, emitted);
         }
     }
 
@@ -633,26 +1171,26 @@ impl StrokeResampler {
         tangent1: Point2D,
         tangent2: Point2D,
         brush_settings: &EngineBrushSettings,
-        emitted: &mut Vec<(Point2D, f32)>,
+        emitted: &mut Vec<(Point2D, PenState)>,
     ) {
         let (c1, c2) = bezier_controls_from_tangents(p0.pos, p1.pos, tangent1, tangent2);
         if let Some(last) = self.last_emitted {
             if point_distance(last, p0.pos) > 1.0e-3 {
-                self.emit_point(p0.pos, p0.pressure, emitted);
+                self.emit_point(p0.pos, p0.pen, emitted);
             }
         } else {
-            self.emit_point(p0.pos, p0.pressure, emitted);
+            self.emit_point(p0.pos, p0.pen, emitted);
         }
 
         let radius = brush_settings
-            .radius_from_pressure((p0.pressure + p1.pressure) * 0.5)
+            .radius_from_pressure((p0.pen.pressure + p1.pen.pressure) * 0.5)
             .max(0.01);
         let mut step =
             resample_step_from_radius(radius, brush_settings.spacing, self.resample_scale);
         let length = cubic_length_approx(p0.pos, c1, c2, p1.pos);
         step = cap_resample_step_for_segment(length, step);
         if !length.is_finite() || length <= 0.0001 {
-            self.emit_point(p1.pos, p1.pressure, emitted);
+            self.emit_point(p1.pos, p1.pen, emitted);
             return;
         }
 
@@ -660,8 +1198,7 @@ impl StrokeResampler {
         for i in 1..=segments {
             let t = (i as f32) / (segments as f32);
             let pos = cubic_point(p0.pos, c1, c2, p1.pos, t);
-            let pres = p0.pressure + (p1.pressure - p0.pressure) * t;
-            self.emit_point(pos, pres, emitted);
+            self.emit_point(pos, p0.pen.mix(p1.pen, t), emitted);
         }
     }
 
@@ -715,6 +1252,7 @@ impl StrokeResampler {
                 let mut x = 0.0f32;
                 let mut y = 0.0f32;
                 let mut pressure_sum = 0.0f32;
+                let mut pen = current.pen;
                 let mut base_rate = 0.0f32;
 
                 for i in (0..self.smooth_history.len()).rev() {
@@ -727,11 +1265,14 @@ impl StrokeResampler {
 
                     if i + 1 < self.smooth_history.len() {
                         let pressure_grad =
-                            next_info.pressure - self.smooth_history[i + 1].pressure;
+                            next_info.pen.pressure - self.smooth_history[i + 1].pen.pressure;
                         if pressure_grad > 0.0 {
                             let tail = 40.0 * SMOOTH_TAIL_AGGRESSIVENESS;
-                            distance +=
-                                pressure_grad * tail * (1.0 - next_info.pressure) * 3.0 * sigma;
+                            distance += pressure_grad
+                                * tail
+                                * (1.0 - next_info.pen.pressure)
+                                * 3.0
+                                * sigma;
                         }
                     }
 
@@ -747,11 +1288,15 @@ impl StrokeResampler {
                         break;
                     }
 
+                    // Running weighted mean, so angles average the short way round.
+                    if scale_sum + rate > 1.0e-6 {
+                        pen = pen.mix(next_info.pen, rate / (scale_sum + rate));
+                    }
                     scale_sum += rate;
                     x += rate * next_info.pos.x;
                     y += rate * next_info.pos.y;
                     if SMOOTH_PRESSURE {
-                        pressure_sum += rate * next_info.pressure;
+                        pressure_sum += rate * next_info.pen.pressure;
                     }
                 }
 
@@ -759,14 +1304,15 @@ impl StrokeResampler {
                     x /= scale_sum;
                     y /= scale_sum;
                     current.pos = Point2D { x, y };
+                    current.pen.tilt = pen.tilt;
+                    current.pen.azimuth = pen.azimuth;
+                    current.pen.rotation = pen.rotation;
                     if SMOOTH_PRESSURE {
-                        current.pressure = pressure_sum / scale_sum;
+                        current.pen.pressure = pressure_sum / scale_sum;
                     }
                     if let Some(last) = self.smooth_history.last_mut() {
                         last.pos = current.pos;
-                        if SMOOTH_PRESSURE {
-                            last.pressure = current.pressure;
-                        }
+                        last.pen = current.pen;
                     }
                 }
             }
@@ -781,7 +1327,7 @@ impl StrokeResampler {
         sample: StrokeSample,
         mode: SmoothingMode,
         brush_settings: &EngineBrushSettings,
-        emitted: &mut Vec<(Point2D, f32)>,
+        emitted: &mut Vec<(Point2D, PenState)>,
     ) {
         let mut current = sample;
         if mode == SmoothingMode::Weighted {
@@ -789,7 +1335,7 @@ impl StrokeResampler {
         }
 
         if self.smooth_previous.is_none() {
-            self.emit_point(current.pos, current.pressure, emitted);
+            self.emit_point(current.pos, current.pen, emitted);
             self.smooth_previous = Some(current);
             return;
         }
@@ -831,7 +1377,7 @@ impl StrokeResampler {
     fn finish_smoothing(
         &mut self,
         brush_settings: &EngineBrushSettings,
-        emitted: &mut Vec<(Point2D, f32)>,
+        emitted: &mut Vec<(Point2D, PenState)>,
     ) {
         if !self.smooth_have_tangent {
             return;
@@ -879,7 +1425,7 @@ impl StrokeResampler {
         }
 
         let points_len = points.len();
-        let mut emitted: Vec<(Point2D, f32)> = Vec::new();
+        let mut emitted: Vec<(Point2D, PenState)> = Vec::new();
         let mut down_count: usize = 0;
         let mut up_count: usize = 0;
 
@@ -889,11 +1435,7 @@ impl StrokeResampler {
         for p in points {
             let x = if p.x.is_finite() { p.x } else { 0.0 };
             let y = if p.y.is_finite() { p.y } else { 0.0 };
-            let pressure = if p.pressure.is_finite() {
-                p.pressure.clamp(0.0, 1.0)
-            } else {
-                0.0
-            };
+            let pen = PenState::from_point(&p);
             let is_down = (p.flags & FLAG_DOWN) != 0;
             let is_up = (p.flags & FLAG_UP) != 0;
             if is_down {
@@ -905,47 +1447,51 @@ impl StrokeResampler {
                 up_count += 1;
             }
 
-            let sample = StrokeSample {
-                pos: Point2D { x, y },
-                pressure,
+            let pos = self
+                .assist
+                .project(&brush_settings.assist.guide, Point2D { x, y }, is_down);
+            let mut sample = StrokeSample {
+                pos,
+                pen,
                 timestamp_us: p.timestamp_us,
             };
 
-            match mode {
-                SmoothingMode::Stabilizer => {
-                    let stabilized = self.stabilizer.process(sample, is_down, is_up);
-                    for stab in stabilized {
-                        // Feed stabilizer samples into the same weighted + bezier smoothing
-                        // pipeline so corners stay rounded.
-                        self.process_smoothing_sample(
-                            stab,
-                            SmoothingMode::Weighted,
-                            brush_settings,
-                            &mut emitted,
-                        );
-                    }
-                    if is_up {
-                        self.finish_smoothing(brush_settings, &mut emitted);
-                    }
-                }
-                SmoothingMode::Weighted | SmoothingMode::Simple => {
-                    self.process_smoothing_sample(sample, mode, brush_settings, &mut emitted);
-                    if is_up {
-                        self.finish_smoothing(brush_settings, &mut emitted);
-                    }
-                }
-                SmoothingMode::None => {
-                    if is_down || self.last_emitted.is_none() {
-                        self.emit_point(sample.pos, sample.pressure, &mut emitted);
-                        continue;
-                    }
-                    let start = StrokeSample {
-                        pos: self.last_emitted.unwrap(),
-                        pressure: self.last_pressure,
-                        timestamp_us: sample.timestamp_us,
-                    };
-                    self.emit_line_segment(start, sample, is_up, brush_settings, &mut emitted);
-                }
+            let simulated = brush_settings.simulated_pressure;
+            if !simulated.is_active() {
+                self.feed_sample(sample, is_down, is_up, mode, brush_settings, &mut emitted);
+                continue;
+            }
+            sample.pen.pressure = self.pressure_simulator.sample(
+                simulated,
+                sample.pos,
+                sample.pen.pressure,
+                sample.timestamp_us,
+            );
+            let tail = if is_up {
+                self.pressure_simulator.tail(simulated, brush_settings.base_radius)
+            } else {
+                Vec::new()
+            };
+            self.feed_sample(
+                sample,
+                is_down,
+                is_up && tail.is_empty(),
+                mode,
+                brush_settings,
+                &mut emitted,
+            );
+            let tail_len = tail.len();
+            for (idx, (pos, pressure, offset_us)) in tail.into_iter().enumerate() {
+                let tail_sample = StrokeSample {
+                    pos,
+                    pen: PenState {
+                        pressure,
+                        ..sample.pen
+                    },
+                    timestamp_us: sample.timestamp_us.saturating_add(offset_us),
+                };
+                let last = idx + 1 == tail_len;
+                self.feed_sample(tail_sample, false, last, mode, brush_settings, &mut emitted);
             }
         }
 
@@ -992,11 +1538,74 @@ impl StrokeResampler {
         })
     }
 
+    fn feed_sample(
+        &mut self,
+        sample: StrokeSample,
+        is_down: bool,
+        is_up: bool,
+        mode: SmoothingMode,
+        brush_settings: &EngineBrushSettings,
+        emitted: &mut Vec<(Point2D, PenState)>,
+    ) {
+        match mode {
+            SmoothingMode::Stabilizer => {
+                let stabilized = self.stabilizer.process(sample, is_down, is_up);
+                for stab in stabilized {
+                    // Feed stabilizer samples into the same weighted + bezier smoothing
+                    // pipeline so corners stay rounded.
+                    self.process_smoothing_sample(
+                        stab,
+                        SmoothingMode::Weighted,
+                        brush_settings,
+                        emitted,
+                    );
+                }
+                if is_up {
+                    self.finish_smoothing(brush_settings, emitted);
+                }
+            }
+            SmoothingMode::Spring => {
+                let settled = self
+                    .spring
+                    .process(sample, is_down, is_up, brush_settings.spring);
+                for tip in settled {
+                    self.process_smoothing_sample(
+                        tip,
+                        SmoothingMode::Simple,
+                        brush_settings,
+                        emitted,
+                    );
+                }
+                if is_up {
+                    self.finish_smoothing(brush_settings, emitted);
+                }
+            }
+            SmoothingMode::Weighted | SmoothingMode::Simple => {
+                self.process_smoothing_sample(sample, mode, brush_settings, emitted);
+                if is_up {
+                    self.finish_smoothing(brush_settings, emitted);
+                }
+            }
+            SmoothingMode::None => {
+                if is_down || self.last_emitted.is_none() {
+                    self.emit_point(sample.pos, sample.pen, emitted);
+                    return;
+                }
+                let start = StrokeSample {
+                    pos: self.last_emitted.unwrap(),
+                    pen: self.last_pen,
+                    timestamp_us: sample.timestamp_us,
+                };
+                self.emit_line_segment(start, sample, is_up, brush_settings, emitted);
+            }
+        }
+    }
+
     pub(crate) fn consume_points(
         &mut self,
         brush_settings: &EngineBrushSettings,
         points: Vec<EnginePoint>,
-    ) -> Vec<(Point2D, f32)> {
+    ) -> Vec<(Point2D, PenState)> {
         self.consume_points_internal(brush_settings, points)
             .map(|out| out.emitted)
             .unwrap_or_default()
@@ -1057,21 +1666,46 @@ impl StrokeResampler {
         brush: &mut BrushRenderer,
         brush_settings: &EngineBrushSettings,
         layer_view: &wgpu::TextureView,
-        emitted: &[(Point2D, f32)],
+        emitted: &[(Point2D, PenState)],
         canvas_width: u32,
         canvas_height: u32,
         before_draw: &mut F,
     ) -> bool {
         self.last_tick_point = emitted.last().map(|(point, _)| *point);
-        let (drew_any, dirty_union) = draw_emitted_points_internal(
-            brush,
-            brush_settings,
-            layer_view,
-            emitted,
-            canvas_width,
-            canvas_height,
-            before_draw,
-        );
+        let origin = self.stroke_origin();
+        let mut drew_any = false;
+        let mut dirty_union: Option<(i32, i32, i32, i32)> = None;
+        let mix = brush_settings.mix;
+        let mix_mode = if brush_settings.mixing() {
+            mix.mode.shader_mode()
+        } else {
+            0
+        };
+        for (slot, copy) in brush_settings.symmetry.apply(emitted).into_iter().enumerate() {
+            // Every copy drags its own paint along.
+            brush.set_mix(
+                mix_mode,
+                mix.pickup,
+                mix.dilution,
+                mix.persistence,
+                mix.extension,
+                slot as u32,
+            );
+            let (drew, dirty) = draw_emitted_points_internal(
+                brush,
+                brush_settings,
+                layer_view,
+                &copy,
+                origin,
+                canvas_width,
+                canvas_height,
+                before_draw,
+            );
+            drew_any |= drew;
+            if let Some(dirty) = dirty {
+                dirty_union = union_dirty_rect_i32(dirty_union, dirty);
+            }
+        }
         self.last_tick_dirty = dirty_union;
         if !drew_any {
             self.last_tick_point = None;
@@ -1084,7 +1718,8 @@ fn draw_emitted_points_internal<F: FnMut(&mut BrushRenderer, (i32, i32, i32, i32
     brush: &mut BrushRenderer,
     brush_settings: &EngineBrushSettings,
     layer_view: &wgpu::TextureView,
-    emitted: &[(Point2D, f32)],
+    emitted: &[(Point2D, PenState)],
+    origin: Point2D,
     canvas_width: u32,
     canvas_height: u32,
     before_draw: &mut F,
@@ -1094,7 +1729,7 @@ fn draw_emitted_points_internal<F: FnMut(&mut BrushRenderer, (i32, i32, i32, i32
     }
 
     brush.set_canvas_size(canvas_width, canvas_height);
-    let softness = brush_settings.softness();
+    let softness = brush_settings.stroke_softness(emitted);
     brush.set_softness(softness);
     brush.set_screentone(
         brush_settings.screentone_enabled,
@@ -1104,15 +1739,25 @@ fn draw_emitted_points_internal<F: FnMut(&mut BrushRenderer, (i32, i32, i32, i32
         brush_settings.screentone_softness,
         brush_settings.screentone_shape,
     );
+    let grain = brush_settings.grain;
+    brush.set_grain(
+        if grain.enabled {
+            grain.blend.shader_mode()
+        } else {
+            0
+        },
+        grain.scale,
+        grain.offset_x,
+        grain.offset_y,
+        grain.depth,
+        grain.invert,
+    );
+    let watercolor = brush_settings.watercolor();
+    let wet = brush_settings.wet;
+    brush.set_watercolor(watercolor, wet.edge, wet.granulation, wet.bleed);
 
-    let hollow_enabled = brush_settings.hollow_enabled
-        && !brush_settings.erase
-        && brush_settings.hollow_ratio > 0.0001;
-    let hollow_ratio = if hollow_enabled {
-        brush_settings.hollow_ratio
-    } else {
-        0.0
-    };
+    let hollow_enabled = brush_settings.hollow_core().is_some();
+    let hollow_ratio = brush_settings.hollow_core().unwrap_or(0.0);
     let hollow_erase = hollow_enabled && brush_settings.hollow_erase_occluded;
 
     let mut dirty_union: Option<(i32, i32, i32, i32)> = None;
@@ -1129,34 +1774,30 @@ fn draw_emitted_points_internal<F: FnMut(&mut BrushRenderer, (i32, i32, i32, i32
         let dirty = compute_dirty_rect_i32(&points, &dirty_radii, canvas_width, canvas_height);
         before_draw(brush, dirty);
 
-        let supports_rotation = brush_settings.supports_rotation();
-        let use_smooth = brush_settings.smooth_rotation && supports_rotation;
-        let use_random =
-            brush_settings.random_rotation && brush_settings.rotation_jitter > 0.0001 && supports_rotation;
-        let rotations = if use_smooth || use_random {
-            let jitter = if brush_settings.rotation_jitter.is_finite() {
-                brush_settings.rotation_jitter.clamp(0.0, 1.0)
-            } else {
-                1.0
-            };
-            let mut rotations: Vec<PointRotation> = Vec::with_capacity(points.len());
-            for (idx, point) in points.iter().enumerate() {
-                let mut angle = if use_smooth {
-                    stroke_direction_angle(&points, idx)
-                } else {
-                    0.0
-                };
-                if use_random {
-                    angle += brush_random_rotation_radians(*point, brush_settings.rotation_seed) * jitter;
-                }
-                rotations.push(PointRotation {
-                    sin: angle.sin(),
-                    cos: angle.cos(),
-                });
-            }
-            Some(rotations)
+        let rotations = compute_point_rotations(brush_settings, &points, emitted);
+        let alphas = dab_alphas(brush_settings, emitted, origin);
+        let colors = dab_colors(brush_settings, emitted, origin);
+
+        let dual = brush_settings.dual;
+        let (dual_points, dual_radii): (Vec<Point2D>, Vec<f32>) = if dual.enabled {
+            let path: Vec<BrushPoint> = points
+                .iter()
+                .zip(radii.iter())
+                .map(|(p, &radius)| BrushPoint {
+                    x: p.x,
+                    y: p.y,
+                    radius,
+                    alpha: 1.0,
+                    rot_sin: 0.0,
+                    rot_cos: 1.0,
+                })
+                .collect();
+            dual_tip_dabs(&dual, &path, brush_settings.rotation_seed)
+                .iter()
+                .map(|d| (Point2D { x: d.x, y: d.y }, d.radius))
+                .unzip()
         } else {
-            None
+            (Vec::new(), Vec::new())
         };
 
         let color = Color {
@@ -1169,11 +1810,23 @@ fn draw_emitted_points_internal<F: FnMut(&mut BrushRenderer, (i32, i32, i32, i32
             let pts = &points[start..end];
             let rs = &radii[start..end];
             let rot_slice = rotations.as_ref().map(|rots| &rots[start..end]);
+            let alpha_slice = alphas.as_ref().map(|values| &values[start..end]);
+            brush.set_dab_colors(colors.as_ref().map(|values| &values[start..end]));
+            brush.set_dual_tip(
+                if dual.enabled {
+                    dual.blend.shader_mode()
+                } else {
+                    0
+                },
+                dual.shape,
+                &dual_points,
+                &dual_radii,
+            );
             match brush.draw_points(
                 layer_view,
                 pts,
                 rs,
-                None,
+                alpha_slice,
                 None,
                 rot_slice,
                 color,
@@ -1184,7 +1837,7 @@ fn draw_emitted_points_internal<F: FnMut(&mut BrushRenderer, (i32, i32, i32, i32
                 hollow_enabled,
                 hollow_ratio,
                 hollow_erase,
-                hollow_enabled,
+                hollow_enabled || watercolor,
                 true,
                 0,
                 None,
@@ -1441,13 +2094,57 @@ fn draw_emitted_points_internal<F: FnMut(&mut BrushRenderer, (i32, i32, i32, i32
 }
 
 
+pub(crate) fn compute_point_rotations(
+    brush_settings: &EngineBrushSettings,
+    points: &[Point2D],
+    emitted: &[(Point2D, PenState)],
+) -> Option<Vec<PointRotation>> {
+    let supports_rotation = brush_settings.supports_rotation();
+    let use_smooth = brush_settings.smooth_rotation && supports_rotation;
+    let use_random =
+        brush_settings.random_rotation && brush_settings.rotation_jitter > 0.0001 && supports_rotation;
+    let use_pen = brush_settings.uses_pen_angle() && emitted.len() == points.len();
+    if !use_smooth && !use_random && !use_pen {
+        return None;
+    }
+    let jitter = if brush_settings.rotation_jitter.is_finite() {
+        brush_settings.rotation_jitter.clamp(0.0, 1.0)
+    } else {
+        1.0
+    };
+    let mut rotations: Vec<PointRotation> = Vec::with_capacity(points.len());
+    for (idx, point) in points.iter().enumerate() {
+        let mut angle = if use_smooth {
+            stroke_direction_angle(points, idx)
+        } else {
+            0.0
+        };
+        if use_random {
+            angle += brush_random_rotation_radians(*point, brush_settings.rotation_seed) * jitter;
+        }
+        if use_pen {
+            angle += brush_settings.dynamics.angle(emitted[idx].1);
+        }
+        rotations.push(PointRotation {
+            sin: angle.sin(),
+            cos: angle.cos(),
+        });
+    }
+    Some(rotations)
+}
+
 const RUST_PRESSURE_MIN_FACTOR: f32 = 0.09;
 // Allow dense resampling on long straight segments (e.g., line/perspective tools)
 // so small brushes don't turn into dashed strokes.
 const MAX_SEGMENT_SAMPLES: usize = 4096;
 const MAX_EMITTED_POINTS: usize = 4096;
 
-fn brush_radius_from_pressure(pressure: f32, base_radius: f32, use_pressure: bool) -> f32 {
+fn brush_radius_from_pressure(
+    pressure: f32,
+    base_radius: f32,
+    use_pressure: bool,
+    curves: &PressureCurves,
+) -> f32 {
     let base = if base_radius.is_finite() {
         base_radius.max(0.0)
     } else {
@@ -1456,6 +2153,9 @@ fn brush_radius_from_pressure(pressure: f32, base_radius: f32, use_pressure: boo
     if !use_pressure {
         return base;
     }
+    if let Some(factor) = curves.response(PressureTarget::Size, pressure) {
+        return base * factor;
+    }
     let p = if pressure.is_finite() {
         pressure.clamp(0.0, 1.0)
     } else {
@@ -1488,25 +2188,97 @@ fn snap_radius_to_pixel(radius: f32) -> f32 {
     (radius * 2.0).round() * 0.5
 }
 
+/// Per-dab opacity from pen tilt, the opacity and flow pressure curves and
+/// opacity jitter, or `None` when the brush uses none of them. `origin` is
+/// the stroke's first point (see [`StrokeResampler::stroke_origin`]).
+pub(crate) fn dab_alphas(
+    brush_settings: &EngineBrushSettings,
+    emitted: &[(Point2D, PenState)],
+    origin: Point2D,
+) -> Option<Vec<f32>> {
+    let curves = &brush_settings.pressure_curves;
+    let pressure_alpha = brush_settings.uses_pressure()
+        && (curves.has(PressureTarget::Opacity) || curves.has(PressureTarget::Flow));
+    let jitter = brush_settings.color.uses_opacity();
+    if !brush_settings.dynamics.uses_opacity() && !pressure_alpha && !jitter {
+        return None;
+    }
+    Some(
+        emitted
+            .iter()
+            .map(|(point, pen)| {
+                let opacity = brush_settings
+                    .pressure_response(PressureTarget::Opacity, pen.pressure)
+                    .unwrap_or(1.0);
+                let flow = brush_settings
+                    .pressure_response(PressureTarget::Flow, pen.pressure)
+                    .unwrap_or(1.0);
+                let jitter = if jitter {
+                    brush_settings
+                        .color
+                        .opacity(origin, *point, brush_settings.rotation_seed)
+                } else {
+                    1.0
+                };
+                brush_settings.dynamics.opacity(*pen) * opacity * flow * jitter
+            })
+            .collect(),
+    )
+}
+
+/// Per-dab colour from colour dynamics, or `None` when they are off. Erasers,
+/// mixing and watercolour brushes ignore it.
+pub(crate) fn dab_colors(
+    brush_settings: &EngineBrushSettings,
+    emitted: &[(Point2D, PenState)],
+    origin: Point2D,
+) -> Option<Vec<u32>> {
+    if !brush_settings.color.uses_color()
+        || brush_settings.erase
+        || brush_settings.mixing()
+        || brush_settings.watercolor()
+    {
+        return None;
+    }
+    Some(
+        emitted
+            .iter()
+            .map(|(point, pen)| {
+                brush_settings.color.color(
+                    brush_settings.color_argb,
+                    origin,
+                    *point,
+                    *pen,
+                    brush_settings.rotation_seed,
+                )
+            })
+            .collect(),
+    )
+}
+
 pub(crate) fn prepare_brush_samples(
     brush_settings: &EngineBrushSettings,
-    emitted: &[(Point2D, f32)],
+    emitted: &[(Point2D, PenState)],
 ) -> (Vec<Point2D>, Vec<f32>) {
     let mut points: Vec<Point2D> = Vec::with_capacity(emitted.len());
     let mut radii: Vec<f32> = Vec::with_capacity(emitted.len());
-    let scatter = brush_settings.scatter;
     let snap = brush_settings.snap_to_pixel;
     let scatter_seed = if brush_settings.random_rotation {
         brush_settings.rotation_seed
     } else {
         0
     };
-    for (idx, (p, pres)) in emitted.iter().enumerate() {
-        let mut radius = brush_settings.radius_from_pressure(*pres);
+    for (idx, (p, pen)) in emitted.iter().enumerate() {
+        let mut radius = brush_settings.radius_from_pressure(pen.pressure)
+            * brush_settings.dynamics.size_factor(*pen);
         if !radius.is_finite() || radius <= 0.0 {
             radius = 0.01;
         }
         let mut point = *p;
+        let scatter = brush_settings.scatter
+            * brush_settings
+                .pressure_response(PressureTarget::Scatter, pen.pressure)
+                .unwrap_or(1.0);
         if scatter > 0.0001 {
             let scatter_radius = radius.abs().max(0.01) * 2.0 * scatter;
             if scatter_radius > 0.0001 {
@@ -1565,7 +2337,7 @@ fn cap_resample_step_for_segment(dist: f32, step: f32) -> f32 {
     }
 }
 
-fn downsample_emitted(points: &[(Point2D, f32)], target_count: usize) -> Vec<(Point2D, f32)> {
+fn downsample_emitted(points: &[(Point2D, PenState)], target_count: usize) -> Vec<(Point2D, PenState)> {
     let len = points.len();
     if len <= target_count {
         return points.to_vec();
@@ -1582,7 +2354,7 @@ fn downsample_emitted(points: &[(Point2D, f32)], target_count: usize) -> Vec<(Po
     output
 }
 
-fn average_segment_length(points: &[(Point2D, f32)]) -> f32 {
+fn average_segment_length(points: &[(Point2D, PenState)]) -> f32 {
     if points.len() < 2 {
         return 0.0;
     }
@@ -1626,20 +2398,20 @@ fn gaussian_kernel(radius: usize, sigma: f32) -> Vec<f32> {
 }
 
 fn gaussian_smooth_points(
-    points: &[(Point2D, f32)],
+    points: &[(Point2D, PenState)],
     kernel: &[f32],
-) -> Vec<(Point2D, f32)> {
+) -> Vec<(Point2D, PenState)> {
     if points.len() < 3 || kernel.is_empty() {
         return points.to_vec();
     }
     let radius = kernel.len() / 2;
     let len = points.len();
-    let mut output: Vec<(Point2D, f32)> = Vec::with_capacity(len);
+    let mut output: Vec<(Point2D, PenState)> = Vec::with_capacity(len);
     for i in 0..len {
         let mut sum_w = 0.0f32;
         let mut x = 0.0f32;
         let mut y = 0.0f32;
-        let mut p = 0.0f32;
+        let mut pen = points[i].1;
         for (k, &w) in kernel.iter().enumerate() {
             let offset = k as isize - radius as isize;
             let idx = if offset < 0 {
@@ -1647,18 +2419,20 @@ fn gaussian_smooth_points(
             } else {
                 (i + offset as usize).min(len - 1)
             };
-            let (pos, pres) = points[idx];
+            let (pos, sample_pen) = points[idx];
             x += pos.x * w;
             y += pos.y * w;
-            p += pres * w;
+            // Running weighted mean, so angles average the short way round.
+            if sum_w + w > 1.0e-6 {
+                pen = pen.mix(sample_pen, w / (sum_w + w));
+            }
             sum_w += w;
         }
         if sum_w > 1.0e-6 {
             x /= sum_w;
             y /= sum_w;
-            p /= sum_w;
         }
-        output.push((Point2D { x, y }, p));
+        output.push((Point2D { x, y }, pen));
     }
     output[0] = points[0];
     output[len - 1] = points[len - 1];
@@ -1666,9 +2440,9 @@ fn gaussian_smooth_points(
 }
 
 pub(crate) fn apply_streamline(
-    points: &[(Point2D, f32)],
+    points: &[(Point2D, PenState)],
     strength: f32,
-) -> Vec<(Point2D, f32)> {
+) -> Vec<(Point2D, PenState)> {
     let strength = if strength.is_finite() {
         strength.clamp(0.0, 1.0)
     } else {
@@ -1702,10 +2476,11 @@ pub(crate) fn apply_streamline(
     resampled[0] = points[0];
     resampled[last_idx] = points[points.len() - 1];
     for (idx, sample) in resampled.iter_mut().enumerate() {
-        let pres = points[idx].1;
-        sample.1 = if pres.is_finite() { pres.clamp(0.0, 1.0) } else { 0.0 };
+        sample.1 = points[idx].1;
+        let pres = sample.1.pressure;
+        sample.1.pressure = if pres.is_finite() { pres.clamp(0.0, 1.0) } else { 0.0 };
     }
-    let mut output: Vec<(Point2D, f32)> = Vec::with_capacity(points.len());
+    let mut output: Vec<(Point2D, PenState)> = Vec::with_capacity(points.len());
     let pos_mix = eased;
     for (orig, smooth) in points.iter().zip(resampled.iter()) {
         let x = orig.0.x + (smooth.0.x - orig.0.x) * pos_mix;
@@ -1716,7 +2491,7 @@ pub(crate) fn apply_streamline(
     output
 }
 
-fn adaptive_resample_spline(points: &[(Point2D, f32)], target_count: usize) -> Vec<(Point2D, f32)> {
+fn adaptive_resample_spline(points: &[(Point2D, PenState)], target_count: usize) -> Vec<(Point2D, PenState)> {
     if points.len() < 2 || target_count < 2 {
         return points.to_vec();
     }
@@ -1754,7 +2529,7 @@ fn adaptive_resample_spline(points: &[(Point2D, f32)], target_count: usize) -> V
         return points.to_vec();
     }
 
-    let mut output: Vec<(Point2D, f32)> = Vec::with_capacity(target_count);
+    let mut output: Vec<(Point2D, PenState)> = Vec::with_capacity(target_count);
     output.push(points[0]);
 
     let step = total_weight / (target_count.saturating_sub(1) as f32);
@@ -1790,8 +2565,8 @@ fn adaptive_resample_spline(points: &[(Point2D, f32)], target_count: usize) -> V
             points[points.len() - 1].0
         };
         let pos = catmull_rom(p0, p1, p2, p3, t);
-        let pres = points[seg_index].1 + (points[seg_index + 1].1 - points[seg_index].1) * t;
-        output.push((pos, pres));
+        let pen = points[seg_index].1.mix(points[seg_index + 1].1, t);
+        output.push((pos, pen));
     }
 
     output.push(points[points.len() - 1]);
@@ -2139,7 +2914,7 @@ fn mix32(mut h: u32) -> u32 {
     h
 }
 
-fn compute_dirty_rect_i32(
+pub(crate) fn compute_dirty_rect_i32(
     points: &[Point2D],
     radii: &[f32],
     canvas_width: u32,
@@ -2181,7 +2956,7 @@ fn compute_dirty_rect_i32(
     (left as i32, top as i32, width, height)
 }
 
-fn union_dirty_rect_i32(
+pub(crate) fn union_dirty_rect_i32(
     existing: Option<(i32, i32, i32, i32)>,
     candidate: (i32, i32, i32, i32),
 ) -> Option<(i32, i32, i32, i32)> {
@@ -2215,3 +2990,28 @@ fn union_dirty_rect_i32(
     let height = (bottom - top).max(0) as i32;
     Some((left as i32, top as i32, width, height))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spring_tip_lags_then_lands_on_release_point() {
+        let settings = SpringSettings::default();
+        let sample = |x: f32, timestamp_us: u64| StrokeSample {
+            pos: Point2D { x, y: 0.0 },
+            pen: PenState::from_pressure(1.0),
+            timestamp_us,
+        };
+        let mut spring = SpringStabilizer::new();
+        spring.process(sample(0.0, 0), true, false, settings);
+        let moving = spring.process(sample(100.0, 16_000), false, false, settings);
+        let lagging = moving.last().unwrap().pos.x;
+        assert!(lagging > 0.0 && lagging < 100.0);
+
+        let finished = spring.process(sample(100.0, 32_000), false, true, settings);
+        let end = finished.last().unwrap();
+        assert_eq!(end.pos.x, 100.0);
+        assert!(finished.iter().all(|tip| tip.pos.x <= 100.0 + 1.0));
+    }
+}
@@ -0,0 +1,199 @@
+use std::f32::consts::{PI, TAU};
+
+use crate::gpu::brush_renderer::Point2D;
+
+use super::stroke::PenState;
+
+// Beyond this the copies sit closer than a dab apart on small canvases and
+// the stroke cost grows for no visible gain.
+const MAX_FOLDS: u32 = 32;
+
+/// How strokes are repeated around the symmetry centre. The indices are the
+/// FFI `mode` values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum SymmetryMode {
+    #[default]
+    Off,
+    /// Mirrored across the vertical axis, left to right.
+    Horizontal,
+    /// Mirrored across the horizontal axis, top to bottom.
+    Vertical,
+    /// Mirrored across both axes, four copies.
+    Both,
+    /// `folds` copies turned evenly around the centre.
+    Radial,
+    /// Radial with every copy also mirrored, `2 * folds` copies.
+    Kaleidoscope,
+}
+
+impl SymmetryMode {
+    pub(crate) fn from_index(index: u32) -> Self {
+        match index {
+            1 => Self::Horizontal,
+            2 => Self::Vertical,
+            3 => Self::Both,
+            4 => Self::Radial,
+            5 => Self::Kaleidoscope,
+            _ => Self::Off,
+        }
+    }
+}
+
+/// One copy of the stroke: reflect `x` about the centre when `mirror`, then
+/// turn by `angle`.
+#[derive(Clone, Copy, Debug)]
+struct SymmetryCopy {
+    mirror: bool,
+    angle: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Symmetry {
+    pub(crate) mode: SymmetryMode,
+    pub(crate) center: Point2D,
+    pub(crate) folds: u32,
+}
+
+impl Default for Symmetry {
+    fn default() -> Self {
+        Self {
+            mode: SymmetryMode::Off,
+            center: Point2D { x: 0.0, y: 0.0 },
+            folds: 6,
+        }
+    }
+}
+
+impl Symmetry {
+    pub(crate) fn sanitize(&mut self) {
+        if !self.center.x.is_finite() || !self.center.y.is_finite() {
+            self.center = Point2D { x: 0.0, y: 0.0 };
+        }
+        self.folds = self.folds.clamp(2, MAX_FOLDS);
+    }
+
+    fn copies(&self) -> Vec<SymmetryCopy> {
+        let copy = |mirror: bool, angle: f32| SymmetryCopy { mirror, angle };
+        match self.mode {
+            SymmetryMode::Off => vec![copy(false, 0.0)],
+            SymmetryMode::Horizontal => vec![copy(false, 0.0), copy(true, 0.0)],
+            // A flip top to bottom is a flip left to right turned half way.
+            SymmetryMode::Vertical => vec![copy(false, 0.0), copy(true, PI)],
+            SymmetryMode::Both => vec![
+                copy(false, 0.0),
+                copy(true, 0.0),
+                copy(true, PI),
+                copy(false, PI),
+            ],
+            SymmetryMode::Radial | SymmetryMode::Kaleidoscope => {
+                let mirrored = self.mode == SymmetryMode::Kaleidoscope;
+                let step = TAU / self.folds as f32;
+                (0..self.folds)
+                    .flat_map(|i| {
+                        let angle = i as f32 * step;
+                        let mirror_copy = mirrored.then(|| copy(true, angle));
+                        std::iter::once(copy(false, angle)).chain(mirror_copy)
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Every copy of `emitted`, the untouched stroke first. Pen angles are
+    /// carried along so tilted and rotated dabs face the way the copy does.
+    pub(crate) fn apply(&self, emitted: &[(Point2D, PenState)]) -> Vec<Vec<(Point2D, PenState)>> {
+        let center = self.center;
+        self.copies()
+            .into_iter()
+            .map(|copy| {
+                if !copy.mirror && copy.angle == 0.0 {
+                    return emitted.to_vec();
+                }
+                let (sin, cos) = copy.angle.sin_cos();
+                emitted
+                    .iter()
+                    .map(|(point, pen)| {
+                        let mut dx = point.x - center.x;
+                        let dy = point.y - center.y;
+                        let mut pen = *pen;
+                        if copy.mirror {
+                            dx = -dx;
+                            pen.azimuth = PI - pen.azimuth;
+                            pen.rotation = -pen.rotation;
+                        }
+                        pen.azimuth += copy.angle;
+                        let moved = Point2D {
+                            x: center.x + dx * cos - dy * sin,
+                            y: center.y + dx * sin + dy * cos,
+                        };
+                        (moved, pen)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas_engine::engine::tests::{
+        brush, point, push_points, read_layer, stop_engine, test_engines,
+    };
+    use crate::canvas_engine::engine::{lookup_engine, EngineCommand};
+
+    #[test]
+    fn copies_land_on_the_mirrored_and_turned_points() {
+        let stroke = [(Point2D { x: 15.0, y: 10.0 }, PenState::from_pressure(1.0))];
+        let mut symmetry = Symmetry {
+            mode: SymmetryMode::Both,
+            center: Point2D { x: 10.0, y: 10.0 },
+            folds: 0,
+        };
+        symmetry.sanitize();
+        let xs: Vec<f32> = symmetry
+            .apply(&stroke)
+            .iter()
+            .map(|copy| copy[0].0.x.round())
+            .collect();
+        assert_eq!(xs, vec![15.0, 5.0, 15.0, 5.0]);
+
+        symmetry.mode = SymmetryMode::Kaleidoscope;
+        symmetry.folds = 4;
+        let copies = symmetry.apply(&stroke);
+        assert_eq!(copies.len(), 8);
+        let quarter = copies[2][0].0;
+        assert!((quarter.x - 10.0).abs() < 1e-4 && (quarter.y - 15.0).abs() < 1e-4);
+    }
+    #[test]
+    fn engines_mirror_a_stroke_as_one_undo_step() {
+        for handle in test_engines(64, 16) {
+            let entry = lookup_engine(handle).unwrap();
+            let send = |cmd: EngineCommand| entry.cmd_tx.send(cmd).unwrap();
+            send(EngineCommand::ResetCanvas {
+                background_color_argb: 0xFFFFFFFF,
+            });
+            send(brush(0xFF000000, 2.0));
+            send(EngineCommand::SetSymmetry {
+                mode: 1,
+                center_x: 32.0,
+                center_y: 8.0,
+                folds: 0,
+            });
+            push_points(
+                &entry,
+                vec![point(6.0, 8.0, 1), point(12.0, 8.0, 2), point(18.0, 8.0, 4)],
+            );
+            let pixels = read_layer(&entry, 0);
+            let backend = entry.backend;
+            assert_eq!(pixels[8 * 64 + 12], 0xFF000000, "{backend:?}: stroke");
+            assert_eq!(pixels[8 * 64 + 51], 0xFF000000, "{backend:?}: mirrored copy");
+            assert_eq!(pixels[8 * 64 + 32], 0xFFFFFFFF, "{backend:?}: centre");
+
+            send(EngineCommand::Undo);
+            let undone = read_layer(&entry, 0);
+            assert!(undone.iter().all(|&px| px == 0xFFFFFFFF), "{backend:?}: undo");
+            stop_engine(handle);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Compression for undo tile patches that are moved out of VRAM.
+//!
+//! Patches are mostly fully transparent or flat-coloured, so texels are run
+//! length encoded: each chunk starts with a LEB128 header `(count << 1) | run`
+//! followed by one texel for a run or `count` texels for a literal stretch.
+
+// Shorter repeats cost more as a run than as literals.
+const MIN_RUN: usize = 3;
+
+pub(crate) fn encode_texels(texels: &[u32]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut literal_start = 0;
+    let mut index = 0;
+    while index < texels.len() {
+        let value = texels[index];
+        let run = texels[index..]
+            .iter()
+            .take_while(|&&texel| texel == value)
+            .count();
+        if run >= MIN_RUN {
+            write_literals(&mut out, &texels[literal_start..index]);
+            write_header(&mut out, run, true);
+            out.extend_from_slice(&value.to_le_bytes());
+            literal_start = index + run;
+        }
+        index += run;
+    }
+    write_literals(&mut out, &texels[literal_start..]);
+    out
+}
+
+/// Decodes `data` written by [`encode_texels`], which must hold exactly
+/// `texel_count` texels.
+pub(crate) fn decode_texels(data: &[u8], texel_count: usize) -> Result<Vec<u32>, String> {
+    let mut out: Vec<u32> = Vec::with_capacity(texel_count);
+    let mut input = data;
+    while !input.is_empty() {
+        let header = read_header(&mut input)?;
+        let (count, run) = (header >> 1, header & 1 == 1);
+        if count > texel_count - out.len() {
+            return Err("tile_codec: chunk runs past the tile".to_string());
+        }
+        if run {
+            let value = read_texel(&mut input)?;
+            out.resize(out.len() + count, value);
+        } else {
+            for _ in 0..count {
+                out.push(read_texel(&mut input)?);
+            }
+        }
+    }
+    if out.len() != texel_count {
+        return Err(format!(
+            "tile_codec: decoded {} texels, expected {texel_count}",
+            out.len()
+        ));
+    }
+    Ok(out)
+}
+
+fn write_literals(out: &mut Vec<u8>, texels: &[u32]) {
+    if texels.is_empty() {
+        return;
+    }
+    write_header(out, texels.len(), false);
+    for texel in texels {
+        out.extend_from_slice(&texel.to_le_bytes());
+    }
+}
+
+fn write_header(out: &mut Vec<u8>, count: usize, run: bool) {
+    let mut value = (count << 1) | usize::from(run);
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_header(input: &mut &[u8]) -> Result<usize, String> {
+    let mut value: usize = 0;
+    let mut shift = 0;
+    loop {
+        let Some((&byte, rest)) = input.split_first() else {
+            return Err("tile_codec: truncated chunk header".to_string());
+        };
+        *input = rest;
+        if shift >= usize::BITS {
+            return Err("tile_codec: chunk header overflow".to_string());
+        }
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+fn read_texel(input: &mut &[u8]) -> Result<u32, String> {
+    if input.len() < 4 {
+        return Err("tile_codec: truncated texel".to_string());
+    }
+    let (bytes, rest) = input.split_at(4);
+    *input = rest;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparse_tile_round_trips_compactly() {
+        let mut texels = vec![0u32; 256 * 256];
+        for (i, texel) in texels.iter_mut().enumerate().skip(1000).take(300) {
+            *texel = if i % 2 == 0 { 0xff20_4060 } else { 0x8010_2030 };
+        }
+        texels[5000..5002].fill(0xffff_ffff);
+
+        let encoded = encode_texels(&texels);
+        assert!(encoded.len() < 2_000);
+        assert_eq!(decode_texels(&encoded, texels.len()).unwrap(), texels);
+        assert!(decode_texels(&encoded[..encoded.len() - 1], texels.len()).is_err());
+        assert!(decode_texels(&encoded, texels.len() + 1).is_err());
+    }
+}
@@ -0,0 +1,384 @@
+/// Edge length of a layer storage tile, in pixels.
+pub(crate) const LAYER_TILE_SIZE: u32 = 256;
+
+const TILE_PIXELS: usize = (LAYER_TILE_SIZE as usize) * (LAYER_TILE_SIZE as usize);
+
+fn tile_count(extent: u32) -> u32 {
+    extent.div_ceil(LAYER_TILE_SIZE)
+}
+
+/// Clamps `(left, top, width, height)` to the canvas and returns it as
+/// `(left, top, right, bottom)`, or `None` when nothing is left.
+fn clamp_rect(rect: (i32, i32, i32, i32), width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+    let (left, top, rect_width, rect_height) = rect;
+    if rect_width <= 0 || rect_height <= 0 {
+        return None;
+    }
+    let x0 = (left.max(0) as u32).min(width);
+    let y0 = (top.max(0) as u32).min(height);
+    let x1 = (left.saturating_add(rect_width).max(0) as u32).min(width);
+    let y1 = (top.saturating_add(rect_height).max(0) as u32).min(height);
+    if x1 <= x0 || y1 <= y0 {
+        return None;
+    }
+    Some((x0, y0, x1, y1))
+}
+
+/// Canvas-space `(x0, y0, x1, y1)` of a tile's non-transparent pixels, or
+/// `None` when the tile is fully transparent.
+type TileExtent = Option<(u32, u32, u32, u32)>;
+
+/// Sparse ARGB layer storage. Only tiles that differ from the layer's uniform
+/// `fill` colour own pixel memory, so mostly empty layers cost a few bytes
+/// instead of width × height × 4.
+pub(crate) struct TiledLayer {
+    width: u32,
+    height: u32,
+    tiles_x: u32,
+    tiles_y: u32,
+    // Row-major; edge tiles are padded to the full tile size.
+    tiles: Vec<Option<Box<[u32]>>>,
+    fill: u32,
+}
+
+impl TiledLayer {
+    pub(crate) fn new(width: u32, height: u32) -> Self {
+        let tiles_x = tile_count(width);
+        let tiles_y = tile_count(height);
+        let mut tiles = Vec::new();
+        tiles.resize_with((tiles_x as usize) * (tiles_y as usize), || None);
+        Self {
+            width,
+            height,
+            tiles_x,
+            tiles_y,
+            tiles,
+            fill: 0,
+        }
+    }
+
+    /// Makes every pixel `color` and releases all tile memory.
+    pub(crate) fn fill(&mut self, color: u32) {
+        for tile in self.tiles.iter_mut() {
+            *tile = None;
+        }
+        self.fill = color;
+    }
+
+    /// Replaces the whole layer with a dense canvas-sized buffer.
+    pub(crate) fn set_pixels(&mut self, pixels: &[u32]) {
+        let expected = (self.width as usize) * (self.height as usize);
+        if pixels.len() != expected {
+            return;
+        }
+        let first = pixels.first().copied().unwrap_or(0);
+        // A uniform buffer (typically a white background) needs no tiles at all.
+        self.fill(if pixels.iter().all(|&px| px == first) {
+            first
+        } else {
+            0
+        });
+        self.write_rect((0, 0, self.width as i32, self.height as i32), pixels);
+    }
+
+    pub(crate) fn to_pixels(&self) -> Vec<u32> {
+        self.read_rect((0, 0, self.width as i32, self.height as i32))
+    }
+
+    pub(crate) fn pixel(&self, x: u32, y: u32) -> u32 {
+        if x >= self.width || y >= self.height {
+            return 0;
+        }
+        let index = self.tile_index(x / LAYER_TILE_SIZE, y / LAYER_TILE_SIZE);
+        match &self.tiles[index] {
+            Some(tile) => {
+                tile[((y % LAYER_TILE_SIZE) * LAYER_TILE_SIZE + x % LAYER_TILE_SIZE) as usize]
+            }
+            None => self.fill,
+        }
+    }
+
+    /// Copies `(left, top, width, height)` into a dense row-major buffer.
+    /// Pixels outside the canvas read as transparent.
+    pub(crate) fn read_rect(&self, rect: (i32, i32, i32, i32)) -> Vec<u32> {
+        let (left, top, rect_width, rect_height) = rect;
+        let out_width = rect_width.max(0) as usize;
+        let mut out = vec![0u32; out_width * rect_height.max(0) as usize];
+        let Some((x0, y0, x1, y1)) = clamp_rect(rect, self.width, self.height) else {
+            return out;
+        };
+        for (tx, ty, tile_x0, tile_y0, tile_x1, tile_y1) in self.tiles_in(x0, y0, x1, y1) {
+            let tile = &self.tiles[self.tile_index(tx, ty)];
+            let span = (tile_x1 - tile_x0) as usize;
+            for y in tile_y0..tile_y1 {
+                let dst_start = ((y as i64 - top as i64) as usize) * out_width
+                    + (tile_x0 as i64 - left as i64) as usize;
+                let dst = &mut out[dst_start..dst_start + span];
+                match tile {
+                    Some(tile) => {
+                        let src_start = ((y % LAYER_TILE_SIZE) * LAYER_TILE_SIZE
+                            + tile_x0 % LAYER_TILE_SIZE)
+                            as usize;
+                        dst.copy_from_slice(&tile[src_start..src_start + span]);
+                    }
+                    None => dst.fill(self.fill),
+                }
+            }
+        }
+        out
+    }
+
+    /// Writes a dense row-major buffer covering `(left, top, width, height)`.
+    /// Tiles are only allocated where the written pixels differ from the
+    /// layer's fill colour.
+    pub(crate) fn write_rect(&mut self, rect: (i32, i32, i32, i32), pixels: &[u32]) {
+        let (left, top, rect_width, rect_height) = rect;
+        let src_width = rect_width.max(0) as usize;
+        if pixels.len() != src_width * rect_height.max(0) as usize {
+            return;
+        }
+        let Some((x0, y0, x1, y1)) = clamp_rect(rect, self.width, self.height) else {
+            return;
+        };
+        let fill = self.fill;
+        for (tx, ty, tile_x0, tile_y0, tile_x1, tile_y1) in self.tiles_in(x0, y0, x1, y1) {
+            let span = (tile_x1 - tile_x0) as usize;
+            let src_row = |y: u32| {
+                let start = ((y as i64 - top as i64) as usize) * src_width
+                    + (tile_x0 as i64 - left as i64) as usize;
+                &pixels[start..start + span]
+            };
+            let index = self.tile_index(tx, ty);
+            if self.tiles[index].is_none() {
+                if (tile_y0..tile_y1).all(|y| src_row(y).iter().all(|&px| px == fill)) {
+                    continue;
+                }
+                self.tiles[index] = Some(vec![fill; TILE_PIXELS].into_boxed_slice());
+            }
+            let Some(tile) = self.tiles[index].as_mut() else {
+                continue;
+            };
+            for y in tile_y0..tile_y1 {
+                let dst_start =
+                    ((y % LAYER_TILE_SIZE) * LAYER_TILE_SIZE + tile_x0 % LAYER_TILE_SIZE) as usize;
+                tile[dst_start..dst_start + span].copy_from_slice(src_row(y));
+            }
+        }
+    }
+
+    /// Releases tiles inside `rect` that went back to the fill colour, e.g.
+    /// after erasing or undoing a stroke.
+    pub(crate) fn release_uniform_tiles(&mut self, rect: (i32, i32, i32, i32)) {
+        let Some((x0, y0, x1, y1)) = clamp_rect(rect, self.width, self.height) else {
+            return;
+        };
+        let fill = self.fill;
+        for (tx, ty, ..) in self.tiles_in(x0, y0, x1, y1) {
+            let index = self.tile_index(tx, ty);
+            let uniform = self.tiles[index]
+                .as_ref()
+                .is_some_and(|tile| self.tile_matches(tx, ty, tile, |px| px == fill));
+            if uniform {
+                self.tiles[index] = None;
+            }
+        }
+    }
+
+    /// Bytes of pixel memory held by allocated tiles.
+    pub(crate) fn allocated_bytes(&self) -> u64 {
+        let tiles = self.tiles.iter().filter(|tile| tile.is_some()).count();
+        (tiles * TILE_PIXELS * std::mem::size_of::<u32>()) as u64
+    }
+
+    /// Pixel rect `(left, top, right, bottom)` covering every allocated tile,
+    /// the whole canvas for an opaque fill, or `None` for an empty layer.
+    /// Cheap, but tiles that were painted and then erased still count.
+    pub(crate) fn occupied_rect(&self) -> Option<(u32, u32, u32, u32)> {
+        if (self.fill >> 24) != 0 {
+            return Some((0, 0, self.width, self.height));
+        }
+        let mut rect: Option<(u32, u32, u32, u32)> = None;
+        for ty in 0..self.tiles_y {
+            for tx in 0..self.tiles_x {
+                if self.tiles[self.tile_index(tx, ty)].is_none() {
+                    continue;
+                }
+                rect = Some(match rect {
+                    Some((x0, y0, x1, y1)) => (x0.min(tx), y0.min(ty), x1.max(tx), y1.max(ty)),
+                    None => (tx, ty, tx, ty),
+                });
+            }
+        }
+        let (tx0, ty0, tx1, ty1) = rect?;
+        Some((
+            tx0 * LAYER_TILE_SIZE,
+            ty0 * LAYER_TILE_SIZE,
+            ((tx1 + 1) * LAYER_TILE_SIZE).min(self.width),
+            ((ty1 + 1) * LAYER_TILE_SIZE).min(self.height),
+        ))
+    }
+
+    /// Bounding box `(left, top, right, bottom)` of non-transparent pixels.
+    /// Only tiles on the outer rows/columns of the allocated set are scanned.
+    /// Empty layers report the full canvas, like the dense scan did.
+    pub(crate) fn bounds(&self) -> (i32, i32, i32, i32) {
+        let full = (0, 0, self.width as i32, self.height as i32);
+        if (self.fill >> 24) != 0 {
+            return full;
+        }
+        let mut extents: Vec<Option<TileExtent>> = vec![None; self.tiles.len()];
+        let mut extent_of = |layer: &Self, tx: u32, ty: u32| -> TileExtent {
+            let index = layer.tile_index(tx, ty);
+            *extents[index].get_or_insert_with(|| layer.tile_alpha_extent(tx, ty))
+        };
+
+        let mut min_x: Option<u32> = None;
+        for tx in 0..self.tiles_x {
+            for ty in 0..self.tiles_y {
+                if let Some((x0, ..)) = extent_of(self, tx, ty) {
+                    min_x = Some(min_x.map_or(x0, |v| v.min(x0)));
+                }
+            }
+            if min_x.is_some() {
+                break;
+            }
+        }
+        let Some(min_x) = min_x else {
+            return full;
+        };
+        let mut max_x = min_x;
+        for tx in (0..self.tiles_x).rev() {
+            let mut found = false;
+            for ty in 0..self.tiles_y {
+                if let Some((_, _, x1, _)) = extent_of(self, tx, ty) {
+                    max_x = max_x.max(x1);
+                    found = true;
+                }
+            }
+            if found {
+                break;
+            }
+        }
+        let mut min_y = self.height;
+        for ty in 0..self.tiles_y {
+            let mut found = false;
+            for tx in 0..self.tiles_x {
+                if let Some((_, y0, _, _)) = extent_of(self, tx, ty) {
+                    min_y = min_y.min(y0);
+                    found = true;
+                }
+            }
+            if found {
+                break;
+            }
+        }
+        let mut max_y = min_y;
+        for ty in (0..self.tiles_y).rev() {
+            let mut found = false;
+            for tx in 0..self.tiles_x {
+                if let Some((_, _, _, y1)) = extent_of(self, tx, ty) {
+                    max_y = max_y.max(y1);
+                    found = true;
+                }
+            }
+            if found {
+                break;
+            }
+        }
+        (min_x as i32, min_y as i32, max_x as i32, max_y as i32)
+    }
+
+    fn tile_index(&self, tx: u32, ty: u32) -> usize {
+        (ty as usize) * (self.tiles_x as usize) + tx as usize
+    }
+
+    /// Canvas-space extent `(x0, y0, x1, y1)` of non-transparent pixels in
+    /// an allocated tile.
+    fn tile_alpha_extent(&self, tx: u32, ty: u32) -> TileExtent {
+        let tile = self.tiles[self.tile_index(tx, ty)].as_ref()?;
+        let origin_x = tx * LAYER_TILE_SIZE;
+        let origin_y = ty * LAYER_TILE_SIZE;
+        let valid_width = LAYER_TILE_SIZE.min(self.width - origin_x);
+        let valid_height = LAYER_TILE_SIZE.min(self.height - origin_y);
+        let mut extent: Option<(u32, u32, u32, u32)> = None;
+        for y in 0..valid_height {
+            let row = &tile[(y * LAYER_TILE_SIZE) as usize..][..valid_width as usize];
+            let Some(first) = row.iter().position(|&px| (px >> 24) != 0) else {
+                continue;
+            };
+            let last = row.iter().rposition(|&px| (px >> 24) != 0).unwrap_or(first);
+            let (x0, x1) = (origin_x + first as u32, origin_x + last as u32 + 1);
+            let (y0, y1) = (origin_y + y, origin_y + y + 1);
+            extent = Some(match extent {
+                Some((ex0, ey0, ex1, _)) => (ex0.min(x0), ey0, ex1.max(x1), y1),
+                None => (x0, y0, x1, y1),
+            });
+        }
+        extent
+    }
+
+    fn tile_matches(
+        &self,
+        tx: u32,
+        ty: u32,
+        tile: &[u32],
+        predicate: impl Fn(u32) -> bool,
+    ) -> bool {
+        let valid_width = LAYER_TILE_SIZE.min(self.width - tx * LAYER_TILE_SIZE) as usize;
+        let valid_height = LAYER_TILE_SIZE.min(self.height - ty * LAYER_TILE_SIZE) as usize;
+        tile.chunks_exact(LAYER_TILE_SIZE as usize)
+            .take(valid_height)
+            .all(|row| row[..valid_width].iter().all(|&px| predicate(px)))
+    }
+
+    /// Tiles overlapping `[x0, x1) × [y0, y1)` together with the clipped
+    /// canvas-space span inside each tile.
+    fn tiles_in(
+        &self,
+        x0: u32,
+        y0: u32,
+        x1: u32,
+        y1: u32,
+    ) -> impl Iterator<Item = (u32, u32, u32, u32, u32, u32)> {
+        let tx0 = x0 / LAYER_TILE_SIZE;
+        let ty0 = y0 / LAYER_TILE_SIZE;
+        let tx1 = (x1 - 1) / LAYER_TILE_SIZE;
+        let ty1 = (y1 - 1) / LAYER_TILE_SIZE;
+        (ty0..=ty1).flat_map(move |ty| {
+            (tx0..=tx1).map(move |tx| {
+                let tile_left = tx * LAYER_TILE_SIZE;
+                let tile_top = ty * LAYER_TILE_SIZE;
+                (
+                    tx,
+                    ty,
+                    x0.max(tile_left),
+                    y0.max(tile_top),
+                    x1.min(tile_left + LAYER_TILE_SIZE),
+                    y1.min(tile_top + LAYER_TILE_SIZE),
+                )
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_allocate_only_touched_tiles() {
+        let mut layer = TiledLayer::new(600, 300);
+        let mut dab = vec![0u32; 4 * 4];
+        dab[5] = 0xFF00_00FF;
+        layer.write_rect((300, 10, 4, 4), &dab);
+        assert_eq!(layer.tiles.iter().filter(|tile| tile.is_some()).count(), 1);
+        assert_eq!(layer.pixel(301, 11), 0xFF00_00FF);
+        assert_eq!(layer.bounds(), (301, 11, 302, 12));
+
+        // Writing back the fill colour leaves nothing allocated.
+        layer.write_rect((300, 10, 4, 4), &[0; 16]);
+        layer.release_uniform_tiles((300, 10, 4, 4));
+        assert!(layer.tiles.iter().all(|tile| tile.is_none()));
+        assert_eq!(layer.bounds(), (0, 0, 600, 300));
+    }
+}
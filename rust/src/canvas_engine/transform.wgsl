@@ -41,8 +41,8 @@ fn load_layer_pixel(x: i32, y: i32, layer: i32) -> vec4<f32> {
   if (x < 0 || y < 0 || x >= i32(dims.x) || y >= i32(dims.y)) {
     return vec4<f32>(0.0, 0.0, 0.0, 0.0);
   }
-  let packed = textureLoad(src_tex, vec2<i32>(x, y), layer, 0).x;
-  return unpack_straight_rgba(packed);
+  let texel = textureLoad(src_tex, vec2<i32>(x, y), layer, 0).x;
+  return unpack_straight_rgba(texel);
 }
 
 fn sample_nearest(coord: vec2<f32>, layer: i32) -> u32 {
@@ -91,6 +91,6 @@ fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
   }
   let board_pos = vec2<f32>(f32(x) + 0.5, f32(y) + 0.5);
   let src = (cfg.matrix * vec4<f32>(board_pos, 0.0, 1.0)).xy;
-  let packed = sample_transformed(src, i32(cfg.layer_index));
-  textureStore(dst_tex, vec2<i32>(x, y), vec4<u32>(packed, 0u, 0u, 0u));
+  let texel = sample_transformed(src, i32(cfg.layer_index));
+  textureStore(dst_tex, vec2<i32>(x, y), vec4<u32>(texel, 0u, 0u, 0u));
 }
@@ -8,6 +8,15 @@ pub struct EnginePoint {
     pub timestamp_us: u64,
     pub flags: u32,
     pub pointer_id: u32,
+    /// Radians away from perpendicular to the surface, `0..=PI/2`.
+    pub tilt: f32,
+    /// Direction the pen leans, in canvas radians (`atan2` of a canvas
+    /// vector; y points down).
+    pub azimuth: f32,
+    /// Barrel rotation in radians; 0 for pens that do not report it.
+    pub rotation: f32,
+    /// Input device the point came from; picks its pressure calibration.
+    pub device: u32,
 }
 
 #[repr(C)]
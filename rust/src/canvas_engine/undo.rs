@@ -1,9 +1,126 @@
 use std::collections::HashMap;
+use std::sync::mpsc;
 
+use crate::api::engine_history::{EngineHistoryKind, EngineHistoryNode};
+use crate::gpu::debug::{self, LogLevel};
 use crate::gpu::layer_format::LAYER_TEXTURE_FORMAT;
 
+use super::engine::EngineLayerEdit;
+use super::history::{HistoryMove, HistoryThumbnail, UndoHistory};
+use super::layers::LayerStore;
+use super::masks::{LayerMaskState, LayerMasks};
+use super::tile_codec::{decode_texels, encode_texels};
+
 const UNDO_TILE_SIZE: u32 = 256;
-const UNDO_STACK_LIMIT: usize = 50;
+/// Default number of steps kept on the undo stack.
+pub(crate) const UNDO_STACK_LIMIT: usize = 50;
+/// Default bytes of tile patches kept ready to apply (VRAM textures here, raw
+/// tiles in the CPU engine) before the oldest ones are compressed.
+pub(crate) const UNDO_BYTE_BUDGET: u64 = 256 * 1024 * 1024;
+const BYTES_PER_TEXEL: u32 = 4;
+
+/// Which texture of a layer an undo record restores.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum UndoTarget {
+    Layer,
+    Mask,
+}
+
+/// Pixels changed by a committed, undone or redone step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct UndoDirtyRect {
+    pub(crate) layer_index: u32,
+    pub(crate) target: UndoTarget,
+    /// `(left, top, width, height)`
+    pub(crate) rect: (u32, u32, u32, u32),
+}
+
+impl UndoDirtyRect {
+    /// Union of the `(left, top, width, height)` tiles of one step, or `None`
+    /// when it has none.
+    pub(crate) fn covering(
+        layer_index: u32,
+        target: UndoTarget,
+        tiles: impl IntoIterator<Item = (u32, u32, u32, u32)>,
+    ) -> Option<Self> {
+        let (left, top, right, bottom) = tiles.into_iter().fold(
+            None,
+            |acc: Option<(u32, u32, u32, u32)>, (left, top, width, height)| {
+                let (right, bottom) = (left + width, top + height);
+                Some(match acc {
+                    Some((l, t, r, b)) => (l.min(left), t.min(top), r.max(right), b.max(bottom)),
+                    None => (left, top, right, bottom),
+                })
+            },
+        )?;
+        Some(Self {
+            layer_index,
+            target,
+            rect: (left, top, right - left, bottom - top),
+        })
+    }
+}
+
+/// Compositing settings of one layer, as a property step restores them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct LayerProperties {
+    pub(crate) opacity: f32,
+    pub(crate) visible: bool,
+    pub(crate) clipping_mask: bool,
+    pub(crate) alpha_locked: bool,
+    pub(crate) blend_mode: u32,
+    /// Whether the layer's mask applies; ignored while it has none.
+    pub(crate) mask_enabled: bool,
+}
+
+/// A history step that changes layers rather than their pixels. Applying an
+/// edit yields its inverse, which then moves to the opposite stack.
+pub(crate) enum LayerEdit<L, C> {
+    /// Puts `properties` back on `layer_index`.
+    Properties {
+        layer_index: u32,
+        properties: LayerProperties,
+    },
+    /// Moves the layer at `from` to `to`, both final positions.
+    Reorder { from: u32, to: u32 },
+    /// Takes out the layer at `layer_index`.
+    Remove { layer_index: u32 },
+    /// Puts a removed layer back at `layer_index`.
+    Insert { layer_index: u32, layer: Box<L> },
+    /// Swaps the whole canvas with the one held here.
+    Canvas(Box<C>),
+}
+
+/// What an undo or redo call did. An edit is handed back to the engine,
+/// which applies it and returns the inverse through `finish_undo` or
+/// `finish_redo`.
+pub(crate) enum UndoApplied<E> {
+    Nothing,
+    Pixels,
+    Edit(E),
+}
+
+/// Whether a property change from `before` to `after` extends the step on
+/// top of the stack instead of adding one. Opacity sliders send a change per
+/// drag frame; they collapse into the step that started the drag.
+pub(crate) fn extends_property_step<L, C>(
+    top: Option<&LayerEdit<L, C>>,
+    layer_index: u32,
+    before: LayerProperties,
+    after: LayerProperties,
+) -> bool {
+    let Some(LayerEdit::Properties {
+        layer_index: top_index,
+        properties: first,
+    }) = top
+    else {
+        return false;
+    };
+    let only_opacity = |a: LayerProperties, b: LayerProperties| {
+        a.opacity != b.opacity && LayerProperties { opacity: b.opacity, ..a } == b
+    };
+    *top_index == layer_index && only_opacity(*first, before) && only_opacity(before, after)
+}
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 struct UndoTileKey {
@@ -25,20 +142,59 @@ struct UndoTileBefore {
     before: wgpu::Texture,
 }
 
+impl UndoTileRect {
+    fn texel_count(self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    fn extent(self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        }
+    }
+
+    fn as_dirty(self) -> (i32, i32, i32, i32) {
+        (self.left as i32, self.top as i32, self.width as i32, self.height as i32)
+    }
+}
+
+/// One side of a tile patch.
+enum UndoTileData {
+    Texture(wgpu::Texture),
+    /// Read back and compressed with `tile_codec`; uploaded straight into the
+    /// layer when the step is applied.
+    Encoded(Vec<u8>),
+}
+
 struct UndoTilePatch {
     rect: UndoTileRect,
-    before: wgpu::Texture,
-    after: wgpu::Texture,
+    before: UndoTileData,
+    after: UndoTileData,
 }
 
 struct UndoRecord {
     layer_index: u32,
+    target: UndoTarget,
     tiles: Vec<UndoTilePatch>,
+    // (before, after) when the step also created, removed or toggled a mask.
+    mask_change: Option<(LayerMaskState, LayerMaskState)>,
+    thumbnail: Option<HistoryThumbnail>,
 }
 
 struct ActiveStrokeUndo {
     layer_index: u32,
+    target: UndoTarget,
     tiles: HashMap<UndoTileKey, UndoTileBefore>,
+    mask_change: Option<(LayerMaskState, LayerMaskState)>,
+}
+
+enum UndoStep {
+    /// Undone last to first and redone first to last; more than one record
+    /// when the step was a transaction.
+    Pixels(Vec<UndoRecord>),
+    Edit(EngineLayerEdit),
 }
 
 pub(crate) struct UndoManager {
@@ -46,9 +202,177 @@ pub(crate) struct UndoManager {
     canvas_height: u32,
     tile_size: u32,
     max_steps: usize,
-    undo_stack: Vec<UndoRecord>,
-    redo_stack: Vec<UndoRecord>,
+    vram_budget: u64,
+    history: UndoHistory<UndoStep>,
     current: Option<ActiveStrokeUndo>,
+    // Records finished since `begin_transaction`, committed as one step.
+    transaction: Option<Vec<UndoRecord>>,
+    dirty: Vec<UndoDirtyRect>,
+    layers_changed: bool,
+}
+
+impl UndoRecord {
+    fn dirty_rect(&self) -> Option<UndoDirtyRect> {
+        UndoDirtyRect::covering(
+            self.layer_index,
+            self.target,
+            self.tiles
+                .iter()
+                .map(|tile| (tile.rect.left, tile.rect.top, tile.rect.width, tile.rect.height)),
+        )
+    }
+
+    fn resident_bytes(&self) -> u64 {
+        self.tiles
+            .iter()
+            .flat_map(|tile| [(&tile.before, tile.rect), (&tile.after, tile.rect)])
+            .filter(|(data, _)| matches!(data, UndoTileData::Texture(_)))
+            .map(|(_, rect)| rect.texel_count() as u64 * BYTES_PER_TEXEL as u64)
+            .sum()
+    }
+
+    /// Preview of the pixels the record leaves behind, read back once.
+    fn thumbnail(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<HistoryThumbnail, String> {
+        if let Some(thumbnail) = &self.thumbnail {
+            return Ok(thumbnail.clone());
+        }
+        let sources: Vec<(&wgpu::Texture, UndoTileRect)> = self
+            .tiles
+            .iter()
+            .filter_map(|tile| match &tile.after {
+                UndoTileData::Texture(texture) => Some((texture, tile.rect)),
+                UndoTileData::Encoded(_) => None,
+            })
+            .collect();
+        let mut read = read_back_tiles(device, queue, &sources)?.into_iter();
+        let mut tiles = Vec::with_capacity(self.tiles.len());
+        for tile in &self.tiles {
+            let texels = match &tile.after {
+                UndoTileData::Texture(_) => read.next().unwrap_or_default(),
+                UndoTileData::Encoded(encoded) => {
+                    decode_texels(encoded, tile.rect.texel_count())?
+                }
+            };
+            let rect = tile.rect;
+            tiles.push(((rect.left, rect.top, rect.width, rect.height), texels));
+        }
+        let rect = self.dirty_rect().map_or((0, 0, 0, 0), |dirty| dirty.rect);
+        let thumbnail = HistoryThumbnail::from_tiles(rect, &tiles);
+        self.thumbnail = Some(thumbnail.clone());
+        Ok(thumbnail)
+    }
+
+    /// Moves the resident tiles of the record to system memory.
+    fn spill(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<(), String> {
+        let mut sources: Vec<(&wgpu::Texture, UndoTileRect)> = Vec::new();
+        for tile in &self.tiles {
+            for data in [&tile.before, &tile.after] {
+                if let UndoTileData::Texture(texture) = data {
+                    sources.push((texture, tile.rect));
+                }
+            }
+        }
+        let mut texels = read_back_tiles(device, queue, &sources)?.into_iter();
+        for tile in &mut self.tiles {
+            for data in [&mut tile.before, &mut tile.after] {
+                if let UndoTileData::Texture(_) = data {
+                    *data =
+                        UndoTileData::Encoded(encode_texels(&texels.next().unwrap_or_default()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the before (or, for `redo`, after) pixels of every tile into
+    /// slice `slice` of `texture`.
+    fn apply(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        slice: u32,
+        redo: bool,
+    ) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some(if redo {
+                "misa-rin redo apply encoder"
+            } else {
+                "misa-rin undo apply encoder"
+            }),
+        });
+        let destination = |rect: UndoTileRect| wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d {
+                x: rect.left,
+                y: rect.top,
+                z: slice,
+            },
+            aspect: wgpu::TextureAspect::All,
+        };
+        for tile in &self.tiles {
+            match if redo { &tile.after } else { &tile.before } {
+                UndoTileData::Texture(source) => {
+                    encoder.copy_texture_to_texture(
+                        wgpu::ImageCopyTexture {
+                            texture: source,
+                            mip_level: 0,
+                            origin: wgpu::Origin3d::ZERO,
+                            aspect: wgpu::TextureAspect::All,
+                        },
+                        destination(tile.rect),
+                        tile.rect.extent(),
+                    );
+                }
+                UndoTileData::Encoded(encoded) => {
+                    let texels = match decode_texels(encoded, tile.rect.texel_count()) {
+                        Ok(texels) => texels,
+                        Err(err) => {
+                            debug::log(
+                                LogLevel::Warn,
+                                format_args!(
+                                    "undo tile at ({}, {}) skipped: {err}",
+                                    tile.rect.left, tile.rect.top
+                                ),
+                            );
+                            continue;
+                        }
+                    };
+                    let bytes: Vec<u8> = texels
+                        .iter()
+                        .flat_map(|texel| texel.to_le_bytes())
+                        .collect();
+                    queue.write_texture(
+                        destination(tile.rect),
+                        &bytes,
+                        wgpu::ImageDataLayout {
+                            offset: 0,
+                            bytes_per_row: Some(tile.rect.width * BYTES_PER_TEXEL),
+                            rows_per_image: Some(tile.rect.height),
+                        },
+                        tile.rect.extent(),
+                    );
+                }
+            }
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+}
+
+impl UndoStep {
+    fn resident_bytes(&self) -> u64 {
+        match self {
+            UndoStep::Pixels(records) => records.iter().map(UndoRecord::resident_bytes).sum(),
+            UndoStep::Edit(LayerEdit::Insert { layer, .. }) => layer.resident_bytes(),
+            UndoStep::Edit(LayerEdit::Canvas(canvas)) => canvas.resident_bytes(),
+            UndoStep::Edit(_) => 0,
+        }
+    }
 }
 
 impl UndoManager {
@@ -58,45 +382,213 @@ impl UndoManager {
             canvas_height,
             tile_size: UNDO_TILE_SIZE,
             max_steps: UNDO_STACK_LIMIT,
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
+            vram_budget: UNDO_BYTE_BUDGET,
+            history: UndoHistory::new(),
             current: None,
+            transaction: None,
+            dirty: Vec::new(),
+            layers_changed: false,
         }
     }
 
+    pub(crate) fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn resident_bytes(&mut self) -> u64 {
+        self.history.steps_mut().map(|step| step.resident_bytes()).sum()
+    }
+
+    pub(crate) fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    /// Drains the areas changed by steps committed, undone or redone since
+    /// the last call.
+    pub(crate) fn take_dirty(&mut self) -> Vec<UndoDirtyRect> {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// Whether an edit step was undone or redone since the last call.
+    pub(crate) fn take_layers_changed(&mut self) -> bool {
+        std::mem::take(&mut self.layers_changed)
+    }
+
+    /// Follows a canvas swap; an active step belongs to the old canvas.
+    pub(crate) fn set_canvas_size(&mut self, canvas_width: u32, canvas_height: u32) {
+        self.canvas_width = canvas_width;
+        self.canvas_height = canvas_height;
+        self.current = None;
+    }
+
     pub(crate) fn begin_stroke(&mut self, layer_index: u32) {
+        self.begin_target_stroke(layer_index, UndoTarget::Layer);
+    }
+
+    pub(crate) fn begin_target_stroke(&mut self, layer_index: u32, target: UndoTarget) {
         self.current = Some(ActiveStrokeUndo {
             layer_index,
+            target,
             tiles: HashMap::new(),
+            mask_change: None,
         });
     }
 
-    pub(crate) fn begin_stroke_if_needed(&mut self, layer_index: u32) {
+    pub(crate) fn begin_stroke_if_needed(&mut self, layer_index: u32, target: UndoTarget) {
         if self.current.is_none() {
-            self.begin_stroke(layer_index);
+            self.begin_target_stroke(layer_index, target);
+        }
+    }
+
+    /// Attaches a mask state change to the active step, so undo restores it
+    /// together with the captured tiles.
+    pub(crate) fn record_mask_change(
+        &mut self,
+        layer_index: u32,
+        before: LayerMaskState,
+        after: LayerMaskState,
+    ) {
+        let Some(active) = self.current.as_mut() else {
+            return;
+        };
+        if active.layer_index != layer_index || before == after {
+            return;
         }
+        let before = active.mask_change.map_or(before, |(first, _)| first);
+        active.mask_change = Some((before, after));
     }
 
     pub(crate) fn cancel_stroke(&mut self) {
         self.current = None;
     }
 
+    /// Keeps at most `max_steps` steps, branches included, and at most
+    /// `vram_budget` bytes in VRAM. Tile patches past the budget are
+    /// compressed into system memory, oldest first; removed layers and
+    /// replaced canvases can't be, so the oldest steps go until they fit.
+    pub(crate) fn set_limits(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        max_steps: usize,
+        vram_budget: u64,
+    ) {
+        self.max_steps = max_steps.max(1);
+        self.vram_budget = vram_budget;
+        self.history.trim(self.max_steps);
+        self.enforce_budget(device, queue);
+    }
+
+    fn enforce_budget(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut resident: u64 = self
+            .history
+            .steps_mut()
+            .map(|step| step.resident_bytes())
+            .sum();
+        'spill: for step in self.history.steps_mut() {
+            if resident <= self.vram_budget {
+                return;
+            }
+            let UndoStep::Pixels(records) = step else {
+                continue;
+            };
+            for record in records {
+                let bytes = record.resident_bytes();
+                if bytes == 0 {
+                    continue;
+                }
+                if let Err(err) = record.spill(device, queue) {
+                    debug::log(LogLevel::Warn, format_args!("undo spill failed: {err}"));
+                    break 'spill;
+                }
+                resident -= bytes;
+            }
+        }
+        while resident > self.vram_budget {
+            if self.history.evict_oldest().is_none() {
+                break;
+            }
+            resident = self
+                .history
+                .steps_mut()
+                .map(|step| step.resident_bytes())
+                .sum();
+        }
+    }
+
     pub(crate) fn reset(&mut self) {
-        self.undo_stack.clear();
-        self.redo_stack.clear();
+        self.history.clear();
         self.current = None;
+        self.transaction = None;
+    }
+
+    /// Collects the pixel steps finished from now on, on any layer, into one
+    /// step. Beginning while a transaction is open joins it.
+    pub(crate) fn begin_transaction(&mut self) {
+        self.transaction.get_or_insert_with(Vec::new);
+    }
+
+    pub(crate) fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    pub(crate) fn commit_transaction(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.close_transaction();
+        self.enforce_budget(device, queue);
+    }
+
+    // Layer edits, undo and redo close an open transaction first, so steps
+    // stay on the stack in the order they happened.
+    fn close_transaction(&mut self) {
+        if let Some(records) = self.transaction.take() {
+            if !records.is_empty() {
+                self.push_step(UndoStep::Pixels(records));
+            }
+        }
+    }
+
+    /// Drops the open transaction and puts back every pixel it changed, the
+    /// step still in progress included. Returns whether anything changed.
+    pub(crate) fn cancel_transaction(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layers: &mut LayerStore,
+        masks: &mut LayerMasks,
+    ) -> bool {
+        let Some(records) = self.transaction.take() else {
+            return false;
+        };
+        let mut restored = false;
+        if let Some(active) = self.current.as_ref() {
+            let (layer_index, target) = (active.layer_index, active.target);
+            if let Some((before, _)) = active.mask_change {
+                masks.set_state(layer_index as usize, before);
+                restored = true;
+            }
+            self.dirty.extend(UndoDirtyRect::covering(
+                layer_index,
+                target,
+                active.tiles.values().map(|tile| {
+                    (tile.rect.left, tile.rect.top, tile.rect.width, tile.rect.height)
+                }),
+            ));
+            restored |= self.restore_current_before(device, queue, layers, masks);
+            self.current = None;
+        }
+        apply_records(&records, device, queue, layers, masks, false);
+        self.dirty.extend(records.iter().filter_map(UndoRecord::dirty_rect));
+        restored || !records.is_empty()
     }
 
+    /// Follows a layer move for the active step. Steps on the stacks keep
+    /// their indices: every move is a step itself, so they are always
+    /// replayed in the layer order they were recorded in.
     pub(crate) fn reorder_layers(&mut self, from: u32, to: u32) {
         if from == to {
             return;
         }
-        for record in self.undo_stack.iter_mut() {
-            record.layer_index = remap_layer_index(record.layer_index, from, to);
-        }
-        for record in self.redo_stack.iter_mut() {
-            record.layer_index = remap_layer_index(record.layer_index, from, to);
-        }
         if let Some(active) = self.current.as_mut() {
             active.layer_index = remap_layer_index(active.layer_index, from, to);
             if !active.tiles.is_empty() {
@@ -117,12 +609,137 @@ impl UndoManager {
         }
     }
 
+    /// Records a property change of `layer_index`; no-op changes are skipped.
+    pub(crate) fn record_layer_properties(
+        &mut self,
+        layer_index: u32,
+        before: LayerProperties,
+        after: LayerProperties,
+    ) {
+        if before == after {
+            return;
+        }
+        self.close_transaction();
+        let top = match self.history.last_undo() {
+            Some(UndoStep::Edit(edit)) => Some(edit),
+            _ => None,
+        };
+        // With redo steps pending the change starts a branch instead.
+        if !self.history.can_redo() && extends_property_step(top, layer_index, before, after) {
+            return;
+        }
+        self.push_step(UndoStep::Edit(LayerEdit::Properties {
+            layer_index,
+            properties: before,
+        }));
+    }
+
+    /// Records `edit` as the step that undoes what the engine just did.
+    pub(crate) fn record_edit(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        edit: EngineLayerEdit,
+    ) {
+        self.close_transaction();
+        self.push_step(UndoStep::Edit(edit));
+        self.enforce_budget(device, queue);
+    }
+
+    /// Whether the next `undo` (or `redo`) puts a removed layer back.
+    pub(crate) fn next_edit_inserts_layer(&self, redo: bool) -> bool {
+        let next = if redo {
+            self.history.last_redo()
+        } else {
+            self.history.last_undo()
+        };
+        matches!(next, Some(UndoStep::Edit(LayerEdit::Insert { .. })))
+    }
+
+    /// Names the current state of the history; an empty name removes it.
+    pub(crate) fn set_checkpoint(&mut self, name: String) {
+        self.close_transaction();
+        self.history.set_checkpoint(name);
+    }
+
+    /// Undo, redo and branch moves from the current state to history state
+    /// `id`, or `None` when it is not kept.
+    pub(crate) fn history_route(&mut self, id: u64) -> Option<Vec<HistoryMove>> {
+        self.close_transaction();
+        self.history.route_to(id)
+    }
+
+    /// Makes redo follow the branch starting with history state `id`.
+    pub(crate) fn enter_history_branch(&mut self, id: u64) -> bool {
+        self.cancel_stroke();
+        self.close_transaction();
+        self.history.enter(id)
+    }
+
+    /// Every state of the history, with thumbnails of the layer pixels each
+    /// step changed.
+    pub(crate) fn history_nodes(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Vec<EngineHistoryNode> {
+        let nodes = self.history.nodes();
+        nodes
+            .into_iter()
+            .map(|node| {
+                let (kind, thumbnail) = match self.history.step_mut(node.id) {
+                    None => (EngineHistoryKind::Start, HistoryThumbnail::default()),
+                    Some(UndoStep::Edit(_)) => (EngineHistoryKind::Layers, HistoryThumbnail::default()),
+                    Some(UndoStep::Pixels(records)) => {
+                        let thumbnail = records
+                            .iter_mut()
+                            .find(|record| record.target == UndoTarget::Layer && !record.tiles.is_empty())
+                            .map_or(Ok(HistoryThumbnail::default()), |record| {
+                                record.thumbnail(device, queue)
+                            })
+                            .unwrap_or_else(|err| {
+                                debug::log(LogLevel::Warn, format_args!("history thumbnail failed: {err}"));
+                                HistoryThumbnail::default()
+                            });
+                        (EngineHistoryKind::Pixels, thumbnail)
+                    }
+                };
+                node.into_api(kind, thumbnail)
+            })
+            .collect()
+    }
+
+    /// Takes the inverse of an edit returned by `undo`.
+    pub(crate) fn finish_undo(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        edit: EngineLayerEdit,
+    ) {
+        self.history.push_redo(UndoStep::Edit(edit));
+        self.layers_changed = true;
+        self.enforce_budget(device, queue);
+    }
+
+    /// Takes the inverse of an edit returned by `redo`.
+    pub(crate) fn finish_redo(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        edit: EngineLayerEdit,
+    ) {
+        self.history.push_undo(UndoStep::Edit(edit));
+        self.layers_changed = true;
+        self.enforce_budget(device, queue);
+    }
+
     pub(crate) fn capture_before_for_dirty_rect(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         layer_texture: &wgpu::Texture,
         layer_index: u32,
+        slice: u32,
         dirty: (i32, i32, i32, i32),
     ) {
         let canvas_width = self.canvas_width;
@@ -209,7 +826,7 @@ impl UndoManager {
                         origin: wgpu::Origin3d {
                             x: rect.left,
                             y: rect.top,
-                            z: layer_index,
+                            z: slice,
                         },
                         aspect: wgpu::TextureAspect::All,
                     },
@@ -245,15 +862,30 @@ impl UndoManager {
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        layer_texture: &wgpu::Texture,
+        layers: &mut LayerStore,
+        masks: &mut LayerMasks,
     ) {
         let Some(active) = self.current.take() else {
             return;
         };
         if active.tiles.is_empty() {
+            if let Some(mask_change) = active.mask_change {
+                self.push_record(UndoRecord {
+                    layer_index: active.layer_index,
+                    target: active.target,
+                    tiles: Vec::new(),
+                    mask_change: Some(mask_change),
+                    thumbnail: None,
+                });
+            }
             return;
         }
 
+        let Some((layer_texture, slice)) =
+            stroke_surface(device, queue, layers, masks, active.layer_index, active.target)
+        else {
+            return;
+        };
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("misa-rin undo capture after encoder"),
         });
@@ -283,7 +915,7 @@ impl UndoManager {
                     origin: wgpu::Origin3d {
                         x: rect.left,
                         y: rect.top,
-                        z: active.layer_index,
+                        z: slice,
                     },
                     aspect: wgpu::TextureAspect::All,
                 },
@@ -302,29 +934,41 @@ impl UndoManager {
 
             patches.push(UndoTilePatch {
                 rect,
-                before: tile_before.before,
-                after: after_tex,
+                before: UndoTileData::Texture(tile_before.before),
+                after: UndoTileData::Texture(after_tex),
             });
         }
 
         queue.submit(Some(encoder.finish()));
 
-        self.undo_stack.push(UndoRecord {
+        self.push_record(UndoRecord {
             layer_index: active.layer_index,
+            target: active.target,
             tiles: patches,
+            mask_change: active.mask_change,
+            thumbnail: None,
         });
-        if self.undo_stack.len() > self.max_steps {
-            let overflow = self.undo_stack.len() - self.max_steps;
-            self.undo_stack.drain(0..overflow);
+        self.enforce_budget(device, queue);
+    }
+
+    fn push_record(&mut self, record: UndoRecord) {
+        self.dirty.extend(record.dirty_rect());
+        match self.transaction.as_mut() {
+            Some(records) => records.push(record),
+            None => self.push_step(UndoStep::Pixels(vec![record])),
         }
-        self.redo_stack.clear();
+    }
+
+    fn push_step(&mut self, step: UndoStep) {
+        self.history.push(step, self.max_steps);
     }
 
     pub(crate) fn restore_current_before(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        layer_texture: &wgpu::Texture,
+        layers: &mut LayerStore,
+        masks: &mut LayerMasks,
     ) -> bool {
         let Some(active) = self.current.as_ref() else {
             return false;
@@ -332,6 +976,11 @@ impl UndoManager {
         if active.tiles.is_empty() {
             return false;
         }
+        let Some((layer_texture, slice)) =
+            stroke_surface(device, queue, layers, masks, active.layer_index, active.target)
+        else {
+            return false;
+        };
 
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("misa-rin undo restore current before encoder"),
@@ -350,7 +999,7 @@ impl UndoManager {
                     origin: wgpu::Origin3d {
                         x: tile.rect.left,
                         y: tile.rect.top,
-                        z: active.layer_index,
+                        z: slice,
                     },
                     aspect: wgpu::TextureAspect::All,
                 },
@@ -362,6 +1011,13 @@ impl UndoManager {
             );
         }
         queue.submit(Some(encoder.finish()));
+        let index = active.layer_index as usize;
+        for tile in active.tiles.values() {
+            match active.target {
+                UndoTarget::Layer => layers.mark_rewritten(index, tile.rect.as_dirty()),
+                UndoTarget::Mask => masks.mark_dirty(index, tile.rect.as_dirty()),
+            }
+        }
         true
     }
 
@@ -369,102 +1025,119 @@ impl UndoManager {
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        layer_texture: &wgpu::Texture,
+        layers: &mut LayerStore,
+        masks: &mut LayerMasks,
         layer_count: usize,
-    ) -> bool {
+    ) -> UndoApplied<EngineLayerEdit> {
         self.cancel_stroke();
-        let Some(record) = self.undo_stack.pop() else {
-            return false;
+        self.close_transaction();
+        let records = match self.history.pop_undo() {
+            None => return UndoApplied::Nothing,
+            Some(UndoStep::Edit(edit)) => return UndoApplied::Edit(edit),
+            Some(UndoStep::Pixels(records)) => records,
         };
-        if (record.layer_index as usize) >= layer_count {
-            return false;
-        };
-        if record.tiles.is_empty() {
-            return false;
-        }
-
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("misa-rin undo apply encoder"),
-        });
-        for tile in &record.tiles {
-            encoder.copy_texture_to_texture(
-                wgpu::ImageCopyTexture {
-                    texture: &tile.before,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d::ZERO,
-                    aspect: wgpu::TextureAspect::All,
-                },
-                wgpu::ImageCopyTexture {
-                    texture: layer_texture,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d {
-                        x: tile.rect.left,
-                        y: tile.rect.top,
-                        z: record.layer_index,
-                    },
-                    aspect: wgpu::TextureAspect::All,
-                },
-                wgpu::Extent3d {
-                    width: tile.rect.width,
-                    height: tile.rect.height,
-                    depth_or_array_layers: 1,
-                },
-            );
+        if !records_fit(&records, layer_count) {
+            return UndoApplied::Nothing;
         }
-        queue.submit(Some(encoder.finish()));
-        self.redo_stack.push(record);
-        true
+        apply_records(&records, device, queue, layers, masks, false);
+        self.dirty.extend(records.iter().filter_map(UndoRecord::dirty_rect));
+        self.history.push_redo(UndoStep::Pixels(records));
+        UndoApplied::Pixels
     }
 
     pub(crate) fn redo(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        layer_texture: &wgpu::Texture,
+        layers: &mut LayerStore,
+        masks: &mut LayerMasks,
         layer_count: usize,
-    ) -> bool {
+    ) -> UndoApplied<EngineLayerEdit> {
         self.cancel_stroke();
-        let Some(record) = self.redo_stack.pop() else {
-            return false;
-        };
-        if (record.layer_index as usize) >= layer_count {
-            return false;
+        self.close_transaction();
+        let records = match self.history.pop_redo() {
+            None => return UndoApplied::Nothing,
+            Some(UndoStep::Edit(edit)) => return UndoApplied::Edit(edit),
+            Some(UndoStep::Pixels(records)) => records,
         };
-        if record.tiles.is_empty() {
-            return false;
+        if !records_fit(&records, layer_count) {
+            return UndoApplied::Nothing;
         }
+        apply_records(&records, device, queue, layers, masks, true);
+        self.dirty.extend(records.iter().filter_map(UndoRecord::dirty_rect));
+        self.history.push_undo(UndoStep::Pixels(records));
+        UndoApplied::Pixels
+    }
+}
 
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("misa-rin redo apply encoder"),
-        });
-        for tile in &record.tiles {
-            encoder.copy_texture_to_texture(
-                wgpu::ImageCopyTexture {
-                    texture: &tile.after,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d::ZERO,
-                    aspect: wgpu::TextureAspect::All,
-                },
-                wgpu::ImageCopyTexture {
-                    texture: layer_texture,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d {
-                        x: tile.rect.left,
-                        y: tile.rect.top,
-                        z: record.layer_index,
-                    },
-                    aspect: wgpu::TextureAspect::All,
-                },
-                wgpu::Extent3d {
-                    width: tile.rect.width,
-                    height: tile.rect.height,
-                    depth_or_array_layers: 1,
-                },
+/// Texture and array slice holding the pixels of `target` on `layer_index`,
+/// checking the layer or its mask out when needed.
+fn stroke_surface<'a>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layers: &'a mut LayerStore,
+    masks: &'a mut LayerMasks,
+    layer_index: u32,
+    target: UndoTarget,
+) -> Option<(&'a wgpu::Texture, u32)> {
+    let checked_out = match target {
+        UndoTarget::Mask => masks.checkout(device, queue, layer_index as usize),
+        UndoTarget::Layer => layers.checkout(device, queue, layer_index as usize),
+    };
+    match checked_out {
+        Ok(slot) => match target {
+            UndoTarget::Mask => Some((masks.texture(), slot)),
+            UndoTarget::Layer => Some((layers.texture(), slot)),
+        },
+        Err(err) => {
+            debug::log(
+                LogLevel::Warn,
+                format_args!("undo skipped layer {layer_index}: {err}"),
             );
+            None
+        }
+    }
+}
+
+fn records_fit(records: &[UndoRecord], layer_count: usize) -> bool {
+    records.iter().all(|record| (record.layer_index as usize) < layer_count)
+        && records
+            .iter()
+            .any(|record| !record.tiles.is_empty() || record.mask_change.is_some())
+}
+
+/// Puts back the before (or, for `redo`, after) state of a pixel step.
+fn apply_records(
+    records: &[UndoRecord],
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layers: &mut LayerStore,
+    masks: &mut LayerMasks,
+    redo: bool,
+) {
+    let mut ordered: Vec<&UndoRecord> = records.iter().collect();
+    if !redo {
+        ordered.reverse();
+    }
+    for record in ordered {
+        if !record.tiles.is_empty() {
+            let Some((texture, slice)) =
+                stroke_surface(device, queue, layers, masks, record.layer_index, record.target)
+            else {
+                continue;
+            };
+            record.apply(device, queue, texture, slice, redo);
+            let index = record.layer_index as usize;
+            for tile in &record.tiles {
+                match record.target {
+                    UndoTarget::Layer => layers.mark_rewritten(index, tile.rect.as_dirty()),
+                    UndoTarget::Mask => masks.mark_dirty(index, tile.rect.as_dirty()),
+                }
+            }
+        }
+        if let Some((before, after)) = record.mask_change {
+            masks.set_state(record.layer_index as usize, if redo { after } else { before });
         }
-        queue.submit(Some(encoder.finish()));
-        self.undo_stack.push(record);
-        true
     }
 }
 
@@ -486,3 +1159,175 @@ fn remap_layer_index(index: u32, from: u32, to: u32) -> u32 {
     }
     index
 }
+
+/// Reads back the texels of tile textures in one submission, in order.
+fn read_back_tiles(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    tiles: &[(&wgpu::Texture, UndoTileRect)],
+) -> Result<Vec<Vec<u32>>, String> {
+    if tiles.is_empty() {
+        return Ok(Vec::new());
+    }
+    let padded_row = |rect: UndoTileRect| {
+        (rect.width * BYTES_PER_TEXEL).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+    };
+    let mut offsets: Vec<u64> = Vec::with_capacity(tiles.len());
+    let mut size: u64 = 0;
+    for (_, rect) in tiles {
+        offsets.push(size);
+        size += padded_row(*rect) as u64 * rect.height as u64;
+    }
+
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("misa-rin undo spill readback"),
+        size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("misa-rin undo spill readback encoder"),
+    });
+    for ((texture, rect), offset) in tiles.iter().zip(&offsets) {
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout {
+                    offset: *offset,
+                    bytes_per_row: Some(padded_row(*rect)),
+                    rows_per_image: Some(rect.height),
+                },
+            },
+            rect.extent(),
+        );
+    }
+    queue.submit(Some(encoder.finish()));
+
+    let buffer_slice = readback.slice(..);
+    let (tx, rx) = mpsc::channel();
+    buffer_slice.map_async(wgpu::MapMode::Read, move |res| {
+        let _ = tx.send(res);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    match rx.recv() {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Err(format!("read_back_tiles: map_async failed: {e:?}")),
+        Err(e) => return Err(format!("read_back_tiles: map_async channel failed: {e}")),
+    }
+
+    let mapped = buffer_slice.get_mapped_range();
+    let texels = tiles
+        .iter()
+        .zip(&offsets)
+        .map(|((_, rect), offset)| {
+            let row_bytes = (rect.width * BYTES_PER_TEXEL) as usize;
+            let mut out: Vec<u32> = Vec::with_capacity(rect.texel_count());
+            for y in 0..rect.height as usize {
+                let start = *offset as usize + y * padded_row(*rect) as usize;
+                out.extend(
+                    mapped[start..start + row_bytes]
+                        .chunks_exact(4)
+                        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                );
+            }
+            out
+        })
+        .collect();
+    drop(mapped);
+    readback.unmap();
+    Ok(texels)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::canvas_engine::engine::tests::{read_layer, stop_engine, test_engines};
+    use crate::canvas_engine::engine::{lookup_engine, EngineCommand};
+
+    #[test]
+    fn structural_changes_undo_in_order() {
+        for handle in test_engines(16, 16) {
+            let entry = lookup_engine(handle).unwrap();
+            let send = |cmd: EngineCommand| entry.cmd_tx.send(cmd).unwrap();
+            send(EngineCommand::ResetCanvasWithLayers {
+                layer_count: 2,
+                background_color_argb: 0xFFFFFFFF,
+            });
+            send(EngineCommand::FillLayer {
+                layer_index: 1,
+                color_argb: 0xFFFF0000,
+            });
+            send(EngineCommand::RemoveLayer { layer_index: 0 });
+            let (reply, rx) = mpsc::channel();
+            send(EngineCommand::ResizeCanvas {
+                width: 8,
+                height: 8,
+                layer_count: 1,
+                background_color_argb: 0xFF000000,
+                reply,
+            });
+            assert!(rx.recv_timeout(Duration::from_secs(5)).unwrap());
+            assert_eq!(read_layer(&entry, 0), vec![0xFF000000; 64]);
+
+            let backend = entry.backend;
+            send(EngineCommand::Undo);
+            assert_eq!(read_layer(&entry, 0), vec![0xFFFF0000; 256], "{backend:?} resize");
+            send(EngineCommand::Undo);
+            assert_eq!(read_layer(&entry, 0), vec![0xFFFFFFFF; 256], "{backend:?} remove");
+            assert_eq!(read_layer(&entry, 1), vec![0xFFFF0000; 256], "{backend:?} remove");
+            send(EngineCommand::Undo);
+            assert_eq!(read_layer(&entry, 1), vec![0; 256], "{backend:?} fill");
+            send(EngineCommand::Redo);
+            send(EngineCommand::Redo);
+            assert_eq!(read_layer(&entry, 0), vec![0xFFFF0000; 256], "{backend:?} redo");
+            stop_engine(handle);
+        }
+    }
+
+    #[test]
+    fn undo_transaction_spans_layers() {
+        for handle in test_engines(16, 16) {
+            let entry = lookup_engine(handle).unwrap();
+            let send = |cmd: EngineCommand| entry.cmd_tx.send(cmd).unwrap();
+            send(EngineCommand::ResetCanvasWithLayers {
+                layer_count: 2,
+                background_color_argb: 0xFFFFFFFF,
+            });
+            send(EngineCommand::BeginUndoTransaction);
+            send(EngineCommand::FillLayer {
+                layer_index: 0,
+                color_argb: 0xFF00FF00,
+            });
+            send(EngineCommand::FillLayer {
+                layer_index: 1,
+                color_argb: 0xFFFF0000,
+            });
+            send(EngineCommand::CommitUndoTransaction);
+            send(EngineCommand::Undo);
+            let backend = entry.backend;
+            assert_eq!(read_layer(&entry, 0), vec![0xFFFFFFFF; 256], "{backend:?} undo");
+            assert_eq!(read_layer(&entry, 1), vec![0; 256], "{backend:?} undo");
+
+            send(EngineCommand::BeginUndoTransaction);
+            send(EngineCommand::ClearLayer { layer_index: 0 });
+            send(EngineCommand::FillLayer {
+                layer_index: 1,
+                color_argb: 0xFF0000FF,
+            });
+            send(EngineCommand::CancelUndoTransaction);
+            assert_eq!(read_layer(&entry, 0), vec![0xFFFFFFFF; 256], "{backend:?} cancel");
+            assert_eq!(read_layer(&entry, 1), vec![0; 256], "{backend:?} cancel");
+            send(EngineCommand::Redo);
+            assert_eq!(read_layer(&entry, 1), vec![0xFFFF0000; 256], "{backend:?} redo");
+            stop_engine(handle);
+        }
+    }
+}
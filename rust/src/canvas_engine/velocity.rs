@@ -0,0 +1,278 @@
+use std::collections::VecDeque;
+
+use crate::gpu::brush_renderer::Point2D;
+
+/// How a brush turns pointer speed into pressure, for mice and pens whose
+/// pressure the artist does not want to use.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum SpeedPressureProfile {
+    /// Keep the pressure the device reports.
+    #[default]
+    Off,
+    /// Slow strokes are heavy and fast flicks thin out.
+    TaperEnds,
+    /// Fast strokes swell, slow ones stay thin.
+    TaperCenter,
+}
+
+impl SpeedPressureProfile {
+    pub(crate) fn from_index(index: u32) -> Self {
+        match index {
+            1 => Self::TaperEnds,
+            2 => Self::TaperCenter,
+            _ => Self::Off,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct SimulatedPressure {
+    pub(crate) profile: SpeedPressureProfile,
+    /// Fade the first few samples in and run a short tail past the release
+    /// point so strokes start and end on a point.
+    pub(crate) tapered_caps: bool,
+}
+
+impl SimulatedPressure {
+    pub(crate) fn is_active(&self) -> bool {
+        self.profile != SpeedPressureProfile::Off || self.tapered_caps
+    }
+}
+
+const MIN_TRACKING_DISTANCE: f32 = 1.4;
+const MAX_SAMPLE_HISTORY: usize = 64;
+const SMOOTHING_SAMPLES: usize = 3;
+// px/ms treated as full speed.
+const MAX_SPEED: f32 = 2.5;
+const AVG_DELTA_SMOOTHING: f32 = 0.85;
+
+/// Pointer speed averaged over enough recent distance that jittery event
+/// timing does not make the simulated pressure flicker.
+struct VelocitySmoother {
+    distances: VecDeque<f32>,
+    last: Option<(Point2D, f32)>,
+    avg_delta_ms: Option<f32>,
+    normalized: f32,
+}
+
+impl VelocitySmoother {
+    fn new() -> Self {
+        Self {
+            distances: VecDeque::with_capacity(MAX_SAMPLE_HISTORY),
+            last: None,
+            avg_delta_ms: None,
+            normalized: 0.0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.distances.clear();
+        self.last = None;
+        self.avg_delta_ms = None;
+        self.normalized = 0.0;
+    }
+
+    /// Adds a sample and returns the speed normalised to `0..=1`.
+    fn add_sample(&mut self, pos: Point2D, timestamp_ms: f32) -> f32 {
+        let Some((last_pos, last_ms)) = self.last else {
+            self.last = Some((pos, timestamp_ms));
+            return self.normalized;
+        };
+        let delta_ms = (timestamp_ms - last_ms).max(0.0);
+        let distance = ((pos.x - last_pos.x).powi(2) + (pos.y - last_pos.y).powi(2)).sqrt();
+        if !delta_ms.is_finite() || !distance.is_finite() {
+            return self.normalized;
+        }
+        if self.distances.len() == MAX_SAMPLE_HISTORY {
+            self.distances.pop_front();
+        }
+        self.distances.push_back(distance);
+        let avg = match self.avg_delta_ms {
+            Some(avg) => avg + (delta_ms - avg) * (1.0 - AVG_DELTA_SMOOTHING),
+            None => delta_ms,
+        };
+        self.avg_delta_ms = Some(avg);
+        self.last = Some((pos, timestamp_ms));
+        if avg <= 0.0 {
+            return self.normalized;
+        }
+
+        let mut total_distance = 0.0;
+        let mut total_time = 0.0;
+        for (searched, distance) in self.distances.iter().rev().enumerate() {
+            total_distance += distance;
+            total_time += avg;
+            if searched + 1 >= SMOOTHING_SAMPLES && total_distance >= MIN_TRACKING_DISTANCE {
+                break;
+            }
+        }
+        self.normalized = if total_distance < MIN_TRACKING_DISTANCE {
+            0.0
+        } else {
+            (total_distance / total_time / MAX_SPEED).clamp(0.0, 1.0)
+        };
+        self.normalized
+    }
+}
+
+const INTENSITY_SMOOTHING: f32 = 0.32;
+const HIGH_SPEED_BIAS: f32 = 0.18;
+const START_TAPER_SAMPLES: u32 = 5;
+const START_TAPER_FLOOR: f32 = 0.1;
+const TAIL_MAX_SAMPLES: usize = 16;
+// Time between tail samples; keeps timestamps rising for the stabilizer.
+const TAIL_STEP_US: u64 = 1_000;
+
+/// Per-stroke state behind [`SimulatedPressure`].
+pub(crate) struct PressureSimulator {
+    velocity: VelocitySmoother,
+    intensity: Option<f32>,
+    samples: u32,
+    last_pos: Option<Point2D>,
+    previous_pos: Option<Point2D>,
+    last_pressure: f32,
+}
+
+impl PressureSimulator {
+    pub(crate) fn new() -> Self {
+        Self {
+            velocity: VelocitySmoother::new(),
+            intensity: None,
+            samples: 0,
+            last_pos: None,
+            previous_pos: None,
+            last_pressure: 1.0,
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.velocity.reset();
+        self.intensity = None;
+        self.samples = 0;
+        self.last_pos = None;
+        self.previous_pos = None;
+        self.last_pressure = 1.0;
+    }
+
+    /// Pressure for the next raw stroke sample.
+    pub(crate) fn sample(
+        &mut self,
+        settings: SimulatedPressure,
+        pos: Point2D,
+        pressure: f32,
+        timestamp_us: u64,
+    ) -> f32 {
+        let speed = self.velocity.add_sample(pos, timestamp_us as f32 / 1000.0);
+        let mut pressure = match settings.profile {
+            SpeedPressureProfile::Off => pressure,
+            profile => {
+                let smoothed = match self.intensity {
+                    Some(previous) => previous + (speed - previous) * INTENSITY_SMOOTHING,
+                    None => speed,
+                };
+                self.intensity = Some(smoothed);
+                let biased = smoothed * (1.0 - HIGH_SPEED_BIAS) + speed * HIGH_SPEED_BIAS;
+                let eased = biased.clamp(0.0, 1.0).powf(0.6);
+                if profile == SpeedPressureProfile::TaperEnds {
+                    1.0 - eased
+                } else {
+                    eased
+                }
+            }
+        };
+        if settings.tapered_caps && self.samples < START_TAPER_SAMPLES {
+            let t = self.samples as f32 / START_TAPER_SAMPLES as f32;
+            pressure *= START_TAPER_FLOOR + (1.0 - START_TAPER_FLOOR) * t;
+        }
+        self.samples = self.samples.saturating_add(1);
+        let moved = !matches!(self.last_pos, Some(last) if last.x == pos.x && last.y == pos.y);
+        if moved {
+            self.previous_pos = self.last_pos;
+            self.last_pos = Some(pos);
+        }
+        self.last_pressure = pressure.clamp(0.0, 1.0);
+        self.last_pressure
+    }
+
+    /// Samples that carry the stroke past its release point and fade it out,
+    /// as `(position, pressure, microseconds after release)`. Empty when caps
+    /// are off or the pointer never moved.
+    pub(crate) fn tail(
+        &self,
+        settings: SimulatedPressure,
+        base_radius: f32,
+    ) -> Vec<(Point2D, f32, u64)> {
+        if !settings.tapered_caps {
+            return Vec::new();
+        }
+        let (Some(tip), Some(previous)) = (self.last_pos, self.previous_pos) else {
+            return Vec::new();
+        };
+        let dx = tip.x - previous.x;
+        let dy = tip.y - previous.y;
+        let length = (dx * dx + dy * dy).sqrt();
+        if !length.is_finite() || length <= 0.001 {
+            return Vec::new();
+        }
+        let base = if base_radius.is_finite() {
+            base_radius.max(0.1)
+        } else {
+            0.1
+        };
+        let taper_length = (base * 6.5).min(length * 2.4 + 2.0);
+        let count =
+            ((taper_length / (base * 0.5).max(1.0)).ceil() as usize).clamp(2, TAIL_MAX_SAMPLES);
+        let (ux, uy) = (dx / length, dy / length);
+        (1..=count)
+            .map(|i| {
+                let t = i as f32 / count as f32;
+                let point = Point2D {
+                    x: tip.x + ux * taper_length * t,
+                    y: tip.y + uy * taper_length * t,
+                };
+                (
+                    point,
+                    self.last_pressure * (1.0 - t),
+                    i as u64 * TAIL_STEP_US,
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fast_strokes_thin_out_and_caps_taper() {
+        let settings = SimulatedPressure {
+            profile: SpeedPressureProfile::TaperEnds,
+            tapered_caps: true,
+        };
+        let mut sim = PressureSimulator::new();
+        let mut slow = 1.0;
+        for i in 0..20 {
+            let pos = Point2D {
+                x: i as f32 * 0.2,
+                y: 0.0,
+            };
+            slow = sim.sample(settings, pos, 1.0, i * 16_000);
+        }
+        let mut fast = 1.0;
+        for i in 20..40 {
+            let pos = Point2D {
+                x: 4.0 + (i - 20) as f32 * 40.0,
+                y: 0.0,
+            };
+            fast = sim.sample(settings, pos, 1.0, i * 16_000);
+        }
+        assert!(fast < slow);
+
+        let tail = sim.tail(settings, 8.0);
+        assert!(tail.len() >= 2);
+        assert!(tail.windows(2).all(|pair| pair[1].1 <= pair[0].1));
+        assert_eq!(tail.last().unwrap().1, 0.0);
+        assert!(tail.iter().all(|(point, _, _)| point.x > 764.0));
+    }
+}
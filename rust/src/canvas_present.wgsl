@@ -29,8 +29,26 @@ struct LayerParams {
   visible: f32,
   clipping_mask: f32,
   blend_mode: u32,
+  // Groups opening before / closing after this layer.
+  group_open: u32,
+  group_close: u32,
+  // 1 when the layer's grayscale mask should be applied.
+  mask_enabled: u32,
+  // Colour filter kind + 1 for adjustment layers, 0 for pixel layers.
+  adjustment: u32,
+  adjustment_params: vec4<f32>,
 };
 
+struct GroupParams {
+  opacity: f32,
+  blend_mode: u32,
+  pass_through: u32,
+  _pad0: u32,
+};
+
+// Must match MAX_COMPOSITE_GROUP_DEPTH on the Rust side.
+const MAX_GROUP_DEPTH: u32 = 8u;
+
 @group(0) @binding(1)
 var<uniform> cfg: CompositeConfig;
 
@@ -45,6 +63,19 @@ struct TransformConfig {
 @group(0) @binding(3)
 var<uniform> transform_cfg: TransformConfig;
 
+// Params of every opened group, in the order layers open them.
+@group(0) @binding(4)
+var<storage, read> group_params: array<GroupParams>;
+
+// Layer masks, parallel to `layer_tex`. Every channel holds the gray value.
+@group(0) @binding(5)
+var mask_tex: texture_2d_array<u32>;
+
+fn layer_mask_value(coord: vec2<i32>, layer: i32) -> f32 {
+  let c = textureLoad(mask_tex, coord, layer, 0).x;
+  return u8_to_f32(c & 0xFFu) * u8_to_f32((c >> 24u) & 0xFFu);
+}
+
 fn u8_to_f32(v: u32) -> f32 {
   return f32(v) / 255.0;
 }
@@ -355,8 +386,8 @@ fn load_layer_pixel(x: i32, y: i32, layer: i32) -> vec4<f32> {
   if (x < 0 || y < 0 || x >= i32(dims.x) || y >= i32(dims.y)) {
     return vec4<f32>(0.0, 0.0, 0.0, 0.0);
   }
-  let packed = textureLoad(layer_tex, vec2<i32>(x, y), layer, 0).x;
-  return unpack_straight_rgba(packed);
+  let texel = textureLoad(layer_tex, vec2<i32>(x, y), layer, 0).x;
+  return unpack_straight_rgba(texel);
 }
 
 fn sample_nearest(coord: vec2<f32>, layer: i32) -> u32 {
@@ -395,6 +426,168 @@ fn sample_transformed(coord: vec2<f32>, layer: i32) -> u32 {
   return sample_nearest(coord, layer);
 }
 
+struct LayerSample {
+  rgb: vec3<f32>,
+  // Effective alpha after opacity and clipping; 0 when the layer adds nothing.
+  a: f32,
+  texel: u32,
+};
+
+fn rgb_to_hsv(rgb: vec3<f32>) -> vec3<f32> {
+  let maxc = max(max(rgb.r, rgb.g), rgb.b);
+  let minc = min(min(rgb.r, rgb.g), rgb.b);
+  let delta = maxc - minc;
+
+  var h = 0.0;
+  if (delta > EPS) {
+    if (maxc == rgb.r) {
+      h = (rgb.g - rgb.b) / delta;
+      if (rgb.g < rgb.b) {
+        h = h + 6.0;
+      }
+    } else if (maxc == rgb.g) {
+      h = (rgb.b - rgb.r) / delta + 2.0;
+    } else {
+      h = (rgb.r - rgb.g) / delta + 4.0;
+    }
+    h = h / 6.0;
+  }
+
+  let s = select(0.0, delta / maxc, maxc > EPS);
+  return vec3<f32>(h, s, maxc);
+}
+
+fn hsv_to_rgb(hsv: vec3<f32>) -> vec3<f32> {
+  let v = hsv.z;
+  let s = hsv.y;
+  if (s <= EPS) {
+    return vec3<f32>(v, v, v);
+  }
+  let h6 = hsv.x * 6.0;
+  let i = floor(h6);
+  let f = h6 - i;
+  let p = v * (1.0 - s);
+  let q = v * (1.0 - s * f);
+  let t = v * (1.0 - s * (1.0 - f));
+  let ii = u32(i) % 6u;
+  if (ii == 0u) { return vec3<f32>(v, t, p); }
+  if (ii == 1u) { return vec3<f32>(q, v, p); }
+  if (ii == 2u) { return vec3<f32>(p, v, t); }
+  if (ii == 3u) { return vec3<f32>(p, q, v); }
+  if (ii == 4u) { return vec3<f32>(t, p, v); }
+  return vec3<f32>(v, p, q);
+}
+
+// Mirrors `color_filter` in the filter shaders for the kinds an adjustment
+// layer can use. Binarize thresholds luminance instead of alpha, since the
+// composite below an adjustment is usually opaque.
+fn adjust_color(kind: u32, rgb: vec3<f32>, p: vec4<f32>) -> vec3<f32> {
+  if (kind == 0u) {
+    let hsv = rgb_to_hsv(rgb);
+    var h = hsv.x + p.x;
+    h = h - floor(h);
+    return hsv_to_rgb(vec3<f32>(h, clamp01(hsv.y + p.y), clamp01(hsv.z + p.z)));
+  }
+  if (kind == 1u) {
+    return clamp((rgb - vec3<f32>(0.5)) * p.y + vec3<f32>(0.5 + p.x), vec3<f32>(0.0), vec3<f32>(1.0));
+  }
+  let lum = dot(rgb, vec3<f32>(0.299, 0.587, 0.114));
+  if (kind == 2u) {
+    return vec3<f32>(clamp01(pow(clamp01((lum - p.x) * p.y), p.z)));
+  }
+  if (kind == 3u) {
+    return vec3<f32>(select(0.0, 1.0, lum >= p.x));
+  }
+  if (kind == 5u) {
+    return vec3<f32>(1.0) - rgb;
+  }
+  return rgb;
+}
+
+// Recolours the composite below adjustment layer `i`; opacity and the layer
+// mask fade between the original and the adjusted colour.
+fn apply_adjustment(i: u32, coord: vec2<i32>, premul: vec4<f32>) -> vec4<f32> {
+  let params = layer_params[i];
+  if (params.visible < 0.5 || premul.a <= 0.0) {
+    return premul;
+  }
+  var strength = clamp(params.opacity, 0.0, 1.0);
+  if (params.mask_enabled != 0u) {
+    strength = strength * layer_mask_value(coord, i32(i));
+  }
+  if (strength <= 0.0) {
+    return premul;
+  }
+  let straight = premul.rgb / premul.a;
+  let adjusted = adjust_color(params.adjustment - 1u, straight, params.adjustment_params);
+  return vec4<f32>(mix(straight, adjusted, strength) * premul.a, premul.a);
+}
+
+fn sample_layer(
+  i: u32,
+  coord: vec2<i32>,
+  board_pos: vec2<f32>,
+  mask_alpha: ptr<function, f32>,
+) -> LayerSample {
+  let empty = LayerSample(vec3<f32>(0.0), 0.0, 0u);
+  let params = layer_params[i];
+  var texel: u32 = 0u;
+  if ((cfg.transform_flags & 1u) != 0u && i == cfg.transform_layer) {
+    let src = (transform_cfg.matrix * vec4<f32>(board_pos, 0.0, 1.0)).xy;
+    texel = sample_transformed(src, i32(i));
+  } else {
+    texel = textureLoad(layer_tex, coord, i32(i), 0).x;
+  }
+  let opacity = clamp(params.opacity, 0.0, 1.0);
+  let visible = params.visible;
+  let clipping = params.clipping_mask;
+  if (visible < 0.5) {
+    return empty;
+  }
+  if (opacity <= 0.0) {
+    if (clipping < 0.5) {
+      *mask_alpha = 0.0;
+    }
+    return empty;
+  }
+
+  var straight = unpack_straight_rgba(texel);
+  if (params.mask_enabled != 0u) {
+    straight.a = straight.a * layer_mask_value(coord, i32(i));
+  }
+  if (straight.a <= 0.0) {
+    if (clipping < 0.5) {
+      *mask_alpha = 0.0;
+    }
+    return empty;
+  }
+
+  var total_opacity = opacity;
+  if (clipping >= 0.5) {
+    if (*mask_alpha <= 0.0) {
+      return empty;
+    }
+    total_opacity = total_opacity * *mask_alpha;
+    if (total_opacity <= 0.0) {
+      return empty;
+    }
+  }
+
+  let a = clamp(straight.a * total_opacity, 0.0, 1.0);
+  if (a <= 0.0) {
+    if (clipping < 0.5) {
+      *mask_alpha = 0.0;
+    }
+    return empty;
+  }
+  if (clipping < 0.5) {
+    *mask_alpha = a;
+  }
+
+  let effective_a_u8 = to_u8(a);
+  return LayerSample(straight.rgb, a, (effective_a_u8 << 24u) | (texel & 0x00FFFFFFu));
+}
+
 @fragment
 fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
   let x = i32(pos.x);
@@ -411,74 +604,102 @@ fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
   var out_premul = vec4<f32>(0.0, 0.0, 0.0, 0.0);
   var mask_alpha: f32 = 0.0;
   var initialized: bool = false;
+
+  // Open groups save the state they started from; isolated groups then
+  // composite into a fresh buffer, pass-through groups keep the backdrop.
+  var stack_premul: array<vec4<f32>, MAX_GROUP_DEPTH>;
+  var stack_initialized: array<u32, MAX_GROUP_DEPTH>;
+  var stack_group: array<u32, MAX_GROUP_DEPTH>;
+  var depth: u32 = 0u;
+  var next_group: u32 = 0u;
+
   for (var i: u32 = 0u; i < cfg.layer_count; i = i + 1u) {
     let params = layer_params[i];
-    var packed: u32 = 0u;
-    if ((cfg.transform_flags & 1u) != 0u && i == cfg.transform_layer) {
-      let src = (transform_cfg.matrix * vec4<f32>(board_pos, 0.0, 1.0)).xy;
-      packed = sample_transformed(src, i32(i));
-    } else {
-      packed = textureLoad(layer_tex, coord, i32(i), 0).x;
-    }
-    let opacity = clamp(params.opacity, 0.0, 1.0);
-    let visible = params.visible;
-    let clipping = params.clipping_mask;
-    let blend_mode = params.blend_mode;
-    if (visible < 0.5) {
-      continue;
-    }
-    if (opacity <= 0.0) {
-      if (clipping < 0.5) {
-        mask_alpha = 0.0;
+    for (var g: u32 = 0u; g < params.group_open; g = g + 1u) {
+      let group = group_params[next_group];
+      if (depth < MAX_GROUP_DEPTH) {
+        stack_premul[depth] = out_premul;
+        stack_initialized[depth] = select(0u, 1u, initialized);
+        stack_group[depth] = next_group;
+        depth = depth + 1u;
+      }
+      next_group = next_group + 1u;
+      if (group.pass_through == 0u) {
+        out_premul = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+        initialized = false;
       }
-      continue;
+      // Clipping masks never reach outside their own folder.
+      mask_alpha = 0.0;
     }
 
-    let straight = unpack_straight_rgba(packed);
-    if (straight.a <= 0.0) {
-      if (clipping < 0.5) {
-        mask_alpha = 0.0;
+    if (params.adjustment != 0u) {
+      // Adjustment layers add no pixels and leave the clipping base alone.
+      if (initialized) {
+        out_premul = apply_adjustment(i, coord, out_premul);
+      }
+    } else {
+      let layer = sample_layer(i, coord, board_pos, &mask_alpha);
+      if (layer.a > 0.0) {
+        if (!initialized) {
+          out_premul = vec4<f32>(layer.rgb * layer.a, layer.a);
+          initialized = true;
+        } else {
+          out_premul = blend_premul(
+            out_premul,
+            layer.rgb,
+            layer.a,
+            params.blend_mode,
+            pixel_index,
+            layer.texel,
+          );
+        }
       }
-      continue;
     }
 
-    var total_opacity = opacity;
-    if (clipping >= 0.5) {
-      if (mask_alpha <= 0.0) {
-        continue;
+    for (var g: u32 = 0u; g < params.group_close; g = g + 1u) {
+      if (depth == 0u) {
+        break;
       }
-      total_opacity = total_opacity * mask_alpha;
-      if (total_opacity <= 0.0) {
+      depth = depth - 1u;
+      let group = group_params[stack_group[depth]];
+      let base_premul = stack_premul[depth];
+      let base_initialized = stack_initialized[depth] != 0u;
+      let group_opacity = clamp(group.opacity, 0.0, 1.0);
+      if (group.pass_through != 0u) {
+        var inner = out_premul;
+        if (!initialized) {
+          inner = base_premul;
+        }
+        out_premul = mix(base_premul, inner, group_opacity);
+        initialized = initialized || base_initialized;
+        mask_alpha = 0.0;
         continue;
       }
-    }
-
-    let a = clamp(straight.a * total_opacity, 0.0, 1.0);
-    if (a <= 0.0) {
-      if (clipping < 0.5) {
+      var group_a: f32 = 0.0;
+      if (initialized) {
+        group_a = out_premul.a * group_opacity;
+      }
+      if (group_a <= 0.0) {
+        out_premul = base_premul;
+        initialized = base_initialized;
         mask_alpha = 0.0;
+        continue;
+      }
+      let group_rgb = out_premul.rgb / out_premul.a;
+      if (base_initialized) {
+        out_premul = blend_premul(
+          base_premul,
+          group_rgb,
+          group_a,
+          group.blend_mode,
+          pixel_index,
+          pack_straight_rgba(vec4<f32>(group_rgb, group_a)),
+        );
+      } else {
+        out_premul = vec4<f32>(group_rgb * group_a, group_a);
       }
-      continue;
-    }
-    if (clipping < 0.5) {
-      mask_alpha = a;
-    }
-
-    let effective_a_u8 = to_u8(a);
-    let effective_packed = (effective_a_u8 << 24u) | (packed & 0x00FFFFFFu);
-
-    if (!initialized) {
-      out_premul = vec4<f32>(straight.rgb * a, a);
       initialized = true;
-    } else {
-      out_premul = blend_premul(
-        out_premul,
-        straight.rgb,
-        a,
-        blend_mode,
-        pixel_index,
-        effective_packed,
-      );
+      mask_alpha = group_a;
     }
   }
 
@@ -1,4 +1,4 @@
-use std::f32::consts::{FRAC_1_SQRT_2, PI};
+use std::f32::consts::{FRAC_1_SQRT_2, PI, SQRT_2};
 
 const EPS: f32 = 1.0e-6;
 const SUBPIXEL_RADIUS_LIMIT: f32 = 0.6;
@@ -9,7 +9,7 @@ const MIN_INTEGRATION_SLICES: i32 = 6;
 const MAX_INTEGRATION_SLICES: i32 = 20;
 
 #[derive(Clone, Copy)]
-struct ScreentoneSettings {
+pub(crate) struct ScreentoneSettings {
     enabled: bool,
     spacing: f32,
     dot_size: f32,
@@ -20,7 +20,7 @@ struct ScreentoneSettings {
 }
 
 impl ScreentoneSettings {
-    fn disabled() -> Self {
+    pub(crate) fn disabled() -> Self {
         Self {
             enabled: false,
             spacing: 10.0,
@@ -41,7 +41,7 @@ fn clamp01(v: f32) -> f32 {
     }
 }
 
-fn antialias_feather(level: u32) -> f32 {
+pub(crate) fn antialias_feather(level: u32) -> f32 {
     match level {
         0 => 0.0,
         1 => 0.7,
@@ -65,7 +65,7 @@ fn antialias_samples_per_axis(level: u32) -> u32 {
     }
 }
 
-fn screentone_settings_from_params(
+pub(crate) fn screentone_settings_from_params(
     enabled: u8,
     spacing: f32,
     dot_size: f32,
@@ -156,6 +156,668 @@ fn screentone_mask_at(
     coverage
 }
 
+/// How paper grain modulates dab coverage. The indices are the FFI `blend`
+/// values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum GrainBlend {
+    /// Valleys dim the dab by `depth`.
+    #[default]
+    Multiply,
+    /// Valleys take `depth` off the coverage, so light strokes skip them.
+    Subtract,
+    /// The grain is a height map; light strokes only reach the peaks and
+    /// heavier ones fill in towards the valleys.
+    Height,
+}
+
+impl GrainBlend {
+    pub(crate) fn from_index(index: u32) -> Self {
+        match index {
+            1 => Self::Subtract,
+            2 => Self::Height,
+            _ => Self::Multiply,
+        }
+    }
+
+    /// Shader `grain_mode` when grain is on.
+    pub(crate) fn shader_mode(self) -> u32 {
+        match self {
+            Self::Multiply => 1,
+            Self::Subtract => 2,
+            Self::Height => 3,
+        }
+    }
+}
+
+/// Canvas-anchored paper texture settings of a brush. The texture itself is
+/// uploaded once per engine.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct GrainSettings {
+    pub(crate) enabled: bool,
+    /// Canvas pixels per texel.
+    pub(crate) scale: f32,
+    pub(crate) offset_x: f32,
+    pub(crate) offset_y: f32,
+    pub(crate) depth: f32,
+    pub(crate) invert: bool,
+    pub(crate) blend: GrainBlend,
+}
+
+impl Default for GrainSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            scale: 1.0,
+            offset_x: 0.0,
+            offset_y: 0.0,
+            depth: 1.0,
+            invert: false,
+            blend: GrainBlend::Multiply,
+        }
+    }
+}
+
+impl GrainSettings {
+    pub(crate) fn sanitize(&mut self) {
+        self.scale = if self.scale.is_finite() {
+            self.scale.clamp(0.05, 64.0)
+        } else {
+            1.0
+        };
+        if !self.offset_x.is_finite() {
+            self.offset_x = 0.0;
+        }
+        if !self.offset_y.is_finite() {
+            self.offset_y = 0.0;
+        }
+        self.depth = clamp01(self.depth);
+    }
+}
+
+/// A tileable 8-bit grayscale grain image, `width * height` bytes.
+#[derive(Clone, Copy)]
+pub(crate) struct GrainTexture<'a> {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) data: &'a [u8],
+}
+
+impl GrainTexture<'_> {
+    /// Grain height under the canvas point, wrapping in both directions.
+    fn height_at(&self, x: f32, y: f32, settings: &GrainSettings) -> f32 {
+        let fx = (x + settings.offset_x) / settings.scale - 0.5;
+        let fy = (y + settings.offset_y) / settings.scale - 0.5;
+        let x0 = fx.floor();
+        let y0 = fy.floor();
+        let tx = fx - x0;
+        let ty = fy - y0;
+        let width = self.width as i64;
+        let height = self.height as i64;
+        let texel = |x: i64, y: i64| -> f32 {
+            let idx = y.rem_euclid(height) * width + x.rem_euclid(width);
+            self.data.get(idx as usize).copied().unwrap_or(0) as f32 / 255.0
+        };
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = texel(x0, y0) + (texel(x0 + 1, y0) - texel(x0, y0)) * tx;
+        let bottom = texel(x0, y0 + 1) + (texel(x0 + 1, y0 + 1) - texel(x0, y0 + 1)) * tx;
+        let value = top + (bottom - top) * ty;
+        if settings.invert {
+            1.0 - value
+        } else {
+            value
+        }
+    }
+
+    pub(crate) fn is_valid(&self) -> bool {
+        self.width > 0
+            && self.height > 0
+            && self.data.len() >= (self.width as usize) * (self.height as usize)
+    }
+}
+
+// Height blend softens the waterline over this much grain height.
+const GRAIN_HEIGHT_BAND: f32 = 0.1;
+
+/// Modulates one coverage sample by the grain at that point. Mirrors
+/// `grain_apply` in the brush shader.
+fn apply_grain(
+    coverage: f32,
+    x: f32,
+    y: f32,
+    settings: &GrainSettings,
+    texture: &GrainTexture<'_>,
+) -> f32 {
+    if coverage <= 0.0 {
+        return coverage;
+    }
+    let grain = texture.height_at(x, y, settings);
+    let depth = settings.depth;
+    match settings.blend {
+        GrainBlend::Multiply => coverage * (1.0 - depth + depth * grain),
+        GrainBlend::Subtract => clamp01(coverage - depth * (1.0 - grain)),
+        GrainBlend::Height => {
+            let reach = clamp01((grain + coverage - 1.0) / GRAIN_HEIGHT_BAND + 1.0);
+            coverage * (1.0 - depth + depth * reach)
+        }
+    }
+}
+
+/// Brushes that take colour from the canvas instead of only laying down
+/// `color_argb`. The indices are the FFI `mode` values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum MixMode {
+    #[default]
+    Off,
+    /// Finger tool: drags the colour under the brush, ignores the brush colour.
+    Smudge,
+    /// Loads the brush colour and mixes it with what it passes over.
+    Blender,
+    /// Blender whose load spreads into empty canvas instead of thinning out
+    /// (SAI's colour extension).
+    Watercolor,
+}
+
+impl MixMode {
+    pub(crate) fn from_index(index: u32) -> Self {
+        match index {
+            1 => Self::Smudge,
+            2 => Self::Blender,
+            3 => Self::Watercolor,
+            _ => Self::Off,
+        }
+    }
+
+    /// Shader `mix_mode`.
+    pub(crate) fn shader_mode(self) -> u32 {
+        match self {
+            Self::Off => 0,
+            Self::Smudge => 1,
+            Self::Blender => 2,
+            Self::Watercolor => 3,
+        }
+    }
+}
+
+/// Colour-mixing settings of a brush, all in 0..1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct MixSettings {
+    pub(crate) mode: MixMode,
+    /// Share of the canvas colour taken into the brush at every dab.
+    pub(crate) pickup: f32,
+    /// Thins the fresh paint with transparency; at 1 the brush only moves
+    /// colour around.
+    pub(crate) dilution: f32,
+    /// Share of the brush load kept from one dab to the next rather than
+    /// refilled with fresh paint.
+    pub(crate) persistence: f32,
+    /// Watercolour only: how much of the load survives a pass over empty
+    /// canvas.
+    pub(crate) extension: f32,
+}
+
+impl Default for MixSettings {
+    fn default() -> Self {
+        Self {
+            mode: MixMode::Off,
+            pickup: 0.5,
+            dilution: 0.0,
+            persistence: 0.5,
+            extension: 0.5,
+        }
+    }
+}
+
+impl MixSettings {
+    pub(crate) fn sanitize(&mut self) {
+        let unit = |value: f32, fallback: f32| {
+            if value.is_finite() {
+                clamp01(value)
+            } else {
+                fallback
+            }
+        };
+        self.pickup = unit(self.pickup, 0.5);
+        self.dilution = unit(self.dilution, 0.0);
+        self.persistence = unit(self.persistence, 0.5);
+        self.extension = unit(self.extension, 0.5);
+    }
+}
+
+/// Watercolour settings of a brush. Dabs pool into a per-stroke mask and the
+/// effects below are applied to the whole stroke when it ends.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct WetSettings {
+    pub(crate) enabled: bool,
+    /// 0..1: pigment drawn from the middle of the wash to its rim.
+    pub(crate) edge: f32,
+    /// 0..1: strength of the speckle left by settling pigment.
+    pub(crate) granulation: f32,
+    /// Canvas pixels the wash spreads past the dabs.
+    pub(crate) bleed: f32,
+}
+
+const WET_MAX_BLEED: f32 = 64.0;
+// Distance at which the rim is measured against the wash around it.
+const WET_EDGE_REACH: f32 = 2.0;
+const WET_DIRECTIONS: [(f32, f32); 8] = [
+    (1.0, 0.0),
+    (FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+    (0.0, 1.0),
+    (-FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+    (-1.0, 0.0),
+    (-FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+    (0.0, -1.0),
+    (FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+];
+
+impl WetSettings {
+    pub(crate) fn sanitize(&mut self) {
+        let unit = |value: f32| if value.is_finite() { clamp01(value) } else { 0.0 };
+        self.edge = unit(self.edge);
+        self.granulation = unit(self.granulation);
+        self.bleed = if self.bleed.is_finite() {
+            self.bleed.clamp(0.0, WET_MAX_BLEED)
+        } else {
+            0.0
+        };
+    }
+
+    /// How far past the pooled dabs the finishing pass reads and writes.
+    pub(crate) fn reach(&self) -> u32 {
+        self.bleed.max(WET_EDGE_REACH).ceil() as u32
+    }
+}
+
+/// 2x2 pixel speckle in 0..1 for granulation.
+fn wet_granule(x: i32, y: i32) -> f32 {
+    let hx = ((x as u32) >> 1).wrapping_mul(0x8DA6_B343);
+    let hy = ((y as u32) >> 1).wrapping_mul(0xD816_3841);
+    mix32(hx ^ hy) as f32 / u32::MAX as f32
+}
+
+fn wet_ring(x: i32, y: i32, radius: f32, mask_at: &impl Fn(i32, i32) -> f32) -> f32 {
+    let total: f32 = WET_DIRECTIONS
+        .iter()
+        .map(|(dx, dy)| {
+            let ox = (dx * radius + 0.5).floor() as i32;
+            let oy = (dy * radius + 0.5).floor() as i32;
+            mask_at(x + ox, y + oy)
+        })
+        .sum();
+    total / WET_DIRECTIONS.len() as f32
+}
+
+/// Pigment density of a finished watercolour stroke at `(x, y)`, from the
+/// pooled stroke mask (`mask_at` is 0 off the canvas). Mirrors `wet_density`
+/// in the brush shader.
+pub(crate) fn wet_density(
+    settings: &WetSettings,
+    x: i32,
+    y: i32,
+    mask_at: impl Fn(i32, i32) -> f32,
+) -> f32 {
+    let mask = mask_at(x, y);
+    let mut pooled = mask;
+    if settings.bleed >= 1.0 {
+        let spread = 0.5
+            * (wet_ring(x, y, settings.bleed, &mask_at)
+                + wet_ring(x, y, settings.bleed * 0.5, &mask_at));
+        pooled = pooled.max(spread);
+    }
+    if pooled <= 0.0 {
+        return 0.0;
+    }
+    let rim = clamp01(2.0 * (mask - wet_ring(x, y, WET_EDGE_REACH, &mask_at)));
+    let edge = settings.edge;
+    let grain = 1.0 + settings.granulation * (wet_granule(x, y) - 0.5);
+    clamp01(pooled * (1.0 - 0.5 * edge + 1.5 * edge * rim) * grain)
+}
+
+/// The pre-stroke pixel `base` under a watercolour wash of `density`.
+pub(crate) fn wet_composite(base: u32, color_argb: u32, density: f32, lock_alpha: bool) -> u32 {
+    let alpha = clamp01(density * unpack_a(color_argb));
+    let (r, g, b) = (unpack_r(color_argb), unpack_g(color_argb), unpack_b(color_argb));
+    if lock_alpha {
+        blend_paint_locked(base, r, g, b, alpha)
+    } else {
+        blend_paint(base, r, g, b, alpha)
+    }
+}
+
+/// A hollow stroke texel: `paint` coverage of the ring over `under`, then
+/// the `erase` coverage of the core cut out of it. Mirrors the hollow branch
+/// of `draw_brush_stroke` in brush_shaders.wgsl.
+pub(crate) fn hollow_composite(
+    under: u32,
+    color_argb: u32,
+    paint: f32,
+    erase: f32,
+    lock_alpha: bool,
+) -> u32 {
+    let alpha = clamp01(paint * unpack_a(color_argb));
+    let (r, g, b) = (unpack_r(color_argb), unpack_g(color_argb), unpack_b(color_argb));
+    if lock_alpha {
+        return blend_paint_locked(under, r, g, b, alpha);
+    }
+    blend_erase(blend_paint(under, r, g, b, alpha), clamp01(erase))
+}
+
+/// How the secondary tip of a dual brush combines with the primary tip.
+/// Indices follow Photoshop's Dual Brush mode list.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum DualBlend {
+    #[default]
+    Multiply,
+    Darken,
+    Overlay,
+    ColorDodge,
+    ColorBurn,
+    LinearBurn,
+    HardMix,
+}
+
+impl DualBlend {
+    pub(crate) fn from_index(index: u32) -> Self {
+        match index {
+            1 => Self::Darken,
+            2 => Self::Overlay,
+            3 => Self::ColorDodge,
+            4 => Self::ColorBurn,
+            5 => Self::LinearBurn,
+            6 => Self::HardMix,
+            _ => Self::Multiply,
+        }
+    }
+
+    /// `dual_mode` of the brush shader; 0 there means no secondary tip.
+    pub(crate) fn shader_mode(self) -> u32 {
+        self as u32 + 1
+    }
+
+    /// Coverage of the primary tip `primary` under secondary coverage `dual`.
+    pub(crate) fn apply(self, primary: f32, dual: f32) -> f32 {
+        let (p, d) = (clamp01(primary), clamp01(dual));
+        let out = match self {
+            Self::Multiply => p * d,
+            Self::Darken => p.min(d),
+            Self::Overlay => {
+                if p < 0.5 {
+                    2.0 * p * d
+                } else {
+                    1.0 - 2.0 * (1.0 - p) * (1.0 - d)
+                }
+            }
+            Self::ColorDodge => {
+                if d >= 1.0 {
+                    if p > 0.0 {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                } else {
+                    p / (1.0 - d)
+                }
+            }
+            Self::ColorBurn => {
+                if d <= 0.0 {
+                    0.0
+                } else {
+                    1.0 - (1.0 - p) / d
+                }
+            }
+            Self::LinearBurn => p + d - 1.0,
+            Self::HardMix => {
+                if p + d > 1.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        };
+        clamp01(out)
+    }
+}
+
+/// Secondary tip of a dual brush. Its dabs are walked along the primary dabs
+/// with their own size, spacing and scatter, and only modulate coverage; the
+/// tip image comes from the engine's dual brush mask, else `shape`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct DualTipSettings {
+    pub(crate) enabled: bool,
+    pub(crate) blend: DualBlend,
+    pub(crate) shape: u32,
+    /// Secondary radius relative to the primary radius.
+    pub(crate) scale: f32,
+    /// Distance between secondary stamps, in secondary diameters.
+    pub(crate) spacing: f32,
+    /// Scatter distance in primary diameters.
+    pub(crate) scatter: f32,
+    /// Secondary dabs per stamp.
+    pub(crate) count: u32,
+    /// 0..1: random shrink of each secondary dab.
+    pub(crate) size_jitter: f32,
+}
+
+impl Default for DualTipSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            blend: DualBlend::Multiply,
+            shape: 0,
+            scale: 0.5,
+            spacing: 0.25,
+            scatter: 0.0,
+            count: 1,
+            size_jitter: 0.0,
+        }
+    }
+}
+
+// Keeps long strokes with tiny, dense secondary tips bounded.
+const DUAL_MAX_DABS: usize = 4096;
+const DUAL_MAX_COUNT: u32 = 16;
+
+impl DualTipSettings {
+    pub(crate) fn sanitize(&mut self) {
+        let finite = |value: f32, fallback: f32| if value.is_finite() { value } else { fallback };
+        self.shape = if self.shape <= 3 { self.shape } else { 0 };
+        self.scale = finite(self.scale, 0.5).clamp(0.05, 4.0);
+        self.spacing = finite(self.spacing, 0.25).clamp(0.02, 2.5);
+        self.scatter = finite(self.scatter, 0.0).clamp(0.0, 5.0);
+        self.count = self.count.clamp(1, DUAL_MAX_COUNT);
+        self.size_jitter = clamp01(finite(self.size_jitter, 0.0));
+    }
+
+    fn stamp(&self, out: &mut Vec<BrushPoint>, x: f32, y: f32, radius: f32, seed: u32) {
+        let scatter_radius = radius * 2.0 * self.scatter;
+        for k in 0..self.count {
+            if out.len() >= DUAL_MAX_DABS {
+                return;
+            }
+            let salt = k.wrapping_mul(4);
+            let (jx, jy) = brush_scatter_offset(x, y, seed, scatter_radius, salt);
+            let shrink = brush_random_unit(x, y, seed, salt.wrapping_add(2)) * self.size_jitter;
+            out.push(BrushPoint {
+                x: x + jx,
+                y: y + jy,
+                radius: radius * self.scale * (1.0 - shrink),
+                alpha: 1.0,
+                rot_sin: 0.0,
+                rot_cos: 1.0,
+            });
+        }
+    }
+}
+
+/// Secondary dabs of a dual brush, stamped along the primary dab centres
+/// `path` at the secondary spacing.
+pub(crate) fn dual_tip_dabs(
+    settings: &DualTipSettings,
+    path: &[BrushPoint],
+    seed: u32,
+) -> Vec<BrushPoint> {
+    let mut out = Vec::new();
+    let Some(first) = path.first() else {
+        return out;
+    };
+    if !settings.enabled {
+        return out;
+    }
+    settings.stamp(&mut out, first.x, first.y, first.radius, seed);
+    let mut since_stamp = 0.0f32;
+    for pair in path.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let len = ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt();
+        if !len.is_finite() || len <= EPS {
+            continue;
+        }
+        let mut pos = 0.0f32;
+        loop {
+            let t = pos / len;
+            let radius = a.radius + (b.radius - a.radius) * t;
+            let step = (radius * settings.scale * 2.0 * settings.spacing).max(0.5);
+            let ahead = step - since_stamp;
+            if pos + ahead > len {
+                since_stamp += len - pos;
+                break;
+            }
+            pos += ahead;
+            since_stamp = 0.0;
+            let t = pos / len;
+            let radius = a.radius + (b.radius - a.radius) * t;
+            let x = a.x + (b.x - a.x) * t;
+            let y = a.y + (b.y - a.y) * t;
+            settings.stamp(&mut out, x, y, radius, seed);
+            if out.len() >= DUAL_MAX_DABS {
+                return out;
+            }
+        }
+    }
+    out
+}
+
+/// A dual brush's secondary dabs and tip as seen by the rasterizer.
+#[derive(Clone, Copy)]
+pub(crate) struct DualTip<'a> {
+    pub(crate) blend: DualBlend,
+    pub(crate) shape: u32,
+    /// Two-channel tip image like `BrushDrawParams::custom_mask`.
+    pub(crate) mask: Option<(u32, u32, &'a [u8])>,
+    pub(crate) dabs: &'a [BrushPoint],
+}
+
+/// Mirrors `dual_coverage` in the brush shader.
+fn dual_coverage(
+    x: f32,
+    y: f32,
+    dabs: &[BrushPoint],
+    shape: u32,
+    mask: Option<&CustomMaskView<'_>>,
+    aa_level: u32,
+) -> f32 {
+    let mut remain = 1.0f32;
+    for d in dabs {
+        let reach = d.radius * SQRT_2 + 4.0;
+        if (x - d.x).powi(2) + (y - d.y).powi(2) > reach * reach {
+            continue;
+        }
+        let a = if let Some(mask) = mask {
+            let (rx, ry) = rotate_to_brush_space(x - d.x, y - d.y, d.rot_sin, d.rot_cos);
+            custom_mask_alpha(rx, ry, d.radius, 0.0, mask)
+        } else {
+            let dist =
+                shape_distance_to_point(x, y, d.x, d.y, d.radius, d.rot_sin, d.rot_cos, shape);
+            brush_alpha(dist, d.radius, 0.0, aa_level)
+        };
+        remain *= 1.0 - clamp01(a * d.alpha);
+        if remain <= 0.0001 {
+            return 1.0;
+        }
+    }
+    1.0 - remain
+}
+
+fn premultiplied(argb: u32) -> [f32; 4] {
+    let a = unpack_a(argb);
+    [unpack_r(argb) * a, unpack_g(argb) * a, unpack_b(argb) * a, a]
+}
+
+fn lerp4(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    std::array::from_fn(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+/// Average premultiplied colour under a dab: its centre and four points half
+/// a radius out. `load` returns the pre-stroke ARGB pixel. Mirrors
+/// `mix_sample` in the brush shader.
+pub(crate) fn mix_pickup_sample(
+    x: f32,
+    y: f32,
+    radius: f32,
+    width: u32,
+    height: u32,
+    mut load: impl FnMut(u32, u32) -> u32,
+) -> [f32; 4] {
+    if width == 0 || height == 0 {
+        return [0.0; 4];
+    }
+    let reach = radius.max(0.0) * 0.5;
+    let taps = [(0.0, 0.0), (reach, 0.0), (-reach, 0.0), (0.0, reach), (0.0, -reach)];
+    let mut sum = [0.0f32; 4];
+    for (dx, dy) in taps {
+        let tx = ((x + dx).floor() as i64).clamp(0, width as i64 - 1) as u32;
+        let ty = ((y + dy).floor() as i64).clamp(0, height as i64 - 1) as u32;
+        let texel = premultiplied(load(tx, ty));
+        for (total, value) in sum.iter_mut().zip(texel) {
+            *total += value;
+        }
+    }
+    sum.map(|value| value / taps.len() as f32)
+}
+
+/// Paint carried by a mixing brush between dabs, premultiplied RGBA.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct MixReservoir {
+    color: [f32; 4],
+    primed: bool,
+}
+
+impl MixReservoir {
+    /// Takes up `sample` (see [`mix_pickup_sample`]) for the next dab and
+    /// returns the premultiplied colour that dab lays down. Mirrors
+    /// `mix_step` in the brush shader.
+    pub(crate) fn step(
+        &mut self,
+        settings: &MixSettings,
+        color_argb: u32,
+        sample: [f32; 4],
+    ) -> [f32; 4] {
+        let color = if settings.mode == MixMode::Smudge {
+            if self.primed {
+                lerp4(self.color, sample, settings.pickup)
+            } else {
+                sample
+            }
+        } else {
+            let fresh = premultiplied(color_argb).map(|value| value * (1.0 - settings.dilution));
+            let load = if self.primed {
+                lerp4(fresh, self.color, settings.persistence)
+            } else {
+                fresh
+            };
+            let taken = if settings.mode == MixMode::Watercolor {
+                let keep = (1.0 - sample[3]) * settings.extension;
+                std::array::from_fn(|i| sample[i] + load[i] * keep)
+            } else {
+                sample
+            };
+            lerp4(load, taken, settings.pickup)
+        };
+        self.color = color.map(clamp01);
+        self.primed = true;
+        self.color
+    }
+}
+
 fn needs_supersampling(radius: f32, antialias_level: u32) -> bool {
     if antialias_level == 0 || radius <= 0.0 {
         return false;
@@ -600,6 +1262,38 @@ fn blend_paint(dst: u32, src_r: f32, src_g: f32, src_b: f32, src_a: f32) -> u32
     pack_argb(out_a, out_r, out_g, out_b)
 }
 
+/// Alpha-locked paint recolours existing pixels and leaves their coverage alone.
+fn blend_paint_locked(dst: u32, src_r: f32, src_g: f32, src_b: f32, src_a: f32) -> u32 {
+    let da = unpack_a(dst);
+    if src_a <= 0.0 || da <= 0.0 {
+        return dst;
+    }
+    let t = clamp01(src_a);
+    let mix = |d: f32, s: f32| d + (s - d) * t;
+    pack_argb(
+        da,
+        mix(unpack_r(dst), src_r),
+        mix(unpack_g(dst), src_g),
+        mix(unpack_b(dst), src_b),
+    )
+}
+
+/// Result of an edit on an alpha-locked layer: the colour of `after` with the
+/// alpha of `before`. Pixels the edit made transparent keep their old colour.
+pub(crate) fn lock_alpha_texel(before: u32, after: u32) -> u32 {
+    if before >> 24 == 0 || after >> 24 == 0 {
+        return before;
+    }
+    (before & 0xFF00_0000) | (after & 0x00FF_FFFF)
+}
+
+/// [`lock_alpha_texel`] over a whole buffer, rewriting `after` in place.
+pub(crate) fn lock_alpha_pixels(before: &[u32], after: &mut [u32]) {
+    for (pixel, &old) in after.iter_mut().zip(before) {
+        *pixel = lock_alpha_texel(old, *pixel);
+    }
+}
+
 fn blend_erase(dst: u32, erase_a: f32) -> u32 {
     if erase_a <= 0.0 {
         return dst;
@@ -615,6 +1309,23 @@ fn blend_erase(dst: u32, erase_a: f32) -> u32 {
     pack_argb(out_a, unpack_r(dst), unpack_g(dst), unpack_b(dst))
 }
 
+/// Lays the premultiplied `dab` colour over `dst` by `amount`, replacing
+/// rather than adding paint so a smudge can also thin the layer out.
+fn blend_mix(dst: u32, dab: [f32; 4], amount: f32, lock_alpha: bool) -> u32 {
+    if amount <= 0.0 {
+        return dst;
+    }
+    let out = lerp4(premultiplied(dst), dab, clamp01(amount));
+    if out[3] <= EPS {
+        return if lock_alpha { dst } else { 0 };
+    }
+    let out_a = if lock_alpha { unpack_a(dst) } else { out[3] };
+    if out_a <= 0.0 {
+        return dst;
+    }
+    pack_argb(out_a, out[0] / out[3], out[1] / out[3], out[2] / out[3])
+}
+
 #[derive(Clone, Copy, Debug)]
 struct CapsuleCoverageSample {
     coverage: f32,
@@ -954,13 +1665,35 @@ fn stroke_stamp_spacing_custom_mask(
 }
 
 #[derive(Clone, Copy)]
-struct BrushPoint {
-    x: f32,
-    y: f32,
-    radius: f32,
-    alpha: f32,
-    rot_sin: f32,
-    rot_cos: f32,
+pub(crate) struct BrushPoint {
+    pub(crate) x: f32,
+    pub(crate) y: f32,
+    pub(crate) radius: f32,
+    pub(crate) alpha: f32,
+    pub(crate) rot_sin: f32,
+    pub(crate) rot_cos: f32,
+}
+
+/// Canvas rectangle backed by the pixel buffer handed to the rasterizer. Dab
+/// positions and the selection mask stay in canvas coordinates; only the
+/// destination indexing is offset.
+#[derive(Clone, Copy)]
+pub(crate) struct PixelWindow {
+    pub(crate) left: u32,
+    pub(crate) top: u32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+impl PixelWindow {
+    pub(crate) fn full(width: u32, height: u32) -> Self {
+        Self {
+            left: 0,
+            top: 0,
+            width,
+            height,
+        }
+    }
 }
 
 fn draw_points_sampled<'a>(
@@ -968,6 +1701,7 @@ fn draw_points_sampled<'a>(
     pixels_len: usize,
     width: u32,
     height: u32,
+    window: PixelWindow,
     points: &[BrushPoint],
     color_argb: u32,
     brush_shape: u32,
@@ -979,12 +1713,28 @@ fn draw_points_sampled<'a>(
     selection_len: usize,
     custom_mask: Option<CustomMaskView<'a>>,
     screentone: ScreentoneSettings,
+    grain: Option<(GrainSettings, GrainTexture<'a>)>,
+    mix_colors: Option<&[[f32; 4]]>,
+    dual: Option<DualTip<'a>>,
+    dab_colors: Option<&[u32]>,
+    lock_alpha: bool,
 ) -> u8 {
     if points.is_empty() || pixels_ptr.is_null() || width == 0 || height == 0 {
         return 0;
     }
     let pixel_count = (width as usize).saturating_mul(height as usize);
-    if pixel_count == 0 || pixels_len < pixel_count {
+    if pixel_count == 0 {
+        return 0;
+    }
+    if window.width == 0
+        || window.height == 0
+        || window.left.saturating_add(window.width) > width
+        || window.top.saturating_add(window.height) > height
+    {
+        return 0;
+    }
+    let window_pixel_count = (window.width as usize) * (window.height as usize);
+    if pixels_len < window_pixel_count {
         return 0;
     }
 
@@ -993,11 +1743,27 @@ fn draw_points_sampled<'a>(
     } else {
         Some(unsafe { std::slice::from_raw_parts(selection_ptr, selection_len) })
     };
-
+    let window_min_x = window.left as f32;
+    let window_min_y = window.top as f32;
+    let window_max_x = (window.left + window.width) as f32 - 1.0;
+    let window_max_y = (window.top + window.height) as f32 - 1.0;
+
+    // Mixing dabs carry their own colours, one per point.
+    let mix_colors = mix_colors
+        .filter(|colors| accumulate && erase == 0 && colors.len() == points.len());
     let base_a = unpack_a(color_argb);
-    if base_a <= 0.0 {
+    if (base_a <= 0.0 && mix_colors.is_none()) || (lock_alpha && erase != 0) {
         return 1;
     }
+    // Colour dynamics tint each dab; only the colour's RGB is used.
+    let dab_colors: Option<Vec<[f32; 3]>> = dab_colors
+        .filter(|colors| mix_colors.is_none() && erase == 0 && colors.len() == points.len())
+        .map(|colors| {
+            colors
+                .iter()
+                .map(|&c| [unpack_r(c), unpack_g(c), unpack_b(c)])
+                .collect()
+        });
     let src_r = unpack_r(color_argb);
     let src_g = unpack_g(color_argb);
     let src_b = unpack_b(color_argb);
@@ -1033,10 +1799,10 @@ fn draw_points_sampled<'a>(
         }
         let edge = feather.max(p.radius * soft);
         let outer = p.radius + edge + 1.5;
-        let min_x = (p.x - outer).floor().max(0.0) as i32;
-        let max_x = (p.x + outer).ceil().min((width as f32) - 1.0) as i32;
-        let min_y = (p.y - outer).floor().max(0.0) as i32;
-        let max_y = (p.y + outer).ceil().min((height as f32) - 1.0) as i32;
+        let min_x = (p.x - outer).floor().max(window_min_x) as i32;
+        let max_x = (p.x + outer).ceil().min(window_max_x) as i32;
+        let min_y = (p.y - outer).floor().max(window_min_y) as i32;
+        let max_y = (p.y + outer).ceil().min(window_max_y) as i32;
         if min_x > max_x || min_y > max_y {
             continue;
         }
@@ -1058,26 +1824,51 @@ fn draw_points_sampled<'a>(
         return 1;
     }
 
-    let pixels = unsafe { std::slice::from_raw_parts_mut(pixels_ptr, pixel_count) };
+    // Only secondary dabs near the primary dabs can change their coverage.
+    let dual = dual.map(|tip| {
+        let mask = tip.mask.and_then(|(mask_width, mask_height, data)| {
+            custom_mask_view_from_params(mask_width, mask_height, data.as_ptr(), data.len())
+        });
+        let dabs: Vec<BrushPoint> = tip
+            .dabs
+            .iter()
+            .filter(|d| {
+                let reach = d.radius * SQRT_2 + 4.0;
+                d.x + reach >= union_min_x as f32
+                    && d.x - reach <= union_max_x as f32 + 1.0
+                    && d.y + reach >= union_min_y as f32
+                    && d.y - reach <= union_max_y as f32 + 1.0
+            })
+            .copied()
+            .collect();
+        (tip.blend, tip.shape, mask, dabs)
+    });
+
+    let pixels = unsafe { std::slice::from_raw_parts_mut(pixels_ptr, window_pixel_count) };
     for y in union_min_y..=union_max_y {
         let src_row = (y as usize) * (width as usize);
+        let dst_row = ((y as u32 - window.top) as usize) * (window.width as usize);
         for x in union_min_x..=union_max_x {
-            let dst_idx = src_row + (x as usize);
+            let dst_idx = dst_row + ((x as u32 - window.left) as usize);
             if let Some(mask) = selection {
-                if mask.get(dst_idx).copied().unwrap_or(0) == 0 {
+                if mask.get(src_row + (x as usize)).copied().unwrap_or(0) == 0 {
                     continue;
                 }
             }
             let mut accum = 0.0f32;
+            let mut mix_accum = [0.0f32; 4];
+            let mut rgb_accum = [0.0f32; 3];
             for sy in 0..samples {
                 for sx in 0..samples {
                     let ox = (sx as f32 + 0.5) * inv_samples - 0.5;
                     let oy = (sy as f32 + 0.5) * inv_samples - 0.5;
                     let sample_x = x as f32 + 0.5 + ox;
                     let sample_y = y as f32 + 0.5 + oy;
+                    let mut sample_mix = [0.0f32; 4];
+                    let mut sample_rgb = [0.0f32; 3];
                     let mut sample_cov = if accumulate {
                         let mut remain = 1.0f32;
-                        for p in points {
+                        for (idx, p) in points.iter().enumerate() {
                             let a = if let Some(mask) = custom_mask {
                                 let rel_x = sample_x - p.x;
                                 let rel_y = sample_y - p.y;
@@ -1098,6 +1889,18 @@ fn draw_points_sampled<'a>(
                                 brush_alpha(dist, p.radius, soft, aa_level) * clamp01(p.alpha)
                             };
                             if a > 0.0 {
+                                if let Some(colors) = mix_colors {
+                                    let contrib = a * remain;
+                                    for (total, value) in sample_mix.iter_mut().zip(colors[idx]) {
+                                        *total += value * contrib;
+                                    }
+                                }
+                                if let Some(colors) = dab_colors.as_ref() {
+                                    let contrib = a * remain;
+                                    for (total, value) in sample_rgb.iter_mut().zip(colors[idx]) {
+                                        *total += value * contrib;
+                                    }
+                                }
                                 remain *= 1.0 - a;
                                 if remain <= 0.0001 {
                                     remain = 0.0;
@@ -1108,7 +1911,8 @@ fn draw_points_sampled<'a>(
                         1.0 - remain
                     } else {
                         let mut best = 0.0f32;
-                        for p in points {
+                        let mut best_idx = 0usize;
+                        for (idx, p) in points.iter().enumerate() {
                             let a = if let Some(mask) = custom_mask {
                                 let rel_x = sample_x - p.x;
                                 let rel_y = sample_y - p.y;
@@ -1130,13 +1934,31 @@ fn draw_points_sampled<'a>(
                             };
                             if a > best {
                                 best = a;
+                                best_idx = idx;
                                 if best >= 1.0 {
                                     break;
                                 }
                             }
                         }
+                        if let Some(colors) = dab_colors.as_ref() {
+                            sample_rgb = colors[best_idx].map(|value| value * best);
+                        }
                         best
                     };
+                    let unmodulated = sample_cov;
+                    if let Some((blend, shape, mask, dabs)) = dual.as_ref() {
+                        if sample_cov > 0.0 {
+                            let d = dual_coverage(
+                                sample_x,
+                                sample_y,
+                                dabs,
+                                *shape,
+                                mask.as_ref(),
+                                aa_level,
+                            );
+                            sample_cov = blend.apply(sample_cov, d);
+                        }
+                    }
                     if use_screentone {
                         let mask = screentone_mask_at(sample_x, sample_y, screentone, aa_level);
                         if mask <= 0.0 {
@@ -1144,6 +1966,21 @@ fn draw_points_sampled<'a>(
                         }
                         sample_cov *= mask;
                     }
+                    if let Some((settings, texture)) = grain.as_ref() {
+                        sample_cov = apply_grain(sample_cov, sample_x, sample_y, settings, texture);
+                    }
+                    if mix_colors.is_some() && unmodulated > EPS {
+                        let scale = sample_cov / unmodulated;
+                        for (total, value) in mix_accum.iter_mut().zip(sample_mix) {
+                            *total += value * scale;
+                        }
+                    }
+                    if dab_colors.is_some() && unmodulated > EPS {
+                        let scale = sample_cov / unmodulated;
+                        for (total, value) in rgb_accum.iter_mut().zip(sample_rgb) {
+                            *total += value * scale;
+                        }
+                    }
                     accum += sample_cov;
                 }
             }
@@ -1151,13 +1988,25 @@ fn draw_points_sampled<'a>(
             if coverage <= 0.0 {
                 continue;
             }
+            if mix_colors.is_some() {
+                let dab = mix_accum.map(|value| value / accum.max(EPS));
+                pixels[dst_idx] = blend_mix(pixels[dst_idx], dab, coverage, lock_alpha);
+                continue;
+            }
             let paint_a = clamp01(coverage * base_a);
             if paint_a <= 0.0 {
                 continue;
             }
+            let [src_r, src_g, src_b] = if dab_colors.is_some() {
+                rgb_accum.map(|value| clamp01(value / accum.max(EPS)))
+            } else {
+                [src_r, src_g, src_b]
+            };
             let dst = pixels[dst_idx];
             pixels[dst_idx] = if erase != 0 {
                 blend_erase(dst, paint_a)
+            } else if lock_alpha {
+                blend_paint_locked(dst, src_r, src_g, src_b, paint_a)
             } else {
                 blend_paint(dst, src_r, src_g, src_b, paint_a)
             };
@@ -1167,6 +2016,73 @@ fn draw_points_sampled<'a>(
     1
 }
 
+/// Dab style shared by every point of a [`cpu_brush_draw_points`] call.
+#[derive(Clone, Copy)]
+pub(crate) struct BrushDrawParams<'a> {
+    pub(crate) color_argb: u32,
+    pub(crate) brush_shape: u32,
+    pub(crate) antialias_level: u32,
+    pub(crate) softness: f32,
+    pub(crate) erase: bool,
+    pub(crate) accumulate: bool,
+    pub(crate) selection: Option<&'a [u8]>,
+    pub(crate) custom_mask: Option<(u32, u32, &'a [u8])>,
+    pub(crate) screentone: ScreentoneSettings,
+    pub(crate) grain: Option<(GrainSettings, GrainTexture<'a>)>,
+    /// Premultiplied colour of each point for mixing brushes, which then
+    /// ignore `color_argb` (see [`MixReservoir`]).
+    pub(crate) mix_colors: Option<&'a [[f32; 4]]>,
+    pub(crate) dual: Option<DualTip<'a>>,
+    /// Straight ARGB colour of each point from colour dynamics; only the
+    /// RGB is used, the opacity stays `color_argb`'s.
+    pub(crate) dab_colors: Option<&'a [u32]>,
+    /// Keep the destination alpha (see [`lock_alpha_texel`]).
+    pub(crate) lock_alpha: bool,
+}
+
+/// Safe entry point for in-process callers (the CPU canvas engine) that own the
+/// layer buffer directly instead of going through the C ABI. `pixels` holds
+/// the `window` rectangle of a `width`x`height` canvas.
+pub(crate) fn cpu_brush_draw_points(
+    pixels: &mut [u32],
+    width: u32,
+    height: u32,
+    window: PixelWindow,
+    points: &[BrushPoint],
+    params: &BrushDrawParams,
+) -> bool {
+    let (selection_ptr, selection_len) = match params.selection {
+        Some(mask) => (mask.as_ptr(), mask.len()),
+        None => (std::ptr::null(), 0),
+    };
+    let custom_mask = params.custom_mask.and_then(|(mask_width, mask_height, data)| {
+        custom_mask_view_from_params(mask_width, mask_height, data.as_ptr(), data.len())
+    });
+    draw_points_sampled(
+        pixels.as_mut_ptr(),
+        pixels.len(),
+        width,
+        height,
+        window,
+        points,
+        params.color_argb,
+        params.brush_shape,
+        params.antialias_level,
+        params.softness,
+        if params.erase { 1 } else { 0 },
+        params.accumulate,
+        selection_ptr,
+        selection_len,
+        custom_mask,
+        params.screentone,
+        params.grain.filter(|(settings, texture)| settings.enabled && texture.is_valid()),
+        params.mix_colors,
+        params.dual,
+        params.dab_colors,
+        params.lock_alpha,
+    ) != 0
+}
+
 #[no_mangle]
 pub extern "C" fn cpu_brush_draw_stamp(
     pixels_ptr: *mut u32,
@@ -1261,6 +2177,7 @@ pub extern "C" fn cpu_brush_draw_stamp(
         pixels_len,
         width,
         height,
+        PixelWindow::full(width, height),
         std::slice::from_ref(&point),
         color_argb,
         brush_shape,
@@ -1272,6 +2189,11 @@ pub extern "C" fn cpu_brush_draw_stamp(
         selection_len,
         custom_mask,
         screentone,
+        None,
+        None,
+        None,
+        None,
+        false,
     )
 }
 
@@ -1477,6 +2399,7 @@ pub extern "C" fn cpu_brush_draw_stamp_segment(
         pixels_len,
         width,
         height,
+        PixelWindow::full(width, height),
         &points,
         color_argb,
         brush_shape,
@@ -1488,6 +2411,11 @@ pub extern "C" fn cpu_brush_draw_stamp_segment(
         selection_len,
         custom_mask,
         screentone,
+        None,
+        None,
+        None,
+        None,
+        false,
     )
 }
 
@@ -1942,6 +2870,7 @@ pub extern "C" fn cpu_brush_draw_spray(
         pixels_len,
         width,
         height,
+        PixelWindow::full(width, height),
         &brush_points,
         color_argb,
         brush_shape,
@@ -1953,6 +2882,11 @@ pub extern "C" fn cpu_brush_draw_spray(
         selection_len,
         None,
         ScreentoneSettings::disabled(),
+        None,
+        None,
+        None,
+        None,
+        false,
     )
 }
 
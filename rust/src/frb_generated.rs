@@ -962,6 +962,80 @@ fn wire__crate__api__cpu_transform__cpu_transform_translate_layer_impl(
         },
     )
 }
+fn wire__crate__api__engine_events__engine_event_stream_impl(
+    port_: flutter_rust_bridge::for_generated::MessagePort,
+    ptr_: flutter_rust_bridge::for_generated::PlatformGeneralizedUint8ListPtr,
+    rust_vec_len_: i32,
+    data_len_: i32,
+) {
+    FLUTTER_RUST_BRIDGE_HANDLER.wrap_normal::<flutter_rust_bridge::for_generated::SseCodec, _, _>(
+        flutter_rust_bridge::for_generated::TaskInfo {
+            debug_name: "engine_event_stream",
+            port: Some(port_),
+            mode: flutter_rust_bridge::for_generated::FfiCallMode::Normal,
+        },
+        move || {
+            let message = unsafe {
+                flutter_rust_bridge::for_generated::Dart2RustMessageSse::from_wire(
+                    ptr_,
+                    rust_vec_len_,
+                    data_len_,
+                )
+            };
+            let mut deserializer =
+                flutter_rust_bridge::for_generated::SseDeserializer::new(message);
+            let api_handle = <u64>::sse_decode(&mut deserializer);
+            let api_sink = <StreamSink<
+                crate::api::engine_events::EngineEvent,
+                flutter_rust_bridge::for_generated::SseCodec,
+            >>::sse_decode(&mut deserializer);
+            deserializer.end();
+            move |context| {
+                transform_result_sse::<_, ()>((move || {
+                    let output_ok = Result::<_, ()>::Ok({
+                        crate::api::engine_events::engine_event_stream(api_handle, api_sink);
+                    })?;
+                    Ok(output_ok)
+                })())
+            }
+        },
+    )
+}
+fn wire__crate__api__engine_history__engine_history_impl(
+    port_: flutter_rust_bridge::for_generated::MessagePort,
+    ptr_: flutter_rust_bridge::for_generated::PlatformGeneralizedUint8ListPtr,
+    rust_vec_len_: i32,
+    data_len_: i32,
+) {
+    FLUTTER_RUST_BRIDGE_HANDLER.wrap_normal::<flutter_rust_bridge::for_generated::SseCodec, _, _>(
+        flutter_rust_bridge::for_generated::TaskInfo {
+            debug_name: "engine_history",
+            port: Some(port_),
+            mode: flutter_rust_bridge::for_generated::FfiCallMode::Normal,
+        },
+        move || {
+            let message = unsafe {
+                flutter_rust_bridge::for_generated::Dart2RustMessageSse::from_wire(
+                    ptr_,
+                    rust_vec_len_,
+                    data_len_,
+                )
+            };
+            let mut deserializer =
+                flutter_rust_bridge::for_generated::SseDeserializer::new(message);
+            let api_handle = <u64>::sse_decode(&mut deserializer);
+            deserializer.end();
+            move |context| {
+                transform_result_sse::<_, ()>((move || {
+                    let output_ok = Result::<_, ()>::Ok(
+                        crate::api::engine_history::engine_history(api_handle),
+                    )?;
+                    Ok(output_ok)
+                })())
+            }
+        },
+    )
+}
 fn wire__crate__api__bucket_fill__flood_fill_in_place_impl(
     port_: flutter_rust_bridge::for_generated::MessagePort,
     ptr_: flutter_rust_bridge::for_generated::PlatformGeneralizedUint8ListPtr,
@@ -1492,6 +1566,40 @@ fn wire__crate__api__psd__import_psd_impl(
         },
     )
 }
+fn wire__crate__api__psd__psd_adjustment_layer_info_impl(
+    ptr_: flutter_rust_bridge::for_generated::PlatformGeneralizedUint8ListPtr,
+    rust_vec_len_: i32,
+    data_len_: i32,
+) -> flutter_rust_bridge::for_generated::WireSyncRust2DartSse {
+    FLUTTER_RUST_BRIDGE_HANDLER.wrap_sync::<flutter_rust_bridge::for_generated::SseCodec, _>(
+        flutter_rust_bridge::for_generated::TaskInfo {
+            debug_name: "psd_adjustment_layer_info",
+            port: None,
+            mode: flutter_rust_bridge::for_generated::FfiCallMode::Sync,
+        },
+        move || {
+            let message = unsafe {
+                flutter_rust_bridge::for_generated::Dart2RustMessageSse::from_wire(
+                    ptr_,
+                    rust_vec_len_,
+                    data_len_,
+                )
+            };
+            let mut deserializer =
+                flutter_rust_bridge::for_generated::SseDeserializer::new(message);
+            let api_filter_type = <u32>::sse_decode(&mut deserializer);
+            let api_params = <Vec<f32>>::sse_decode(&mut deserializer);
+            deserializer.end();
+            transform_result_sse::<_, ()>((move || {
+                let output_ok = Result::<_, ()>::Ok(crate::api::psd::psd_adjustment_layer_info(
+                    api_filter_type,
+                    api_params,
+                ))?;
+                Ok(output_ok)
+            })())
+        },
+    )
+}
 fn wire__crate__api__simple__init_app_impl(
     port_: flutter_rust_bridge::for_generated::MessagePort,
     ptr_: flutter_rust_bridge::for_generated::PlatformGeneralizedUint8ListPtr,
@@ -1965,6 +2073,16 @@ fn wire__crate__api__workspace__workspace_state_default_impl(
 
 // Section: dart2rust
 
+impl SseDecode
+    for StreamSink<crate::api::engine_events::EngineEvent, flutter_rust_bridge::for_generated::SseCodec>
+{
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    fn sse_decode(deserializer: &mut flutter_rust_bridge::for_generated::SseDeserializer) -> Self {
+        let mut inner = <String>::sse_decode(deserializer);
+        return StreamSink::deserialize(inner);
+    }
+}
+
 impl SseDecode for String {
     // Codec=Sse (Serialization based), see doc to use other codecs
     fn sse_decode(deserializer: &mut flutter_rust_bridge::for_generated::SseDeserializer) -> Self {
@@ -2228,6 +2346,94 @@ impl SseDecode for crate::api::cpu_transform::CpuTransformTranslateResult {
     }
 }
 
+impl SseDecode for crate::api::engine_events::EngineEvent {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    fn sse_decode(deserializer: &mut flutter_rust_bridge::for_generated::SseDeserializer) -> Self {
+        let mut var_kind = <crate::api::engine_events::EngineEventKind>::sse_decode(deserializer);
+        let mut var_layerIndex = <u32>::sse_decode(deserializer);
+        let mut var_left = <i32>::sse_decode(deserializer);
+        let mut var_top = <i32>::sse_decode(deserializer);
+        let mut var_width = <i32>::sse_decode(deserializer);
+        let mut var_height = <i32>::sse_decode(deserializer);
+        let mut var_onMask = <bool>::sse_decode(deserializer);
+        let mut var_canUndo = <bool>::sse_decode(deserializer);
+        let mut var_canRedo = <bool>::sse_decode(deserializer);
+        let mut var_applied = <bool>::sse_decode(deserializer);
+        let mut var_message = <String>::sse_decode(deserializer);
+        return crate::api::engine_events::EngineEvent {
+            kind: var_kind,
+            layer_index: var_layerIndex,
+            left: var_left,
+            top: var_top,
+            width: var_width,
+            height: var_height,
+            on_mask: var_onMask,
+            can_undo: var_canUndo,
+            can_redo: var_canRedo,
+            applied: var_applied,
+            message: var_message,
+        };
+    }
+}
+
+impl SseDecode for crate::api::engine_events::EngineEventKind {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    fn sse_decode(deserializer: &mut flutter_rust_bridge::for_generated::SseDeserializer) -> Self {
+        let mut inner = <i32>::sse_decode(deserializer);
+        return match inner {
+            0 => crate::api::engine_events::EngineEventKind::UndoState,
+            1 => crate::api::engine_events::EngineEventKind::LayerDirty,
+            2 => crate::api::engine_events::EngineEventKind::LayerBounds,
+            3 => crate::api::engine_events::EngineEventKind::BucketFillFinished,
+            4 => crate::api::engine_events::EngineEventKind::FilterFinished,
+            5 => crate::api::engine_events::EngineEventKind::LayersChanged,
+            6 => crate::api::engine_events::EngineEventKind::Error,
+            7 => crate::api::engine_events::EngineEventKind::Warning,
+            _ => unreachable!("Invalid variant for EngineEventKind: {}", inner),
+        };
+    }
+}
+
+impl SseDecode for crate::api::engine_history::EngineHistoryKind {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    fn sse_decode(deserializer: &mut flutter_rust_bridge::for_generated::SseDeserializer) -> Self {
+        let mut inner = <i32>::sse_decode(deserializer);
+        return match inner {
+            0 => crate::api::engine_history::EngineHistoryKind::Start,
+            1 => crate::api::engine_history::EngineHistoryKind::Pixels,
+            2 => crate::api::engine_history::EngineHistoryKind::Layers,
+            _ => unreachable!("Invalid variant for EngineHistoryKind: {}", inner),
+        };
+    }
+}
+
+impl SseDecode for crate::api::engine_history::EngineHistoryNode {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    fn sse_decode(deserializer: &mut flutter_rust_bridge::for_generated::SseDeserializer) -> Self {
+        let mut var_id = <u64>::sse_decode(deserializer);
+        let mut var_parentId = <u64>::sse_decode(deserializer);
+        let mut var_kind =
+            <crate::api::engine_history::EngineHistoryKind>::sse_decode(deserializer);
+        let mut var_label = <String>::sse_decode(deserializer);
+        let mut var_current = <bool>::sse_decode(deserializer);
+        let mut var_onActiveBranch = <bool>::sse_decode(deserializer);
+        let mut var_thumbnailWidth = <u32>::sse_decode(deserializer);
+        let mut var_thumbnailHeight = <u32>::sse_decode(deserializer);
+        let mut var_thumbnail = <Vec<u8>>::sse_decode(deserializer);
+        return crate::api::engine_history::EngineHistoryNode {
+            id: var_id,
+            parent_id: var_parentId,
+            kind: var_kind,
+            label: var_label,
+            current: var_current,
+            on_active_branch: var_onActiveBranch,
+            thumbnail_width: var_thumbnailWidth,
+            thumbnail_height: var_thumbnailHeight,
+            thumbnail: var_thumbnail,
+        };
+    }
+}
+
 impl SseDecode for f32 {
     // Codec=Sse (Serialization based), see doc to use other codecs
     fn sse_decode(deserializer: &mut flutter_rust_bridge::for_generated::SseDeserializer) -> Self {
@@ -2284,12 +2490,14 @@ impl SseDecode for crate::api::gpu_composite::GpuLayerData {
         let mut var_blendModeIndex = <u32>::sse_decode(deserializer);
         let mut var_visible = <bool>::sse_decode(deserializer);
         let mut var_clippingMask = <bool>::sse_decode(deserializer);
+        let mut var_mask = <Option<Vec<u8>>>::sse_decode(deserializer);
         return crate::api::gpu_composite::GpuLayerData {
             pixels: var_pixels,
             opacity: var_opacity,
             blend_mode_index: var_blendModeIndex,
             visible: var_visible,
             clipping_mask: var_clippingMask,
+            mask: var_mask,
         };
     }
 }
@@ -2354,6 +2562,20 @@ impl SseDecode for Vec<crate::api::cpu_brush::CpuBrushCommand> {
     }
 }
 
+impl SseDecode for Vec<crate::api::engine_history::EngineHistoryNode> {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    fn sse_decode(deserializer: &mut flutter_rust_bridge::for_generated::SseDeserializer) -> Self {
+        let mut len_ = <i32>::sse_decode(deserializer);
+        let mut ans_ = vec![];
+        for idx_ in 0..len_ {
+            ans_.push(<crate::api::engine_history::EngineHistoryNode>::sse_decode(
+                deserializer,
+            ));
+        }
+        return ans_;
+    }
+}
+
 impl SseDecode for Vec<crate::api::gpu_composite::GpuLayerData> {
     // Codec=Sse (Serialization based), see doc to use other codecs
     fn sse_decode(deserializer: &mut flutter_rust_bridge::for_generated::SseDeserializer) -> Self {
@@ -2430,6 +2652,18 @@ impl SseDecode for Vec<u8> {
     }
 }
 
+impl SseDecode for Vec<crate::api::psd::PsdGroup> {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    fn sse_decode(deserializer: &mut flutter_rust_bridge::for_generated::SseDeserializer) -> Self {
+        let mut len_ = <i32>::sse_decode(deserializer);
+        let mut ans_ = vec![];
+        for idx_ in 0..len_ {
+            ans_.push(<crate::api::psd::PsdGroup>::sse_decode(deserializer));
+        }
+        return ans_;
+    }
+}
+
 impl SseDecode for Vec<crate::api::psd::PsdLayer> {
     // Codec=Sse (Serialization based), see doc to use other codecs
     fn sse_decode(deserializer: &mut flutter_rust_bridge::for_generated::SseDeserializer) -> Self {
@@ -2552,10 +2786,32 @@ impl SseDecode for crate::api::psd::PsdDocument {
         let mut var_width = <i32>::sse_decode(deserializer);
         let mut var_height = <i32>::sse_decode(deserializer);
         let mut var_layers = <Vec<crate::api::psd::PsdLayer>>::sse_decode(deserializer);
+        let mut var_groups = <Vec<crate::api::psd::PsdGroup>>::sse_decode(deserializer);
         return crate::api::psd::PsdDocument {
             width: var_width,
             height: var_height,
             layers: var_layers,
+            groups: var_groups,
+        };
+    }
+}
+
+impl SseDecode for crate::api::psd::PsdGroup {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    fn sse_decode(deserializer: &mut flutter_rust_bridge::for_generated::SseDeserializer) -> Self {
+        let mut var_id = <u32>::sse_decode(deserializer);
+        let mut var_parentId = <u32>::sse_decode(deserializer);
+        let mut var_name = <String>::sse_decode(deserializer);
+        let mut var_visible = <bool>::sse_decode(deserializer);
+        let mut var_opacity = <u8>::sse_decode(deserializer);
+        let mut var_blendModeKey = <String>::sse_decode(deserializer);
+        return crate::api::psd::PsdGroup {
+            id: var_id,
+            parent_id: var_parentId,
+            name: var_name,
+            visible: var_visible,
+            opacity: var_opacity,
+            blend_mode_key: var_blendModeKey,
         };
     }
 }
@@ -2573,6 +2829,9 @@ impl SseDecode for crate::api::psd::PsdLayer {
         let mut var_bitmapHeight = <i32>::sse_decode(deserializer);
         let mut var_bitmapLeft = <i32>::sse_decode(deserializer);
         let mut var_bitmapTop = <i32>::sse_decode(deserializer);
+        let mut var_parentId = <u32>::sse_decode(deserializer);
+        let mut var_adjustmentFilterType = <Option<u32>>::sse_decode(deserializer);
+        let mut var_adjustmentParams = <Vec<f32>>::sse_decode(deserializer);
         return crate::api::psd::PsdLayer {
             name: var_name,
             visible: var_visible,
@@ -2584,6 +2843,9 @@ impl SseDecode for crate::api::psd::PsdLayer {
             bitmap_height: var_bitmapHeight,
             bitmap_left: var_bitmapLeft,
             bitmap_top: var_bitmapTop,
+            parent_id: var_parentId,
+            adjustment_filter_type: var_adjustmentFilterType,
+            adjustment_params: var_adjustmentParams,
         };
     }
 }
@@ -2701,6 +2963,15 @@ fn pde_ffi_dispatcher_primary_impl(
             rust_vec_len,
             data_len,
         ),
+        48 => wire__crate__api__engine_events__engine_event_stream_impl(
+            port,
+            ptr,
+            rust_vec_len,
+            data_len,
+        ),
+        49 => {
+            wire__crate__api__engine_history__engine_history_impl(port, ptr, rust_vec_len, data_len)
+        }
         _ => unreachable!(),
     }
 }
@@ -2798,6 +3069,7 @@ fn pde_ffi_dispatcher_sync_impl(
         43 => wire__crate__api__workspace__workspace_reset_impl(ptr, rust_vec_len, data_len),
         44 => wire__crate__api__workspace__workspace_set_active_impl(ptr, rust_vec_len, data_len),
         45 => wire__crate__api__workspace__workspace_state_impl(ptr, rust_vec_len, data_len),
+        47 => wire__crate__api__psd__psd_adjustment_layer_info_impl(ptr, rust_vec_len, data_len),
         _ => unreachable!(),
     }
 }
@@ -3099,6 +3371,113 @@ impl flutter_rust_bridge::IntoIntoDart<crate::api::cpu_transform::CpuTransformTr
     }
 }
 // Codec=Dco (DartCObject based), see doc to use other codecs
+impl flutter_rust_bridge::IntoDart for crate::api::engine_events::EngineEvent {
+    fn into_dart(self) -> flutter_rust_bridge::for_generated::DartAbi {
+        [
+            self.kind.into_into_dart().into_dart(),
+            self.layer_index.into_into_dart().into_dart(),
+            self.left.into_into_dart().into_dart(),
+            self.top.into_into_dart().into_dart(),
+            self.width.into_into_dart().into_dart(),
+            self.height.into_into_dart().into_dart(),
+            self.on_mask.into_into_dart().into_dart(),
+            self.can_undo.into_into_dart().into_dart(),
+            self.can_redo.into_into_dart().into_dart(),
+            self.applied.into_into_dart().into_dart(),
+            self.message.into_into_dart().into_dart(),
+        ]
+        .into_dart()
+    }
+}
+impl flutter_rust_bridge::for_generated::IntoDartExceptPrimitive
+    for crate::api::engine_events::EngineEvent
+{
+}
+impl flutter_rust_bridge::IntoIntoDart<crate::api::engine_events::EngineEvent>
+    for crate::api::engine_events::EngineEvent
+{
+    fn into_into_dart(self) -> crate::api::engine_events::EngineEvent {
+        self
+    }
+}
+// Codec=Dco (DartCObject based), see doc to use other codecs
+impl flutter_rust_bridge::IntoDart for crate::api::engine_events::EngineEventKind {
+    fn into_dart(self) -> flutter_rust_bridge::for_generated::DartAbi {
+        match self {
+            Self::UndoState => 0.into_dart(),
+            Self::LayerDirty => 1.into_dart(),
+            Self::LayerBounds => 2.into_dart(),
+            Self::BucketFillFinished => 3.into_dart(),
+            Self::FilterFinished => 4.into_dart(),
+            Self::LayersChanged => 5.into_dart(),
+            Self::Error => 6.into_dart(),
+            Self::Warning => 7.into_dart(),
+            _ => unreachable!(),
+        }
+    }
+}
+impl flutter_rust_bridge::for_generated::IntoDartExceptPrimitive
+    for crate::api::engine_events::EngineEventKind
+{
+}
+impl flutter_rust_bridge::IntoIntoDart<crate::api::engine_events::EngineEventKind>
+    for crate::api::engine_events::EngineEventKind
+{
+    fn into_into_dart(self) -> crate::api::engine_events::EngineEventKind {
+        self
+    }
+}
+// Codec=Dco (DartCObject based), see doc to use other codecs
+impl flutter_rust_bridge::IntoDart for crate::api::engine_history::EngineHistoryKind {
+    fn into_dart(self) -> flutter_rust_bridge::for_generated::DartAbi {
+        match self {
+            Self::Start => 0.into_dart(),
+            Self::Pixels => 1.into_dart(),
+            Self::Layers => 2.into_dart(),
+            _ => unreachable!(),
+        }
+    }
+}
+impl flutter_rust_bridge::for_generated::IntoDartExceptPrimitive
+    for crate::api::engine_history::EngineHistoryKind
+{
+}
+impl flutter_rust_bridge::IntoIntoDart<crate::api::engine_history::EngineHistoryKind>
+    for crate::api::engine_history::EngineHistoryKind
+{
+    fn into_into_dart(self) -> crate::api::engine_history::EngineHistoryKind {
+        self
+    }
+}
+// Codec=Dco (DartCObject based), see doc to use other codecs
+impl flutter_rust_bridge::IntoDart for crate::api::engine_history::EngineHistoryNode {
+    fn into_dart(self) -> flutter_rust_bridge::for_generated::DartAbi {
+        [
+            self.id.into_into_dart().into_dart(),
+            self.parent_id.into_into_dart().into_dart(),
+            self.kind.into_into_dart().into_dart(),
+            self.label.into_into_dart().into_dart(),
+            self.current.into_into_dart().into_dart(),
+            self.on_active_branch.into_into_dart().into_dart(),
+            self.thumbnail_width.into_into_dart().into_dart(),
+            self.thumbnail_height.into_into_dart().into_dart(),
+            self.thumbnail.into_into_dart().into_dart(),
+        ]
+        .into_dart()
+    }
+}
+impl flutter_rust_bridge::for_generated::IntoDartExceptPrimitive
+    for crate::api::engine_history::EngineHistoryNode
+{
+}
+impl flutter_rust_bridge::IntoIntoDart<crate::api::engine_history::EngineHistoryNode>
+    for crate::api::engine_history::EngineHistoryNode
+{
+    fn into_into_dart(self) -> crate::api::engine_history::EngineHistoryNode {
+        self
+    }
+}
+// Codec=Dco (DartCObject based), see doc to use other codecs
 impl flutter_rust_bridge::IntoDart for crate::api::bucket_fill::FloodFillPatch {
     fn into_dart(self) -> flutter_rust_bridge::for_generated::DartAbi {
         [
@@ -3154,6 +3533,7 @@ impl flutter_rust_bridge::IntoDart for crate::api::gpu_composite::GpuLayerData {
             self.blend_mode_index.into_into_dart().into_dart(),
             self.visible.into_into_dart().into_dart(),
             self.clipping_mask.into_into_dart().into_dart(),
+            self.mask.into_into_dart().into_dart(),
         ]
         .into_dart()
     }
@@ -3221,6 +3601,7 @@ impl flutter_rust_bridge::IntoDart for crate::api::psd::PsdDocument {
             self.width.into_into_dart().into_dart(),
             self.height.into_into_dart().into_dart(),
             self.layers.into_into_dart().into_dart(),
+            self.groups.into_into_dart().into_dart(),
         ]
         .into_dart()
     }
@@ -3234,6 +3615,26 @@ impl flutter_rust_bridge::IntoIntoDart<crate::api::psd::PsdDocument>
     }
 }
 // Codec=Dco (DartCObject based), see doc to use other codecs
+impl flutter_rust_bridge::IntoDart for crate::api::psd::PsdGroup {
+    fn into_dart(self) -> flutter_rust_bridge::for_generated::DartAbi {
+        [
+            self.id.into_into_dart().into_dart(),
+            self.parent_id.into_into_dart().into_dart(),
+            self.name.into_into_dart().into_dart(),
+            self.visible.into_into_dart().into_dart(),
+            self.opacity.into_into_dart().into_dart(),
+            self.blend_mode_key.into_into_dart().into_dart(),
+        ]
+        .into_dart()
+    }
+}
+impl flutter_rust_bridge::for_generated::IntoDartExceptPrimitive for crate::api::psd::PsdGroup {}
+impl flutter_rust_bridge::IntoIntoDart<crate::api::psd::PsdGroup> for crate::api::psd::PsdGroup {
+    fn into_into_dart(self) -> crate::api::psd::PsdGroup {
+        self
+    }
+}
+// Codec=Dco (DartCObject based), see doc to use other codecs
 impl flutter_rust_bridge::IntoDart for crate::api::psd::PsdLayer {
     fn into_dart(self) -> flutter_rust_bridge::for_generated::DartAbi {
         [
@@ -3247,6 +3648,9 @@ impl flutter_rust_bridge::IntoDart for crate::api::psd::PsdLayer {
             self.bitmap_height.into_into_dart().into_dart(),
             self.bitmap_left.into_into_dart().into_dart(),
             self.bitmap_top.into_into_dart().into_dart(),
+            self.parent_id.into_into_dart().into_dart(),
+            self.adjustment_filter_type.into_into_dart().into_dart(),
+            self.adjustment_params.into_into_dart().into_dart(),
         ]
         .into_dart()
     }
@@ -3301,6 +3705,15 @@ impl flutter_rust_bridge::IntoIntoDart<crate::api::workspace::WorkspaceState>
     }
 }
 
+impl SseEncode
+    for StreamSink<crate::api::engine_events::EngineEvent, flutter_rust_bridge::for_generated::SseCodec>
+{
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    fn sse_encode(self, serializer: &mut flutter_rust_bridge::for_generated::SseSerializer) {
+        unimplemented!("")
+    }
+}
+
 impl SseEncode for String {
     // Codec=Sse (Serialization based), see doc to use other codecs
     fn sse_encode(self, serializer: &mut flutter_rust_bridge::for_generated::SseSerializer) {
@@ -3463,6 +3876,77 @@ impl SseEncode for crate::api::cpu_transform::CpuTransformTranslateResult {
     }
 }
 
+impl SseEncode for crate::api::engine_events::EngineEvent {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    fn sse_encode(self, serializer: &mut flutter_rust_bridge::for_generated::SseSerializer) {
+        <crate::api::engine_events::EngineEventKind>::sse_encode(self.kind, serializer);
+        <u32>::sse_encode(self.layer_index, serializer);
+        <i32>::sse_encode(self.left, serializer);
+        <i32>::sse_encode(self.top, serializer);
+        <i32>::sse_encode(self.width, serializer);
+        <i32>::sse_encode(self.height, serializer);
+        <bool>::sse_encode(self.on_mask, serializer);
+        <bool>::sse_encode(self.can_undo, serializer);
+        <bool>::sse_encode(self.can_redo, serializer);
+        <bool>::sse_encode(self.applied, serializer);
+        <String>::sse_encode(self.message, serializer);
+    }
+}
+
+impl SseEncode for crate::api::engine_events::EngineEventKind {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    fn sse_encode(self, serializer: &mut flutter_rust_bridge::for_generated::SseSerializer) {
+        <i32>::sse_encode(
+            match self {
+                crate::api::engine_events::EngineEventKind::UndoState => 0,
+                crate::api::engine_events::EngineEventKind::LayerDirty => 1,
+                crate::api::engine_events::EngineEventKind::LayerBounds => 2,
+                crate::api::engine_events::EngineEventKind::BucketFillFinished => 3,
+                crate::api::engine_events::EngineEventKind::FilterFinished => 4,
+                crate::api::engine_events::EngineEventKind::LayersChanged => 5,
+                crate::api::engine_events::EngineEventKind::Error => 6,
+                crate::api::engine_events::EngineEventKind::Warning => 7,
+                _ => {
+                    unimplemented!("");
+                }
+            },
+            serializer,
+        );
+    }
+}
+
+impl SseEncode for crate::api::engine_history::EngineHistoryKind {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    fn sse_encode(self, serializer: &mut flutter_rust_bridge::for_generated::SseSerializer) {
+        <i32>::sse_encode(
+            match self {
+                crate::api::engine_history::EngineHistoryKind::Start => 0,
+                crate::api::engine_history::EngineHistoryKind::Pixels => 1,
+                crate::api::engine_history::EngineHistoryKind::Layers => 2,
+                _ => {
+                    unimplemented!("");
+                }
+            },
+            serializer,
+        );
+    }
+}
+
+impl SseEncode for crate::api::engine_history::EngineHistoryNode {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    fn sse_encode(self, serializer: &mut flutter_rust_bridge::for_generated::SseSerializer) {
+        <u64>::sse_encode(self.id, serializer);
+        <u64>::sse_encode(self.parent_id, serializer);
+        <crate::api::engine_history::EngineHistoryKind>::sse_encode(self.kind, serializer);
+        <String>::sse_encode(self.label, serializer);
+        <bool>::sse_encode(self.current, serializer);
+        <bool>::sse_encode(self.on_active_branch, serializer);
+        <u32>::sse_encode(self.thumbnail_width, serializer);
+        <u32>::sse_encode(self.thumbnail_height, serializer);
+        <Vec<u8>>::sse_encode(self.thumbnail, serializer);
+    }
+}
+
 impl SseEncode for f32 {
     // Codec=Sse (Serialization based), see doc to use other codecs
     fn sse_encode(self, serializer: &mut flutter_rust_bridge::for_generated::SseSerializer) {
@@ -3506,6 +3990,7 @@ impl SseEncode for crate::api::gpu_composite::GpuLayerData {
         <u32>::sse_encode(self.blend_mode_index, serializer);
         <bool>::sse_encode(self.visible, serializer);
         <bool>::sse_encode(self.clipping_mask, serializer);
+        <Option<Vec<u8>>>::sse_encode(self.mask, serializer);
     }
 }
 
@@ -3555,6 +4040,16 @@ impl SseEncode for Vec<crate::api::cpu_brush::CpuBrushCommand> {
     }
 }
 
+impl SseEncode for Vec<crate::api::engine_history::EngineHistoryNode> {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    fn sse_encode(self, serializer: &mut flutter_rust_bridge::for_generated::SseSerializer) {
+        <i32>::sse_encode(self.len() as _, serializer);
+        for item in self {
+            <crate::api::engine_history::EngineHistoryNode>::sse_encode(item, serializer);
+        }
+    }
+}
+
 impl SseEncode for Vec<crate::api::gpu_composite::GpuLayerData> {
     // Codec=Sse (Serialization based), see doc to use other codecs
     fn sse_encode(self, serializer: &mut flutter_rust_bridge::for_generated::SseSerializer) {
@@ -3615,6 +4110,16 @@ impl SseEncode for Vec<u8> {
     }
 }
 
+impl SseEncode for Vec<crate::api::psd::PsdGroup> {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    fn sse_encode(self, serializer: &mut flutter_rust_bridge::for_generated::SseSerializer) {
+        <i32>::sse_encode(self.len() as _, serializer);
+        for item in self {
+            <crate::api::psd::PsdGroup>::sse_encode(item, serializer);
+        }
+    }
+}
+
 impl SseEncode for Vec<crate::api::psd::PsdLayer> {
     // Codec=Sse (Serialization based), see doc to use other codecs
     fn sse_encode(self, serializer: &mut flutter_rust_bridge::for_generated::SseSerializer) {
@@ -3721,6 +4226,19 @@ impl SseEncode for crate::api::psd::PsdDocument {
         <i32>::sse_encode(self.width, serializer);
         <i32>::sse_encode(self.height, serializer);
         <Vec<crate::api::psd::PsdLayer>>::sse_encode(self.layers, serializer);
+        <Vec<crate::api::psd::PsdGroup>>::sse_encode(self.groups, serializer);
+    }
+}
+
+impl SseEncode for crate::api::psd::PsdGroup {
+    // Codec=Sse (Serialization based), see doc to use other codecs
+    fn sse_encode(self, serializer: &mut flutter_rust_bridge::for_generated::SseSerializer) {
+        <u32>::sse_encode(self.id, serializer);
+        <u32>::sse_encode(self.parent_id, serializer);
+        <String>::sse_encode(self.name, serializer);
+        <bool>::sse_encode(self.visible, serializer);
+        <u8>::sse_encode(self.opacity, serializer);
+        <String>::sse_encode(self.blend_mode_key, serializer);
     }
 }
 
@@ -3737,6 +4255,9 @@ impl SseEncode for crate::api::psd::PsdLayer {
         <i32>::sse_encode(self.bitmap_height, serializer);
         <i32>::sse_encode(self.bitmap_left, serializer);
         <i32>::sse_encode(self.bitmap_top, serializer);
+        <u32>::sse_encode(self.parent_id, serializer);
+        <Option<u32>>::sse_encode(self.adjustment_filter_type, serializer);
+        <Vec<f32>>::sse_encode(self.adjustment_params, serializer);
     }
 }
 
@@ -48,7 +48,8 @@ struct ShaderStrokePoint {
     sat: f32,
     rot_sin: f32,
     rot_cos: f32,
-    pad: f32,
+    /// Straight ARGB of the dab when `dab_color_mode` is on.
+    color: u32,
 }
 
 #[repr(C)]
@@ -85,6 +86,31 @@ struct BrushShaderConfig {
     screentone_rotation_cos: f32,
     screentone_softness: f32,
     screentone_shape: u32,
+    alpha_lock_mode: u32,
+    grain_mode: u32,
+    grain_scale: f32,
+    grain_offset_x: f32,
+    grain_offset_y: f32,
+    grain_depth: f32,
+    grain_invert: u32,
+    mix_mode: u32,
+    mix_slot: u32,
+    mix_reset: u32,
+    mix_pickup: f32,
+    mix_dilution: f32,
+    mix_persistence: f32,
+    mix_extension: f32,
+    wet_mode: u32,
+    wet_edge: f32,
+    wet_granulation: f32,
+    wet_bleed: f32,
+    dual_mode: u32,
+    dual_count: u32,
+    dual_shape: u32,
+    dab_color_mode: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -98,6 +124,9 @@ struct DirtyRect {
 const WORKGROUP_SIZE: u32 = 16;
 pub(crate) const MAX_POINTS: usize = 8192;
 const STROKE_BASE_TILE_SIZE: u32 = 256;
+// One paint reservoir per symmetry copy (32-fold kaleidoscope at most); the
+// per-dab colours of the current draw follow them in the mix buffer.
+const MIX_SLOTS: usize = 64;
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 struct StrokeBaseTile {
@@ -109,15 +138,20 @@ pub struct BrushRenderer {
     device: Arc<Device>,
     queue: Arc<Queue>,
     pipeline: ComputePipeline,
+    mix_pipeline: ComputePipeline,
+    wet_pipeline: ComputePipeline,
 
     bind_group_layout: wgpu::BindGroupLayout,
     uniform_buffer: wgpu::Buffer,
     points_buffer: Option<wgpu::Buffer>,
     points_capacity: usize,
+    mix_buffer: wgpu::Buffer,
     stroke_mask: wgpu::Texture,
     stroke_mask_view: wgpu::TextureView,
     stroke_mask_width: u32,
     stroke_mask_height: u32,
+    stroke_mask_read: wgpu::Texture,
+    stroke_mask_read_view: wgpu::TextureView,
     stroke_base: wgpu::Texture,
     stroke_base_view: wgpu::TextureView,
     stroke_base_width: u32,
@@ -134,6 +168,17 @@ pub struct BrushRenderer {
     custom_mask_width: u32,
     custom_mask_height: u32,
     custom_mask_enabled: bool,
+    grain_texture: wgpu::Texture,
+    grain_texture_view: wgpu::TextureView,
+    grain_texture_width: u32,
+    grain_texture_height: u32,
+    grain_texture_enabled: bool,
+    dual_mask: wgpu::Texture,
+    dual_mask_view: wgpu::TextureView,
+    dual_mask_width: u32,
+    dual_mask_height: u32,
+    dual_mask_enabled: bool,
+    alpha_lock: bool,
     layer_read: Option<wgpu::Texture>,
     layer_read_view: Option<wgpu::TextureView>,
     layer_read_width: u32,
@@ -149,6 +194,31 @@ pub struct BrushRenderer {
     screentone_rotation_cos: f32,
     screentone_softness: f32,
     screentone_shape: BrushShape,
+    grain_mode: u32,
+    grain_scale: f32,
+    grain_offset_x: f32,
+    grain_offset_y: f32,
+    grain_depth: f32,
+    grain_invert: bool,
+    mix_mode: u32,
+    mix_pickup: f32,
+    mix_dilution: f32,
+    mix_persistence: f32,
+    mix_extension: f32,
+    mix_slot: u32,
+    mix_primed_slots: u64,
+    wet_enabled: bool,
+    wet_edge: f32,
+    wet_granulation: f32,
+    wet_bleed: f32,
+    // Pixels the pooled watercolour of this stroke touched so far, and the
+    // config of its last draw for the finishing pass.
+    wet_dirty: Option<DirtyRect>,
+    wet_config: Option<BrushShaderConfig>,
+    dual_mode: u32,
+    dual_shape: u32,
+    dual_points: Vec<ShaderStrokePoint>,
+    dab_colors: Option<Vec<u32>>,
 }
 
 impl BrushRenderer {
@@ -224,6 +294,66 @@ impl BrushRenderer {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: LAYER_TEXTURE_FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 10,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 11,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -240,6 +370,27 @@ impl BrushRenderer {
             entry_point: "draw_brush_stroke",
         });
 
+        let mix_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("BrushRenderer mix pickup pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "mix_pickup",
+        });
+
+        let wet_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("BrushRenderer wet finish pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "wet_finish",
+        });
+
+        let mix_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("BrushRenderer mix buffer"),
+            size: ((MAX_POINTS + MIX_SLOTS) * std::mem::size_of::<[f32; 4]>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
         let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("BrushRenderer uniform buffer"),
             size: std::mem::size_of::<BrushShaderConfig>() as u64,
@@ -255,22 +406,31 @@ impl BrushRenderer {
         }
 
         let (stroke_mask, stroke_mask_view) = create_stroke_mask(device.as_ref(), 1, 1);
+        let (stroke_mask_read, stroke_mask_read_view) =
+            create_stroke_mask_read(device.as_ref(), 1, 1);
         let (stroke_base, stroke_base_view) = create_stroke_base(device.as_ref(), 1, 1);
         let (selection_mask, selection_mask_view) = create_selection_mask(device.as_ref(), 1, 1);
         let (custom_mask, custom_mask_view) = create_custom_mask(device.as_ref(), 1, 1);
+        let (grain_texture, grain_texture_view) = create_grain_texture(device.as_ref(), 1, 1);
+        let (dual_mask, dual_mask_view) = create_custom_mask(device.as_ref(), 1, 1);
 
         Ok(Self {
             device,
             queue,
             pipeline,
+            mix_pipeline,
+            wet_pipeline,
             bind_group_layout,
             uniform_buffer,
             points_buffer: None,
             points_capacity: 0,
+            mix_buffer,
             stroke_mask,
             stroke_mask_view,
             stroke_mask_width: 1,
             stroke_mask_height: 1,
+            stroke_mask_read,
+            stroke_mask_read_view,
             stroke_base,
             stroke_base_view,
             stroke_base_width: 1,
@@ -287,6 +447,17 @@ impl BrushRenderer {
             custom_mask_width: 1,
             custom_mask_height: 1,
             custom_mask_enabled: false,
+            grain_texture,
+            grain_texture_view,
+            grain_texture_width: 1,
+            grain_texture_height: 1,
+            grain_texture_enabled: false,
+            dual_mask,
+            dual_mask_view,
+            dual_mask_width: 1,
+            dual_mask_height: 1,
+            dual_mask_enabled: false,
+            alpha_lock: false,
             layer_read: None,
             layer_read_view: None,
             layer_read_width: 0,
@@ -301,6 +472,29 @@ impl BrushRenderer {
             screentone_rotation_cos: 1.0,
             screentone_softness: 0.0,
             screentone_shape: BrushShape::Circle,
+            grain_mode: 0,
+            grain_scale: 1.0,
+            grain_offset_x: 0.0,
+            grain_offset_y: 0.0,
+            grain_depth: 1.0,
+            grain_invert: false,
+            mix_mode: 0,
+            mix_pickup: 0.5,
+            mix_dilution: 0.0,
+            mix_persistence: 0.5,
+            mix_extension: 0.5,
+            mix_slot: 0,
+            mix_primed_slots: 0,
+            wet_enabled: false,
+            wet_edge: 0.0,
+            wet_granulation: 0.0,
+            wet_bleed: 0.0,
+            wet_dirty: None,
+            wet_config: None,
+            dual_mode: 0,
+            dual_shape: 0,
+            dual_points: Vec::new(),
+            dab_colors: None,
         })
     }
 
@@ -322,22 +516,26 @@ impl BrushRenderer {
         self.ensure_stroke_mask()?;
         self.stroke_base_valid = false;
         self.stroke_base_tiles.clear();
+        self.wet_dirty = None;
+        self.wet_config = None;
         device_push_scopes(self.device.as_ref());
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("BrushRenderer clear stroke mask"),
             });
-        encoder.clear_texture(
-            &self.stroke_mask,
-            &wgpu::ImageSubresourceRange {
-                aspect: wgpu::TextureAspect::All,
-                base_mip_level: 0,
-                mip_level_count: Some(1),
-                base_array_layer: 0,
-                array_layer_count: Some(1),
-            },
-        );
+        for texture in [&self.stroke_mask, &self.stroke_mask_read] {
+            encoder.clear_texture(
+                texture,
+                &wgpu::ImageSubresourceRange {
+                    aspect: wgpu::TextureAspect::All,
+                    base_mip_level: 0,
+                    mip_level_count: Some(1),
+                    base_array_layer: 0,
+                    array_layer_count: Some(1),
+                },
+            );
+        }
         self.queue.submit(Some(encoder.finish()));
         if let Some(err) = device_pop_scope(self.device.as_ref()) {
             return Err(format!("wgpu validation error during mask clear: {err}"));
@@ -348,9 +546,12 @@ impl BrushRenderer {
         Ok(())
     }
 
+    /// Starts a stroke that reads the layer as it was before the stroke:
+    /// forgets the captured tiles and empties the mixing reservoirs.
     pub fn begin_stroke_base_capture(&mut self) {
         self.stroke_base_valid = false;
         self.stroke_base_tiles.clear();
+        self.mix_primed_slots = 0;
     }
 
     pub fn capture_stroke_base_region(
@@ -606,12 +807,13 @@ impl BrushRenderer {
             ));
         }
         self.ensure_selection_mask()?;
-        write_selection_mask(
+        write_r8_texture(
             self.queue.as_ref(),
             &self.selection_mask,
             self.canvas_width,
             self.canvas_height,
             mask,
+            "selection mask",
         )?;
         self.selection_mask_enabled = true;
         Ok(())
@@ -656,6 +858,267 @@ impl BrushRenderer {
         self.custom_mask_enabled = false;
     }
 
+    /// Uploads the tileable grayscale paper texture, one byte per texel.
+    pub fn set_grain_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<(), String> {
+        if width == 0 || height == 0 {
+            self.grain_texture_enabled = false;
+            return Ok(());
+        }
+        if self.grain_texture_width != width || self.grain_texture_height != height {
+            let (tex, view) = create_grain_texture(self.device.as_ref(), width, height);
+            self.grain_texture = tex;
+            self.grain_texture_view = view;
+            self.grain_texture_width = width;
+            self.grain_texture_height = height;
+        }
+        self.grain_texture_enabled = false;
+        write_r8_texture(
+            self.queue.as_ref(),
+            &self.grain_texture,
+            width,
+            height,
+            pixels,
+            "grain texture",
+        )?;
+        self.grain_texture_enabled = true;
+        Ok(())
+    }
+
+    pub fn clear_grain_texture(&mut self) {
+        self.grain_texture_enabled = false;
+    }
+
+    /// Tip image of the dual brush in the custom mask layout.
+    pub fn set_dual_mask(&mut self, width: u32, height: u32, mask: &[u8]) -> Result<(), String> {
+        if width == 0 || height == 0 {
+            self.dual_mask_enabled = false;
+            return Ok(());
+        }
+        let expected_len = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|v| v.checked_mul(2))
+            .ok_or_else(|| "dual brush mask size overflow".to_string())?;
+        if mask.len() != expected_len {
+            return Err(format!(
+                "dual brush mask length mismatch: {} vs {}",
+                mask.len(),
+                expected_len
+            ));
+        }
+        if self.dual_mask_width != width || self.dual_mask_height != height {
+            let (tex, view) = create_custom_mask(self.device.as_ref(), width, height);
+            self.dual_mask = tex;
+            self.dual_mask_view = view;
+            self.dual_mask_width = width;
+            self.dual_mask_height = height;
+        }
+        write_custom_mask(self.queue.as_ref(), &self.dual_mask, width, height, mask)?;
+        self.dual_mask_enabled = true;
+        Ok(())
+    }
+
+    pub fn clear_dual_mask(&mut self) {
+        self.dual_mask_enabled = false;
+    }
+
+    /// Secondary dabs of a dual brush for the next draw only. `mode` is
+    /// `DualBlend::shader_mode` (0 turns it off) and `shape` the brush shape
+    /// index used while no dual brush mask is set.
+    pub fn set_dual_tip(&mut self, mode: u32, shape: u32, points: &[Point2D], radii: &[f32]) {
+        self.dual_mode = mode.min(7);
+        self.dual_shape = shape.min(3);
+        self.dual_points.clear();
+        if self.dual_mode == 0 {
+            return;
+        }
+        self.dual_points
+            .extend(points.iter().zip(radii).map(|(p, &r)| ShaderStrokePoint {
+                pos: [finite_f32(p.x), finite_f32(p.y)],
+                radius: if r.is_finite() { r.max(0.0) } else { 0.0 },
+                alpha: 1.0,
+                sat: 1.0,
+                rot_sin: 0.0,
+                rot_cos: 1.0,
+                color: 0,
+            }));
+    }
+
+    /// Straight ARGB colour of each point of the next draw only, from colour
+    /// dynamics. The RGB replaces the draw colour's; its alpha stays. Erasing,
+    /// mixing and watercolour draws ignore it.
+    pub fn set_dab_colors(&mut self, colors: Option<&[u32]>) {
+        self.dab_colors = colors.map(<[u32]>::to_vec);
+    }
+
+    /// Paper grain for the following draws. `mode` 0 turns it off, 1
+    /// multiplies, 2 subtracts and 3 treats the grain as a height map;
+    /// `scale` is canvas pixels per texel.
+    pub fn set_grain(
+        &mut self,
+        mode: u32,
+        scale: f32,
+        offset_x: f32,
+        offset_y: f32,
+        depth: f32,
+        invert: bool,
+    ) {
+        self.grain_mode = mode.min(3);
+        self.grain_scale = if scale.is_finite() && scale > 0.0 {
+            scale
+        } else {
+            1.0
+        };
+        self.grain_offset_x = finite_f32(offset_x);
+        self.grain_offset_y = finite_f32(offset_y);
+        self.grain_depth = if depth.is_finite() {
+            depth.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        self.grain_invert = invert;
+    }
+
+    /// Colour mixing for the following draws: `mode` 0 is off, 1 smudge,
+    /// 2 blender and 3 watercolour. `slot` picks the paint reservoir, one per
+    /// symmetry copy. Mixing reads the stroke base, so the caller captures it.
+    pub fn set_mix(
+        &mut self,
+        mode: u32,
+        pickup: f32,
+        dilution: f32,
+        persistence: f32,
+        extension: f32,
+        slot: u32,
+    ) {
+        let unit = |value: f32| {
+            if value.is_finite() {
+                value.clamp(0.0, 1.0)
+            } else {
+                0.0
+            }
+        };
+        self.mix_mode = if mode <= 3 { mode } else { 0 };
+        self.mix_pickup = unit(pickup);
+        self.mix_dilution = unit(dilution);
+        self.mix_persistence = unit(persistence);
+        self.mix_extension = unit(extension);
+        self.mix_slot = slot.min(MIX_SLOTS as u32 - 1);
+    }
+
+    /// Watercolour for the following draws. Dabs pool in the stroke mask, so
+    /// the caller clears it and captures the stroke base, and ends the stroke
+    /// with [`Self::finish_wet_stroke`]. `bleed` is in canvas pixels.
+    pub fn set_watercolor(&mut self, enabled: bool, edge: f32, granulation: f32, bleed: f32) {
+        let unit = |value: f32| {
+            if value.is_finite() {
+                value.clamp(0.0, 1.0)
+            } else {
+                0.0
+            }
+        };
+        self.wet_enabled = enabled;
+        self.wet_edge = unit(edge);
+        self.wet_granulation = unit(granulation);
+        self.wet_bleed = if bleed.is_finite() { bleed.max(0.0) } else { 0.0 };
+    }
+
+    /// The pooled watercolour of the current stroke grown by `reach` pixels
+    /// and clamped to the canvas, as `(left, top, width, height)`.
+    pub fn wet_stroke_rect(&self, reach: u32) -> Option<(i32, i32, i32, i32)> {
+        let dirty = self.wet_dirty?;
+        self.wet_config?;
+        let left = dirty.origin_x.saturating_sub(reach);
+        let top = dirty.origin_y.saturating_sub(reach);
+        let right = (dirty.origin_x + dirty.width)
+            .saturating_add(reach)
+            .min(self.canvas_width);
+        let bottom = (dirty.origin_y + dirty.height)
+            .saturating_add(reach)
+            .min(self.canvas_height);
+        if right <= left || bottom <= top {
+            return None;
+        }
+        Some((
+            left as i32,
+            top as i32,
+            (right - left) as i32,
+            (bottom - top) as i32,
+        ))
+    }
+
+    /// Ends a watercolour stroke: redraws `rect` (see
+    /// [`Self::wet_stroke_rect`]) from the stroke base with the wet edge,
+    /// granulation and bleed applied to the pooled coverage.
+    pub fn finish_wet_stroke(
+        &mut self,
+        layer_view: &wgpu::TextureView,
+        rect: (i32, i32, i32, i32),
+    ) -> Result<(), String> {
+        let dirty = self.wet_dirty.take();
+        let config = self.wet_config.take();
+        let (Some(dirty), Some(config)) = (dirty, config) else {
+            return Ok(());
+        };
+        if !self.stroke_base_valid {
+            return Ok(());
+        }
+        let (left, top, width, height) = rect;
+        if left < 0 || top < 0 || width <= 0 || height <= 0 {
+            return Ok(());
+        }
+        let config = BrushShaderConfig {
+            origin_x: left as u32,
+            origin_y: top as u32,
+            region_width: width as u32,
+            region_height: height as u32,
+            selection_mask_mode: if self.selection_mask_enabled { 1 } else { 0 },
+            alpha_lock_mode: if self.alpha_lock { 1 } else { 0 },
+            wet_mode: 2,
+            ..config
+        };
+        self.queue
+            .write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&config));
+        let bind_group = self.create_bind_group(layer_view)?;
+
+        device_push_scopes(self.device.as_ref());
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("BrushRenderer wet finish encoder"),
+            });
+        self.copy_stroke_mask_read(&mut encoder, dirty);
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("BrushRenderer wet finish pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.wet_pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let wg_x = config.region_width.div_ceil(WORKGROUP_SIZE);
+            let wg_y = config.region_height.div_ceil(WORKGROUP_SIZE);
+            pass.dispatch_workgroups(wg_x, wg_y, 1);
+        }
+        self.queue.submit(Some(encoder.finish()));
+        if let Some(err) = device_pop_scope(self.device.as_ref()) {
+            return Err(format!("wgpu validation error during wet finish: {err}"));
+        }
+        if let Some(err) = device_pop_scope(self.device.as_ref()) {
+            return Err(format!("wgpu out-of-memory error during wet finish: {err}"));
+        }
+        Ok(())
+    }
+
+    /// Keeps the destination alpha of every pixel: paint only recolours
+    /// existing pixels and erasing leaves the layer untouched.
+    pub fn set_alpha_lock(&mut self, enabled: bool) {
+        self.alpha_lock = enabled;
+    }
+
     pub fn draw_stroke(
         &mut self,
         layer_view: &wgpu::TextureView,
@@ -825,7 +1288,18 @@ impl BrushRenderer {
         } else {
             0
         };
-        let stroke_mask_mode = if hollow_mode != 0 && use_stroke_mask {
+        // Watercolour composites over the stroke base, so it needs one.
+        let wet_mode = if self.wet_enabled
+            && !erase
+            && use_stroke_mask
+            && self.stroke_base_valid
+            && matches!(stroke_mode, BrushStrokeMode::Points)
+        {
+            1
+        } else {
+            0
+        };
+        let stroke_mask_mode = if (hollow_mode != 0 || wet_mode != 0) && use_stroke_mask {
             self.ensure_stroke_mask()?;
             1
         } else {
@@ -874,13 +1348,25 @@ impl BrushRenderer {
             ),
         );
 
-        self.ensure_points_buffer(points.len())?;
+        let dual_mode = self.dual_mode;
+        self.dual_mode = 0;
+        let dual_points = std::mem::take(&mut self.dual_points);
+        let dab_colors = self.dab_colors.take().filter(|colors| {
+            colors.len() == points.len()
+                && !erase
+                && wet_mode == 0
+                && self.mix_mode == 0
+                && accumulate_segments
+                && matches!(stroke_mode, BrushStrokeMode::Points)
+        });
+        self.ensure_points_buffer(points.len() + dual_points.len())?;
         let points_buffer = self
             .points_buffer
             .as_ref()
             .ok_or_else(|| "wgpu points buffer not initialized".to_string())?;
 
-        let mut shader_points: Vec<ShaderStrokePoint> = Vec::with_capacity(points.len());
+        let mut shader_points: Vec<ShaderStrokePoint> =
+            Vec::with_capacity(points.len() + dual_points.len());
         let use_point_rotation = matches!(stroke_mode, BrushStrokeMode::Points);
         let default_sin = rotation.sin();
         let default_cos = rotation.cos();
@@ -923,9 +1409,11 @@ impl BrushRenderer {
                 sat,
                 rot_sin,
                 rot_cos,
-                pad: 0.0,
+                color: dab_colors.as_ref().map_or(0, |colors| colors[idx]),
             });
         }
+        // The secondary dabs follow the primary ones in the same buffer.
+        shader_points.extend_from_slice(&dual_points);
 
         self.queue
             .write_buffer(points_buffer, 0, bytemuck::cast_slice(&shader_points));
@@ -940,6 +1428,19 @@ impl BrushRenderer {
                 }
             }
         };
+        let mix_mode = if erase
+            || wet_mode != 0
+            || !matches!(stroke_mode, BrushStrokeMode::Points)
+        {
+            0
+        } else {
+            self.mix_mode
+        };
+        let slot_bit = 1u64 << self.mix_slot;
+        let mix_reset = mix_mode != 0 && self.mix_primed_slots & slot_bit == 0;
+        if mix_mode != 0 {
+            self.mix_primed_slots |= slot_bit;
+        }
         let config = BrushShaderConfig {
             canvas_width: self.canvas_width,
             canvas_height: self.canvas_height,
@@ -947,7 +1448,7 @@ impl BrushRenderer {
             origin_y: dirty.origin_y,
             region_width: dirty.width,
             region_height: dirty.height,
-            point_count: shader_points.len() as u32,
+            point_count: points.len() as u32,
             brush_shape: match brush_shape {
                 BrushShape::Circle => 0,
                 BrushShape::Triangle => 1,
@@ -985,10 +1486,109 @@ impl BrushRenderer {
                 BrushShape::Square => 2,
                 BrushShape::Star => 3,
             },
+            alpha_lock_mode: if self.alpha_lock { 1 } else { 0 },
+            grain_mode: if self.grain_texture_enabled {
+                self.grain_mode
+            } else {
+                0
+            },
+            grain_scale: self.grain_scale,
+            grain_offset_x: self.grain_offset_x,
+            grain_offset_y: self.grain_offset_y,
+            grain_depth: self.grain_depth,
+            grain_invert: if self.grain_invert { 1 } else { 0 },
+            mix_mode,
+            mix_slot: self.mix_slot,
+            mix_reset: if mix_reset { 1 } else { 0 },
+            mix_pickup: self.mix_pickup,
+            mix_dilution: self.mix_dilution,
+            mix_persistence: self.mix_persistence,
+            mix_extension: self.mix_extension,
+            wet_mode,
+            wet_edge: self.wet_edge,
+            wet_granulation: self.wet_granulation,
+            wet_bleed: self.wet_bleed,
+            dual_mode,
+            dual_count: dual_points.len() as u32,
+            dual_shape: if self.dual_mask_enabled {
+                4
+            } else {
+                self.dual_shape
+            },
+            dab_color_mode: if dab_colors.is_some() { 1 } else { 0 },
+            _pad0: 0,
+            _pad1: 0,
+            _pad2: 0,
         };
         self.queue
             .write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&config));
 
+        let bind_group = self.create_bind_group(layer_view)?;
+        if wet_mode != 0 {
+            self.wet_dirty = Some(union_dirty_rect(self.wet_dirty, dirty));
+            self.wet_config = Some(config);
+        }
+
+        device_push_scopes(self.device.as_ref());
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("BrushRenderer encoder"),
+            });
+        if wet_mode != 0 || stroke_mask_mode != 0 {
+            self.copy_stroke_mask_read(&mut encoder, dirty);
+        }
+        if mix_mode != 0 {
+            // Dab colours depend on every dab before them, so one invocation
+            // walks the points before the draw pass fans out over pixels.
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("BrushRenderer mix pickup pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.mix_pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(1, 1, 1);
+        }
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("BrushRenderer pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let wg_x = dirty.width.div_ceil(WORKGROUP_SIZE);
+            let wg_y = dirty.height.div_ceil(WORKGROUP_SIZE);
+            pass.dispatch_workgroups(wg_x, wg_y, 1);
+        }
+
+        self.queue.submit(Some(encoder.finish()));
+
+        if let Some(err) = device_pop_scope(self.device.as_ref()) {
+            return Err(format!("wgpu validation error during brush draw: {err}"));
+        }
+        if let Some(err) = device_pop_scope(self.device.as_ref()) {
+            return Err(format!("wgpu out-of-memory error during brush draw: {err}"));
+        }
+
+        if let Some(t0) = t0 {
+            debug::log(
+                LogLevel::Verbose,
+                format_args!("BrushRenderer draw_stroke dispatch in {:?}.", t0.elapsed()),
+            );
+        }
+
+        Ok(())
+    }
+
+    fn create_bind_group(
+        &self,
+        layer_view: &wgpu::TextureView,
+    ) -> Result<wgpu::BindGroup, String> {
+        let points_buffer = self
+            .points_buffer
+            .as_ref()
+            .ok_or_else(|| "wgpu points buffer not initialized".to_string())?;
         let mut entries: Vec<wgpu::BindGroupEntry> = vec![
             wgpu::BindGroupEntry {
                 binding: 0,
@@ -1019,6 +1619,30 @@ impl BrushRenderer {
             binding: 5,
             resource: wgpu::BindingResource::TextureView(&self.custom_mask_view),
         });
+        entries.push(wgpu::BindGroupEntry {
+            binding: 6,
+            resource: wgpu::BindingResource::TextureView(&self.grain_texture_view),
+        });
+        entries.push(wgpu::BindGroupEntry {
+            binding: 7,
+            resource: wgpu::BindingResource::TextureView(&self.stroke_base_view),
+        });
+        entries.push(wgpu::BindGroupEntry {
+            binding: 8,
+            resource: self.mix_buffer.as_entire_binding(),
+        });
+        entries.push(wgpu::BindGroupEntry {
+            binding: 9,
+            resource: wgpu::BindingResource::TextureView(&self.stroke_mask_view),
+        });
+        entries.push(wgpu::BindGroupEntry {
+            binding: 10,
+            resource: wgpu::BindingResource::TextureView(&self.stroke_mask_read_view),
+        });
+        entries.push(wgpu::BindGroupEntry {
+            binding: 11,
+            resource: wgpu::BindingResource::TextureView(&self.dual_mask_view),
+        });
         device_push_scopes(self.device.as_ref());
         let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("BrushRenderer bind group"),
@@ -1035,43 +1659,35 @@ impl BrushRenderer {
                 "wgpu out-of-memory error during brush bind group: {err}"
             ));
         }
+        Ok(bind_group)
+    }
 
-        device_push_scopes(self.device.as_ref());
-
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("BrushRenderer encoder"),
-            });
-        {
-            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some("BrushRenderer pass"),
-                timestamp_writes: None,
-            });
-            pass.set_pipeline(&self.pipeline);
-            pass.set_bind_group(0, &bind_group, &[]);
-            let wg_x = (dirty.width + (WORKGROUP_SIZE - 1)) / WORKGROUP_SIZE;
-            let wg_y = (dirty.height + (WORKGROUP_SIZE - 1)) / WORKGROUP_SIZE;
-            pass.dispatch_workgroups(wg_x, wg_y, 1);
-        }
-
-        self.queue.submit(Some(encoder.finish()));
-
-        if let Some(err) = device_pop_scope(self.device.as_ref()) {
-            return Err(format!("wgpu validation error during brush draw: {err}"));
-        }
-        if let Some(err) = device_pop_scope(self.device.as_ref()) {
-            return Err(format!("wgpu out-of-memory error during brush draw: {err}"));
-        }
-
-        if let Some(t0) = t0 {
-            debug::log(
-                LogLevel::Verbose,
-                format_args!("BrushRenderer draw_stroke dispatch in {:?}.", t0.elapsed()),
-            );
-        }
-
-        Ok(())
+    /// Copies the stroke mask under `dirty` to the copy the shader reads.
+    fn copy_stroke_mask_read(&self, encoder: &mut wgpu::CommandEncoder, dirty: DirtyRect) {
+        let origin = wgpu::Origin3d {
+            x: dirty.origin_x,
+            y: dirty.origin_y,
+            z: 0,
+        };
+        encoder.copy_texture_to_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.stroke_mask,
+                mip_level: 0,
+                origin,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyTexture {
+                texture: &self.stroke_mask_read,
+                mip_level: 0,
+                origin,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::Extent3d {
+                width: dirty.width,
+                height: dirty.height,
+                depth_or_array_layers: 1,
+            },
+        );
     }
 
     fn ensure_points_buffer(&mut self, point_count: usize) -> Result<(), String> {
@@ -1144,6 +1760,10 @@ impl BrushRenderer {
             create_stroke_mask(self.device.as_ref(), self.canvas_width, self.canvas_height);
         self.stroke_mask = mask;
         self.stroke_mask_view = view;
+        let (mask_read, read_view) =
+            create_stroke_mask_read(self.device.as_ref(), self.canvas_width, self.canvas_height);
+        self.stroke_mask_read = mask_read;
+        self.stroke_mask_read_view = read_view;
         self.stroke_mask_width = self.canvas_width;
         self.stroke_mask_height = self.canvas_height;
         Ok(())
@@ -1250,7 +1870,32 @@ fn create_stroke_mask(
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
         format: LAYER_TEXTURE_FORMAT,
-        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_DST,
+        usage: wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+fn create_stroke_mask_read(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("BrushRenderer stroke mask read"),
+        size: wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: LAYER_TEXTURE_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
         view_formats: &[],
     });
     let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
@@ -1273,7 +1918,9 @@ fn create_stroke_base(
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
         format: LAYER_TEXTURE_FORMAT,
-        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_DST,
+        usage: wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST,
         view_formats: &[],
     });
     let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
@@ -1330,6 +1977,29 @@ fn create_custom_mask(
     (texture, view)
 }
 
+fn create_grain_texture(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("BrushRenderer grain texture"),
+        size: wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
 fn antialias_feather(level: u32) -> f32 {
     match level {
         0 => 0.0,
@@ -1345,6 +2015,22 @@ fn antialias_feather(level: u32) -> f32 {
     }
 }
 
+fn union_dirty_rect(current: Option<DirtyRect>, next: DirtyRect) -> DirtyRect {
+    let Some(current) = current else {
+        return next;
+    };
+    let left = current.origin_x.min(next.origin_x);
+    let top = current.origin_y.min(next.origin_y);
+    let right = (current.origin_x + current.width).max(next.origin_x + next.width);
+    let bottom = (current.origin_y + current.height).max(next.origin_y + next.height);
+    DirtyRect {
+        origin_x: left,
+        origin_y: top,
+        width: right - left,
+        height: bottom - top,
+    }
+}
+
 fn compute_dirty_rect(
     points: &[Point2D],
     radii: &[f32],
@@ -1432,12 +2118,13 @@ fn compute_dirty_rect(
     })
 }
 
-fn write_selection_mask(
+fn write_r8_texture(
     queue: &wgpu::Queue,
     texture: &wgpu::Texture,
     width: u32,
     height: u32,
     mask: &[u8],
+    label: &str,
 ) -> Result<(), String> {
     if width == 0 || height == 0 {
         return Ok(());
@@ -1445,10 +2132,10 @@ fn write_selection_mask(
 
     let expected_len = (width as usize)
         .checked_mul(height as usize)
-        .ok_or_else(|| "selection mask size overflow".to_string())?;
+        .ok_or_else(|| format!("{label} size overflow"))?;
     if mask.len() != expected_len {
         return Err(format!(
-            "selection mask length mismatch: {} vs {}",
+            "{label} length mismatch: {} vs {}",
             mask.len(),
             expected_len
         ));
@@ -1461,7 +2148,7 @@ fn write_selection_mask(
     let bytes_per_row_padded =
         align_up_u32(bytes_per_row_unpadded, COPY_BYTES_PER_ROW_ALIGNMENT);
     if bytes_per_row_padded == 0 {
-        return Err("selection mask bytes_per_row == 0".to_string());
+        return Err(format!("{label} bytes_per_row == 0"));
     }
     let row_bytes = bytes_per_row_padded as usize;
     let rows_per_chunk = (MAX_CHUNK_BYTES / row_bytes).max(1) as u32;
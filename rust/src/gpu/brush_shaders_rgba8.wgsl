@@ -7,7 +7,7 @@ struct StrokePoint {
   sat: f32,
   rot_sin: f32,
   rot_cos: f32,
-  pad: f32,
+  color: u32,              // straight ARGB of the dab when dab_color_mode is on
 };
 
 struct Config {
@@ -42,9 +42,37 @@ struct Config {
   screentone_rotation_cos: f32,
   screentone_softness: f32,
   screentone_shape: u32,
+  alpha_lock_mode: u32,    // 0: disabled, 1: keep destination alpha
+  grain_mode: u32,         // 0: off, 1: multiply, 2: subtract, 3: height
+  grain_scale: f32,        // canvas pixels per grain texel
+  grain_offset_x: f32,
+  grain_offset_y: f32,
+  grain_depth: f32,
+  grain_invert: u32,
+  mix_mode: u32,           // 0: off, 1: smudge, 2: blender, 3: watercolour
+  mix_slot: u32,           // reservoir of this draw, one per symmetry copy
+  mix_reset: u32,          // 1: first draw of the stroke for this reservoir
+  mix_pickup: f32,
+  mix_dilution: f32,
+  mix_persistence: f32,
+  mix_extension: f32,
+  wet_mode: u32,
+  wet_edge: f32,
+  wet_granulation: f32,
+  wet_bleed: f32,
+  dual_mode: u32,          // 0: off, else DualBlend index + 1
+  dual_count: u32,         // secondary dabs after the first point_count points
+  dual_shape: u32,         // brush shape of the secondary tip, 4: dual_mask
+  dab_color_mode: u32,     // 0: cfg.color_argb, 1: per-dab colour (accumulated points)
+  _pad0: u32,
+  _pad1: u32,
+  _pad2: u32,
 };
 
 const SQRT2: f32 = 1.414213562;
+const MIX_SLOTS: u32 = 64u;
+const WET_EDGE_REACH: f32 = 2.0;
+const FRAC_1_SQRT2: f32 = 0.707106781;
 
 @group(0) @binding(0)
 var<storage, read> stroke_points: array<StrokePoint>;
@@ -64,6 +92,29 @@ var selection_mask: texture_2d<f32>;
 @group(0) @binding(5)
 var brush_mask: texture_2d<f32>;
 
+@group(0) @binding(6)
+var grain_tex: texture_2d<f32>;
+
+@group(0) @binding(7)
+var stroke_base_tex: texture_2d<f32>;
+
+// Reservoirs in the first MIX_SLOTS entries, then one colour per dab of the
+// current draw. All premultiplied.
+@group(0) @binding(8)
+var<storage, read_write> mix_state: array<vec4<f32>>;
+
+// Pooled watercolour coverage of the stroke in `.r` (hollow strokes pool
+// their core in `.r` and their ring in `.g`), and a copy of it taken before
+// the current pass.
+@group(0) @binding(9)
+var stroke_mask: texture_storage_2d<rgba8unorm, write>;
+
+@group(0) @binding(10)
+var stroke_mask_read: texture_2d<f32>;
+
+@group(0) @binding(11)
+var dual_mask: texture_2d<f32>;
+
 fn to_u8(x: f32) -> u32 {
   let v = floor(clamp(x, 0.0, 1.0) * 255.0 + 0.5);
   return u32(clamp(v, 0.0, 255.0));
@@ -235,6 +286,152 @@ fn screentone_distance(rel: vec2<f32>, radius: f32) -> f32 {
   return length(rel);
 }
 
+fn grain_texel(x: i32, y: i32, dims: vec2<i32>) -> f32 {
+  let wrapped = vec2<i32>(((x % dims.x) + dims.x) % dims.x, ((y % dims.y) + dims.y) % dims.y);
+  return textureLoad(grain_tex, wrapped, 0).r;
+}
+
+// Canvas-anchored paper grain; mirrors `apply_grain` in cpu_brush.rs.
+fn grain_apply(sample_pos: vec2<f32>, coverage: f32) -> f32 {
+  if (cfg.grain_mode == 0u || coverage <= 0.0) {
+    return coverage;
+  }
+  let dims = vec2<i32>(textureDimensions(grain_tex));
+  if (dims.x <= 0 || dims.y <= 0) {
+    return coverage;
+  }
+  let offset = vec2<f32>(cfg.grain_offset_x, cfg.grain_offset_y);
+  let f = (sample_pos + offset) / max(cfg.grain_scale, EPS) - vec2<f32>(0.5, 0.5);
+  let base = floor(f);
+  let t = f - base;
+  let x0 = i32(base.x);
+  let y0 = i32(base.y);
+  let top = mix(grain_texel(x0, y0, dims), grain_texel(x0 + 1, y0, dims), t.x);
+  let bottom = mix(grain_texel(x0, y0 + 1, dims), grain_texel(x0 + 1, y0 + 1, dims), t.x);
+  var grain = mix(top, bottom, t.y);
+  if (cfg.grain_invert != 0u) {
+    grain = 1.0 - grain;
+  }
+  let depth = clamp01(cfg.grain_depth);
+  if (cfg.grain_mode == 2u) {
+    return clamp01(coverage - depth * (1.0 - grain));
+  }
+  if (cfg.grain_mode == 3u) {
+    let reach = clamp01((grain + coverage - 1.0) / 0.1 + 1.0);
+    return coverage * (1.0 - depth + depth * reach);
+  }
+  return coverage * (1.0 - depth + depth * grain);
+}
+
+fn premultiplied(c: u32) -> vec4<f32> {
+  let a = unpack_a(c);
+  return vec4<f32>(unpack_r(c) * a, unpack_g(c) * a, unpack_b(c) * a, a);
+}
+
+// Mirrors `mix_pickup_sample` in cpu_brush.rs.
+fn mix_sample(pos: vec2<f32>, radius: f32) -> vec4<f32> {
+  let dims = vec2<i32>(textureDimensions(stroke_base_tex));
+  let limit = min(dims, vec2<i32>(i32(cfg.canvas_width), i32(cfg.canvas_height))) - vec2<i32>(1, 1);
+  let reach = max(radius, 0.0) * 0.5;
+  var taps = array<vec2<f32>, 5>(
+    vec2<f32>(0.0, 0.0),
+    vec2<f32>(reach, 0.0),
+    vec2<f32>(-reach, 0.0),
+    vec2<f32>(0.0, reach),
+    vec2<f32>(0.0, -reach),
+  );
+  var sum = vec4<f32>(0.0);
+  for (var i: u32 = 0u; i < 5u; i = i + 1u) {
+    let p = floor(pos + taps[i]);
+    let coord = clamp(vec2<i32>(p), vec2<i32>(0, 0), max(limit, vec2<i32>(0, 0)));
+    sum = sum + premultiplied(unpack_u32(textureLoad(stroke_base_tex, coord, 0)));
+  }
+  return sum / 5.0;
+}
+
+// Mirrors `MixReservoir::step` in cpu_brush.rs.
+fn mix_step(load_in: vec4<f32>, primed: bool, sample: vec4<f32>) -> vec4<f32> {
+  if (cfg.mix_mode == 1u) {
+    if (!primed) {
+      return clamp(sample, vec4<f32>(0.0), vec4<f32>(1.0));
+    }
+    return clamp(mix(load_in, sample, cfg.mix_pickup), vec4<f32>(0.0), vec4<f32>(1.0));
+  }
+  let fresh = premultiplied(cfg.color_argb) * (1.0 - cfg.mix_dilution);
+  var load = fresh;
+  if (primed) {
+    load = mix(fresh, load_in, cfg.mix_persistence);
+  }
+  var taken = sample;
+  if (cfg.mix_mode == 3u) {
+    taken = sample + load * ((1.0 - sample.w) * cfg.mix_extension);
+  }
+  return clamp(mix(load, taken, cfg.mix_pickup), vec4<f32>(0.0), vec4<f32>(1.0));
+}
+
+@compute @workgroup_size(1)
+fn mix_pickup() {
+  let slot = min(cfg.mix_slot, MIX_SLOTS - 1u);
+  var load = mix_state[slot];
+  var primed = cfg.mix_reset == 0u;
+  for (var i: u32 = 0u; i < cfg.point_count; i = i + 1u) {
+    let sp = stroke_points[i];
+    load = mix_step(load, primed, mix_sample(sp.pos, sp.radius));
+    primed = true;
+    mix_state[MIX_SLOTS + i] = load;
+  }
+  mix_state[slot] = load;
+}
+
+struct MixCoverage {
+  alpha: f32,
+  color: vec4<f32>,
+};
+
+// `stroke_coverage_at` for accumulated point strokes, also summing the dab
+// colours left by `mix_pickup` under the same weights.
+fn mix_coverage_at(sample_pos: vec2<f32>) -> MixCoverage {
+  var out: MixCoverage;
+  out.alpha = 0.0;
+  out.color = vec4<f32>(0.0);
+  let tone = screentone_mask(sample_pos);
+  var remain = 1.0;
+  for (var i: u32 = 0u; i < cfg.point_count; i = i + 1u) {
+    let sp = stroke_points[i];
+    let cov = point_coverage(sample_pos, sp.pos, sp.radius, sp.rot_sin, sp.rot_cos);
+    let a = clamp01(cov * clamp01(sp.alpha));
+    let contrib = a * remain;
+    out.alpha = out.alpha + contrib;
+    out.color = out.color + mix_state[MIX_SLOTS + i] * contrib;
+    remain = remain * (1.0 - a);
+  }
+  out.alpha = out.alpha * tone;
+  out.color = out.color * tone;
+  return out;
+}
+
+// `mix_coverage_at` with the straight dab colours from colour dynamics.
+fn dab_color_coverage_at(sample_pos: vec2<f32>) -> MixCoverage {
+  var out: MixCoverage;
+  out.alpha = 0.0;
+  out.color = vec4<f32>(0.0);
+  let tone = screentone_mask(sample_pos);
+  var remain = 1.0;
+  for (var i: u32 = 0u; i < cfg.point_count; i = i + 1u) {
+    let sp = stroke_points[i];
+    let cov = point_coverage(sample_pos, sp.pos, sp.radius, sp.rot_sin, sp.rot_cos);
+    let a = clamp01(cov * clamp01(sp.alpha));
+    let contrib = a * remain;
+    let rgb = vec3<f32>(unpack_r(sp.color), unpack_g(sp.color), unpack_b(sp.color));
+    out.alpha = out.alpha + contrib;
+    out.color = out.color + vec4<f32>(rgb, 1.0) * contrib;
+    remain = remain * (1.0 - a);
+  }
+  out.alpha = out.alpha * tone;
+  out.color = out.color * tone;
+  return out;
+}
+
 fn closest_t_to_segment(p: vec2<f32>, a: vec2<f32>, b: vec2<f32>) -> f32 {
   let ab = b - a;
   let ap = p - a;
@@ -253,10 +450,14 @@ fn rotate_to_brush_space(v: vec2<f32>, rot_sin: f32, rot_cos: f32) -> vec2<f32>
 }
 
 fn custom_mask_sample(rel: vec2<f32>, radius: f32) -> vec2<f32> {
+  return mask_texture_sample(brush_mask, rel, radius);
+}
+
+fn mask_texture_sample(tex: texture_2d<f32>, rel: vec2<f32>, radius: f32) -> vec2<f32> {
   if (radius <= EPS) {
     return vec2<f32>(0.0, 0.0);
   }
-  let dims = textureDimensions(brush_mask);
+  let dims = textureDimensions(tex);
   if (dims.x == 0u || dims.y == 0u) {
     return vec2<f32>(0.0, 0.0);
   }
@@ -273,10 +474,10 @@ fn custom_mask_sample(rel: vec2<f32>, radius: f32) -> vec2<f32> {
   let y1 = min(y0 + 1, i32(dims.y - 1u));
   let tx = fx - f32(x0);
   let ty = fy - f32(y0);
-  let c00 = textureLoad(brush_mask, vec2<i32>(x0, y0), 0).rg;
-  let c10 = textureLoad(brush_mask, vec2<i32>(x1, y0), 0).rg;
-  let c01 = textureLoad(brush_mask, vec2<i32>(x0, y1), 0).rg;
-  let c11 = textureLoad(brush_mask, vec2<i32>(x1, y1), 0).rg;
+  let c00 = textureLoad(tex, vec2<i32>(x0, y0), 0).rg;
+  let c10 = textureLoad(tex, vec2<i32>(x1, y0), 0).rg;
+  let c01 = textureLoad(tex, vec2<i32>(x0, y1), 0).rg;
+  let c11 = textureLoad(tex, vec2<i32>(x1, y1), 0).rg;
   let a0 = mix(c00, c10, tx);
   let a1 = mix(c01, c11, tx);
   return mix(a0, a1, ty);
@@ -387,13 +588,17 @@ fn shape_distance_to_point(
   rot_cos: f32,
 ) -> f32 {
   let rel = rotate_to_brush_space(sample_pos - center, rot_sin, rot_cos);
-  if (cfg.brush_shape == 0u) {
+  return shape_distance_rel(cfg.brush_shape, rel, radius);
+}
+
+fn shape_distance_rel(shape: u32, rel: vec2<f32>, radius: f32) -> f32 {
+  if (shape == 0u) {
     return length(rel);
   }
   if (radius <= EPS) {
     return radius;
   }
-  if (cfg.brush_shape == 2u) {
+  if (shape == 2u) {
     let half_side = radius / SQRT2;
     let sd = signed_distance_box(rel, vec2<f32>(half_side, half_side));
     return radius + sd;
@@ -401,7 +606,7 @@ fn shape_distance_to_point(
   let inv_r = 1.0 / radius;
   let rel_unit = rel * inv_r;
   var sd_unit = signed_distance_triangle_unit(rel_unit);
-  if (cfg.brush_shape == 3u) {
+  if (shape == 3u) {
     sd_unit = signed_distance_star_unit(rel_unit);
   }
   return radius + sd_unit * radius;
@@ -563,6 +768,71 @@ fn stroke_coverage_at(sample_pos: vec2<f32>, radius_scale: f32) -> vec2<f32> {
   return vec2<f32>(alpha_accum * tone, sat_accum / alpha_accum);
 }
 
+// Mirrors `dual_coverage` in cpu_brush.rs.
+fn dual_coverage(sample_pos: vec2<f32>) -> f32 {
+  var remain = 1.0;
+  for (var i: u32 = 0u; i < cfg.dual_count; i = i + 1u) {
+    let dp = stroke_points[cfg.point_count + i];
+    let reach = dp.radius * SQRT2 + 4.0;
+    let rel = rotate_to_brush_space(sample_pos - dp.pos, dp.rot_sin, dp.rot_cos);
+    if (dot(rel, rel) > reach * reach) {
+      continue;
+    }
+    var a = 0.0;
+    if (cfg.dual_shape == 4u) {
+      a = mask_texture_sample(dual_mask, rel, dp.radius).x;
+    } else {
+      a = brush_alpha(shape_distance_rel(cfg.dual_shape, rel, dp.radius), dp.radius, 0.0);
+    }
+    remain = remain * (1.0 - clamp01(a * dp.alpha));
+    if (remain <= 0.0001) {
+      return 1.0;
+    }
+  }
+  return 1.0 - remain;
+}
+
+// Mirrors `DualBlend::apply`.
+fn dual_blend(mode: u32, primary: f32, dual: f32) -> f32 {
+  let p = clamp01(primary);
+  let d = clamp01(dual);
+  if (mode == 2u) {
+    return min(p, d);
+  }
+  if (mode == 3u) {
+    if (p < 0.5) {
+      return clamp01(2.0 * p * d);
+    }
+    return clamp01(1.0 - 2.0 * (1.0 - p) * (1.0 - d));
+  }
+  if (mode == 4u) {
+    if (d >= 1.0) {
+      return select(0.0, 1.0, p > 0.0);
+    }
+    return clamp01(p / (1.0 - d));
+  }
+  if (mode == 5u) {
+    if (d <= 0.0) {
+      return 0.0;
+    }
+    return clamp01(1.0 - (1.0 - p) / d);
+  }
+  if (mode == 6u) {
+    return clamp01(p + d - 1.0);
+  }
+  if (mode == 7u) {
+    return select(0.0, 1.0, p + d > 1.0);
+  }
+  return p * d;
+}
+
+fn dual_apply(sample_pos: vec2<f32>, coverage: f32) -> f32 {
+  if (cfg.dual_mode == 0u || coverage <= 0.0) {
+    return coverage;
+  }
+  return dual_blend(cfg.dual_mode, coverage, dual_coverage(sample_pos));
+}
+
 fn antialias_samples_per_axis(level: u32) -> u32 {
   let clamped = min(level, 9u);
   if (clamped <= 3u) {
@@ -597,6 +867,146 @@ fn blend_paint(dst: u32, src_rgb: vec3<f32>, src_a: f32) -> u32 {
   return pack_argb(out_a, out_rgb.x, out_rgb.y, out_rgb.z);
 }
 
+// Alpha-locked paint recolours existing pixels and leaves their coverage alone.
+fn blend_paint_locked(dst: u32, src_rgb: vec3<f32>, src_a: f32) -> u32 {
+  let da = unpack_a(dst);
+  if (src_a <= 0.0 || da <= 0.0) {
+    return dst;
+  }
+  let dst_rgb = vec3<f32>(unpack_r(dst), unpack_g(dst), unpack_b(dst));
+  let out_rgb = mix(dst_rgb, src_rgb, clamp01(src_a));
+  return pack_argb(da, out_rgb.x, out_rgb.y, out_rgb.z);
+}
+
+// Mirrors `blend_mix` in cpu_brush.rs.
+fn blend_mix(dst: u32, dab: vec4<f32>, amount: f32) -> u32 {
+  if (amount <= 0.0) {
+    return dst;
+  }
+  let out = mix(premultiplied(dst), dab, clamp01(amount));
+  let locked = cfg.alpha_lock_mode != 0u;
+  if (out.w <= EPS) {
+    return select(0u, dst, locked);
+  }
+  let out_a = select(out.w, unpack_a(dst), locked);
+  if (out_a <= 0.0) {
+    return dst;
+  }
+  return pack_argb(out_a, out.x / out.w, out.y / out.w, out.z / out.w);
+}
+
+fn draw_mixed(x: u32, y: u32, samples: u32) {
+  let inv_samples = 1.0 / f32(samples);
+  var accum = 0.0;
+  var color_accum = vec4<f32>(0.0);
+  for (var sy: u32 = 0u; sy < samples; sy = sy + 1u) {
+    for (var sx: u32 = 0u; sx < samples; sx = sx + 1u) {
+      let ox = (f32(sx) + 0.5) * inv_samples - 0.5;
+      let oy = (f32(sy) + 0.5) * inv_samples - 0.5;
+      let sample_pos = vec2<f32>(f32(x) + 0.5 + ox, f32(y) + 0.5 + oy);
+      let m = mix_coverage_at(sample_pos);
+      let a = grain_apply(sample_pos, dual_apply(sample_pos, m.alpha));
+      if (m.alpha > EPS) {
+        color_accum = color_accum + m.color * (a / m.alpha);
+      }
+      accum = accum + a;
+    }
+  }
+  let coverage = clamp01(accum / max(1.0, f32(samples * samples)));
+  if (coverage <= 0.0) {
+    return;
+  }
+  let coord = vec2<i32>(i32(x), i32(y));
+  let dab = color_accum / max(accum, EPS);
+  layer_store(coord, blend_mix(layer_load(coord), dab, coverage));
+}
+
+fn mix32(h_in: u32) -> u32 {
+  var h = h_in;
+  h = h ^ (h >> 16u);
+  h = h * 0x7FEB352Du;
+  h = h ^ (h >> 15u);
+  h = h * 0x846CA68Bu;
+  h = h ^ (h >> 16u);
+  return h;
+}
+
+fn wet_mask_at(x: i32, y: i32) -> f32 {
+  if (x < 0 || y < 0 || x >= i32(cfg.canvas_width) || y >= i32(cfg.canvas_height)) {
+    return 0.0;
+  }
+  return textureLoad(stroke_mask_read, vec2<i32>(x, y), 0).r;
+}
+
+fn wet_granule(x: i32, y: i32) -> f32 {
+  let h = mix32(((u32(x) >> 1u) * 0x8DA6B343u) ^ ((u32(y) >> 1u) * 0xD8163841u));
+  return f32(h) / 4294967295.0;
+}
+
+fn wet_ring(x: i32, y: i32, radius: f32) -> f32 {
+  var dirs = array<vec2<f32>, 8>(
+    vec2<f32>(1.0, 0.0),
+    vec2<f32>(FRAC_1_SQRT2, FRAC_1_SQRT2),
+    vec2<f32>(0.0, 1.0),
+    vec2<f32>(-FRAC_1_SQRT2, FRAC_1_SQRT2),
+    vec2<f32>(-1.0, 0.0),
+    vec2<f32>(-FRAC_1_SQRT2, -FRAC_1_SQRT2),
+    vec2<f32>(0.0, -1.0),
+    vec2<f32>(FRAC_1_SQRT2, -FRAC_1_SQRT2),
+  );
+  var total = 0.0;
+  for (var i: u32 = 0u; i < 8u; i = i + 1u) {
+    let offset = vec2<i32>(floor(dirs[i] * radius + vec2<f32>(0.5)));
+    total = total + wet_mask_at(x + offset.x, y + offset.y);
+  }
+  return total / 8.0;
+}
+
+// Mirrors `wet_density` in cpu_brush.rs.
+fn wet_density(x: i32, y: i32) -> f32 {
+  let mask = wet_mask_at(x, y);
+  var pooled = mask;
+  if (cfg.wet_bleed >= 1.0) {
+    let spread = 0.5 * (wet_ring(x, y, cfg.wet_bleed) + wet_ring(x, y, cfg.wet_bleed * 0.5));
+    pooled = max(pooled, spread);
+  }
+  if (pooled <= 0.0) {
+    return 0.0;
+  }
+  let rim = clamp01(2.0 * (mask - wet_ring(x, y, WET_EDGE_REACH)));
+  let edge = cfg.wet_edge;
+  let grain = 1.0 + cfg.wet_granulation * (wet_granule(x, y) - 0.5);
+  return clamp01(pooled * (1.0 - 0.5 * edge + 1.5 * edge * rim) * grain);
+}
+
+fn wet_composite(coord: vec2<i32>, rgb: vec3<f32>, density: f32) -> u32 {
+  let base = unpack_u32(textureLoad(stroke_base_tex, coord, 0));
+  let a = clamp01(density * unpack_a(cfg.color_argb));
+  if (cfg.alpha_lock_mode != 0u) {
+    return blend_paint_locked(base, rgb, a);
+  }
+  return blend_paint(base, rgb, a);
+}
+
+// Rebuilds a finished watercolour stroke from the stroke base.
+@compute @workgroup_size(16, 16)
+fn wet_finish(@builtin(global_invocation_id) id: vec3<u32>) {
+  if (id.x >= cfg.region_width || id.y >= cfg.region_height) {
+    return;
+  }
+  let x = cfg.origin_x + id.x;
+  let y = cfg.origin_y + id.y;
+  if (x >= cfg.canvas_width || y >= cfg.canvas_height) {
+    return;
+  }
+  let coord = vec2<i32>(i32(x), i32(y));
+  if (cfg.selection_mask_mode != 0u && selection_mask_load(coord) == 0u) {
+    return;
+  }
+  let rgb = vec3<f32>(unpack_r(cfg.color_argb), unpack_g(cfg.color_argb), unpack_b(cfg.color_argb));
+  layer_store(coord, wet_composite(coord, rgb, wet_density(i32(x), i32(y))));
+}
+
 fn blend_erase(dst: u32, erase_a: f32) -> u32 {
   if (erase_a <= 0.0) {
     return dst;
@@ -633,17 +1043,40 @@ fn draw_brush_stroke(@builtin(global_invocation_id) id: vec3<u32>) {
   }
 
   let samples = antialias_samples_per_axis(cfg.antialias_level);
+  if (cfg.mix_mode != 0u) {
+    draw_mixed(x, y, samples);
+    return;
+  }
   let inv_samples = 1.0 / f32(samples);
+  let ratio = clamp(cfg.hollow_ratio, 0.0, 1.0);
+  let use_hollow = (cfg.hollow_mode != 0u) && (ratio > 0.0001) && (cfg.erase_mode == 0u);
   var outer_accum = 0.0;
+  var inner_accum = 0.0;
   var sat_accum = 0.0;
+  var rgb_accum = vec3<f32>(0.0);
   for (var sy: u32 = 0u; sy < samples; sy = sy + 1u) {
     for (var sx: u32 = 0u; sx < samples; sx = sx + 1u) {
       let ox = (f32(sx) + 0.5) * inv_samples - 0.5;
       let oy = (f32(sy) + 0.5) * inv_samples - 0.5;
       let sample_pos = vec2<f32>(f32(x) + 0.5 + ox, f32(y) + 0.5 + oy);
+      if (use_hollow) {
+        let core = stroke_coverage_at(sample_pos, ratio).x;
+        inner_accum = inner_accum + grain_apply(sample_pos, dual_apply(sample_pos, core));
+      }
+      if (cfg.dab_color_mode != 0u) {
+        let m = dab_color_coverage_at(sample_pos);
+        let a = grain_apply(sample_pos, dual_apply(sample_pos, m.alpha));
+        if (m.alpha > EPS) {
+          rgb_accum = rgb_accum + m.color.rgb * (a / m.alpha);
+        }
+        outer_accum = outer_accum + a;
+        sat_accum = sat_accum + a;
+        continue;
+      }
       let cov = stroke_coverage_at(sample_pos, 1.0);
-      outer_accum = outer_accum + cov.x;
-      sat_accum = sat_accum + cov.y * cov.x;
+      let a = grain_apply(sample_pos, dual_apply(sample_pos, cov.x));
+      outer_accum = outer_accum + a;
+      sat_accum = sat_accum + cov.y * a;
     }
   }
   let total_samples = f32(samples * samples);
@@ -652,6 +1085,9 @@ fn draw_brush_stroke(@builtin(global_invocation_id) id: vec3<u32>) {
 
   let src_a_base = unpack_a(cfg.color_argb);
   if (cfg.erase_mode != 0u) {
+    if (cfg.alpha_lock_mode != 0u) {
+      return;
+    }
     let erase_a = clamp01(outer * src_a_base);
     if (erase_a <= 0.0) {
       return;
@@ -663,7 +1099,7 @@ fn draw_brush_stroke(@builtin(global_invocation_id) id: vec3<u32>) {
   }
 
   let paint_a = clamp01(outer * src_a_base);
-  if (paint_a <= 0.0) {
+  if (paint_a <= 0.0 && !use_hollow) {
     return;
   }
 
@@ -673,9 +1109,61 @@ fn draw_brush_stroke(@builtin(global_invocation_id) id: vec3<u32>) {
     unpack_b(cfg.color_argb),
   );
   let gray = dot(base_rgb, vec3<f32>(0.299, 0.587, 0.114));
-  let src_rgb = vec3<f32>(gray) + (base_rgb - vec3<f32>(gray)) * sat;
+  var src_rgb = vec3<f32>(gray) + (base_rgb - vec3<f32>(gray)) * sat;
+  if (cfg.dab_color_mode != 0u) {
+    src_rgb = clamp(rgb_accum / max(outer_accum, EPS), vec3<f32>(0.0), vec3<f32>(1.0));
+  }
+
+  if (cfg.wet_mode != 0u) {
+    // Dabs pool instead of stacking; the layer shows the pool over the base.
+    let coord = vec2<i32>(i32(x), i32(y));
+    let prev = textureLoad(stroke_mask_read, coord, 0).r;
+    let pooled = f32(to_u8(prev + outer * (1.0 - prev))) / 255.0;
+    textureStore(stroke_mask, coord, vec4<f32>(pooled, 0.0, 0.0, 1.0));
+    layer_store(coord, wet_composite(coord, src_rgb, pooled));
+    return;
+  }
+
+  if (use_hollow) {
+    // The core and ring pool over the whole stroke so later dabs never
+    // paint over a core an earlier dab cut out.
+    let coord = vec2<i32>(i32(x), i32(y));
+    var hole = clamp01(inner_accum / max(1.0, total_samples));
+    var ring = outer;
+    if (cfg.stroke_mask_mode != 0u) {
+      let prev = textureLoad(stroke_mask_read, coord, 0);
+      hole = max(prev.r, hole);
+      let ring_union = max(prev.g, outer);
+      textureStore(stroke_mask, coord, vec4<f32>(hole, ring_union, 0.0, 1.0));
+      if (cfg.stroke_base_mode != 0u) {
+        ring = ring_union;
+      }
+    }
+    // Without erasing, the ring is rebuilt over the pre-stroke pixels so
+    // the core shows what was there before.
+    var under = layer_load(coord);
+    if (cfg.stroke_base_mode != 0u) {
+      under = unpack_u32(textureLoad(stroke_base_tex, coord, 0));
+    }
+    let ring_a = clamp01(max(ring - hole, 0.0) * src_a_base);
+    if (cfg.alpha_lock_mode != 0u) {
+      layer_store(coord, blend_paint_locked(under, src_rgb, ring_a));
+      return;
+    }
+    var hollow_out = blend_paint(under, src_rgb, ring_a);
+    if (cfg.hollow_erase != 0u) {
+      hollow_out = blend_erase(hollow_out, hole);
+    }
+    layer_store(coord, hollow_out);
+    return;
+  }
 
   let dst = layer_load(vec2<i32>(i32(x), i32(y)));
-  let out = blend_paint(dst, src_rgb, paint_a);
+  var out = 0u;
+  if (cfg.alpha_lock_mode != 0u) {
+    out = blend_paint_locked(dst, src_rgb, paint_a);
+  } else {
+    out = blend_paint(dst, src_rgb, paint_a);
+  }
   layer_store(vec2<i32>(i32(x), i32(y)), out);
 }
@@ -100,6 +100,14 @@ struct BucketFillStateSnapshot {
     seed_root: u32,
 }
 
+/// Layers the fill samples: a tile atlas and the page table that maps each
+/// layer's tiles into it (see `canvas_engine::layers::LayerStore`).
+#[derive(Clone, Copy)]
+pub struct SampledLayers<'a> {
+    pub atlas: &'a wgpu::TextureView,
+    pub pages: &'a wgpu::TextureView,
+}
+
 pub struct BucketFillRenderer {
     device: Arc<Device>,
     queue: Arc<Queue>,
@@ -116,6 +124,8 @@ pub struct BucketFillRenderer {
     dummy_layer_view: wgpu::TextureView,
     dummy_layers: wgpu::Texture,
     dummy_layers_view: wgpu::TextureView,
+    dummy_pages: wgpu::Texture,
+    dummy_pages_view: wgpu::TextureView,
 
     config_buffer: wgpu::Buffer,
     state_buffer: wgpu::Buffer,
@@ -269,6 +279,16 @@ impl BucketFillRenderer {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 13,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Uint,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
                 wgpu::BindGroupLayoutEntry {
                     binding: 12,
                     visibility: wgpu::ShaderStages::COMPUTE,
@@ -365,6 +385,7 @@ impl BucketFillRenderer {
             create_mask_texture(device.as_ref(), 1, 1, "BucketFill dummy layer");
         let (dummy_layers, dummy_layers_view) =
             create_sampled_layers_texture(device.as_ref(), 1, 1, "BucketFill dummy layers");
+        let (dummy_pages, dummy_pages_view) = create_dummy_pages_texture(device.as_ref());
 
         if let Some(err) = device_pop_scope(device.as_ref()) {
             return Err(format!(
@@ -392,6 +413,8 @@ impl BucketFillRenderer {
             dummy_layer_view,
             dummy_layers,
             dummy_layers_view,
+            dummy_pages,
+            dummy_pages_view,
             config_buffer,
             state_buffer,
             state_readback,
@@ -413,7 +436,7 @@ impl BucketFillRenderer {
     pub fn bucket_fill(
         &mut self,
         layer_view: &wgpu::TextureView,
-        layers_view: &wgpu::TextureView,
+        layers: SampledLayers<'_>,
         layer_index: u32,
         layer_count: usize,
         layer_opacity: &[f32],
@@ -509,8 +532,12 @@ impl BucketFillRenderer {
         self.write_state_field(STATE_SNAP_FOUND_OFFSET, 0);
         self.write_state_field(STATE_SEED_ROOT_OFFSET, u32::MAX);
 
-        let write_bind_group = self.create_bind_group(layer_view, &self.dummy_layers_view)?;
-        let sample_bind_group = self.create_bind_group(&self.dummy_layer_view, layers_view)?;
+        let dummy_layers = SampledLayers {
+            atlas: &self.dummy_layers_view,
+            pages: &self.dummy_pages_view,
+        };
+        let write_bind_group = self.create_bind_group(layer_view, dummy_layers)?;
+        let sample_bind_group = self.create_bind_group(&self.dummy_layer_view, layers)?;
         let dispatch = |config: &BucketFillConfig| -> Result<(), String> {
             let bind_group = if matches!(config.mode, MODE_READ_BASE | MODE_BUILD_TARGET) {
                 &sample_bind_group
@@ -823,7 +850,7 @@ impl BucketFillRenderer {
     fn create_bind_group(
         &self,
         layer_view: &wgpu::TextureView,
-        layers_view: &wgpu::TextureView,
+        layers: SampledLayers<'_>,
     ) -> Result<BindGroup, String> {
         Ok(self.device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("BucketFillRenderer bind group"),
@@ -835,7 +862,7 @@ impl BucketFillRenderer {
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::TextureView(layers_view),
+                    resource: wgpu::BindingResource::TextureView(layers.atlas),
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
@@ -881,6 +908,10 @@ impl BucketFillRenderer {
                     binding: 12,
                     resource: self.visited_bits.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 13,
+                    resource: wgpu::BindingResource::TextureView(layers.pages),
+                },
             ],
         }))
     }
@@ -1232,6 +1263,25 @@ fn create_sampled_layers_texture(
     (texture, view)
 }
 
+fn create_dummy_pages_texture(device: &wgpu::Device) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("BucketFill dummy layer pages"),
+        size: wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R32Uint,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
 fn create_layer_params_buffer(
     device: &wgpu::Device,
     capacity: usize,
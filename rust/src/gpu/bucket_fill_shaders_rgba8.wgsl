@@ -56,6 +56,9 @@ struct FrontierCounts {
 @group(0) @binding(10) var<storage, read_write> frontier_counts: FrontierCounts;
 @group(0) @binding(11) var<storage, read_write> frontier_indirect: array<u32>;
 @group(0) @binding(12) var<storage, read_write> visited_bits: array<atomic<u32>>;
+// Page table of the tile atlas in `layers_tex`: row 0 is (width, height,
+// tiles_x, page_tiles), row 1 + layer the fill colour and one slot per tile.
+@group(0) @binding(13) var layer_pages: texture_2d<u32>;
 
 fn to_u8(x: f32) -> u32 {
   let v = floor(clamp(x, 0.0, 1.0) * 255.0 + 0.5);
@@ -88,7 +91,19 @@ fn layer_store(coord: vec2<i32>, value: u32) {
 }
 
 fn layers_load(coord: vec2<i32>, layer: i32) -> u32 {
-  return unpack_u32(textureLoad(layers_tex, coord, layer, 0));
+  let tiles_x = textureLoad(layer_pages, vec2<i32>(2, 0), 0).x;
+  let page_tiles = textureLoad(layer_pages, vec2<i32>(3, 0), 0).x;
+  let tile = vec2<u32>(coord) / 256u;
+  let row = layer + 1;
+  let slot = textureLoad(layer_pages, vec2<i32>(i32(1u + tile.y * tiles_x + tile.x), row), 0).x;
+  if (slot == 0xFFFFFFFFu) {
+    return textureLoad(layer_pages, vec2<i32>(0, row), 0).x;
+  }
+  let per_page = page_tiles * page_tiles;
+  let local = slot % per_page;
+  let origin = vec2<u32>(local % page_tiles, local / page_tiles) * 256u;
+  let texel = origin + vec2<u32>(coord) % 256u;
+  return unpack_u32(textureLoad(layers_tex, vec2<i32>(texel), i32(slot / per_page), 0));
 }
 
 fn mask_a_load(coord: vec2<i32>) -> u32 {
@@ -1,3 +1,4 @@
+use std::cell::RefCell;
 use std::collections::VecDeque;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Mutex, OnceLock};
@@ -15,6 +16,12 @@ static SEQ: AtomicU64 = AtomicU64::new(1);
 static LOG_BUFFER: OnceLock<Mutex<VecDeque<String>>> = OnceLock::new();
 const LOG_BUFFER_CAPACITY: usize = 512;
 
+pub type WarnHook = Box<dyn Fn(&str)>;
+
+thread_local! {
+    static WARN_HOOK: RefCell<Option<WarnHook>> = const { RefCell::new(None) };
+}
+
 pub fn level() -> LogLevel {
     *LOG_LEVEL.get_or_init(read_level_from_env)
 }
@@ -37,7 +44,20 @@ pub fn next_seq() -> u64 {
     SEQ.fetch_add(1, Ordering::Relaxed)
 }
 
+/// Sends every warning logged on the calling thread to `hook` as well,
+/// whatever the log level. `None` removes the hook.
+pub fn set_thread_warn_hook(hook: Option<WarnHook>) {
+    WARN_HOOK.with(|slot| *slot.borrow_mut() = hook);
+}
+
 pub fn log(required: LogLevel, args: std::fmt::Arguments) {
+    if required == LogLevel::Warn {
+        WARN_HOOK.with(|slot| {
+            if let Some(hook) = slot.borrow().as_ref() {
+                hook(&args.to_string());
+            }
+        });
+    }
     if level() < required {
         return;
     }
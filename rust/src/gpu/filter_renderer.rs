@@ -520,6 +520,61 @@ impl FilterRenderer {
     }
 }
 
+/// Maps a per-pixel colour filter and its UI parameters to the `color_filter`
+/// shader kind and `params0` uniform. Returns `None` for filters that read
+/// neighbouring pixels (blur, morphology, leak removal).
+pub fn color_filter_params(
+    filter_type: u32,
+    param0: f32,
+    param1: f32,
+    param2: f32,
+) -> Option<(u32, [f32; 4])> {
+    match filter_type {
+        FILTER_HUE_SATURATION => Some((
+            0,
+            [param0 / 360.0, param1 / 100.0, param2 / 100.0, 0.0],
+        )),
+        FILTER_BRIGHTNESS_CONTRAST => {
+            let brightness = param0 / 100.0;
+            let contrast = (1.0 + param1 / 100.0).max(0.0);
+            Some((1, [brightness, contrast, 0.0, 0.0]))
+        }
+        FILTER_BLACK_WHITE => {
+            let (black, inv_range, gamma) = tone_range_params(param0, param1, param2);
+            Some((2, [black, inv_range, gamma, 0.0]))
+        }
+        FILTER_BINARIZE => {
+            let threshold = param0.clamp(0.0, 255.0) / 255.0;
+            Some((3, [threshold, 0.0, 0.0, 0.0]))
+        }
+        FILTER_SCAN_PAPER_DRAWING => {
+            let (black, inv_range, gamma) = tone_range_params(param0, param1, param2);
+            let tone_enabled = if param0.abs() > 1.0e-4
+                || (param1 - 100.0).abs() > 1.0e-4
+                || param2.abs() > 1.0e-4
+            {
+                1.0
+            } else {
+                0.0
+            };
+            Some((4, [black, inv_range, gamma, tone_enabled]))
+        }
+        FILTER_INVERT => Some((5, [0.0; 4])),
+        _ => None,
+    }
+}
+
+/// Black point, inverse range and gamma from percentage black/white points
+/// and a -100..100 mid-tone shift.
+fn tone_range_params(black_percent: f32, white_percent: f32, mid_tone: f32) -> (f32, f32, f32) {
+    let black = (black_percent / 100.0).clamp(0.0, 1.0);
+    let white = (white_percent / 100.0).clamp(0.0, 1.0);
+    let safe_white = (black + 0.01).max(white);
+    let inv_range = 1.0 / (safe_white - black).max(1.0e-4);
+    let gamma = 2.0_f32.powf(mid_tone.clamp(-100.0, 100.0) / 100.0);
+    (black, inv_range, gamma)
+}
+
 fn antialias_profile(level: u32) -> Option<&'static [f64]> {
     match level {
         0 => Some(&[0.25]),
@@ -40,6 +40,8 @@ pub struct LayerProperties {
     pub(crate) blend_mode: BlendMode,
     /// If layer is nested, contains parent group ID, otherwise `None`
     pub(crate) group_id: Option<u32>,
+    /// Key and payload of the adjustment this layer applies, if any
+    pub(crate) adjustment: Option<([u8; 4], Vec<u8>)>,
 }
 
 impl LayerProperties {
@@ -63,6 +65,7 @@ impl LayerProperties {
             psd_width,
             psd_height,
             group_id,
+            adjustment: layer_record.adjustment.clone(),
         }
     }
 
@@ -127,6 +130,14 @@ impl LayerProperties {
     pub fn parent_id(&self) -> Option<u32> {
         self.group_id
     }
+
+    /// For adjustment layers, the additional layer info key (e.g. `hue2`)
+    /// and its raw payload
+    pub fn adjustment(&self) -> Option<(&[u8; 4], &[u8])> {
+        self.adjustment
+            .as_ref()
+            .map(|(key, data)| (key, data.as_slice()))
+    }
 }
 
 /// PsdGroup represents a group of layers
@@ -165,6 +176,11 @@ impl PsdGroup {
     pub fn id(&self) -> u32 {
         self.id
     }
+
+    /// Return the PSD 4-byte blend mode key (`b"pass"` for pass-through groups).
+    pub fn blend_mode_key(&self) -> [u8; 4] {
+        self.layer_properties.blend_mode.key_bytes()
+    }
 }
 
 impl Deref for PsdGroup {
@@ -443,6 +459,8 @@ pub struct LayerRecord {
     pub(super) blend_mode: BlendMode,
     /// Group divider tag
     pub(super) divider_type: Option<GroupDivider>,
+    /// Adjustment layer key and payload
+    pub(super) adjustment: Option<([u8; 4], Vec<u8>)>,
 }
 
 impl LayerRecord {
@@ -21,6 +21,12 @@ const SIGNATURE_EIGHT_B64: [u8; 4] = [56, 66, 54, 52];
 const KEY_UNICODE_LAYER_NAME: &[u8; 4] = b"luni";
 /// Key of `Section divider setting (Photoshop 6.0)`, "lsct"
 const KEY_SECTION_DIVIDER_SETTING: &[u8; 4] = b"lsct";
+/// Keys of the adjustment and fill layer blocks
+const ADJUSTMENT_LAYER_KEYS: [&[u8; 4]; 21] = [
+    b"SoCo", b"GdFl", b"PtFl", b"brit", b"levl", b"curv", b"expA", b"vibA", b"hue ", b"hue2",
+    b"blnc", b"blwh", b"phfl", b"mixr", b"clrL", b"nvrt", b"post", b"thrs", b"grdm", b"selc",
+    b"CgEd",
+];
 
 pub mod groups;
 pub mod layer;
@@ -420,6 +426,7 @@ fn read_layer_record(cursor: &mut PsdCursor) -> Result<LayerRecord, PsdLayerErro
     cursor.read(padding as u32);
 
     let mut divider_type = None;
+    let mut adjustment = None;
     // There can be multiple additional layer information sections so we'll loop
     // until we stop seeing them.
     while cursor.peek_4() == SIGNATURE_EIGHT_BIM || cursor.peek_4() == SIGNATURE_EIGHT_B64 {
@@ -449,6 +456,14 @@ fn read_layer_record(cursor: &mut PsdCursor) -> Result<LayerRecord, PsdLayerErro
                 }
             }
 
+            _ if ADJUSTMENT_LAYER_KEYS.contains(&&key) => {
+                let data = cursor.read(additional_layer_info_len).to_vec();
+                // `CgEd` only supplements a `brit` block.
+                if adjustment.is_none() || &key != b"CgEd" {
+                    adjustment = Some((key, data));
+                }
+            }
+
             // TODO: Skipping other keys until we implement parsing for them
             _ => {
                 cursor.read(additional_layer_info_len);
@@ -468,5 +483,6 @@ fn read_layer_record(cursor: &mut PsdCursor) -> Result<LayerRecord, PsdLayerErro
         clipping_base,
         blend_mode,
         divider_type,
+        adjustment,
     })
 }